@@ -0,0 +1,84 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Shell command for the battery history, which records the battery voltage and charge level on the External SPI
+//  Flash for analysing the discharge curve. The commands are executed in rust/app/src/power/history.rs:
+//    battery history [count] Prints the newest <count> samples, 20 by default
+//    battery send            Sends the entire history to the CoAP server
+//    battery clear           Erases the history
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BATTERY_SHELL)  //  If battery shell commands are enabled...
+#include <stdlib.h>
+#include <string.h>
+#include "defs/error.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// Defined in rust/app/src/power/history.rs
+int battery_shell_history(uint32_t count);
+int battery_shell_send(void);
+int battery_shell_clear(void);
+
+static int battery_shell(int argc, char **argv);
+
+static struct shell_cmd battery_cmd = {
+    .sc_cmd      = "battery",
+    .sc_cmd_func = battery_shell,
+};
+
+/// Register the battery shell command. Called by main() in rust/app/src/lib.rs.
+int start_battery_shell(void) {
+    return shell_cmd_register(&battery_cmd);
+}
+
+/// Shell command `battery history [count] | send | clear`
+static int battery_shell(int argc, char **argv) {
+    int rc;
+    if (argc >= 2 && strcmp(argv[1], "history") == 0) {
+        unsigned long count = 0;
+        if (argc >= 3) {
+            char *end;
+            count = strtoul(argv[2], &end, 10);
+            if (*end != '\0') {
+                console_printf("battery: invalid count %s\n", argv[2]);
+                return SYS_EINVAL;
+            }
+        }
+        rc = battery_shell_history((uint32_t) count);
+    } else if (argc >= 2 && strcmp(argv[1], "send") == 0) {
+        rc = battery_shell_send();
+    } else if (argc >= 2 && strcmp(argv[1], "clear") == 0) {
+        rc = battery_shell_clear();
+    } else {
+        console_printf("usage: battery history [count] | send | clear\n");
+        return SYS_EINVAL;
+    }
+    if (rc != 0) {
+        console_printf("battery: FAILED (%d)%s\n", rc, (rc == SYS_EAGAIN) ? ", network not ready" : "");
+    }
+    return rc;
+}
+
+#else  //  If battery shell commands are disabled...
+
+int start_battery_shell(void) {
+    //  Battery shell commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(BATTERY_SHELL)
@@ -0,0 +1,144 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Current Time Service client: reads the time from the phone when the link is encrypted, so that the watch knows
+//  the time without a network time source. The Current Time characteristic holds the local time of the phone,
+//  and the Local Time Information characteristic (optional) holds the time zone and daylight saving offset.
+//  Both are read by UUID, without discovering the service. The time is set by cts_set_time() in rust/app/src/cts.rs.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BLUETOOTH_LE)  //  If Bluetooth LE is enabled...
+#include <string.h>
+#include "host/ble_hs.h"
+#include "host/ble_uuid.h"
+#include "ble_prph.h"
+
+/// Defined in rust/app/src/cts.rs
+int cts_set_time(const uint8_t *current_time, uint16_t len, int16_t minutes_east);
+
+/// Length of the Current Time value: Exact Time 256 (date, time, day of week, fractions) and Adjust Reason
+#define CTS_CURRENT_TIME_LEN 10
+
+/// Local Time Information offsets in 15-minute units, and the value for an unknown daylight saving offset
+#define CTS_OFFSET_MINUTES 15
+#define CTS_DST_UNKNOWN    255
+#define CTS_TZ_UNKNOWN     (-128)
+
+/// Current Time read from the phone, until the Local Time Information has been read
+static uint8_t current_time[CTS_CURRENT_TIME_LEN];
+static uint16_t current_time_len;
+
+/// Offset of the local time from UTC in minutes, 0 if the phone doesn't tell
+static int16_t local_offset;
+
+static int cts_current_time_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                               struct ble_gatt_attr *attr, void *arg);
+static int cts_local_time_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                             struct ble_gatt_attr *attr, void *arg);
+
+/// Read the Current Time from the phone connected at `conn_handle`. Returns 0 if the read has been started.
+/// The time is set when the read completes. Called by ble_main.c when the link is encrypted.
+int
+cts_client_read(uint16_t conn_handle)
+{
+    current_time_len = 0;
+    local_offset = 0;
+    return ble_gattc_read_by_uuid(conn_handle, 1, 0xffff,
+                                  BLE_UUID16_DECLARE(GATT_CTS_CHR_CURRENT_TIME_UUID),
+                                  cts_current_time_cb, NULL);
+}
+
+/// Keep the Current Time, then read the Local Time Information
+static int
+cts_current_time_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                    struct ble_gatt_attr *attr, void *arg)
+{
+    uint16_t len;
+    int rc;
+
+    switch (error->status) {
+    case 0:
+        len = OS_MBUF_PKTLEN(attr->om);
+        if (len > sizeof current_time) {
+            len = sizeof current_time;
+        }
+        rc = ble_hs_mbuf_to_flat(attr->om, current_time, len, &current_time_len);
+        if (rc != 0) {
+            current_time_len = 0;
+        }
+        return 0;
+
+    case BLE_HS_EDONE:
+        if (current_time_len == 0) {
+            /* The phone has no Current Time Service. */
+            MODLOG_DFLT(INFO, "cts: no current time\n");
+            notify_client_start(conn_handle);
+            return 0;
+        }
+        rc = ble_gattc_read_by_uuid(conn_handle, 1, 0xffff,
+                                    BLE_UUID16_DECLARE(GATT_CTS_CHR_LOCAL_TIME_INFO_UUID),
+                                    cts_local_time_cb, NULL);
+        if (rc != 0) {
+            /* Assume that the phone is on UTC. */
+            cts_set_time(current_time, current_time_len, 0);
+            notify_client_start(conn_handle);
+        }
+        return 0;
+
+    default:
+        MODLOG_DFLT(INFO, "cts: read current time failed; status=%d\n", error->status);
+        notify_client_start(conn_handle);
+        return 0;
+    }
+}
+
+/// Keep the time zone and daylight saving offset, then set the time
+static int
+cts_local_time_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                  struct ble_gatt_attr *attr, void *arg)
+{
+    uint8_t info[2];
+    uint16_t len;
+
+    switch (error->status) {
+    case 0:
+        if (ble_hs_mbuf_to_flat(attr->om, info, sizeof info, &len) == 0 && len == sizeof info &&
+            (int8_t) info[0] != CTS_TZ_UNKNOWN) {
+            local_offset = (int8_t) info[0] * CTS_OFFSET_MINUTES;
+            if (info[1] != CTS_DST_UNKNOWN) {
+                local_offset += info[1] * CTS_OFFSET_MINUTES;
+            }
+        }
+        return 0;
+
+    default:
+        /* Done, or the phone has no Local Time Information: set the time with the offset known so far. */
+        cts_set_time(current_time, current_time_len, local_offset);
+        /* Subscribe to the phone notifications after the reads, since one GATT procedure runs at a time. */
+        notify_client_start(conn_handle);
+        return 0;
+    }
+}
+
+#else  //  If Bluetooth LE is disabled...
+
+int cts_client_read(uint16_t conn_handle) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+#endif  //  MYNEWT_VAL(BLUETOOTH_LE)
@@ -0,0 +1,178 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  GATT service for uploading a boot logo from a phone app. The characteristic writes are forwarded
+//  to the Rust logo uploader in rust/app/src/logo/ble.rs, which defines the protocol.
+//  Writes need an encrypted link with an authenticated (passkey) pairing, so the phone pairs on the first write.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BLUETOOTH_LE)  //  If Bluetooth LE is enabled...
+#include <assert.h>
+#include <string.h>
+#include "host/ble_hs.h"
+#include "host/ble_uuid.h"
+#include "ble_prph.h"
+
+/// Max size of a control command or data chunk: 4-byte offset plus the max ATT payload
+#define LOGO_MAX_WRITE (4 + 512)
+
+/* 6c6f676f-0000-4a6b-9a3d-2d5e8e1f0a00: Logo Upload Service */
+static const ble_uuid128_t logo_svc_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x00, 0x00, 0x6f, 0x67, 0x6f, 0x6c);
+
+/* 6c6f676f-0001-4a6b-9a3d-2d5e8e1f0a00: Control Characteristic */
+static const ble_uuid128_t logo_chr_control_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x01, 0x00, 0x6f, 0x67, 0x6f, 0x6c);
+
+/* 6c6f676f-0002-4a6b-9a3d-2d5e8e1f0a00: Data Characteristic */
+static const ble_uuid128_t logo_chr_data_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x02, 0x00, 0x6f, 0x67, 0x6f, 0x6c);
+
+/// Handle of the Control Characteristic value, for sending notifications
+static uint16_t logo_control_val_handle;
+
+/// Connection that is uploading the logo
+static uint16_t logo_conn_handle = BLE_HS_CONN_HANDLE_NONE;
+
+/// Buffer for the characteristic value being written
+static uint8_t logo_write_buf[LOGO_MAX_WRITE];
+
+/// Defined in rust/app/src/logo/ble.rs
+int logo_ble_control(const uint8_t *data, uint16_t len);
+int logo_ble_data(const uint8_t *data, uint16_t len);
+
+static int
+logo_chr_access(uint16_t conn_handle, uint16_t attr_handle,
+                struct ble_gatt_access_ctxt *ctxt, void *arg);
+
+static const struct ble_gatt_svc_def logo_svcs[] = {
+    {
+        /*** Service: Logo Upload. */
+        .type = BLE_GATT_SVC_TYPE_PRIMARY,
+        .uuid = &logo_svc_uuid.u,
+        .characteristics = (struct ble_gatt_chr_def[]) { {
+            /*** Characteristic: Control. */
+            .uuid = &logo_chr_control_uuid.u,
+            .access_cb = logo_chr_access,
+            .val_handle = &logo_control_val_handle,
+            .flags = BLE_GATT_CHR_F_WRITE | BLE_GATT_CHR_F_NOTIFY |
+                     BLE_GATT_CHR_F_WRITE_ENC | BLE_GATT_CHR_F_WRITE_AUTHEN,
+        }, {
+            /*** Characteristic: Data. */
+            .uuid = &logo_chr_data_uuid.u,
+            .access_cb = logo_chr_access,
+            .flags = BLE_GATT_CHR_F_WRITE | BLE_GATT_CHR_F_WRITE_NO_RSP |
+                     BLE_GATT_CHR_F_WRITE_ENC | BLE_GATT_CHR_F_WRITE_AUTHEN,
+        }, {
+            0, /* No more characteristics in this service. */
+        } },
+    },
+
+    {
+        0, /* No more services. */
+    },
+};
+
+/// Forward the characteristic write to the Rust logo uploader
+static int
+logo_chr_access(uint16_t conn_handle, uint16_t attr_handle,
+                struct ble_gatt_access_ctxt *ctxt, void *arg)
+{
+    uint16_t len;
+    int rc;
+
+    if (ctxt->op != BLE_GATT_ACCESS_OP_WRITE_CHR) {
+        return BLE_ATT_ERR_UNLIKELY;
+    }
+    if (OS_MBUF_PKTLEN(ctxt->om) > sizeof(logo_write_buf)) {
+        return BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN;
+    }
+    rc = ble_hs_mbuf_to_flat(ctxt->om, logo_write_buf, sizeof(logo_write_buf), &len);
+    if (rc != 0) {
+        return BLE_ATT_ERR_UNLIKELY;
+    }
+
+    //  Remember the connection for sending notifications.
+    logo_conn_handle = conn_handle;
+    if (ble_uuid_cmp(ctxt->chr->uuid, &logo_chr_control_uuid.u) == 0) {
+        rc = logo_ble_control(logo_write_buf, len);
+    } else {
+        rc = logo_ble_data(logo_write_buf, len);
+    }
+    return rc == 0 ? 0 : BLE_ATT_ERR_UNLIKELY;
+}
+
+/// Notify the connected phone on the Control Characteristic. Returns 0 if successful.
+/// Called by rust/app/src/logo/ble.rs.
+int
+logo_ble_notify(const uint8_t *data, uint16_t len)
+{
+    struct os_mbuf *om;
+
+    if (logo_conn_handle == BLE_HS_CONN_HANDLE_NONE) {
+        return BLE_HS_ENOTCONN;
+    }
+    om = ble_hs_mbuf_from_flat(data, len);
+    if (om == NULL) {
+        return BLE_HS_ENOMEM;
+    }
+    return ble_gattc_notify_custom(logo_conn_handle, logo_control_val_handle, om);
+}
+
+/// Return the connection that is uploading the logo, or BLE_HS_CONN_HANDLE_NONE if none.
+/// Called by rust/app/src/logo/ble.rs.
+uint16_t
+logo_ble_conn_handle(void)
+{
+    return logo_conn_handle;
+}
+
+/// Register the Logo Upload Service. Called by start_ble() before the host is synced.
+int
+logo_svc_init(void)
+{
+    int rc;
+
+    rc = ble_gatts_count_cfg(logo_svcs);
+    if (rc != 0) {
+        return rc;
+    }
+
+    rc = ble_gatts_add_svcs(logo_svcs);
+    if (rc != 0) {
+        return rc;
+    }
+
+    return 0;
+}
+
+#else  //  If Bluetooth LE is disabled...
+
+int logo_ble_notify(const uint8_t *data, uint16_t len) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+uint16_t logo_ble_conn_handle(void) {
+    //  Bluetooth LE not supported.
+    return 0xffff;
+}
+#endif  //  MYNEWT_VAL(BLUETOOTH_LE)
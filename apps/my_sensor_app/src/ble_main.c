@@ -34,8 +34,9 @@
 #if MYNEWT_VAL(BLE_SVC_DIS_FIRMWARE_REVISION_READ_PERM) >= 0
 #include "bootutil/image.h"
 #include "imgmgr/imgmgr.h"
-#include "services/dis/ble_svc_dis.h"
 #endif
+#include "services/dis/ble_svc_dis.h"
+#include "nrf.h"
 
 /* BLE */
 #include "nimble/ble.h"
@@ -45,9 +46,48 @@
 
 /* Application-specified header. */
 #include "ble_prph.h"
+#include "mynewt_rust/ble_helper.h"
 
 static int bleprph_gap_event(struct ble_gap_event *event, void *arg);
 
+/// Defined in rust/app/src/pairing.rs
+int pairing_show_passkey(uint32_t passkey);
+int pairing_show_result(int status);
+
+/// Defined in rust/app/src/version.rs
+const char *version_build_string(void);
+
+/// HCI reason for disconnecting a peer that failed to encrypt the link: Authentication Failure
+#define BLE_HCI_REASON_AUTH_FAIL 0x05
+
+/// 1 if a passkey is shown on the screen for the pairing in progress
+static int passkey_shown;
+
+/// Number of connected centrals, e.g. a phone and a collection gateway. Advertising continues after each
+/// connection until BLE_MAX_CONNECTIONS centrals are connected.
+static int num_conns;
+
+/// 1 while the advertising is paused by ble_pause_advertising(), e.g. while the watch is in Deep Sleep
+static int adv_paused;
+
+#if MYNEWT_VAL(BLE_WHITELIST)
+/// 1 while any phone may connect and pair, during the pairing window after startup
+static int pairing_window_open = 1;
+
+/// Timer that closes the pairing window
+static struct os_callout pairing_window_timer;
+
+static void bleprph_advertise(void);
+#endif  //  MYNEWT_VAL(BLE_WHITELIST)
+
+#if MYNEWT_VAL(SMP_BLE)
+/// SMP service UUID 8D53DC1D-1DB7-4CD3-868B-8A527460AA84, advertised in the scan response so that
+/// nRF Connect Device Manager lists the device
+static const ble_uuid128_t smp_svc_uuid =
+    BLE_UUID128_INIT(0x84, 0xaa, 0x60, 0x74, 0x52, 0x8a, 0x8b, 0x86,
+                     0xd3, 0x4c, 0xb7, 0x1d, 0x1d, 0xdc, 0x53, 0x8d);
+#endif  //  MYNEWT_VAL(SMP_BLE)
+
 //  Print info and error messages to Semihosting Console
 #define MODLOG_DFLT_INFO    console_printf
 #define MODLOG_DFLT_ERROR   console_printf
@@ -85,10 +125,56 @@ bleprph_print_conn_desc(struct ble_gap_conn_desc *desc)
                 desc->sec_state.bonded);
 }
 
+#if MYNEWT_VAL(BLE_WHITELIST)
+/**
+ * Sets the whitelist to the identity addresses of the bonded phones. Must not be called while advertising.
+ *
+ * @return                      The number of bonded phones in the whitelist.
+ */
+static int
+bleprph_set_whitelist(void)
+{
+    ble_addr_t peers[MYNEWT_VAL(BLE_STORE_MAX_BONDS)];
+    int num_peers;
+    int rc;
+
+    rc = ble_store_util_bonded_peers(peers, &num_peers, MYNEWT_VAL(BLE_STORE_MAX_BONDS));
+    if (rc != 0 || num_peers == 0) {
+        return 0;
+    }
+    rc = ble_gap_wl_set(peers, num_peers);
+    if (rc != 0) {
+        MODLOG_DFLT_ERROR("error setting whitelist; rc=%d\n", rc);
+        return 0;
+    }
+    return num_peers;
+}
+
+/**
+ * Closes the pairing window: from now on, only the bonded phones may connect. Restarts the advertising with
+ * the whitelist, unless a phone is connected.
+ */
+static void
+bleprph_close_pairing_window(struct os_event *ev)
+{
+    pairing_window_open = 0;
+    MODLOG_DFLT_INFO("pairing window closed\n");
+    if (ble_gap_adv_active() && !MYNEWT_VAL(SENSOR_BEACON)) {
+        ble_gap_adv_stop();
+        bleprph_advertise();
+    }
+}
+#endif  //  MYNEWT_VAL(BLE_WHITELIST)
+
 /**
  * Enables advertising with the following parameters:
  *     o General discoverable mode.
  *     o Undirected connectable mode.
+ *     o Resolvable private address, if BLE_PRIVACY is enabled.
+ *     o Scan requests and connections from the bonded phones only, after the pairing window, if BLE_WHITELIST
+ *       is enabled.
+ * Does nothing if already advertising, if BLE_MAX_CONNECTIONS centrals are connected, or if the advertising
+ * is paused by ble_pause_advertising().
  */
 static void
 bleprph_advertise(void)
@@ -96,11 +182,18 @@ bleprph_advertise(void)
     uint8_t own_addr_type;
     struct ble_gap_adv_params adv_params;
     struct ble_hs_adv_fields fields;
+#if MYNEWT_VAL(SMP_BLE)
+    struct ble_hs_adv_fields rsp_fields;
+#endif  //  MYNEWT_VAL(SMP_BLE)
     const char *name;
     int rc;
 
-    /* Figure out address to use while advertising (no privacy for now) */
-    rc = ble_hs_id_infer_auto(0, &own_addr_type);
+    if (adv_paused || num_conns >= MYNEWT_VAL(BLE_MAX_CONNECTIONS) || ble_gap_adv_active()) {
+        return;
+    }
+
+    /* Figure out address to use while advertising: a resolvable private address if privacy is enabled */
+    rc = ble_hs_id_infer_auto(MYNEWT_VAL(BLE_PRIVACY), &own_addr_type);
     if (rc != 0) {
         MODLOG_DFLT_ERROR("error determining address type; rc=%d\n", rc);
         MODLOG_DFLT_FLUSH();
@@ -112,7 +205,7 @@ bleprph_advertise(void)
      *     o Flags (indicates advertisement type and other general info).
      *     o Advertising tx power.
      *     o Device name.
-     *     o 16-bit service UUIDs (alert notifications).
+     *     o 16-bit service UUIDs (alert notifications and sensors).
      */
 
     memset(&fields, 0, sizeof fields);
@@ -137,9 +230,12 @@ bleprph_advertise(void)
     fields.name_is_complete = 1;
 
     fields.uuids16 = (ble_uuid16_t[]){
-        BLE_UUID16_INIT(GATT_SVR_SVC_ALERT_UUID)
+        BLE_UUID16_INIT(GATT_SVR_SVC_ALERT_UUID),
+        BLE_UUID16_INIT(GATT_SVR_SVC_HEART_RATE_UUID),
+        BLE_UUID16_INIT(GATT_SVR_SVC_ENV_SENSING_UUID),
+        BLE_UUID16_INIT(GATT_SVR_SVC_BATTERY_UUID)
     };
-    fields.num_uuids16 = 1;
+    fields.num_uuids16 = 4;
     fields.uuids16_is_complete = 1;
 
     rc = ble_gap_adv_set_fields(&fields);
@@ -149,10 +245,30 @@ bleprph_advertise(void)
         return;
     }
 
+#if MYNEWT_VAL(SMP_BLE)
+    /* The SMP service UUID doesn't fit in the advertisement, so send it in the scan response. */
+    memset(&rsp_fields, 0, sizeof rsp_fields);
+    rsp_fields.uuids128 = (ble_uuid128_t *) &smp_svc_uuid;
+    rsp_fields.num_uuids128 = 1;
+    rsp_fields.uuids128_is_complete = 1;
+    rc = ble_gap_adv_rsp_set_fields(&rsp_fields);
+    if (rc != 0) {
+        MODLOG_DFLT_ERROR("error setting scan response data; rc=%d\n", rc);
+        MODLOG_DFLT_FLUSH();
+        return;
+    }
+#endif  //  MYNEWT_VAL(SMP_BLE)
+
     /* Begin advertising. */
     memset(&adv_params, 0, sizeof adv_params);
     adv_params.conn_mode = BLE_GAP_CONN_MODE_UND;
     adv_params.disc_mode = BLE_GAP_DISC_MODE_GEN;
+#if MYNEWT_VAL(BLE_WHITELIST)
+    /* After the pairing window, ignore the phones that are not bonded. If none is bonded, any phone may pair. */
+    if (!pairing_window_open && bleprph_set_whitelist() > 0) {
+        adv_params.filter_policy = BLE_HCI_ADV_FILT_BOTH;
+    }
+#endif  //  MYNEWT_VAL(BLE_WHITELIST)
     rc = ble_gap_adv_start(own_addr_type, NULL, BLE_HS_FOREVER,
                            &adv_params, bleprph_gap_event, NULL);
     if (rc != 0) {
@@ -204,6 +320,15 @@ bleprph_gap_event(struct ble_gap_event *event, void *arg)
             /* Connection failed; resume advertising. */
             bleprph_advertise();
         }
+        else {
+            /* NimBLE stops advertising when a central connects. Resume advertising for the next central. */
+            num_conns++;
+            bleprph_advertise();
+#if MYNEWT_VAL(SMP_BLE)
+            /* Firmware updates over SMP need an encrypted link: pair or restore the bond at once. */
+            ble_gap_security_initiate(event->connect.conn_handle);
+#endif  //  MYNEWT_VAL(SMP_BLE)
+        }
         return 0;
 
     case BLE_GAP_EVENT_DISCONNECT:
@@ -214,8 +339,12 @@ bleprph_gap_event(struct ble_gap_event *event, void *arg)
 #if MYNEWT_VAL(BLEPRPH_LE_PHY_SUPPORT)
         phy_conn_changed(CONN_HANDLE_INVALID);
 #endif
+        notify_client_disconnected(event->disconnect.conn.conn_handle);
 
-        /* Connection terminated; resume advertising. */
+        /* Connection terminated; resume advertising if stopped with all connections used. */
+        if (num_conns > 0) {
+            num_conns--;
+        }
         bleprph_advertise();
         MODLOG_DFLT_FLUSH();
         return 0;
@@ -247,6 +376,51 @@ bleprph_gap_event(struct ble_gap_event *event, void *arg)
         bleprph_print_conn_desc(&desc);
         MODLOG_DFLT_INFO("\n");
         MODLOG_DFLT_FLUSH();
+        if (passkey_shown) {
+            /* Replace the passkey on the screen by the pairing result. */
+            passkey_shown = 0;
+            pairing_show_result(event->enc_change.status);
+        }
+        if (event->enc_change.status == 0) {
+            /* The phone is bonded: read its clock to set the wall clock, then subscribe to its notifications. */
+            if (cts_client_read(event->enc_change.conn_handle) != 0) {
+                notify_client_start(event->enc_change.conn_handle);
+            }
+            /* Uploads need an encrypted link, so ask for a faster link now: 2M PHY, longer packets, larger MTU. */
+            ble_helper_high_throughput(event->enc_change.conn_handle);
+        }
+#if MYNEWT_VAL(SMP_BLE)
+        if (event->enc_change.status != 0) {
+            /* Don't allow firmware updates over an unencrypted link. */
+            ble_gap_terminate(event->enc_change.conn_handle, BLE_HCI_REASON_AUTH_FAIL);
+        }
+#endif  //  MYNEWT_VAL(SMP_BLE)
+        return 0;
+
+    case BLE_GAP_EVENT_PASSKEY_ACTION:
+        /* The watch is a display-only device: show a random passkey for the user to type on the phone. */
+        MODLOG_DFLT_INFO("passkey action event; action=%d\n", event->passkey.params.action);
+        if (event->passkey.params.action == BLE_SM_IOACT_DISP) {
+            struct ble_sm_io pkey;
+            uint32_t rand_val;
+
+            memset(&pkey, 0, sizeof pkey);
+            pkey.action = BLE_SM_IOACT_DISP;
+            rc = ble_hs_hci_util_rand(&rand_val, sizeof rand_val);
+            if (rc != 0) {
+                MODLOG_DFLT_ERROR("error generating passkey; rc=%d\n", rc);
+                MODLOG_DFLT_FLUSH();
+                return rc;
+            }
+            pkey.passkey = rand_val % 1000000;
+            passkey_shown = 1;
+            pairing_show_passkey(pkey.passkey);
+            rc = ble_sm_inject_io(event->passkey.conn_handle, &pkey);
+            if (rc != 0) {
+                MODLOG_DFLT_ERROR("error injecting passkey; rc=%d\n", rc);
+            }
+        }
+        MODLOG_DFLT_FLUSH();
         return 0;
 
     case BLE_GAP_EVENT_SUBSCRIBE:
@@ -260,6 +434,12 @@ bleprph_gap_event(struct ble_gap_event *event, void *arg)
                     event->subscribe.prev_indicate,
                     event->subscribe.cur_indicate);
         MODLOG_DFLT_FLUSH();
+        nus_subscribe(event->subscribe.conn_handle, event->subscribe.attr_handle, event->subscribe.cur_notify);
+        return 0;
+
+    case BLE_GAP_EVENT_NOTIFY_RX:
+        /* The phone has notified a characteristic that we subscribed to, e.g. a new phone notification. */
+        notify_client_rx(event->notify_rx.conn_handle, event->notify_rx.attr_handle, event->notify_rx.om);
         return 0;
 
     case BLE_GAP_EVENT_MTU:
@@ -315,8 +495,79 @@ bleprph_on_sync(void)
     rc = ble_hs_util_ensure_addr(0);
     assert(rc == 0);
 
+#if MYNEWT_VAL(SENSOR_BEACON)
+    /* Broadcast the sensor readings instead of advertising for connections. */
+    rc = sensor_beacon_start();
+    assert(rc == 0);
+#else
     /* Begin advertising. */
     bleprph_advertise();
+#endif  //  MYNEWT_VAL(SENSOR_BEACON)
+}
+
+/**
+ * Stops the connectable advertising if pause is 1, so that the radio only wakes for the connected centrals,
+ * or restarts it if pause is 0. The connections are kept. The sensor beacon is not paused. Called by the power
+ * manager in rust/app/src/power/manager.rs on the default event queue, like the GAP events.
+ *
+ * @return int 0
+ */
+int
+ble_pause_advertising(int pause)
+{
+    adv_paused = pause;
+    if (MYNEWT_VAL(SENSOR_BEACON) || !ble_hs_synced()) {
+        return 0;
+    }
+    if (pause) {
+        if (ble_gap_adv_active()) {
+            ble_gap_adv_stop();
+        }
+    } else {
+        bleprph_advertise();
+    }
+    MODLOG_DFLT_INFO("advertising %s\n", pause ? "paused" : "resumed");
+    return 0;
+}
+
+/**
+ * Sets the Device Information Service values that are only known at startup: the firmware version from the
+ * MCUBoot image header, the serial number from the nRF52 device ID in FICR, and the hardware revision from the
+ * nRF52 part and variant in FICR. The manufacturer and model are the BLE_SVC_DIS_*_DEFAULT values in syscfg.yml.
+ */
+static void
+bleprph_dis_init(void)
+{
+#if MYNEWT_VAL(BLE_SVC_DIS_FIRMWARE_REVISION_READ_PERM) >= 0
+    struct image_version ver;
+    static char ver_str[IMGMGR_NMGR_MAX_VER];
+#endif
+    static char serial_str[17];   /* 64-bit device ID in hex */
+    static char hw_rev_str[16];   /* e.g. "nRF52832 AAE0" */
+    uint32_t variant;
+
+#if MYNEWT_VAL(BLE_SVC_DIS_FIRMWARE_REVISION_READ_PERM) >= 0
+    /* Set firmware version in DIS */
+    imgr_my_version(&ver);
+    imgr_ver_str(&ver, ver_str);
+    ble_svc_dis_firmware_revision_set(ver_str);
+#endif
+
+#if MYNEWT_VAL(BLE_SVC_DIS_SOFTWARE_REVISION_READ_PERM) >= 0
+    /* Set the build string in DIS, e.g. "0.1.0+1b84bea3 2020-05-01T12:34:56Z" */
+    ble_svc_dis_software_revision_set(version_build_string());
+#endif
+
+    snprintf(serial_str, sizeof serial_str, "%08lx%08lx",
+             (unsigned long) NRF_FICR->DEVICEID[1], (unsigned long) NRF_FICR->DEVICEID[0]);
+    ble_svc_dis_serial_number_set(serial_str);
+
+    /* The variant is 4 ASCII characters, most significant first. */
+    variant = NRF_FICR->INFO.VARIANT;
+    snprintf(hw_rev_str, sizeof hw_rev_str, "nRF%lx %c%c%c%c",
+             (unsigned long) NRF_FICR->INFO.PART,
+             (char) (variant >> 24), (char) (variant >> 16), (char) (variant >> 8), (char) variant);
+    ble_svc_dis_hardware_revision_set(hw_rev_str);
 }
 
 /**
@@ -333,10 +584,6 @@ start_ble(void)
     MODLOG_DFLT_INFO("Starting BLE...\n");
     MODLOG_DFLT_FLUSH();
 
-#if MYNEWT_VAL(BLE_SVC_DIS_FIRMWARE_REVISION_READ_PERM) >= 0
-    struct image_version ver;
-    static char ver_str[IMGMGR_NMGR_MAX_VER];
-#endif
     int rc;
 
     /* Initialize the NimBLE host configuration. */
@@ -348,21 +595,31 @@ start_ble(void)
     rc = gatt_svr_init();
     assert(rc == 0);
 
+    rc = logo_svc_init();
+    assert(rc == 0);
+
+    rc = sensor_svc_init();
+    assert(rc == 0);
+
+    rc = nus_svc_init();
+    assert(rc == 0);
+
     /* Set the default device name. */
     rc = ble_svc_gap_device_name_set("pinetime");
     assert(rc == 0);
 
-#if MYNEWT_VAL(BLE_SVC_DIS_FIRMWARE_REVISION_READ_PERM) >= 0
-    /* Set firmware version in DIS */
-    imgr_my_version(&ver);
-    imgr_ver_str(&ver, ver_str);
-    ble_svc_dis_firmware_revision_set(ver_str);
-#endif
+    bleprph_dis_init();
 
 #if MYNEWT_VAL(BLEPRPH_LE_PHY_SUPPORT)
     phy_init();
 #endif
 
+#if MYNEWT_VAL(BLE_WHITELIST)
+    /* Let any phone pair during the pairing window after startup. */
+    os_callout_init(&pairing_window_timer, os_eventq_dflt_get(), bleprph_close_pairing_window, NULL);
+    os_callout_reset(&pairing_window_timer, MYNEWT_VAL(BLE_PAIRING_WINDOW_SECS) * OS_TICKS_PER_SEC);
+#endif  //  MYNEWT_VAL(BLE_WHITELIST)
+
     conf_load();
 
     /* If this app is acting as the loader in a split image setup, jump into
@@ -388,4 +645,9 @@ int start_ble(void) {
     //  Bluetooth LE not supported.
     return 0;
 }
+
+int ble_pause_advertising(int pause) {
+    //  Bluetooth LE not supported.
+    return 0;
+}
 #endif  //  MYNEWT_VAL(BLUETOOTH_LE)
\ No newline at end of file
@@ -0,0 +1,377 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Phone notification client: subscribes to the notifications of the bonded phone, so that the watch shows the
+//  title of each new notification. The phone service is discovered when the link is encrypted:
+//    Apple Notification Center Service (ANCS) on iOS: the Notification Source reports each new notification by UID,
+//      the title is requested on the Control Point, and returned on the Data Source, possibly over several
+//      notifications. Notifications that existed before the watch connected are skipped.
+//    Companion Notification Service otherwise, hosted by the companion app: the Notification Characteristic notifies
+//      `category:u8 title:[u8]` for each new notification.
+//  The category is the ANCS CategoryID in both cases. The titles are shown by phone_notify_show() in
+//  rust/app/src/phone_notify.rs. One phone is followed at a time.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BLUETOOTH_LE)  //  If Bluetooth LE is enabled...
+#include <string.h>
+#include "host/ble_hs.h"
+#include "host/ble_uuid.h"
+#include "os/endian.h"
+#include "ble_prph.h"
+
+/// Defined in rust/app/src/phone_notify.rs
+int phone_notify_show(uint8_t category, const uint8_t *title, uint16_t len);
+
+/// Max length of a title requested from ANCS. Must sync with `MAX_TITLE` in rust/app/src/phone_notify.rs.
+#define NOTIFY_TITLE_MAX 32
+
+/// ANCS Notification Source: EventID, EventFlags, CategoryID, CategoryCount, NotificationUID
+#define ANCS_NS_LEN              8
+#define ANCS_EVENT_ADDED         0
+#define ANCS_FLAG_PRE_EXISTING   (1 << 2)
+
+/// ANCS Control Point command and attribute for the title
+#define ANCS_CMD_GET_NOTIF_ATTRS 0
+#define ANCS_ATTR_TITLE          1
+
+/// ANCS Data Source response header: CommandID, NotificationUID, AttributeID, AttributeLength
+#define ANCS_DS_HEADER_LEN       8
+
+/// Client Characteristic Configuration value for enabling notifications
+#define CCCD_NOTIFY              0x0001
+
+/* 7905f431-b5ce-4e99-a40f-4b1e122d00d0: Apple Notification Center Service */
+static const ble_uuid128_t ancs_svc_uuid =
+    BLE_UUID128_INIT(0xd0, 0x00, 0x2d, 0x12, 0x1e, 0x4b, 0x0f, 0xa4,
+                     0x99, 0x4e, 0xce, 0xb5, 0x31, 0xf4, 0x05, 0x79);
+
+/* 9fbf120d-6301-42d9-8c58-25e699a21dbd: ANCS Notification Source */
+static const ble_uuid128_t ancs_ns_uuid =
+    BLE_UUID128_INIT(0xbd, 0x1d, 0xa2, 0x99, 0xe6, 0x25, 0x58, 0x8c,
+                     0xd9, 0x42, 0x01, 0x63, 0x0d, 0x12, 0xbf, 0x9f);
+
+/* 69d1d8f3-45e1-49a8-9821-9bbdfdaad9d9: ANCS Control Point */
+static const ble_uuid128_t ancs_cp_uuid =
+    BLE_UUID128_INIT(0xd9, 0xd9, 0xaa, 0xfd, 0xbd, 0x9b, 0x21, 0x98,
+                     0xa8, 0x49, 0xe1, 0x45, 0xf3, 0xd8, 0xd1, 0x69);
+
+/* 22eac6e9-24d6-4bb5-be44-b36ace7c7bfb: ANCS Data Source */
+static const ble_uuid128_t ancs_ds_uuid =
+    BLE_UUID128_INIT(0xfb, 0x7b, 0x7c, 0xce, 0x6a, 0xb3, 0x44, 0xbe,
+                     0xb5, 0x4b, 0xd6, 0x24, 0xe9, 0xc6, 0xea, 0x22);
+
+/* 6e6f7469-0000-4a6b-9a3d-2d5e8e1f0a00: Companion Notification Service */
+static const ble_uuid128_t companion_svc_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x00, 0x00, 0x69, 0x74, 0x6f, 0x6e);
+
+/* 6e6f7469-0001-4a6b-9a3d-2d5e8e1f0a00: Companion Notification Characteristic */
+static const ble_uuid128_t companion_chr_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x01, 0x00, 0x69, 0x74, 0x6f, 0x6e);
+
+/// Notification service found on the phone
+enum notify_kind {
+    NOTIFY_NONE,
+    NOTIFY_ANCS,
+    NOTIFY_COMPANION,
+};
+
+/// Phone that is followed, and the handles discovered on it
+static struct {
+    uint16_t conn_handle;
+    enum notify_kind kind;
+    uint16_t svc_start;
+    uint16_t svc_end;
+    /// Value handles of the ANCS Notification Source (or the Companion Notification), Control Point and Data Source
+    uint16_t ns_val;
+    uint16_t cp_val;
+    uint16_t ds_val;
+    /// Client Characteristic Configuration handles of the Notification Source and Data Source
+    uint16_t ns_cccd;
+    uint16_t ds_cccd;
+} client = { .conn_handle = BLE_HS_CONN_HANDLE_NONE };
+
+/// NotificationUID and CategoryID of the title requested from ANCS
+static uint32_t pending_uid;
+static uint8_t pending_category;
+
+/// Data Source response received so far
+static uint8_t ds_buf[ANCS_DS_HEADER_LEN + NOTIFY_TITLE_MAX];
+static uint16_t ds_len;
+
+static int notify_svc_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                         const struct ble_gatt_svc *service, void *arg);
+static int notify_chr_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                         const struct ble_gatt_chr *chr, void *arg);
+static int notify_dsc_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                         uint16_t chr_val_handle, const struct ble_gatt_dsc *dsc, void *arg);
+static int notify_subscribe_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                               struct ble_gatt_attr *attr, void *arg);
+
+/// Discover the notification service of the phone connected at `conn_handle`, then subscribe to its notifications.
+/// Returns 0 if the discovery has been started. Called when the link is encrypted, after the Current Time has been
+/// read, since ANCS needs an encrypted link.
+int
+notify_client_start(uint16_t conn_handle)
+{
+    memset(&client, 0, sizeof client);
+    client.conn_handle = conn_handle;
+    ds_len = 0;
+    return ble_gattc_disc_svc_by_uuid(conn_handle, &ancs_svc_uuid.u, notify_svc_cb, NULL);
+}
+
+/// Remember the service range. When ANCS is not found, look for the Companion Notification Service.
+static int
+notify_svc_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+              const struct ble_gatt_svc *service, void *arg)
+{
+    switch (error->status) {
+    case 0:
+        client.kind = (ble_uuid_cmp(&service->uuid.u, &ancs_svc_uuid.u) == 0) ? NOTIFY_ANCS : NOTIFY_COMPANION;
+        client.svc_start = service->start_handle;
+        client.svc_end = service->end_handle;
+        return 0;
+
+    case BLE_HS_EDONE:
+        if (client.kind != NOTIFY_NONE) {
+            return ble_gattc_disc_all_chrs(conn_handle, client.svc_start, client.svc_end, notify_chr_cb, NULL);
+        }
+        if (arg == NULL) {
+            /* No ANCS: try the companion app. */
+            return ble_gattc_disc_svc_by_uuid(conn_handle, &companion_svc_uuid.u, notify_svc_cb,
+                                              (void *) &companion_svc_uuid);
+        }
+        MODLOG_DFLT(INFO, "notify: no notification service\n");
+        return 0;
+
+    default:
+        MODLOG_DFLT(INFO, "notify: service discovery failed; status=%d\n", error->status);
+        return 0;
+    }
+}
+
+/// Remember the value handles of the characteristics, then discover their descriptors
+static int
+notify_chr_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+              const struct ble_gatt_chr *chr, void *arg)
+{
+    switch (error->status) {
+    case 0:
+        if (ble_uuid_cmp(&chr->uuid.u, &ancs_ns_uuid.u) == 0 ||
+            ble_uuid_cmp(&chr->uuid.u, &companion_chr_uuid.u) == 0) {
+            client.ns_val = chr->val_handle;
+        } else if (ble_uuid_cmp(&chr->uuid.u, &ancs_cp_uuid.u) == 0) {
+            client.cp_val = chr->val_handle;
+        } else if (ble_uuid_cmp(&chr->uuid.u, &ancs_ds_uuid.u) == 0) {
+            client.ds_val = chr->val_handle;
+        }
+        return 0;
+
+    case BLE_HS_EDONE:
+        if (client.ns_val == 0 ||
+            (client.kind == NOTIFY_ANCS && (client.cp_val == 0 || client.ds_val == 0))) {
+            MODLOG_DFLT(INFO, "notify: characteristics missing\n");
+            return 0;
+        }
+        return ble_gattc_disc_all_dscs(conn_handle, client.svc_start, client.svc_end, notify_dsc_cb, NULL);
+
+    default:
+        MODLOG_DFLT(INFO, "notify: characteristic discovery failed; status=%d\n", error->status);
+        return 0;
+    }
+}
+
+/// Return the value handle of the discovered characteristic that is nearest before `handle`, or 0 if none
+static uint16_t
+notify_chr_before(uint16_t handle)
+{
+    uint16_t vals[3] = { client.ns_val, client.cp_val, client.ds_val };
+    uint16_t owner = 0;
+    int i;
+
+    for (i = 0; i < 3; i++) {
+        if (vals[i] != 0 && vals[i] < handle && vals[i] > owner) {
+            owner = vals[i];
+        }
+    }
+    return owner;
+}
+
+/// Remember the Client Characteristic Configuration handles, each following its characteristic value, then
+/// subscribe to the Data Source before the Notification Source, so that no title response is missed
+static int
+notify_dsc_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+              uint16_t chr_val_handle, const struct ble_gatt_dsc *dsc, void *arg)
+{
+    uint16_t value = htole16(CCCD_NOTIFY);
+    uint16_t owner;
+
+    switch (error->status) {
+    case 0:
+        if (ble_uuid_cmp(&dsc->uuid.u, BLE_UUID16_DECLARE(BLE_GATT_DSC_CLT_CFG_UUID16)) != 0) {
+            return 0;
+        }
+        /* The descriptor belongs to the nearest characteristic value before it. */
+        owner = notify_chr_before(dsc->handle);
+        if (owner != 0 && owner == client.ns_val) {
+            client.ns_cccd = dsc->handle;
+        } else if (owner != 0 && owner == client.ds_val) {
+            client.ds_cccd = dsc->handle;
+        }
+        return 0;
+
+    case BLE_HS_EDONE:
+        if (client.kind == NOTIFY_ANCS && client.ds_cccd != 0) {
+            return ble_gattc_write_flat(conn_handle, client.ds_cccd, &value, sizeof value,
+                                        notify_subscribe_cb, NULL);
+        }
+        if (client.ns_cccd != 0) {
+            return ble_gattc_write_flat(conn_handle, client.ns_cccd, &value, sizeof value,
+                                        notify_subscribe_cb, NULL);
+        }
+        MODLOG_DFLT(INFO, "notify: no client configuration\n");
+        return 0;
+
+    default:
+        MODLOG_DFLT(INFO, "notify: descriptor discovery failed; status=%d\n", error->status);
+        return 0;
+    }
+}
+
+/// After subscribing to the ANCS Data Source, subscribe to the Notification Source
+static int
+notify_subscribe_cb(uint16_t conn_handle, const struct ble_gatt_error *error,
+                    struct ble_gatt_attr *attr, void *arg)
+{
+    uint16_t value = htole16(CCCD_NOTIFY);
+
+    if (error->status != 0) {
+        MODLOG_DFLT(INFO, "notify: subscribe failed; status=%d\n", error->status);
+        return 0;
+    }
+    if (attr->handle == client.ds_cccd && client.ns_cccd != 0) {
+        return ble_gattc_write_flat(conn_handle, client.ns_cccd, &value, sizeof value,
+                                    notify_subscribe_cb, NULL);
+    }
+    MODLOG_DFLT(INFO, "notify: subscribed\n");
+    return 0;
+}
+
+/// Ask ANCS for the title of the notification
+static int
+ancs_request_title(uint16_t conn_handle, uint32_t uid, uint8_t category)
+{
+    uint8_t cmd[8];
+
+    cmd[0] = ANCS_CMD_GET_NOTIF_ATTRS;
+    put_le32(&cmd[1], uid);
+    cmd[5] = ANCS_ATTR_TITLE;
+    put_le16(&cmd[6], NOTIFY_TITLE_MAX);
+    pending_uid = uid;
+    pending_category = category;
+    ds_len = 0;
+    return ble_gattc_write_flat(conn_handle, client.cp_val, cmd, sizeof cmd, NULL, NULL);
+}
+
+/// Append the Data Source fragment, and show the title when the response is complete
+static void
+ancs_data_rx(const uint8_t *data, uint16_t len)
+{
+    uint16_t title_len;
+
+    if (ds_len + len > sizeof ds_buf) {
+        /* Longer than requested: drop the response. */
+        ds_len = 0;
+        return;
+    }
+    memcpy(&ds_buf[ds_len], data, len);
+    ds_len += len;
+    if (ds_len < ANCS_DS_HEADER_LEN) {
+        return;
+    }
+    title_len = get_le16(&ds_buf[6]);
+    if (ds_buf[0] != ANCS_CMD_GET_NOTIF_ATTRS || ds_buf[5] != ANCS_ATTR_TITLE || title_len > NOTIFY_TITLE_MAX) {
+        ds_len = 0;
+        return;
+    }
+    if (ds_len < ANCS_DS_HEADER_LEN + title_len) {
+        return;
+    }
+    phone_notify_show(get_le32(&ds_buf[1]) == pending_uid ? pending_category : 0,
+                      &ds_buf[ANCS_DS_HEADER_LEN], title_len);
+    ds_len = 0;
+}
+
+/// Handle a notification received from the phone. Returns 0 if it was for the notification client.
+/// Called by ble_main.c for each BLE_GAP_EVENT_NOTIFY_RX.
+int
+notify_client_rx(uint16_t conn_handle, uint16_t attr_handle, struct os_mbuf *om)
+{
+    uint8_t buf[MYNEWT_VAL(BLE_ATT_PREFERRED_MTU)];
+    uint16_t len;
+    int rc;
+
+    if (conn_handle != client.conn_handle || attr_handle == 0 ||
+        (attr_handle != client.ns_val && attr_handle != client.ds_val)) {
+        return BLE_HS_ENOENT;
+    }
+    rc = ble_hs_mbuf_to_flat(om, buf, sizeof buf, &len);
+    if (rc != 0) {
+        return rc;
+    }
+    if (client.kind == NOTIFY_COMPANION) {
+        if (len >= 1) {
+            phone_notify_show(buf[0], &buf[1], len - 1);
+        }
+        return 0;
+    }
+    if (attr_handle == client.ds_val) {
+        ancs_data_rx(buf, len);
+        return 0;
+    }
+    if (len >= ANCS_NS_LEN && buf[0] == ANCS_EVENT_ADDED && !(buf[1] & ANCS_FLAG_PRE_EXISTING)) {
+        return ancs_request_title(conn_handle, get_le32(&buf[4]), buf[2]);
+    }
+    return 0;
+}
+
+/// Stop following the phone if it has disconnected. Called by ble_main.c for each disconnect.
+void
+notify_client_disconnected(uint16_t conn_handle)
+{
+    if (conn_handle == client.conn_handle) {
+        memset(&client, 0, sizeof client);
+        client.conn_handle = BLE_HS_CONN_HANDLE_NONE;
+    }
+}
+
+#else  //  If Bluetooth LE is disabled...
+
+int notify_client_start(uint16_t conn_handle) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+int notify_client_rx(uint16_t conn_handle, uint16_t attr_handle, struct os_mbuf *om) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+void notify_client_disconnected(uint16_t conn_handle) {}
+#endif  //  MYNEWT_VAL(BLUETOOTH_LE)
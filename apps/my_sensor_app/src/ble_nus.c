@@ -0,0 +1,362 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Nordic UART Service (NUS) console: bridges the console and the shell to a phone over Bluetooth LE, so that the
+//  logs can be read and the shell commands can be run when no debugger or serial port is connected. Works with the
+//  UART terminals in nRF Toolbox, nRF Connect and Serial Bluetooth Terminal.
+//    RX Characteristic: The phone writes shell command lines, terminated by a newline. Each line is executed by
+//                       the Mynewt shell, like `sensor list` in apps/my_sensor_app/src/sensor_shell.c.
+//    TX Characteristic: The console output, including the logs and the shell output, is notified to the phone
+//                       that subscribed, in chunks of up to the ATT MTU minus 3 bytes, while the console sink
+//                       `nus` is selected, e.g. with the shell command `console use nus`.
+//  Both need an encrypted link with an authenticated (passkey) pairing, so that strangers can't read the logs
+//  or run commands.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BLE_NUS_CONSOLE)  //  If the Bluetooth LE console is enabled...
+#include <assert.h>
+#include <string.h>
+#include "os/mynewt.h"
+#include "host/ble_hs.h"
+#include "host/ble_uuid.h"
+#include "console/console.h"
+#include "shell/shell.h"
+#include "ble_prph.h"
+
+/// Size of the buffer for the console output that has not been notified yet. Output is dropped when full.
+#define NUS_TX_BUF_SIZE 1024
+
+/// Max size of a notification: the preferred ATT MTU minus the 3-byte ATT header
+#define NUS_TX_MAX_CHUNK (MYNEWT_VAL(BLE_ATT_PREFERRED_MTU) - 3)
+
+/// Delay before notifying the console output, so that the console writes are batched into fewer notifications
+#define NUS_TX_DELAY_MS 20
+
+/// Max length of a shell command line
+#define NUS_RX_LINE_LEN 128
+
+/// Max number of arguments in a shell command line, including the command
+#define NUS_MAX_ARGS 8
+
+/* 6e400001-b5a3-f393-e0a9-e50e24dcca9e: Nordic UART Service */
+static const ble_uuid128_t nus_svc_uuid =
+    BLE_UUID128_INIT(0x9e, 0xca, 0xdc, 0x24, 0x0e, 0xe5, 0xa9, 0xe0,
+                     0x93, 0xf3, 0xa3, 0xb5, 0x01, 0x00, 0x40, 0x6e);
+
+/* 6e400002-b5a3-f393-e0a9-e50e24dcca9e: RX Characteristic, written by the phone */
+static const ble_uuid128_t nus_chr_rx_uuid =
+    BLE_UUID128_INIT(0x9e, 0xca, 0xdc, 0x24, 0x0e, 0xe5, 0xa9, 0xe0,
+                     0x93, 0xf3, 0xa3, 0xb5, 0x02, 0x00, 0x40, 0x6e);
+
+/* 6e400003-b5a3-f393-e0a9-e50e24dcca9e: TX Characteristic, notified to the phone */
+static const ble_uuid128_t nus_chr_tx_uuid =
+    BLE_UUID128_INIT(0x9e, 0xca, 0xdc, 0x24, 0x0e, 0xe5, 0xa9, 0xe0,
+                     0x93, 0xf3, 0xa3, 0xb5, 0x03, 0x00, 0x40, 0x6e);
+
+/// Handle of the TX Characteristic value, for sending notifications
+static uint16_t nus_tx_val_handle;
+
+/// Connection that subscribed to the TX Characteristic
+static uint16_t nus_conn_handle = BLE_HS_CONN_HANDLE_NONE;
+
+/// Ring buffer of console output to be notified. Written by any task, read by the default event queue.
+static uint8_t nus_tx_buf[NUS_TX_BUF_SIZE];
+static uint16_t nus_tx_head;  //  Next byte to be written
+static uint16_t nus_tx_tail;  //  Next byte to be notified
+
+/// Chunk of console output being notified
+static uint8_t nus_tx_chunk[NUS_TX_MAX_CHUNK];
+
+/// Timer that notifies the console output on the default event queue
+static struct os_callout nus_tx_timer;
+
+/// Shell command line being written by the phone, terminated by null
+static char nus_rx_line[NUS_RX_LINE_LEN + 1];
+static uint16_t nus_rx_len;
+
+/// 1 if the command line being written is longer than NUS_RX_LINE_LEN and will be rejected
+static int nus_rx_overflow;
+
+static int
+nus_chr_access(uint16_t conn_handle, uint16_t attr_handle,
+               struct ble_gatt_access_ctxt *ctxt, void *arg);
+
+static const struct ble_gatt_svc_def nus_svcs[] = {
+    {
+        /*** Service: Nordic UART. */
+        .type = BLE_GATT_SVC_TYPE_PRIMARY,
+        .uuid = &nus_svc_uuid.u,
+        .characteristics = (struct ble_gatt_chr_def[]) { {
+            /*** Characteristic: RX. Shell command lines written by the phone. */
+            .uuid = &nus_chr_rx_uuid.u,
+            .access_cb = nus_chr_access,
+            .flags = BLE_GATT_CHR_F_WRITE | BLE_GATT_CHR_F_WRITE_NO_RSP |
+                     BLE_GATT_CHR_F_WRITE_ENC | BLE_GATT_CHR_F_WRITE_AUTHEN,
+        }, {
+            /*** Characteristic: TX. Console output notified to the phone. */
+            .uuid = &nus_chr_tx_uuid.u,
+            .access_cb = nus_chr_access,
+            .val_handle = &nus_tx_val_handle,
+            .flags = BLE_GATT_CHR_F_NOTIFY,
+        }, {
+            0, /* No more characteristics in this service. */
+        } },
+    },
+
+    {
+        0, /* No more services. */
+    },
+};
+
+/// Append the console output to the ring buffer and schedule the notification. Called by the console sink `nus` in
+/// rust/app/src/console_sinks.rs for all output, in any task or interrupt. Drops the output if no phone has
+/// subscribed or if the buffer is full.
+void
+nus_console_write(const char *buffer, unsigned int length)
+{
+    unsigned int i;
+    uint16_t next;
+    os_sr_t sr;
+
+    if (nus_conn_handle == BLE_HS_CONN_HANDLE_NONE) {
+        return;
+    }
+    OS_ENTER_CRITICAL(sr);
+    for (i = 0; i < length; i++) {
+        next = (nus_tx_head + 1) % NUS_TX_BUF_SIZE;
+        if (next == nus_tx_tail) {
+            break;  //  Buffer is full.
+        }
+        nus_tx_buf[nus_tx_head] = buffer[i];
+        nus_tx_head = next;
+    }
+    OS_EXIT_CRITICAL(sr);
+    if (!os_callout_queued(&nus_tx_timer)) {
+        os_callout_reset(&nus_tx_timer, os_time_ms_to_ticks32(NUS_TX_DELAY_MS));
+    }
+}
+
+/// Notify the buffered console output to the subscribed phone. If the controller is out of buffers, retry later.
+static void
+nus_tx_flush(struct os_event *ev)
+{
+    struct os_mbuf *om;
+    uint16_t max_len;
+    uint16_t len;
+    uint16_t tail;
+    os_sr_t sr;
+    int rc;
+
+    max_len = ble_att_mtu(nus_conn_handle);
+    if (max_len <= 3) {
+        return;  //  Not connected.
+    }
+    max_len -= 3;
+    if (max_len > NUS_TX_MAX_CHUNK) {
+        max_len = NUS_TX_MAX_CHUNK;
+    }
+    for (;;) {
+        /* Copy the next chunk without removing it, in case the notification fails. */
+        OS_ENTER_CRITICAL(sr);
+        tail = nus_tx_tail;
+        for (len = 0; len < max_len && tail != nus_tx_head; len++) {
+            nus_tx_chunk[len] = nus_tx_buf[tail];
+            tail = (tail + 1) % NUS_TX_BUF_SIZE;
+        }
+        OS_EXIT_CRITICAL(sr);
+        if (len == 0) {
+            return;  //  Nothing more to notify.
+        }
+
+        om = ble_hs_mbuf_from_flat(nus_tx_chunk, len);
+        if (om == NULL) {
+            break;
+        }
+        rc = ble_gattc_notify_custom(nus_conn_handle, nus_tx_val_handle, om);
+        if (rc != 0) {
+            break;
+        }
+        OS_ENTER_CRITICAL(sr);
+        nus_tx_tail = tail;
+        OS_EXIT_CRITICAL(sr);
+    }
+    /* Out of buffers. Retry later. */
+    os_callout_reset(&nus_tx_timer, os_time_ms_to_ticks32(NUS_TX_DELAY_MS));
+}
+
+/// Split the command line into arguments separated by spaces and execute the shell command.
+/// The output of the command is notified to the phone through the console.
+static void
+nus_exec(char *line)
+{
+    char *argv[NUS_MAX_ARGS + 1];
+    int argc;
+    int rc;
+
+    argc = 0;
+    while (*line != '\0') {
+        while (*line == ' ') {
+            *line++ = '\0';
+        }
+        if (*line == '\0') {
+            break;
+        }
+        if (argc == NUS_MAX_ARGS) {
+            console_printf("nus: too many arguments\n");
+            return;
+        }
+        argv[argc++] = line;
+        while (*line != ' ' && *line != '\0') {
+            line++;
+        }
+    }
+    if (argc == 0) {
+        return;  //  Empty line.
+    }
+    argv[argc] = NULL;
+
+    rc = shell_exec(argc, argv);
+    if (rc != 0) {
+        console_printf("%s: FAILED (%d)\n", argv[0], rc);
+    }
+    console_flush();
+}
+
+/// Append the bytes written by the phone to the command line. Execute the line when a newline is received.
+static void
+nus_rx(const uint8_t *data, uint16_t len)
+{
+    uint16_t i;
+    char c;
+
+    for (i = 0; i < len; i++) {
+        c = (char) data[i];
+        if (c == '\r' || c == '\n') {
+            if (nus_rx_overflow) {
+                console_printf("nus: command too long\n");
+            } else {
+                nus_rx_line[nus_rx_len] = '\0';
+                nus_exec(nus_rx_line);
+            }
+            nus_rx_len = 0;
+            nus_rx_overflow = 0;
+        } else if (nus_rx_len < NUS_RX_LINE_LEN) {
+            nus_rx_line[nus_rx_len++] = c;
+        } else {
+            nus_rx_overflow = 1;
+        }
+    }
+}
+
+/// Handle a write to the RX Characteristic
+static int
+nus_chr_access(uint16_t conn_handle, uint16_t attr_handle,
+               struct ble_gatt_access_ctxt *ctxt, void *arg)
+{
+    uint8_t buf[NUS_RX_LINE_LEN];
+    uint16_t len;
+    int rc;
+
+    if (ctxt->op != BLE_GATT_ACCESS_OP_WRITE_CHR) {
+        return BLE_ATT_ERR_UNLIKELY;
+    }
+    rc = ble_hs_mbuf_to_flat(ctxt->om, buf, sizeof(buf), &len);
+    if (rc != 0) {
+        return BLE_ATT_ERR_INVALID_ATTR_VALUE_LEN;
+    }
+    nus_rx(buf, len);
+    return 0;
+}
+
+/// Start or stop notifying the console output to the phone when it subscribes or unsubscribes to the TX
+/// Characteristic, or disconnects. Only a phone with an authenticated pairing receives the console output.
+/// Called by the GAP event handler in ble_main.c for each subscribe event.
+void
+nus_subscribe(uint16_t conn_handle, uint16_t attr_handle, int notify)
+{
+    struct ble_gap_conn_desc desc;
+    os_sr_t sr;
+    int rc;
+
+    if (attr_handle != nus_tx_val_handle) {
+        return;
+    }
+    if (notify) {
+        rc = ble_gap_conn_find(conn_handle, &desc);
+        if (rc != 0 || !desc.sec_state.encrypted || !desc.sec_state.authenticated) {
+            return;  //  Ignore the phones that have not paired with a passkey.
+        }
+        nus_conn_handle = conn_handle;
+    } else if (conn_handle == nus_conn_handle) {
+        nus_conn_handle = BLE_HS_CONN_HANDLE_NONE;
+        os_callout_stop(&nus_tx_timer);
+        OS_ENTER_CRITICAL(sr);
+        nus_tx_tail = nus_tx_head;  //  Drop the output that was not notified.
+        OS_EXIT_CRITICAL(sr);
+        nus_rx_len = 0;
+        nus_rx_overflow = 0;
+    }
+}
+
+/// Return 1 if the Bluetooth LE console is enabled. Called by the console sink `nus`.
+int
+nus_console_is_enabled(void)
+{
+    return 1;
+}
+
+/// Register the Nordic UART Service. Called by start_ble() before the host is synced.
+int
+nus_svc_init(void)
+{
+    int rc;
+
+    os_callout_init(&nus_tx_timer, os_eventq_dflt_get(), nus_tx_flush, NULL);
+
+    rc = ble_gatts_count_cfg(nus_svcs);
+    if (rc != 0) {
+        return rc;
+    }
+
+    rc = ble_gatts_add_svcs(nus_svcs);
+    if (rc != 0) {
+        return rc;
+    }
+
+    return 0;
+}
+
+#else  //  If the Bluetooth LE console is disabled...
+
+int nus_svc_init(void) {
+    //  Bluetooth LE console not supported.
+    return 0;
+}
+
+void nus_subscribe(uint16_t conn_handle, uint16_t attr_handle, int notify) {
+    //  Bluetooth LE console not supported.
+}
+
+void nus_console_write(const char *buffer, unsigned int length) {
+    //  Bluetooth LE console not supported.
+}
+
+int nus_console_is_enabled(void) {
+    return 0;
+}
+#endif  //  MYNEWT_VAL(BLE_NUS_CONSOLE)
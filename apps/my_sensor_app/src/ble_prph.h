@@ -29,6 +29,7 @@ extern "C" {
 
 struct ble_hs_cfg;
 struct ble_gatt_register_ctxt;
+struct os_mbuf;
 
 /** GATT server. */
 #define GATT_SVR_SVC_ALERT_UUID               0x1811
@@ -41,6 +42,39 @@ struct ble_gatt_register_ctxt;
 void gatt_svr_register_cb(struct ble_gatt_register_ctxt *ctxt, void *arg);
 int gatt_svr_init(void);
 
+/** Logo Upload Service. */
+int logo_svc_init(void);
+
+/** Standard sensor services. */
+#define GATT_SVR_SVC_HEART_RATE_UUID              0x180D
+#define GATT_SVR_CHR_HEART_RATE_MEASUREMENT_UUID  0x2A37
+#define GATT_SVR_CHR_BODY_SENSOR_LOCATION_UUID    0x2A38
+#define GATT_SVR_SVC_ENV_SENSING_UUID             0x181A
+#define GATT_SVR_CHR_TEMPERATURE_UUID             0x2A6E
+#define GATT_SVR_SVC_BATTERY_UUID                 0x180F
+
+int sensor_svc_init(void);
+
+/** Current Time Service client. */
+#define GATT_CTS_CHR_CURRENT_TIME_UUID            0x2A2B
+#define GATT_CTS_CHR_LOCAL_TIME_INFO_UUID         0x2A0F
+
+int cts_client_read(uint16_t conn_handle);
+
+/** Phone notification client: ANCS or the Companion Notification Service. */
+int notify_client_start(uint16_t conn_handle);
+int notify_client_rx(uint16_t conn_handle, uint16_t attr_handle, struct os_mbuf *om);
+void notify_client_disconnected(uint16_t conn_handle);
+
+/** Nordic UART Service console. */
+int nus_svc_init(void);
+void nus_subscribe(uint16_t conn_handle, uint16_t attr_handle, int notify);
+void nus_console_write(const char *buffer, unsigned int length);
+int nus_console_is_enabled(void);
+
+/** Sensor beacon, defined in rust/app/src/beacon.rs. */
+int sensor_beacon_start(void);
+
 /* PHY support */
 #if MYNEWT_VAL(BLEPRPH_LE_PHY_SUPPORT)
 #define CONN_HANDLE_INVALID     0xffff
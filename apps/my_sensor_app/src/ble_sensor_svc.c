@@ -0,0 +1,290 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Standard GATT services for the sensors, so that off-the-shelf phone apps can read them without the CoAP server:
+//  Heart Rate Service for the heart rate, Environmental Sensing Service for the temperature, and Battery Service
+//  (from NimBLE) for the battery level. The readings are set by rust/app/src/ble_sensors.rs after each poll.
+//  Subscribed phones are notified by ble_gatts_chr_updated(). The Battery Service notifies only when the level
+//  changes, with BLE_SVC_BAS_BATTERY_LEVEL_NOTIFY_ENABLE in syscfg.yml.
+//  Also defines the Motion Stream Service, whose notifications carry the accelerometer samples streamed by
+//  rust/app/src/ble_sensors.rs: x, y, z in milli-g as little endian int16, one or more samples per notification.
+//  And the Link Quality Service, whose characteristic is set by rust/app/src/link_quality.rs while a phone is
+//  connected: signal strength of the weakest connection and transmit power on the advertising channels, in dBm as int8.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BLUETOOTH_LE)  //  If Bluetooth LE is enabled...
+#include <assert.h>
+#include <string.h>
+#include "host/ble_hs.h"
+#include "host/ble_uuid.h"
+#include "services/bas/ble_svc_bas.h"
+#include "ble_prph.h"
+
+/// Body Sensor Location: Wrist
+#define BODY_SENSOR_LOCATION_WRIST 2
+
+/// Heart Rate Measurement flags: 8-bit heart rate, sensor contact detected and supported
+#define HRM_FLAGS_CONTACT_DETECTED 0x06
+
+/// Latest heart rate in beats per minute, 0 if not measured yet
+static uint8_t heart_rate;
+
+/// Latest temperature in degrees Celsius times 100
+static int16_t temperature;
+
+/* 6d6f7469-0000-4a6b-9a3d-2d5e8e1f0a00: Motion Stream Service */
+static const ble_uuid128_t motion_svc_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x00, 0x00, 0x69, 0x74, 0x6f, 0x6d);
+
+/* 6d6f7469-0001-4a6b-9a3d-2d5e8e1f0a00: Motion Samples Characteristic */
+static const ble_uuid128_t motion_chr_samples_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x01, 0x00, 0x69, 0x74, 0x6f, 0x6d);
+
+/* 6c696e6b-0000-4a6b-9a3d-2d5e8e1f0a00: Link Quality Service */
+static const ble_uuid128_t link_svc_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x00, 0x00, 0x6b, 0x6e, 0x69, 0x6c);
+
+/* 6c696e6b-0001-4a6b-9a3d-2d5e8e1f0a00: Link Quality Characteristic */
+static const ble_uuid128_t link_chr_quality_uuid =
+    BLE_UUID128_INIT(0x00, 0x0a, 0x1f, 0x8e, 0x5e, 0x2d, 0x3d, 0x9a,
+                     0x6b, 0x4a, 0x01, 0x00, 0x6b, 0x6e, 0x69, 0x6c);
+
+/// Latest signal strength of the weakest connection and advertising transmit power in dBm, 0 if not measured yet
+static int8_t link_rssi;
+static int8_t link_adv_tx_power;
+
+/// Handles of the characteristic values, for sending notifications
+static uint16_t hrm_val_handle;
+static uint16_t temp_val_handle;
+static uint16_t motion_val_handle;
+static uint16_t link_val_handle;
+
+static int
+sensor_chr_access(uint16_t conn_handle, uint16_t attr_handle,
+                  struct ble_gatt_access_ctxt *ctxt, void *arg);
+
+static const struct ble_gatt_svc_def sensor_svcs[] = {
+    {
+        /*** Service: Heart Rate. */
+        .type = BLE_GATT_SVC_TYPE_PRIMARY,
+        .uuid = BLE_UUID16_DECLARE(GATT_SVR_SVC_HEART_RATE_UUID),
+        .characteristics = (struct ble_gatt_chr_def[]) { {
+            /*** Characteristic: Heart Rate Measurement. */
+            .uuid = BLE_UUID16_DECLARE(GATT_SVR_CHR_HEART_RATE_MEASUREMENT_UUID),
+            .access_cb = sensor_chr_access,
+            .val_handle = &hrm_val_handle,
+            .flags = BLE_GATT_CHR_F_NOTIFY,
+        }, {
+            /*** Characteristic: Body Sensor Location. */
+            .uuid = BLE_UUID16_DECLARE(GATT_SVR_CHR_BODY_SENSOR_LOCATION_UUID),
+            .access_cb = sensor_chr_access,
+            .flags = BLE_GATT_CHR_F_READ,
+        }, {
+            0, /* No more characteristics in this service. */
+        } },
+    },
+
+    {
+        /*** Service: Environmental Sensing. */
+        .type = BLE_GATT_SVC_TYPE_PRIMARY,
+        .uuid = BLE_UUID16_DECLARE(GATT_SVR_SVC_ENV_SENSING_UUID),
+        .characteristics = (struct ble_gatt_chr_def[]) { {
+            /*** Characteristic: Temperature. */
+            .uuid = BLE_UUID16_DECLARE(GATT_SVR_CHR_TEMPERATURE_UUID),
+            .access_cb = sensor_chr_access,
+            .val_handle = &temp_val_handle,
+            .flags = BLE_GATT_CHR_F_READ | BLE_GATT_CHR_F_NOTIFY,
+        }, {
+            0, /* No more characteristics in this service. */
+        } },
+    },
+
+    {
+        /*** Service: Motion Stream. */
+        .type = BLE_GATT_SVC_TYPE_PRIMARY,
+        .uuid = &motion_svc_uuid.u,
+        .characteristics = (struct ble_gatt_chr_def[]) { {
+            /*** Characteristic: Motion Samples. Notified by rust/app/src/ble_sensors.rs. */
+            .uuid = &motion_chr_samples_uuid.u,
+            .access_cb = sensor_chr_access,
+            .val_handle = &motion_val_handle,
+            .flags = BLE_GATT_CHR_F_NOTIFY,
+        }, {
+            0, /* No more characteristics in this service. */
+        } },
+    },
+
+    {
+        /*** Service: Link Quality. */
+        .type = BLE_GATT_SVC_TYPE_PRIMARY,
+        .uuid = &link_svc_uuid.u,
+        .characteristics = (struct ble_gatt_chr_def[]) { {
+            /*** Characteristic: Link Quality. Set by rust/app/src/link_quality.rs. */
+            .uuid = &link_chr_quality_uuid.u,
+            .access_cb = sensor_chr_access,
+            .val_handle = &link_val_handle,
+            .flags = BLE_GATT_CHR_F_READ | BLE_GATT_CHR_F_NOTIFY,
+        }, {
+            0, /* No more characteristics in this service. */
+        } },
+    },
+
+    {
+        0, /* No more services. */
+    },
+};
+
+/// Return the characteristic value. Multi-byte values are little endian.
+static int
+sensor_chr_access(uint16_t conn_handle, uint16_t attr_handle,
+                  struct ble_gatt_access_ctxt *ctxt, void *arg)
+{
+    uint16_t uuid;
+    uint8_t buf[2];
+    int rc;
+
+    if (ctxt->op != BLE_GATT_ACCESS_OP_READ_CHR) {
+        return BLE_ATT_ERR_UNLIKELY;
+    }
+    if (attr_handle == link_val_handle) {
+        buf[0] = (uint8_t) link_rssi;
+        buf[1] = (uint8_t) link_adv_tx_power;
+        rc = os_mbuf_append(ctxt->om, buf, 2);
+        return rc == 0 ? 0 : BLE_ATT_ERR_INSUFFICIENT_RES;
+    }
+    if (ctxt->chr->uuid->type != BLE_UUID_TYPE_16) {
+        return BLE_ATT_ERR_UNLIKELY;
+    }
+    uuid = ble_uuid_u16(ctxt->chr->uuid);
+    switch (uuid) {
+    case GATT_SVR_CHR_HEART_RATE_MEASUREMENT_UUID:
+        buf[0] = HRM_FLAGS_CONTACT_DETECTED;
+        buf[1] = heart_rate;
+        rc = os_mbuf_append(ctxt->om, buf, 2);
+        break;
+    case GATT_SVR_CHR_BODY_SENSOR_LOCATION_UUID:
+        buf[0] = BODY_SENSOR_LOCATION_WRIST;
+        rc = os_mbuf_append(ctxt->om, buf, 1);
+        break;
+    case GATT_SVR_CHR_TEMPERATURE_UUID:
+        put_le16(buf, (uint16_t) temperature);
+        rc = os_mbuf_append(ctxt->om, buf, 2);
+        break;
+    default:
+        return BLE_ATT_ERR_UNLIKELY;
+    }
+    return rc == 0 ? 0 : BLE_ATT_ERR_INSUFFICIENT_RES;
+}
+
+/// Set the heart rate in beats per minute and notify the subscribed phones. Returns 0 if successful.
+/// Called by rust/app/src/ble_sensors.rs.
+int
+sensor_svc_set_heart_rate(uint8_t bpm)
+{
+    heart_rate = bpm;
+    ble_gatts_chr_updated(hrm_val_handle);
+    return 0;
+}
+
+/// Set the temperature in degrees Celsius times 100 and notify the subscribed phones. Returns 0 if successful.
+/// Called by rust/app/src/ble_sensors.rs.
+int
+sensor_svc_set_temperature(int16_t temp)
+{
+    temperature = temp;
+    ble_gatts_chr_updated(temp_val_handle);
+    return 0;
+}
+
+/// Set the battery level in percent and notify the subscribed phones if the level has changed.
+/// Returns 0 if successful. Called by rust/app/src/ble_sensors.rs.
+int
+sensor_svc_set_battery_level(uint8_t percent)
+{
+    return ble_svc_bas_battery_level_set(percent);
+}
+
+/// Set the signal strength of the weakest connection and the advertising transmit power in dBm, and notify the
+/// subscribed phones. Returns 0 if successful. Called by rust/app/src/link_quality.rs.
+int
+sensor_svc_set_link_quality(int8_t rssi, int8_t adv_tx_power)
+{
+    link_rssi = rssi;
+    link_adv_tx_power = adv_tx_power;
+    ble_gatts_chr_updated(link_val_handle);
+    return 0;
+}
+
+/// Return the handle of the Motion Samples value, for streaming the notifications.
+/// Called by rust/app/src/ble_sensors.rs.
+uint16_t
+sensor_svc_motion_handle(void)
+{
+    return motion_val_handle;
+}
+
+/// Register the Heart Rate, Environmental Sensing, Motion Stream and Link Quality Services. The Battery Service is registered by NimBLE.
+/// Called by start_ble() before the host is synced.
+int
+sensor_svc_init(void)
+{
+    int rc;
+
+    rc = ble_gatts_count_cfg(sensor_svcs);
+    if (rc != 0) {
+        return rc;
+    }
+
+    rc = ble_gatts_add_svcs(sensor_svcs);
+    if (rc != 0) {
+        return rc;
+    }
+
+    return 0;
+}
+
+#else  //  If Bluetooth LE is disabled...
+
+int sensor_svc_set_heart_rate(uint8_t bpm) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+int sensor_svc_set_temperature(int16_t temp) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+int sensor_svc_set_battery_level(uint8_t percent) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+int sensor_svc_set_link_quality(int8_t rssi, int8_t adv_tx_power) {
+    //  Bluetooth LE not supported.
+    return -1;
+}
+
+uint16_t sensor_svc_motion_handle(void) {
+    //  Bluetooth LE not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(BLUETOOTH_LE)
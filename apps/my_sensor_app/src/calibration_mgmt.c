@@ -0,0 +1,110 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  newtmgr / SMP command group and shell command for calibrating the sensors. SMP commands are accepted over the
+//  serial port and Bluetooth LE. The calibration is done in rust/app/src/calibration.rs and saved in the settings.
+//  Commands in group CALIBRATION_MGMT_GROUP_ID:
+//    0 Accel (write): { } calibrates the accelerometer with the watch lying flat, face up, for 2 seconds.
+//                     Returns { "rc": int, "x": int, "y": int, "z": int } with the saved offsets.
+//  Also registers the shell command `calibrate accel`.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(CALIBRATION_MGMT)  //  If calibration commands are enabled...
+#include <string.h>
+#include "defs/error.h"
+#include "mgmt/mgmt.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// SMP group ID for the calibration commands: the group after the logo commands
+#define CALIBRATION_MGMT_GROUP_ID (MGMT_GROUP_ID_PERUSER + 1)
+
+/// SMP command IDs
+#define CALIBRATION_MGMT_ID_ACCEL 0
+
+/// Defined in rust/app/src/calibration.rs
+int calibration_run_accel(int32_t *offsets);
+
+static int calibration_mgmt_accel(struct mgmt_ctxt *ctxt);
+static int calibration_shell(int argc, char **argv);
+
+static const struct mgmt_handler calibration_mgmt_handlers[] = {
+    [CALIBRATION_MGMT_ID_ACCEL] = { .mh_read = NULL, .mh_write = calibration_mgmt_accel },
+};
+
+static struct mgmt_group calibration_mgmt_group = {
+    .mg_handlers       = calibration_mgmt_handlers,
+    .mg_handlers_count = sizeof(calibration_mgmt_handlers) / sizeof(calibration_mgmt_handlers[0]),
+    .mg_group_id       = CALIBRATION_MGMT_GROUP_ID,
+};
+
+static struct shell_cmd calibration_cmd = {
+    .sc_cmd      = "calibrate",
+    .sc_cmd_func = calibration_shell,
+};
+
+/// Register the calibration command group with SMP and the calibration shell command. Called by main() in rust/app/src/lib.rs.
+int start_calibration_mgmt(void) {
+    int rc = mgmt_register_group(&calibration_mgmt_group);
+    if (rc != 0) { return rc; }
+    return shell_cmd_register(&calibration_cmd);
+}
+
+/// Shell command `calibrate accel`: Calibrate the accelerometer with the watch lying flat
+static int calibration_shell(int argc, char **argv) {
+    int32_t offsets[3] = { 0 };
+    if (argc < 2 || strcmp(argv[1], "accel") != 0) {
+        console_printf("usage: calibrate accel\n");
+        return SYS_EINVAL;
+    }
+    int rc = calibration_run_accel(offsets);
+    if (rc != 0) {
+        console_printf("calibrate: FAILED (%d)%s\n", rc, (rc == SYS_EINVAL) ? ", lay the watch flat" : "");
+        return rc;
+    }
+    console_printf("calibrate: OK x=%ld y=%ld z=%ld\n", (long) offsets[0], (long) offsets[1], (long) offsets[2]);
+    return 0;
+}
+
+/// Accel: Calibrate the accelerometer and return the offsets
+static int calibration_mgmt_accel(struct mgmt_ctxt *ctxt) {
+    int32_t offsets[3] = { 0 };
+    int rc = calibration_run_accel(offsets);
+
+    CborError err = 0;
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "rc");
+    err |= cbor_encode_int(&ctxt->encoder, rc);
+    if (rc == 0) {
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "x");
+        err |= cbor_encode_int(&ctxt->encoder, offsets[0]);
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "y");
+        err |= cbor_encode_int(&ctxt->encoder, offsets[1]);
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "z");
+        err |= cbor_encode_int(&ctxt->encoder, offsets[2]);
+    }
+    if (err != 0) { return MGMT_ERR_ENOMEM; }
+    return 0;
+}
+
+#else  //  If calibration commands are disabled...
+
+int start_calibration_mgmt(void) {
+    //  Calibration commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(CALIBRATION_MGMT)
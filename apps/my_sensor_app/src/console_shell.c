@@ -0,0 +1,72 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Shell command for selecting the console sink that receives the console output, e.g. the UART on the bench or
+//  the Bluetooth LE console on a sealed watch. The commands are executed in rust/app/src/console_sinks.rs:
+//    console list            Lists the console sinks, marking the selected sink with *
+//    console use <name>      Sends the console output to the sink until the next restart
+//  The sink at startup is set with `newtmgr config app/console <name>`.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(CONSOLE_SHELL)  //  If console shell commands are enabled...
+#include <string.h>
+#include "defs/error.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// Defined in rust/app/src/console_sinks.rs
+int console_shell_list(void);
+int console_shell_use(const char *name);
+
+static int console_shell(int argc, char **argv);
+
+static struct shell_cmd console_cmd = {
+    .sc_cmd      = "console",
+    .sc_cmd_func = console_shell,
+};
+
+/// Register the console shell command. Called by main() in rust/app/src/lib.rs.
+int start_console_shell(void) {
+    return shell_cmd_register(&console_cmd);
+}
+
+/// Shell command `console list | use <name>`
+static int console_shell(int argc, char **argv) {
+    int rc;
+    if (argc >= 2 && strcmp(argv[1], "list") == 0) {
+        rc = console_shell_list();
+    } else if (argc >= 3 && strcmp(argv[1], "use") == 0) {
+        rc = console_shell_use(argv[2]);
+    } else {
+        console_printf("usage: console list | use <name>\n");
+        return SYS_EINVAL;
+    }
+    if (rc != 0) {
+        console_printf("console: FAILED (%d)%s\n", rc,
+            (rc == SYS_ENOENT) ? ", no such sink" : (rc == SYS_ENOTSUP) ? ", not enabled" : "");
+    }
+    return rc;
+}
+
+#else  //  If console shell commands are disabled...
+
+int start_console_shell(void) {
+    //  Console shell commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(CONSOLE_SHELL)
@@ -0,0 +1,84 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Shell command for the sensor and event logs, which keep the readings, alerts and warnings on the External SPI
+//  Flash across restarts. The commands are executed in rust/app/src/data_log.rs:
+//    datalog readings [count] Prints the newest <count> sensor readings, 20 by default
+//    datalog events [count]   Prints the newest <count> alerts and warnings, 20 by default
+//    datalog clear            Erases both logs
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(DATA_LOG_SHELL)  //  If data log shell commands are enabled...
+#include <stdlib.h>
+#include <string.h>
+#include "defs/error.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// Defined in rust/app/src/data_log.rs
+int data_log_shell_readings(uint32_t count);
+int data_log_shell_events(uint32_t count);
+int data_log_shell_clear(void);
+
+static int data_log_shell(int argc, char **argv);
+
+static struct shell_cmd data_log_cmd = {
+    .sc_cmd      = "datalog",
+    .sc_cmd_func = data_log_shell,
+};
+
+/// Register the data log shell command. Called by main() in rust/app/src/lib.rs.
+int start_data_log_shell(void) {
+    return shell_cmd_register(&data_log_cmd);
+}
+
+/// Shell command `datalog readings [count] | events [count] | clear`
+static int data_log_shell(int argc, char **argv) {
+    int rc;
+    unsigned long count = 0;
+    if (argc >= 3) {
+        char *end;
+        count = strtoul(argv[2], &end, 10);
+        if (*end != '\0') {
+            console_printf("datalog: invalid count %s\n", argv[2]);
+            return SYS_EINVAL;
+        }
+    }
+    if (argc >= 2 && strcmp(argv[1], "readings") == 0) {
+        rc = data_log_shell_readings((uint32_t) count);
+    } else if (argc >= 2 && strcmp(argv[1], "events") == 0) {
+        rc = data_log_shell_events((uint32_t) count);
+    } else if (argc >= 2 && strcmp(argv[1], "clear") == 0) {
+        rc = data_log_shell_clear();
+    } else {
+        console_printf("usage: datalog readings [count] | events [count] | clear\n");
+        return SYS_EINVAL;
+    }
+    if (rc != 0) {
+        console_printf("datalog: FAILED (%d)\n", rc);
+    }
+    return rc;
+}
+
+#else  //  If data log shell commands are disabled...
+
+int start_data_log_shell(void) {
+    //  Data log shell commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(DATA_LOG_SHELL)
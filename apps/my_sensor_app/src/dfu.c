@@ -0,0 +1,157 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Over-the-air firmware update with MCUBoot. The new image is uploaded into slot 1 (Standby) by the mcumgr
+//  image upload command over SMP. The upload progress is passed to rust/app/src/dfu.rs, which shows it on the
+//  screen. After the upload, the SHA256 hash of the image is verified against the hash TLV, the image is marked
+//  pending (test mode) and the watch restarts so that MCUBoot swaps it in. The image is confirmed by
+//  rust/app/src/dfu.rs after it has run for a while, else MCUBoot reverts to the previous image at the next restart.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(SMP_BLE)  //  If mcumgr / SMP over Bluetooth LE is enabled...
+#include <string.h>
+#include "os/mynewt.h"
+#include "defs/error.h"
+#include "flash_map/flash_map.h"
+#include "sysflash/sysflash.h"
+#include "bootutil/bootutil.h"
+#include "bootutil/image.h"
+#include "imgmgr/imgmgr.h"
+#include "tinycrypt/sha256.h"
+
+/// Defined in rust/app/src/dfu.rs
+void dfu_progress(uint32_t received, uint32_t size);
+
+/// Length of the SHA256 hash
+#define DFU_HASH_LEN 32
+
+/// Buffer for reading the image from flash while hashing
+static uint8_t dfu_buf[256];
+
+/// Called by imgmgr after each chunk of the image upload is written to slot 1
+static void dfu_upload_cb(uint32_t offset, uint32_t size, void *arg) {
+    dfu_progress(offset, size);
+}
+
+/// Report the image upload progress to rust/app/src/dfu.rs. Called by start() in rust/app/src/dfu.rs.
+int dfu_start(void) {
+    imgr_set_upload_cb(dfu_upload_cb, NULL);
+    return 0;
+}
+
+/// Hash `len` bytes of the flash area from offset 0 into `hash`
+static int dfu_hash(const struct flash_area *fa, uint32_t len, uint8_t *hash) {
+    struct tc_sha256_state_struct sha;
+    uint32_t off, chunk;
+    int rc;
+
+    tc_sha256_init(&sha);
+    for (off = 0; off < len; off += chunk) {
+        chunk = len - off;
+        if (chunk > sizeof(dfu_buf)) { chunk = sizeof(dfu_buf); }
+        rc = flash_area_read(fa, off, dfu_buf, chunk);
+        if (rc != 0) { return SYS_EIO; }
+        tc_sha256_update(&sha, dfu_buf, chunk);
+    }
+    tc_sha256_final(hash, &sha);
+    return 0;
+}
+
+/// Find the SHA256 hash TLV of the image with header `hdr` and copy it to `hash`
+static int dfu_find_hash_tlv(const struct flash_area *fa, const struct image_header *hdr, uint8_t *hash) {
+    struct image_tlv_info info;
+    struct image_tlv tlv;
+    uint32_t off, end;
+    int rc;
+
+    //  The unprotected TLV area follows the header, the image and the protected TLV area.
+    off = hdr->ih_hdr_size + hdr->ih_img_size + hdr->ih_protect_tlv_size;
+    rc = flash_area_read(fa, off, &info, sizeof(info));
+    if (rc != 0) { return SYS_EIO; }
+    if (info.it_magic != IMAGE_TLV_INFO_MAGIC) { return SYS_ENOENT; }
+    end = off + info.it_tlv_tot;
+    for (off += sizeof(info); off + sizeof(tlv) <= end; off += sizeof(tlv) + tlv.it_len) {
+        rc = flash_area_read(fa, off, &tlv, sizeof(tlv));
+        if (rc != 0) { return SYS_EIO; }
+        if (tlv.it_type == IMAGE_TLV_SHA256 && tlv.it_len == DFU_HASH_LEN) {
+            rc = flash_area_read(fa, off + sizeof(tlv), hash, DFU_HASH_LEN);
+            return (rc == 0) ? 0 : SYS_EIO;
+        }
+    }
+    return SYS_ENOENT;
+}
+
+/// Verify the SHA256 hash of the image in slot 1: the header, the image and the protected TLVs are hashed and
+/// compared with the hash TLV. Returns 0 if the hash matches, SYS_ENOENT if there's no image or no hash TLV,
+/// SYS_EINVAL if the hash doesn't match. The signature, if any, is verified by MCUBoot before swapping.
+int dfu_verify_standby(void) {
+    const struct flash_area *fa;
+    struct image_header hdr;
+    uint8_t hash[DFU_HASH_LEN];
+    uint8_t expected[DFU_HASH_LEN];
+    int rc;
+
+    rc = flash_area_open(FLASH_AREA_IMAGE_1, &fa);
+    if (rc != 0) { return SYS_ENODEV; }
+    rc = flash_area_read(fa, 0, &hdr, sizeof(hdr));
+    if (rc != 0) { rc = SYS_EIO; goto out; }
+    if (hdr.ih_magic != IMAGE_MAGIC) { rc = SYS_ENOENT; goto out; }
+
+    rc = dfu_find_hash_tlv(fa, &hdr, expected);
+    if (rc != 0) { goto out; }
+    rc = dfu_hash(fa, hdr.ih_hdr_size + hdr.ih_img_size + hdr.ih_protect_tlv_size, hash);
+    if (rc != 0) { goto out; }
+    rc = (memcmp(hash, expected, DFU_HASH_LEN) == 0) ? 0 : SYS_EINVAL;
+out:
+    flash_area_close(fa);
+    return rc;
+}
+
+/// Mark the image in slot 1 pending for a test swap at the next restart. Returns 0 if successful.
+int dfu_set_pending(void) {
+    return boot_set_pending(0);
+}
+
+/// Confirm the running image, so that MCUBoot doesn't revert it at the next restart. Returns 0 if successful.
+int dfu_confirm(void) {
+    return boot_set_confirmed();
+}
+
+#else  //  If mcumgr / SMP over Bluetooth LE is disabled...
+#include "defs/error.h"
+
+int dfu_start(void) {
+    //  Firmware update not supported.
+    return SYS_ENOTSUP;
+}
+
+int dfu_verify_standby(void) {
+    //  Firmware update not supported.
+    return SYS_ENOTSUP;
+}
+
+int dfu_set_pending(void) {
+    //  Firmware update not supported.
+    return SYS_ENOTSUP;
+}
+
+int dfu_confirm(void) {
+    //  Firmware update not supported.
+    return SYS_ENOTSUP;
+}
+#endif  //  MYNEWT_VAL(SMP_BLE)
@@ -0,0 +1,210 @@
+//  Deep Power-Down of the External SPI Flash. In Deep Power-Down the chip draws about one microamp instead of tens
+//  of microamps in standby, but ignores all commands except the release. rust/mynewt/src/hw/flash.rs powers down
+//  the chip when no operation is pending, and this file releases it before the next operation, including the
+//  operations of the C callers of the SPI Flash driver, e.g. the firmware update and the logs to flash. The
+//  hal_flash_*() functions are wrapped via the Linker Flags "-Wl,-wrap,hal_flash_read" etc. in
+//  apps/my_sensor_app/pkg.yml. The wrappers also lock the SPI bus, which the External SPI Flash shares with the
+//  display, so that the C callers don't select the chip in the middle of a display refresh. The chip is released at
+//  startup by the SPI Flash probe in hw/bsp/nrf52/src/spiflash_probe.c, in case a reset left it powered down.
+#include <sysinit/sysinit.h>  //  Contains all app settings consolidated from "apps/my_sensor_app/syscfg.yml"
+#include "os/mynewt.h"
+
+/// Flash device ID of the External SPI Flash, see hw/bsp/nrf52/src/hal_bsp.c
+#define EXTERNAL_FLASH_ID      1
+
+int __real_hal_flash_read(uint8_t flash_id, uint32_t address, void *dst, uint32_t num_bytes);
+int __real_hal_flash_write(uint8_t flash_id, uint32_t address, const void *src, uint32_t num_bytes);
+int __real_hal_flash_erase(uint8_t flash_id, uint32_t address, uint32_t num_bytes);
+int __real_hal_flash_erase_sector(uint8_t flash_id, uint32_t sector_address);
+
+static int begin_op(uint8_t flash_id);
+static void end_op(uint8_t flash_id, int locked);
+
+/// Lock the SPI bus for the SPI Flash driver. Returns 0 if locked, SYS_EBUSY if the calling task already holds the
+/// bus. Defined in rust/mynewt/src/hw/flash.rs
+int flash_bus_lock(void);
+
+/// Unlock the SPI bus locked by flash_bus_lock(). Defined in rust/mynewt/src/hw/flash.rs
+void flash_bus_unlock(void);
+
+#if MYNEWT_VAL(FLASH_POWER_DOWN)
+#include "hal/hal_gpio.h"
+#include "hal/hal_spi.h"
+
+//  SPI Flash commands
+#define CMD_DEEP_POWER_DOWN    0xB9  //  Enter deep power-down
+#define CMD_RELEASE_POWER_DOWN 0xAB  //  Wake up from deep power-down
+
+/// Time for the chip to leave deep power-down. tRES1 is at most 30 microseconds.
+#define RELEASE_TIME_US        30
+
+/// 1 while the chip is in deep power-down
+static int powered_down;
+
+/// Number of operations of the C callers in progress. The chip is not powered down while busy.
+static int busy;
+
+static void send_command(uint8_t cmd);
+
+/// Restart the power-down timer after an operation. Defined in rust/mynewt/src/hw/flash.rs
+void flash_power_activity(void);
+
+/// Put the chip into deep power-down. The caller must have locked the SPI bus and configured it for the chip.
+/// Returns SYS_EBUSY if an operation is in progress.
+int
+flash_power_down(void)
+{
+    os_sr_t sr;
+    int rc = 0;
+
+    OS_ENTER_CRITICAL(sr);
+    if (busy > 0) {
+        rc = SYS_EBUSY;
+    } else if (!powered_down) {
+        send_command(CMD_DEEP_POWER_DOWN);
+        powered_down = 1;
+    }
+    OS_EXIT_CRITICAL(sr);
+    return rc;
+}
+
+/// Release the chip from deep power-down and wait until it's ready. Does nothing if the chip is not powered down.
+void
+flash_power_release(void)
+{
+    os_sr_t sr;
+    int release;
+
+    OS_ENTER_CRITICAL(sr);
+    release = powered_down;
+    if (release) {
+        send_command(CMD_RELEASE_POWER_DOWN);
+        powered_down = 0;
+    }
+    OS_EXIT_CRITICAL(sr);
+    if (release) {
+        os_cputime_delay_usecs(RELEASE_TIME_US);
+    }
+}
+
+/// Return 1 if the chip is in deep power-down
+int
+flash_power_is_down(void)
+{
+    return powered_down;
+}
+
+/// Send the one-byte command `cmd` to the chip
+static void
+send_command(uint8_t cmd)
+{
+    hal_gpio_write(MYNEWT_VAL(SPIFLASH_SPI_CS_PIN), 0);
+    hal_spi_tx_val(MYNEWT_VAL(SPIFLASH_SPI_NUM), cmd);
+    hal_gpio_write(MYNEWT_VAL(SPIFLASH_SPI_CS_PIN), 1);
+}
+
+#else  //  If flash power-down is disabled...
+
+int
+flash_power_down(void)
+{
+    return SYS_ENOTSUP;
+}
+
+void
+flash_power_release(void)
+{
+}
+
+int
+flash_power_is_down(void)
+{
+    return 0;
+}
+#endif  //  MYNEWT_VAL(FLASH_POWER_DOWN)
+
+int
+__wrap_hal_flash_read(uint8_t flash_id, uint32_t address, void *dst, uint32_t num_bytes)
+{
+    int locked, rc;
+
+    locked = begin_op(flash_id);
+    rc = __real_hal_flash_read(flash_id, address, dst, num_bytes);
+    end_op(flash_id, locked);
+    return rc;
+}
+
+int
+__wrap_hal_flash_write(uint8_t flash_id, uint32_t address, const void *src, uint32_t num_bytes)
+{
+    int locked, rc;
+
+    locked = begin_op(flash_id);
+    rc = __real_hal_flash_write(flash_id, address, src, num_bytes);
+    end_op(flash_id, locked);
+    return rc;
+}
+
+int
+__wrap_hal_flash_erase(uint8_t flash_id, uint32_t address, uint32_t num_bytes)
+{
+    int locked, rc;
+
+    locked = begin_op(flash_id);
+    rc = __real_hal_flash_erase(flash_id, address, num_bytes);
+    end_op(flash_id, locked);
+    return rc;
+}
+
+int
+__wrap_hal_flash_erase_sector(uint8_t flash_id, uint32_t sector_address)
+{
+    int locked, rc;
+
+    locked = begin_op(flash_id);
+    rc = __real_hal_flash_erase_sector(flash_id, sector_address);
+    end_op(flash_id, locked);
+    return rc;
+}
+
+/// Lock the SPI bus, mark an operation in progress and release the chip before the operation. Returns 1 if the bus
+/// was locked by this call, 0 if the caller already holds the bus or the OS has not started.
+static int
+begin_op(uint8_t flash_id)
+{
+    int locked;
+
+    if (flash_id != EXTERNAL_FLASH_ID) { return 0; }
+    locked = (flash_bus_lock() == 0);
+#if MYNEWT_VAL(FLASH_POWER_DOWN)
+    {
+        os_sr_t sr;
+
+        OS_ENTER_CRITICAL(sr);
+        busy++;
+        OS_EXIT_CRITICAL(sr);
+        flash_power_release();
+    }
+#endif  //  MYNEWT_VAL(FLASH_POWER_DOWN)
+    return locked;
+}
+
+/// Mark the end of the operation, restart the power-down timer and unlock the SPI bus if `locked`
+static void
+end_op(uint8_t flash_id, int locked)
+{
+    if (flash_id != EXTERNAL_FLASH_ID) { return; }
+#if MYNEWT_VAL(FLASH_POWER_DOWN)
+    {
+        os_sr_t sr;
+
+        OS_ENTER_CRITICAL(sr);
+        busy--;
+        OS_EXIT_CRITICAL(sr);
+        flash_power_activity();
+    }
+#endif  //  MYNEWT_VAL(FLASH_POWER_DOWN)
+    if (locked) {
+        flash_bus_unlock();
+    }
+}
@@ -0,0 +1,62 @@
+//  Idle statistics: measure how long the CPU sleeps in the idle task, so that a task polling in a loop, which
+//  keeps the CPU out of System ON sleep, can be detected. Mynewt calls os_tick_idle() in the idle task with
+//  interrupts disabled when no task is ready. The nRF52 port is tickless: os_tick_idle() sets the RTC compare
+//  to the next timer and sleeps with WFI. The wrapper counts the sleeps and the RTC1 ticks slept. The wrapping
+//  is done via the Linker Flag "-Wl,-wrap,os_tick_idle" in apps/my_sensor_app/pkg.yml when IDLE_STATS is enabled.
+//  Read by idle_stats_get() in rust/mynewt/src/kernel/idle.rs.
+#include <sysinit/sysinit.h>  //  Contains all app settings consolidated from "apps/my_sensor_app/syscfg.yml"
+#include "os/mynewt.h"
+
+/// Idle statistics since startup. Must sync with `IdleStats` in rust/mynewt/src/kernel/idle.rs
+struct idle_stats {
+    /// Number of times the CPU went to sleep
+    uint32_t sleeps;
+    /// Total time asleep, in RTC ticks at 32768 Hz
+    uint64_t asleep_rtc;
+};
+
+#if MYNEWT_VAL(IDLE_STATS)
+#include "nrf.h"
+
+/// RTC1 counter is 24 bits
+#define RTC_COUNTER_MASK 0xffffff
+
+/// Statistics updated by the idle task, with interrupts disabled
+static struct idle_stats stats;
+
+void __real_os_tick_idle(os_time_t ticks);
+
+/// Sleep until the next timer or interrupt, and count the time asleep. RTC1 runs the OS tick, and keeps running
+/// while the CPU sleeps.
+void
+__wrap_os_tick_idle(os_time_t ticks)
+{
+    uint32_t start;
+
+    OS_ASSERT_CRITICAL();
+    start = NRF_RTC1->COUNTER;
+    __real_os_tick_idle(ticks);
+    stats.sleeps++;
+    stats.asleep_rtc += (NRF_RTC1->COUNTER - start) & RTC_COUNTER_MASK;
+}
+
+/// Copy the idle statistics to `out`
+void
+idle_stats_get(struct idle_stats *out)
+{
+    os_sr_t sr;
+
+    OS_ENTER_CRITICAL(sr);
+    *out = stats;
+    OS_EXIT_CRITICAL(sr);
+}
+
+#else  //  If idle statistics are disabled...
+
+void
+idle_stats_get(struct idle_stats *out)
+{
+    out->sleeps = 0;
+    out->asleep_rtc = 0;
+}
+#endif  //  MYNEWT_VAL(IDLE_STATS)
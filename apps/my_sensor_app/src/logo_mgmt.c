@@ -0,0 +1,369 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  newtmgr / SMP command group for managing the boot logos over the serial port (LOGO_SMP) or over Bluetooth LE
+//  with the SMP GATT service (SMP_BLE), so that desk tools and phone apps use the same protocol as for firmware
+//  updates. The requests are decoded here and forwarded to the Rust logo functions in rust/app/src/logo/serial.rs,
+//  which share the uploader with the Bluetooth LE logo service.
+//  Commands in group LOGO_MGMT_GROUP_ID (all are writes):
+//    0 Begin:  { "slot": uint, "len": uint, "crc": uint, "name": text, "ver": text (optional), "ts": uint (optional),
+//                "format": uint (optional, 1 for RGB565, 2 for heatshrink compressed,
+//                           3 for RGB888, 4 for RGB888 with dithering) }
+//    1 Chunk:  { "off": uint, "data": bytes, "sum": uint (optional, CRC16-CCITT of "data" with initial value 0) }
+//    2 Finish: { }
+//  Every response contains { "rc": int, "off": uint } where "off" is the number of bytes received so far.
+//  Command 3 (read) returns the manifest of a logo slot:
+//    3 Manifest: { "slot": uint } returns { "rc": int, "fmt": uint, "ver": text, "crc": uint, "ts": uint }
+//  Commands for the logo slots:
+//    4 List (read):    { } returns { "rc": int, "active": uint, "slots": [ { "slot": uint, "name": text, "len": uint,
+//                      "crc": uint }, ... ] } where "len" is 0 and "name" is empty for an empty slot
+//    5 Select (write): { "slot": uint } selects the logo for display by the bootloader, returns { "rc": int, "off": uint }
+//    6 Verify (read):  { "slot": uint } reads back the logo, returns { "rc": int, "crc": uint } where "rc" is
+//                      SYS_EIO if the CRC32 or the logo header is invalid
+//    7 Erase (write):  { "slot": uint } erases the logo, returns { "rc": int, "off": uint }. If the slot was selected,
+//                      the default slot is selected instead.
+//  With LOGO_SMP, also registers the shell command `logo_reset`, which erases all logos and restores the built-in logo.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(LOGO_SMP) || MYNEWT_VAL(SMP_BLE)  //  If logo upload over SMP is enabled...
+#include <string.h>
+#include "mgmt/mgmt.h"
+#include "cborattr/cborattr.h"
+#include "console/console.h"
+#if MYNEWT_VAL(LOGO_SMP)  //  If the serial shell is enabled...
+#include "shell/shell.h"
+#endif  //  MYNEWT_VAL(LOGO_SMP)
+
+/// SMP group ID for the logo commands: first user-defined group
+#define LOGO_MGMT_GROUP_ID MGMT_GROUP_ID_PERUSER
+
+/// SMP command IDs
+#define LOGO_MGMT_ID_BEGIN  0
+#define LOGO_MGMT_ID_CHUNK  1
+#define LOGO_MGMT_ID_FINISH 2
+#define LOGO_MGMT_ID_MANIFEST 3
+#define LOGO_MGMT_ID_LIST   4
+#define LOGO_MGMT_ID_SELECT 5
+#define LOGO_MGMT_ID_VERIFY 6
+#define LOGO_MGMT_ID_ERASE  7
+
+/// Number of logo slots. Must sync with `MAX_LOGO_SLOTS` in rust/app/src/logo/index.rs
+#define LOGO_MGMT_MAX_SLOTS 2
+
+/// Max size of a data chunk
+#define LOGO_MGMT_MAX_CHUNK 512
+
+/// Value of "sum" when the chunk has no CRC16, outside the range of CRC16
+#define NO_CHUNK_SUM 0x10000
+
+/// Max length of the logo name, including the terminating null
+#define LOGO_MGMT_NAME_SIZE 16
+
+/// Max length of the image version string, including the terminating null
+#define LOGO_MGMT_VERSION_SIZE 16
+
+/// Logo manifest. Must sync with `LogoManifest` in rust/app/src/logo/manifest.rs
+struct logo_manifest {
+    uint16_t format_version;
+    uint16_t reserved;
+    char     version[LOGO_MGMT_VERSION_SIZE];
+    uint32_t logo_crc;
+    uint32_t timestamp;
+    uint32_t manifest_crc;
+};
+
+/// Logo slot in the index table. Must sync with `LogoSlot` in rust/app/src/logo/index.rs
+struct logo_slot {
+    char     name[LOGO_MGMT_NAME_SIZE];
+    uint32_t offset;
+    uint32_t length;
+    uint32_t checksum;
+};
+
+/// Defined in rust/app/src/logo/serial.rs
+int logo_serial_begin(uint8_t slot, const uint8_t *name, uint16_t name_len, uint32_t length, uint32_t checksum);
+int logo_serial_chunk(uint32_t offset, const uint8_t *data, uint16_t len);
+int logo_serial_check_chunk(const uint8_t *data, uint16_t len, uint16_t sum);
+int logo_serial_finish(void);
+int logo_serial_set_manifest(const uint8_t *version, uint16_t version_len, uint32_t timestamp);
+int logo_serial_set_format(uint16_t format);
+int logo_serial_get_manifest(uint8_t slot, struct logo_manifest *dest);
+uint32_t logo_serial_received(void);
+int logo_serial_get_slot(uint8_t slot, struct logo_slot *dest);
+int logo_serial_active(void);
+int logo_serial_select(uint8_t slot);
+int logo_serial_verify(uint8_t slot, uint32_t *crc);
+int logo_serial_erase(uint8_t slot);
+
+/// Defined in rust/app/src/logo/reset.rs
+int logo_factory_reset(void);
+
+static int logo_mgmt_begin(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_chunk(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_finish(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_manifest(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_list(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_select(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_verify(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_erase(struct mgmt_ctxt *ctxt);
+static int logo_mgmt_read_slot(struct mgmt_ctxt *ctxt, uint8_t *slot);
+static int logo_mgmt_respond(struct mgmt_ctxt *ctxt, int rc);
+#if MYNEWT_VAL(LOGO_SMP)
+static int logo_shell_reset(int argc, char **argv);
+#endif  //  MYNEWT_VAL(LOGO_SMP)
+
+/// Buffer for the data chunk being received
+static uint8_t chunk_buf[LOGO_MGMT_MAX_CHUNK];
+
+static const struct mgmt_handler logo_mgmt_handlers[] = {
+    [LOGO_MGMT_ID_BEGIN]  = { .mh_read = NULL, .mh_write = logo_mgmt_begin },
+    [LOGO_MGMT_ID_CHUNK]  = { .mh_read = NULL, .mh_write = logo_mgmt_chunk },
+    [LOGO_MGMT_ID_FINISH] = { .mh_read = NULL, .mh_write = logo_mgmt_finish },
+    [LOGO_MGMT_ID_MANIFEST] = { .mh_read = logo_mgmt_manifest, .mh_write = NULL },
+    [LOGO_MGMT_ID_LIST]   = { .mh_read = logo_mgmt_list,   .mh_write = NULL },
+    [LOGO_MGMT_ID_SELECT] = { .mh_read = NULL, .mh_write = logo_mgmt_select },
+    [LOGO_MGMT_ID_VERIFY] = { .mh_read = logo_mgmt_verify, .mh_write = NULL },
+    [LOGO_MGMT_ID_ERASE]  = { .mh_read = NULL, .mh_write = logo_mgmt_erase },
+};
+
+static struct mgmt_group logo_mgmt_group = {
+    .mg_handlers       = logo_mgmt_handlers,
+    .mg_handlers_count = sizeof(logo_mgmt_handlers) / sizeof(logo_mgmt_handlers[0]),
+    .mg_group_id       = LOGO_MGMT_GROUP_ID,
+};
+
+#if MYNEWT_VAL(LOGO_SMP)
+static struct shell_cmd logo_reset_cmd = {
+    .sc_cmd      = "logo_reset",
+    .sc_cmd_func = logo_shell_reset,
+};
+#endif  //  MYNEWT_VAL(LOGO_SMP)
+
+/// Register the logo command group with SMP, which serves both the serial and Bluetooth LE transports,
+/// and the logo shell command. Called by main() in rust/app/src/lib.rs.
+int start_logo_mgmt(void) {
+    int rc = mgmt_register_group(&logo_mgmt_group);
+    if (rc != 0) { return rc; }
+#if MYNEWT_VAL(LOGO_SMP)
+    return shell_cmd_register(&logo_reset_cmd);
+#else
+    return 0;
+#endif  //  MYNEWT_VAL(LOGO_SMP)
+}
+
+#if MYNEWT_VAL(LOGO_SMP)
+/// Shell command `logo_reset`: Erase all logos and restore the built-in logo
+static int logo_shell_reset(int argc, char **argv) {
+    int rc = logo_factory_reset();
+    console_printf("logo_reset: %s (%d)\n", (rc == 0) ? "OK" : "FAILED", rc);
+    return rc;
+}
+#endif  //  MYNEWT_VAL(LOGO_SMP)
+
+/// Begin: Start uploading a logo
+static int logo_mgmt_begin(struct mgmt_ctxt *ctxt) {
+    uint64_t slot = 0, len = 0, crc = 0, ts = 0, format = 1;
+    char name[LOGO_MGMT_NAME_SIZE] = { 0 };
+    char ver[LOGO_MGMT_VERSION_SIZE] = { 0 };
+    const struct cbor_attr_t attrs[] = {
+        { .attribute = "slot", .type = CborAttrUnsignedIntegerType, .addr.uinteger = &slot, .nodefault = true },
+        { .attribute = "len",  .type = CborAttrUnsignedIntegerType, .addr.uinteger = &len,  .nodefault = true },
+        { .attribute = "crc",  .type = CborAttrUnsignedIntegerType, .addr.uinteger = &crc,  .nodefault = true },
+        { .attribute = "name", .type = CborAttrTextStringType, .addr.string = name, .len = sizeof(name) },
+        { .attribute = "ver",  .type = CborAttrTextStringType, .addr.string = ver,  .len = sizeof(ver) },
+        { .attribute = "ts",   .type = CborAttrUnsignedIntegerType, .addr.uinteger = &ts },
+        { .attribute = "format", .type = CborAttrUnsignedIntegerType, .addr.uinteger = &format },
+        { .attribute = NULL }
+    };
+    int rc = cbor_read_object(&ctxt->it, attrs);
+    if (rc != 0) { return MGMT_ERR_EINVAL; }
+    rc = logo_serial_begin(slot, (const uint8_t *) name, strlen(name), len, crc);
+    if (rc == 0) {
+        rc = logo_serial_set_manifest((const uint8_t *) ver, strlen(ver), ts);
+    }
+    if (rc == 0) {
+        rc = logo_serial_set_format(format);
+    }
+    return logo_mgmt_respond(ctxt, rc);
+}
+
+/// Chunk: Write a chunk of the logo
+static int logo_mgmt_chunk(struct mgmt_ctxt *ctxt) {
+    uint64_t off = 0;
+    uint64_t sum = NO_CHUNK_SUM;  //  Kept if "sum" is absent
+    size_t data_len = 0;
+    const struct cbor_attr_t attrs[] = {
+        { .attribute = "off", .type = CborAttrUnsignedIntegerType, .addr.uinteger = &off, .nodefault = true },
+        { .attribute = "data", .type = CborAttrByteStringType, .addr.bytestring.data = chunk_buf,
+          .addr.bytestring.len = &data_len, .len = sizeof(chunk_buf) },
+        { .attribute = "sum", .type = CborAttrUnsignedIntegerType, .addr.uinteger = &sum, .nodefault = true },
+        { .attribute = NULL }
+    };
+    int rc = cbor_read_object(&ctxt->it, attrs);
+    if (rc != 0) { return MGMT_ERR_EINVAL; }
+    if (sum != NO_CHUNK_SUM) {
+        //  Reject a chunk that was corrupted on the serial port, before it's written.
+        rc = logo_serial_check_chunk(chunk_buf, data_len, sum);
+        if (rc != 0) { return logo_mgmt_respond(ctxt, rc); }
+    }
+    rc = logo_serial_chunk(off, chunk_buf, data_len);
+    return logo_mgmt_respond(ctxt, rc);
+}
+
+/// Finish: Verify the logo and select it for display
+static int logo_mgmt_finish(struct mgmt_ctxt *ctxt) {
+    int rc = logo_serial_finish();
+    return logo_mgmt_respond(ctxt, rc);
+}
+
+/// Manifest: Return the manifest of a logo slot
+static int logo_mgmt_manifest(struct mgmt_ctxt *ctxt) {
+    uint64_t slot = 0;
+    struct logo_manifest manifest;
+    const struct cbor_attr_t attrs[] = {
+        { .attribute = "slot", .type = CborAttrUnsignedIntegerType, .addr.uinteger = &slot, .nodefault = true },
+        { .attribute = NULL }
+    };
+    int rc = cbor_read_object(&ctxt->it, attrs);
+    if (rc != 0) { return MGMT_ERR_EINVAL; }
+    memset(&manifest, 0, sizeof(manifest));
+    rc = logo_serial_get_manifest(slot, &manifest);
+
+    CborError err = 0;
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "rc");
+    err |= cbor_encode_int(&ctxt->encoder, rc);
+    if (rc == 0) {
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "fmt");
+        err |= cbor_encode_uint(&ctxt->encoder, manifest.format_version);
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "ver");
+        err |= cbor_encode_text_stringz(&ctxt->encoder, manifest.version);
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "crc");
+        err |= cbor_encode_uint(&ctxt->encoder, manifest.logo_crc);
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "ts");
+        err |= cbor_encode_uint(&ctxt->encoder, manifest.timestamp);
+    }
+    if (err != 0) { return MGMT_ERR_ENOMEM; }
+    return 0;
+}
+
+/// List: Return the logo slots and the slot selected for display
+static int logo_mgmt_list(struct mgmt_ctxt *ctxt) {
+    struct logo_slot entry;
+    CborEncoder slots;
+    CborEncoder slot_map;
+    CborError err = 0;
+    int active = logo_serial_active();
+    int rc = (active < 0) ? active : 0;
+
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "rc");
+    err |= cbor_encode_int(&ctxt->encoder, rc);
+    if (rc == 0) {
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "active");
+        err |= cbor_encode_uint(&ctxt->encoder, active);
+        err |= cbor_encode_text_stringz(&ctxt->encoder, "slots");
+        err |= cbor_encoder_create_array(&ctxt->encoder, &slots, CborIndefiniteLength);
+        for (uint8_t slot = 0; slot < LOGO_MGMT_MAX_SLOTS; slot++) {
+            memset(&entry, 0, sizeof(entry));
+            if (logo_serial_get_slot(slot, &entry) != 0) {
+                //  Empty or unreadable slot.
+                memset(&entry, 0, sizeof(entry));
+            }
+            entry.name[LOGO_MGMT_NAME_SIZE - 1] = '\0';
+            err |= cbor_encoder_create_map(&slots, &slot_map, CborIndefiniteLength);
+            err |= cbor_encode_text_stringz(&slot_map, "slot");
+            err |= cbor_encode_uint(&slot_map, slot);
+            err |= cbor_encode_text_stringz(&slot_map, "name");
+            err |= cbor_encode_text_stringz(&slot_map, entry.name);
+            err |= cbor_encode_text_stringz(&slot_map, "len");
+            err |= cbor_encode_uint(&slot_map, entry.length);
+            err |= cbor_encode_text_stringz(&slot_map, "crc");
+            err |= cbor_encode_uint(&slot_map, entry.checksum);
+            err |= cbor_encoder_close_container(&slots, &slot_map);
+        }
+        err |= cbor_encoder_close_container(&ctxt->encoder, &slots);
+    }
+    if (err != 0) { return MGMT_ERR_ENOMEM; }
+    return 0;
+}
+
+/// Select: Select the logo in a slot for display by the bootloader
+static int logo_mgmt_select(struct mgmt_ctxt *ctxt) {
+    uint8_t slot;
+    int rc = logo_mgmt_read_slot(ctxt, &slot);
+    if (rc != 0) { return rc; }
+    rc = logo_serial_select(slot);
+    return logo_mgmt_respond(ctxt, rc);
+}
+
+/// Verify: Read back the logo in a slot and check the CRC32 and the logo header
+static int logo_mgmt_verify(struct mgmt_ctxt *ctxt) {
+    uint8_t slot;
+    uint32_t crc = 0;
+    int rc = logo_mgmt_read_slot(ctxt, &slot);
+    if (rc != 0) { return rc; }
+    rc = logo_serial_verify(slot, &crc);
+
+    CborError err = 0;
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "rc");
+    err |= cbor_encode_int(&ctxt->encoder, rc);
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "crc");
+    err |= cbor_encode_uint(&ctxt->encoder, crc);
+    if (err != 0) { return MGMT_ERR_ENOMEM; }
+    return 0;
+}
+
+/// Erase: Erase the logo in a slot
+static int logo_mgmt_erase(struct mgmt_ctxt *ctxt) {
+    uint8_t slot;
+    int rc = logo_mgmt_read_slot(ctxt, &slot);
+    if (rc != 0) { return rc; }
+    rc = logo_serial_erase(slot);
+    return logo_mgmt_respond(ctxt, rc);
+}
+
+/// Decode the request { "slot": uint }. Returns 0 if successful, else MGMT_ERR_EINVAL.
+static int logo_mgmt_read_slot(struct mgmt_ctxt *ctxt, uint8_t *slot) {
+    uint64_t value = 0;
+    const struct cbor_attr_t attrs[] = {
+        { .attribute = "slot", .type = CborAttrUnsignedIntegerType, .addr.uinteger = &value, .nodefault = true },
+        { .attribute = NULL }
+    };
+    int rc = cbor_read_object(&ctxt->it, attrs);
+    if (rc != 0 || value >= LOGO_MGMT_MAX_SLOTS) { return MGMT_ERR_EINVAL; }
+    *slot = (uint8_t) value;
+    return 0;
+}
+
+/// Encode the response { "rc": rc, "off": bytes received }
+static int logo_mgmt_respond(struct mgmt_ctxt *ctxt, int rc) {
+    CborError err = 0;
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "rc");
+    err |= cbor_encode_int(&ctxt->encoder, rc);
+    err |= cbor_encode_text_stringz(&ctxt->encoder, "off");
+    err |= cbor_encode_uint(&ctxt->encoder, logo_serial_received());
+    if (err != 0) { return MGMT_ERR_ENOMEM; }
+    return 0;
+}
+
+#else  //  If logo upload over SMP is disabled...
+
+int start_logo_mgmt(void) {
+    //  Logo upload over SMP not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(LOGO_SMP) || MYNEWT_VAL(SMP_BLE)
@@ -0,0 +1,70 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Shell command for checking the memory usage, for spotting leaks and fragmentation on long-running watches.
+//  The usage is measured by rust/mynewt/src/diag/memory.rs:
+//    mem                     Shows the heap, mbuf and memory pool usage
+//    mem send                Sends the memory usage to the CoAP server
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(MEMORY_SHELL)  //  If memory shell commands are enabled...
+#include <string.h>
+#include "defs/error.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// Defined in rust/app/src/memory_report.rs
+int memory_shell_show(void);
+int memory_shell_send(void);
+
+static int memory_shell(int argc, char **argv);
+
+static struct shell_cmd memory_cmd = {
+    .sc_cmd      = "mem",
+    .sc_cmd_func = memory_shell,
+};
+
+/// Register the memory shell command. Called by main() in rust/app/src/lib.rs.
+int start_memory_shell(void) {
+    return shell_cmd_register(&memory_cmd);
+}
+
+/// Shell command `mem [send]`
+static int memory_shell(int argc, char **argv) {
+    int rc;
+    if (argc < 2) {
+        rc = memory_shell_show();
+    } else if (strcmp(argv[1], "send") == 0) {
+        rc = memory_shell_send();
+    } else {
+        console_printf("usage: mem [send]\n");
+        return SYS_EINVAL;
+    }
+    if (rc != 0) {
+        console_printf("mem: FAILED (%d)%s\n", rc, (rc == SYS_EAGAIN) ? ", network not ready" : "");
+    }
+    return rc;
+}
+
+#else  //  If memory shell commands are disabled...
+
+int start_memory_shell(void) {
+    //  Memory shell commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(MEMORY_SHELL)
@@ -0,0 +1,46 @@
+//  Console output over SEGGER RTT, for reading the logs with a J-Link or an ST-Link running the J-Link firmware.
+//  The debug probe reads the RTT up-buffer in RAM in the background, so unlike Arm Semihosting the CPU is never
+//  halted and the watch runs at full speed. The output is written by the console sink `rtt` in
+//  rust/mynewt/src/sys/console_sink.rs when RTT_CONSOLE_SINK is enabled. The Mynewt RTT console is disabled with
+//  CONSOLE_RTT: 0, since the console is provided by libs/semihosting_console.
+#include <sysinit/sysinit.h>  //  Contains all app settings consolidated from "apps/my_sensor_app/syscfg.yml"
+#include "os/mynewt.h"
+
+#if MYNEWT_VAL(RTT_CONSOLE_SINK)  //  If the RTT console sink is enabled...
+#include "rtt/SEGGER_RTT.h"
+
+/// RTT up-buffer for the console, read by the debug probe as terminal 0
+#define RTT_CONSOLE_BUFFER 0
+
+/// Append "length" bytes from "buffer" to the RTT up-buffer. The bytes that don't fit are dropped, since the probe
+/// may not be attached. May be called in any task or interrupt.
+void
+rtt_console_write(const char *buffer, unsigned int length)
+{
+    os_sr_t sr;
+
+    OS_ENTER_CRITICAL(sr);
+    SEGGER_RTT_WriteNoLock(RTT_CONSOLE_BUFFER, buffer, length);
+    OS_EXIT_CRITICAL(sr);
+}
+
+/// Return 1 if the RTT console sink is enabled
+int
+rtt_console_is_enabled(void)
+{
+    return 1;
+}
+
+#else  //  If the RTT console sink is disabled...
+
+void
+rtt_console_write(const char *buffer, unsigned int length)
+{
+}
+
+int
+rtt_console_is_enabled(void)
+{
+    return 0;
+}
+#endif  //  MYNEWT_VAL(RTT_CONSOLE_SINK)
@@ -0,0 +1,82 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Shell command for inspecting the sensors over the serial console during bring-up. The commands are executed
+//  in rust/app/src/sensor_shell.rs with the Rust sensor wrappers:
+//    sensor list             Lists the sensors, their types and poll intervals
+//    sensor read <name>      Reads the sensor once and prints the readings
+//    sensor poll <name> <ms> Polls the sensor every <ms> milliseconds, or stops polling if <ms> is 0
+//  Replaces the `sensor` command of the Mynewt Sensor CLI, which is disabled with SENSOR_CLI: 0.
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(SENSOR_SHELL)  //  If sensor shell commands are enabled...
+#include <stdlib.h>
+#include <string.h>
+#include "defs/error.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// Defined in rust/app/src/sensor_shell.rs
+int sensor_shell_list(void);
+int sensor_shell_read(const char *name);
+int sensor_shell_poll(const char *name, uint32_t ms);
+
+static int sensor_shell(int argc, char **argv);
+
+static struct shell_cmd sensor_cmd = {
+    .sc_cmd      = "sensor",
+    .sc_cmd_func = sensor_shell,
+};
+
+/// Register the sensor shell command. Called by main() in rust/app/src/lib.rs.
+int start_sensor_shell(void) {
+    return shell_cmd_register(&sensor_cmd);
+}
+
+/// Shell command `sensor list | read <name> | poll <name> <ms>`
+static int sensor_shell(int argc, char **argv) {
+    int rc;
+    if (argc >= 2 && strcmp(argv[1], "list") == 0) {
+        rc = sensor_shell_list();
+    } else if (argc >= 3 && strcmp(argv[1], "read") == 0) {
+        rc = sensor_shell_read(argv[2]);
+    } else if (argc >= 4 && strcmp(argv[1], "poll") == 0) {
+        char *end;
+        unsigned long ms = strtoul(argv[3], &end, 10);
+        if (*end != '\0') {
+            console_printf("sensor: invalid interval %s\n", argv[3]);
+            return SYS_EINVAL;
+        }
+        rc = sensor_shell_poll(argv[2], (uint32_t) ms);
+    } else {
+        console_printf("usage: sensor list | read <name> | poll <name> <ms>\n");
+        return SYS_EINVAL;
+    }
+    if (rc != 0) {
+        console_printf("sensor: FAILED (%d)%s\n", rc, (rc == SYS_ENODEV) ? ", no such sensor" : "");
+    }
+    return rc;
+}
+
+#else  //  If sensor shell commands are disabled...
+
+int start_sensor_shell(void) {
+    //  Sensor shell commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(SENSOR_SHELL)
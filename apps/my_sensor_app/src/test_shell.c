@@ -0,0 +1,70 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Shell command for running the on-target tests registered with `#[device_test]`, for hardware-in-the-loop regression
+//  runs. The tests run in the task `test` in rust/mynewt/src/sys/device_test.rs, which prints the results in TAP format:
+//    test list               Lists the tests
+//    test run [filter]       Runs the tests whose names contain the filter, or all tests
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(TEST_SHELL)  //  If test shell commands are enabled...
+#include <string.h>
+#include "defs/error.h"
+#include "shell/shell.h"
+#include "console/console.h"
+
+/// Defined in rust/mynewt/src/sys/device_test.rs
+int test_shell_list(void);
+int test_shell_run(const char *filter);
+
+static int test_shell(int argc, char **argv);
+
+static struct shell_cmd test_cmd = {
+    .sc_cmd      = "test",
+    .sc_cmd_func = test_shell,
+};
+
+/// Register the test shell command. Called by main() in rust/app/src/lib.rs.
+int start_test_shell(void) {
+    return shell_cmd_register(&test_cmd);
+}
+
+/// Shell command `test list | run [filter]`
+static int test_shell(int argc, char **argv) {
+    int rc;
+    if (argc >= 2 && strcmp(argv[1], "list") == 0) {
+        rc = test_shell_list();
+    } else if (argc >= 2 && strcmp(argv[1], "run") == 0) {
+        rc = test_shell_run(argc >= 3 ? argv[2] : NULL);
+    } else {
+        console_printf("usage: test list | run [filter]\n");
+        return SYS_EINVAL;
+    }
+    if (rc != 0) {
+        console_printf("test: FAILED (%d)%s\n", rc, (rc == SYS_EBUSY) ? ", tests are running" : "");
+    }
+    return rc;
+}
+
+#else  //  If test shell commands are disabled...
+
+int start_test_shell(void) {
+    //  Test shell commands not supported.
+    return 0;
+}
+#endif  //  MYNEWT_VAL(TEST_SHELL)
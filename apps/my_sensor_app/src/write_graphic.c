@@ -28,35 +28,17 @@
 #include <stdio.h>
 #include <string.h>
 
-#define BATCH_SIZE  4096  //  Max number of data bytes to be written in a batch
-
-//  Flash Device for Image
-#define FLASH_DEVICE 1  //  0 for Internal Flash ROM, 1 for External SPI Flash
-
-
-//  Converted from PNG file by https://github.com/lupyuen/pinetime-graphic
-static const uint8_t image_data[] = {  //  Should be 115,200 bytes
+//  Converted from PNG file by https://github.com/lupyuen/pinetime-graphic, or an asset bundle created by scripts/write-graphic/make-bundle.c
+static const uint8_t image_data[] = {  //  Should be 115,200 bytes for a graphic
 #include "write_graphic.inc"
 };
 
-/// Write a converted graphic file to SPI Flash
-int write_graphic(void) {
-    console_printf("Writing graphic to flash...\n"); console_flush();
-    uint32_t offset = 0;
-    for (;;) {
-        if (offset >= sizeof(image_data)) { break; }
-        //  How many bytes we will write.
-        uint16_t len = BATCH_SIZE;
-        if (offset + len >= sizeof(image_data)) {
-            len = sizeof(image_data) - offset;
-        }        
-        //  Erase the bytes.
-        int rc = hal_flash_erase(FLASH_DEVICE, offset, len); assert(rc == 0);
+/// Return the converted graphic file or asset bundle. Will be written to SPI Flash by the Rust `logo` module.
+const uint8_t *get_graphic_data(void) {
+    return image_data;
+}
 
-        //  Write the bytes.
-        rc = hal_flash_write(FLASH_DEVICE, offset, (void *) &image_data[offset], len); assert(rc == 0);
-        offset += len;
-    }
-    console_printf("Graphic written to flash\n"); console_flush();
-    return 0;
-}
\ No newline at end of file
+/// Return the size of the converted graphic file in bytes
+uint32_t get_graphic_size(void) {
+    return sizeof(image_data);
+}
@@ -48,6 +48,24 @@ extern uint8_t _ram_start;
 #define BUTTON_1        (14)  /* Labelled SW1 on the board */
 #define BUTTON_2        (13)  /* Labelled SW2 on the board */
 
+/* SPI Flash chip detected by JEDEC ID. Defined in src/spiflash_probe.c */
+struct bsp_spiflash_chip {
+    const char *name;
+    uint8_t  manufacturer;  /* JEDEC manufacturer ID */
+    uint8_t  memory_type;   /* JEDEC memory type */
+    uint8_t  capacity;      /* JEDEC capacity: size is 2 ^ capacity bytes */
+    uint32_t sector_size;   /* Number of bytes erased at a time */
+    uint32_t page_size;     /* Number of bytes programmed at a time */
+    uint8_t  fast_read;     /* 1 if the chip supports Fast Read (0x0B) at the max baudrate */
+    uint32_t max_baudrate;  /* Max SPI baudrate for reads, in kHz */
+};
+
+/* Probe the SPI Flash by JEDEC ID and configure the SPI Flash Driver. Returns 0 if the chip is known. */
+int bsp_spiflash_probe(void);
+
+/* Return the SPI Flash chip detected by bsp_spiflash_probe(), or NULL if unknown */
+const struct bsp_spiflash_chip *bsp_spiflash_chip(void);
+
 #ifdef __cplusplus
 }
 #endif
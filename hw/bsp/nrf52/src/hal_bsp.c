@@ -109,4 +109,7 @@ hal_bsp_init(void)
 
     /* Create all available nRF52840 peripherals */
     nrf52_periph_create();
+
+    /* Configure the SPI Flash Driver for the SPI Flash chip that is fitted */
+    bsp_spiflash_probe();
 }
@@ -0,0 +1,128 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Probe the External SPI Flash by its JEDEC ID and configure the SPI Flash Driver for the detected chip,
+//  so that the firmware works on PineTime revisions and mods with different SPI Flash chips.
+//  Must be called after the SPI port has been created and before the SPI Flash Driver is initialised.
+//  The console is not ready at that point, so call bsp_spiflash_chip() later to show the detected chip.
+#include <assert.h>
+#include <stddef.h>
+#include "os/mynewt.h"
+#include "hal/hal_gpio.h"
+#include "hal/hal_spi.h"
+#include "bsp/bsp.h"
+
+#if MYNEWT_VAL(SPIFLASH) && MYNEWT_VAL(SPIFLASH_AUTO_DETECT)  //  If SPI Flash auto-detection is enabled...
+#include <spiflash/spiflash.h>
+
+//  SPI Flash commands
+#define CMD_READ_JEDEC_ID      0x9F  //  Read manufacturer, memory type and capacity
+#define CMD_RELEASE_POWER_DOWN 0xAB  //  Wake up from deep power-down
+
+/// Known SPI Flash chips. Capacity is 2 ^ `capacity` bytes.
+static const struct bsp_spiflash_chip chips[] = {
+    //  Name          Mfr   Type  Cap   Sector  Page  Fast  Max kHz
+    { "XT25F32B",     0x0B, 0x40, 0x16, 4096,   256,  1,    8000 },  //  XTX, fitted to the PineTime
+    { "MX25L3233F",   0xC2, 0x20, 0x16, 4096,   256,  1,    8000 },  //  Macronix 4 MB
+    { "W25Q32",       0xEF, 0x40, 0x16, 4096,   256,  1,    8000 },  //  Winbond 4 MB
+    { "W25Q64",       0xEF, 0x40, 0x17, 4096,   256,  1,    8000 },  //  Winbond 8 MB
+    { "W25Q128",      0xEF, 0x40, 0x18, 4096,   256,  1,    8000 },  //  Winbond 16 MB
+    { "GD25Q32",      0xC8, 0x40, 0x16, 4096,   256,  1,    8000 },  //  GigaDevice 4 MB
+    { "GD25Q64",      0xC8, 0x40, 0x17, 4096,   256,  1,    8000 },  //  GigaDevice 8 MB
+    { "P25Q32H",      0x85, 0x60, 0x16, 4096,   256,  0,    4000 },  //  Puya 4 MB, no fast read below 2.3V
+};
+
+/// Chip detected by bsp_spiflash_probe(), or NULL if not detected
+static const struct bsp_spiflash_chip *detected_chip;
+
+static void read_jedec_id(uint8_t *manufacturer, uint8_t *memory_type, uint8_t *capacity);
+
+/// Read the JEDEC ID of the SPI Flash and configure the SPI Flash Driver for the chip: size, sector count,
+/// sector size, page size and SPI baudrate. Returns 0 if the chip is known, SYS_ENOENT if unknown,
+/// in which case the driver keeps the defaults in syscfg.yml.
+int bsp_spiflash_probe(void) {
+    uint8_t manufacturer, memory_type, capacity;
+    read_jedec_id(&manufacturer, &memory_type, &capacity);
+
+    for (size_t i = 0; i < sizeof(chips) / sizeof(chips[0]); i++) {
+        const struct bsp_spiflash_chip *chip = &chips[i];
+        if (chip->manufacturer != manufacturer || chip->memory_type != memory_type || chip->capacity != capacity) { continue; }
+
+        //  Configure the driver for the chip.
+        uint32_t size = (uint32_t) 1 << chip->capacity;
+        spiflash_dev.hal.hf_size       = size;
+        spiflash_dev.hal.hf_sector_cnt = size / chip->sector_size;
+        spiflash_dev.sector_size       = chip->sector_size;
+        spiflash_dev.page_size         = chip->page_size;
+        if (spiflash_dev.spi_settings.baudrate > chip->max_baudrate) {
+            spiflash_dev.spi_settings.baudrate = chip->max_baudrate;
+        }
+        detected_chip = chip;
+        return 0;
+    }
+    return SYS_ENOENT;
+}
+
+/// Return the SPI Flash chip detected by bsp_spiflash_probe(), or NULL if the chip is unknown
+const struct bsp_spiflash_chip *bsp_spiflash_chip(void) {
+    return detected_chip;
+}
+
+/// Read the manufacturer, memory type and capacity from the SPI Flash with the Read JEDEC ID command.
+/// Configures the SPI port with the same settings as the SPI Flash Driver.
+static void read_jedec_id(uint8_t *manufacturer, uint8_t *memory_type, uint8_t *capacity) {
+    int spi_num = MYNEWT_VAL(SPIFLASH_SPI_NUM);
+    int cs_pin  = MYNEWT_VAL(SPIFLASH_SPI_CS_PIN);
+    struct hal_spi_settings settings = {
+        .data_order = HAL_SPI_MSB_FIRST,
+        .data_mode  = HAL_SPI_MODE3,
+        .baudrate   = MYNEWT_VAL(SPIFLASH_BAUDRATE),
+        .word_size  = HAL_SPI_WORD_SIZE_8BIT,
+    };
+    hal_gpio_init_out(cs_pin, 1);
+    hal_spi_disable(spi_num);
+    int rc = hal_spi_config(spi_num, &settings); assert(rc == 0);
+    hal_spi_enable(spi_num);
+
+    //  Wake up the chip in case the bootloader left it in deep power-down.
+    hal_gpio_write(cs_pin, 0);
+    hal_spi_tx_val(spi_num, CMD_RELEASE_POWER_DOWN);
+    hal_gpio_write(cs_pin, 1);
+    os_cputime_delay_usecs(50);  //  tRES1 is at most 30 microseconds
+
+    //  Read the JEDEC ID.
+    hal_gpio_write(cs_pin, 0);
+    hal_spi_tx_val(spi_num, CMD_READ_JEDEC_ID);
+    *manufacturer = hal_spi_tx_val(spi_num, 0xFF);
+    *memory_type  = hal_spi_tx_val(spi_num, 0xFF);
+    *capacity     = hal_spi_tx_val(spi_num, 0xFF);
+    hal_gpio_write(cs_pin, 1);
+    hal_spi_disable(spi_num);  //  SPI Flash Driver will configure the port again
+}
+
+#else  //  If SPI Flash auto-detection is disabled...
+
+int bsp_spiflash_probe(void) {
+    //  SPI Flash auto-detection not supported.
+    return 0;
+}
+
+const struct bsp_spiflash_chip *bsp_spiflash_chip(void) {
+    return NULL;
+}
+#endif  //  MYNEWT_VAL(SPIFLASH) && MYNEWT_VAL(SPIFLASH_AUTO_DETECT)
@@ -0,0 +1,117 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//  Driver for the PineTime battery voltage sensor, measured by the nRF52 ADC on pin P0.31 (AIN7).
+//  Registered with the Sensor Manager as SENSOR_TYPE_BATTERY. Returns the battery voltage in millivolts,
+//  the charge level in percent and the charging state.
+
+#ifndef __BATTERY_H__
+#define __BATTERY_H__
+
+#include "os/mynewt.h"
+#include "sensor/sensor.h"
+#include "custom_sensor/custom_sensor.h"  //  For SENSOR_TYPE_BATTERY
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+struct adc_dev;  //  ADC device
+
+//  Configuration for the battery sensor
+struct battery_cfg {
+    sensor_type_t mask;        //  Sensor data types that will be returned, i.e. battery.
+    const char *adc_dev_name;  //  Name of the ADC device that will be opened to access the sensor, e.g. "adc0"
+    uint8_t adc_channel;       //  Analog input for the battery voltage, e.g. 7 for AIN7
+    int charge_pin;            //  GPIO pin that is low while charging, or -1 if none
+};
+
+//  Device for the battery sensor
+struct battery {
+    struct os_dev dev;       //  Mynewt device
+    struct sensor sensor;    //  Mynewt sensor
+    struct battery_cfg cfg;  //  Sensor configuration
+    struct adc_dev *adc;     //  ADC device that will be used to access the sensor, while the sensor is open
+};
+
+/**
+ * Create the battery sensor instance.  Implemented in creator.c, function DEVICE_CREATE().
+ */
+void battery_create(void);
+
+/**
+ * Return the default configuration for the battery sensor.
+ *
+ * @param cfg  Pointer to the battery_cfg device config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int battery_default_cfg(struct battery_cfg *cfg);
+
+/**
+ * Initialize the battery sensor.
+ *
+ * @param dev  Pointer to the battery device descriptor
+ * @param arg  Sensor interface
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int battery_init(struct os_dev *dev, void *arg);
+
+/**
+ * Configure the battery sensor
+ *
+ * @param Sensor device battery structure
+ * @param Sensor device battery_cfg config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int battery_config(struct battery *dev, struct battery_cfg *cfg);
+
+/**
+ * Measure the battery voltage. Will block until the ADC conversion is complete.
+ *
+ * @param dev The battery device
+ * @param mv Will store the battery voltage in millivolts
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int battery_get_mv(struct battery *dev, uint32_t *mv);
+
+/**
+ * Return the charge level in percent (0 to 100) for the battery voltage.
+ *
+ * @param mv Battery voltage in millivolts
+ *
+ * @return Charge level in percent
+ */
+uint8_t battery_mv_to_percent(uint32_t mv);
+
+/**
+ * Return 1 if the battery is charging, 0 otherwise.
+ *
+ * @param dev The battery device
+ */
+int battery_is_charging(struct battery *dev);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* __BATTERY_H__ */
@@ -0,0 +1,238 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+#include <string.h>
+#include "os/mynewt.h"
+#include "console/console.h"
+#include "hal/hal_gpio.h"
+#include "sensor/sensor.h"
+#include "adc/adc.h"
+#include "nrf_saadc.h"
+#include "battery/battery.h"
+
+//  Exports for the sensor API
+static int battery_sensor_read(struct sensor *, sensor_type_t, sensor_data_func_t, void *, uint32_t);
+static int battery_sensor_get_config(struct sensor *, sensor_type_t, struct sensor_cfg *);
+
+//  Global instance of the sensor driver
+static const struct sensor_driver g_battery_sensor_driver = {
+    battery_sensor_read,
+    battery_sensor_get_config
+};
+
+//  The battery is connected through a 1/2 voltage divider, so the battery voltage is twice the measured voltage.
+#define DIVIDER_RATIO 2
+
+//  Config for the battery channel: Gain 1/5 with the internal 0.6 V reference measures up to 3.0 V,
+//  which covers 6.0 V at the battery. Long acquisition time because the voltage divider has high impedance.
+static nrf_saadc_channel_config_t battery_channel_config = {
+    .resistor_p = NRF_SAADC_RESISTOR_DISABLED,
+    .resistor_n = NRF_SAADC_RESISTOR_DISABLED,
+    .gain       = NRF_SAADC_GAIN1_5,
+    .reference  = NRF_SAADC_REFERENCE_INTERNAL,
+    .acq_time   = NRF_SAADC_ACQTIME_40US,
+    .mode       = NRF_SAADC_MODE_SINGLE_ENDED,
+    .burst      = NRF_SAADC_BURST_DISABLED,
+    .pin_p      = NRF_SAADC_INPUT_AIN0,  //  Set to the configured channel in battery_open()
+    .pin_n      = NRF_SAADC_INPUT_DISABLED,
+};
+
+int battery_default_cfg(struct battery_cfg *cfg) {
+    //  Return the default sensor configuration.
+    memset(cfg, 0, sizeof(struct battery_cfg));  //  Zero the entire object.
+    cfg->mask         = SENSOR_TYPE_ALL;                   //  Return all sensor values, i.e. battery.
+    cfg->adc_dev_name = MYNEWT_VAL(BATTERY_ADC_DEVICE);    //  "adc0"
+    cfg->adc_channel  = MYNEWT_VAL(BATTERY_ADC_CHANNEL);   //  AIN7
+    cfg->charge_pin   = MYNEWT_VAL(BATTERY_CHARGE_PIN);    //  P0.12
+    return 0;
+}
+
+static int battery_open(struct os_dev *dev0, uint32_t timeout, void *arg) {
+    //  Open the ADC and configure the battery channel.  Return 0 if successful.
+    //  This locks the ADC until the sensor is closed.
+    struct battery *dev;
+    struct battery_cfg *cfg;
+    int rc;
+    dev = (struct battery *) dev0;  assert(dev);
+    cfg = &dev->cfg;  assert(cfg->adc_dev_name);
+
+    //  Open the ADC.
+    dev->adc = (struct adc_dev *) os_dev_open(cfg->adc_dev_name, timeout, NULL);
+    if (!dev->adc) { return SYS_ENODEV; }
+
+    //  Configure channel 0 of the ADC to measure the battery input. SAADC inputs AIN0 to AIN7 are numbered from 1.
+    battery_channel_config.pin_p = (nrf_saadc_input_t) (NRF_SAADC_INPUT_AIN0 + cfg->adc_channel);
+    rc = adc_chan_config(dev->adc, 0, &battery_channel_config);
+    if (rc) {
+        os_dev_close((struct os_dev *) dev->adc);
+        dev->adc = NULL;
+        return rc;
+    }
+    return 0;
+}
+
+static int battery_close(struct os_dev *dev0) {
+    //  Close the sensor.  This unlocks the ADC.  Return 0 if successful.
+    struct battery *dev;
+    dev = (struct battery *) dev0;
+    if (dev->adc) {
+        os_dev_close((struct os_dev *) dev->adc);
+        dev->adc = NULL;
+    }
+    return 0;
+}
+
+/**
+ * Expects to be called back through os_dev_create().
+ *
+ * @param The device object associated with battery
+ * @param Argument passed to OS device init: the sensor interface
+ *
+ * @return 0 on success, non-zero error on failure.
+ */
+int battery_init(struct os_dev *dev0, void *arg) {
+    struct battery *dev;
+    struct sensor *sensor;
+    int rc;
+    if (!arg || !dev0) { rc = SYS_ENODEV; goto err; }
+    dev = (struct battery *) dev0;
+    dev->adc = NULL;
+
+    //  Get the default config.
+    rc = battery_default_cfg(&dev->cfg);
+    if (rc) { goto err; }
+
+    //  Init the sensor.
+    sensor = &dev->sensor;
+    rc = sensor_init(sensor, dev0);
+    if (rc != 0) { goto err; }
+
+    //  Add the driver with all the supported sensor data types.
+    rc = sensor_set_driver(sensor, SENSOR_TYPE_BATTERY,
+        (struct sensor_driver *) &g_battery_sensor_driver);
+    if (rc != 0) { goto err; }
+
+    //  Set the interface.
+    rc = sensor_set_interface(sensor, arg);
+    if (rc) { goto err; }
+
+    //  Register with the Sensor Manager.
+    rc = sensor_mgr_register(sensor);
+    if (rc != 0) { goto err; }
+
+    //  Set the handlers for opening and closing the device.
+    OS_DEV_SETHANDLERS(dev0, battery_open, battery_close);
+    return (0);
+err:
+    return (rc);
+}
+
+int battery_config(struct battery *dev, struct battery_cfg *cfg) {
+    int rc;
+    dev->cfg = *cfg;
+
+    //  The charge indicator is open drain: low while charging.
+    if (cfg->charge_pin >= 0) {
+        rc = hal_gpio_init_in(cfg->charge_pin, HAL_GPIO_PULL_UP);
+        if (rc) { goto err; }
+    }
+    rc = sensor_set_type_mask(&(dev->sensor), cfg->mask);
+    if (rc) { goto err; }
+    return 0;
+err:
+    return (rc);
+}
+
+int battery_get_mv(struct battery *dev, uint32_t *mv) {
+    int rc, raw;
+    assert(dev->adc);  assert(mv);
+
+    //  Block until the voltage is read from the ADC channel.
+    raw = 0;
+    rc = adc_read_channel(dev->adc, 0, &raw);
+    if (rc) { return rc; }
+    if (raw < 0) { raw = 0; }  //  Single-ended input may return slightly negative values near 0 V
+
+    //  Convert the raw value to millivolts at the ADC input, then at the battery.
+    *mv = (uint32_t) adc_result_mv(dev->adc, 0, raw) * DIVIDER_RATIO;
+    return 0;
+}
+
+uint8_t battery_mv_to_percent(uint32_t mv) {
+    //  Lithium polymer discharge is roughly linear between the empty and full voltages.
+    const uint32_t empty = MYNEWT_VAL(BATTERY_EMPTY_MV);
+    const uint32_t full  = MYNEWT_VAL(BATTERY_FULL_MV);
+    if (mv <= empty) { return 0; }
+    if (mv >= full)  { return 100; }
+    return (uint8_t) ((mv - empty) * 100 / (full - empty));
+}
+
+int battery_is_charging(struct battery *dev) {
+    if (dev->cfg.charge_pin < 0) { return 0; }
+    return hal_gpio_read(dev->cfg.charge_pin) == 0;
+}
+
+static int battery_sensor_read(struct sensor *sensor, sensor_type_t type,
+    sensor_data_func_t data_func, void *data_arg, uint32_t timeout) {
+    //  Measure the battery voltage and return it with the charge level and charging state.
+    struct sensor_battery_data data;
+    struct battery *dev;
+    uint32_t mv;
+    int rc;
+
+    //  We only allow reading of battery values.
+    if (!(type & SENSOR_TYPE_BATTERY)) { rc = SYS_EINVAL; goto err; }
+    dev = (struct battery *) SENSOR_GET_DEVICE(sensor); assert(dev);
+    {   //  Begin ADC Lock: Open and lock the ADC, configure the battery channel.
+        rc = battery_open((struct os_dev *) dev, timeout, NULL);
+        if (rc) { goto err; }
+
+        //  Measure the battery voltage.
+        rc = battery_get_mv(dev, &mv);
+
+        battery_close((struct os_dev *) dev);
+    }   //  End ADC Lock: Close and unlock the ADC.
+    if (rc) { goto err; }
+
+    data.sbd_mv = mv;
+    data.sbd_percent = battery_mv_to_percent(mv);
+    data.sbd_charging = battery_is_charging(dev);
+    data.sbd_mv_is_valid = 1;
+
+    if (data_func) {  //  Call the Listener Function to process the sensor data.
+        rc = data_func(sensor, data_arg, &data, SENSOR_TYPE_BATTERY);
+        if (rc) { goto err; }
+    }
+    return 0;
+err:
+    return rc;
+}
+
+static int battery_sensor_get_config(struct sensor *sensor, sensor_type_t type,
+    struct sensor_cfg *cfg) {
+    //  Return the type of the sensor value returned by the sensor.
+    int rc;
+    if (!(type & SENSOR_TYPE_BATTERY)) {
+        rc = SYS_EINVAL;
+        goto err;
+    }
+    cfg->sc_valtype = SENSOR_VALUE_TYPE_INT32;  //  We return millivolts as integer.
+    return (0);
+err:
+    return (rc);
+}
@@ -0,0 +1,199 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//  Driver for the Bosch BMA421 / BMA425 accelerometer in PineTime, connected to I2C port 1.
+//  Registered with the Sensor Manager as an accelerometer that returns acceleration in m/s².
+//  Register map from the BMA423 datasheet, which is the same for BMA421 and BMA425.
+
+#ifndef __BMA421_H__
+#define __BMA421_H__
+
+#include "os/mynewt.h"
+#include "hal/hal_gpio.h"
+#include "sensor/sensor.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+//  Output data rates for ACC_CONF
+#define BMA421_ODR_12_5HZ  0x05
+#define BMA421_ODR_25HZ    0x06
+#define BMA421_ODR_50HZ    0x07
+#define BMA421_ODR_100HZ   0x08
+#define BMA421_ODR_200HZ   0x09
+#define BMA421_ODR_400HZ   0x0a
+#define BMA421_ODR_800HZ   0x0b
+#define BMA421_ODR_1600HZ  0x0c
+
+//  Acceleration ranges for ACC_RANGE
+#define BMA421_RANGE_2G    0x00
+#define BMA421_RANGE_4G    0x01
+#define BMA421_RANGE_8G    0x02
+#define BMA421_RANGE_16G   0x03
+
+//  Interrupt sources for INT_MAP_DATA, mapped to pin INT1
+#define BMA421_INT_FIFO_FULL  0x01
+#define BMA421_INT_FIFO_WM    0x02
+#define BMA421_INT_DRDY       0x04
+
+//  Max number of samples in the FIFO: 1 KB of 6-byte frames
+#define BMA421_FIFO_MAX_SAMPLES  170
+
+//  Configuration for the accelerometer
+struct bma421_cfg {
+    sensor_type_t mask;       //  Sensor data types that will be returned, i.e. accelerometer.
+    uint8_t odr;              //  Output data rate, e.g. BMA421_ODR_100HZ
+    uint8_t range;            //  Acceleration range, e.g. BMA421_RANGE_2G
+    uint8_t fifo_enable;      //  1 to store the samples in the FIFO, for reading in batches with bma421_read_fifo()
+    uint16_t fifo_watermark;  //  Number of FIFO bytes that triggers BMA421_INT_FIFO_WM
+};
+
+//  Device for the accelerometer
+struct bma421 {
+    struct os_dev dev;        //  Mynewt device
+    struct sensor sensor;     //  Mynewt sensor
+    struct bma421_cfg cfg;    //  Sensor configuration
+    uint8_t low_power;        //  1 if sampling in low-power mode, set by bma421_set_low_power()
+};
+
+//  Raw sample: 12-bit signed acceleration for each axis
+struct bma421_sample {
+    int16_t x;
+    int16_t y;
+    int16_t z;
+};
+
+/**
+ * Create the accelerometer instance.  Implemented in creator.c, function DEVICE_CREATE().
+ */
+void bma421_create(void);
+
+/**
+ * Return the default configuration for the accelerometer: 100 Hz, ±2g, FIFO disabled.
+ *
+ * @param cfg  Pointer to the bma421_cfg device config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_default_cfg(struct bma421_cfg *cfg);
+
+/**
+ * Initialize the accelerometer.
+ *
+ * @param dev  Pointer to the bma421 device descriptor
+ * @param arg  Pointer to the sensor interface
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_init(struct os_dev *dev, void *arg);
+
+/**
+ * Reset and configure the accelerometer, then start sampling.
+ *
+ * @param dev  The bma421 device
+ * @param cfg  Sensor device config
+ *
+ * @return 0 on success, SYS_ENODEV if the chip ID is not BMA421 / BMA425, other non-zero error code on failure
+ */
+int bma421_config(struct bma421 *dev, struct bma421_cfg *cfg);
+
+/**
+ * Get the latest raw sample.
+ *
+ * @param dev     The bma421 device
+ * @param sample  Will store the raw acceleration, 12-bit signed
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_get_raw_xyz(struct bma421 *dev, struct bma421_sample *sample);
+
+/**
+ * Convert a raw sample to acceleration in m/s², according to the configured range.
+ *
+ * @param dev     The bma421 device
+ * @param sample  The raw sample
+ * @param data    Will store the acceleration
+ */
+void bma421_convert(struct bma421 *dev, const struct bma421_sample *sample, struct sensor_accel_data *data);
+
+/**
+ * Read the samples stored in the FIFO, oldest first. The FIFO must be enabled in the config.
+ *
+ * @param dev          The bma421 device
+ * @param samples      Array that will store the raw samples
+ * @param max_samples  Size of the array
+ *
+ * @return Number of samples read, or negative error code on failure
+ */
+int bma421_read_fifo(struct bma421 *dev, struct bma421_sample *samples, int max_samples);
+
+/**
+ * Map interrupt sources to pin INT1 and enable the GPIO interrupt. When the interrupt fires, the Sensor Manager
+ * reads the sensor and calls the listeners.
+ *
+ * @param dev      The bma421 device
+ * @param int_map  Interrupt sources, e.g. BMA421_INT_DRDY. 0 to disable the interrupt.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_set_int(struct bma421 *dev, uint8_t int_map);
+
+/**
+ * Map interrupt sources to pin INT1 and call the handler when the interrupt fires, instead of the Sensor Manager.
+ * The handler is called in interrupt context, so it should only post an event for reading the accelerometer.
+ *
+ * @param dev      The bma421 device
+ * @param int_map  Interrupt sources, e.g. BMA421_INT_FIFO_WM. 0 to disable the interrupt.
+ * @param handler  Function called when the interrupt fires
+ * @param arg      Argument passed to the handler
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_set_int_handler(struct bma421 *dev, uint8_t int_map, hal_gpio_irq_handler_t handler, void *arg);
+
+/**
+ * Change the output data rate and the FIFO watermark without resetting the accelerometer, then enable and
+ * flush the FIFO. Used for sampling at a higher rate in batches, e.g. for detecting taps.
+ *
+ * @param dev             The bma421 device
+ * @param odr             Output data rate, e.g. BMA421_ODR_200HZ
+ * @param fifo_watermark  Number of FIFO bytes that triggers BMA421_INT_FIFO_WM
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_set_fifo(struct bma421 *dev, uint8_t odr, uint16_t fifo_watermark);
+
+/**
+ * Switch between low-power mode and normal mode without resetting the accelerometer. In low-power mode the
+ * accelerometer averages fewer samples and sleeps between samples, so the samples are noisier but the current
+ * drops from about 150 microamps to about 15. The output data rate, the FIFO and the interrupts are unchanged.
+ *
+ * @param dev     The bma421 device
+ * @param enable  1 for low-power mode, 0 for normal mode
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int bma421_set_low_power(struct bma421 *dev, int enable);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* __BMA421_H__ */
@@ -0,0 +1,366 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+#include <string.h>
+#include "os/mynewt.h"
+#include "hal/hal_i2c.h"
+#include "hal/hal_gpio.h"
+#include "console/console.h"
+#include "sensor/sensor.h"
+#include "sensor/accel.h"
+#include "bma421/bma421.h"
+
+//  Registers. From the BMA423 datasheet.
+#define REG_CHIP_ID        0x00
+#define REG_DATA_8         0x12  //  ACC_X LSB, followed by ACC_X MSB, ACC_Y, ACC_Z
+#define REG_FIFO_LENGTH_0  0x24
+#define REG_FIFO_DATA      0x26
+#define REG_ACC_CONF       0x40
+#define REG_ACC_RANGE      0x41
+#define REG_FIFO_WTM_0     0x46
+#define REG_FIFO_CONFIG_1  0x49
+#define REG_INT1_IO_CTRL   0x53
+#define REG_INT_LATCH      0x55
+#define REG_INT_MAP_DATA   0x58
+#define REG_PWR_CONF       0x7c
+#define REG_PWR_CTRL       0x7d
+#define REG_CMD            0x7e
+
+#define CHIP_ID_BMA421     0x11
+#define CHIP_ID_BMA425     0x13
+#define CMD_SOFT_RESET     0xb6
+#define CMD_FIFO_FLUSH     0xb0
+#define ACC_CONF_PERF_NORMAL 0xa0  //  Continuous filter, normal averaging
+#define ACC_CONF_PERF_LOW  0x10    //  Averaging of 2 samples, for low-power mode
+#define PWR_CONF_LOW_POWER 0x03    //  Advanced power save, with the FIFO readable while saving power
+#define PWR_CTRL_ACC_EN    0x04
+#define FIFO_CONFIG_ACC_EN 0x40    //  Headerless mode with accelerometer data only
+#define INT1_OUTPUT_HIGH   0x0a    //  Output enabled, push-pull, active high
+#define FIFO_FRAME_SIZE    6
+#define FIFO_BATCH_SAMPLES 16      //  Samples per I2C transfer when reading the FIFO
+#define I2C_TIMEOUT        (OS_TICKS_PER_SEC / 10)
+
+//  Exports for the sensor API
+static int bma421_sensor_read(struct sensor *, sensor_type_t, sensor_data_func_t, void *, uint32_t);
+static int bma421_sensor_get_config(struct sensor *, sensor_type_t, struct sensor_cfg *);
+
+//  Global instance of the sensor driver
+static const struct sensor_driver g_bma421_sensor_driver = {
+    bma421_sensor_read,
+    bma421_sensor_get_config
+};
+
+static int write_reg(struct bma421 *dev, uint8_t reg, uint8_t val) {
+    //  Write the register over I2C.  Return 0 if successful.
+    struct sensor_itf *itf = SENSOR_GET_ITF(&dev->sensor);
+    uint8_t buf[2] = { reg, val };
+    struct hal_i2c_master_data data = { .address = itf->si_addr, .len = 2, .buffer = buf };
+    return hal_i2c_master_write(itf->si_num, &data, I2C_TIMEOUT, 1);
+}
+
+static int read_regs(struct bma421 *dev, uint8_t reg, uint8_t *buf, uint16_t len) {
+    //  Read consecutive registers over I2C, starting at reg.  Return 0 if successful.
+    struct sensor_itf *itf = SENSOR_GET_ITF(&dev->sensor);
+    struct hal_i2c_master_data data = { .address = itf->si_addr, .len = 1, .buffer = &reg };
+    int rc = hal_i2c_master_write(itf->si_num, &data, I2C_TIMEOUT, 0);  //  No stop, continue with the read
+    if (rc) { return rc; }
+    data.len = len;  data.buffer = buf;
+    return hal_i2c_master_read(itf->si_num, &data, I2C_TIMEOUT, 1);
+}
+
+static int write_acc_conf(struct bma421 *dev, uint8_t odr) {
+    //  Write the output data rate with the filter for normal or low-power mode.  Return 0 if successful.
+    return write_reg(dev, REG_ACC_CONF, (dev->low_power ? ACC_CONF_PERF_LOW : ACC_CONF_PERF_NORMAL) | (odr & 0x0f));
+}
+
+static int write_power_save(struct bma421 *dev, int enable) {
+    //  Enable or disable the advanced power save.  Return 0 if successful.
+    int rc = write_reg(dev, REG_PWR_CONF, enable ? PWR_CONF_LOW_POWER : 0);
+    if (rc) { return rc; }
+    //  Without power save, the registers may be written after 450 microseconds.
+    if (!enable) { os_time_delay(1); }
+    return 0;
+}
+
+static void decode_sample(const uint8_t *buf, struct bma421_sample *sample) {
+    //  Each axis is 12 bits, left-aligned in 16 bits, LSB first.
+    sample->x = ((int16_t) (buf[0] | (buf[1] << 8))) >> 4;
+    sample->y = ((int16_t) (buf[2] | (buf[3] << 8))) >> 4;
+    sample->z = ((int16_t) (buf[4] | (buf[5] << 8))) >> 4;
+}
+
+int bma421_default_cfg(struct bma421_cfg *cfg) {
+    //  Return the default sensor configuration.
+    memset(cfg, 0, sizeof(struct bma421_cfg));  //  Zero the entire object.
+    cfg->mask  = SENSOR_TYPE_ACCELEROMETER;     //  Return accelerometer values.
+    cfg->odr   = BMA421_ODR_100HZ;
+    cfg->range = BMA421_RANGE_2G;
+    return 0;
+}
+
+static int bma421_open(struct os_dev *dev0, uint32_t timeout, void *arg) {
+    //  Nothing to set up, the I2C port is opened by the BSP.  Return 0 if successful.
+    return 0;
+}
+
+static int bma421_close(struct os_dev *dev0) {
+    //  Close the sensor.  Return 0 if successful.
+    return 0;
+}
+
+/**
+ * Expects to be called back through os_dev_create().
+ *
+ * @param The device object associated with bma421
+ * @param Argument passed to OS device init: the sensor interface
+ *
+ * @return 0 on success, non-zero error on failure.
+ */
+int bma421_init(struct os_dev *dev0, void *arg) {
+    struct bma421 *dev;
+    struct sensor *sensor;
+    int rc;
+    if (!arg || !dev0) { rc = SYS_ENODEV; goto err; }
+    dev = (struct bma421 *) dev0;
+
+    //  Get the default config.
+    rc = bma421_default_cfg(&dev->cfg);
+    if (rc) { goto err; }
+
+    //  Init the sensor.
+    sensor = &dev->sensor;
+    rc = sensor_init(sensor, dev0);
+    if (rc != 0) { goto err; }
+
+    //  Add the driver with all the supported sensor data types.
+    rc = sensor_set_driver(sensor, SENSOR_TYPE_ACCELEROMETER,
+        (struct sensor_driver *) &g_bma421_sensor_driver);
+    if (rc != 0) { goto err; }
+
+    //  Set the interface.
+    rc = sensor_set_interface(sensor, arg);
+    if (rc) { goto err; }
+
+    //  Register with the Sensor Manager.
+    rc = sensor_mgr_register(sensor);
+    if (rc != 0) { goto err; }
+
+    //  Set the handlers for opening and closing the device.
+    OS_DEV_SETHANDLERS(dev0, bma421_open, bma421_close);
+    return (0);
+err:
+    return (rc);
+}
+
+int bma421_config(struct bma421 *dev, struct bma421_cfg *cfg) {
+    uint8_t chip_id;
+    int rc;
+
+    //  Reset the accelerometer and check the chip ID.
+    rc = write_reg(dev, REG_CMD, CMD_SOFT_RESET);
+    if (rc) { goto err; }
+    os_time_delay(OS_TICKS_PER_SEC / 100 + 1);  //  Wait 2 ms after reset
+    rc = read_regs(dev, REG_CHIP_ID, &chip_id, 1);
+    if (rc) { goto err; }
+    if (chip_id != CHIP_ID_BMA421 && chip_id != CHIP_ID_BMA425) {
+        console_printf("BMA bad chip id %x\n", chip_id);
+        rc = SYS_ENODEV; goto err;
+    }
+
+    //  Disable advanced power save, so that the registers may be written without delays.
+    rc = write_reg(dev, REG_PWR_CONF, 0);
+    if (rc) { goto err; }
+    os_time_delay(1);
+
+    //  Set the output data rate and range. The reset has restored normal mode.
+    dev->low_power = 0;
+    rc = write_acc_conf(dev, cfg->odr);
+    if (rc) { goto err; }
+    rc = write_reg(dev, REG_ACC_RANGE, cfg->range & 0x03);
+    if (rc) { goto err; }
+
+    //  Store the samples in the FIFO if enabled.
+    rc = write_reg(dev, REG_FIFO_WTM_0, cfg->fifo_watermark & 0xff);
+    if (rc) { goto err; }
+    rc = write_reg(dev, REG_FIFO_WTM_0 + 1, (cfg->fifo_watermark >> 8) & 0x1f);
+    if (rc) { goto err; }
+    rc = write_reg(dev, REG_FIFO_CONFIG_1, cfg->fifo_enable ? FIFO_CONFIG_ACC_EN : 0);
+    if (rc) { goto err; }
+    rc = write_reg(dev, REG_CMD, CMD_FIFO_FLUSH);
+    if (rc) { goto err; }
+
+    //  Start sampling.
+    rc = write_reg(dev, REG_PWR_CTRL, PWR_CTRL_ACC_EN);
+    if (rc) { goto err; }
+
+    rc = sensor_set_type_mask(&(dev->sensor), cfg->mask);
+    if (rc) { goto err; }
+    dev->cfg = *cfg;
+    return 0;
+err:
+    return (rc);
+}
+
+int bma421_get_raw_xyz(struct bma421 *dev, struct bma421_sample *sample) {
+    uint8_t buf[FIFO_FRAME_SIZE];
+    int rc = read_regs(dev, REG_DATA_8, buf, sizeof(buf));
+    if (rc) { return rc; }
+    decode_sample(buf, sample);
+    return 0;
+}
+
+void bma421_convert(struct bma421 *dev, const struct bma421_sample *sample, struct sensor_accel_data *data) {
+    //  12-bit samples: 2048 steps for the full range in each direction.
+    float scale = (float) (2 << dev->cfg.range) * STANDARD_ACCEL_GRAVITY / 2048.0f;
+    memset(data, 0, sizeof(struct sensor_accel_data));
+    data->sad_x = sample->x * scale;  data->sad_x_is_valid = 1;
+    data->sad_y = sample->y * scale;  data->sad_y_is_valid = 1;
+    data->sad_z = sample->z * scale;  data->sad_z_is_valid = 1;
+}
+
+int bma421_read_fifo(struct bma421 *dev, struct bma421_sample *samples, int max_samples) {
+    uint8_t buf[FIFO_BATCH_SAMPLES * FIFO_FRAME_SIZE];
+    uint8_t len_buf[2];
+    int count, done, batch, i, rc;
+    if (!dev->cfg.fifo_enable) { return SYS_EINVAL; }
+
+    //  Number of bytes in the FIFO, 14 bits.
+    rc = read_regs(dev, REG_FIFO_LENGTH_0, len_buf, sizeof(len_buf));
+    if (rc) { return rc > 0 ? -rc : rc; }  //  HAL errors are positive
+    count = (len_buf[0] | ((len_buf[1] & 0x3f) << 8)) / FIFO_FRAME_SIZE;
+    if (count > max_samples) { count = max_samples; }
+
+    //  Read the frames in batches. Each read of FIFO_DATA returns the next frames.
+    for (done = 0; done < count; done += batch) {
+        batch = count - done;
+        if (batch > FIFO_BATCH_SAMPLES) { batch = FIFO_BATCH_SAMPLES; }
+        rc = read_regs(dev, REG_FIFO_DATA, buf, batch * FIFO_FRAME_SIZE);
+        if (rc) { return rc > 0 ? -rc : rc; }  //  HAL errors are positive
+        for (i = 0; i < batch; i++) {
+            decode_sample(&buf[i * FIFO_FRAME_SIZE], &samples[done + i]);
+        }
+    }
+    return count;
+}
+
+static void bma421_int_handler(void *arg) {
+    //  Ask the Sensor Manager to read the sensor and call the listeners.  Don't do any processing here.
+    struct bma421 *dev = (struct bma421 *) arg;
+    sensor_mgr_put_read_evt(&dev->sensor);
+}
+
+int bma421_set_int(struct bma421 *dev, uint8_t int_map) {
+    return bma421_set_int_handler(dev, int_map, bma421_int_handler, dev);
+}
+
+int bma421_set_int_handler(struct bma421 *dev, uint8_t int_map, hal_gpio_irq_handler_t handler, void *arg) {
+    int pin = MYNEWT_VAL(BMA421_INT_PIN);
+    int rc;
+    hal_gpio_irq_release(pin);
+    rc = write_reg(dev, REG_INT_MAP_DATA, int_map & 0x07);
+    if (rc || !int_map) { return rc; }
+
+    //  Non-latched, active high interrupt on pin INT1.
+    rc = write_reg(dev, REG_INT1_IO_CTRL, INT1_OUTPUT_HIGH);
+    if (rc) { return rc; }
+    rc = write_reg(dev, REG_INT_LATCH, 0);
+    if (rc) { return rc; }
+    rc = hal_gpio_irq_init(pin, handler, arg, HAL_GPIO_TRIG_RISING, HAL_GPIO_PULL_NONE);
+    if (rc) { return rc; }
+    hal_gpio_irq_enable(pin);
+    return 0;
+}
+
+int bma421_set_fifo(struct bma421 *dev, uint8_t odr, uint16_t fifo_watermark) {
+    int rc;
+    //  Registers can't be written back to back in power save.
+    if (dev->low_power) {
+        rc = write_power_save(dev, 0);
+        if (rc) { return rc; }
+    }
+    rc = write_acc_conf(dev, odr);
+    if (rc) { return rc; }
+    rc = write_reg(dev, REG_FIFO_WTM_0, fifo_watermark & 0xff);
+    if (rc) { return rc; }
+    rc = write_reg(dev, REG_FIFO_WTM_0 + 1, (fifo_watermark >> 8) & 0x1f);
+    if (rc) { return rc; }
+    rc = write_reg(dev, REG_FIFO_CONFIG_1, FIFO_CONFIG_ACC_EN);
+    if (rc) { return rc; }
+    rc = write_reg(dev, REG_CMD, CMD_FIFO_FLUSH);
+    if (rc) { return rc; }
+    dev->cfg.odr = odr;
+    dev->cfg.fifo_enable = 1;
+    dev->cfg.fifo_watermark = fifo_watermark;
+    if (dev->low_power) { return write_power_save(dev, 1); }
+    return 0;
+}
+
+int bma421_set_low_power(struct bma421 *dev, int enable) {
+    int rc;
+    enable = enable ? 1 : 0;
+    if (dev->low_power == enable) { return 0; }
+    if (enable) {
+        //  Change the filter before power save, which delays the register writes.
+        dev->low_power = 1;
+        rc = write_acc_conf(dev, dev->cfg.odr);
+        if (rc) { dev->low_power = 0; return rc; }
+        return write_power_save(dev, 1);
+    }
+    rc = write_power_save(dev, 0);
+    if (rc) { return rc; }
+    dev->low_power = 0;
+    return write_acc_conf(dev, dev->cfg.odr);
+}
+
+static int bma421_sensor_read(struct sensor *sensor, sensor_type_t type,
+    sensor_data_func_t data_func, void *data_arg, uint32_t timeout) {
+    //  Read the latest sample and convert to m/s².
+    struct sensor_accel_data data;
+    struct bma421_sample sample;
+    struct bma421 *dev;
+    int rc;
+
+    //  We only allow reading of accelerometer values.
+    if (!(type & SENSOR_TYPE_ACCELEROMETER)) { rc = SYS_EINVAL; goto err; }
+    dev = (struct bma421 *) SENSOR_GET_DEVICE(sensor); assert(dev);
+    rc = bma421_get_raw_xyz(dev, &sample);
+    if (rc) { goto err; }
+    bma421_convert(dev, &sample, &data);
+
+    if (data_func) {  //  Call the Listener Function to process the sensor data.
+        rc = data_func(sensor, data_arg, &data, SENSOR_TYPE_ACCELEROMETER);
+        if (rc) { goto err; }
+    }
+    return 0;
+err:
+    return rc;
+}
+
+static int bma421_sensor_get_config(struct sensor *sensor, sensor_type_t type,
+    struct sensor_cfg *cfg) {
+    //  Return the type of the sensor value returned by the sensor.
+    int rc;
+    if (!(type & SENSOR_TYPE_ACCELEROMETER)) {
+        rc = SYS_EINVAL;
+        goto err;
+    }
+    cfg->sc_valtype = SENSOR_VALUE_TYPE_FLOAT_TRIPLET;  //  We return x, y and z in m/s².
+    return (0);
+err:
+    return (rc);
+}
@@ -34,6 +34,12 @@ extern "C" {
 //  Allocate the next unused Sensor Type ID.
 #define SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW SENSOR_TYPE_USER_DEFINED_1
 #define SENSOR_TYPE_GEOLOCATION             SENSOR_TYPE_USER_DEFINED_2
+#define SENSOR_TYPE_HEART_RATE              SENSOR_TYPE_USER_DEFINED_3
+#define SENSOR_TYPE_BATTERY                 SENSOR_TYPE_USER_DEFINED_4
+#define SENSOR_TYPE_STEPS                   SENSOR_TYPE_USER_DEFINED_5
+#define SENSOR_TYPE_ORIENTATION             SENSOR_TYPE_USER_DEFINED_6
+//  The user-defined Sensor Type IDs are used up. Allocate the unassigned standard IDs from the top.
+#define SENSOR_TYPE_LINK_QUALITY            ((sensor_type_t) (1 << 25))
 
 //  Raw Temperature Sensor: Instead of floating-point computed temperature, we transmit the
 //  raw temperature value as integer to the Collector Node and CoAP Server to reduce message
@@ -68,6 +74,54 @@ struct sensor_geolocation_data {
     uint8_t  sgd_altitude_is_valid;  
 } __attribute__((packed));
 
+//  Heart Rate
+struct sensor_heart_rate_data {   
+    ///  Heart rate (beats per minute)
+    uint32_t shrd_bpm;
+    ///  1 if heart rate is valid
+    uint8_t  shrd_bpm_is_valid;  
+} __attribute__((packed));
+
+//  Battery
+struct sensor_battery_data {   
+    ///  Battery voltage (millivolts)
+    uint32_t sbd_mv;
+    ///  Charge level (percent)
+    uint8_t  sbd_percent;
+    ///  1 if charging
+    uint8_t  sbd_charging;
+    ///  1 if battery voltage is valid
+    uint8_t  sbd_mv_is_valid;  
+} __attribute__((packed));
+
+//  Step Count, computed by the pedometer in Rust
+struct sensor_steps_data {   
+    ///  Steps counted today
+    uint32_t ssd_steps;
+    ///  1 if step count is valid
+    uint8_t  ssd_steps_is_valid;  
+} __attribute__((packed));
+
+//  Orientation, computed from the accelerometer in Rust
+struct sensor_orientation_data {   
+    ///  Pitch (hundredths of a degree, -9000 to 9000)
+    int32_t sod_pitch;
+    ///  Roll (hundredths of a degree, -18000 to 18000)
+    int32_t sod_roll;
+    ///  1 if orientation is valid
+    uint8_t sod_is_valid;  
+} __attribute__((packed));
+
+//  Bluetooth LE Link Quality, measured by the controller and read in Rust
+struct sensor_link_quality_data {   
+    ///  Signal strength of the weakest connection (dBm)
+    int32_t slqd_rssi;
+    ///  Transmit power on the advertising channels (dBm)
+    int32_t slqd_adv_tx_power;
+    ///  1 if the signal strength is valid, i.e. a phone is connected
+    uint8_t slqd_rssi_is_valid;  
+} __attribute__((packed));
+
 #ifdef __cplusplus
 }
 #endif
@@ -0,0 +1,165 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//  Driver for the HRS3300 heart rate sensor in PineTime, connected to I2C port 1.
+//  Registered with the Sensor Manager as SENSOR_TYPE_HEART_RATE. A background task samples the PPG signal
+//  and computes the heart rate in beats per minute.
+
+#ifndef __HRS3300_H__
+#define __HRS3300_H__
+
+#include "os/mynewt.h"
+#include "sensor/sensor.h"
+#include "custom_sensor/custom_sensor.h"  //  For SENSOR_TYPE_HEART_RATE
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+//  LED drive current
+#define HRS3300_LED_12_5MA  0
+#define HRS3300_LED_20MA    1
+#define HRS3300_LED_30MA    2
+#define HRS3300_LED_40MA    3
+
+//  Number of pulse intervals that are averaged to compute the heart rate
+#define HRS3300_MAX_INTERVALS  8
+
+//  Configuration for the heart rate sensor
+struct hrs3300_cfg {
+    sensor_type_t mask;       //  Sensor data types that will be returned, i.e. heart rate.
+    uint8_t led_current;      //  LED drive current, e.g. HRS3300_LED_20MA
+    uint8_t gain;             //  HGAIN register value, e.g. 0x10 for 64x gain
+};
+
+//  State of the heart rate computation, updated by the background task
+struct hrs3300_pulse {
+    int32_t dc;               //  Average of the PPG samples (DC level), times 16
+    int32_t smoothed;         //  Smoothed PPG samples with the DC level removed
+    os_time_t last_beat;      //  Time of the last pulse
+    uint16_t intervals[HRS3300_MAX_INTERVALS];  //  Last intervals between pulses, in milliseconds
+    uint8_t num_intervals;    //  Number of valid intervals
+    uint8_t next_interval;    //  Index of the next interval to be replaced
+    uint32_t bpm;             //  Latest heart rate, 0 if not computed yet
+};
+
+//  Device for the heart rate sensor
+struct hrs3300 {
+    struct os_dev dev;        //  Mynewt device
+    struct sensor sensor;     //  Mynewt sensor
+    struct hrs3300_cfg cfg;   //  Sensor configuration
+    struct hrs3300_pulse pulse;  //  Heart rate computation
+    struct os_task task;      //  Task that samples the sensor
+    struct os_sem start_sem;  //  Released to wake up the task when the sensor is started
+    uint8_t running;          //  1 if the sensor is started
+    uint8_t suspended;        //  1 if the sensor was stopped by hrs3300_suspend() and will be restarted by hrs3300_resume()
+};
+
+/**
+ * Create the heart rate sensor instance.  Implemented in creator.c, function DEVICE_CREATE().
+ */
+void hrs3300_create(void);
+
+/**
+ * Return the default configuration for the heart rate sensor: 20 mA LED current, 64x gain.
+ *
+ * @param cfg  Pointer to the hrs3300_cfg device config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_default_cfg(struct hrs3300_cfg *cfg);
+
+/**
+ * Initialize the heart rate sensor and create the sampling task.
+ *
+ * @param dev  Pointer to the hrs3300 device descriptor
+ * @param arg  Pointer to the sensor interface
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_init(struct os_dev *dev, void *arg);
+
+/**
+ * Configure the heart rate sensor. The sensor stays disabled until hrs3300_start() is called.
+ *
+ * @param dev  The hrs3300 device
+ * @param cfg  Sensor device config
+ *
+ * @return 0 on success, SYS_ENODEV if the chip ID is not HRS3300, other non-zero error code on failure
+ */
+int hrs3300_config(struct hrs3300 *dev, struct hrs3300_cfg *cfg);
+
+/**
+ * Switch on the LED and start sampling and computing the heart rate.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_start(struct hrs3300 *dev);
+
+/**
+ * Stop sampling and switch off the LED, to save power.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_stop(struct hrs3300 *dev);
+
+/**
+ * Stop sampling and switch off the LED while the heart rate is not needed, e.g. when the watch sleeps.
+ * Does nothing if the sensor is not started.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_suspend(struct hrs3300 *dev);
+
+/**
+ * Start sampling again if the sensor was started before hrs3300_suspend(). The heart rate is computed again
+ * from scratch, so it's valid after a few pulses.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_resume(struct hrs3300 *dev);
+
+/**
+ * Set the LED drive current, e.g. HRS3300_LED_20MA. Higher current works better for darker skin but uses more power.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_set_led_current(struct hrs3300 *dev, uint8_t led_current);
+
+/**
+ * Read the raw PPG (heart rate channel) and ambient light samples.
+ *
+ * @param dev  The hrs3300 device
+ * @param hrs  Will store the PPG sample
+ * @param als  Will store the ambient light sample. May be NULL.
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int hrs3300_read_samples(struct hrs3300 *dev, uint32_t *hrs, uint32_t *als);
+
+/**
+ * Return the latest heart rate in beats per minute, or 0 if the heart rate has not been computed yet.
+ */
+uint32_t hrs3300_get_bpm(struct hrs3300 *dev);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* __HRS3300_H__ */
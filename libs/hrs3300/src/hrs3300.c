@@ -0,0 +1,343 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+#include <string.h>
+#include "os/mynewt.h"
+#include "hal/hal_i2c.h"
+#include "console/console.h"
+#include "sensor/sensor.h"
+#include "hrs3300/hrs3300.h"
+
+//  Registers. From the HRS3300 datasheet.
+#define REG_ID          0x00
+#define REG_ENABLE      0x01
+#define REG_C1DATAM     0x08
+#define REG_C0DATAM     0x09
+#define REG_C0DATAH     0x0a
+#define REG_PDRIVER     0x0c
+#define REG_C1DATAH     0x0d
+#define REG_C1DATAL     0x0e
+#define REG_C0DATAL     0x0f
+#define REG_RES         0x16
+#define REG_HGAIN       0x17
+
+#define CHIP_ID         0x21
+#define ENABLE_HEN      0x80  //  Enable heart rate sensor
+#define ENABLE_WAIT_12_5MS 0x60  //  Wait 12.5 ms between conversions
+#define ENABLE_PDRIVE1  0x08  //  LED drive current, high bit
+#define PDRIVER_PDRIVE0 0x40  //  LED drive current, low bit
+#define PDRIVER_PON     0x20  //  LED power on
+#define PDRIVER_RESERVED 0x08 //  Must be set, according to the datasheet
+#define RES_16BIT       0x88  //  Heart rate and ambient light channels in 16-bit mode
+#define I2C_TIMEOUT     (OS_TICKS_PER_SEC / 10)
+
+//  Heart rate computation
+#define SAMPLE_INTERVAL_MS  40    //  Sample at 25 Hz
+#define MIN_INTERVAL_MS     300   //  200 beats per minute
+#define MAX_INTERVAL_MS     1500  //  40 beats per minute
+#define MIN_INTERVALS       4     //  Number of intervals before the heart rate is valid
+
+//  Exports for the sensor API
+static int hrs3300_sensor_read(struct sensor *, sensor_type_t, sensor_data_func_t, void *, uint32_t);
+static int hrs3300_sensor_get_config(struct sensor *, sensor_type_t, struct sensor_cfg *);
+
+//  Global instance of the sensor driver
+static const struct sensor_driver g_hrs3300_sensor_driver = {
+    hrs3300_sensor_read,
+    hrs3300_sensor_get_config
+};
+
+//  Stack for the sampling task
+static os_stack_t hrs3300_stack[MYNEWT_VAL(HRS3300_TASK_STACK_SIZE)];
+
+static int write_reg(struct hrs3300 *dev, uint8_t reg, uint8_t val) {
+    //  Write the register over I2C.  Return 0 if successful.
+    struct sensor_itf *itf = SENSOR_GET_ITF(&dev->sensor);
+    uint8_t buf[2] = { reg, val };
+    struct hal_i2c_master_data data = { .address = itf->si_addr, .len = 2, .buffer = buf };
+    return hal_i2c_master_write(itf->si_num, &data, I2C_TIMEOUT, 1);
+}
+
+static int read_reg(struct hrs3300 *dev, uint8_t reg, uint8_t *val) {
+    //  Read the register over I2C.  Return 0 if successful.
+    struct sensor_itf *itf = SENSOR_GET_ITF(&dev->sensor);
+    struct hal_i2c_master_data data = { .address = itf->si_addr, .len = 1, .buffer = &reg };
+    int rc = hal_i2c_master_write(itf->si_num, &data, I2C_TIMEOUT, 0);  //  No stop, continue with the read
+    if (rc) { return rc; }
+    data.buffer = val;
+    return hal_i2c_master_read(itf->si_num, &data, I2C_TIMEOUT, 1);
+}
+
+static int write_enable(struct hrs3300 *dev, uint8_t hen) {
+    //  Write the ENABLE and PDRIVER registers with the LED current.  Return 0 if successful.
+    uint8_t enable = ENABLE_WAIT_12_5MS
+        | (hen ? ENABLE_HEN : 0)
+        | ((dev->cfg.led_current & 0x02) ? ENABLE_PDRIVE1 : 0);
+    uint8_t pdriver = PDRIVER_RESERVED
+        | (hen ? PDRIVER_PON : 0)
+        | ((dev->cfg.led_current & 0x01) ? PDRIVER_PDRIVE0 : 0);
+    int rc = write_reg(dev, REG_ENABLE, enable);
+    if (rc) { return rc; }
+    return write_reg(dev, REG_PDRIVER, pdriver);
+}
+
+static void reset_pulse(struct hrs3300_pulse *pulse) {
+    memset(pulse, 0, sizeof(struct hrs3300_pulse));
+}
+
+static void process_sample(struct hrs3300_pulse *pulse, uint32_t sample, os_time_t now) {
+    //  Detect a pulse when the PPG signal rises above its average, then compute the heart rate from
+    //  the average interval between pulses.
+    int32_t x = (int32_t) sample;
+    if (pulse->dc == 0) { pulse->dc = x * 16; }   //  First sample
+    pulse->dc += x - pulse->dc / 16;              //  Track the DC level
+    int32_t previous = pulse->smoothed;
+    pulse->smoothed = (pulse->smoothed * 3 + (x - pulse->dc / 16)) / 4;  //  Remove DC and smooth the noise
+
+    uint32_t elapsed = os_time_ticks_to_ms32(now - pulse->last_beat);  //  Since the last pulse
+    if (pulse->last_beat != 0 && elapsed > 2 * MAX_INTERVAL_MS) {
+        //  No pulse for a while, e.g. the watch is not worn. Start over.
+        reset_pulse(pulse);
+        return;
+    }
+    if (!(previous <= 0 && pulse->smoothed > 0)) { return; }  //  Not a rising crossing
+    if (pulse->last_beat == 0) { pulse->last_beat = now; return; }  //  First pulse
+    if (elapsed < MIN_INTERVAL_MS) { return; }  //  Too soon, probably noise
+
+    pulse->last_beat = now;
+    if (elapsed > MAX_INTERVAL_MS) { return; }  //  Missed a pulse
+    pulse->intervals[pulse->next_interval] = elapsed;
+    pulse->next_interval = (pulse->next_interval + 1) % HRS3300_MAX_INTERVALS;
+    if (pulse->num_intervals < HRS3300_MAX_INTERVALS) { pulse->num_intervals++; }
+    if (pulse->num_intervals < MIN_INTERVALS) { return; }
+
+    uint32_t sum = 0;
+    for (int i = 0; i < pulse->num_intervals; i++) { sum += pulse->intervals[i]; }
+    pulse->bpm = 60000 * pulse->num_intervals / sum;
+}
+
+static void hrs3300_task_func(void *arg) {
+    //  Sample the sensor and update the heart rate while the sensor is started.
+    struct hrs3300 *dev = (struct hrs3300 *) arg;
+    uint32_t hrs;
+    for (;;) {
+        if (!dev->running) {
+            os_sem_pend(&dev->start_sem, OS_TIMEOUT_NEVER);  //  Wait for hrs3300_start()
+            continue;
+        }
+        if (hrs3300_read_samples(dev, &hrs, NULL) == 0) {
+            process_sample(&dev->pulse, hrs, os_time_get());
+        }
+        os_time_delay(os_time_ms_to_ticks32(SAMPLE_INTERVAL_MS));
+    }
+}
+
+int hrs3300_default_cfg(struct hrs3300_cfg *cfg) {
+    //  Return the default sensor configuration.
+    memset(cfg, 0, sizeof(struct hrs3300_cfg));  //  Zero the entire object.
+    cfg->mask        = SENSOR_TYPE_HEART_RATE;   //  Return heart rate values.
+    cfg->led_current = HRS3300_LED_20MA;
+    cfg->gain        = 0x10;                     //  64x gain
+    return 0;
+}
+
+static int hrs3300_open(struct os_dev *dev0, uint32_t timeout, void *arg) {
+    //  Nothing to set up, the I2C port is opened by the BSP.  Return 0 if successful.
+    return 0;
+}
+
+static int hrs3300_close(struct os_dev *dev0) {
+    //  Close the sensor.  Return 0 if successful.
+    return 0;
+}
+
+/**
+ * Expects to be called back through os_dev_create().
+ *
+ * @param The device object associated with hrs3300
+ * @param Argument passed to OS device init: the sensor interface
+ *
+ * @return 0 on success, non-zero error on failure.
+ */
+int hrs3300_init(struct os_dev *dev0, void *arg) {
+    struct hrs3300 *dev;
+    struct sensor *sensor;
+    int rc;
+    if (!arg || !dev0) { rc = SYS_ENODEV; goto err; }
+    dev = (struct hrs3300 *) dev0;
+
+    //  Get the default config.
+    rc = hrs3300_default_cfg(&dev->cfg);
+    if (rc) { goto err; }
+
+    //  Init the sensor.
+    sensor = &dev->sensor;
+    rc = sensor_init(sensor, dev0);
+    if (rc != 0) { goto err; }
+
+    //  Add the driver with all the supported sensor data types.
+    rc = sensor_set_driver(sensor, SENSOR_TYPE_HEART_RATE,
+        (struct sensor_driver *) &g_hrs3300_sensor_driver);
+    if (rc != 0) { goto err; }
+
+    //  Set the interface.
+    rc = sensor_set_interface(sensor, arg);
+    if (rc) { goto err; }
+
+    //  Register with the Sensor Manager.
+    rc = sensor_mgr_register(sensor);
+    if (rc != 0) { goto err; }
+
+    //  Create the task that samples the sensor. The task waits until the sensor is started.
+    reset_pulse(&dev->pulse);
+    dev->running = 0;
+    dev->suspended = 0;
+    rc = os_sem_init(&dev->start_sem, 0);
+    if (rc) { goto err; }
+    rc = os_task_init(&dev->task, "hrs3300", hrs3300_task_func, dev,
+        MYNEWT_VAL(HRS3300_TASK_PRIO), OS_WAIT_FOREVER,
+        hrs3300_stack, MYNEWT_VAL(HRS3300_TASK_STACK_SIZE));
+    if (rc) { goto err; }
+
+    //  Set the handlers for opening and closing the device.
+    OS_DEV_SETHANDLERS(dev0, hrs3300_open, hrs3300_close);
+    return (0);
+err:
+    return (rc);
+}
+
+int hrs3300_config(struct hrs3300 *dev, struct hrs3300_cfg *cfg) {
+    uint8_t id;
+    int rc;
+    rc = read_reg(dev, REG_ID, &id);
+    if (rc) { goto err; }
+    if (id != CHIP_ID) {
+        console_printf("HRS bad chip id %x\n", id);
+        rc = SYS_ENODEV; goto err;
+    }
+    dev->cfg = *cfg;
+
+    //  Keep the sensor and LED off until started.
+    rc = write_enable(dev, 0);
+    if (rc) { goto err; }
+    rc = write_reg(dev, REG_RES, RES_16BIT);
+    if (rc) { goto err; }
+    rc = write_reg(dev, REG_HGAIN, cfg->gain);
+    if (rc) { goto err; }
+
+    rc = sensor_set_type_mask(&(dev->sensor), cfg->mask);
+    if (rc) { goto err; }
+    return 0;
+err:
+    return (rc);
+}
+
+int hrs3300_start(struct hrs3300 *dev) {
+    int rc;
+    if (dev->running) { return 0; }
+    rc = write_enable(dev, 1);
+    if (rc) { return rc; }
+    reset_pulse(&dev->pulse);
+    dev->running = 1;
+    return os_sem_release(&dev->start_sem);
+}
+
+int hrs3300_stop(struct hrs3300 *dev) {
+    dev->running = 0;
+    dev->suspended = 0;
+    dev->pulse.bpm = 0;
+    return write_enable(dev, 0);
+}
+
+int hrs3300_suspend(struct hrs3300 *dev) {
+    int rc;
+    if (!dev->running) { return 0; }
+    rc = hrs3300_stop(dev);
+    dev->suspended = 1;  //  Even if the LED couldn't be switched off, the sampling has stopped
+    return rc;
+}
+
+int hrs3300_resume(struct hrs3300 *dev) {
+    if (!dev->suspended) { return 0; }
+    dev->suspended = 0;
+    return hrs3300_start(dev);
+}
+
+int hrs3300_set_led_current(struct hrs3300 *dev, uint8_t led_current) {
+    if (led_current > HRS3300_LED_40MA) { return SYS_EINVAL; }
+    dev->cfg.led_current = led_current;
+    return write_enable(dev, dev->running);
+}
+
+int hrs3300_read_samples(struct hrs3300 *dev, uint32_t *hrs, uint32_t *als) {
+    uint8_t m, h, l;
+    int rc;
+    //  Heart rate channel 0: bits are spread over 3 registers.
+    if ((rc = read_reg(dev, REG_C0DATAM, &m))) { return rc; }
+    if ((rc = read_reg(dev, REG_C0DATAH, &h))) { return rc; }
+    if ((rc = read_reg(dev, REG_C0DATAL, &l))) { return rc; }
+    *hrs = (m << 8) | ((h & 0x0f) << 4) | (l & 0x0f) | ((l & 0x30) << 12);
+    if (!als) { return 0; }
+
+    //  Ambient light channel 1.
+    if ((rc = read_reg(dev, REG_C1DATAM, &m))) { return rc; }
+    if ((rc = read_reg(dev, REG_C1DATAH, &h))) { return rc; }
+    if ((rc = read_reg(dev, REG_C1DATAL, &l))) { return rc; }
+    *als = (m << 3) | ((h & 0x3f) << 11) | (l & 0x07);
+    return 0;
+}
+
+uint32_t hrs3300_get_bpm(struct hrs3300 *dev) {
+    return dev->running ? dev->pulse.bpm : 0;
+}
+
+static int hrs3300_sensor_read(struct sensor *sensor, sensor_type_t type,
+    sensor_data_func_t data_func, void *data_arg, uint32_t timeout) {
+    //  Return the latest heart rate computed by the task.
+    struct sensor_heart_rate_data data;
+    struct hrs3300 *dev;
+    int rc;
+
+    //  We only allow reading of heart rate values.
+    if (!(type & SENSOR_TYPE_HEART_RATE)) { rc = SYS_EINVAL; goto err; }
+    dev = (struct hrs3300 *) SENSOR_GET_DEVICE(sensor); assert(dev);
+    data.shrd_bpm = hrs3300_get_bpm(dev);
+    data.shrd_bpm_is_valid = (data.shrd_bpm != 0);
+
+    if (data_func) {  //  Call the Listener Function to process the sensor data.
+        rc = data_func(sensor, data_arg, &data, SENSOR_TYPE_HEART_RATE);
+        if (rc) { goto err; }
+    }
+    return 0;
+err:
+    return rc;
+}
+
+static int hrs3300_sensor_get_config(struct sensor *sensor, sensor_type_t type,
+    struct sensor_cfg *cfg) {
+    //  Return the type of the sensor value returned by the sensor.
+    int rc;
+    if (!(type & SENSOR_TYPE_HEART_RATE)) {
+        rc = SYS_EINVAL;
+        goto err;
+    }
+    cfg->sc_valtype = SENSOR_VALUE_TYPE_INT32;  //  We return beats per minute as integer.
+    return (0);
+err:
+    return (rc);
+}
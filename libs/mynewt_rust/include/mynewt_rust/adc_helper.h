@@ -0,0 +1,86 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+
+#ifndef __ADC_HELPER_H__
+#define __ADC_HELPER_H__
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+//! Helper Functions for the nRF52 SAADC, opened through the Mynewt ADC driver. The nrfx configuration structs are
+//! awkward to build from Rust, so the settings are passed as the values of the nrfx enums.
+
+struct adc_dev;
+
+///  Settings of a SAADC channel for `adc_helper_chan_config()`. Each field is the value of the nrfx enum.
+struct adc_helper_chan_cfg {
+    ///  Analog input, `nrf_saadc_input_t`, e.g. `NRF_SAADC_INPUT_AIN7`
+    uint8_t input;
+    ///  Gain, `nrf_saadc_gain_t`, e.g. `NRF_SAADC_GAIN1_5`
+    uint8_t gain;
+    ///  Reference, `nrf_saadc_reference_t`: internal 0.6 V or VDD/4
+    uint8_t reference;
+    ///  Acquisition time, `nrf_saadc_acqtime_t`, longer for high impedance sources
+    uint8_t acq_time;
+    ///  Pull resistor on the input, `nrf_saadc_resistor_t`
+    uint8_t resistor;
+    ///  1 to take all the oversamples for a result in one burst
+    uint8_t burst;
+};
+
+///  Callback for buffered sampling, called in interrupt context with each full buffer of `count` samples
+typedef void adc_helper_buf_fn(void *arg, const int16_t *samples, int count);
+
+///  Return the SAADC configuration with the resolution (`nrf_saadc_resolution_t`) and oversampling
+///  (`nrf_saadc_oversample_t`), to be passed to `os_dev_open()` for the ADC device, e.g. "adc0".
+///  Returns NULL if the SAADC is disabled.
+void *adc_helper_dev_cfg(uint8_t resolution, uint8_t oversample);
+
+///  Configure the channel `chan`, from 0 to 7. Returns 0 if successful.
+int adc_helper_chan_config(struct adc_dev *adc, uint8_t chan, const struct adc_helper_chan_cfg *cfg);
+
+///  Sample the channel once and wait for the raw result. Returns 0 if successful.
+int adc_helper_read(struct adc_dev *adc, uint8_t chan, int *raw);
+
+///  Convert the raw result of the channel to millivolts at the input
+int adc_helper_result_mv(struct adc_dev *adc, uint8_t chan, int raw);
+
+///  Prepare buffered sampling into two buffers of `count` samples each, alternately: `cb` is called with each full
+///  buffer, which is sampled again after the callback returns. Returns 0 if successful.
+int adc_helper_buf_start(struct adc_dev *adc, int16_t *buf1, int16_t *buf2, int count,
+                         adc_helper_buf_fn *cb, void *arg);
+
+///  Stop calling the callback of buffered sampling, e.g. before the ADC device is closed. Returns 0 if successful.
+int adc_helper_buf_stop(struct adc_dev *adc);
+
+///  Take the next sample of the enabled channels into the current buffer. Returns 0 if successful.
+int adc_helper_sample(struct adc_dev *adc);
+
+///  Calibrate the offset of the SAADC and wait until done. Must not be sampling. Returns 0 if successful,
+///  `SYS_EBUSY` if the SAADC is sampling.
+int adc_helper_calibrate(struct adc_dev *adc);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* __ADC_HELPER_H__ */
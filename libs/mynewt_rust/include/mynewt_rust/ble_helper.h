@@ -0,0 +1,179 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+
+#ifndef __BLE_HELPER_H__
+#define __BLE_HELPER_H__
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+//! Helper Functions for the NimBLE host API, for the structs and unions that are awkward to access from Rust
+
+struct ble_gap_event;
+
+///  GAP event flattened from `struct ble_gap_event`. Fields that don't apply to the event type are 0.
+struct ble_helper_gap_info {
+    ///  Event type, e.g. `BLE_GAP_EVENT_CONNECT`
+    uint8_t  type;
+    ///  Connection handle
+    uint16_t conn_handle;
+    ///  Status of a connect, connection update or encryption change, reason of a disconnect, or reason of an
+    ///  advertising complete
+    int32_t  status;
+    ///  Attribute handle of a subscribe or notify TX event
+    uint16_t attr_handle;
+    ///  1 if notifications are enabled by a subscribe event
+    uint8_t  notify;
+    ///  ATT MTU negotiated by an MTU event
+    uint16_t mtu;
+};
+
+///  Callback for GAP events, called with the flattened event and the argument passed to `ble_helper_advertise()`
+typedef int ble_helper_gap_fn(const struct ble_helper_gap_info *info, void *arg);
+
+///  Set the callback for the host sync, when the host and controller are ready. Call before the host starts.
+void ble_helper_set_sync_cb(void (*cb)(void));
+
+///  Set the callback for the host reset, e.g. when the controller fails. Call before the host starts.
+void ble_helper_set_reset_cb(void (*cb)(int reason));
+
+///  Advertising configuration for `ble_helper_advertise_cfg()`
+struct ble_helper_adv_cfg {
+    ///  Device name
+    const char     *name;
+    ///  16-bit service UUIDs
+    const uint16_t *uuids16;
+    uint8_t         num_uuids16;
+    ///  Manufacturer-specific data, starting with the company identifier. NULL if none.
+    const uint8_t  *mfg_data;
+    uint8_t         mfg_data_len;
+    ///  1 if `tx_power` is set, else the controller default is used
+    uint8_t         tx_power_is_present;
+    ///  Transmit power in dBm
+    int8_t          tx_power;
+    ///  Min and max advertising intervals in units of 0.625 ms, or 0 for the NimBLE defaults
+    uint16_t        itvl_min;
+    uint16_t        itvl_max;
+    ///  1 for undirected connectable advertising, 0 for non-connectable advertising
+    uint8_t         connectable;
+    ///  Advertising duration in milliseconds, or negative to advertise forever
+    int32_t         duration_ms;
+};
+
+///  Start general discoverable advertising with the configuration `cfg`. `cb` is called with the GAP events of the
+///  advertising and of the connections. Returns 0 if successful, `BLE_HS_EMSGSIZE` if the fields don't fit in the
+///  31-byte advertising data.
+int ble_helper_advertise_cfg(const struct ble_helper_adv_cfg *cfg, ble_helper_gap_fn *cb, void *arg);
+
+///  Start general discoverable, undirected connectable advertising with the device name `name` and the 16-bit
+///  service UUIDs `uuids16`. Advertises for `duration_ms` milliseconds, or forever if negative.
+///  `cb` is called with the GAP events of the advertising and of the connections. Returns 0 if successful.
+int ble_helper_advertise(const char *name, const uint16_t *uuids16, uint8_t num_uuids16, int32_t duration_ms,
+    ble_helper_gap_fn *cb, void *arg);
+
+///  Set the callback for connection parameter updates, called with the connection handle and the status of the
+///  update: 0 if the central accepted the parameters. Receives the updates of all connections.
+///  Returns 0 if successful.
+int ble_helper_set_conn_update_cb(void (*cb)(uint16_t conn_handle, int status));
+
+///  Set the callback for the GAP events of all connections, including those not created by `ble_helper_advertise()`.
+///  The return value of `cb` is ignored. Returns 0 if successful.
+int ble_helper_set_event_listener(ble_helper_gap_fn *cb, void *arg);
+
+///  Return the number of ACL data buffers in the controller, i.e. the number of notifications that may be queued
+///  in the link layer
+uint8_t ble_helper_acl_buf_count(void);
+
+///  Ask the central to update the connection parameters: intervals in units of 1.25 ms, slave latency in connection
+///  events and supervision timeout in units of 10 ms. Returns 0 if the request was sent.
+int ble_helper_update_params(uint16_t conn_handle, uint16_t itvl_min, uint16_t itvl_max, uint16_t latency,
+    uint16_t supervision_timeout);
+
+///  Get the current parameters of the connection: interval in units of 1.25 ms, slave latency in connection
+///  events and supervision timeout in units of 10 ms. Returns 0 if successful, `BLE_HS_ENOTCONN` if not connected.
+int ble_helper_conn_params(uint16_t conn_handle, uint16_t *itvl, uint16_t *latency, uint16_t *supervision_timeout);
+
+///  Send a notification with `len` bytes of `data` for the characteristic value `attr_handle` on the connection.
+///  Returns 0 if successful.
+int ble_helper_notify(uint16_t conn_handle, uint16_t attr_handle, const uint8_t *data, uint16_t len);
+
+///  Ask for a faster link: the 2M PHY, the longest link layer packets (data length extension) and the largest
+///  ATT MTU in `syscfg.yml`. Each request is skipped if the controller doesn't support it, and the peer may refuse.
+///  The negotiated MTU is reported by a `BLE_GAP_EVENT_MTU` event. Returns 0 if the requests were sent.
+int ble_helper_high_throughput(uint16_t conn_handle);
+
+///  Return the ATT MTU of the connection, or 0 if not connected
+uint16_t ble_helper_att_mtu(uint16_t conn_handle);
+
+///  Read the signal strength of the last packet received on the connection, in dBm. Returns 0 if successful,
+///  `BLE_HS_ENOTCONN` if not connected.
+int ble_helper_conn_rssi(uint16_t conn_handle, int8_t *rssi);
+
+///  Get the security state of the connection: 1 if the link is encrypted, 1 if the key was authenticated by a
+///  passkey, 1 if the peer is bonded. Returns 0 if successful, `BLE_HS_ENOTCONN` if not connected.
+int ble_helper_conn_security(uint16_t conn_handle, uint8_t *encrypted, uint8_t *authenticated, uint8_t *bonded);
+
+///  Ask the central to pair, or to encrypt the link with the bond. The result is reported by the encryption change
+///  event. Returns 0 if successful, `BLE_HS_ENOTCONN` if not connected.
+int ble_helper_security_initiate(uint16_t conn_handle);
+
+///  Read the transmit power on the advertising channels, in dBm. Returns 0 if successful.
+int ble_helper_adv_tx_power(int8_t *tx_power);
+
+///  L2CAP CoC event types for `struct ble_helper_coc_info`
+#define BLE_HELPER_COC_CONNECTED    0  //  Channel connected
+#define BLE_HELPER_COC_DISCONNECTED 1  //  Channel disconnected
+#define BLE_HELPER_COC_READABLE     2  //  SDU received, read it with `ble_helper_coc_read()`
+#define BLE_HELPER_COC_WRITABLE     3  //  Peer gave credits, the SDU being sent has been sent
+
+///  L2CAP connection-oriented channel event, flattened from `struct ble_l2cap_event`
+struct ble_helper_coc_info {
+    ///  Event type, e.g. `BLE_HELPER_COC_CONNECTED`
+    uint8_t  type;
+    ///  Connection handle of the channel
+    uint16_t conn_handle;
+};
+
+///  Callback for L2CAP CoC events, called with the flattened event and the argument passed to `ble_helper_coc_listen()`
+typedef void ble_helper_coc_fn(const struct ble_helper_coc_info *info, void *arg);
+
+///  Accept L2CAP connection-oriented channels on the PSM, receiving SDUs of up to `mtu` bytes. One channel may be
+///  connected at a time. Returns 0 if successful, `BLE_HS_ENOTSUP` if `BLE_L2CAP_COC_MAX_NUM` is 0.
+int ble_helper_coc_listen(uint16_t psm, uint16_t mtu, ble_helper_coc_fn *cb, void *arg);
+
+///  Copy up to `len` bytes of the received SDU to `buf` and set `read_len` to the number of bytes copied, 0 if no SDU
+///  has been received. When the SDU has been read, the peer is given the credits to send the next SDU.
+///  Returns 0 if successful, `BLE_HS_ENOTCONN` if the channel is not connected.
+int ble_helper_coc_read(uint16_t conn_handle, uint8_t *buf, uint16_t len, uint16_t *read_len);
+
+///  Send `len` bytes of `data` as one SDU on the channel, at most the MTU of the peer. The SDU is sent as the peer
+///  gives credits. Returns 0 if successful, `BLE_HS_EBUSY` if the previous SDU has not been sent yet.
+int ble_helper_coc_write(uint16_t conn_handle, const uint8_t *data, uint16_t len);
+
+///  Disconnect the channel. Returns 0 if successful, `BLE_HS_ENOTCONN` if the channel is not connected.
+int ble_helper_coc_close(uint16_t conn_handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* __BLE_HELPER_H__ */
@@ -0,0 +1,149 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Helper Functions for the nRF52 SAADC, called by the Rust `mynewt::hal::saadc` module
+#include "sysinit/sysinit.h"
+#include "os/mynewt.h"
+#include "mynewt_rust/adc_helper.h"
+
+#if MYNEWT_VAL(ADC_0)  //  If the SAADC is enabled...
+#include <assert.h>
+#include <string.h>
+#include "adc/adc.h"
+#include "nrfx_saadc.h"
+
+///  Callback and argument for buffered sampling, passed to `adc_helper_buf_start()`
+static adc_helper_buf_fn *buf_cb;
+static void *buf_arg;
+
+///  SAADC configuration returned by `adc_helper_dev_cfg()`
+static nrfx_saadc_config_t dev_cfg = NRFX_SAADC_DEFAULT_CONFIG;
+
+void *adc_helper_dev_cfg(uint8_t resolution, uint8_t oversample) {
+    //  The resolution and oversampling apply to all channels, so they are set when the SAADC is opened.
+    dev_cfg.resolution = (nrf_saadc_resolution_t) resolution;
+    dev_cfg.oversample = (nrf_saadc_oversample_t) oversample;
+    return &dev_cfg;
+}
+
+int adc_helper_chan_config(struct adc_dev *adc, uint8_t chan, const struct adc_helper_chan_cfg *cfg) {
+    nrf_saadc_channel_config_t chan_cfg;
+    assert(adc);  assert(cfg);
+    memset(&chan_cfg, 0, sizeof(chan_cfg));
+    chan_cfg.resistor_p = (nrf_saadc_resistor_t) cfg->resistor;
+    chan_cfg.resistor_n = NRF_SAADC_RESISTOR_DISABLED;
+    chan_cfg.gain       = (nrf_saadc_gain_t) cfg->gain;
+    chan_cfg.reference  = (nrf_saadc_reference_t) cfg->reference;
+    chan_cfg.acq_time   = (nrf_saadc_acqtime_t) cfg->acq_time;
+    chan_cfg.mode       = NRF_SAADC_MODE_SINGLE_ENDED;
+    chan_cfg.burst      = cfg->burst ? NRF_SAADC_BURST_ENABLED : NRF_SAADC_BURST_DISABLED;
+    chan_cfg.pin_p      = (nrf_saadc_input_t) cfg->input;
+    chan_cfg.pin_n      = NRF_SAADC_INPUT_DISABLED;
+    return adc_chan_config(adc, chan, &chan_cfg);
+}
+
+int adc_helper_read(struct adc_dev *adc, uint8_t chan, int *raw) {
+    assert(adc);  assert(raw);
+    return adc_read_channel(adc, chan, raw);
+}
+
+int adc_helper_result_mv(struct adc_dev *adc, uint8_t chan, int raw) {
+    assert(adc);
+    return adc_result_mv(adc, chan, raw);
+}
+
+///  Pass each full buffer to the Rust callback, then give the buffer back to the SAADC. Called in interrupt context.
+static int adc_helper_event(struct adc_dev *adc, void *arg, adc_event_type_t type, void *buffer, int buffer_len) {
+    if (type != ADC_EVENT_RESULT) { return 0; }
+    if (buf_cb) { buf_cb(buf_arg, (const int16_t *) buffer, buffer_len / sizeof(int16_t)); }
+    return adc_buf_release(adc, buffer, buffer_len);
+}
+
+int adc_helper_buf_start(struct adc_dev *adc, int16_t *buf1, int16_t *buf2, int count,
+                         adc_helper_buf_fn *cb, void *arg) {
+    int rc;
+    assert(adc);  assert(buf1);  assert(buf2);  assert(cb);
+    buf_cb  = cb;
+    buf_arg = arg;
+    rc = adc_event_handler_set(adc, adc_helper_event, NULL);
+    if (rc) { return rc; }
+    return adc_buf_set(adc, buf1, buf2, count * sizeof(int16_t));
+}
+
+int adc_helper_buf_stop(struct adc_dev *adc) {
+    assert(adc);
+    buf_cb = NULL;
+    return adc_event_handler_set(adc, NULL, NULL);
+}
+
+int adc_helper_sample(struct adc_dev *adc) {
+    assert(adc);
+    return adc_sample(adc);
+}
+
+int adc_helper_calibrate(struct adc_dev *adc) {
+    assert(adc);
+    if (nrfx_saadc_calibrate_offset() != NRFX_SUCCESS) { return SYS_EBUSY; }
+    //  Calibration takes a few hundred microseconds. The driver is busy until the CALIBRATEDONE interrupt.
+    while (nrfx_saadc_is_busy()) {}
+    return 0;
+}
+
+#else  //  If the SAADC is disabled...
+
+void *adc_helper_dev_cfg(uint8_t resolution, uint8_t oversample) {
+    //  SAADC not enabled.
+    return NULL;
+}
+
+int adc_helper_chan_config(struct adc_dev *adc, uint8_t chan, const struct adc_helper_chan_cfg *cfg) {
+    //  SAADC not enabled.
+    return SYS_ENOTSUP;
+}
+
+int adc_helper_read(struct adc_dev *adc, uint8_t chan, int *raw) {
+    //  SAADC not enabled.
+    return SYS_ENOTSUP;
+}
+
+int adc_helper_result_mv(struct adc_dev *adc, uint8_t chan, int raw) {
+    //  SAADC not enabled.
+    return 0;
+}
+
+int adc_helper_buf_start(struct adc_dev *adc, int16_t *buf1, int16_t *buf2, int count,
+                         adc_helper_buf_fn *cb, void *arg) {
+    //  SAADC not enabled.
+    return SYS_ENOTSUP;
+}
+
+int adc_helper_buf_stop(struct adc_dev *adc) {
+    //  SAADC not enabled.
+    return SYS_ENOTSUP;
+}
+
+int adc_helper_sample(struct adc_dev *adc) {
+    //  SAADC not enabled.
+    return SYS_ENOTSUP;
+}
+
+int adc_helper_calibrate(struct adc_dev *adc) {
+    //  SAADC not enabled.
+    return SYS_ENOTSUP;
+}
+#endif  //  MYNEWT_VAL(ADC_0)
@@ -0,0 +1,547 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Helper Functions for the NimBLE host API, called by the Rust `mynewt::ble` module
+#include "sysinit/sysinit.h"
+
+#if MYNEWT_VAL(BLUETOOTH_LE)  //  If Bluetooth LE is enabled...
+#include <assert.h>
+#include <string.h>
+#include "host/ble_hs.h"
+#include "host/ble_hs_adv.h"
+#include "mynewt_rust/ble_helper.h"
+#if MYNEWT_VAL(BLE_CONTROLLER)  //  If the controller runs on this device...
+#include "controller/ble_phy.h"
+#endif  //  MYNEWT_VAL(BLE_CONTROLLER)
+
+///  Max number of 16-bit service UUIDs in the advertisement, to fit in the 31-byte advertising data
+#define MAX_ADV_UUIDS16 4
+
+///  Number of ACL buffers in the controller if the transport doesn't define BLE_ACL_BUF_COUNT
+#define BLE_HELPER_DEFAULT_ACL_BUF_COUNT 4
+
+///  Longest link layer payload and its transmit time in microseconds at 1M PHY, for data length extension
+#define BLE_HELPER_MAX_TX_OCTETS 251
+#define BLE_HELPER_MAX_TX_TIME   2120
+
+///  Callback and argument for the GAP events, passed to `ble_helper_advertise()`
+static ble_helper_gap_fn *gap_cb;
+static void *gap_arg;
+
+///  Flatten the GAP event for Rust
+static void ble_helper_flatten(const struct ble_gap_event *event, struct ble_helper_gap_info *info) {
+    memset(info, 0, sizeof(*info));
+    info->type = event->type;
+    switch (event->type) {
+    case BLE_GAP_EVENT_CONNECT:
+        info->conn_handle = event->connect.conn_handle;
+        info->status      = event->connect.status;
+        break;
+    case BLE_GAP_EVENT_DISCONNECT:
+        info->conn_handle = event->disconnect.conn.conn_handle;
+        info->status      = event->disconnect.reason;
+        break;
+    case BLE_GAP_EVENT_CONN_UPDATE:
+        info->conn_handle = event->conn_update.conn_handle;
+        info->status      = event->conn_update.status;
+        break;
+    case BLE_GAP_EVENT_ADV_COMPLETE:
+        info->status      = event->adv_complete.reason;
+        break;
+    case BLE_GAP_EVENT_ENC_CHANGE:
+        info->conn_handle = event->enc_change.conn_handle;
+        info->status      = event->enc_change.status;
+        break;
+    case BLE_GAP_EVENT_NOTIFY_TX:
+        info->conn_handle = event->notify_tx.conn_handle;
+        info->attr_handle = event->notify_tx.attr_handle;
+        info->status      = event->notify_tx.status;
+        break;
+    case BLE_GAP_EVENT_SUBSCRIBE:
+        info->conn_handle = event->subscribe.conn_handle;
+        info->attr_handle = event->subscribe.attr_handle;
+        info->notify      = event->subscribe.cur_notify;
+        break;
+    case BLE_GAP_EVENT_MTU:
+        info->conn_handle = event->mtu.conn_handle;
+        info->mtu         = event->mtu.value;
+        break;
+    default:
+        break;
+    }
+}
+
+///  Called by NimBLE with each GAP event. Flatten the event and call the Rust callback.
+static int ble_helper_gap_event(struct ble_gap_event *event, void *arg) {
+    struct ble_helper_gap_info info;
+    ble_helper_flatten(event, &info);
+    if (gap_cb == NULL) { return 0; }
+    return gap_cb(&info, gap_arg);
+}
+
+///  Callback for connection parameter updates, passed to `ble_helper_set_conn_update_cb()`
+static void (*conn_update_cb)(uint16_t conn_handle, int status);
+
+///  Callback and argument for the GAP events of all connections, passed to `ble_helper_set_event_listener()`
+static ble_helper_gap_fn *listener_cb;
+static void *listener_arg;
+
+///  Listener for the GAP events of all connections, including those not created by `ble_helper_advertise()`
+static struct ble_gap_event_listener conn_listener;
+
+///  1 if `conn_listener` has been registered
+static int conn_listener_registered;
+
+///  Called by NimBLE with each GAP event of all connections. Pass the connection updates and the flattened
+///  events to the Rust callbacks.
+static int ble_helper_conn_event(struct ble_gap_event *event, void *arg) {
+    struct ble_helper_gap_info info;
+    if (event->type == BLE_GAP_EVENT_CONN_UPDATE && conn_update_cb != NULL) {
+        conn_update_cb(event->conn_update.conn_handle, event->conn_update.status);
+    }
+    if (listener_cb != NULL) {
+        ble_helper_flatten(event, &info);
+        listener_cb(&info, listener_arg);
+    }
+    return 0;
+}
+
+///  Register the listener for the GAP events of all connections, if not registered yet
+static int ble_helper_register_listener(void) {
+    int rc;
+    if (conn_listener_registered) { return 0; }
+    rc = ble_gap_event_listener_register(&conn_listener, ble_helper_conn_event, NULL);
+    if (rc == 0) { conn_listener_registered = 1; }
+    return rc;
+}
+
+void ble_helper_set_sync_cb(void (*cb)(void)) {
+    ble_hs_cfg.sync_cb = cb;
+}
+
+void ble_helper_set_reset_cb(void (*cb)(int reason)) {
+    ble_hs_cfg.reset_cb = cb;
+}
+
+int ble_helper_advertise(const char *name, const uint16_t *uuids16, uint8_t num_uuids16, int32_t duration_ms,
+    ble_helper_gap_fn *cb, void *arg) {
+    struct ble_helper_adv_cfg cfg;
+    memset(&cfg, 0, sizeof(cfg));
+    cfg.name        = name;
+    cfg.uuids16     = uuids16;
+    cfg.num_uuids16 = num_uuids16;
+    cfg.connectable = 1;
+    cfg.duration_ms = duration_ms;
+    return ble_helper_advertise_cfg(&cfg, cb, arg);
+}
+
+int ble_helper_advertise_cfg(const struct ble_helper_adv_cfg *cfg, ble_helper_gap_fn *cb, void *arg) {
+    struct ble_hs_adv_fields fields;
+    struct ble_gap_adv_params adv_params;
+    ble_uuid16_t uuids[MAX_ADV_UUIDS16];
+    uint8_t own_addr_type;
+    int rc;
+    assert(cfg);  assert(cfg->name);
+    if (cfg->num_uuids16 > MAX_ADV_UUIDS16) { return BLE_HS_EINVAL; }
+
+    rc = ble_hs_id_infer_auto(0, &own_addr_type);
+    if (rc != 0) { return rc; }
+
+#if MYNEWT_VAL(BLE_CONTROLLER)  //  If the controller runs on this device, set the radio power directly.
+    if (cfg->tx_power_is_present) {
+        rc = ble_phy_txpwr_set(cfg->tx_power);
+        if (rc != 0) { return rc; }
+    }
+#endif  //  MYNEWT_VAL(BLE_CONTROLLER)
+
+    memset(&fields, 0, sizeof(fields));
+    fields.flags = BLE_HS_ADV_F_DISC_GEN | BLE_HS_ADV_F_BREDR_UNSUP;
+    fields.tx_pwr_lvl_is_present = 1;
+    fields.tx_pwr_lvl = cfg->tx_power_is_present ? cfg->tx_power : BLE_HS_ADV_TX_PWR_LVL_AUTO;
+    fields.name = (uint8_t *) cfg->name;
+    fields.name_len = strlen(cfg->name);
+    fields.name_is_complete = 1;
+    for (int i = 0; i < cfg->num_uuids16; i++) {
+        uuids[i].u.type = BLE_UUID_TYPE_16;
+        uuids[i].value = cfg->uuids16[i];
+    }
+    fields.uuids16 = uuids;
+    fields.num_uuids16 = cfg->num_uuids16;
+    fields.uuids16_is_complete = 1;
+    if (cfg->mfg_data != NULL && cfg->mfg_data_len > 0) {
+        fields.mfg_data = cfg->mfg_data;
+        fields.mfg_data_len = cfg->mfg_data_len;
+    }
+    rc = ble_gap_adv_set_fields(&fields);
+    if (rc != 0) { return rc; }
+
+    gap_cb = cb;
+    gap_arg = arg;
+    memset(&adv_params, 0, sizeof(adv_params));
+    adv_params.conn_mode = cfg->connectable ? BLE_GAP_CONN_MODE_UND : BLE_GAP_CONN_MODE_NON;
+    adv_params.disc_mode = BLE_GAP_DISC_MODE_GEN;
+    adv_params.itvl_min  = cfg->itvl_min;
+    adv_params.itvl_max  = cfg->itvl_max;
+    return ble_gap_adv_start(own_addr_type, NULL, (cfg->duration_ms < 0) ? BLE_HS_FOREVER : cfg->duration_ms,
+        &adv_params, ble_helper_gap_event, NULL);
+}
+
+int ble_helper_set_conn_update_cb(void (*cb)(uint16_t conn_handle, int status)) {
+    conn_update_cb = cb;
+    return ble_helper_register_listener();
+}
+
+int ble_helper_set_event_listener(ble_helper_gap_fn *cb, void *arg) {
+    listener_cb = cb;
+    listener_arg = arg;
+    return ble_helper_register_listener();
+}
+
+uint8_t ble_helper_acl_buf_count(void) {
+#ifdef MYNEWT_VAL_BLE_ACL_BUF_COUNT  //  If the transport defines the number of ACL buffers...
+    return MYNEWT_VAL(BLE_ACL_BUF_COUNT);
+#else
+    return BLE_HELPER_DEFAULT_ACL_BUF_COUNT;
+#endif  //  MYNEWT_VAL_BLE_ACL_BUF_COUNT
+}
+
+int ble_helper_update_params(uint16_t conn_handle, uint16_t itvl_min, uint16_t itvl_max, uint16_t latency,
+    uint16_t supervision_timeout) {
+    struct ble_gap_upd_params params;
+    memset(&params, 0, sizeof(params));
+    params.itvl_min            = itvl_min;
+    params.itvl_max            = itvl_max;
+    params.latency             = latency;
+    params.supervision_timeout = supervision_timeout;
+    return ble_gap_update_params(conn_handle, &params);
+}
+
+int ble_helper_conn_params(uint16_t conn_handle, uint16_t *itvl, uint16_t *latency, uint16_t *supervision_timeout) {
+    struct ble_gap_conn_desc desc;
+    int rc;
+    assert(itvl);  assert(latency);  assert(supervision_timeout);
+    rc = ble_gap_conn_find(conn_handle, &desc);
+    if (rc != 0) { return rc; }
+    *itvl = desc.conn_itvl;
+    *latency = desc.conn_latency;
+    *supervision_timeout = desc.supervision_timeout;
+    return 0;
+}
+
+int ble_helper_notify(uint16_t conn_handle, uint16_t attr_handle, const uint8_t *data, uint16_t len) {
+    struct os_mbuf *om = ble_hs_mbuf_from_flat(data, len);
+    if (om == NULL) { return BLE_HS_ENOMEM; }
+    return ble_gattc_notify_custom(conn_handle, attr_handle, om);
+}
+
+int ble_helper_high_throughput(uint16_t conn_handle) {
+    int rc;
+#if MYNEWT_VAL(BLE_LL_CFG_FEAT_LE_2M_PHY)  //  If the controller supports the 2M PHY...
+    rc = ble_gap_set_prefered_le_phy(conn_handle, BLE_GAP_LE_PHY_2M_MASK, BLE_GAP_LE_PHY_2M_MASK,
+        BLE_GAP_LE_PHY_CODED_ANY);
+    if (rc != 0) { return rc; }
+#endif  //  MYNEWT_VAL(BLE_LL_CFG_FEAT_LE_2M_PHY)
+#if MYNEWT_VAL(BLE_LL_CFG_FEAT_DATA_LEN_EXT)  //  If the controller supports data length extension...
+    rc = ble_hs_hci_util_set_data_len(conn_handle, BLE_HELPER_MAX_TX_OCTETS, BLE_HELPER_MAX_TX_TIME);
+    if (rc != 0) { return rc; }
+#endif  //  MYNEWT_VAL(BLE_LL_CFG_FEAT_DATA_LEN_EXT)
+    //  The MTU may be exchanged only once per connection, e.g. the phone may have exchanged it already.
+    rc = ble_gattc_exchange_mtu(conn_handle, NULL, NULL);
+    if (rc == BLE_HS_EALREADY) { return 0; }
+    return rc;
+}
+
+uint16_t ble_helper_att_mtu(uint16_t conn_handle) {
+    return ble_att_mtu(conn_handle);
+}
+
+int ble_helper_conn_rssi(uint16_t conn_handle, int8_t *rssi) {
+    assert(rssi);
+    return ble_gap_conn_rssi(conn_handle, rssi);
+}
+
+int ble_helper_adv_tx_power(int8_t *tx_power) {
+    assert(tx_power);
+    return ble_hs_hci_util_read_adv_tx_pwr(tx_power);
+}
+
+int ble_helper_conn_security(uint16_t conn_handle, uint8_t *encrypted, uint8_t *authenticated, uint8_t *bonded) {
+    struct ble_gap_conn_desc desc;
+    int rc;
+    assert(encrypted);  assert(authenticated);  assert(bonded);
+    rc = ble_gap_conn_find(conn_handle, &desc);
+    if (rc != 0) { return rc; }
+    *encrypted     = desc.sec_state.encrypted;
+    *authenticated = desc.sec_state.authenticated;
+    *bonded        = desc.sec_state.bonded;
+    return 0;
+}
+
+int ble_helper_security_initiate(uint16_t conn_handle) {
+    return ble_gap_security_initiate(conn_handle);
+}
+
+#if MYNEWT_VAL(BLE_L2CAP_COC_MAX_NUM) > 0  //  If L2CAP connection-oriented channels are enabled...
+
+///  Callback and argument for the L2CAP CoC events, passed to `ble_helper_coc_listen()`
+static ble_helper_coc_fn *coc_cb;
+static void *coc_arg;
+
+///  Max size of a received SDU
+static uint16_t coc_mtu;
+
+///  Connected channel and its connection, NULL if none
+static struct ble_l2cap_chan *coc_chan;
+static uint16_t coc_conn_handle = BLE_HS_CONN_HANDLE_NONE;
+
+///  Received SDU that has not been read completely, NULL if none
+static struct os_mbuf *coc_rx_sdu;
+
+///  1 if the buffer for the next SDU could not be allocated, so the peer has no credits until the next read
+static int coc_rx_pending;
+
+///  Give the channel a buffer for the next SDU, which gives credits to the peer
+static int ble_helper_coc_rx_ready(struct ble_l2cap_chan *chan) {
+    struct os_mbuf *sdu = os_msys_get_pkthdr(coc_mtu, 0);
+    coc_rx_pending = (sdu == NULL);
+    if (sdu == NULL) { return BLE_HS_ENOMEM; }
+    return ble_l2cap_recv_ready(chan, sdu);
+}
+
+///  Called by NimBLE with each L2CAP event of the server. Flatten the event and call the Rust callback.
+static int ble_helper_coc_event(struct ble_l2cap_event *event, void *arg) {
+    struct ble_helper_coc_info info;
+    memset(&info, 0, sizeof(info));
+    switch (event->type) {
+    case BLE_L2CAP_EVENT_COC_ACCEPT:
+        //  Reject the channel if another is connected, else give it a buffer for the first SDU.
+        if (coc_chan != NULL) { return BLE_HS_EBUSY; }
+        return ble_helper_coc_rx_ready(event->accept.chan);
+    case BLE_L2CAP_EVENT_COC_CONNECTED:
+        if (event->connect.status != 0) { return 0; }
+        coc_chan        = event->connect.chan;
+        coc_conn_handle = event->connect.conn_handle;
+        info.type = BLE_HELPER_COC_CONNECTED;
+        break;
+    case BLE_L2CAP_EVENT_COC_DISCONNECTED:
+        if (event->disconnect.chan != coc_chan) { return 0; }
+        if (coc_rx_sdu != NULL) { os_mbuf_free_chain(coc_rx_sdu); }
+        coc_rx_sdu      = NULL;
+        coc_chan        = NULL;
+        coc_conn_handle = BLE_HS_CONN_HANDLE_NONE;
+        info.type = BLE_HELPER_COC_DISCONNECTED;
+        break;
+    case BLE_L2CAP_EVENT_COC_DATA_RECEIVED:
+        //  Keep the SDU until it's read. The peer gets no credits for the next SDU until then.
+        if (coc_rx_sdu != NULL) { os_mbuf_concat(coc_rx_sdu, event->receive.sdu_rx); }
+        else { coc_rx_sdu = event->receive.sdu_rx; }
+        info.type = BLE_HELPER_COC_READABLE;
+        break;
+    case BLE_L2CAP_EVENT_COC_TX_UNSTALLED:
+        info.type = BLE_HELPER_COC_WRITABLE;
+        break;
+    default:
+        return 0;
+    }
+    info.conn_handle = (event->type == BLE_L2CAP_EVENT_COC_DISCONNECTED)
+        ? event->disconnect.conn_handle
+        : coc_conn_handle;
+    if (coc_cb != NULL) { coc_cb(&info, coc_arg); }
+    return 0;
+}
+
+int ble_helper_coc_listen(uint16_t psm, uint16_t mtu, ble_helper_coc_fn *cb, void *arg) {
+    coc_cb  = cb;
+    coc_arg = arg;
+    coc_mtu = mtu;
+    return ble_l2cap_create_server(psm, mtu, ble_helper_coc_event, NULL);
+}
+
+int ble_helper_coc_read(uint16_t conn_handle, uint8_t *buf, uint16_t len, uint16_t *read_len) {
+    uint16_t avail;
+    int rc;
+    assert(buf);  assert(read_len);
+    *read_len = 0;
+    if (coc_chan == NULL || conn_handle != coc_conn_handle) { return BLE_HS_ENOTCONN; }
+    if (coc_rx_sdu == NULL) {
+        //  Retry the buffer for the next SDU if it could not be allocated.
+        return coc_rx_pending ? ble_helper_coc_rx_ready(coc_chan) : 0;
+    }
+    avail = OS_MBUF_PKTLEN(coc_rx_sdu);
+    if (len > avail) { len = avail; }
+    rc = os_mbuf_copydata(coc_rx_sdu, 0, len, buf);
+    if (rc != 0) { return BLE_HS_EUNKNOWN; }
+    os_mbuf_adj(coc_rx_sdu, len);
+    *read_len = len;
+    if (OS_MBUF_PKTLEN(coc_rx_sdu) > 0) { return 0; }
+
+    //  The SDU has been read. Give the peer the credits for the next SDU.
+    os_mbuf_free_chain(coc_rx_sdu);
+    coc_rx_sdu = NULL;
+    ble_helper_coc_rx_ready(coc_chan);  //  Retried by the next read if out of buffers
+    return 0;
+}
+
+int ble_helper_coc_write(uint16_t conn_handle, const uint8_t *data, uint16_t len) {
+    struct os_mbuf *sdu;
+    int rc;
+    if (coc_chan == NULL || conn_handle != coc_conn_handle) { return BLE_HS_ENOTCONN; }
+    sdu = ble_hs_mbuf_from_flat(data, len);
+    if (sdu == NULL) { return BLE_HS_ENOMEM; }
+    rc = ble_l2cap_send(coc_chan, sdu);
+    //  A stalled SDU is kept by NimBLE and sent when the peer gives credits.
+    if (rc == BLE_HS_ESTALLED) { return 0; }
+    //  A busy channel doesn't take the SDU.
+    if (rc == BLE_HS_EBUSY) { os_mbuf_free_chain(sdu); }
+    return rc;
+}
+
+int ble_helper_coc_close(uint16_t conn_handle) {
+    if (coc_chan == NULL || conn_handle != coc_conn_handle) { return BLE_HS_ENOTCONN; }
+    return ble_l2cap_disconnect(coc_chan);
+}
+
+#else  //  If L2CAP connection-oriented channels are disabled...
+
+int ble_helper_coc_listen(uint16_t psm, uint16_t mtu, ble_helper_coc_fn *cb, void *arg) {
+    //  L2CAP CoC not supported.
+    return BLE_HS_ENOTSUP;
+}
+
+int ble_helper_coc_read(uint16_t conn_handle, uint8_t *buf, uint16_t len, uint16_t *read_len) {
+    //  L2CAP CoC not supported.
+    return BLE_HS_ENOTSUP;
+}
+
+int ble_helper_coc_write(uint16_t conn_handle, const uint8_t *data, uint16_t len) {
+    //  L2CAP CoC not supported.
+    return BLE_HS_ENOTSUP;
+}
+
+int ble_helper_coc_close(uint16_t conn_handle) {
+    //  L2CAP CoC not supported.
+    return BLE_HS_ENOTSUP;
+}
+#endif  //  MYNEWT_VAL(BLE_L2CAP_COC_MAX_NUM) > 0
+
+#else  //  If Bluetooth LE is disabled...
+#include "mynewt_rust/ble_helper.h"
+
+///  Same value as `BLE_HS_ENOTSUP`
+#define BLE_HELPER_ENOTSUP 8
+
+void ble_helper_set_sync_cb(void (*cb)(void)) {
+    //  Bluetooth LE not supported.
+}
+
+void ble_helper_set_reset_cb(void (*cb)(int reason)) {
+    //  Bluetooth LE not supported.
+}
+
+int ble_helper_advertise_cfg(const struct ble_helper_adv_cfg *cfg, ble_helper_gap_fn *cb, void *arg) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_advertise(const char *name, const uint16_t *uuids16, uint8_t num_uuids16, int32_t duration_ms,
+    ble_helper_gap_fn *cb, void *arg) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_set_conn_update_cb(void (*cb)(uint16_t conn_handle, int status)) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_set_event_listener(ble_helper_gap_fn *cb, void *arg) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+uint8_t ble_helper_acl_buf_count(void) {
+    //  Bluetooth LE not supported.
+    return 0;
+}
+
+int ble_helper_update_params(uint16_t conn_handle, uint16_t itvl_min, uint16_t itvl_max, uint16_t latency,
+    uint16_t supervision_timeout) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_conn_params(uint16_t conn_handle, uint16_t *itvl, uint16_t *latency, uint16_t *supervision_timeout) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_notify(uint16_t conn_handle, uint16_t attr_handle, const uint8_t *data, uint16_t len) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_high_throughput(uint16_t conn_handle) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+uint16_t ble_helper_att_mtu(uint16_t conn_handle) {
+    //  Bluetooth LE not supported.
+    return 0;
+}
+
+int ble_helper_conn_rssi(uint16_t conn_handle, int8_t *rssi) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_adv_tx_power(int8_t *tx_power) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_conn_security(uint16_t conn_handle, uint8_t *encrypted, uint8_t *authenticated, uint8_t *bonded) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_security_initiate(uint16_t conn_handle) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_coc_listen(uint16_t psm, uint16_t mtu, ble_helper_coc_fn *cb, void *arg) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_coc_read(uint16_t conn_handle, uint8_t *buf, uint16_t len, uint16_t *read_len) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_coc_write(uint16_t conn_handle, const uint8_t *data, uint16_t len) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+
+int ble_helper_coc_close(uint16_t conn_handle) {
+    //  Bluetooth LE not supported.
+    return BLE_HELPER_ENOTSUP;
+}
+#endif  //  MYNEWT_VAL(BLUETOOTH_LE)
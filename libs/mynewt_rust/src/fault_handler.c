@@ -0,0 +1,36 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Entry point of the fault handlers for the Rust module `mynewt::kernel::fault`, installed in the vector table for
+//  HardFault, MemManage, BusFault and UsageFault by `fault::start()` in rust/mynewt/src/kernel/fault.rs.
+//  The CPU stacks r0-r3, r12, lr, pc and xPSR on the stack that was in use: the Process Stack for a task, or the
+//  Main Stack for an interrupt. Bit 2 of EXC_RETURN in lr tells which. We pass the stack frame and EXC_RETURN to
+//  rust_fault_handler(), which records the fault and restarts the device. Naked, so that the stack is untouched.
+
+void rust_fault_handler(const unsigned int *frame, unsigned int exc_return);
+
+__attribute__((naked)) void rust_fault_entry(void) {
+    __asm volatile(
+        "tst   lr, #4               \n"  //  Which stack has the frame?
+        "ite   eq                   \n"
+        "mrseq r0, msp              \n"  //  Main Stack
+        "mrsne r0, psp              \n"  //  Process Stack
+        "mov   r1, lr               \n"  //  EXC_RETURN
+        "b     rust_fault_handler   \n"  //  Never returns
+    );
+}
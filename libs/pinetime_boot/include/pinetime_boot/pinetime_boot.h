@@ -20,11 +20,52 @@
 #ifndef __PINETIME_BOOT_H__
 #define __PINETIME_BOOT_H__
 #include <stdint.h>
+#include "pinetime_logo/pinetime_logo.h"
 
 #ifdef __cplusplus
 extern "C" {  //  Expose the types and functions below to C functions.
 #endif
 
+/// Max number of logo slots in SPI Flash
+#define PINETIME_BOOT_MAX_LOGO_SLOTS 2
+
+/// Size of each logo slot in SPI Flash
+#define PINETIME_BOOT_LOGO_SLOT_SIZE 0x1D000
+
+/// Offset of the logo index table in SPI Flash, after the last logo slot
+#define PINETIME_BOOT_LOGO_INDEX_OFFSET (PINETIME_BOOT_LOGO_SLOT_SIZE * PINETIME_BOOT_MAX_LOGO_SLOTS)
+
+/// Magic number that marks a valid logo index table: `LOGO`
+#define PINETIME_BOOT_LOGO_INDEX_MAGIC 0x4c4f474f
+
+/// Metadata for a logo slot. Must sync with `LogoSlot` in rust/app/src/logo/index.rs
+struct pinetime_boot_logo_slot {
+    char     name[16];  //  Null-terminated name of the logo. Empty if the slot is unused.
+    uint32_t offset;    //  Offset of the logo in SPI Flash
+    uint32_t length;    //  Length of the logo in bytes
+    uint32_t checksum;  //  CRC32 of the logo
+};
+
+/// Manifest for a logo slot. Must sync with `LogoManifest` in rust/app/src/logo/manifest.rs
+struct pinetime_boot_logo_manifest {
+    uint16_t format_version;  //  Version of the manifest format
+    uint16_t reserved;
+    char     version[16];     //  Null-terminated image version string
+    uint32_t logo_crc;        //  CRC32 of the logo
+    uint32_t timestamp;       //  Time that the logo was created, in seconds since 1970. 0 if unknown.
+    uint32_t manifest_crc;    //  CRC32 of the fields above
+};
+
+/// Logo index table in SPI Flash. Must sync with `LogoIndex` in rust/app/src/logo/index.rs
+struct pinetime_boot_logo_index {
+    uint32_t magic;     //  Must be PINETIME_BOOT_LOGO_INDEX_MAGIC
+    uint8_t  active;    //  Slot to be displayed by the bootloader
+    uint8_t  reserved[3];
+    struct pinetime_boot_logo_slot slots[PINETIME_BOOT_MAX_LOGO_SLOTS];
+    struct pinetime_boot_logo_manifest manifests[PINETIME_BOOT_MAX_LOGO_SLOTS];
+    struct pinetime_logo_relocation relocations[PINETIME_LOGO_MAX_RELOCATIONS];  //  Bad sectors relocated to spare sectors
+};
+
 /// Init the display and render the boot graphic. Called by sysinit() during startup, defined in pkg.yml.
 void pinetime_boot_init(void);
 
@@ -34,6 +75,9 @@ int pinetime_boot_write_image(void);
 /// Display the image in SPI Flash to ST7789 display controller
 int pinetime_boot_display_image(void);
 
+/// Return the offset in SPI Flash of the logo selected in the logo index table. Returns 0 if there is no index table.
+uint32_t pinetime_boot_logo_offset(void);
+
 /// Check whether the watch button is pressed
 void pinetime_boot_check_button(void);
 
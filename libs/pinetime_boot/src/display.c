@@ -28,6 +28,7 @@
 #include <stdio.h>
 #include <string.h>
 #include "pinetime_boot/pinetime_boot.h"
+#include "pinetime_logo/pinetime_logo.h"
 
 //  GPIO Pins. From rust\piet-embedded\piet-embedded-graphics\src\display.rs
 #define DISPLAY_SPI   0  //  Mynewt SPI port 0
@@ -50,6 +51,9 @@
 //  Flash Device for Image
 #define FLASH_DEVICE 1  //  0 for Internal Flash ROM, 1 for External SPI Flash
 
+//  Colour of the built-in fallback image (RGB565), rendered when the logo in flash is invalid
+#define FALLBACK_COLOUR 0x001f  //  Blue
+
 //  ST7789 Commands. From https://github.com/lupyuen/st7735-lcd-batch-rs/blob/master/src/instruction.rs
 #define NOP 0x00
 #define SWRESET 0x01
@@ -96,6 +100,8 @@
 #define LandscapeSwapped 0xA0
 
 static int init_display(void);
+static int display_fallback(void);
+static int display_frame(uint32_t base, uint32_t length, int compressed);
 static int set_window(uint8_t left, uint8_t top, uint8_t right, uint8_t bottom);
 static int hard_reset(void);
 static int set_orientation(uint8_t orientation);
@@ -110,43 +116,26 @@ static uint8_t flash_buffer[BATCH_SIZE];
 /// Display the image in SPI Flash to ST7789 display controller. 
 /// Derived from https://github.com/lupyuen/pinetime-rust-mynewt/blob/main/logs/spi-non-blocking.log
 int pinetime_boot_display_image(void) {
-    console_printf("Displaying image...\n"); console_flush();
+    uint32_t base = pinetime_boot_logo_offset();
+    console_printf("Displaying image at 0x%lx...\n", (long unsigned int) base); console_flush();
     int rc = init_display();  assert(rc == 0);
     rc = set_orientation(Landscape);  assert(rc == 0);
 
-    //  Render each row of pixels.
-    for (uint8_t row = 0; row < ROW_COUNT; row++) {
-        uint8_t top = row;
-        uint8_t bottom = row;
-        uint8_t left = 0;
-        //  Screen Buffer: 240 * 240 * 2 / 1024 = 112.5 KB
-        //  Render a batch of columns in that row.
-        for (;;) {
-            if (left >= COL_COUNT) { break; }
-
-            //  How many columns we will render in a batch.
-            uint16_t batch_columns = BATCH_SIZE / BYTES_PER_PIXEL;
-            uint16_t right = left + batch_columns - 1;
-            if (right >= COL_COUNT) { right = COL_COUNT - 1; }
-
-            //  How many bytes we will transmit.
-            uint16_t len = (right - left + 1) * BYTES_PER_PIXEL;
-
-            //  Read the bytes from flash memory.
-            uint32_t offset = ((top * COL_COUNT) + left) * BYTES_PER_PIXEL;
-            int rc = hal_flash_read(FLASH_DEVICE, offset, flash_buffer, len); assert(rc == 0);
-
-            //  console_printf("%lx: ", offset); console_dump(flash_buffer, len); console_printf("\n"); console_flush();
-
-            //  Set the display window.
-            rc = set_window(left, top, right, bottom); assert(rc == 0);
-
-            //  Write Pixels (RAMWR): st7735_lcd::draw() → set_pixel()
-            rc = write_command(RAMWR, NULL, 0); assert(rc == 0);
-            rc = write_data(flash_buffer, len); assert(rc == 0);
+    //  Validate the logo header and checksum. If the logo is invalid, render the built-in image.
+    uint32_t header_offset = base + PINETIME_BOOT_LOGO_SLOT_SIZE - PINETIME_LOGO_HEADER_SIZE;
+    rc = pinetime_logo_validate(FLASH_DEVICE, base, header_offset);
+    if (rc != PINETIME_LOGO_OK) {
+        console_printf("Invalid logo (%d), displaying built-in image\n", rc); console_flush();
+        return display_fallback();
+    }
 
-            left = right + 1;
-        }
+    //  Render the logo into a full-screen window. Compressed logos are decompressed while rendering.
+    struct pinetime_logo_header header;
+    rc = pinetime_logo_read(FLASH_DEVICE, header_offset, &header, sizeof(header)); assert(rc == 0);
+    rc = display_frame(base, header.length, header.format == PINETIME_LOGO_FORMAT_HEATSHRINK);
+    if (rc != 0) {
+        console_printf("Logo truncated, displaying built-in image\n"); console_flush();
+        return display_fallback();
     }
 
     /*
@@ -185,6 +174,76 @@ int pinetime_boot_display_image(void) {
     return 0;
 }
 
+/// Return the offset in SPI Flash of the logo selected in the logo index table. Returns 0 if there is no index table.
+uint32_t pinetime_boot_logo_offset(void) {
+    static struct pinetime_boot_logo_index index;
+    int rc = hal_flash_read(FLASH_DEVICE, PINETIME_BOOT_LOGO_INDEX_OFFSET, &index, sizeof(index));
+    if (rc != 0 || index.magic != PINETIME_BOOT_LOGO_INDEX_MAGIC) { return 0; }  //  No index table, use the default logo
+    pinetime_logo_set_relocations(index.relocations, PINETIME_LOGO_MAX_RELOCATIONS);  //  Read bad sectors from spare sectors
+    if (index.active >= PINETIME_BOOT_MAX_LOGO_SLOTS) { return 0; }
+    const struct pinetime_boot_logo_slot *slot = &index.slots[index.active];
+    if (slot->length == 0) { return 0; }  //  Slot is empty
+    return slot->offset;
+}
+
+/// Render the built-in fallback image, a solid colour, when the logo in flash is invalid.
+/// The bootloader is too small to embed a full logo.
+static int display_fallback(void) {
+    //  Fill the flash buffer with the fallback colour.
+    for (int i = 0; i < BATCH_SIZE; i += BYTES_PER_PIXEL) {
+        flash_buffer[i]     = FALLBACK_COLOUR >> 8;
+        flash_buffer[i + 1] = FALLBACK_COLOUR & 0xff;
+    }
+    //  Render each row of pixels.
+    for (uint8_t row = 0; row < ROW_COUNT; row++) {
+        uint8_t left = 0;
+        for (;;) {
+            if (left >= COL_COUNT) { break; }
+
+            //  How many columns we will render in a batch.
+            uint16_t right = left + (BATCH_SIZE / BYTES_PER_PIXEL) - 1;
+            if (right >= COL_COUNT) { right = COL_COUNT - 1; }
+            uint16_t len = (right - left + 1) * BYTES_PER_PIXEL;
+
+            int rc = set_window(left, row, right, row); assert(rc == 0);
+            rc = write_command(RAMWR, NULL, 0); assert(rc == 0);
+            rc = write_data(flash_buffer, len); assert(rc == 0);
+
+            left = right + 1;
+        }
+    }
+    console_printf("Built-in image displayed\n"); console_flush();
+    return 0;
+}
+
+/// Decoder for compressed logos
+static struct pinetime_logo_decoder decoder;
+
+/// Render the logo with `length` bytes in SPI Flash at `base` (decompressed if `compressed` is set) into a
+/// full-screen display window. The pixels are read in batches of BATCH_SIZE bytes (an even number, so pixels
+/// are never split), and each batch is transmitted to the display before the next batch is read, since the flash
+/// and the display share SPI port 0. The window is set once for the whole frame instead of once per batch.
+/// Returns 0 if successful, or -1 if the logo doesn't have a full frame of pixels.
+static int display_frame(uint32_t base, uint32_t length, int compressed) {
+    if (compressed) { pinetime_logo_decoder_init(&decoder, FLASH_DEVICE, base, length); }
+    int rc = set_window(0, 0, COL_COUNT - 1, ROW_COUNT - 1); assert(rc == 0);
+    rc = write_command(RAMWR, NULL, 0); assert(rc == 0);
+
+    //  Read each batch of pixels, then transmit it.
+    uint32_t offset = 0;
+    uint32_t total = ROW_COUNT * COL_COUNT * BYTES_PER_PIXEL;
+    while (offset < total) {
+        uint16_t len = (total - offset > BATCH_SIZE) ? BATCH_SIZE : total - offset;
+        int count = compressed
+            ? pinetime_logo_decoder_read(&decoder, flash_buffer, len)
+            : ((pinetime_logo_read(FLASH_DEVICE, base + offset, flash_buffer, len) == 0) ? len : -1);
+        if (count != len) { break; }
+        rc = write_data(flash_buffer, len); assert(rc == 0);
+        offset += len;
+    }
+    return (offset == total) ? 0 : -1;
+}
+
 /// Set the ST7789 display window to the coordinates (left, top), (right, bottom)
 static int set_window(uint8_t left, uint8_t top, uint8_t right, uint8_t bottom) {
     assert(left < COL_COUNT && right < COL_COUNT && top < ROW_COUNT && bottom < ROW_COUNT);
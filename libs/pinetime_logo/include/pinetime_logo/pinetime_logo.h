@@ -0,0 +1,134 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Logo header and validator shared by the bootloader and the application
+#ifndef __PINETIME_LOGO_H__
+#define __PINETIME_LOGO_H__
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {  //  Expose the types and functions below to C functions.
+#endif
+
+/// Magic number that marks a valid logo header: `LGHD`
+#define PINETIME_LOGO_HEADER_MAGIC 0x4448474c
+
+/// Version of the logo header format
+#define PINETIME_LOGO_HEADER_VERSION 1
+
+/// Size of the logo header. The header is stored in the last bytes of the logo slot.
+#define PINETIME_LOGO_HEADER_SIZE 32
+
+/// Logo format: Raw RGB565 pixels, 2 bytes per pixel, row by row
+#define PINETIME_LOGO_FORMAT_RGB565 1
+
+/// Logo format: RGB565 pixels compressed with heatshrink (window 2^8 bytes, lookahead 2^4 bytes),
+/// e.g. `heatshrink -e -w 8 -l 4`. The header length and CRC32 refer to the compressed bytes.
+#define PINETIME_LOGO_FORMAT_HEATSHRINK 2
+
+/// heatshrink parameters for PINETIME_LOGO_FORMAT_HEATSHRINK
+#define PINETIME_LOGO_WINDOW_BITS    8
+#define PINETIME_LOGO_LOOKAHEAD_BITS 4
+#define PINETIME_LOGO_WINDOW_SIZE    (1 << PINETIME_LOGO_WINDOW_BITS)
+
+/// Number of compressed bytes read from flash in a batch by the decoder
+#define PINETIME_LOGO_DECODER_INPUT_SIZE 64
+
+/// Logo dimensions supported by the ST7789 display
+#define PINETIME_LOGO_WIDTH  240
+#define PINETIME_LOGO_HEIGHT 240
+
+/// Size of a flash sector. Bad sectors are relocated one sector at a time.
+#define PINETIME_LOGO_SECTOR_SIZE 4096
+
+/// Max number of bad sectors that may be relocated to spare sectors
+#define PINETIME_LOGO_MAX_RELOCATIONS 4
+
+/// Result codes returned by pinetime_logo_validate()
+#define PINETIME_LOGO_OK          0  //  Logo is valid
+#define PINETIME_LOGO_EREAD      -1  //  Flash read failed
+#define PINETIME_LOGO_EMAGIC     -2  //  Header is missing
+#define PINETIME_LOGO_EFORMAT    -3  //  Unsupported header version, format, dimensions or length
+#define PINETIME_LOGO_ECHECKSUM  -4  //  CRC32 of the logo doesn't match the header
+
+/// Logo header stored at the end of the logo slot. Must sync with `LogoHeader` in rust/app/src/logo/header.rs
+struct pinetime_logo_header {
+    uint32_t magic;        //  Must be PINETIME_LOGO_HEADER_MAGIC
+    uint16_t version;      //  Must be PINETIME_LOGO_HEADER_VERSION
+    uint16_t format;       //  PINETIME_LOGO_FORMAT_RGB565 or PINETIME_LOGO_FORMAT_HEATSHRINK
+    uint16_t width;        //  Width of the logo in pixels
+    uint16_t height;       //  Height of the logo in pixels
+    uint32_t length;       //  Length of the logo in bytes, as stored in flash
+    uint32_t checksum;     //  CRC32 of the logo
+    uint32_t reserved[3];  //  Reserved, set to 0
+};
+
+/// Bad sector that has been relocated to a spare sector. Stored in the logo index table.
+/// Must sync with `LogoRelocation` in rust/app/src/logo/relocate.rs
+struct pinetime_logo_relocation {
+    uint32_t bad_offset;    //  Flash offset of the bad sector. 0xffffffff if the entry is unused.
+    uint32_t spare_offset;  //  Flash offset of the spare sector that holds the data
+};
+
+/// Streaming decoder for PINETIME_LOGO_FORMAT_HEATSHRINK. Reads the compressed logo from flash in batches of
+/// PINETIME_LOGO_DECODER_INPUT_SIZE bytes and keeps the last PINETIME_LOGO_WINDOW_SIZE decompressed bytes for back-references,
+/// so the logo is decompressed in any size of output chunks without buffering the whole frame.
+/// Must sync with `Decoder` in rust/app/src/logo/decompress.rs
+struct pinetime_logo_decoder {
+    uint32_t offset;         //  Flash offset of the next compressed byte to be read
+    uint32_t end;            //  Flash offset after the last compressed byte
+    uint16_t input_len;      //  Number of bytes in input
+    uint16_t input_pos;      //  Index of the next byte in input
+    uint16_t head;           //  Number of decompressed bytes, modulo the window size
+    uint16_t backref_index;  //  Distance of the back-reference being copied
+    uint16_t backref_count;  //  Number of bytes remaining in the back-reference being copied
+    uint8_t  flash_id;       //  Flash device
+    uint8_t  bit_mask;       //  Mask of the next bit in current_byte, 0 if a byte must be fetched
+    uint8_t  current_byte;   //  Compressed byte being decoded
+    uint8_t  reserved[3];    //  Reserved, set to 0
+    uint8_t  input[PINETIME_LOGO_DECODER_INPUT_SIZE];  //  Compressed bytes read from flash
+    uint8_t  window[PINETIME_LOGO_WINDOW_SIZE];        //  Last decompressed bytes
+};
+
+/// Validate the logo in flash device `flash_id` at `logo_offset`, with the header at `header_offset`.
+/// Checks the header fields and the CRC32 of the logo. Returns PINETIME_LOGO_OK if the logo is valid.
+int pinetime_logo_validate(uint8_t flash_id, uint32_t logo_offset, uint32_t header_offset);
+
+/// Set the table of `count` relocated sectors that will be used by pinetime_logo_read(), the validator and the decoder.
+/// The table must remain valid until the next call. Pass NULL if there are no relocated sectors.
+void pinetime_logo_set_relocations(const struct pinetime_logo_relocation *relocations, int count);
+
+/// Read `len` bytes from flash device `flash_id` at `offset` into `dst`, reading relocated sectors from their
+/// spare sectors. Returns 0 if successful, or PINETIME_LOGO_EREAD if the flash read failed.
+int pinetime_logo_read(uint8_t flash_id, uint32_t offset, void *dst, uint32_t len);
+
+/// Start decoding the `length` bytes of compressed logo in flash device `flash_id` at `offset`
+void pinetime_logo_decoder_init(struct pinetime_logo_decoder *decoder, uint8_t flash_id, uint32_t offset, uint32_t length);
+
+/// Decompress up to `len` bytes into `out`. Returns the number of bytes decompressed, which is less than `len`
+/// only at the end of the logo, or PINETIME_LOGO_EREAD if the flash read failed.
+int pinetime_logo_decoder_read(struct pinetime_logo_decoder *decoder, uint8_t *out, uint16_t len);
+
+/// Update the running CRC32 (IEEE 802.3) with `len` bytes in `data`. Start with 0xffffffff and XOR the final result with 0xffffffff.
+uint32_t pinetime_logo_crc32(uint32_t crc, const uint8_t *data, uint32_t len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif  //  __PINETIME_LOGO_H__
@@ -0,0 +1,118 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Streaming heatshrink decoder for compressed logos, shared by the bootloader and the application.
+//  Compatible with the heatshrink encoder (https://github.com/atomicobject/heatshrink) with window 2^8 and lookahead 2^4.
+//  Bitstream is MSB first. Each tag bit is followed by either a literal byte (tag 1) or a back-reference (tag 0)
+//  made of the distance minus 1 (PINETIME_LOGO_WINDOW_BITS) and the count minus 1 (PINETIME_LOGO_LOOKAHEAD_BITS).
+#include <inttypes.h>
+#include <string.h>
+#include "pinetime_logo/pinetime_logo.h"
+
+#define WINDOW_MASK (PINETIME_LOGO_WINDOW_SIZE - 1)
+#define DECODER_END -100  //  Internal result code: no more compressed bits
+
+static int get_bits(struct pinetime_logo_decoder *decoder, uint8_t count, uint16_t *bits);
+static int next_byte(struct pinetime_logo_decoder *decoder);
+
+/// Start decoding the `length` bytes of compressed logo in flash device `flash_id` at `offset`
+void pinetime_logo_decoder_init(struct pinetime_logo_decoder *decoder, uint8_t flash_id, uint32_t offset, uint32_t length) {
+    memset(decoder, 0, sizeof(*decoder));  //  Window starts with zeros, like the encoder
+    decoder->flash_id = flash_id;
+    decoder->offset   = offset;
+    decoder->end      = offset + length;
+}
+
+/// Decompress up to `len` bytes into `out`. Returns the number of bytes decompressed, which is less than `len`
+/// only at the end of the logo, or PINETIME_LOGO_EREAD if the flash read failed.
+int pinetime_logo_decoder_read(struct pinetime_logo_decoder *decoder, uint8_t *out, uint16_t len) {
+    uint16_t produced = 0;
+    while (produced < len) {
+        uint8_t c;
+        if (decoder->backref_count > 0) {
+            //  Copy the next byte of the back-reference from the window.
+            c = decoder->window[(uint16_t) (decoder->head - decoder->backref_index) & WINDOW_MASK];
+            decoder->backref_count--;
+        } else {
+            //  Decode the next tag.
+            uint16_t tag, bits;
+            int rc = get_bits(decoder, 1, &tag);
+            if (rc == DECODER_END) { break; }
+            if (rc != 0) { return rc; }
+            if (tag) {
+                //  Literal byte.
+                rc = get_bits(decoder, 8, &bits);
+                if (rc == DECODER_END) { break; }
+                if (rc != 0) { return rc; }
+                c = bits;
+            } else {
+                //  Back-reference: distance then count. Trailing zero bits at the end are padding.
+                uint16_t index, count;
+                rc = get_bits(decoder, PINETIME_LOGO_WINDOW_BITS, &index);
+                if (rc == DECODER_END) { break; }
+                if (rc != 0) { return rc; }
+                rc = get_bits(decoder, PINETIME_LOGO_LOOKAHEAD_BITS, &count);
+                if (rc == DECODER_END) { break; }
+                if (rc != 0) { return rc; }
+                decoder->backref_index = index + 1;
+                decoder->backref_count = count + 1;
+                continue;
+            }
+        }
+        //  Emit the byte and remember it for later back-references.
+        out[produced++] = c;
+        decoder->window[decoder->head & WINDOW_MASK] = c;
+        decoder->head++;
+    }
+    return produced;
+}
+
+/// Read `count` bits (up to 16), MSB first, into `bits`. Returns 0 if successful, DECODER_END if there are
+/// no more compressed bytes, or PINETIME_LOGO_EREAD if the flash read failed.
+static int get_bits(struct pinetime_logo_decoder *decoder, uint8_t count, uint16_t *bits) {
+    uint16_t value = 0;
+    for (uint8_t i = 0; i < count; i++) {
+        if (decoder->bit_mask == 0) {
+            int b = next_byte(decoder);
+            if (b < 0) { return b; }
+            decoder->current_byte = b;
+            decoder->bit_mask = 0x80;
+        }
+        value <<= 1;
+        if (decoder->current_byte & decoder->bit_mask) { value |= 1; }
+        decoder->bit_mask >>= 1;
+    }
+    *bits = value;
+    return 0;
+}
+
+/// Return the next compressed byte, reading a batch from flash if needed. Returns DECODER_END if there
+/// are no more compressed bytes, or PINETIME_LOGO_EREAD if the flash read failed.
+static int next_byte(struct pinetime_logo_decoder *decoder) {
+    if (decoder->input_pos >= decoder->input_len) {
+        if (decoder->offset >= decoder->end) { return DECODER_END; }
+        uint32_t len = decoder->end - decoder->offset;
+        if (len > PINETIME_LOGO_DECODER_INPUT_SIZE) { len = PINETIME_LOGO_DECODER_INPUT_SIZE; }
+        int rc = pinetime_logo_read(decoder->flash_id, decoder->offset, decoder->input, len);
+        if (rc != 0) { return PINETIME_LOGO_EREAD; }
+        decoder->offset   += len;
+        decoder->input_len = len;
+        decoder->input_pos = 0;
+    }
+    return decoder->input[decoder->input_pos++];
+}
@@ -0,0 +1,108 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+//  Logo header and validator shared by the bootloader and the application
+#include <inttypes.h>
+#include <hal/hal_flash.h>
+#include "pinetime_logo/pinetime_logo.h"
+
+#define READ_SIZE 256  //  Number of bytes to be read from flash in a batch when computing the CRC32
+
+/// Buffer for reading the logo from flash
+static uint8_t read_buffer[READ_SIZE];
+
+/// Table of relocated sectors, set by pinetime_logo_set_relocations()
+static const struct pinetime_logo_relocation *relocation_table;
+static int relocation_count;
+
+/// Validate the logo in flash device `flash_id` at `logo_offset`, with the header at `header_offset`.
+/// Checks the header fields and the CRC32 of the logo. Returns PINETIME_LOGO_OK if the logo is valid.
+int pinetime_logo_validate(uint8_t flash_id, uint32_t logo_offset, uint32_t header_offset) {
+    //  Read and check the header.
+    struct pinetime_logo_header header;
+    int rc = pinetime_logo_read(flash_id, header_offset, &header, sizeof(header));
+    if (rc != 0) { return PINETIME_LOGO_EREAD; }
+    if (header.magic != PINETIME_LOGO_HEADER_MAGIC) { return PINETIME_LOGO_EMAGIC; }
+    if (header.version != PINETIME_LOGO_HEADER_VERSION ||
+        header.width   != PINETIME_LOGO_WIDTH ||
+        header.height  != PINETIME_LOGO_HEIGHT ||
+        header.length  == 0 ||
+        logo_offset + header.length > header_offset) { return PINETIME_LOGO_EFORMAT; }
+    //  Raw logos must contain every pixel. Compressed logos are checked for length when decompressed.
+    if (header.format == PINETIME_LOGO_FORMAT_RGB565) {
+        if (header.length != (uint32_t) header.width * header.height * 2) { return PINETIME_LOGO_EFORMAT; }
+    } else if (header.format != PINETIME_LOGO_FORMAT_HEATSHRINK) { return PINETIME_LOGO_EFORMAT; }
+
+    //  Read the logo in chunks and compute the CRC32.
+    uint32_t crc = 0xffffffff;
+    for (uint32_t offset = 0; offset < header.length; offset += READ_SIZE) {
+        uint32_t len = header.length - offset;
+        if (len > READ_SIZE) { len = READ_SIZE; }
+        rc = pinetime_logo_read(flash_id, logo_offset + offset, read_buffer, len);
+        if (rc != 0) { return PINETIME_LOGO_EREAD; }
+        crc = pinetime_logo_crc32(crc, read_buffer, len);
+    }
+    if ((crc ^ 0xffffffff) != header.checksum) { return PINETIME_LOGO_ECHECKSUM; }
+    return PINETIME_LOGO_OK;
+}
+
+/// Set the table of `count` relocated sectors that will be used by pinetime_logo_read(), the validator and the decoder.
+/// The table must remain valid until the next call. Pass NULL if there are no relocated sectors.
+void pinetime_logo_set_relocations(const struct pinetime_logo_relocation *relocations, int count) {
+    relocation_table = relocations;
+    relocation_count = (relocations == NULL) ? 0 : count;
+}
+
+/// Read `len` bytes from flash device `flash_id` at `offset` into `dst`, reading relocated sectors from their
+/// spare sectors. Returns 0 if successful, or PINETIME_LOGO_EREAD if the flash read failed.
+int pinetime_logo_read(uint8_t flash_id, uint32_t offset, void *dst, uint32_t len) {
+    uint8_t *buf = dst;
+    while (len > 0) {
+        //  Read up to the end of the sector.
+        uint32_t sector = offset & ~(PINETIME_LOGO_SECTOR_SIZE - 1);
+        uint32_t size = sector + PINETIME_LOGO_SECTOR_SIZE - offset;
+        if (size > len) { size = len; }
+
+        //  If the sector has been relocated, read from the spare sector.
+        uint32_t physical = offset;
+        for (int i = 0; i < relocation_count; i++) {
+            if (relocation_table[i].bad_offset == sector) {
+                physical = relocation_table[i].spare_offset + (offset - sector);
+                break;
+            }
+        }
+        if (hal_flash_read(flash_id, physical, buf, size) != 0) { return PINETIME_LOGO_EREAD; }
+        offset += size;
+        buf    += size;
+        len    -= size;
+    }
+    return 0;
+}
+
+/// Update the running CRC32 (IEEE 802.3, reflected polynomial 0xEDB88320) with `len` bytes in `data`.
+/// Start with 0xffffffff and XOR the final result with 0xffffffff.
+uint32_t pinetime_logo_crc32(uint32_t crc, const uint8_t *data, uint32_t len) {
+    for (uint32_t i = 0; i < len; i++) {
+        crc ^= data[i];
+        for (int bit = 0; bit < 8; bit++) {
+            uint32_t mask = -(crc & 1);  //  All 1s if lowest bit is set, else all 0s
+            crc = (crc >> 1) ^ (0xedb88320 & mask);
+        }
+    }
+    return crc;
+}
@@ -53,6 +53,10 @@ void console_printdouble(double f);  //  Write a double to the output buffer, wi
 void console_dump(const uint8_t *buffer, unsigned int len);  //  Append "length" number of bytes from "buffer" to the output buffer in hex format.
 void console_flush(void);  //  Flush the output buffer to the console.
 
+typedef void (*console_output_cb)(const char *buffer, unsigned int length);
+void console_set_output_cb(console_output_cb cb);  //  Send the console output to the callback instead of Semihosting, e.g. the UART. NULL to stop.
+void semihosting_console_write(const char *buffer, unsigned int length);  //  Append the bytes to the Semihosting output buffer.
+
 void console_deinit(void);
 void console_reinit(void);
 int console_init(console_rx_cb rx_cb);
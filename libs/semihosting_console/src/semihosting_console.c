@@ -33,6 +33,7 @@ static struct hal_timer semihosting_timer;
 #define OUTPUT_BUFFER_SIZE 2048  //  Use a larger buffer size so that we don't affect interrupt processing.
 static bool log_enabled = true;     //  Logging is on by default.
 static bool buffer_enabled = true;  //  Buffering is on by default.
+static console_output_cb output_cb = NULL;  //  Receives the console output instead of Semihosting, if set.
 
 void enable_log(void)  { log_enabled = true; }
 void disable_log(void) { log_enabled = false; }
@@ -134,8 +135,20 @@ void console_flush(void) {
     if (old) { os_mbuf_free_chain(old); }  //  Deallocate the old chain.
 }
 
+void console_set_output_cb(console_output_cb cb) {
+    //  Send the console output to the callback instead of Semihosting, e.g. the console sink selected in
+    //  rust/mynewt/src/sys/console_sink.rs. NULL to send to Semihosting again.
+    output_cb = cb;
+}
+
 void console_buffer(const char *buffer, unsigned int length) {
     //  Append "length" number of bytes from "buffer" to the output buffer.
+    if (output_cb) { output_cb(buffer, length); return; }  //  Send the output to the callback, e.g. the UART.
+    semihosting_console_write(buffer, length);
+}
+
+void semihosting_console_write(const char *buffer, unsigned int length) {
+    //  Append "length" number of bytes from "buffer" to the Semihosting output buffer, flushed by console_flush().
 #ifdef DISABLE_SEMIHOSTING  //  If Arm Semihosting is disabled...
     return;                 //  Don't write debug messages.
 #else                       //  If Arm Semihosting is enabled...
@@ -42,6 +42,17 @@ bool prepare_sensor_post(struct oc_server_handle *server, const char *uri, int c
 //  Send the sensor post request to CoAP server.
 bool do_sensor_post(void);
 
+//  Handler for the items in the payload of a CoAP response, e.g. name `poll_ms` and value `10000` for the CBOR
+//  payload `{"poll_ms": 10000}`. Integers and booleans are converted to text, booleans as `1` or `0`.
+typedef void (*sensor_coap_response_func)(const char *name, const char *value);
+
+//  Set the handler for the payload of the responses to the requests that expect a response. NULL to ignore responses.
+void sensor_coap_set_response_handler(sensor_coap_response_func func);
+
+//  Keep the request being composed until the server responds or COAP_RESPONSE_TIMEOUT elapses, so that the
+//  response is passed to the response handler. Call after preparing the request, before sending it.
+void sensor_coap_expect_response(void);
+
 ///////////////////////////////////////////////////////////////////////////////
 //  JSON Common Encoding Macros
 
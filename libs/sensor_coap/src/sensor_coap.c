@@ -18,11 +18,13 @@
 //  is implemented by esp8266/transport.h and nrf24l01/transport.h.  This is a simpler version of oc_client_api 
 //  that supports sensors and JSON.  Original version: repos\apache-mynewt-core\net\oic\src\api\oc_client_api.c
 
+#include <stdio.h>
 #include <os/mynewt.h>
 #include <oic/port/mynewt/config.h>
 #include <oic/messaging/coap/coap.h>
 #include <oic/oc_buffer.h>
 #include <oic/oc_client_state.h>
+#include <oic/oc_rep.h>
 #include <console/console.h>
 #include "sensor_coap/sensor_coap.h"
 #if MYNEWT_VAL(COAP_CBOR_ENCODING) && MYNEWT_VAL(COAP_JSON_ENCODING)  //  For coexistence of CBOR and JSON encoding...
@@ -45,6 +47,10 @@ static struct os_sem oc_sem;
 static bool oc_sensor_coap_ready = false;  
 ///  CoAP Payload encoding format: APPLICATION_JSON or APPLICATION_CBOR. If 0, let Sensor Network decide.
 int oc_content_format = 0;            
+///  True if the request being composed expects a response from the server.
+static bool oc_c_expect_response = false;
+///  Handler for the payload of the responses, set by sensor_coap_set_response_handler().
+static sensor_coap_response_func oc_c_response_handler = NULL;
 
 ///////////////////////////////////////////////////////////////////////////////
 //  CoAP Functions
@@ -61,9 +67,38 @@ bool sensor_coap_ready(void) {
     return oc_sensor_coap_ready;
 }
 
-///  Handle CoAP response.
+///  Handle CoAP response. Pass the integer, boolean and string items in the CBOR payload to the response handler.
 static void handle_coap_response(oc_client_response_t *data) {
     console_printf("handle_coap\n");
+    if (!oc_c_response_handler) { return; }
+    char value[32];
+    for (oc_rep_t *rep = data->payload; rep; rep = rep->next) {
+        switch (rep->type) {
+            case INT:
+                if (rep->value_int < 0) { snprintf(value, sizeof(value), "%ld", (long) rep->value_int); }
+                else { snprintf(value, sizeof(value), "%lu", (unsigned long) rep->value_int); }
+                oc_c_response_handler(oc_string(rep->name), value);
+                break;
+            case BOOL:
+                oc_c_response_handler(oc_string(rep->name), rep->value_boolean ? "1" : "0");
+                break;
+            case STRING:
+                oc_c_response_handler(oc_string(rep->name), oc_string(rep->value_string));
+                break;
+            default:
+                break;  //  Other types are not handled.
+        }
+    }
+}
+
+///  Set the handler for the payload of the responses to the requests that expect a response.
+void sensor_coap_set_response_handler(sensor_coap_response_func func) {
+    oc_c_response_handler = func;
+}
+
+///  Keep the request being composed until the server responds, so that the response is passed to the handler.
+void sensor_coap_expect_response(void) {
+    oc_c_expect_response = true;
 }
 
 //  Serialise the CoAP request and payload into the final mbuf format for transmitting.
@@ -98,13 +133,15 @@ dispatch_coap_request(void)
             os_mbuf_free_chain(oc_c_message);
         }
 
-        //  Deallocate the client callback for the message ID.  We won't be processing the response from server.  
+        //  Deallocate the client callback for the message ID, unless we are processing the response from server.
+        //  Otherwise the callback is deallocated after the response, or after OC_CLIENT_CB_TIMEOUT_SECS.
         //  TODO: Handle errors from server.
-        oc_ri_remove_client_cb_by_mid(oc_c_request->mid);
+        if (!oc_c_expect_response) { oc_ri_remove_client_cb_by_mid(oc_c_request->mid); }
 
         oc_c_message = NULL;
         ret = true;
     }
+    oc_c_expect_response = false;
     os_error_t rc = os_sem_release(&oc_sem);  //  Request completed.  Release the semaphore for another request.
     assert(rc == OS_OK);
     return ret;
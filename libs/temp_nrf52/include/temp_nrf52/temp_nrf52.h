@@ -0,0 +1,110 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+
+//  Driver for the nRF52 internal die temperature sensor (TEMP peripheral).
+//  This sensor is selected if TEMP_NRF52=1 in syscfg.yml.
+
+//  Temperature sensor values may be Computed or Raw:
+//  Computed Temperature Sensor Value (default): Sensor values are in degrees Celsius, in steps of 0.25 degrees.
+//  Raw Temperature Sensor Value (if RAW_TEMP=1 in syscfg.yml): Sensor values are integers, degrees Celsius times 100.
+
+#ifndef __TEMP_NRF52_H__
+#define __TEMP_NRF52_H__
+
+#include "os/mynewt.h"
+#include "sensor/sensor.h"
+
+//  Define Sensor Type, Sensor Value Type and Sensor Key (Raw and Computed Temperature)
+
+#if MYNEWT_VAL(RAW_TEMP)                                       //  If we are returning raw temperature (integers)...
+#include "custom_sensor/custom_sensor.h"                       //  For SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW
+#define TEMP_SENSOR_TYPE       SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW  //  Set to raw sensor type
+#define TEMP_SENSOR_VALUE_TYPE SENSOR_VALUE_TYPE_INT32         //  Return integer sensor values
+#define TEMP_SENSOR_KEY        "t"                             //  Use key (field name) "t" to transmit raw temperature to CoAP Server or Collector Node
+
+#else                                                          //  If we are returning computed temperature (floating-point)...
+#define TEMP_SENSOR_TYPE       SENSOR_TYPE_AMBIENT_TEMPERATURE //  Set to floating-point sensor type
+#define TEMP_SENSOR_VALUE_TYPE SENSOR_VALUE_TYPE_FLOAT         //  Return floating-point sensor values
+#define TEMP_SENSOR_KEY        "tmp"                           //  Use key (field name) "tmp" to transmit computed temperature to CoAP Server or Collector Node
+#endif  //  MYNEWT_VAL(RAW_TEMP)
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+//  Configuration for the nRF52 internal temperature sensor
+struct temp_nrf52_cfg {
+    sensor_type_t bc_s_mask;   //  Sensor data types that will be returned, i.e. temperature.
+};
+
+//  Device for the nRF52 internal temperature sensor
+struct temp_nrf52 {
+    struct os_dev dev;          //  Mynewt device
+    struct sensor sensor;       //  Mynewt sensor
+    struct temp_nrf52_cfg cfg;  //  Sensor configuration
+};
+
+/**
+ * Create the nRF52 internal temperature sensor instance.  Implemented in creator.c, function DEVICE_CREATE().
+ */
+void temp_nrf52_create(void);
+
+/**
+ * Return the default configuration for the nRF52 internal temperature sensor.
+ *
+ * @param cfg  Pointer to the temp_nrf52_cfg device config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int temp_nrf52_default_cfg(struct temp_nrf52_cfg *cfg);
+
+/**
+ * Initialize the nRF52 internal temperature sensor.
+ *
+ * @param dev  Pointer to the temp_nrf52 device descriptor
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int temp_nrf52_init(struct os_dev *dev, void *arg);
+
+/**
+ * Configure the nRF52 internal temperature sensor
+ *
+ * @param Sensor device temp_nrf52 structure
+ * @param Sensor device temp_nrf52_cfg config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int temp_nrf52_config(struct temp_nrf52 *dev, struct temp_nrf52_cfg *cfg);
+
+/**
+ * Measure the die temperature with the TEMP peripheral. Will block for about 36 microseconds until the measurement is done.
+ *
+ * @param dev The temp_nrf52 device
+ * @param temp Will store the temperature in units of 0.25 degrees Celsius
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int temp_nrf52_get_temperature(struct temp_nrf52 *dev, int32_t *temp);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* __TEMP_NRF52_H__ */
@@ -0,0 +1,193 @@
+/*
+ * Licensed to the Apache Software Foundation (ASF) under one
+ * or more contributor license agreements.  See the NOTICE file
+ * distributed with this work for additional information
+ * regarding copyright ownership.  The ASF licenses this file
+ * to you under the Apache License, Version 2.0 (the
+ * "License"); you may not use this file except in compliance
+ * with the License.  You may obtain a copy of the License at
+ *
+ *  http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing,
+ * software distributed under the License is distributed on an
+ * "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+ * KIND, either express or implied.  See the License for the
+ * specific language governing permissions and limitations
+ * under the License.
+ */
+#include <string.h>
+#include "os/mynewt.h"
+#include "console/console.h"
+#include "sensor/sensor.h"
+#include "sensor/temperature.h"
+#include "nrf.h"
+#include "temp_nrf52/temp_nrf52.h"
+
+//  Exports for the sensor API
+static int temp_nrf52_sensor_read(struct sensor *, sensor_type_t, sensor_data_func_t, void *, uint32_t);
+static int temp_nrf52_sensor_get_config(struct sensor *, sensor_type_t, struct sensor_cfg *);
+
+//  Global instance of the sensor driver
+static const struct sensor_driver g_temp_nrf52_sensor_driver = {
+    temp_nrf52_sensor_read,
+    temp_nrf52_sensor_get_config
+};
+
+//  Max number of polls while waiting for the measurement. The measurement takes 36 microseconds.
+#define MAX_POLLS 10000
+
+int temp_nrf52_default_cfg(struct temp_nrf52_cfg *cfg) {
+    //  Return the default sensor configuration.
+    memset(cfg, 0, sizeof(struct temp_nrf52_cfg));  //  Zero the entire object.
+    cfg->bc_s_mask       = SENSOR_TYPE_ALL;          //  Return all sensor values, i.e. temperature.
+    return 0;
+}
+
+static int temp_nrf52_open(struct os_dev *dev0, uint32_t timeout, void *arg) {
+    //  Nothing to set up, the TEMP peripheral is always powered.  Return 0 if successful.
+    return 0;
+}
+
+static int temp_nrf52_close(struct os_dev *dev0) {
+    //  Close the sensor.  Return 0 if successful.
+    return 0;
+}
+
+/**
+ * Expects to be called back through os_dev_create().
+ *
+ * @param The device object associated with temp_nrf52
+ * @param Argument passed to OS device init, unused
+ *
+ * @return 0 on success, non-zero error on failure.
+ */
+int temp_nrf52_init(struct os_dev *dev0, void *arg) {
+    struct temp_nrf52 *dev;
+    struct sensor *sensor;
+    int rc;
+    if (!arg || !dev0) { rc = SYS_ENODEV; goto err; }
+    dev = (struct temp_nrf52 *) dev0;
+
+    //  Get the default config.
+    rc = temp_nrf52_default_cfg(&dev->cfg);
+    if (rc) { goto err; }
+
+    //  Init the sensor.
+    sensor = &dev->sensor;
+    rc = sensor_init(sensor, dev0);
+    if (rc != 0) { goto err; }
+
+    //  Add the driver with all the supported sensor data types.
+    rc = sensor_set_driver(sensor, TEMP_SENSOR_TYPE,
+        (struct sensor_driver *) &g_temp_nrf52_sensor_driver);
+    if (rc != 0) { goto err; }
+
+    //  Set the interface.
+    rc = sensor_set_interface(sensor, arg);
+    if (rc) { goto err; }
+
+    //  Register with the Sensor Manager.
+    rc = sensor_mgr_register(sensor);
+    if (rc != 0) { goto err; }
+
+    //  Set the handlers for opening and closing the device.
+    OS_DEV_SETHANDLERS(dev0, temp_nrf52_open, temp_nrf52_close);
+    return (0);
+err:
+    return (rc);
+}
+
+static int temp_nrf52_sensor_read(struct sensor *sensor, sensor_type_t type,
+    sensor_data_func_t data_func, void *data_arg, uint32_t timeout) {
+    //  Read the sensor values depending on the sensor types specified in the sensor config.
+    union {  //  Union that represents all possible sensor values.
+#if MYNEWT_VAL(RAW_TEMP)                   //  If we are returning raw temperature (integers)...
+        struct sensor_temp_raw_data strd;  //  For passing raw temperature sensor value
+#else                                      //  If we are returning computed temperature (floating-point)...
+        struct sensor_temp_data std;       //  For passing computed temperature sensor value
+#endif  //  MYNEWT_VAL(RAW_TEMP)
+    } databuf;
+    struct temp_nrf52 *dev;
+    int32_t temp;
+    int rc = 0;
+
+    //  We only allow reading of temperature values.
+    if (!(type & TEMP_SENSOR_TYPE)) { rc = SYS_EINVAL; goto err; }
+    dev = (struct temp_nrf52 *) SENSOR_GET_DEVICE(sensor); assert(dev);
+
+    //  Measure the die temperature, in units of 0.25 degrees Celsius.
+    rc = temp_nrf52_get_temperature(dev, &temp);
+    if (rc) { goto err; }
+
+#if MYNEWT_VAL(RAW_TEMP)  //  If we are returning raw temperature (integers)...    
+    //  Save the raw temperature as degrees Celsius times 100. Temperatures below 0 are returned as 0.
+    struct sensor_temp_raw_data *temp_data = &databuf.strd;
+    temp_data->strd_temp_raw = (temp > 0) ? (uint32_t) temp * 25 : 0;
+    temp_data->strd_temp_raw_is_valid = 1;
+
+#else  //  If we are returning computed temperature (floating-point)...
+    //  Save the floating-point temperature.
+    struct sensor_temp_data *temp_data = &databuf.std;
+    temp_data->std_temp = temp * 0.25f;
+    temp_data->std_temp_is_valid = 1;
+#endif  //  MYNEWT_VAL(RAW_TEMP)
+    
+    if (data_func) {  //  Call the Listener Function to process the sensor data.
+        rc = data_func(sensor, data_arg, temp_data, TEMP_SENSOR_TYPE);
+        if (rc) { goto err; }
+    }
+    return 0;
+err:
+    return rc;
+}
+
+int temp_nrf52_get_temperature(struct temp_nrf52 *dev, int32_t *temp) {
+    int polls;
+    assert(temp);
+
+    //  Start the measurement and wait for the result.
+    NRF_TEMP->EVENTS_DATARDY = 0;
+    NRF_TEMP->TASKS_START = 1;
+    for (polls = 0; polls < MAX_POLLS && NRF_TEMP->EVENTS_DATARDY == 0; polls++) {}
+    NRF_TEMP->TASKS_STOP = 1;  //  Stop the measurement to save power
+    if (NRF_TEMP->EVENTS_DATARDY == 0) { return SYS_ETIMEOUT; }
+    NRF_TEMP->EVENTS_DATARDY = 0;
+
+    //  The result is a signed value in units of 0.25 degrees Celsius.
+    *temp = (int32_t) NRF_TEMP->TEMP;
+    return 0;
+}
+
+static int temp_nrf52_sensor_get_config(struct sensor *sensor, sensor_type_t type,
+    struct sensor_cfg *cfg) {
+    //  Return the type of the sensor value returned by the sensor.
+    int rc;
+    if (!(type & TEMP_SENSOR_TYPE)) {
+        rc = SYS_EINVAL;
+        goto err;
+    }
+    cfg->sc_valtype = TEMP_SENSOR_VALUE_TYPE;  //  We return float (computed values) or int (raw values).
+    return (0);
+err:
+    return (rc);
+}
+
+/**
+ * Configure the nRF52 internal temperature sensor
+ *
+ * @param Sensor device temp_nrf52 structure
+ * @param Sensor device temp_nrf52_cfg config
+ *
+ * @return 0 on success, and non-zero error code on failure
+ */
+int temp_nrf52_config(struct temp_nrf52 *dev, struct temp_nrf52_cfg *cfg) {
+    int rc;
+    rc = sensor_set_type_mask(&(dev->sensor),  cfg->bc_s_mask);
+    if (rc) { goto err; }
+
+    dev->cfg.bc_s_mask = cfg->bc_s_mask;
+    return 0;
+err:
+    return (rc);
+}
@@ -0,0 +1,56 @@
+//!  Build script for the Rust application. Embeds the git commit and the build time into the firmware as the
+//!  environment variables `BUILD_GIT_HASH` and `BUILD_TIME`, which are read by `src/version.rs`.
+//!  The build time is taken from `SOURCE_DATE_EPOCH` if set, for reproducible builds.
+
+use std::{
+    env,
+    process::Command,
+    time::{ SystemTime, UNIX_EPOCH },
+};
+
+fn main() {
+    //  Commit of the source files, e.g. `1b84bea3`, with `-dirty` if the tracked files have been changed.
+    let mut git_hash = git(&["rev-parse", "--short=8", "HEAD"]).unwrap_or_else(|| "unknown".into());
+    if git(&["status", "--porcelain", "--untracked-files=no"]).map_or(false, |status| !status.is_empty()) {
+        git_hash.push_str("-dirty");
+    }
+    println!("cargo:rustc-env=BUILD_GIT_HASH={}", git_hash);
+
+    //  Build time in UTC, e.g. `2020-05-01T12:34:56Z`.
+    let secs = env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.parse().ok())
+        .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0));
+    println!("cargo:rustc-env=BUILD_TIME={}", utc_time(secs));
+
+    //  Build again when the commit, the tracked files or the sources change.
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/index", git_dir);
+    }
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
+
+///  Run git with the arguments and return the output without the trailing newline, or `None` if git failed
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() { return None; }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+///  Format the seconds since 1970 as a UTC time, e.g. `2020-05-01T12:34:56Z`
+fn utc_time(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    //  Convert the days since 1970 to the civil date. From http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, rem / 3_600, rem % 3_600 / 60, rem % 60)
+}
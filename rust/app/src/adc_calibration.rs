@@ -0,0 +1,74 @@
+//!  Offset calibration of the nRF52 SAADC, which measures the battery voltage. The offset error of the SAADC drifts
+//!  with the temperature, enough to move the battery gauge by several percent, since the gauge spans only 700 mV.
+//!  So the offset is calibrated at startup, every `CALIBRATION_INTERVAL`, and when the die temperature polled by
+//!  `app_sensor.rs` has changed by `MAX_DRIFT` since the last calibration.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hal::saadc::{ Saadc, SaadcConfig },
+    hw::sensor::units::MilliCelsius,
+    kernel::timer::Callout,
+};
+
+///  Interval between calibrations when the temperature is stable
+const CALIBRATION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+///  Change of temperature since the last calibration that triggers a calibration. Recommended by the nRF52832
+///  Product Specification, section 37.6.
+const MAX_DRIFT: MilliCelsius = MilliCelsius(10_000);
+
+///  Max time to wait for the battery sensor to release the SAADC
+const OPEN_TIMEOUT: Duration = Duration::from_secs(1);
+
+///  Timer that calibrates the SAADC in the default event queue
+static CALIBRATION_TIMER: Callout<fn()> = Callout::new(calibrate);
+
+///  Latest die temperature, set by `update_temperature()`
+static mut TEMPERATURE: Option<MilliCelsius> = None;
+
+///  True if the SAADC has been calibrated
+static mut CALIBRATED: bool = false;
+
+///  Die temperature at the last calibration, `None` if not calibrated or the temperature was not known yet
+static mut CALIBRATED_AT: Option<MilliCelsius> = None;
+
+///  Calibrate the SAADC now and every `CALIBRATION_INTERVAL`. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    CALIBRATION_TIMER.reset(Duration::from_secs(0))
+}
+
+///  Record the die temperature, and calibrate the SAADC if it has drifted by `MAX_DRIFT` since the last
+///  calibration. Called by the sensor listener in `app_sensor.rs` when the temperature is polled.
+pub fn update_temperature(temp: MilliCelsius) -> MynewtResult<()> {
+    unsafe { TEMPERATURE = Some(temp) };
+    let drifted = match unsafe { CALIBRATED_AT } {
+        Some(MilliCelsius(at)) => (temp.0 - at).abs() >= MAX_DRIFT.0,
+        None => {
+            //  Calibrated before the first temperature reading: Assume the temperature hasn't changed since.
+            if unsafe { CALIBRATED } { unsafe { CALIBRATED_AT = Some(temp) }; }
+            false
+        }
+    };
+    if !drifted { return Ok(()); }
+    //  Calibrate in the default event queue, not in the sensor task.
+    CALIBRATION_TIMER.reset(Duration::from_secs(0))
+}
+
+///  Calibrate the offset of the SAADC and schedule the next calibration
+fn calibrate() {
+    match calibrate_offset() {
+        Ok(()) => unsafe {
+            CALIBRATED = true;
+            CALIBRATED_AT = TEMPERATURE;
+        },
+        Err(err) => log::warn!("SAADC calibration fail {:?}", err),  //  Try again at the next interval
+    }
+    CALIBRATION_TIMER.reset(CALIBRATION_INTERVAL).expect("SAADC timer fail");
+}
+
+///  Open the SAADC, waiting for the battery sensor, and calibrate its offset
+fn calibrate_offset() -> MynewtResult<()> {
+    let mut adc = Saadc::open(SaadcConfig::new(), OPEN_TIMEOUT) ? ;
+    adc.calibrate_offset()
+}
@@ -0,0 +1,140 @@
+//!  Daily alarms: at each alarm time, the display is switched on and the vibration motor plays the pattern
+//!  `settings::HAPTIC_ALARM` a few times. The alarm times are saved in `settings::ALARMS`, e.g. `07:30,22:00`, and
+//!  scheduled again at startup with `schedule::at()`. The alarms ring once the wall clock has been set by the phone.
+//!  The alarms may be changed with the shell command `alarm add 07:30` or over newtmgr with
+//!  `newtmgr config app/alarms 07:30,22:00` then `newtmgr config save`, and take effect at once.
+
+use core::{ fmt::Write, time::Duration };
+use mynewt::{
+    result::*,
+    kernel::{
+        schedule::{ self, JobId, WallClockTime },
+        timer::Callout,
+    },
+    sys::{ config::{ self, ConfigString }, console, shell::{ self, Args } },
+};
+use crate::{ haptics::{ self, HapticEvent }, power::{ self, WakeReason }, settings };
+
+///  Max number of alarms
+const MAX_ALARMS: usize = 4;
+type MaxAlarms = heapless::consts::U4;
+
+///  Number of times the pattern is played when an alarm rings
+const RING_COUNT: u8 = 5;
+
+///  Interval between the patterns of a ringing alarm
+const RING_INTERVAL: Duration = Duration::from_secs(2);
+
+///  Alarms that are scheduled, with their times
+static mut SCHEDULED: heapless::Vec<(WallClockTime, JobId), MaxAlarms> = heapless::Vec(heapless::i::Vec::new());
+
+///  Number of patterns left to play for the ringing alarm
+static mut RINGS_LEFT: u8 = 0;
+
+///  Timer that plays the next pattern of the ringing alarm
+static RING_TIMER: Callout<fn()> = Callout::new(ring_timer);
+
+///  Timer that schedules the alarms again after the setting has changed, on the default event queue
+static RELOAD_TIMER: Callout<fn()> = Callout::new(reload_timer);
+
+///  Schedule the alarms in the settings and register the shell command `alarm`. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    reload() ? ;
+    config::on_change(setting_changed) ? ;
+    shell::register("alarm", "alarm [add hh:mm | del hh:mm]", shell_command)
+}
+
+///  Show the alarms and the scheduled jobs on the console
+pub fn show() {
+    let alarms = settings::ALARMS.get();
+    console::print("alarms ");
+    console::print(if alarms.is_empty() { "none" } else { alarms.as_str() });
+    console::print("\n");
+    console::flush();
+    schedule::show();
+}
+
+///  Cancel the scheduled alarms and schedule the alarms in the settings. Invalid times are logged and skipped.
+fn reload() -> MynewtResult<()> {
+    let scheduled = unsafe { &mut SCHEDULED };
+    for (_, id) in scheduled.iter() { schedule::cancel(*id).ok(); }  //  Ignore the alarms that are gone
+    scheduled.clear();
+    for time in parse(&settings::ALARMS.get()) {
+        let time = match time {
+            Ok(time) => time,
+            Err(text) => { log::warn!("alarm {} invalid", text); continue; }
+        };
+        if scheduled.len() >= MAX_ALARMS { log::warn!("alarm {} skipped, too many", time); break; }
+        let id = schedule::at("alarm", time, ring) ? ;
+        scheduled.push((time, id)).ok();  //  Checked above
+    }
+    Ok(())
+}
+
+///  Parse the alarm times separated by commas, e.g. `07:30,22:00`. Returns the invalid texts as errors.
+fn parse<'a>(text: &'a str) -> impl Iterator<Item = Result<WallClockTime, &'a str>> + 'a {
+    text.split(',')
+        .map(str::trim)
+        .filter(|time| !time.is_empty())
+        .map(|time| WallClockTime::parse(time).map_err(|_| time))
+}
+
+///  Ring the alarm: switch on the display and play the pattern `RING_COUNT` times. Called by the scheduler.
+fn ring() {
+    log::info!("alarm ringing");
+    if let Err(err) = power::wake(WakeReason::Alarm) { log::warn!("alarm wake fail {:?}", err); }
+    unsafe { RINGS_LEFT = RING_COUNT };
+    ring_timer();
+}
+
+///  Play the pattern, then play it again after the interval until `RINGS_LEFT` patterns have been played. Called
+///  by the default event queue.
+fn ring_timer() {
+    let left = unsafe { RINGS_LEFT };
+    if left == 0 { return; }
+    unsafe { RINGS_LEFT = left - 1 };
+    haptics::play(HapticEvent::Alarm);
+    if left > 1 { RING_TIMER.reset(RING_INTERVAL).expect("alarm timer fail"); }
+}
+
+///  Schedule the alarms again when the setting changes, from the shell, newtmgr or the CoAP server. Called by the
+///  task that changed the setting, so the alarms are scheduled on the default event queue.
+fn setting_changed(name: &str) {
+    if name != "alarms" { return; }  //  Name of `settings::ALARMS`
+    if let Err(err) = RELOAD_TIMER.reset(Duration::from_millis(0)) { log::warn!("alarm reload fail {:?}", err); }
+}
+
+///  Schedule the alarms in the settings. Called by the default event queue.
+fn reload_timer() {
+    if let Err(err) = reload() { log::warn!("alarm reload fail {:?}", err); }
+}
+
+///  Shell command `alarm [add hh:mm | del hh:mm]`: Show the alarms and the scheduled jobs, or add or delete an
+///  alarm and save the alarms to flash
+fn shell_command(args: &mut Args) -> MynewtResult<()> {
+    let add = args.take("add");
+    if !add && !args.take("del") {
+        args.end() ? ;
+        show();
+        return Ok(());
+    }
+    let time = WallClockTime::parse(args.str() ? ) ? ;
+    args.end() ? ;
+
+    //  Rebuild the list of alarm times without `time`, then append `time` if adding.
+    let mut alarms = ConfigString::new();
+    let mut count = 0;
+    for other in parse(&settings::ALARMS.get()).flatten().filter(|other| *other != time) {
+        if count > 0 { alarms.push(',').map_err(|_| MynewtError::SYS_ENOMEM) ? ; }
+        write!(alarms, "{}", other).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+        count += 1;
+    }
+    if add {
+        if count >= MAX_ALARMS { return Err(MynewtError::SYS_ENOMEM); }
+        if count > 0 { alarms.push(',').map_err(|_| MynewtError::SYS_ENOMEM) ? ; }
+        write!(alarms, "{}", time).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    }
+    settings::ALARMS.set(alarms) ? ;  //  Scheduled again by `setting_changed()`
+    show();
+    Ok(())
+}
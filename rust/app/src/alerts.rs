@@ -0,0 +1,63 @@
+//!  Sensor alerts: each reading is checked against the alert thresholds in the settings, e.g. heart rate above 150.
+//!  When a threshold is crossed, the alert is sent to the CoAP server at once, without waiting for the next report,
+//!  and the display is switched on so that the UI can show the alert from `ALERT_EVENTS`, with a haptic pattern.
+//!  The thresholds may be changed over newtmgr, e.g. `newtmgr config app/hr_high 160` then `newtmgr config save`.
+
+use mynewt::{
+    result::*,
+    hw::sensor::{ Alert, AlertEvent, SensorValueType, Threshold },
+    kernel::event::EventQueue,
+    sys::console,
+    Strn,
+};
+use crate::{ app_network, app_sensor, data_log, haptics::{ self, HapticEvent }, power, settings };
+
+///  Alerts for the UI. Call `ALERT_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static ALERT_EVENTS: EventQueue<AlertEvent> = EventQueue::new();
+
+///  Heart rate above the limit, in beats per minute
+static HR_HIGH_ALERT: Alert = Alert::new(&app_sensor::HR_SENSOR_KEY, Threshold::Disabled, 5);
+///  Heart rate below the limit, in beats per minute
+static HR_LOW_ALERT: Alert = Alert::new(&app_sensor::HR_SENSOR_KEY, Threshold::Disabled, 5);
+///  Battery voltage below the limit, in millivolts
+static BATTERY_LOW_ALERT: Alert = Alert::new(&app_sensor::BATTERY_SENSOR_KEY, Threshold::Disabled, 50);
+///  Temperature change, in degrees Celsius times 100
+static TEMP_DELTA_ALERT: Alert = Alert::new(&app_sensor::TEMP_SENSOR_KEY, Threshold::Disabled, 0);
+
+///  All alerts, checked at every reading
+static ALERTS: [&Alert; 4] = [&HR_HIGH_ALERT, &HR_LOW_ALERT, &BATTERY_LOW_ALERT, &TEMP_DELTA_ALERT];
+
+///  Set the alert thresholds from the settings. A setting of 0 disables the alert.
+pub fn start_alerts() -> MynewtResult<()> {
+    console::print("Rust alerts\n");
+    HR_HIGH_ALERT.set_threshold(threshold(settings::HR_HIGH_ALERT.get(), |limit| Threshold::Above(limit as i32)));
+    HR_LOW_ALERT.set_threshold(threshold(settings::HR_LOW_ALERT.get(), |limit| Threshold::Below(limit as i32)));
+    BATTERY_LOW_ALERT.set_threshold(threshold(settings::BATTERY_LOW_ALERT.get(), |limit| Threshold::Below(limit as i32)));
+    TEMP_DELTA_ALERT.set_threshold(threshold(settings::TEMP_DELTA_ALERT.get(), Threshold::Delta));
+    Ok(())
+}
+
+///  Check the reading `value` of the sensor with key `key` against the alerts for the sensor. Send the alerts that
+///  have fired to the CoAP server and the UI.
+pub fn check(key: &'static Strn, value: &SensorValueType) {
+    for alert in ALERTS.iter().filter(|alert| core::ptr::eq(alert.key(), key)) {
+        if let Some(event) = alert.check(value) {
+            handle_alert(&event);
+        }
+    }
+}
+
+///  Send the alert to the CoAP server, switch on the display, vibrate and notify the UI
+fn handle_alert(event: &AlertEvent) {
+    log::warn!("alert {:?} value {}", event.threshold, event.value);
+    data_log::record_alert(event);
+    if let Err(err) = app_network::send_alert(event) { log::warn!("alert send fail {:?}", err); }
+    if let Err(err) = power::wake(power::WakeReason::Alert) { log::warn!("alert wake fail {:?}", err); }
+    haptics::play(HapticEvent::Alert);
+    ALERT_EVENTS.post(*event).ok();  //  Drop the event if the UI is not receiving events
+}
+
+///  Return the threshold for the setting `limit`, or `Disabled` if the setting is 0
+fn threshold<F: FnOnce(u32) -> Threshold>(limit: u32, make: F) -> Threshold {
+    if limit == 0 { Threshold::Disabled } else { make(limit) }
+}
@@ -22,19 +22,37 @@
 //!  fixes response parsing bugs.  The patched file must be present in that location.
 //!  This is the Rust version of `https://github.com/lupyuen/stm32bluepill-mynewt-sensor/blob/rust-nbiot/apps/my_sensor_app/OLDsrc/network.c`
 
+use core::fmt::Write;
+use core::sync::atomic::{ AtomicBool, Ordering };
 use mynewt::{
     result::*,                  //  Import Mynewt result and error types
     hw::sensor::{               //  Import Mynewt Sensor API
-        SensorValue, SensorValueType,
+        AlertEvent, History, SensorValue, SensorValueType, Threshold,
     },
-    sys::console,               //  Import Mynewt Console API
+    kernel::time::{ self, Instant },  //  Import Mynewt Time API
+    sys::{
+        console,                //  Import Mynewt Console API
+        config,                 //  Import Mynewt Config API
+        crash_dump::{ self, CrashDump },  //  Import Mynewt Crash Dump API
+        power_profile,          //  Import Mynewt Power Profiling API
+    },
+    diag::memory,               //  Import Mynewt Memory Diagnostics API
     encoding::coap_context::*,  //  Import Mynewt Encoding API
     libs::{
         sensor_network,         //  Import Mynewt Sensor Network API
     },
-    coap, d, Strn,              //  Import Mynewt macros
+    coap_array, coap_item, coap_item_str, coap_root,
+    json_rep_set_int, json_rep_set_text_string,
+    d, Strn,                    //  Import Mynewt macros
 };
 use mynewt_macros::strn;        //  Import Mynewt procedural macros
+use crate::settings;            //  Import `settings.rs` for the CoAP server URI
+use crate::version;             //  Import `version.rs` for the firmware and build information
+use crate::mcuboot::{ self, Slot };  //  Import `mcuboot.rs` for the firmware slots
+use crate::power::low_battery::BatteryEvent;  //  Import `power/low_battery.rs` for the low-battery warnings
+use crate::power::history::BatterySample;     //  Import `power/history.rs` for the battery history
+#[cfg(feature = "use_float")]   //  If floating-point is enabled...
+use mynewt::kernel::sync::Mutex;  //  Import Mynewt Mutex API
 
 ///  Aggregate the sensor value with other sensor data before transmitting to server.
 ///  If the sensor value is a GPS geolocation, we remember it and attach it to other sensor data for transmission.
@@ -42,12 +60,12 @@ use mynewt_macros::strn;        //  Import Mynewt procedural macros
 pub fn aggregate_sensor_data(sensor_value: &SensorValue) -> MynewtResult<()>  {  //  Returns an error code upon error.
     if let SensorValueType::Geolocation {..} = sensor_value.value {
         //  If this is a geolocation, save the geolocation for later transmission.
-        unsafe { CURRENT_GEOLOCATION = sensor_value.value };  //  Current geolocation is unsafe because it's a mutable static
+        *CURRENT_GEOLOCATION.lock() ? = sensor_value.value;  //  Lock the current geolocation, shared with other sensors
         Ok(())
     } else {
         //  If this is temperature sensor data, attach the current geolocation to the sensor data for transmission.
         let transmit_value = SensorValue {
-            geo: unsafe { CURRENT_GEOLOCATION },  //  Current geolocation is unsafe because it's a mutable static
+            geo: *CURRENT_GEOLOCATION.lock() ? ,  //  Lock the current geolocation, shared with other sensors
             ..*sensor_value                       //  Copy the sensor name and value for transmission
         };
         //  Transmit sensor value with geolocation and return the result
@@ -64,15 +82,18 @@ pub fn aggregate_sensor_data(sensor_value: &SensorValue) -> MynewtResult<()>  {
 /// Compose a CoAP JSON message with the Sensor Key (field name), Value and Geolocation (optional) in `val`
 /// and send to the CoAP server.  The message will be enqueued for transmission by the CoAP / OIC 
 /// Background Task so this function will return without waiting for the message to be transmitted.
-/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet.
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
 /// For the CoAP server hosted at thethings.io, the CoAP payload shall be encoded in JSON like this:
 /// ```json
 /// {"values":[
-///   {"key":"t",      "value":1715, "geo": { "lat": ..., "long": ... }},
+///   {"key":"t",      "value":1715, "ts":1571234567, "geo": { "lat": ..., "long": ... }},
 ///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
 /// ]}
 /// ```
+/// `ts` is the Unix time of the reading, sent only when the wall clock has been set.
 fn send_sensor_data(val: &SensorValue) -> MynewtResult<()>  {  //  Returns an error code upon error.
+    //  If the posts are paused while a phone is syncing, tell caller (Sensor Listener) to try again later.
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
     console::print("Rust send_sensor_data: ");
     if let SensorValueType::Uint(i) = val.value {
         console::print_strn(val.key);
@@ -88,23 +109,30 @@ fn send_sensor_data(val: &SensorValue) -> MynewtResult<()>  {  //  Returns an er
     //  Start composing the CoAP Server message with the sensor data in the payload.  This will 
     //  block other tasks from composing and posting CoAP messages (through a semaphore).
     //  We only have 1 memory buffer for composing CoAP messages so it needs to be locked.
-    let rc = sensor_network::init_server_post( strn!(()) ) ? ;  //  `strn!(())` means use default CoAP URI in `syscfg.yml`
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;  //  Empty URI means use default CoAP URI in `syscfg.yml`
 
     //  If network transport not ready, tell caller (Sensor Listener) to try again later.
     if !rc { return Err(MynewtError::SYS_EAGAIN); }
 
-    //  Compose the CoAP Payload using the coap!() macro.
-    //  Select @json or @cbor To encode CoAP Payload in JSON or CBOR format.
-    let _payload = coap!( @json {        
+    //  Compose the CoAP Payload with the coap_root!() and coap_array!() macros used by coap!(), since the timestamp
+    //  is only sent when the wall clock has been set, e.g. by a phone over Bluetooth LE.
+    let ts = timestamp(0);
+    coap_root!(@json COAP_CONTEXT {
         //  Create `values` as an array of items under the root.
-        //  Assume `val` contains `key: "t", val: 2870, geo: { lat, long }`. 
-        //  Append to the `values` array the Sensor Key, Value and optional Geolocation:
-        //  `{"key": "t", "value": 2870, "geo": { "lat": ..., "long": ... }}`
-        val,
-
-        //  Append to the `values` array the random device ID:
-        //  `{"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}`
-        "device": &device_id,
+        coap_array!(@json COAP_CONTEXT, values, {
+            //  Append to the `values` array the Sensor Key, Value, optional Timestamp and optional Geolocation:
+            //  `{"key": "t", "value": 2870, "ts": 1571234567, "geo": { "lat": ..., "long": ... }}`
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", val.key);
+                unsafe { COAP_CONTEXT.json_set_value(b"value", val.value) };
+                if let Some(ts) = ts { json_rep_set_int!(COAP_CONTEXT, "ts", ts); }
+                unsafe { COAP_CONTEXT.json_set_geolocation(strn!("geo"), strn!("lat"), strn!("long"), val.geo) };
+            });
+
+            //  Append to the `values` array the random device ID:
+            //  `{"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}`
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
     });
 
     //  Post the CoAP Server message to the CoAP Background Task for transmission.  After posting the
@@ -120,6 +148,452 @@ fn send_sensor_data(val: &SensorValue) -> MynewtResult<()>  {  //  Returns an er
     Ok(())
 }
 
+///  Max number of readings to be transmitted in one batch, to fit in the CoAP message buffer
+const MAX_BATCH_SIZE: usize = 16;
+
+/// Compose a CoAP JSON message with the unsent readings in `histories` and send to the CoAP server, e.g. after the
+/// network was down. Up to `MAX_BATCH_SIZE` readings are sent, oldest first, and marked as sent.
+/// Each reading includes its age in seconds, since the readings were not sent when recorded, and its Unix time `ts`
+/// when the wall clock has been set:
+/// ```json
+/// {"values":[
+///   {"key":"t",      "value":1715, "age":60, "ts":1571234507},
+///   {"key":"t",      "value":1720, "age":30, "ts":1571234537},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_history(histories: &[&'static History]) -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_history\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    //  Number of readings to be sent for each history, counted before composing the message.
+    //  Readings recorded while composing are sent in the next batch.
+    let mut counts: [usize; MAX_BATCH_SIZE] = [0; MAX_BATCH_SIZE];
+    let mut remaining = MAX_BATCH_SIZE;
+    for (i, history) in histories.iter().enumerate().take(MAX_BATCH_SIZE) {
+        counts[i] = core::cmp::min(history.unsent(), remaining);
+        remaining -= counts[i];
+    }
+
+    //  Compose the CoAP Payload with the coap_root!() and coap_array!() macros used by coap!(), since the number of
+    //  readings is not known in advance.
+    let now = Instant::now();
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            for (i, history) in histories.iter().enumerate().take(MAX_BATCH_SIZE) {
+                history.for_each_unsent(counts[i], |sample| {
+                    if sample.value.is_none() { return; }  //  Skip readings without values
+                    let age = now.duration_since(sample.time()).as_secs();
+                    coap_item!(@json COAP_CONTEXT, {
+                        json_rep_set_text_string!(COAP_CONTEXT, "key", history.key());
+                        unsafe { COAP_CONTEXT.json_set_value(b"value", sample.value) };
+                        json_rep_set_int!(COAP_CONTEXT, "age", age);
+                        if let Some(ts) = timestamp(age) { json_rep_set_int!(COAP_CONTEXT, "ts", ts); }
+                    });
+                });
+            }
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+
+    //  The readings have been enqueued for transmission by the CoAP Background Task.
+    for (i, history) in histories.iter().enumerate().take(MAX_BATCH_SIZE) {
+        history.mark_sent(counts[i]);
+    }
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with the alert in `event` and send to the CoAP server at once, without waiting for
+/// the next report or batch. The reading is also sent as usual, so the alert is not recorded in the history:
+/// ```json
+/// {"values":[
+///   {"key":"hr",     "value":160, "alert":"above", "limit":150, "ts":1571234567},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// `ts` is the Unix time of the alert, sent only when the wall clock has been set.
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_alert(event: &AlertEvent) -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_alert\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    let (alert, limit) = match event.threshold {
+        Threshold::Above(limit) => ("above", limit),
+        Threshold::Below(limit) => ("below", limit),
+        Threshold::Delta(delta) => ("delta", delta as i32),
+        Threshold::Disabled     => ("none",  0),
+    };
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", event.key);
+                //  Values may be negative, so encode them like sensor values instead of `json_rep_set_int!()`
+                unsafe { COAP_CONTEXT.json_set_value(b"value", SensorValueType::Int(event.value)) };
+                json_rep_set_text_string!(COAP_CONTEXT, "alert", alert);
+                unsafe { COAP_CONTEXT.json_set_value(b"limit", SensorValueType::Int(limit)) };
+                if let Some(ts) = timestamp(0) { json_rep_set_int!(COAP_CONTEXT, "ts", ts); }
+            });
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with the low-battery warning in `event` and send to the CoAP server at once:
+/// ```json
+/// {"values":[
+///   {"key":"bat_warn", "value":9, "level":"critical", "ts":1571234567},
+///   {"key":"device",   "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// `value` is the charge level in percent, and `level` is `low`, `critical` or `shutdown`. `ts` is the Unix time of
+/// the warning, sent only when the wall clock has been set.
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_battery_warning(event: &BatteryEvent) -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_battery_warning\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", "bat_warn");
+                json_rep_set_int!(COAP_CONTEXT, "value", event.percent);
+                json_rep_set_text_string!(COAP_CONTEXT, "level", event.warning.name());
+                if let Some(ts) = timestamp(0) { json_rep_set_int!(COAP_CONTEXT, "ts", ts); }
+            });
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with the battery samples from the battery history and send to the CoAP server.
+/// Each sample has the sequence number, the voltage in millivolts, the charge level in percent, 1 if charging,
+/// the packed firmware version and the Unix time, if the wall clock was set when the sample was recorded:
+/// ```json
+/// {"values":[
+///   {"key":"bat_hist", "seq":41, "value":3912, "pct":65, "chg":0, "ver":16908291, "ts":1577836800},
+///   {"key":"bat_hist", "seq":42, "value":3908, "pct":64, "chg":0, "ver":16908291, "ts":1577837700},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_battery_history(samples: &[BatterySample]) -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_battery_history\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            for sample in samples {
+                coap_item!(@json COAP_CONTEXT, {
+                    json_rep_set_text_string!(COAP_CONTEXT, "key", "bat_hist");
+                    json_rep_set_int!(COAP_CONTEXT, "seq", sample.seq);
+                    json_rep_set_int!(COAP_CONTEXT, "value", sample.mv);
+                    json_rep_set_int!(COAP_CONTEXT, "pct", sample.percent);
+                    json_rep_set_int!(COAP_CONTEXT, "chg", sample.charging);
+                    json_rep_set_int!(COAP_CONTEXT, "ver", sample.version);
+                    if sample.time != 0 { json_rep_set_int!(COAP_CONTEXT, "ts", sample.time); }
+                });
+            }
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with the estimated charge drawn by each high-power subsystem since startup, in
+/// microamp-hours, the active time in milliseconds and the number of activities, and send to the CoAP server:
+/// ```json
+/// {"values":[
+///   {"key":"power", "sub":"radio",   "value":12,  "ms":6100,   "count":3050},
+///   {"key":"power", "sub":"display", "value":950, "ms":285000, "count":41},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_power_profile() -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_power_profile\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            for subsystem in power_profile::SUBSYSTEMS.iter() {
+                let usage = power_profile::usage(*subsystem);
+                coap_item!(@json COAP_CONTEXT, {
+                    json_rep_set_text_string!(COAP_CONTEXT, "key", "power");
+                    json_rep_set_text_string!(COAP_CONTEXT, "sub", subsystem.name());
+                    json_rep_set_int!(COAP_CONTEXT, "value", usage.charge_uah(*subsystem));
+                    json_rep_set_int!(COAP_CONTEXT, "ms", usage.active_us / 1000);
+                    json_rep_set_int!(COAP_CONTEXT, "count", usage.count);
+                });
+            }
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with the memory usage: the free bytes, the largest free block and the
+/// fragmentation percent of the heap, the free mbufs, and the free and lowest free blocks of each memory pool,
+/// and send to the CoAP server for spotting leaks and fragmentation:
+/// ```json
+/// {"values":[
+///   {"key":"mem", "sub":"heap",   "value":9120, "largest":8064, "frag":11},
+///   {"key":"mem", "sub":"msys",   "value":18,   "total":24},
+///   {"key":"mem", "sub":"msys_1", "value":18,   "total":24, "min":6, "size":292},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_memory_stats() -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_memory_stats\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    //  Measure before composing the message, which takes mbufs.
+    let heap = memory::heap();
+    let msys = memory::msys();
+    let pools = memory::pools();
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", "mem");
+                json_rep_set_text_string!(COAP_CONTEXT, "sub", "heap");
+                json_rep_set_int!(COAP_CONTEXT, "value", heap.free);
+                json_rep_set_int!(COAP_CONTEXT, "largest", heap.largest_free);
+                json_rep_set_int!(COAP_CONTEXT, "frag", heap.fragmentation());
+            });
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", "mem");
+                json_rep_set_text_string!(COAP_CONTEXT, "sub", "msys");
+                json_rep_set_int!(COAP_CONTEXT, "value", msys.free);
+                json_rep_set_int!(COAP_CONTEXT, "total", msys.blocks);
+            });
+            for pool in pools.iter() {
+                coap_item!(@json COAP_CONTEXT, {
+                    json_rep_set_text_string!(COAP_CONTEXT, "key", "mem");
+                    json_rep_set_text_string!(COAP_CONTEXT, "sub", pool.name.as_str());
+                    json_rep_set_int!(COAP_CONTEXT, "value", pool.free);
+                    json_rep_set_int!(COAP_CONTEXT, "total", pool.blocks);
+                    json_rep_set_int!(COAP_CONTEXT, "min", pool.min_free);
+                    json_rep_set_int!(COAP_CONTEXT, "size", pool.block_size);
+                });
+            }
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with a crash dump written before the last restart, and send to the CoAP server.
+/// The dump has the reason, the `pc` and `lr` for looking up the crash with `arm-none-eabi-addr2line`, the task,
+/// the seconds since startup, the packed firmware version and the details, e.g. the panic location and message:
+/// ```json
+/// {"values":[
+///   {"key":"crash", "value":"panic", "pc":0, "lr":0, "task":"main", "up":3605, "ver":16908291,
+///    "detail":"app/src/lib.rs:42 index out of bounds"},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// The payload takes at most `CRASH_DUMP_PAYLOAD_SIZE` bytes, so the dump is sent in a single CoAP message.
+/// Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_crash_dump(dump: &CrashDump) -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_crash_dump\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    let reason = dump.reason().map(|reason| reason.name()).unwrap_or("unknown");
+    let mut task = [0u8; crash_dump::DUMP_TASK_SIZE + 1];
+    let mut detail = [0u8; crash_dump::DUMP_DETAIL_SIZE + 1];
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", "crash");
+                json_rep_set_text_string!(COAP_CONTEXT, "value", reason);
+                json_rep_set_int!(COAP_CONTEXT, "pc", dump.pc);
+                json_rep_set_int!(COAP_CONTEXT, "lr", dump.lr);
+                json_rep_set_text_string!(COAP_CONTEXT, "task", json_text(&mut task, dump.task()));
+                json_rep_set_int!(COAP_CONTEXT, "up", dump.uptime);
+                json_rep_set_int!(COAP_CONTEXT, "ver", dump.version);
+                json_rep_set_text_string!(COAP_CONTEXT, "detail", json_text(&mut detail, dump.detail()));
+            });
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Compose a CoAP JSON message with the current values of the settings from index `first`, at most
+/// `SETTINGS_PER_POST` settings, and send to the CoAP server for remote configuration:
+/// ```json
+/// {"values":[
+///   {"key":"setting", "name":"poll_ms",  "value":"30000"},
+///   {"key":"setting", "name":"hap_mute", "value":"0"},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// The server may respond with new values in a CBOR map, e.g. `{"poll_ms": 10000}`, which are passed to the CoAP
+/// response handler set in `remote_config.rs`. Return the index of the next setting to send if successful,
+/// `SYS_EAGAIN` if network is not ready yet or the posts are paused.
+pub fn send_settings(first: usize) -> MynewtResult<usize>  {  //  Returns an error code upon error.
+    console::print("Rust send_settings\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    let last = core::cmp::min(first + SETTINGS_PER_POST, config::count());
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            for index in first..last {
+                if let Some((name, value)) = config::entry(index) {
+                    coap_item!(@json COAP_CONTEXT, {
+                        json_rep_set_text_string!(COAP_CONTEXT, "key", "setting");
+                        json_rep_set_text_string!(COAP_CONTEXT, "name", name);
+                        json_rep_set_text_string!(COAP_CONTEXT, "value", value.as_str());
+                    });
+                }
+            }
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    unsafe { sensor_coap_expect_response() };  //  Pass the new values from the server to the response handler
+    sensor_network::do_server_post() ? ;
+    Ok(last)
+}
+
+/// Compose a CoAP JSON message with the firmware and build information, and send to the CoAP server, so that
+/// deployments can verify exactly what each watch runs. The semantic version, git commit and build time are
+/// embedded by `build.rs`, and each firmware slot has the packed MCUBoot version, the image hash and the state:
+/// ```json
+/// {"values":[
+///   {"key":"version", "value":"0.1.0", "git":"1b84bea3", "built":"2020-05-01T12:34:56Z"},
+///   {"key":"slot", "value":0, "ver":16908291, "hash":"0102030405060708", "pending":0, "confirmed":1},
+///   {"key":"slot", "value":1, "ver":16908290, "hash":"0807060504030201", "pending":0, "confirmed":0},
+///   {"key":"device", "value":"0102030405060708090a0b0c0d0e0f10"}
+/// ]}
+/// ```
+/// Empty slots are skipped. Return `Ok()` if successful, `SYS_EAGAIN` if network is not ready yet or the posts are
+/// paused.
+pub fn send_version() -> MynewtResult<()>  {  //  Returns an error code upon error.
+    console::print("Rust send_version\n");
+    if is_paused() { return Err(MynewtError::SYS_EAGAIN); }
+    let img0 = mcuboot::read_image_info(Slot::Active) ? ;
+    let img1 = mcuboot::read_image_info(Slot::Standby) ? ;
+    let device_id = sensor_network::get_device_id() ? ;
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    coap_root!(@json COAP_CONTEXT {
+        coap_array!(@json COAP_CONTEXT, values, {
+            coap_item!(@json COAP_CONTEXT, {
+                json_rep_set_text_string!(COAP_CONTEXT, "key", "version");
+                json_rep_set_text_string!(COAP_CONTEXT, "value", version::VERSION);
+                json_rep_set_text_string!(COAP_CONTEXT, "git", version::GIT_HASH);
+                json_rep_set_text_string!(COAP_CONTEXT, "built", version::BUILD_TIME);
+            });
+            for (slot, info) in [img0, img1].iter().enumerate() {
+                if let Some(info) = info {
+                    let mut hash = heapless::String::<heapless::consts::U16>::new();
+                    for b in info.hash.iter().flatten() { write!(hash, "{:02x}", b).ok(); }
+                    coap_item!(@json COAP_CONTEXT, {
+                        json_rep_set_text_string!(COAP_CONTEXT, "key", "slot");
+                        json_rep_set_int!(COAP_CONTEXT, "value", slot);
+                        json_rep_set_int!(COAP_CONTEXT, "ver", info.header.ih_ver.packed());
+                        json_rep_set_text_string!(COAP_CONTEXT, "hash", hash.as_str());
+                        json_rep_set_int!(COAP_CONTEXT, "pending", info.magic_set);
+                        json_rep_set_int!(COAP_CONTEXT, "confirmed", info.image_ok);
+                    });
+                }
+            }
+            coap_item_str!(@json COAP_CONTEXT, "device", &device_id);
+        });
+    });
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+///  Pause or resume the CoAP posts, e.g. while a phone is syncing over Bluetooth LE. While paused, the posts fail
+///  with `SYS_EAGAIN`, so that the callers try again later.
+pub fn pause(paused: bool) {
+    PAUSED.store(paused, Ordering::Relaxed);
+}
+
+///  Return true if the CoAP posts are paused
+fn is_paused() -> bool {
+    PAUSED.load(Ordering::Relaxed)
+}
+
+///  Copy `text` into `buf` with a terminating null, for encoding as a JSON text value of at most 2 bytes per byte of
+///  `text`. Control characters are replaced by `?`, since JSON escapes them in 6 bytes. The null lets the value be
+///  longer than the value buffer of `COAP_CONTEXT`. `buf` must be longer than `text`.
+fn json_text<'a>(buf: &'a mut [u8], text: &str) -> &'a [u8] {
+    let text = text.as_bytes();
+    for (dest, &byte) in buf.iter_mut().zip(text) {
+        *dest = if byte < b' ' || byte == 0x7f { b'?' } else { byte };
+    }
+    buf[text.len()] = 0;
+    &buf[..text.len() + 1]
+}
+
+///  Return the Unix time of a reading recorded `age` seconds ago, or `None` if the wall clock has not been set
+fn timestamp(age: u64) -> Option<u64> {
+    let now = time::wall_clock() ? ;
+    if now < age as i64 { return None; }
+    Some(now as u64 - age)
+}
+
+///  Max number of settings in a CoAP message, to keep the message small
+const SETTINGS_PER_POST: usize = 8;
+
+///  Max size of the CoAP payload that is sent in a single message. Must sync with `OC_MAX_PAYLOAD_SIZE` in
+///  `apps/my_sensor_app/syscfg.yml`.
+const COAP_MAX_PAYLOAD_SIZE: usize = 400;
+
+///  Max size of the payload of `send_crash_dump()`: 200 bytes for the keys, the reason, the numbers of 10 digits and
+///  the device ID, plus the task and the details, which take 2 bytes per byte when every byte is escaped
+const CRASH_DUMP_PAYLOAD_SIZE: usize = 200 + 2 * (crash_dump::DUMP_TASK_SIZE + crash_dump::DUMP_DETAIL_SIZE);
+
+///  Fails to compile if a crash dump doesn't fit in a single CoAP message, since the CoAP client doesn't send
+///  blockwise. Shorten `DUMP_DETAIL_SIZE` in `mynewt::sys::crash_dump` or increase `OC_MAX_PAYLOAD_SIZE` if so.
+#[allow(dead_code)]
+const CRASH_DUMP_FITS: [(); 0] = [(); (CRASH_DUMP_PAYLOAD_SIZE > COAP_MAX_PAYLOAD_SIZE) as usize];
+
+extern "C" {
+    ///  Keep the CoAP request being composed until the server responds. C API: `libs/sensor_coap`
+    fn sensor_coap_expect_response();
+}
+
+///  True while the CoAP posts are paused by `pause()`
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
 ///  Current geolocation recorded from GPS
 #[cfg(feature = "use_float")]  //  If floating-point is enabled...
-static mut CURRENT_GEOLOCATION: SensorValueType = SensorValueType::None;
\ No newline at end of file
+static CURRENT_GEOLOCATION: Mutex<SensorValueType> = Mutex::new(SensorValueType::None);
\ No newline at end of file
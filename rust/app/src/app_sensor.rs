@@ -16,55 +16,293 @@
  * specific language governing permissions and limitations
  * under the License.
  */
-//!  Poll the temperature sensor every 10 seconds. Transmit the sensor data to the CoAP server after polling.
+//!  Poll the temperature, heart rate, battery and step count sensors. Transmit the sensor data to the CoAP server after polling.
+//!  The readings are kept in a history on the device, so that readings missed while the network is down are sent later.
+//!  The accelerometer motion is downsampled to one mean reading every 10 seconds, so that it doesn't keep the radio busy.
+//!  While the watch is idle, the sensors are polled `IDLE_SLOWDOWN` times less often, and in Deep Sleep only the battery is polled
+//!  and the heart rate LED is switched off.
 //!  This is the Rust version of https://github.com/lupyuen/stm32bluepill-mynewt-sensor/blob/nrf52/apps/my_sensor_app/OLDsrc/sensor.c
 
 use mynewt::{
     result::*,                              //  Import Mynewt API Result and Error types
-    hw::sensor_mgr,                         //  Import Mynewt Sensor Manager API
+    kernel::os,                             //  Import Mynewt Kernel API
     hw::sensor::{        
         self,                               //  Import Mynewt Sensor API
         sensor_type_t,
+        Listener, Reading,                  //  Import Mynewt Sensor Listener API
+        History,                            //  Import Mynewt Sensor History API
+        Aggregation, Aggregator,            //  Import Mynewt Sensor Aggregation API
+        SensorValueType,
+        MilliG, units::BMA421_COUNTS_PER_G, //  Import Mynewt Sensor Units
+        poller,                             //  Import Mynewt Sensor Poller API
     },
     sys::console,                           //  Import Mynewt Console API
     Strn,                                   //  Import Mynewt macros    
 };
+use core::time::Duration;
 use mynewt_macros::{ init_strn };           //  Import Mynewt procedural macros
 use crate::app_network;                     //  Import `app_network.rs` for sending sensor data
+use crate::settings;                        //  Import `settings.rs` for the poll interval
+use crate::pedometer;                       //  Import `pedometer.rs` for the step count sensor
+use crate::orientation;                     //  Import `orientation.rs` for the acceleration magnitude
+use crate::alerts;                          //  Import `alerts.rs` for checking the alert thresholds
+use crate::ble_sensors;                     //  Import `ble_sensors.rs` for the standard Bluetooth LE services
+use crate::beacon;                          //  Import `beacon.rs` for broadcasting the readings in advertisements
+use crate::adc_calibration;                 //  Import `adc_calibration.rs` for recalibrating the battery ADC
+use crate::data_log;                        //  Import `data_log.rs` for logging the readings in flash
+use crate::power::{                         //  Import `power.rs` for the battery fuel gauge and the power manager
+    self,
+    manager::{ self, PowerHooks, SystemState },
+};
 
-///  Sensor to be polled: `temp_stub_0` is the stub temperature sensor that simulates a temperature sensor
-static SENSOR_DEVICE: Strn      = init_strn!("temp_stub_0");
-///  Poll sensor every 30,000 milliseconds (30 seconds)  
-const SENSOR_POLL_TIME: u32     = 30 * 1000;  
-///  Use key (field name) `t` to transmit raw temperature to CoAP Server
-const TEMP_SENSOR_KEY: Strn     = init_strn!("t");
-///  Type of sensor: Raw temperature sensor (integer sensor values 0 to 4095)
+///  Sensor to be polled: `temp_nrf52_0` is the nRF52 internal die temperature sensor
+static SENSOR_DEVICE: Strn      = init_strn!("temp_nrf52_0");
+///  Use key (field name) `t` to transmit raw temperature (degrees Celsius times 100) to CoAP Server
+pub static TEMP_SENSOR_KEY: Strn    = init_strn!("t");
+///  Type of sensor: Raw temperature sensor (integer sensor values)
 const TEMP_SENSOR_TYPE: sensor_type_t = sensor::SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW;
+///  Listener that sends the polled temperature to the CoAP server, after calibration
+static TEMP_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> =
+    Listener::with_calibration(send_temperature, &settings::TEMP_CALIBRATION);
+///  Recent temperature readings
+static TEMP_HISTORY: History = History::new(&TEMP_SENSOR_KEY);
+
+///  Heart rate sensor: `hrs3300_0` is the HRS3300 sensor in PineTime
+static HR_SENSOR_DEVICE: Strn   = init_strn!("hrs3300_0");
+///  Use key (field name) `hr` to transmit heart rate (beats per minute) to CoAP Server
+pub static HR_SENSOR_KEY: Strn      = init_strn!("hr");
+///  Read the heart rate every minute, since the sensor task computes it in the background
+const HR_POLL_TIME: Duration    = Duration::from_secs(60);
+///  Listener that sends the polled heart rate to the CoAP server
+static HR_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> = Listener::new(send_heart_rate);
+///  Recent heart rate readings
+static HR_HISTORY: History = History::new(&HR_SENSOR_KEY);
+
+///  Battery sensor: `battery_0` measures the PineTime battery voltage
+static BATTERY_SENSOR_DEVICE: Strn = init_strn!("battery_0");
+///  Use key (field name) `bat` to transmit battery voltage (millivolts) to CoAP Server
+pub static BATTERY_SENSOR_KEY: Strn    = init_strn!("bat");
+///  Read the battery voltage every 5 minutes, since it changes slowly
+const BATTERY_POLL_TIME: Duration  = Duration::from_secs(5 * 60);
+///  Listener that sends the polled battery voltage to the CoAP server, after calibration
+static BATTERY_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> =
+    Listener::with_calibration(send_battery, &settings::BATTERY_CALIBRATION);
+///  Recent battery voltage readings
+static BATTERY_HISTORY: History = History::new(&BATTERY_SENSOR_KEY);
+
+///  Use key (field name) `steps` to transmit the daily step count to CoAP Server
+pub static STEPS_SENSOR_KEY: Strn  = init_strn!("steps");
+///  Listener that sends the polled step count to the CoAP server
+static STEPS_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> = Listener::new(send_steps);
+///  Recent step count readings
+static STEPS_HISTORY: History = History::new(&STEPS_SENSOR_KEY);
+
+///  Use key (field name) `motion` to transmit the motion (acceleration away from 1 g, in milli-g) to CoAP Server
+static MOTION_SENSOR_KEY: Strn     = init_strn!("motion");
+///  Report the mean motion every 10 seconds, instead of every accelerometer sample (25 Hz)
+static MOTION_AGGREGATOR: Aggregator =
+    Aggregator::new(&MOTION_SENSOR_KEY, Aggregation::Mean, Duration::from_secs(10));
+///  Recent motion readings, one per aggregation window
+static MOTION_HISTORY: History = History::new(&MOTION_SENSOR_KEY);
 
-///  Ask Mynewt to poll or read the temperature sensor and call `aggregate_sensor_data()`
+///  Histories of all sensors, for uploading the unsent readings in one batch
+static HISTORIES: [&History; 5] = [&TEMP_HISTORY, &HR_HISTORY, &BATTERY_HISTORY, &STEPS_HISTORY, &MOTION_HISTORY];
+
+///  While the watch is idle, poll the sensors this many times less often
+const IDLE_SLOWDOWN: u32 = 4;
+///  Hooks for the sensors, which are polled less often in Idle and paused in Deep Sleep, except the battery.
+///  The heart rate sensor is suspended in Deep Sleep.
+static SENSOR_HOOKS: PowerHooks = PowerHooks { name: "sensors", enter: enter_sensors, exit: exit_sensors };
+
+///  Poll the temperature sensor at the interval in the settings and call `send_temperature()`
 ///  Return `Ok()` if successful, else return `Err()` with `MynewtError` error code inside.
-#[allow(dead_code)]
 pub fn start_sensor_listener() -> MynewtResult<()>  {  //  Returns an error code upon error.
     console::print("Rust TMP poll\n");
 
-    //  Fetch the sensor by name.
-    let sensor = sensor_mgr::find_bydevname(&SENSOR_DEVICE)
-        .next()             //  Fetch the first sensor that matches
-        .expect("no TMP");  //  Stop if no sensor found
+    //  Call `send_temperature` with the raw temperature (integer) after polling the sensor.
+    TEMP_LISTENER.register(&SENSOR_DEVICE, TEMP_SENSOR_TYPE) ? ;  //  `?` means in case of error, return error now.
 
-    //  At power on, we ask Mynewt to poll our temperature sensor every 10 seconds.
-    sensor::set_poll_rate_ms(&SENSOR_DEVICE, SENSOR_POLL_TIME) ? ;
+    //  At power on, we poll our temperature sensor at the interval in the settings (30 seconds by default).
+    poller::add(&SENSOR_DEVICE, TEMP_SENSOR_TYPE, poll_time()) ? ;
 
-    // Create a sensor listener that will call function `aggregate_sensor_data` after polling the sensor data
-    let listener = sensor::new_sensor_listener(
-        &TEMP_SENSOR_KEY,   //  Transmit as field: `t`
-        TEMP_SENSOR_TYPE,   //  Type of sensor data: Raw temperature (integer from 0 to 4095)
-        app_network::aggregate_sensor_data  //  Call this function with the polled data: `aggregate_sensor_data`
-    ) ? ;
-
-    //  Register the Listener Function to be called with the polled sensor data.
-    sensor::register_listener(sensor, listener) ? ;  //  `?` means in case of error, return error now.
+    //  Poll less often while the watch is idle.
+    manager::register(&SENSOR_HOOKS) ? ;
 
     //  Return `Ok()` to indicate success.  This line should not end with a semicolon (;).
     Ok(())
-}
\ No newline at end of file
+}
+
+///  Transmit the polled temperature as field `t` to the CoAP server. The battery ADC is recalibrated if the
+///  temperature has drifted.
+fn send_temperature(reading: &Reading) -> MynewtResult<()> {
+    if let Reading::TempRaw(temp) = reading { adc_calibration::update_temperature(*temp) ? ; }
+    send_reading(&TEMP_HISTORY, reading)
+}
+
+///  Start the heart rate sensor and poll it every minute.
+///  Call `send_heart_rate()` with the heart rate after polling. Returns `SYS_ENODEV` if the sensor is not enabled in `syscfg.yml`.
+pub fn start_heart_rate_listener() -> MynewtResult<()> {
+    console::print("Rust HRS poll\n");
+
+    //  Turn on the LED and start sampling. The sensor task computes the heart rate in the background.
+    check(unsafe { hrs3300_start(heart_rate_device() ? ) }) ? ;
+
+    //  Read the computed heart rate at the poll interval.
+    HR_LISTENER.register(&HR_SENSOR_DEVICE, sensor::SENSOR_TYPE_HEART_RATE) ? ;
+    poller::add(&HR_SENSOR_DEVICE, sensor::SENSOR_TYPE_HEART_RATE, HR_POLL_TIME) ? ;
+    Ok(())
+}
+
+///  Transmit the polled heart rate as field `hr` to the CoAP server
+fn send_heart_rate(reading: &Reading) -> MynewtResult<()> {
+    send_reading(&HR_HISTORY, reading)
+}
+
+///  Return the heart rate sensor driver. Returns `SYS_ENODEV` if the sensor is not enabled in `syscfg.yml`.
+fn heart_rate_device() -> MynewtResult<*mut ::cty::c_void> {
+    let dev = unsafe { os::os_dev_lookup(HR_SENSOR_DEVICE.as_cstr() as *const ::cty::c_char) };
+    if dev.is_null() { return Err(MynewtError::SYS_ENODEV); }
+    Ok(dev as *mut ::cty::c_void)
+}
+
+///  Switch off the heart rate LED and stop sampling, or restart the sampling if it was started before. Does nothing
+///  if the sensor is not enabled in `syscfg.yml`.
+fn suspend_heart_rate(suspend: bool) -> MynewtResult<()> {
+    let dev = match heart_rate_device() {
+        Ok(dev) => dev,
+        Err(MynewtError::SYS_ENODEV) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    check(unsafe { if suspend { hrs3300_suspend(dev) } else { hrs3300_resume(dev) } })
+}
+
+///  Poll the battery sensor every 5 minutes and call `send_battery()` after polling.
+///  Returns `SYS_ENODEV` if the sensor is not enabled in `syscfg.yml`.
+pub fn start_battery_listener() -> MynewtResult<()> {
+    console::print("Rust BAT poll\n");
+    BATTERY_LISTENER.register(&BATTERY_SENSOR_DEVICE, sensor::SENSOR_TYPE_BATTERY) ? ;
+    poller::add(&BATTERY_SENSOR_DEVICE, sensor::SENSOR_TYPE_BATTERY, BATTERY_POLL_TIME) ? ;
+    Ok(())
+}
+
+///  Transmit the polled battery voltage as field `bat` to the CoAP server. The charge level computed by the fuel
+///  gauge is published over the Bluetooth LE Battery Service and recorded in the battery history.
+fn send_battery(reading: &Reading) -> MynewtResult<()> {
+    let reading = power::gauge::update(reading);
+    if let Err(err) = power::history::record(&reading) { log::warn!("battery history fail {:?}", err); }
+    ble_sensors::update_battery(&reading);
+    beacon::update_battery(&reading);
+    send_reading(&BATTERY_HISTORY, &reading)
+}
+
+///  Poll the pedometer virtual sensor at the interval in the settings and call `send_steps()` after polling.
+///  The pedometer must be started first.
+pub fn start_steps_listener() -> MynewtResult<()> {
+    STEPS_LISTENER.register(&pedometer::PEDOMETER_DEVICE, sensor::SENSOR_TYPE_STEPS) ? ;
+    poller::add(&pedometer::PEDOMETER_DEVICE, sensor::SENSOR_TYPE_STEPS, poll_time()) ? ;
+    Ok(())
+}
+
+///  Transmit the polled step count as field `steps` to the CoAP server
+fn send_steps(reading: &Reading) -> MynewtResult<()> {
+    send_reading(&STEPS_HISTORY, reading)
+}
+
+///  Add the calibrated accelerometer sample `[x, y, z]` to the motion aggregator. Called by the pedometer task for
+///  each sample. At the end of each window, the mean motion is recorded in the history but not transmitted here, to
+///  keep the network out of the pedometer task. The unsent motion readings are transmitted in the next batch.
+///  The sample is also streamed over Bluetooth LE to a subscribed phone.
+pub fn record_motion(sample: &[i32; 3]) {
+    ble_sensors::stream_motion(&[
+        MilliG::from_counts(sample[0], BMA421_COUNTS_PER_G),
+        MilliG::from_counts(sample[1], BMA421_COUNTS_PER_G),
+        MilliG::from_counts(sample[2], BMA421_COUNTS_PER_G),
+    ]);
+    let (x, y, z) = (sample[0] as i64, sample[1] as i64, sample[2] as i64);
+    let counts = (orientation::isqrt(x * x + y * y + z * z) - orientation::ONE_G).abs();
+    let MilliG(motion) = MilliG::from_counts(counts, BMA421_COUNTS_PER_G);
+    if let Some(value) = MOTION_AGGREGATOR.add(&SensorValueType::Int(motion)) {
+        MOTION_HISTORY.record(value);
+    }
+}
+
+///  Record the reading in the sensor history and transmit it to the CoAP server. If earlier readings could not be
+///  transmitted, e.g. the network was down, transmit all unsent readings in one batch instead.
+///  Alerts for the reading are sent before the reading. The reading is also published over Bluetooth LE, in the
+///  standard services and the sensor beacon.
+fn send_reading(history: &'static History, reading: &Reading) -> MynewtResult<()> {
+    let sensor_value = reading.to_sensor_value(history.key());
+    alerts::check(history.key(), &sensor_value.value);
+    ble_sensors::update(history.key(), &sensor_value.value);
+    beacon::update(history.key(), &sensor_value.value);
+    history.record(sensor_value.value);
+    data_log::record_reading(history.key(), &sensor_value.value);
+    let unsent: usize = HISTORIES.iter().map(|h| h.unsent()).sum();
+    if unsent > 1 { return app_network::send_history(&HISTORIES); }
+    app_network::aggregate_sensor_data(&sensor_value) ? ;
+    history.mark_sent(1);
+    Ok(())
+}
+
+///  Change the poll intervals for the new power state, or pause the sensors and switch off the heart rate LED in
+///  Deep Sleep
+fn enter_sensors(state: SystemState) -> MynewtResult<()> {
+    let slowdown = match state {
+        SystemState::Active    => 1,
+        SystemState::Idle      => IDLE_SLOWDOWN,
+        SystemState::DeepSleep => {
+            for_each_sensor(|devname| poller::enable(devname, false)) ? ;
+            return suspend_heart_rate(true);
+        }
+    };
+    set_interval(&SENSOR_DEVICE, poll_time() * slowdown) ? ;
+    set_interval(&HR_SENSOR_DEVICE, HR_POLL_TIME * slowdown) ? ;
+    set_interval(&pedometer::PEDOMETER_DEVICE, poll_time() * slowdown)
+}
+
+///  Resume the sensors after Deep Sleep. They are read at once, so the first heart rate may be invalid.
+fn exit_sensors(state: SystemState) -> MynewtResult<()> {
+    if state != SystemState::DeepSleep { return Ok(()); }
+    suspend_heart_rate(false) ? ;
+    for_each_sensor(|devname| poller::enable(devname, true))
+}
+
+///  Call `f` with each sensor that is paused in Deep Sleep
+fn for_each_sensor(f: fn(&Strn) -> MynewtResult<()>) -> MynewtResult<()> {
+    for devname in &[&SENSOR_DEVICE, &HR_SENSOR_DEVICE, &pedometer::PEDOMETER_DEVICE] {
+        skip_missing(f(devname)) ? ;
+    }
+    Ok(())
+}
+
+///  Change the poll interval of the sensor `devname`, if it is polled
+fn set_interval(devname: &Strn, interval: Duration) -> MynewtResult<()> {
+    skip_missing(poller::set_interval(devname, interval))
+}
+
+///  Ignore `SYS_ENOENT` for the sensors that are not polled, e.g. disabled in `syscfg.yml`
+fn skip_missing(result: MynewtResult<()>) -> MynewtResult<()> {
+    match result {
+        Err(MynewtError::SYS_ENOENT) => Ok(()),
+        result => result,
+    }
+}
+
+///  Return the poll interval in the settings
+fn poll_time() -> Duration {
+    Duration::from_millis(settings::POLL_TIME.get() as u64)
+}
+
+extern "C" {
+    ///  Start sampling the heart rate sensor. `dev` is the `struct hrs3300`, which starts with the `os_dev`.
+    ///  C API: `int hrs3300_start(struct hrs3300 *dev)`
+    fn hrs3300_start(dev: *mut ::cty::c_void) -> ::cty::c_int;
+
+    ///  Stop sampling and switch off the LED, if started.
+    ///  C API: `int hrs3300_suspend(struct hrs3300 *dev)`
+    fn hrs3300_suspend(dev: *mut ::cty::c_void) -> ::cty::c_int;
+
+    ///  Start sampling again if the sensor was started before `hrs3300_suspend()`.
+    ///  C API: `int hrs3300_resume(struct hrs3300 *dev)`
+    fn hrs3300_resume(dev: *mut ::cty::c_void) -> ::cty::c_int;
+}
@@ -0,0 +1,155 @@
+//!  Sensor beacon mode: broadcast the latest sensor readings in non-connectable advertising, so that gateways can
+//!  collect the telemetry passively without connecting. Enabled by `SENSOR_BEACON: 1` in `syscfg.yml`, which
+//!  replaces the connectable advertising in `apps/my_sensor_app/src/ble_main.c` with `sensor_beacon_start()`.
+//!  The advertising rotates through the sensors every `ROTATE_INTERVAL`, one reading in each advertisement,
+//!  in the manufacturer-specific data:
+//!  ```text
+//!  Bytes 0-1  Company identifier 0xFFFF (for testing), little endian
+//!  Byte  2    Format version 1
+//!  Byte  3    Sensor: 1 temperature, 2 heart rate, 3 battery, 4 steps
+//!  Bytes 4-7  Value as little endian int32: degrees Celsius times 100, beats per minute, percent or steps
+//!  Byte  8    Sequence number of the reading, incremented for each new reading of the sensor
+//!  ```
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    ble::{
+        adv::{ self, AdvConfig },
+        BleError,
+    },
+    hw::sensor::{ Reading, SensorValueType },
+    kernel::{ sync::Mutex, timer::Callout },
+    Strn,
+};
+use mynewt_macros::init_strn;
+use crate::app_sensor;
+
+///  Company identifier reserved for testing by the Bluetooth SIG
+const COMPANY_ID: u16 = 0xFFFF;
+
+///  Version of the manufacturer-specific data format
+const FORMAT_VERSION: u8 = 1;
+
+///  Length of the manufacturer-specific data
+const MFG_DATA_LEN: usize = 9;
+
+///  Advertise the next sensor after this interval
+const ROTATE_INTERVAL: Duration = Duration::from_secs(2);
+
+///  Min and max advertising intervals. Gateways scanning continuously receive each reading a few times.
+const ADV_INTERVAL: (Duration, Duration) = (Duration::from_millis(500), Duration::from_millis(600));
+
+///  Sensors broadcast by the beacon, in the order of rotation. The value is the sensor byte in the advertisement.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BeaconSensor {
+    Temperature = 1,
+    HeartRate   = 2,
+    Battery     = 3,
+    Steps       = 4,
+}
+
+///  Number of sensors in `SENSORS`
+const NUM_SENSORS: usize = 4;
+
+///  Sensors in the order of rotation
+const SENSORS: [BeaconSensor; NUM_SENSORS] =
+    [BeaconSensor::Temperature, BeaconSensor::HeartRate, BeaconSensor::Battery, BeaconSensor::Steps];
+
+///  Latest value and sequence number of each sensor in `SENSORS`, `None` until the first reading
+static READINGS: Mutex<[Option<(i32, u8)>; NUM_SENSORS]> = Mutex::new([None; NUM_SENSORS]);
+
+///  Index in `SENSORS` of the sensor advertised last
+static mut CURRENT: usize = NUM_SENSORS - 1;
+
+///  Manufacturer-specific data being advertised. Copied by NimBLE when the advertising starts.
+static mut MFG_DATA: [u8; MFG_DATA_LEN] = [0; MFG_DATA_LEN];
+
+///  Device name in the advertisement
+static DEVICE_NAME: Strn = init_strn!("pinetime");
+
+///  Timer that advertises the next sensor
+static ROTATE_TIMER: Callout<fn()> = Callout::new(rotate);
+
+///  Start broadcasting the sensor readings. Called by `ble_main.c` when the host is synced, instead of
+///  the connectable advertising. Returns 0.
+#[no_mangle]
+extern "C" fn sensor_beacon_start() -> i32 {
+    rotate();
+    0
+}
+
+///  Save the reading `value` of the sensor with key `key` for broadcasting. Readings of other sensors are ignored.
+///  The battery voltage is ignored too, the battery level is saved by `update_battery()`.
+pub fn update(key: &'static Strn, value: &SensorValueType) {
+    let value = match value.as_int() { Some(value) => value, None => return };
+    let sensor =
+        if core::ptr::eq(key, &app_sensor::TEMP_SENSOR_KEY)       { BeaconSensor::Temperature }
+        else if core::ptr::eq(key, &app_sensor::HR_SENSOR_KEY)    { BeaconSensor::HeartRate }
+        else if core::ptr::eq(key, &app_sensor::STEPS_SENSOR_KEY) { BeaconSensor::Steps }
+        else { return };
+    save(sensor, value);
+}
+
+///  Save the charge level of the battery `reading` for broadcasting. Other readings are ignored.
+pub fn update_battery(reading: &Reading) {
+    if let Reading::Battery { percent, .. } = reading {
+        save(BeaconSensor::Battery, core::cmp::min(*percent, 100) as i32);
+    }
+}
+
+///  Save the latest value of the sensor and increment its sequence number
+fn save(sensor: BeaconSensor, value: i32) {
+    let i = SENSORS.iter().position(|s| *s == sensor).expect("beacon sensor");
+    //  Ignore the error if the mutex can't be locked, the next reading will be saved.
+    if let Ok(mut readings) = READINGS.lock() {
+        let seq = match readings[i] { Some((_, seq)) => seq.wrapping_add(1), None => 0 };
+        readings[i] = Some((value, seq));
+    }
+}
+
+///  Advertise the next sensor that has a reading, then schedule the next rotation
+fn rotate() {
+    if let Err(err) = advertise_next() {
+        log::warn!("beacon adv failed {:?}", err);
+    }
+    ROTATE_TIMER.reset(ROTATE_INTERVAL).expect("beacon timer fail");
+}
+
+///  Restart the non-connectable advertising with the reading of the next sensor. If there are no readings yet,
+///  only the device name is advertised.
+fn advertise_next() -> MynewtResult<()> {
+    let len = next_mfg_data() ? ;
+    match adv::stop() {
+        Ok(()) | Err(BleError::EALREADY) => {}
+        Err(err) => return Err(err.into()),
+    }
+    adv::start(AdvConfig {
+        mfg_data: unsafe { &MFG_DATA[..len] },
+        interval: Some(ADV_INTERVAL),
+        connectable: false,
+        restart_on_disconnect: false,
+        ..AdvConfig::new(&DEVICE_NAME)
+    }) ? ;
+    Ok(())
+}
+
+///  Pack the reading of the next sensor after `CURRENT` into `MFG_DATA`. Returns the length of the data, or 0 if
+///  there are no readings yet.
+fn next_mfg_data() -> MynewtResult<usize> {
+    let readings = READINGS.lock() ? ;
+    for step in 1..=NUM_SENSORS {
+        let i = unsafe { (CURRENT + step) % NUM_SENSORS };
+        let (value, seq) = match readings[i] { Some(reading) => reading, None => continue };
+        unsafe {
+            CURRENT = i;
+            MFG_DATA[0..2].copy_from_slice(&COMPANY_ID.to_le_bytes());
+            MFG_DATA[2] = FORMAT_VERSION;
+            MFG_DATA[3] = SENSORS[i] as u8;
+            MFG_DATA[4..8].copy_from_slice(&value.to_le_bytes());
+            MFG_DATA[8] = seq;
+        }
+        return Ok(MFG_DATA_LEN);
+    }
+    Ok(0)
+}
@@ -0,0 +1,81 @@
+//!  Publish the sensor readings over the standard Bluetooth LE services in `apps/my_sensor_app/src/ble_sensor_svc.c`,
+//!  so that off-the-shelf phone apps can read the heart rate, temperature and battery level without the CoAP server.
+//!  `app_sensor.rs` calls `update()` with each reading, and the phones that subscribed to the characteristic are
+//!  notified. The battery level is the charge level in percent computed by the fuel gauge in `power/gauge.rs`,
+//!  and the Battery Service notifies the subscribed phones only when the level changes. The accelerometer samples are streamed to a subscribed phone over the Motion Stream Service.
+
+use mynewt::{
+    result::*,
+    ble::{ notify::Stream, BleError },
+    hw::sensor::{ MilliG, Reading, SensorValueType },
+    Strn,
+};
+use crate::app_sensor;
+
+///  Stream of accelerometer samples, limited to the number of link layer buffers
+static MOTION_STREAM: Stream = Stream::new(motion_handle, 0);
+
+///  Start streaming the accelerometer samples to the phones that subscribe. Called by main() in `lib.rs`.
+pub fn start_streams() -> MynewtResult<()> {
+    match MOTION_STREAM.register() {
+        Err(BleError::ENOTSUP) => Ok(()),  //  Bluetooth LE is disabled
+        result => result.map_err(|err| err.into()),
+    }
+}
+
+///  Stream the accelerometer sample to the subscribed phone as x, y, z in milli-g
+pub fn stream_motion(sample: &[MilliG; 3]) {
+    let mut record = [0u8; 6];
+    for (i, MilliG(axis)) in sample.iter().enumerate() {
+        let axis = clamp(*axis, -32768, 32767) as i16;
+        record[i * 2..i * 2 + 2].copy_from_slice(&axis.to_le_bytes());
+    }
+    //  Ignore the error if no phone has subscribed.
+    MOTION_STREAM.push(&record).ok();
+}
+
+///  Return the handle of the Motion Samples characteristic value
+fn motion_handle() -> u16 {
+    unsafe { sensor_svc_motion_handle() }
+}
+
+///  Set the characteristic for the reading `value` of the sensor with key `key`. Readings of sensors without
+///  a standard service are ignored. The battery voltage is ignored too, the battery level is set by `update_battery()`.
+pub fn update(key: &'static Strn, value: &SensorValueType) {
+    let value = match value.as_int() { Some(value) => value, None => return };
+    //  Ignore the error if Bluetooth LE is disabled.
+    if core::ptr::eq(key, &app_sensor::HR_SENSOR_KEY) {
+        unsafe { sensor_svc_set_heart_rate(clamp(value, 0, 255) as u8) };
+    } else if core::ptr::eq(key, &app_sensor::TEMP_SENSOR_KEY) {
+        unsafe { sensor_svc_set_temperature(clamp(value, -32768, 32767) as i16) };
+    }
+}
+
+///  Set the battery level to the charge level of the battery `reading`, and notify the subscribed phones if the
+///  level has changed. Other readings are ignored.
+pub fn update_battery(reading: &Reading) {
+    if let Reading::Battery { percent, .. } = reading {
+        //  Ignore the error if Bluetooth LE is disabled.
+        unsafe { sensor_svc_set_battery_level(core::cmp::min(*percent, 100)) };
+    }
+}
+
+///  Return `value` limited to `min..=max`
+fn clamp(value: i32, min: i32, max: i32) -> i32 {
+    if value < min { min } else if value > max { max } else { value }
+}
+
+extern "C" {
+    ///  Set the heart rate in beats per minute and notify the subscribed phones.
+    ///  C API: `int sensor_svc_set_heart_rate(uint8_t bpm)`
+    fn sensor_svc_set_heart_rate(bpm: u8) -> i32;
+    ///  Set the temperature in degrees Celsius times 100 and notify the subscribed phones.
+    ///  C API: `int sensor_svc_set_temperature(int16_t temp)`
+    fn sensor_svc_set_temperature(temp: i16) -> i32;
+    ///  Set the battery level in percent and notify the subscribed phones if the level has changed.
+    ///  C API: `int sensor_svc_set_battery_level(uint8_t percent)`
+    fn sensor_svc_set_battery_level(percent: u8) -> i32;
+    ///  Return the handle of the Motion Samples characteristic value.
+    ///  C API: `uint16_t sensor_svc_motion_handle(void)`
+    fn sensor_svc_motion_handle() -> u16;
+}
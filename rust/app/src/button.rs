@@ -0,0 +1,142 @@
+//!  Driver for the side button of the watch. The button is only powered while the enable pin PUSH_BUTTON_OUT (P0.15)
+//!  is high, and PUSH_BUTTON_IN (P0.13) floats when the button is released, so the input is pulled down. The pin
+//!  interrupt fires on both edges, and the contacts bounce for a few milliseconds, so the level is read again after
+//!  `DEBOUNCE_TIME` on the default event queue. The presses are reported as `ButtonEvent`s to the UI through
+//!  `BUTTON_EVENTS`, and to the handlers registered with `register()`, e.g. the factory reset in `logo/reset.rs`.
+//!  A click is only reported after `DOUBLE_CLICK_TIME` without a second click, so a click is never reported as part
+//!  of a double click.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    board::pinetime,
+    hal::gpio::{ Interrupt, Level, PinEvent },
+    kernel::{
+        event::EventQueue,
+        time::Instant,
+        timer::Callout,
+    },
+};
+use crate::power::{ self, WakeReason };
+
+///  Read the level again after the contacts have stopped bouncing
+const DEBOUNCE_TIME: Duration = Duration::from_millis(20);
+
+///  Max time between the release of the first click and the release of the second click of a double click
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(300);
+
+///  Min time that the button must be held for a long press
+pub const LONG_PRESS_TIME: Duration = Duration::from_millis(1000);
+
+///  Press of the button
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ButtonEvent {
+    ///  Button was pressed and released once
+    Click,
+    ///  Button was clicked twice within `DOUBLE_CLICK_TIME`
+    DoubleClick,
+    ///  Button was released after being held for `held`, at least `LONG_PRESS_TIME`
+    LongPress { held: Duration },
+}
+
+///  Button events for the UI. Call `BUTTON_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static BUTTON_EVENTS: EventQueue<ButtonEvent> = EventQueue::new();
+
+///  Max number of handlers that may be registered. Must match `MaxHandlers`.
+pub const MAX_HANDLERS: usize = 4;
+type MaxHandlers = heapless::consts::U4;
+
+///  Handlers called on the default event queue with each button event
+static mut HANDLERS: heapless::Vec<fn(ButtonEvent), MaxHandlers> = heapless::Vec(heapless::i::Vec::new());
+
+///  Button input, set by `start()`
+static mut BUTTON: Option<Interrupt> = None;
+
+///  Timer that reads the level after the bouncing
+static DEBOUNCE_TIMER: Callout<fn()> = Callout::new(debounce);
+
+///  Timer that reports a click when there is no second click
+static CLICK_TIMER: Callout<fn()> = Callout::new(report_click);
+
+///  Time that the button was pressed, `None` while released
+static mut PRESSED_AT: Option<Instant> = None;
+
+///  True if a click is waiting for a possible second click
+static mut CLICK_PENDING: bool = false;
+
+///  Enable the button and report its presses. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    //  Power the button and interrupt on press and release.
+    let mut button = pinetime::button(handle_edge) ? ;
+    button.enable();
+    unsafe { BUTTON = Some(button) };
+    Ok(())
+}
+
+///  Call `handler` on the default event queue with each button event. Returns `SYS_ENOMEM` if there are more than
+///  `MAX_HANDLERS` handlers. Must be called before the button is pressed, e.g. in `main()`.
+pub fn register(handler: fn(ButtonEvent)) -> MynewtResult<()> {
+    unsafe { HANDLERS.push(handler) }.map_err(|_| MynewtError::SYS_ENOMEM)
+}
+
+///  Return true if the button is pressed, after debouncing
+pub fn is_pressed() -> bool {
+    unsafe { PRESSED_AT.is_some() }
+}
+
+///  Restart the debounce timer when the button level changes, including bounces. Called by the default event queue.
+fn handle_edge(_event: PinEvent) {
+    DEBOUNCE_TIMER.reset(DEBOUNCE_TIME).expect("button timer fail");
+}
+
+///  Handle the press or release after the bouncing has stopped
+fn debounce() {
+    let pressed = match unsafe { &BUTTON } {
+        Some(button) => button.level() == Level::High,
+        None => return,
+    };
+    let now = Instant::now();
+    match (pressed, unsafe { PRESSED_AT }) {
+        (true, None) => unsafe { PRESSED_AT = Some(now) },
+        (false, Some(pressed_at)) => {
+            unsafe { PRESSED_AT = None };
+            handle_release(now - pressed_at);
+        }
+        _ => {}  //  Bounce without a change of level
+    }
+}
+
+///  Report a long press or a double click, or wait for the second click
+fn handle_release(held: Duration) {
+    if held >= LONG_PRESS_TIME {
+        //  Drop a pending click, it belongs to another gesture.
+        CLICK_TIMER.stop();
+        unsafe { CLICK_PENDING = false };
+        report(ButtonEvent::LongPress { held });
+    } else if unsafe { CLICK_PENDING } {
+        CLICK_TIMER.stop();
+        unsafe { CLICK_PENDING = false };
+        report(ButtonEvent::DoubleClick);
+    } else {
+        unsafe { CLICK_PENDING = true };
+        CLICK_TIMER.reset(DOUBLE_CLICK_TIME).expect("button timer fail");
+    }
+}
+
+///  Report the click, since there was no second click
+fn report_click() {
+    if !unsafe { CLICK_PENDING } { return; }
+    unsafe { CLICK_PENDING = false };
+    report(ButtonEvent::Click);
+}
+
+///  Switch on the display and pass the event to the UI and the handlers
+fn report(event: ButtonEvent) {
+    log::info!("button {:?}", event);
+    //  Ignore the error if the display can't be woken, the event is still reported.
+    power::wake(WakeReason::Button).ok();
+    BUTTON_EVENTS.post(event).ok();  //  Drop the event if the UI is not receiving events
+    for handler in unsafe { HANDLERS.iter() } {
+        handler(event);
+    }
+}
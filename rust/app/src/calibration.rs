@@ -0,0 +1,71 @@
+//!  Accelerometer flat calibration. With the watch lying flat and still, face up, the accelerometer should measure
+//!  0 on the X and Y axes and 1 g on the Z axis. The calibration averages the samples for 2 seconds and saves the
+//!  offsets that correct the difference, in the settings `app/accx_off`, `app/accy_off` and `app/accz_off`.
+//!  The pedometer applies the offsets to every sample. Started by the shell command `calibrate` or the newtmgr / SMP
+//!  command in `calibration_mgmt.c`, over the serial port or Bluetooth LE.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hw::sensor::calibration::UNIT_SCALE,
+    kernel::{
+        device::Device,
+        time,
+    },
+    sys::console,
+};
+use crate::{ pedometer, settings };
+
+///  Acceleration of 1 g in raw units, at the ±2g range of the accelerometer
+const ONE_G: i32 = 1024;
+
+///  Number of samples to be averaged: 2 seconds at 25 Hz
+const CALIBRATION_SAMPLES: i32 = 50;
+
+///  Interval between samples, in milliseconds
+const SAMPLE_INTERVAL_MS: u32 = 40;
+
+///  Max difference from the expected acceleration on each axis, in raw units. The calibration fails if the watch is
+///  not lying flat, since the offsets would be wrong.
+const MAX_DEVIATION: i32 = 200;
+
+///  Calibrate the accelerometer with the watch lying flat and save the offsets `(x, y, z)`.
+///  Returns `SYS_EINVAL` if the watch is not flat, `SYS_ENODEV` if the accelerometer is not enabled.
+pub fn calibrate_accel() -> MynewtResult<(i32, i32, i32)> {
+    console::print("Rust calibrate accel\n");
+    let dev = Device::open(&pedometer::ACCEL_DEVICE, Duration::from_secs(1)) ? ;
+
+    //  Average the uncalibrated samples.
+    let (mut sum_x, mut sum_y, mut sum_z) = (0, 0, 0);
+    for _ in 0..CALIBRATION_SAMPLES {
+        let (x, y, z) = pedometer::read_accel(&dev) ? ;
+        sum_x += x; sum_y += y; sum_z += z;
+        time::sleep_ms(SAMPLE_INTERVAL_MS);
+    }
+    let (x, y, z) = (sum_x / CALIBRATION_SAMPLES, sum_y / CALIBRATION_SAMPLES, sum_z / CALIBRATION_SAMPLES);
+    if x.abs() > MAX_DEVIATION || y.abs() > MAX_DEVIATION || (z - ONE_G).abs() > MAX_DEVIATION {
+        return Err(MynewtError::SYS_EINVAL);  //  Not flat
+    }
+
+    //  Save the offsets that correct the averages.
+    let offsets = (-x, -y, ONE_G - z);
+    settings::ACCEL_X_CALIBRATION.set(offsets.0, UNIT_SCALE) ? ;
+    settings::ACCEL_Y_CALIBRATION.set(offsets.1, UNIT_SCALE) ? ;
+    settings::ACCEL_Z_CALIBRATION.set(offsets.2, UNIT_SCALE) ? ;
+    Ok(offsets)
+}
+
+///  Calibrate the accelerometer and return the offsets in `offsets[0..3]`. Returns 0 if successful, else a Mynewt
+///  error code. Called by the `calibrate` shell command and the SMP command in `calibration_mgmt.c`.
+#[no_mangle]
+extern "C" fn calibration_run_accel(offsets: *mut i32) -> i32 {
+    match calibrate_accel() {
+        Ok((x, y, z)) => {
+            if !offsets.is_null() {
+                unsafe { *offsets = x; *offsets.add(1) = y; *offsets.add(2) = z; }
+            }
+            0
+        }
+        Err(err) => err.into(),
+    }
+}
@@ -0,0 +1,123 @@
+//!  Charging detection. The charger drives two open-drain pins: POWER PRESENCE INDICATION (P0.19) is low while the
+//!  charger supplies power, e.g. on the cradle, and CHARGE INDICATION (P0.12) is low while the battery is charging.
+//!  The pins interrupt on both edges, and they bounce when the watch is placed on the cradle, so they are read again
+//!  after `DEBOUNCE_TIME` on the default event queue. Each change of the state is posted to `CHARGE_EVENTS` for the
+//!  UI and shown on the screen. While the charger supplies power, the display is not switched off by the wrist drop,
+//!  a power-failure warning is cleared, and the watch resumes after a low-battery shutdown.
+
+use core::time::Duration;
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use mynewt::{
+    result::*,
+    board::pinetime,
+    hal::{ gpio::{ Interrupt, Level, PinEvent }, pof },
+    kernel::{ event::EventQueue, timer::Callout },
+};
+use crate::power::{ self, WakeReason };
+
+///  Read the pins again after they have stopped bouncing
+const DEBOUNCE_TIME: Duration = Duration::from_millis(200);
+
+///  Row of the screen for showing the charge state
+const STATE_ROW: i32 = 220;
+
+///  Charge state of the battery
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChargeEvent {
+    ///  Charger supplies power and the battery is charging
+    Charging,
+    ///  Charger supplies power and the battery is full
+    Full,
+    ///  No charger, the watch runs on the battery
+    Discharging,
+}
+
+///  Charge events for the UI. Call `CHARGE_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static CHARGE_EVENTS: EventQueue<ChargeEvent> = EventQueue::new();
+
+///  Charge and power presence indicators, set by `start()`
+static mut CHARGE_PIN: Option<Interrupt> = None;
+static mut POWER_PIN: Option<Interrupt> = None;
+
+///  Timer that reads the pins after the bouncing
+static DEBOUNCE_TIMER: Callout<fn()> = Callout::new(debounce);
+
+///  Last charge state, `None` before the pins have been read
+static mut STATE: Option<ChargeEvent> = None;
+
+///  Watch the charge and power presence indicators, and report the state at startup. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    let mut charge = pinetime::charge_interrupt(handle_edge) ? ;
+    let mut power = pinetime::power_present_interrupt(handle_edge) ? ;
+    charge.enable();
+    power.enable();
+    unsafe {
+        CHARGE_PIN = Some(charge);
+        POWER_PIN = Some(power);
+    }
+    debounce();
+    Ok(())
+}
+
+///  Return the charge state, or `None` if the pins have not been read yet
+pub fn state() -> Option<ChargeEvent> {
+    unsafe { STATE }
+}
+
+///  Restart the debounce timer when either pin changes, including bounces. Called by the default event queue.
+fn handle_edge(_event: PinEvent) {
+    DEBOUNCE_TIMER.reset(DEBOUNCE_TIME).expect("charger timer fail");
+}
+
+///  Read the pins after the bouncing and report the change of state
+fn debounce() {
+    let (charging, powered) = match unsafe { (&CHARGE_PIN, &POWER_PIN) } {
+        (Some(charge), Some(power)) => (charge.level() == Level::Low, power.level() == Level::Low),
+        _ => return,
+    };
+    let event = match (powered, charging) {
+        (true, true)  => ChargeEvent::Charging,
+        (true, false) => ChargeEvent::Full,
+        //  The charge indicator may stay low for a moment after the charger is removed.
+        (false, _)    => ChargeEvent::Discharging,
+    };
+    let previous = unsafe { STATE };
+    if previous == Some(event) { return; }
+    unsafe { STATE = Some(event) };
+    report(event, previous);
+}
+
+///  Pause the automatic sleep while powered, show the state and notify the UI
+fn report(event: ChargeEvent, previous: Option<ChargeEvent>) {
+    log::info!("charger {:?}", event);
+    let powered = event != ChargeEvent::Discharging;
+    power::hold_awake(powered);
+    if powered {
+        pof::clear_warning();  //  The supply voltage has recovered
+        power::manager::resume();
+    }
+    //  Wake the display when the charger is connected or removed, not when the battery becomes full.
+    let plugged = previous.map(|previous| (previous != ChargeEvent::Discharging) != powered).unwrap_or(false);
+    if plugged { power::wake(WakeReason::Charger).ok(); }  //  Ignore the error, the state is also logged
+    show_state(event);
+    CHARGE_EVENTS.post(event).ok();  //  Drop the event if the UI is not receiving events
+}
+
+///  Show the charge state at the bottom of the screen
+fn show_state(event: ChargeEvent) {
+    let label = match event {
+        ChargeEvent::Charging    => " Charging     ",
+        ChargeEvent::Full        => " Charged      ",
+        ChargeEvent::Discharging => "              ",  //  Erase the state
+    };
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(label)                                    //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0xff, 0xff )) ) )  //  White text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, STATE_ROW ));              //  Shift the text to the row
+    druid::draw_to_display(text);
+}
@@ -1,4 +1,5 @@
 //  CHIP8 Emulator App. Need to edit apps/my_sensor_app/syscfg.yml and reduce the main stack size (OS_MAIN_STACK_SIZE) to 2048.
+use core::time::Duration;
 use embedded_graphics::{
     prelude::*,
     pixelcolor::Rgb565,
@@ -9,8 +10,9 @@ use embedded_graphics::{
 use mynewt::{
     result::*,
     sys::console,
-    kernel::os,
-    NULL, Ptr, Strn, fill_zero,
+    hal::rng::{ Rng, RngCore },
+    kernel::{ self, os, task, supervisor::{ self, Supervised } },
+    Strn,
 };
 use mynewt_macros::{
     init_strn,
@@ -50,35 +52,31 @@ pub fn on_start() -> MynewtResult<()> {
     render_region(0, 0, SCREEN_WIDTH as u8 - 1, SCREEN_HEIGHT as u8 - 1);
 
     //  Start the emulator in a background task
-    os::task_init(                  //  Create a new task and start it...
-        unsafe { &mut CHIP8_TASK }, //  Task object will be saved here
-        &init_strn!( "chip8" ),     //  Name of task
-        Some( task_func ),    //  Function to execute when task starts
-        NULL,  //  Argument to be passed to above function
+    task::spawn_with_stack(
+        &init_strn!( "chip8" ),   //  Name of task
         20,    //  Task priority: highest is 0, lowest is 255 (main task is 127), SPI is 10
-        os::OS_WAIT_FOREVER as u32,       //  Don't do sanity / watchdog checking
-        unsafe { &mut CHIP8_TASK_STACK }, //  Stack space for the task
-        CHIP8_TASK_STACK_SIZE as u16      //  Size of the stack (in 4-byte units)
-    ) ? ;                                 //  `?` means check for error
+        unsafe { &mut CHIP8_TASK_STACK },  //  Stack space for the task
+        task_func                 //  Function to execute when task starts
+    ) ? ;                         //  `?` means check for error
 
     //  Return success to the caller
     Ok(())
 }
 
-/// CHIP8 Background Task
-static mut CHIP8_TASK: os::os_task = fill_zero!(os::os_task);
-
-/// Stack space for CHIP8 Task, initialised to 0.
-static mut CHIP8_TASK_STACK: [os::os_stack_t; CHIP8_TASK_STACK_SIZE] = 
-    [0; CHIP8_TASK_STACK_SIZE];
-
 /// Size of the stack (in 4-byte units). Previously `OS_STACK_ALIGN(256)`  
 const CHIP8_TASK_STACK_SIZE: usize = 4096;  //  Must be 4096 and above because CHIP8 Emulator requires substantial stack space
 
+/// Stack space for the task, only allocated when the CHIP8 Emulator app is enabled
+static mut CHIP8_TASK_STACK: [os::os_stack_t; CHIP8_TASK_STACK_SIZE] = [0; CHIP8_TASK_STACK_SIZE];
+
 ///  Run the emulator
-extern "C" fn task_func(_arg: Ptr) {    
+fn task_func() {    
+    //  Check in with the supervisor at every step, so that a hung emulator restarts the watch
+    let supervised = supervisor::register("chip8", Duration::from_secs(10))
+        .expect("supervisor fail");
+
     //  Create the hardware API for rendering the emulator
-    let hardware = Hardware::new();
+    let hardware = Hardware::new(supervised);
 
     //  Create the emulator
     let chip8 = libchip8::Chip8::new(hardware);
@@ -108,11 +106,13 @@ struct Hardware {
     is_interactive: bool,
     /// True if emulator is checking input, i.e. emulator has updated a sprite
     is_checking_input: bool,
+    /// Handle for checking in with the supervisor
+    supervised: Supervised,
 }
 
 impl Hardware {
     /// Return a new Hardware API for rendering CHIP8 Emulator
-    pub fn new() -> Hardware {
+    pub fn new(supervised: Supervised) -> Hardware {
         Hardware {
             update_left: 0,
             update_top: 0,
@@ -120,6 +120,7 @@ impl Hardware {
             update_bottom: 0,
             is_interactive: false,
             is_checking_input: false,
+            supervised,
         }
     }
 }
@@ -127,7 +128,7 @@ impl Hardware {
 impl libchip8::Hardware for Hardware {
     /// Return a random value.
     fn rand(&mut self) -> u8 {
-        123  //  TODO
+        Rng::new().next_u32() as u8
     }
 
     /// Check if the key is pressed.
@@ -212,6 +213,9 @@ impl libchip8::Hardware for Hardware {
     fn sched(&mut self) -> bool {
         //  console::print("sched\n"); console::flush(); ////
 
+        //  Tell the supervisor that the emulator is alive
+        self.supervised.checkin();
+
         //  If no screen update, return
         if self.update_left == 0 && self.update_right == 0 &&
             self.update_top == 0 && self.update_bottom == 0 { return false; }
@@ -219,15 +223,12 @@ impl libchip8::Hardware for Hardware {
         //  If emulator is preparing the initial screen, refresh the screen later
         if !self.is_interactive { return false; }
 
-        //  Tickle the watchdog so that the Watchdog Timer doesn't expire. Mynewt assumes the process is hung if we don't tickle the watchdog.
-        unsafe { hal_watchdog_tickle() };
-
         //  If emulator is not ready to accept input, refresh the screen later
         if !self.is_checking_input { return false; }
         self.is_checking_input = false;
 
-        //  Sleep a while to allow other tasks to run, e.g. SPI background task
-        unsafe { os::os_time_delay(1) };
+        //  Allow lower priority tasks to run, e.g. the main task
+        kernel::yield_now();
 
         //  Render the updated region
         render_region(
@@ -503,12 +504,6 @@ pub fn handle_touch(x: u16, _y: u16) {
 /// Represents the key pressed: 0-9 for keys "0" to "9", 0xa-0xf to keys "A" to "F", None for nothing pressed
 static mut KEY_PRESSED: Option<u8> = None;
 
-//  TODO: Move this to Mynewt library
-extern "C" { 
-    /// Tickles the watchdog so that the Watchdog Timer doesn't expire. This needs to be done periodically, before the value configured in hal_watchdog_init() expires.
-    fn hal_watchdog_tickle(); 
-}
-
 /// Return Bounding Box of Physical Pixels (left, top, right, bottom) that correspond to the Virtual Pixels
 #[cfg(not(feature = "chip8_curve"))]  //  If we are not rendering CHIP8 Emulator as curved surface...
 fn get_bounding_box(virtual_left: u8, virtual_top: u8, virtual_right: u8, virtual_bottom: u8) -> (u8, u8, u8, u8) {
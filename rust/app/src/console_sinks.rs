@@ -0,0 +1,97 @@
+//!  Select the console sink that receives the console output, so that the same firmware can be debugged on the bench
+//!  and on a sealed watch. The sinks are Semihosting and RTT for a debug probe, the UART registered by
+//!  `uart_shell.rs`, and the Bluetooth LE console `nus` in `ble_nus.c` for a paired phone. The sink is selected at
+//!  startup from `settings::CONSOLE`, e.g. `newtmgr config app/console nus` then `newtmgr config save`, and may be
+//!  switched until the next restart with the shell command `console use <name>` in
+//!  `apps/my_sensor_app/src/console_shell.c`. `console list` shows the sinks.
+
+use core::fmt::Write;
+use mynewt::{
+    result::*,
+    sys::{
+        console,
+        console_sink::{ self, ConsoleSink },
+    },
+    Strn,
+};
+use crate::settings;
+
+///  Bluetooth LE console, which notifies the output to a paired phone subscribed to the Nordic UART Service
+pub static NUS_SINK: NusSink = NusSink;
+
+///  Console sink for the Bluetooth LE console. The output is dropped while no phone is subscribed.
+pub struct NusSink;
+
+impl ConsoleSink for NusSink {
+    fn name(&self) -> &'static str { "nus" }
+
+    fn write(&self, bytes: &[u8]) {
+        unsafe { nus_console_write(bytes.as_ptr(), bytes.len() as u32) };
+    }
+
+    fn attach(&self) -> MynewtResult<()> {
+        if unsafe { nus_console_is_enabled() } == 0 { return Err(MynewtError::SYS_ENOTSUP); }
+        Ok(())
+    }
+}
+
+///  Register the sinks and select the sink in `settings::CONSOLE`. If the setting is empty or the sink can't be
+///  selected, select the UART if the UART shell has registered it, or Semihosting otherwise. Called by main() in
+///  `lib.rs` after `uart_shell::start()`.
+pub fn start() -> MynewtResult<()> {
+    console_sink::register(&console_sink::SEMIHOSTING_SINK) ? ;
+    console_sink::register(&console_sink::RTT_SINK) ? ;
+    console_sink::register(&NUS_SINK) ? ;
+    let name = settings::CONSOLE.get();
+    if !name.is_empty() {
+        match console_sink::select(&name) {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                //  Fall back to the default sink, so that the output is not lost.
+                let mut line = heapless::String::<heapless::consts::U64>::new();
+                write!(line, "console {} fail {:?}\n", name.as_str(), err).ok();  //  Truncated if too long
+                console::print(&line);
+            }
+        }
+    }
+    let uart = console_sink::sinks().any(|sink| sink.name() == "uart");
+    console_sink::select(if uart { "uart" } else { "semihosting" })
+}
+
+///  Shell command `console list`: Print the names of the sinks, marking the selected sink with `*`
+#[no_mangle]
+extern "C" fn console_shell_list() -> i32 {
+    let selected = console_sink::selected();
+    for sink in console_sink::sinks() {
+        console::print(if selected == Some(sink.name()) { "* " } else { "  " });
+        console::print(sink.name());
+        console::print("\n");
+    }
+    console::flush();
+    0
+}
+
+///  Shell command `console use <name>`: Send the console output to the sink named `name` until the next restart.
+///  Returns `SYS_ENOENT` if there is no such sink, or `SYS_ENOTSUP` if the sink is disabled in `syscfg.yml`.
+#[no_mangle]
+extern "C" fn console_shell_use(name: *const u8) -> i32 {
+    if name.is_null() { return MynewtError::SYS_EINVAL.into(); }
+    let name = Strn::from_cstr(name);
+    let bytes = unsafe { core::slice::from_raw_parts(name.as_ptr(), name.len()) };
+    let result = core::str::from_utf8(bytes)
+        .map_err(|_| MynewtError::SYS_EINVAL)
+        .and_then(console_sink::select);
+    match result {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
+
+extern "C" {
+    ///  Notify the console output to the subscribed phone.
+    ///  C API: `void nus_console_write(const char *buffer, unsigned int length)` in `apps/my_sensor_app/src/ble_nus.c`
+    fn nus_console_write(buffer: *const u8, length: u32);
+    ///  Return 1 if the Bluetooth LE console is enabled in `syscfg.yml`.
+    ///  C API: `int nus_console_is_enabled(void)` in `apps/my_sensor_app/src/ble_nus.c`
+    fn nus_console_is_enabled() -> i32;
+}
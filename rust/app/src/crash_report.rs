@@ -0,0 +1,57 @@
+//!  Upload the crash dumps written by `mynewt::sys::crash_dump` to the CoAP server after restarting, so that panics,
+//!  faults, stack overflows and hung tasks in the field are reported without a debugger. The network is not ready
+//!  right after startup, so the dumps are uploaded by a timer, which tries again every `RETRY_PERIOD` until all
+//!  dumps are uploaded, then erases them. Each dump is marked as uploaded when sent, so that a restart in the middle
+//!  of the upload doesn't send it twice. Each dump is sent in its own CoAP message of at most
+//!  `CRASH_DUMP_PAYLOAD_SIZE` bytes, which `app_network.rs` checks at compile time against the max CoAP payload,
+//!  since the CoAP client doesn't send blockwise.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    kernel::timer::Callout,
+    sys::crash_dump,
+};
+use crate::{
+    app_network,
+    mcuboot::{ self, Slot },
+};
+
+///  Delay after startup before the first upload, for the network to be ready
+const FIRST_UPLOAD_DELAY: Duration = Duration::from_secs(60);
+
+///  Interval between uploads while some dumps have not been uploaded
+const RETRY_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+///  Timer that uploads the dumps
+static UPLOAD_TIMER: Callout<fn()> = Callout::new(upload_timer);
+
+///  Start writing crash dumps with the version of the running firmware, and start uploading the dumps written
+///  before the restart, if any. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    let version = match mcuboot::read_image_info(Slot::Active) ? {
+        Some(info) => info.header.ih_ver.packed(),
+        None       => 0,
+    };
+    crash_dump::start(version);
+    if crash_dump::pending() ? == 0 { return Ok(()); }
+    UPLOAD_TIMER.reset(FIRST_UPLOAD_DELAY)
+}
+
+///  Send the dumps that have not been uploaded to the CoAP server, then erase all dumps
+pub fn upload() -> MynewtResult<()> {
+    crash_dump::for_each(|slot, dump| {
+        if dump.is_uploaded() { return Ok(()); }
+        app_network::send_crash_dump(dump) ? ;
+        crash_dump::mark_uploaded(slot)
+    }) ? ;
+    crash_dump::clear()
+}
+
+///  Upload the dumps, and try again after the period if the upload failed. Called by the default event queue.
+fn upload_timer() {
+    if let Err(err) = upload() {
+        log::warn!("crash dump post fail {:?}", err);
+        UPLOAD_TIMER.reset(RETRY_PERIOD).expect("crash timer fail");
+    }
+}
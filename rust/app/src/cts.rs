@@ -0,0 +1,52 @@
+//!  Set the wall clock from the phone. When a bonded phone connects, the Current Time Service client in
+//!  `apps/my_sensor_app/src/ble_cts_client.c` reads the Current Time and the Local Time Information from the phone
+//!  and calls `cts_set_time()`. The wall clock then timestamps the sensor readings sent to the CoAP server,
+//!  without a network time source.
+
+use core::slice;
+use mynewt::{
+    result::*,
+    kernel::{ schedule, time::{ wallclock, DateTime } },
+};
+
+///  Min length of the Current Time value: year, month, day, hours, minutes, seconds
+const CURRENT_TIME_LEN: usize = 7;
+
+///  Set the wall clock from the Current Time value `current_time` of length `len`, which holds the local time of
+///  the phone, `minutes_east` minutes east of UTC. Returns 0 if successful, `SYS_EINVAL` if the time is unknown.
+///  Called by `ble_cts_client.c` from the NimBLE host task.
+#[no_mangle]
+extern "C" fn cts_set_time(current_time: *const u8, len: u16, minutes_east: i16) -> i32 {
+    if current_time.is_null() { return MynewtError::SYS_EINVAL.into(); }
+    let current_time = unsafe { slice::from_raw_parts(current_time, len as usize) };
+    match set_time(current_time, minutes_east) {
+        Ok(())   => 0,
+        Err(err) => { log::warn!("cts set time failed {:?}", err); err.into() }
+    }
+}
+
+///  Set the wall clock from the Current Time value, for the local time `minutes_east` minutes east of UTC
+fn set_time(current_time: &[u8], minutes_east: i16) -> MynewtResult<()> {
+    let local = parse(current_time).ok_or(MynewtError::SYS_EINVAL) ? ;
+    wallclock::set_local(&local, minutes_east) ? ;
+    schedule::clock_changed();  //  Run the alarms and nightly jobs at the new time
+    log::info!("cts time {}-{:02}-{:02} {:02}:{:02}:{:02}",
+        local.year, local.month, local.day, local.hour, local.minute, local.second);
+    Ok(())
+}
+
+///  Return the date and time in the Current Time value: year as little endian `u16`, then month, day, hours,
+///  minutes and seconds. Returns `None` if the value is too short or the date is unknown (year or month 0).
+fn parse(current_time: &[u8]) -> Option<DateTime> {
+    if current_time.len() < CURRENT_TIME_LEN { return None; }
+    let year = u16::from_le_bytes([current_time[0], current_time[1]]);
+    if year == 0 || current_time[2] == 0 { return None; }
+    Some(DateTime {
+        year,
+        month:  current_time[2],
+        day:    current_time[3],
+        hour:   current_time[4],
+        minute: current_time[5],
+        second: current_time[6],
+    })
+}
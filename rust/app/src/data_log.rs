@@ -0,0 +1,182 @@
+//!  Sensor and event logs that survive restarts, for looking back at the readings and alerts after the battery ran
+//!  out or the watch restarted. Each sensor reading is appended as a `SensorRecord` to the flash circular buffer in
+//!  the region `map::SENSOR_LOG`, and each alert and low-battery warning is appended as text to the buffer in the
+//!  region `map::EVENT_LOG`. When a buffer is full, the oldest sector of records is erased, so the logs keep the
+//!  newest readings and events: about 3,000 readings, or a day at the default poll interval. The shell command
+//!  `datalog` in `apps/my_sensor_app/src/data_log_shell.c` shows or clears the logs.
+
+use core::fmt::Write;
+use mynewt::{
+    result::*,
+    hw::{
+        flash::{ fcb::FlashLog, map },
+        sensor::{ AlertEvent, SensorValueType },
+    },
+    kernel::{ os, time },
+    sys::console,
+    Strn,
+};
+use crate::power::low_battery::BatteryEvent;
+
+///  Size of a sector of the External SPI Flash, the unit of erasing
+const SECTOR_SIZE: u32 = 4096;
+
+///  Max number of bytes of the sensor key that will be recorded
+const KEY_SIZE: usize = 8;
+
+///  Size of a sensor record in flash
+const RECORD_SIZE: usize = core::mem::size_of::<SensorRecord>();
+
+///  Size of the times at the start of an event record
+const EVENT_HEADER_SIZE: usize = 8;
+
+///  Number of records shown by `datalog readings` and `datalog events` without a count
+const DEFAULT_SHOW_COUNT: usize = 20;
+
+///  Type of the text of an event
+type EventText = heapless::String<heapless::consts::U64>;
+
+///  Sensor readings
+static SENSOR_LOG: FlashLog = FlashLog::new(map::SENSOR_LOG, SECTOR_SIZE);
+
+///  Alerts and warnings
+static EVENT_LOG: FlashLog = FlashLog::new(map::EVENT_LOG, SECTOR_SIZE);
+
+///  Sensor reading in the sensor log
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct SensorRecord {
+    ///  Unix time of the reading, 0 if the wall clock was not set
+    time:   u32,
+    ///  Seconds since startup
+    uptime: u32,
+    ///  Integer value of the reading
+    value:  i32,
+    ///  Key (field name) of the sensor, e.g. `hr`, padded with nulls
+    key:    [u8; KEY_SIZE],
+}
+
+///  Find the newest records in the logs. Must be called before the sensors start. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    SENSOR_LOG.init() ? ;
+    EVENT_LOG.init()
+}
+
+///  Append the reading `value` of the sensor with key `key` to the sensor log. Only integer readings are logged.
+///  Called for each reading by `app_sensor.rs`.
+pub fn record_reading(key: &Strn, value: &SensorValueType) {
+    let value = match *value {
+        SensorValueType::Int(value)  => value,
+        SensorValueType::Uint(value) => value as i32,
+        _ => return,
+    };
+    let mut record = SensorRecord {
+        time:   time::wall_clock().unwrap_or(0) as u32,
+        uptime: uptime(),
+        value,
+        key:    [0; KEY_SIZE],
+    };
+    let key = unsafe { core::slice::from_raw_parts(key.as_ptr(), key.len()) };
+    let len = core::cmp::min(key.len(), KEY_SIZE);
+    record.key[..len].copy_from_slice(&key[..len]);
+    if let Err(err) = SENSOR_LOG.append(as_bytes(&record)) { log::warn!("sensor log fail {:?}", err); }
+}
+
+///  Append the alert to the event log. Called by `alerts.rs`.
+pub fn record_alert(event: &AlertEvent) {
+    let mut text = EventText::new();
+    let key = unsafe { core::slice::from_raw_parts(event.key.as_ptr(), event.key.len()) };
+    write!(text, "alert {} {:?} {}", core::str::from_utf8(key).unwrap_or("?"), event.threshold, event.value).ok();
+    record_event(&text);
+}
+
+///  Append the low-battery warning to the event log. Called by `power/low_battery.rs`.
+pub fn record_battery_warning(event: &BatteryEvent) {
+    let mut text = EventText::new();
+    write!(text, "battery {} {}%", event.warning.name(), event.percent).ok();
+    record_event(&text);
+}
+
+///  Append the event `text` with the current time to the event log. The text is truncated if too long.
+fn record_event(text: &str) {
+    let mut record = heapless::Vec::<u8, heapless::consts::U72>::new();
+    let time = time::wall_clock().unwrap_or(0) as u32;
+    record.extend_from_slice(&time.to_le_bytes()).ok();
+    record.extend_from_slice(&uptime().to_le_bytes()).ok();
+    record.extend_from_slice(text.as_bytes()).ok();  //  Truncated if too long
+    if let Err(err) = EVENT_LOG.append(&record) { log::warn!("event log fail {:?}", err); }
+}
+
+///  Return the seconds since startup
+fn uptime() -> u32 {
+    unsafe { os::os_time_get() } / time::TICKS_PER_SEC
+}
+
+///  Shell command `datalog readings [count]`: Print the newest `count` readings, oldest first
+#[no_mangle]
+extern "C" fn data_log_shell_readings(count: u32) -> i32 {
+    console::print("      time   uptime key        value\n");
+    let result = for_each_newest(&SENSOR_LOG, count, |bytes| {
+        if bytes.len() != RECORD_SIZE { return; }  //  Not a sensor record
+        let record = unsafe { core::ptr::read_unaligned(bytes.as_ptr() as *const SensorRecord) };
+        let len = record.key.iter().position(|&b| b == 0).unwrap_or(KEY_SIZE);
+        let mut line = heapless::String::<heapless::consts::U64>::new();
+        write!(line, "{:10} {:8} {:8} {:8}\n", record.time, record.uptime,
+            core::str::from_utf8(&record.key[..len]).unwrap_or("?"), record.value).ok();
+        console::print(&line);
+    });
+    console::flush();
+    to_rc(result)
+}
+
+///  Shell command `datalog events [count]`: Print the newest `count` events, oldest first
+#[no_mangle]
+extern "C" fn data_log_shell_events(count: u32) -> i32 {
+    console::print("      time   uptime event\n");
+    let result = for_each_newest(&EVENT_LOG, count, |bytes| {
+        if bytes.len() < EVENT_HEADER_SIZE { return; }  //  Not an event record
+        let word = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let mut line = heapless::String::<heapless::consts::U96>::new();
+        write!(line, "{:10} {:8} {}\n", word(0), word(4),
+            core::str::from_utf8(&bytes[EVENT_HEADER_SIZE..]).unwrap_or("?")).ok();
+        console::print(&line);
+    });
+    console::flush();
+    to_rc(result)
+}
+
+///  Shell command `datalog clear`: Erase the sensor and event logs
+#[no_mangle]
+extern "C" fn data_log_shell_clear() -> i32 {
+    to_rc(SENSOR_LOG.clear().and_then(|_| EVENT_LOG.clear()))
+}
+
+///  Call `f` with the newest `count` records in the log, oldest first, or `DEFAULT_SHOW_COUNT` if `count` is 0
+fn for_each_newest<F: FnMut(&[u8])>(flash_log: &'static FlashLog, count: u32, mut f: F) -> MynewtResult<()> {
+    let count = if count == 0 { DEFAULT_SHOW_COUNT } else { count as usize };
+    let skip = flash_log.len() ? .saturating_sub(count);
+    let mut index = 0;
+    flash_log.for_each(|bytes| {
+        if index >= skip { f(bytes); }
+        index += 1;
+        Ok(())
+    })
+}
+
+///  Convert the result to a shell return code
+fn to_rc(result: MynewtResult<()>) -> i32 {
+    match result {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
+
+///  Return the record as bytes for writing to SPI Flash
+fn as_bytes(record: &SensorRecord) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            record as *const SensorRecord as *const u8,
+            RECORD_SIZE
+        )
+    }
+}
@@ -0,0 +1,83 @@
+//!  On-target tests for hardware-in-the-loop regression runs, registered with `#[device_test]` and run in the task
+//!  `test` by the shell command `test run [filter]`, see `mynewt::sys::device_test`. Compiled only with the feature
+//!  `device_test`. The tests run while the app is running, so they must not disturb the app, e.g. by locking the
+//!  display or writing to the regions in use.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hw::flash::map::{ self, Storage },
+    kernel::{
+        sync::Semaphore,
+        task,
+        time::{ self, Instant },
+        timer::Callout,
+    },
+    sys::device_test::ensure,
+    util::crc,
+};
+use mynewt_macros::device_test;
+
+///  Given by `TEST_TIMER` when it fires
+static TIMER_FIRED: Semaphore = Semaphore::new(0);
+
+///  Timer for `timer_fires`
+static TEST_TIMER: Callout<fn()> = Callout::new(handle_test_timer);
+
+///  CRC32 matches the standard check value, with the lookup table if `crc_table` is enabled
+#[device_test]
+fn crc32_check() -> MynewtResult<()> {
+    ensure(crc::crc32(b"123456789") == 0xcbf4_3926, MynewtError::SYS_EUNKNOWN)
+}
+
+///  The OS clock advances while the task sleeps
+#[device_test]
+fn sleep_advances_clock() -> MynewtResult<()> {
+    let start = Instant::now();
+    time::sleep(Duration::from_millis(100));
+    let elapsed = start.elapsed();
+    ensure(elapsed >= Duration::from_millis(100) && elapsed < Duration::from_millis(150), MynewtError::SYS_ERANGE)
+}
+
+///  A timer on the default event queue fires after its timeout
+#[device_test]
+fn timer_fires() -> MynewtResult<()> {
+    while TIMER_FIRED.try_take().is_ok() {}  //  Drop the tokens of earlier runs
+    TEST_TIMER.reset(Duration::from_millis(50)) ? ;
+    TIMER_FIRED.take(Duration::from_secs(1)) ? ;  //  `SYS_ETIMEOUT` if the timer didn't fire
+    Ok(())
+}
+
+///  A pattern written to the Internal Flash is read back. Uses the MCUBoot scratch region, which is only used while
+///  swapping images in the bootloader.
+#[device_test]
+fn flash_round_trip() -> MynewtResult<()> {
+    const SIZE: usize = 64;
+    let region = map::SCRATCH;
+    let mut pattern = [0u8; SIZE];
+    for (i, b) in pattern.iter_mut().enumerate() { *b = (i as u8).wrapping_mul(37) ^ 0x5a; }
+    region.erase(0, region.size) ? ;
+    region.write(0, &pattern) ? ;
+    let mut read = [0u8; SIZE];
+    region.read(0, &mut read) ? ;
+    region.erase(0, region.size) ? ;
+    ensure(read == pattern, MynewtError::SYS_EIO)
+}
+
+///  The tasks with stacks from the stack pool, `spi` and `pedometer`, leave a quarter of their stacks unused at the
+///  high-water mark
+#[device_test]
+fn spawned_stacks_have_headroom() -> MynewtResult<()> {
+    for stats in task::stats().iter().filter(|stats| stats.name() == "spi" || stats.name() == "pedometer") {
+        if stats.stack_percent() >= 75 {
+            stats.show();
+            return Err(MynewtError::SYS_ENOMEM);
+        }
+    }
+    Ok(())
+}
+
+///  Called by the default event queue when `TEST_TIMER` fires
+fn handle_test_timer() {
+    TIMER_FIRED.give().expect("test timer fail");
+}
@@ -0,0 +1,140 @@
+//!  Over-the-air firmware update with MCUBoot, for images uploaded over SMP by `apps/my_sensor_app/src/dfu.c`.
+//!  The upload progress is shown on the screen. When the upload is complete, the SHA256 hash of the image in
+//!  the Standby slot is verified, the image is marked pending and the watch restarts so that MCUBoot swaps it in
+//!  for a test boot. After the new firmware has run for `CONFIRM_DELAY`, the image is confirmed. If the firmware
+//!  crashes or hangs before that, the watchdog restarts the watch and MCUBoot reverts to the previous image.
+
+use core::time::Duration;
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use mynewt::{
+    result::*,
+    kernel::{
+        reboot::{ self, RebootReason },
+        timer::Callout,
+    },
+};
+use crate::{
+    mcuboot::{ self, Slot },
+    power::{ self, WakeReason },
+};
+
+///  Time that the new firmware must run before its image is confirmed
+const CONFIRM_DELAY: Duration = Duration::from_secs(60);
+
+///  Delay before restarting after the upload, so that the last SMP response reaches the phone
+const RESTART_DELAY: Duration = Duration::from_secs(3);
+
+///  Show the progress on the screen every 10 percent
+const PROGRESS_STEP: u32 = 10;
+
+///  Line of text on the screen
+type Line = heapless::String<heapless::consts::U20>;
+
+///  Timer that verifies the uploaded image outside the SMP handler
+static VERIFY_TIMER: Callout<fn()> = Callout::new(verify_and_restart);
+
+///  Timer that confirms the running image after it has run for `CONFIRM_DELAY`
+static CONFIRM_TIMER: Callout<fn()> = Callout::new(confirm);
+
+///  Percentage last shown on the screen, or `None` if no upload is in progress
+static mut SHOWN_PERCENT: Option<u32> = None;
+
+///  Show the progress of firmware uploads, and confirm the running image if it is on a test boot.
+///  Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    match check(unsafe { dfu_start() }) {
+        Err(MynewtError::SYS_ENOTSUP) => return Ok(()),  //  Firmware update over SMP is disabled
+        result => result ? ,
+    }
+    if let Some(info) = mcuboot::read_image_info(Slot::Active) ? {
+        if !info.image_ok { CONFIRM_TIMER.reset(CONFIRM_DELAY) ? ; }
+    }
+    Ok(())
+}
+
+///  Show the upload progress: `received` of `size` bytes have been written to the Standby slot.
+///  Called by `dfu.c` from the SMP handler after each chunk.
+#[no_mangle]
+extern "C" fn dfu_progress(received: u32, size: u32) {
+    if size == 0 { return; }
+    let percent = (received as u64 * 100 / size as u64) as u32;
+    //  A new upload starts with no progress shown, or restarts below the progress shown.
+    let shown = unsafe { SHOWN_PERCENT };
+    let new_upload = match shown {
+        None        => true,
+        Some(shown) => percent < shown,
+    };
+    if new_upload {
+        //  Cancel any restart scheduled by an earlier upload.
+        reboot::cancel();
+        power::wake(WakeReason::Update).ok();  //  Ignore the error, the progress is also logged
+        log::info!("dfu upload {} bytes", size);
+        show_line(" Updating      ", 80);
+    }
+    if new_upload || received >= size || percent >= shown.unwrap_or(0) + PROGRESS_STEP {
+        let mut line = Line::new();
+        core::fmt::write(&mut line, format_args!(" {:3}%          ", percent)).ok();
+        show_line(&line, 110);
+        unsafe { SHOWN_PERCENT = Some(percent) };
+    }
+    if received >= size {
+        unsafe { SHOWN_PERCENT = None };
+        if let Err(err) = VERIFY_TIMER.reset(Duration::from_millis(0)) { log::warn!("dfu verify fail {:?}", err); }
+    }
+}
+
+///  Verify the uploaded image, mark it pending and restart. Called by `VERIFY_TIMER` in the default task.
+fn verify_and_restart() {
+    show_line(" Verifying     ", 80);
+    let result = check(unsafe { dfu_verify_standby() })
+        .and_then(|_| check(unsafe { dfu_set_pending() }))
+        .and_then(|_| reboot::after(RESTART_DELAY, RebootReason::FirmwareUpdate));
+    match result {
+        Ok(()) => show_line(" Restarting    ", 80),
+        Err(err) => {
+            log::warn!("dfu update fail {:?}", err);
+            show_line(" Update failed ", 80);
+        }
+    }
+}
+
+///  Confirm the running image, so that MCUBoot keeps it. Called by `CONFIRM_TIMER` in the default task.
+fn confirm() {
+    match check(unsafe { dfu_confirm() }) {
+        Ok(()) => {
+            log::info!("dfu image confirmed");
+            show_line(" Update OK     ", 80);
+        }
+        Err(err) => log::warn!("dfu confirm fail {:?}", err),
+    }
+}
+
+///  Render the line in white on black at row `y`
+fn show_line(text: &str, y: i32) {
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(text)                                     //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0xff, 0xff )) ) )  //  White text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, y ));                      //  Shift the text to the row
+    druid::draw_to_display(text);
+}
+
+///  Import the firmware update functions from `apps/my_sensor_app/src/dfu.c`
+extern "C" {
+    ///  Report the image upload progress to `dfu_progress()`. Returns `SYS_ENOTSUP` if SMP is disabled.
+    ///  C API: `int dfu_start(void)`
+    fn dfu_start() -> i32;
+    ///  Verify the SHA256 hash of the image in the Standby slot. Returns 0 if the hash matches.
+    ///  C API: `int dfu_verify_standby(void)`
+    fn dfu_verify_standby() -> i32;
+    ///  Mark the image in the Standby slot pending for a test swap at the next restart.
+    ///  C API: `int dfu_set_pending(void)`
+    fn dfu_set_pending() -> i32;
+    ///  Confirm the running image.
+    ///  C API: `int dfu_confirm(void)`
+    fn dfu_confirm() -> i32;
+}
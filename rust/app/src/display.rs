@@ -10,6 +10,7 @@ use embedded_graphics::{
 };
 use mynewt::{
     result::*,
+    board::pinetime::Backlight,
     sys::console,
 };
 use embedded_hal::{
@@ -64,10 +65,10 @@ fn test_backlight() -> MynewtResult<()> {
     //  Create 3 GPIOs for controlling backlight: Low, Mid and High brightness
     let mut backlights = [ mynewt::GPIO::new(), mynewt::GPIO::new(), mynewt::GPIO::new() ];
 
-    //  GPIO settings for the backlight: LCD_BACKLIGHT_{LOW,MID,HIGH} (P0.14, 22, 23)
-    backlights[0].init(14) ? ;  //  Low Backlight
-    backlights[1].init(22) ? ;  //  Mid Backlight
-    backlights[2].init(23) ? ;  //  High Backlight
+    //  GPIO settings for the backlight: LCD_BACKLIGHT_{LOW,MID,HIGH}
+    backlights[0].init(Backlight::Low.pin().number()) ? ;   //  Low Backlight
+    backlights[1].init(Backlight::Mid.pin().number()) ? ;   //  Mid Backlight
+    backlights[2].init(Backlight::High.pin().number()) ? ;  //  High Backlight
 
     //  Define pulse patterns from slow to fast: From Low (0) to Mid (1) to High (2) and back
     let slower_pulse = [0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1];  //  Slower pulse
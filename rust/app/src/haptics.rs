@@ -0,0 +1,150 @@
+//!  Haptic feedback: the vibration motor plays a short pattern for each UI event, e.g. a tap for a button press and
+//!  a double pulse for a completed logo flash. The pattern of each event is chosen in the settings, and all patterns
+//!  are silenced by `settings::HAPTIC_MUTE`, e.g. `newtmgr config app/hap_notify 3` for a long pulse on incoming
+//!  notifications, or `newtmgr config app/hap_mute 1` then `newtmgr config save`.
+//!  `play()` may be called from any task: the events are pushed to a channel and played one at a time on the default
+//!  event queue. A new event replaces the pattern that is playing, so the latest event is always felt.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    board::pinetime,
+    hal::gpio::Output,
+    kernel::{ channel::Channel, os, timer::Callout },
+    sys::{ config::Setting, power_profile::{ self, Subsystem } },
+};
+use crate::{ button::{ self, ButtonEvent }, settings };
+
+///  UI event that is felt through the vibration motor
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HapticEvent {
+    ///  Watch button or UI button pressed
+    Button,
+    ///  Logo flashed and verified
+    Flash,
+    ///  Sensor alert threshold crossed
+    Alert,
+    ///  Notification received from the phone
+    Notification,
+    ///  Battery charge level below a warning threshold
+    LowBattery,
+    ///  Daily alarm ringing
+    Alarm,
+}
+
+impl HapticEvent {
+    ///  Return the setting that chooses the pattern of the event
+    fn setting(self) -> &'static Setting<u8> {
+        match self {
+            HapticEvent::Button       => &settings::HAPTIC_BUTTON,
+            HapticEvent::Flash        => &settings::HAPTIC_FLASH,
+            HapticEvent::Alert        => &settings::HAPTIC_ALERT,
+            HapticEvent::Notification => &settings::HAPTIC_NOTIFY,
+            HapticEvent::LowBattery   => &settings::HAPTIC_LOW_BATTERY,
+            HapticEvent::Alarm        => &settings::HAPTIC_ALARM,
+        }
+    }
+}
+
+///  Vibration pattern, numbered as in the settings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+    ///  0: No vibration
+    Off,
+    ///  1: Short tap
+    Tap,
+    ///  2: Two short pulses
+    Double,
+    ///  3: Long pulse
+    Long,
+    ///  4: Three pulses, for attention
+    Triple,
+}
+
+impl Pattern {
+    ///  Return the pattern numbered `number` in the settings, or `Off` if unknown
+    pub fn from_number(number: u8) -> Pattern {
+        match number {
+            1 => Pattern::Tap,
+            2 => Pattern::Double,
+            3 => Pattern::Long,
+            4 => Pattern::Triple,
+            _ => Pattern::Off,
+        }
+    }
+
+    ///  Return the durations in milliseconds of the steps of the pattern, alternating on and off, starting with on
+    fn steps(self) -> &'static [u16] {
+        match self {
+            Pattern::Off    => &[],
+            Pattern::Tap    => &[ 30 ],
+            Pattern::Double => &[ 60, 100, 60 ],
+            Pattern::Long   => &[ 400 ],
+            Pattern::Triple => &[ 150, 100, 150, 100, 150 ],
+        }
+    }
+}
+
+///  Events pushed by `play()` and played on the default event queue
+static EVENTS: Channel<HapticEvent> = Channel::new();
+
+///  Vibration motor, configured by `start()`
+static mut VIBRATOR: Option<Output> = None;
+
+///  Steps of the pattern that is playing, and the index of the next step
+static mut STEPS: &[u16] = &[];
+static mut NEXT_STEP: usize = 0;
+
+///  Timer that starts the next step of the pattern
+static STEP_TIMER: Callout<fn()> = Callout::new(next_step);
+
+///  Configure the vibration motor and play a pattern for the button presses. Called by main() in `lib.rs` before
+///  the button is started.
+pub fn start() -> MynewtResult<()> {
+    let vibrator = pinetime::vibrator() ? ;
+    unsafe { VIBRATOR = Some(vibrator) };
+    EVENTS.notify(os::eventq_dflt_get() ? , handle_events);
+    button::register(handle_button)
+}
+
+///  Play the pattern chosen in the settings for `event`, unless muted. May be called from any task. The event is
+///  dropped if too many events are waiting.
+pub fn play(event: HapticEvent) {
+    if settings::HAPTIC_MUTE.get() { return; }
+    EVENTS.push(event).ok();
+}
+
+///  Play the pattern of the latest event, dropping the earlier events. Called by the default event queue.
+extern "C" fn handle_events(_ev: *mut os::os_event) {
+    let mut latest = None;
+    while let Some(event) = EVENTS.pop() { latest = Some(event); }
+    if let Some(event) = latest {
+        let pattern = Pattern::from_number(event.setting().get());
+        unsafe {
+            STEPS = pattern.steps();
+            NEXT_STEP = 0;
+        }
+        next_step();
+    }
+}
+
+///  Switch the motor on for the even steps and off for the odd steps, then wait for the step to end. Switch the
+///  motor off after the last step.
+fn next_step() {
+    let (steps, step) = unsafe { (STEPS, NEXT_STEP) };
+    let level = if step < steps.len() && step % 2 == 0 { pinetime::VIBRATOR_ON } else { pinetime::VIBRATOR_OFF };
+    if let Some(vibrator) = unsafe { VIBRATOR.as_mut() } { vibrator.set_level(level); }
+    if level == pinetime::VIBRATOR_ON { power_profile::begin(Subsystem::Haptic); }
+    else { power_profile::end(Subsystem::Haptic); }
+    if step >= steps.len() {
+        STEP_TIMER.stop();
+        return;
+    }
+    unsafe { NEXT_STEP = step + 1 };
+    STEP_TIMER.reset(Duration::from_millis(steps[step] as u64)).expect("haptic timer fail");
+}
+
+///  Tap for each button press
+fn handle_button(_event: ButtonEvent) {
+    play(HapticEvent::Button);
+}
@@ -33,6 +33,37 @@ extern crate macros as mynewt_macros;   //  Declare the Mynewt Procedural Macros
 mod app_network;    //  Declare `app_network.rs` as Rust module `app_network` for Application Network functions
 mod app_sensor;     //  Declare `app_sensor.rs` as Rust module `app_sensor` for Application Sensor functions
 mod touch_sensor;   //  Declare `touch_sensor.rs` as Rust module `touch_sensor` for Touch Sensor functions
+mod mcuboot;        //  Declare `mcuboot.rs` as Rust module `mcuboot` for MCUBoot image info
+mod logo;           //  Declare `logo.rs` as Rust module `logo` for writing and uploading the boot logo
+mod settings;       //  Declare `settings.rs` as Rust module `settings` for the persisted settings
+mod pedometer;      //  Declare `pedometer.rs` as Rust module `pedometer` for counting steps
+mod calibration;    //  Declare `calibration.rs` as Rust module `calibration` for calibrating the accelerometer
+mod tap;            //  Declare `tap.rs` as Rust module `tap` for detecting taps with the accelerometer
+mod power;          //  Declare `power.rs` as Rust module `power` for switching the display on and off
+mod wrist;          //  Declare `wrist.rs` as Rust module `wrist` for waking the display when the wrist is raised
+mod orientation;    //  Declare `orientation.rs` as Rust module `orientation` for the pitch and roll of the watch
+mod alerts;         //  Declare `alerts.rs` as Rust module `alerts` for the sensor alert thresholds
+mod sensor_shell;   //  Declare `sensor_shell.rs` as Rust module `sensor_shell` for the sensor shell commands
+mod ble_sensors;    //  Declare `ble_sensors.rs` as Rust module `ble_sensors` for the standard Bluetooth LE sensor services
+mod pairing;        //  Declare `pairing.rs` as Rust module `pairing` for showing the Bluetooth LE pairing code
+mod cts;            //  Declare `cts.rs` as Rust module `cts` for setting the wall clock from the phone
+mod dfu;            //  Declare `dfu.rs` as Rust module `dfu` for over-the-air firmware updates
+mod phone;          //  Declare `phone.rs` as Rust module `phone` for reacting to the phone connections
+mod beacon;         //  Declare `beacon.rs` as Rust module `beacon` for broadcasting the sensor readings
+mod link_quality;   //  Declare `link_quality.rs` as Rust module `link_quality` for the Bluetooth LE signal strength
+mod phone_notify;   //  Declare `phone_notify.rs` as Rust module `phone_notify` for showing the phone notifications
+mod button;         //  Declare `button.rs` as Rust module `button` for the clicks and long presses of the watch button
+mod adc_calibration; // Declare `adc_calibration.rs` as Rust module `adc_calibration` for calibrating the battery ADC
+mod power_fail;     //  Declare `power_fail.rs` as Rust module `power_fail` for stopping flash writes before power loss
+mod haptics;        //  Declare `haptics.rs` as Rust module `haptics` for the vibration patterns of the UI events
+mod charger;        //  Declare `charger.rs` as Rust module `charger` for the charging state of the battery
+mod console_sinks;  //  Declare `console_sinks.rs` as Rust module `console_sinks` for selecting the console output
+mod crash_report;   //  Declare `crash_report.rs` as Rust module `crash_report` for uploading the crash dumps
+mod memory_report;  //  Declare `memory_report.rs` as Rust module `memory_report` for reporting the memory usage
+mod data_log;       //  Declare `data_log.rs` as Rust module `data_log` for the sensor and event logs in flash
+mod remote_config;  //  Declare `remote_config.rs` as Rust module `remote_config` for configuring the settings over CoAP
+mod version;        //  Declare `version.rs` as Rust module `version` for the firmware and build information
+mod alarms;         //  Declare `alarms.rs` as Rust module `alarms` for the daily alarms with vibration
 
 //  Declare the optional modules depending on the options in `../Cargo.toml`
 #[cfg(feature = "display_app")]  //  If graphics display app is enabled...
@@ -48,15 +79,25 @@ mod visual;                      //  Include the Visual Rust app
 #[cfg(feature = "chip8_app")]    //  If CHIP8 Emulator app is enabled...
 mod chip8;                       //  Include the CHIP8 Emulator app
 
+#[cfg(feature = "uart_console")] //  If the UART console is enabled...
+mod uart_shell;                  //  Include the shell over the UART console
+
 #[cfg(feature = "use_float")]    //  If floating-point is enabled...
 mod gps_sensor;                  //  Include the GPS Sensor functions
 
+#[cfg(feature = "device_test")]  //  If the on-target tests are enabled...
+mod device_tests;                //  Include the on-target tests
+
+
 //  Declare the system modules
 use core::panic::PanicInfo; //  Import `PanicInfo` type which is used by `panic()` below
+use core::time::Duration;   //  Import `Duration` type for timeouts and intervals
 use cortex_m::asm::bkpt;    //  Import cortex_m assembly function to inject breakpoint
 use mynewt::{
-    kernel::os,             //  Import Mynewt OS API
-    sys::console,           //  Import Mynewt Console API
+    sys::{
+        console,            //  Import Mynewt Console API
+        panic,              //  Import Mynewt Panic Record API
+    },
 };
 
 //  Select the touch handler depending on the options in `../Cargo.toml`
@@ -72,6 +113,9 @@ use chip8::handle_touch;        //  Use the touch handler from the CHIP8 Emulato
 #[cfg(not(any(feature = "ui_app", feature = "visual_app", feature = "chip8_app")))]  //  If neither druid UI app nor Visual Rust app are enabled...
 pub fn handle_touch(_x: u16, _y: u16) { console::print("touch not handled\n"); console::flush(); }  //  Define a touch handler that does nothing
 
+//  Start the display during `sysinit()`, after the SPI Flash Driver.
+mynewt::init_hook!(mynewt::sys::init::STAGE_DISPLAY, DISPLAY_HOOK, druid::start_display);
+
 ///  Main program that initialises the sensor, network driver and starts reading and sending sensor data in the background.
 ///  main() will be called at Mynewt startup. It replaces the C version of the main() function.
 #[no_mangle]                 //  Don't mangle the name "main"
@@ -80,8 +124,60 @@ extern "C" fn main() -> ! {  //  Declare extern "C" because it will be called by
     //  functions defined in pkg.yml of our custom drivers and libraries will be called by 
     //  sysinit().  Here are the startup functions consolidated by Mynewt:
     //  bin/targets/nrf52_my_sensor/generated/src/nrf52_my_sensor-sysinit-app.c
+    //  The init hooks registered with `init_hook!()` will also be called, e.g. to start the display.
     mynewt::sysinit();
 
+    //  Record and print the registers on HardFault and the other faults, then restart.
+    mynewt::kernel::fault::start()
+        .expect("FAULT fail");
+
+    //  Show the reason for the last restart, and the panic, fault, stack overflow or hung task that caused it, if any.
+    mynewt::kernel::reset::show_reason();
+    mynewt::kernel::reboot::show_last();
+    panic::show_last();
+    mynewt::kernel::fault::show_last();
+    mynewt::kernel::stack_guard::show_last();
+    mynewt::kernel::supervisor::show_last_culprit();
+
+    //  Write a crash dump to flash on panic and fault, and upload the dumps written before the restart, if any.
+    crash_report::start()
+        .expect("CRASH fail");
+
+    //  Find the newest records in the sensor and event logs in flash, before the sensors start.
+    data_log::start()
+        .expect("DATA LOG fail");
+
+    //  Stop flash writes and blank the display when the battery is about to fail.
+    power_fail::start()
+        .expect("POF fail");
+
+    //  Run the shell on the UART console, with the console output.
+    #[cfg(feature = "uart_console")]  //  If the UART console is enabled...
+    uart_shell::start()
+        .expect("UART fail");
+
+    //  Send the console output to the sink in the settings: Semihosting, RTT, UART or the Bluetooth LE console.
+    console_sinks::start()
+        .expect("CONSOLE fail");
+
+    //  Start the task that runs the on-target tests, and report the test that restarted the device, if any.
+    //  Reported after selecting the console sink, so that the test harness on the host receives the report.
+    #[cfg(feature = "device_test")]  //  If the on-target tests are enabled...
+    {
+        mynewt::sys::device_test::show_last();
+        mynewt::sys::device_test::start()
+            .expect("TEST fail");
+    }
+
+    //  Send the messages from `log::info!()`, `log::warn!()`, ... to the Mynewt log.
+    mynewt::sys::logger::init(log::LevelFilter::Info)
+        .expect("LOG fail");
+    //  Apply the per-module log levels in the settings, e.g. `app::power=debug,mynewt::spi=warn`.
+    if let Err(err) = mynewt::sys::logger::set_filters(&settings::LOG_FILTER.get()) {
+        log::warn!("log filter fail {:?}", err);
+    }
+    log::info!("firmware {} started", version::build_string());
+
     //  Write graphic image to SPI Flash. Must run before testing the display, to avoid contention for SPI port.
     //  extern { fn write_image() -> i32; }
     //  let rc = unsafe { write_image() };
@@ -99,28 +195,186 @@ extern "C" fn main() -> ! {  //  Declare extern "C" because it will be called by
     let rc = unsafe { test_flash() };
     assert!(rc == 0, "FLASH fail");
 
+    //  Benchmark External SPI Flash on the spare area before the battery history, which is erased.
+    #[cfg(feature = "flash_bench")]  //  If flash benchmark is enabled...
+    {
+        let region = &mynewt::hw::flash::map::SPARE;
+        mynewt::hw::flash::bench::run(region, 0, region.size)
+            .expect("FLASH bench fail");
+        //  Run the benchmark again with the shell command `flashbench`.
+        mynewt::sys::shell::register("flashbench", "flashbench [offset] [len]", flash_bench_command)
+            .expect("FLASH bench shell fail");
+    }
+
+    //  Mount the littlefs file system in the user area of External SPI Flash, formatting it on first use.
+    #[cfg(feature = "littlefs")]  //  If the file system is enabled...
+    mynewt::fs::mount()
+        .expect("FS fail");
+
     //  Start Bluetooth LE, including over-the-air firmware upgrade.  TODO: Create a safe wrapper for starting Bluetooth LE.
     extern { fn start_ble() -> i32; }
     let rc = unsafe { start_ble() };
     assert!(rc == 0, "BLE fail");
 
-    //  Write the boot graphic
-    extern { fn write_graphic() -> i32; }  //  Defined in apps/my_sensor_app/src/write_graphic.c
-    #[cfg(feature = "write_graphic")]      //  If writing of boot graphic is enabled...
-    unsafe { write_graphic() };
+    //  Stream the accelerometer samples to the phones that subscribe over Bluetooth LE.
+    start_optional("BLE stream", ble_sensors::start_streams());
+
+    //  Pause the CoAP posts while a phone is connected over Bluetooth LE.
+    start_optional("PHONE", phone::start());
+
+    //  Measure the signal strength of the phone connections, for diagnosing uploads.
+    start_optional("LINK", link_quality::start());
+
+    //  Vibrate for the button presses, completed flashes, alerts and notifications, unless muted in the settings.
+    start_optional("HAPTIC", haptics::start());
+
+    //  Ring the daily alarms in the settings, once the wall clock is set by the phone.
+    start_optional("ALARM", alarms::start());
+
+    //  Show the notifications of the phone, with a haptic pattern.
+    start_optional("NOTIFY", phone_notify::start());
+
+    //  Show the progress of firmware uploads over SMP, and confirm the running firmware after a test boot.
+    dfu::start()
+        .expect("DFU fail");
+
+    //  Accept the L2CAP channel for uploading the boot logo over Bluetooth LE.
+    logo::ble::start_l2cap()
+        .expect("LOGO L2CAP fail");
+
+    //  Register the newtmgr / SMP commands for uploading the boot logo over serial.
+    extern { fn start_logo_mgmt() -> i32; }
+    let rc = unsafe { start_logo_mgmt() };
+    assert!(rc == 0, "LOGO SMP fail");
+
+    //  Register the newtmgr / SMP and shell commands for calibrating the sensors.
+    extern { fn start_calibration_mgmt() -> i32; }
+    let rc = unsafe { start_calibration_mgmt() };
+    assert!(rc == 0, "CAL SMP fail");
+
+    //  Register the shell commands for reading and polling the sensors.
+    extern { fn start_sensor_shell() -> i32; }
+    let rc = unsafe { start_sensor_shell() };
+    assert!(rc == 0, "SENSOR shell fail");
+
+    //  Register the shell command for switching the console output to another sink.
+    extern { fn start_console_shell() -> i32; }
+    let rc = unsafe { start_console_shell() };
+    assert!(rc == 0, "CONSOLE shell fail");
+
+    //  Register the shell command for showing and uploading the battery history.
+    extern { fn start_battery_shell() -> i32; }
+    let rc = unsafe { start_battery_shell() };
+    assert!(rc == 0, "BAT shell fail");
+
+    //  Register the shell command for running the on-target tests.
+    extern { fn start_test_shell() -> i32; }
+    let rc = unsafe { start_test_shell() };
+    assert!(rc == 0, "TEST shell fail");
+
+    //  Register the shell command for showing and sending the memory usage.
+    extern { fn start_memory_shell() -> i32; }
+    let rc = unsafe { start_memory_shell() };
+    assert!(rc == 0, "MEM shell fail");
+
+    //  Register the shell command for showing and clearing the sensor and event logs.
+    extern { fn start_data_log_shell() -> i32; }
+    let rc = unsafe { start_data_log_shell() };
+    assert!(rc == 0, "LOG shell fail");
 
-    //  Start the display
-    druid::start_display()
-        .expect("DSP fail");
+    //  Report the clicks and long presses of the watch button.
+    button::start()
+        .expect("BUTTON fail");
+
+    //  Restore the built-in logo when the watch button is held for 5 seconds.
+    start_optional("LOGO reset", logo::reset::start_button_reset());
+
+    //  Log the tasks whose stacks are 80% full, checking every minute.
+    start_optional("STACK", mynewt::kernel::task::start_stack_monitor(80, Duration::from_secs(60)));
+
+    //  Restart when a task overwrites the canaries at the bottom of its stack, checking every second.
+    start_optional("GUARD", mynewt::kernel::stack_guard::start(Duration::from_secs(1)));
+
+    //  Log the busiest task when the CPU sleeps less than half of the time, checking every minute.
+    start_optional("IDLE", mynewt::kernel::idle::start_idle_monitor(50, Duration::from_secs(60)));
+
+    //  Show the MCUBoot firmware images in both slots
+    start_optional("MCUBOOT", mcuboot::show_image_info());
+
+    //  Write the boot graphic and verify the CRC32
+    #[cfg(feature = "write_graphic")]  //  If writing of boot graphic is enabled...
+    let logo_verified = logo::write_logo()
+        .expect("LOGO fail");
+
+    //  Show the assets in the asset bundle. Ignore the error if the bundle is corrupted.
+    logo::bundle::show_bundle().ok();
+
+    //  Preview the logo that the bootloader will display. Ignore the error if no logo has been stored.
+    logo::blit::preview_active_logo().ok();
+
+    //  Show the logo verification result
+    #[cfg(feature = "write_graphic")]  //  If writing of boot graphic is enabled...
+    logo::show_verify_result(logo_verified)
+        .expect("LOGO show fail");
+
+    //  Show the firmware version
+    start_optional("VER", mcuboot::show_version_on_screen());
+
+    //  Send the firmware versions to the CoAP server. Ignore the error if the network is not ready.
+    mcuboot::send_image_info().ok();
 
     //  Test the display
     #[cfg(feature = "display_app")]  //  If graphics display app is enabled...
     display::test_display()
         .expect("DSP test fail");
 
-    //  Start the touch sensor
-    touch_sensor::start_touch_sensor()
-        .expect("TCH fail");
+    //  Send alerts when the sensor readings cross the thresholds in the settings
+    start_optional("ALERT", alerts::start_alerts());
+
+    //  Send the nRF52 internal temperature to the CoAP server
+    start_optional("TMP", app_sensor::start_sensor_listener());
+
+    //  Start the heart rate sensor and send the heart rate to the CoAP server
+    start_optional("HRS", app_sensor::start_heart_rate_listener());
+
+    //  Calibrate the battery ADC now, hourly and when the temperature drifts.
+    start_optional("ADC CAL", adc_calibration::start());
+
+    //  Record the battery samples on flash and upload them to the CoAP server every hour. Must start before the battery listener.
+    start_optional("BAT HIST", power::history::start());
+
+    //  Send the battery voltage to the CoAP server
+    start_optional("BAT", app_sensor::start_battery_listener());
+
+    //  Count steps with the accelerometer and send the step count to the CoAP server
+    start_optional("STEP", pedometer::start_pedometer());
+    start_optional("ORIENT", orientation::start_orientation());
+    start_optional("STEP listen", app_sensor::start_steps_listener());
+
+    //  Detect taps and double taps with the accelerometer
+    start_optional("TAP", tap::start_tap_detection());
+
+    //  Report the charging state, and keep the display on while the charger supplies power
+    start_optional("CHARGER", charger::start());
+
+    //  Switch the display on when the wrist is raised, and off when the wrist drops
+    start_optional("WRIST", wrist::start_wrist_detection());
+
+    //  Switch off the display and slow down the sensors when the watch is not used, then stop advertising
+    power::manager::start()
+        .expect("POWER fail");
+
+    //  Report the estimated charge drawn by the radio, flash, display and vibration motor every hour
+    start_optional("PROFILE", power::profile::start());
+
+    //  Report the heap, mbuf and memory pool usage every hour
+    start_optional("MEM report", memory_report::start());
+
+    //  Send the settings to the CoAP server every hour, and save the new values in the responses
+    start_optional("CONFIG", remote_config::start());
+
+    //  Register the shell command `version` and send the firmware and build information to the CoAP server after startup
+    start_optional("VERSION", version::start());
 
     //  Test the touch sensor
     //  touch_sensor::test()
@@ -140,35 +394,49 @@ extern "C" fn main() -> ! {  //  Declare extern "C" because it will be called by
     chip8::on_start()
         .expect("CHIP8 fail");
 
-    //  Main event loop
-    loop {                            //  Loop forever...
-        os::eventq_run(               //  Processing events...
-            os::eventq_dflt_get()     //  From default event queue.
-                .expect("GET fail")
-        ).expect("RUN fail");
-    }
-    //  Never comes here
+    //  Start the watchdog after the slow startup tasks. The supervisor restarts the watch if the main task or a registered task hangs for 30 seconds.
+    //  A `const` so that an invalid timeout fails to compile.
+    const WATCHDOG: mynewt::hal::watchdog::WatchdogConfig = mynewt::hal::watchdog::WatchdogConfig::new(30_000);
+    mynewt::kernel::supervisor::start(WATCHDOG)
+        .expect("WDOG fail");
+
+    //  Main event loop: process events from the default event queue forever. The CPU sleeps while there are no events.
+    mynewt::kernel::idle::run_default()
+}
+
+///  Log the error if the optional subsystem `name` failed to start, so that the watch keeps running without it.
+///  The essential subsystems, like the console, Bluetooth LE and the power manager, panic instead.
+fn start_optional<T>(name: &str, result: mynewt::result::MynewtResult<T>) {
+    if let Err(err) = result { log::error!("{} fail {:?}", name, err); }
 }
 
-///  This function is called on panic, like an assertion failure. We display the filename and line number and pause in the debugger. From https://os.phil-opp.com/freestanding-rust-binary/
+///  This function is called on panic, like an assertion failure. We display the filename, line number and message,
+///  record the panic for displaying after restart, and pause in the debugger if attached. Then we restart the device.
+///  From https://os.phil-opp.com/freestanding-rust-binary/
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    //  Display the filename and line number to the Semihosting Console.
-    console::print("panic ");
-    if let Some(location) = info.location() {
-        let file = location.file();
-        let line = location.line();
-        console::print("at ");       console::buffer(&file);
-        console::print(" line ");    console::printint(line as i32);
-        console::print("\n");        console::flush();
-    } else {
-        console::print("no loc\n");  console::flush();
+    //  Display and record the panic, unless the panic handler itself has panicked.
+    if panic::record(info) {
+        //  Pause in the debugger. Without a debugger, `bkpt` would cause a HardFault.
+        if debugger_attached() { bkpt(); }
     }
-    //  Pause in the debugger.
-    bkpt();
-    //  Display the payload.
-    console::print(info.payload().downcast_ref::<&str>().unwrap());
-    console::print("\n");  console::flush();
-    //  Loop forever so that device won't restart.
-    loop {}
+    //  Restart the device instead of hanging.
+    panic::reset()
+}
+
+///  Shell command `flashbench [offset] [len]`: Benchmark the spare area of External SPI Flash, which is erased.
+///  By default the whole spare area is benchmarked.
+#[cfg(feature = "flash_bench")]  //  If flash benchmark is enabled...
+fn flash_bench_command(args: &mut mynewt::sys::shell::Args) -> mynewt::result::MynewtResult<()> {
+    let region = &mynewt::hw::flash::map::SPARE;
+    let offset: u32 = args.int_or(0) ? ;
+    let len:    u32 = args.int_or(region.size.saturating_sub(offset)) ? ;
+    args.end() ? ;
+    mynewt::hw::flash::bench::run(region, offset, len)
+}
+
+///  Return true if a debugger is attached, according to the C_DEBUGEN bit of the Debug Halting Control and Status Register
+fn debugger_attached() -> bool {
+    const DHCSR: *const u32 = 0xE000_EDF0 as *const u32;
+    unsafe { core::ptr::read_volatile(DHCSR) & 1 != 0 }
 }
\ No newline at end of file
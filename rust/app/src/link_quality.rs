@@ -0,0 +1,115 @@
+//!  Report the Bluetooth LE link quality, for diagnosing slow or failed logo and firmware uploads. While a phone is
+//!  connected, the signal strength (RSSI) of each connection is read from the controller, and the weakest is exposed
+//!  with the transmit power on the advertising channels as the virtual sensor `link_0`. Each reading sets the
+//!  Link Quality Characteristic in `apps/my_sensor_app/src/ble_sensor_svc.c`, so the phone app may show the signal
+//!  during a transfer, and weak signals are logged. The watch can't measure the signal on the advertising channels
+//!  itself, so the advertising includes the transmit power, and scanners compute the path loss from their RSSI.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    ble::{
+        adv, conn,
+        gap::ConnHandle,
+        lifecycle::{ self, ConnHandler },
+        BleError,
+    },
+    hw::sensor::{
+        self,
+        poller,
+        sensor_link_quality_data, Listener, Reading, VirtualSensor,
+    },
+    kernel::sync::Mutex,
+    Strn,
+};
+use mynewt_macros::{ init_strn };
+
+///  Name of the virtual sensor for the link quality
+pub static LINK_DEVICE: Strn = init_strn!("link_0");
+
+///  Virtual sensor that returns the signal strength of the weakest connection
+static LINK_SENSOR: VirtualSensor<sensor_link_quality_data> =
+    VirtualSensor::new(sensor::SENSOR_TYPE_LINK_QUALITY, sensor::SENSOR_VALUE_TYPE_INT32, read_link_quality);
+
+///  Listener that publishes each reading over Bluetooth LE
+static LINK_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> = Listener::new(publish_link_quality);
+
+///  Interval for polling the link quality. Only connected phones are measured, so polling is cheap when idle.
+const LINK_POLL_TIME: Duration = Duration::from_secs(5);
+
+///  Signals weaker than this in dBm are logged, since packets are often lost and transfers slow down
+const WEAK_RSSI: i32 = -85;
+
+///  Max number of connections measured. Must match `MaxConns`.
+type MaxConns = heapless::consts::U4;
+
+///  Connections of the phones, updated on the default event queue
+static CONNS: Mutex<heapless::Vec<ConnHandle, MaxConns>> = Mutex::new(heapless::Vec(heapless::i::Vec::new()));
+
+///  Handler that follows the phone connections
+struct LinkHandler;
+
+///  Registered with `mynewt::ble::lifecycle`
+static LINK_HANDLER: LinkHandler = LinkHandler;
+
+///  Register the virtual sensor, poll it and follow the phone connections. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    match lifecycle::register(&LINK_HANDLER) {
+        Ok(()) => {}
+        Err(BleError::ENOTSUP) => return Ok(()),  //  Bluetooth LE is disabled
+        Err(err) => return Err(err.into()),
+    }
+    LINK_SENSOR.create(&LINK_DEVICE) ? ;
+    LINK_LISTENER.register(&LINK_DEVICE, sensor::SENSOR_TYPE_LINK_QUALITY) ? ;
+    poller::add(&LINK_DEVICE, sensor::SENSOR_TYPE_LINK_QUALITY, LINK_POLL_TIME)
+}
+
+impl ConnHandler for LinkHandler {
+    ///  Measure the new connection
+    fn on_connect(&self, conn: ConnHandle) {
+        //  Ignore the error if the mutex can't be locked or too many phones are connected.
+        if let Ok(mut conns) = CONNS.lock() { conns.push(conn).ok(); }
+    }
+
+    ///  Stop measuring the connection
+    fn on_disconnect(&self, conn: ConnHandle, _reason: i32) {
+        if let Ok(mut conns) = CONNS.lock() {
+            if let Some(i) = conns.iter().position(|c| *c == conn) { conns.swap_remove(i); }
+        }
+    }
+}
+
+///  Called by the Sensor Manager to read the virtual sensor. Returns `None` if no phone is connected.
+fn read_link_quality() -> Option<sensor_link_quality_data> {
+    let rssi = {
+        let conns = CONNS.lock().ok() ? ;
+        //  Skip the connections that have just been terminated.
+        conns.iter()
+            .filter_map(|conn| conn::rssi(*conn).ok())
+            .min() ?
+    };
+    let adv_tx_power = adv::tx_power().unwrap_or(0);
+    Some(sensor_link_quality_data {
+        slqd_rssi:          rssi as i32,
+        slqd_adv_tx_power:  adv_tx_power as i32,
+        slqd_rssi_is_valid: 1,
+    })
+}
+
+///  Set the Link Quality Characteristic to the reading and log weak signals
+fn publish_link_quality(reading: &Reading) -> MynewtResult<()> {
+    let (rssi, adv_tx_power) = match *reading {
+        Reading::LinkQuality { rssi, adv_tx_power } => (rssi, adv_tx_power),
+        _ => return Ok(()),
+    };
+    if rssi < WEAK_RSSI { log::warn!("weak signal {} dBm", rssi); }
+    //  Ignore the error if Bluetooth LE is disabled.
+    unsafe { sensor_svc_set_link_quality(rssi as i8, adv_tx_power as i8) };
+    Ok(())
+}
+
+extern "C" {
+    ///  Set the signal strength and advertising transmit power in dBm, and notify the subscribed phones.
+    ///  C API: `int sensor_svc_set_link_quality(int8_t rssi, int8_t adv_tx_power)`
+    fn sensor_svc_set_link_quality(rssi: i8, adv_tx_power: i8) -> i32;
+}
@@ -0,0 +1,250 @@
+//!  Write the boot logo to External SPI Flash and verify the written logo.
+//!  The boot logo is a 240 x 240 RGB565 graphic converted from PNG by https://github.com/lupyuen/pinetime-graphic
+//!  The bootloader (`libs/pinetime_boot`) renders the logo from SPI Flash at startup.
+//!  Uploaded logos may also be stored compressed with heatshrink, see `logo/decompress.rs`.
+//!  The built-in graphic may also be an asset bundle with images and fonts, see `logo/bundle.rs`.
+
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use mynewt::{
+    result::*,
+    hw::flash::map::{ self, Region },
+    sys::console,
+    util::crc::{ self, Crc32 },
+};
+
+/// Index table for storing multiple logos in SPI Flash
+pub mod index;  //  Export `logo/index.rs` as Rust module `logo::index`
+
+/// Logo header for validation by the bootloader
+pub mod header; //  Export `logo/header.rs` as Rust module `logo::header`
+
+/// Upload a logo into a logo slot in chunks
+pub mod upload; //  Export `logo/upload.rs` as Rust module `logo::upload`
+
+/// Upload a logo over Bluetooth LE
+pub mod ble;    //  Export `logo/ble.rs` as Rust module `logo::ble`
+
+/// Upload a logo over serial with newtmgr / SMP
+pub mod serial; //  Export `logo/serial.rs` as Rust module `logo::serial`
+
+/// Manifest with the version, CRC32 and timestamp of each logo
+pub mod manifest; //  Export `logo/manifest.rs` as Rust module `logo::manifest`
+
+/// Journal for resuming an interrupted flash
+pub mod journal;  //  Export `logo/journal.rs` as Rust module `logo::journal`
+
+/// Decompress logos that are stored compressed in SPI Flash
+pub mod decompress; //  Export `logo/decompress.rs` as Rust module `logo::decompress`
+
+/// Convert logos that are uploaded as RGB888 to RGB565, with optional dithering
+pub mod convert;  //  Export `logo/convert.rs` as Rust module `logo::convert`
+
+/// Factory reset for the logo region
+pub mod reset;    //  Export `logo/reset.rs` as Rust module `logo::reset`
+
+/// Relocate bad sectors to spare sectors
+pub mod relocate; //  Export `logo/relocate.rs` as Rust module `logo::relocate`
+
+/// Preview a stored logo by streaming it from SPI Flash to the display
+pub mod blit;     //  Export `logo/blit.rs` as Rust module `logo::blit`
+
+/// Asset bundle with images, fonts and animations
+pub mod bundle;   //  Export `logo/bundle.rs` as Rust module `logo::bundle`
+
+/// Flash region for the logo: Bootloader Assets in External SPI Flash
+const LOGO_REGION: Region = map::LOGO;
+
+/// Max number of bytes to be written in a batch. Equals the SPI Flash sector size.
+const BATCH_SIZE: usize = 4096;
+
+/// Number of bytes to be read back in a batch when comparing or verifying the logo
+const READ_SIZE: usize = 256;
+
+/// Buffer for reading back the logo from SPI Flash
+static mut READ_BUFFER: [u8; READ_SIZE] = [0; READ_SIZE];
+
+/// Write the built-in logo to the default slot in SPI Flash, then read it back and verify the CRC32.
+/// If the built-in graphic is an asset bundle, the bundle is flashed and its `logo` image becomes the built-in logo.
+/// If the default slot already contains the built-in logo, nothing is written, so that the journal of an
+/// interrupted upload is preserved. The slot chosen in `settings::LOGO_SLOT` is selected for display by the
+/// bootloader, or the default slot if the chosen slot is empty and no other logo has been selected. Progress is displayed on the console.
+/// Returns `Ok(true)` if the written logo matches the source, `Ok(false)` if the logo is corrupted.
+#[cfg(feature = "write_graphic")]  //  If writing of boot graphic is enabled...
+pub fn write_logo() -> MynewtResult<bool> {
+    let builtin = get_logo();
+    let logo =
+        if bundle::is_bundle(builtin) {
+            //  Flash the entire bundle, then install the logo image from the bundle.
+            if !bundle::flash_bundle(builtin) ? { return Ok(false); }
+            match bundle::find_in(builtin, bundle::LOGO_ASSET_NAME) {
+                Some((entry, logo)) if entry.kind == bundle::ASSET_IMAGE && entry.format == header::LOGO_FORMAT_RGB565 => logo,
+                _ => {
+                    console::print("Bundle has no RGB565 logo\n"); console::flush();
+                    return Ok(false);
+                }
+            }
+        } else { builtin };
+    let base = index::slot_offset(index::DEFAULT_SLOT);
+    let table = index::read_index() ? ;
+    let entry = table.slots[index::DEFAULT_SLOT as usize];
+    let verified =
+        if entry.is_used() && entry.length == logo.len() as u32 && entry.checksum == checksum(logo)
+            && header::validate(base) ? {
+            console::print("Logo unchanged\n"); console::flush();
+            true
+        } else {
+            let version = env!("CARGO_PKG_VERSION").as_bytes();  //  Built-in logo has the same version as the firmware
+            index::store_logo(index::DEFAULT_SLOT, b"default", version, 0, logo) ?
+        };
+    if let Some((slot, _, _)) = journal::pending() ? {
+        console::print("Logo upload to slot "); console::printint(slot as i32);
+        console::print(" was interrupted, upload again to resume\n"); console::flush();
+    }
+    //  Select the logo slot chosen in the settings. If the chosen slot has no logo, select the default slot
+    //  unless another logo has been uploaded and selected.
+    let chosen = crate::settings::LOGO_SLOT.get();
+    let table = index::read_index() ? ;
+    if (chosen as usize) < index::MAX_LOGO_SLOTS && table.slots[chosen as usize].is_used() {
+        index::select_slot(chosen) ? ;
+    } else if verified && !table.slots[table.active as usize].is_used() {
+        index::select_slot(index::DEFAULT_SLOT) ? ;
+    }
+    Ok(verified)
+}
+
+/// Write `logo` to SPI Flash at `base`, starting at offset `start` of the logo (0 to write the entire logo,
+/// or a sector boundary to resume an interrupted write). After each batch, call `progress(bytes_done, total)`
+/// so that the caller may update the UI progress bar, console log or Bluetooth LE status.
+/// Sectors that already contain the same data are not erased and written, to reduce flash wear.
+/// Returns the number of sectors skipped.
+pub fn flash_logo<F>(base: u32, logo: &[u8], start: usize, mut progress: F) -> MynewtResult<usize>
+where F: FnMut(usize, usize) {
+    let total = logo.len();
+    let mut offset: usize = core::cmp::min(start, total);
+    progress(offset, total);
+    let mut skipped: usize = 0;
+    while offset < total {
+        //  How many bytes we will write.
+        let len = core::cmp::min(BATCH_SIZE, total - offset);
+        let addr = base + offset as u32;
+        let data = &logo[offset..offset + len];
+
+        if sector_matches(addr, data) ? {
+            //  Sector is identical, skip the erase and write.
+            skipped += 1;
+        } else {
+            //  Erase the sector, then write and verify the bytes. Bad sectors are relocated.
+            relocate::erase_sector(addr) ? ;
+            relocate::write(addr, data) ? ;
+        }
+        offset += len;
+
+        //  Report the progress.
+        progress(offset, total);
+    }
+    Ok(skipped)
+}
+
+/// Return true if the SPI Flash at `addr` already contains the bytes in `data`
+fn sector_matches(addr: u32, data: &[u8]) -> MynewtResult<bool> {
+    let mut offset: usize = 0;
+    while offset < data.len() {
+        //  How many bytes we will compare.
+        let len = core::cmp::min(READ_SIZE, data.len() - offset);
+        let buf = unsafe { &mut READ_BUFFER[..len] };
+        relocate::read(addr + offset as u32, buf) ? ;
+        if buf != &data[offset..offset + len] { return Ok(false); }
+        offset += len;
+    }
+    Ok(true)
+}
+
+/// Progress callback that displays the percentage of bytes written on the console
+pub fn show_progress(bytes_done: usize, total: usize) {
+    if total == 0 { return; }
+    console::print("Logo ");
+    console::printint((bytes_done * 100 / total) as i32);
+    console::print("%\n"); console::flush();
+}
+
+/// Read back the logo in SPI Flash at `base` in chunks and compare the CRC32 with the CRC32 of `expected`.
+/// Displays the pass / fail result on the console. Returns `Ok(true)` if the CRC32 matches.
+pub fn verify_logo(base: u32, expected: &[u8]) -> MynewtResult<bool> {
+    let expected_crc = checksum(expected);
+    let actual_crc = flash_checksum(base, expected.len()) ? ;
+    let passed = actual_crc == expected_crc;
+
+    //  Display the result.
+    console::print("Logo CRC ");   print_hex32(actual_crc);
+    console::print(", expected "); print_hex32(expected_crc);
+    console::print(if passed { ": OK\n" } else { ": FAILED\n" });
+    console::flush();
+    Ok(passed)
+}
+
+/// Display the logo verification result on the screen. `start_display()` must have been called earlier.
+pub fn show_verify_result(passed: bool) -> MynewtResult<()> {
+    let (message, background) =
+        if passed { (" Logo Verified OK ", Rgb565::from(( 0x00, 0xff, 0x00 ))) }  //  Green background
+        else      { (" Logo CRC FAILED ",  Rgb565::from(( 0xff, 0x00, 0x00 ))) }; //  Red background
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(message)                                  //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black text
+        .fill(   Some( background ) )                          //  Green or red background
+        .translate( Coord::new( 20, 200 ));                    //  Shift the text to the bottom of the screen
+    druid::draw_to_display(text);
+    Ok(())
+}
+
+/// Return the logo or asset bundle to be written, which is compiled into the firmware by `apps/my_sensor_app/src/write_graphic.c`
+#[cfg(feature = "write_graphic")]  //  If writing of boot graphic is enabled...
+fn get_logo() -> &'static [u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            get_graphic_data(),
+            get_graphic_size() as usize
+        )
+    }
+}
+
+/// Return the CRC32 of the `len` bytes in SPI Flash at `base`. The bytes are read in chunks.
+pub fn flash_checksum(base: u32, len: usize) -> MynewtResult<u32> {
+    let mut crc = Crc32::new();
+    let mut offset: usize = 0;
+    while offset < len {
+        //  How many bytes we will read.
+        let size = core::cmp::min(READ_SIZE, len - offset);
+        let buf = unsafe { &mut READ_BUFFER[..size] };
+        relocate::read(base + offset as u32, buf) ? ;
+        crc.update(buf);
+        offset += size;
+    }
+    Ok(crc.finish())
+}
+
+/// Return the CRC32 of the bytes in `data`
+pub fn checksum(data: &[u8]) -> u32 {
+    crc::crc32(data)
+}
+
+/// Display a 32-bit number in hexadecimal on the console
+fn print_hex32(v: u32) {
+    for b in v.to_be_bytes().iter() {
+        console::printhex(*b);
+    }
+}
+
+///  Import the converted graphic file from `apps/my_sensor_app/src/write_graphic.c`
+extern {
+    ///  Return the converted graphic file.
+    ///  C API: `const uint8_t *get_graphic_data(void)`
+    fn get_graphic_data() -> *const u8;
+
+    ///  Return the size of the converted graphic file in bytes.
+    ///  C API: `uint32_t get_graphic_size(void)`
+    fn get_graphic_size() -> u32;
+}
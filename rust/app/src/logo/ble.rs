@@ -0,0 +1,244 @@
+//!  Upload a logo from a phone app over Bluetooth LE. The GATT service is defined in
+//!  `apps/my_sensor_app/src/ble_logo_svc.c`, which forwards the characteristic writes to the functions below.
+//!
+//!  Control Characteristic (write, notify):
+//!  ```text
+//!  0x01 slot:u8 length:u32 checksum:u32 name:[u8]  Begin upload of `length` bytes with CRC32 `checksum` into `slot`
+//!  0x02                                            Finish upload: verify the CRC32 and select the slot for display
+//!  0x03                                            Abort upload
+//!  0x04 timestamp:u32 version:[u8]                 Set the manifest of the upload, before Finish
+//!  0x05 format:u16                                 Set the logo format after Begin: 1 for RGB565, 2 for heatshrink,
+//!                                                  3 for RGB888, 4 for RGB888 with dithering
+//!  0x06                                            Factory reset: erase all logos and restore the built-in logo
+//!  0x07 delay_ms:u32                               Reboot after `delay_ms` so that the bootloader shows the new logo
+//!  ```
+//!  Notifications on the Control Characteristic: `status:u8 received:u32 total:u32 max_chunk:u16`
+//!  where status is 0 for progress, 1 for upload OK, 2 for upload failed, and `max_chunk` is the largest `data`
+//!  that fits in one Data Characteristic write with the negotiated ATT MTU. The phone should size its chunks
+//!  by the latest `max_chunk`, since the MTU may grow after Begin.
+//!
+//!  Data Characteristic (write, write without response): `offset:u32 data:[u8]`
+//!
+//!  All integers are little endian.
+//!
+//!  For faster uploads, the phone may connect an L2CAP connection-oriented channel to PSM `L2CAP_PSM` after Begin,
+//!  and send the logo bytes in order over the channel instead of the Data Characteristic, without the offsets.
+//!  The channel is flow controlled by credits, so the phone sends the next SDU only after the watch has written the
+//!  previous SDU to flash. Progress is notified on the Control Characteristic as for the Data Characteristic,
+//!  and the upload is finished by the Finish command. Requires `BLE_L2CAP_COC_MAX_NUM: 1` in `syscfg.yml`.
+//!  Like the characteristics, the channel requires an authenticated link, and is closed on other connections.
+//!
+//!  During the upload, the phone is asked for fast connection parameters, the 2M PHY, longer link layer packets
+//!  and a larger MTU, and for slow parameters afterwards to save power. The phone may reject the requests,
+//!  the upload works either way.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    ble::{
+        conn::{ self, ConnParams },
+        conns::{ self, Security },
+        l2cap::{ self, CocEvent },
+        BleError,
+    },
+    kernel::reboot::{ self, RebootReason },
+};
+use super::{
+    BATCH_SIZE,
+    reset, upload,
+};
+
+/// Control commands
+const CMD_BEGIN:  u8 = 0x01;
+const CMD_FINISH: u8 = 0x02;
+const CMD_ABORT:  u8 = 0x03;
+const CMD_MANIFEST: u8 = 0x04;
+const CMD_FORMAT: u8 = 0x05;
+const CMD_RESET:  u8 = 0x06;
+const CMD_REBOOT: u8 = 0x07;
+
+/// Notification status codes
+const STATUS_PROGRESS: u8 = 0;
+const STATUS_OK:       u8 = 1;
+const STATUS_FAILED:   u8 = 2;
+
+/// Max size of the data in a Data Characteristic write. Must sync with `LOGO_MAX_WRITE` in `ble_logo_svc.c`.
+const MAX_CHUNK: u16 = 512;
+
+/// Size of the offset that precedes the data in a Data Characteristic write
+const OFFSET_LEN: u16 = 4;
+
+/// Dynamic L2CAP PSM for uploading the logo data
+const L2CAP_PSM: u16 = 0x0080;
+
+/// Max size of an SDU sent by the phone over the L2CAP channel
+const L2CAP_MTU: u16 = 512;
+
+/// Buffer for reading the L2CAP channel, static to save stack space in the NimBLE host task
+static mut L2CAP_BUF: [u8; L2CAP_MTU as usize] = [0; L2CAP_MTU as usize];
+
+/// Accept the L2CAP channel for uploading the logo data. Called by main() in `lib.rs`.
+/// Does nothing if L2CAP channels are disabled.
+pub fn start_l2cap() -> MynewtResult<()> {
+    //  Follow the security of the connections.
+    match conns::start() {
+        Ok(()) => {}
+        Err(BleError::ENOTSUP) => return Ok(()),  //  Bluetooth LE is disabled
+        Err(err) => return Err(err.into()),
+    }
+    match l2cap::listen(L2CAP_PSM, L2CAP_MTU, handle_l2cap) {
+        Err(BleError::ENOTSUP) => Ok(()),  //  L2CAP channels or Bluetooth LE are disabled
+        result => result.map_err(|err| err.into()),
+    }
+}
+
+/// Handle a write to the Control Characteristic. Returns 0 if successful, else a Mynewt error code.
+/// Called by `ble_logo_svc.c`.
+#[no_mangle]
+extern "C" fn logo_ble_control(data: *const u8, len: u16) -> i32 {
+    let cmd = unsafe { core::slice::from_raw_parts(data, len as usize) };
+    match handle_control(cmd) {
+        Ok(())   => 0,
+        Err(err) => { upload::abort(); request_params(&ConnParams::SLOW); err.into() }
+    }
+}
+
+/// Handle a write to the Data Characteristic. Returns 0 if successful, else a Mynewt error code.
+/// Called by `ble_logo_svc.c`.
+#[no_mangle]
+extern "C" fn logo_ble_data(data: *const u8, len: u16) -> i32 {
+    let chunk = unsafe { core::slice::from_raw_parts(data, len as usize) };
+    if chunk.len() < 4 { return MynewtError::SYS_EINVAL.into(); }
+    let offset = read_u32(&chunk[0..4]);
+    match upload::write_chunk(offset, &chunk[4..], notify_progress) {
+        Ok(_)    => 0,
+        Err(err) => { upload::abort(); notify(STATUS_FAILED); request_params(&ConnParams::SLOW); err.into() }
+    }
+}
+
+/// Handle an event of the L2CAP channel. Write the received logo data at the end of the data received so far.
+/// Close the channel if the link is not authenticated, which asks the phone to pair.
+fn handle_l2cap(event: CocEvent) {
+    let chan = match event {
+        CocEvent::Connected(chan) => {
+            if conns::require(chan.conn, Security::Authenticated).is_err() { chan.close().ok(); }
+            return;
+        }
+        CocEvent::Readable(chan) => chan,
+        _ => return,
+    };
+    loop {
+        let buf = unsafe { &mut L2CAP_BUF };
+        let len = match chan.read(buf) {
+            Ok(0) | Err(_) => return,  //  No more data, or the phone has disconnected
+            Ok(len) => len,
+        };
+        let (received, _) = upload::status();
+        if upload::write_chunk(received, &buf[..len], notify_progress).is_err() {
+            upload::abort();
+            notify(STATUS_FAILED);
+            request_params(&ConnParams::SLOW);
+            chan.close().ok();  //  Ignore the error if the phone has disconnected
+            return;
+        }
+    }
+}
+
+/// Parse and execute the control command
+fn handle_control(cmd: &[u8]) -> MynewtResult<()> {
+    if cmd.is_empty() { return Err(MynewtError::SYS_EINVAL); }
+    match cmd[0] {
+        CMD_BEGIN => {
+            if cmd.len() < 10 { return Err(MynewtError::SYS_EINVAL); }
+            upload::begin(
+                cmd[1],                //  Slot
+                &cmd[10..],            //  Name
+                read_u32(&cmd[2..6]),  //  Length
+                read_u32(&cmd[6..10])  //  Checksum
+            ) ? ;
+            request_params(&ConnParams::FAST);
+            notify(STATUS_PROGRESS);
+        }
+        CMD_FINISH => {
+            let ok = upload::finish() ? ;
+            notify(if ok { STATUS_OK } else { STATUS_FAILED });
+            request_params(&ConnParams::SLOW);
+        }
+        CMD_ABORT => {
+            upload::abort();
+            notify(STATUS_FAILED);
+            request_params(&ConnParams::SLOW);
+        }
+        CMD_MANIFEST => {
+            if cmd.len() < 5 { return Err(MynewtError::SYS_EINVAL); }
+            upload::set_manifest(
+                &cmd[5..],            //  Version
+                read_u32(&cmd[1..5])  //  Timestamp
+            ) ? ;
+        }
+        CMD_FORMAT => {
+            if cmd.len() < 3 { return Err(MynewtError::SYS_EINVAL); }
+            upload::set_format(u16::from_le_bytes([ cmd[1], cmd[2] ])) ? ;
+        }
+        CMD_RESET => {
+            let ok = reset::factory_reset() ? ;
+            notify(if ok { STATUS_OK } else { STATUS_FAILED });
+        }
+        CMD_REBOOT => {
+            if cmd.len() < 5 { return Err(MynewtError::SYS_EINVAL); }
+            //  Delay the reboot so that the write response reaches the phone.
+            let delay = Duration::from_millis(read_u32(&cmd[1..5]) as u64);
+            reboot::after(delay, RebootReason::LogoUpdated) ? ;
+        }
+        _ => { return Err(MynewtError::SYS_EINVAL); }
+    }
+    Ok(())
+}
+
+/// Progress callback that notifies the phone app after every sector and at the end of the upload
+fn notify_progress(bytes_done: usize, total: usize) {
+    if bytes_done % BATCH_SIZE != 0 && bytes_done != total { return; }
+    notify(STATUS_PROGRESS);
+}
+
+/// Send a notification with the status and the upload progress on the Control Characteristic
+fn notify(status: u8) {
+    let (received, total) = upload::status();
+    let mut buf = [0u8; 11];
+    buf[0] = status;
+    buf[1..5].copy_from_slice(&received.to_le_bytes());
+    buf[5..9].copy_from_slice(&total.to_le_bytes());
+    buf[9..11].copy_from_slice(&max_chunk().to_le_bytes());
+    unsafe { logo_ble_notify(buf.as_ptr(), buf.len() as u16); }  //  Ignore the error if the phone is not subscribed
+}
+
+/// Ask the phone that is uploading the logo for new connection parameters. For fast parameters, also ask for
+/// the 2M PHY, longer link layer packets and a larger MTU.
+fn request_params(params: &ConnParams) {
+    let conn_handle = unsafe { logo_ble_conn_handle() };
+    //  Ignore the errors if Bluetooth LE is disabled or the phone has disconnected.
+    conn::request(conn_handle, params).ok();
+    if *params == ConnParams::FAST { conn::request_high_throughput(conn_handle).ok(); }
+}
+
+/// Return the largest data chunk that fits in one Data Characteristic write with the negotiated MTU
+fn max_chunk() -> u16 {
+    let conn_handle = unsafe { logo_ble_conn_handle() };
+    let write_len = conn::max_write_len(conn_handle).unwrap_or(conn::DEFAULT_WRITE_LEN);
+    core::cmp::min(write_len.saturating_sub(OFFSET_LEN), MAX_CHUNK)
+}
+
+/// Return the little endian `u32` in the first 4 bytes of `buf`
+fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_le_bytes([ buf[0], buf[1], buf[2], buf[3] ])
+}
+
+///  Import the logo GATT service from `apps/my_sensor_app/src/ble_logo_svc.c`
+extern {
+    ///  Notify the connected phone on the Control Characteristic. Returns 0 if successful.
+    ///  C API: `int logo_ble_notify(const uint8_t *data, uint16_t len)`
+    fn logo_ble_notify(data: *const u8, len: u16) -> i32;
+    ///  Return the connection that is uploading the logo.
+    ///  C API: `uint16_t logo_ble_conn_handle(void)`
+    fn logo_ble_conn_handle() -> u16;
+}
@@ -0,0 +1,110 @@
+//!  Preview a stored logo by streaming it from External SPI Flash to the display. A 240 x 240 RGB565 logo
+//!  takes 115,200 bytes, which doesn't fit in RAM, so the logo is read in small chunks and each pixel is
+//!  sent to the display window as soon as it has been read. Compressed logos are decompressed on the fly:
+//!  the decoder reads 64 compressed bytes at a time and fills `BLIT_BUFFER`, which the display driver drains.
+
+use mynewt::{
+    result::*,
+    sys::console,
+};
+use super::{
+    READ_SIZE,
+    decompress, relocate,
+    header::{ self, LOGO_WIDTH, LOGO_HEIGHT, LOGO_FORMAT_HEATSHRINK },
+    index::{ self, MAX_LOGO_SLOTS },
+};
+
+/// Buffer for the chunk of the logo that is being sent to the display
+static mut BLIT_BUFFER: [u8; READ_SIZE] = [0; READ_SIZE];
+
+/// Iterator that reads the RGB565 pixels of a logo from SPI Flash in chunks and returns each pixel colour,
+/// row by row. The display driver writes the pixels into the display window as they are returned.
+struct LogoPixels {
+    /// Offset of the logo in SPI Flash
+    base:   u32,
+    /// Number of bytes of pixels in the logo, after decompression
+    length: u32,
+    /// True if the logo is compressed
+    compressed: bool,
+    /// Offset from the start of the logo of the chunk in `BLIT_BUFFER`
+    chunk:  u32,
+    /// Offset of the next pixel from the start of the logo
+    offset: u32,
+    /// Set if the SPI Flash could not be read. The remaining pixels are returned as black.
+    error:  Option<MynewtError>,
+}
+
+impl LogoPixels {
+    /// Return an iterator for the `length` bytes of pixels of the logo in SPI Flash at `base`.
+    /// If `compressed` is true, `decompress::start()` must have been called.
+    fn new(base: u32, length: u32, compressed: bool) -> Self {
+        LogoPixels { base, length, compressed, chunk: 0, offset: 0, error: None }
+    }
+
+    /// Read the chunk of the logo that starts at `self.offset` into `BLIT_BUFFER`
+    fn read_chunk(&mut self) {
+        let size = core::cmp::min(READ_SIZE as u32, self.length - self.offset);
+        let buf = unsafe { &mut BLIT_BUFFER[..size as usize] };
+        let result =
+            if self.compressed {
+                //  Decompressed logo must have every pixel.
+                match decompress::read(buf) {
+                    Ok(count) if count < buf.len() => Err(MynewtError::SYS_EIO),
+                    Ok(_)   => Ok(()),
+                    Err(err) => Err(err),
+                }
+            }
+            else { relocate::read(self.base + self.offset, buf) };
+        if let Err(err) = result {
+            self.error = Some(err);
+        }
+        self.chunk = self.offset;
+    }
+}
+
+impl Iterator for LogoPixels {
+    /// This Iterator returns RGB565 pixel colour words (16-bit)
+    type Item = u16;
+
+    /// Return the next pixel colour
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset + 1 >= self.length { return None; }  //  No more pixels
+        if self.error.is_some() { self.offset += 2; return Some(0); }
+        //  Read the next chunk when the pixels in the buffer have been used up. `READ_SIZE` is even, so pixels never straddle chunks.
+        if self.offset == 0 || self.offset - self.chunk >= READ_SIZE as u32 { self.read_chunk(); }
+        let i = (self.offset - self.chunk) as usize;
+        let color = unsafe { u16::from_be_bytes([ BLIT_BUFFER[i], BLIT_BUFFER[i + 1] ]) };  //  Logo is stored big endian
+        self.offset += 2;
+        Some(color)
+    }
+}
+
+/// Preview the logo in the slot by streaming it from SPI Flash to the display. `start_display()` must have been called earlier.
+/// Returns `SYS_ENOENT` if the slot doesn't contain a logo that the bootloader would accept.
+pub fn preview_logo(slot: u8) -> MynewtResult<()> {
+    if slot as usize >= MAX_LOGO_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    let table = index::read_index() ? ;
+    let entry = table.slots[slot as usize];
+    let base = index::slot_offset(slot);
+    if !entry.is_used() || !header::validate(base) ? { return Err(MynewtError::SYS_ENOENT); }
+
+    //  Blit the logo row by row into the full-screen display window.
+    let frame = LOGO_WIDTH as u32 * LOGO_HEIGHT as u32 * 2;
+    let compressed = header::read_header(base) ? .format == LOGO_FORMAT_HEATSHRINK;
+    if compressed { decompress::start(base, entry.length); }
+    let length = if compressed { frame } else { core::cmp::min(entry.length, frame) };
+    let mut pixels = LogoPixels::new(base, length, compressed);
+    druid::set_display_pixels(0, 0, LOGO_WIDTH - 1, LOGO_HEIGHT - 1, &mut pixels)
+        .map_err(|_| MynewtError::SYS_EIO) ? ;
+    if let Some(err) = pixels.error {
+        console::print("Logo preview read failed\n"); console::flush();
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Preview the logo that will be displayed by the bootloader. `start_display()` must have been called earlier.
+pub fn preview_active_logo() -> MynewtResult<()> {
+    let table = index::read_index() ? ;
+    preview_logo(table.active)
+}
@@ -0,0 +1,329 @@
+//!  Asset bundle that packs images, fonts and animations into a single blob, so that the loader flashes one
+//!  bundle instead of individually managed images. The bundle is stored in the `ASSETS` region of External
+//!  SPI Flash. Assets are looked up by name and read in chunks, since most assets don't fit in RAM.
+//!
+//!  Bundle layout, all numbers little endian:
+//!  ```text
+//!  Bundle header (16 bytes): magic `ABND`, version, entry count, bundle length, CRC32 of the bytes after the header
+//!  Table of contents: One 32-byte entry per asset with name, kind, format, offset, length and CRC32
+//!  Assets: Offsets are relative to the start of the bundle
+//!  ```
+
+use mynewt::{
+    result::*,
+    hw::flash::map::{ self, Region, Storage },
+    sys::console,
+    util::crc::{ self, Crc32 },
+};
+use super::{
+    BATCH_SIZE, READ_SIZE, READ_BUFFER,
+    print_hex32, show_progress,
+};
+
+/// Flash region for the asset bundle
+const BUNDLE_REGION: Region = map::ASSETS;
+
+/// Magic number that marks a valid bundle header: `ABND`
+const BUNDLE_MAGIC: u32 = 0x444e_4241;
+
+/// Version of the bundle format
+const BUNDLE_VERSION: u16 = 1;
+
+/// Size of the bundle header
+const BUNDLE_HEADER_SIZE: usize = 16;
+
+/// Size of each entry in the table of contents
+const BUNDLE_ENTRY_SIZE: usize = 32;
+
+/// Max length of an asset name, including the terminating null
+pub const ASSET_NAME_SIZE: usize = 16;
+
+/// Asset kind: Image, e.g. the boot logo. The format is `LOGO_FORMAT_RGB565` or `LOGO_FORMAT_HEATSHRINK`.
+pub const ASSET_IMAGE: u16 = 1;
+
+/// Asset kind: Font
+pub const ASSET_FONT: u16 = 2;
+
+/// Asset kind: Animation, a sequence of images
+pub const ASSET_ANIMATION: u16 = 3;
+
+/// Name of the image in the bundle that is installed as the default boot logo
+pub const LOGO_ASSET_NAME: &[u8] = b"logo";
+
+/// Header at the start of the bundle
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BundleHeader {
+    /// Must be `BUNDLE_MAGIC`
+    pub magic:    u32,
+    /// Must be `BUNDLE_VERSION`
+    pub version:  u16,
+    /// Number of entries in the table of contents
+    pub count:    u16,
+    /// Length of the bundle in bytes, including the header
+    pub length:   u32,
+    /// CRC32 of the bytes after the header
+    pub checksum: u32,
+}
+
+/// Entry in the table of contents for an asset
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BundleEntry {
+    /// Null-terminated name of the asset
+    pub name:     [u8; ASSET_NAME_SIZE],
+    /// `ASSET_IMAGE`, `ASSET_FONT` or `ASSET_ANIMATION`
+    pub kind:     u16,
+    /// Format of the asset, depending on the kind
+    pub format:   u16,
+    /// Offset of the asset from the start of the bundle
+    pub offset:   u32,
+    /// Length of the asset in bytes
+    pub length:   u32,
+    /// CRC32 of the asset
+    pub checksum: u32,
+}
+
+impl BundleHeader {
+    /// Return the header in `data` if `data` starts with a valid bundle header that fits in `max_len` bytes
+    fn parse(data: &[u8], max_len: u32) -> Option<Self> {
+        if data.len() < BUNDLE_HEADER_SIZE { return None; }
+        let header = BundleHeader {
+            magic:    read_u32(data, 0),
+            version:  read_u16(data, 4),
+            count:    read_u16(data, 6),
+            length:   read_u32(data, 8),
+            checksum: read_u32(data, 12),
+        };
+        let toc_end = BUNDLE_HEADER_SIZE + header.count as usize * BUNDLE_ENTRY_SIZE;
+        if header.magic != BUNDLE_MAGIC || header.version != BUNDLE_VERSION
+            || (header.length as usize) < toc_end || header.length > max_len {
+            return None;
+        }
+        Some(header)
+    }
+}
+
+impl BundleEntry {
+    /// Return the entry in `data`, which contains `BUNDLE_ENTRY_SIZE` bytes
+    fn parse(data: &[u8]) -> Self {
+        let mut name = [0; ASSET_NAME_SIZE];
+        name.copy_from_slice(&data[..ASSET_NAME_SIZE]);
+        name[ASSET_NAME_SIZE - 1] = 0;  //  Always null-terminated
+        BundleEntry {
+            name,
+            kind:     read_u16(data, 16),
+            format:   read_u16(data, 18),
+            offset:   read_u32(data, 20),
+            length:   read_u32(data, 24),
+            checksum: read_u32(data, 28),
+        }
+    }
+
+    /// Return the name of the asset, without the terminating null
+    pub fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|c| *c == 0).unwrap_or(ASSET_NAME_SIZE);
+        &self.name[..len]
+    }
+
+    /// Return true if the asset lies within a bundle of `length` bytes
+    fn fits(&self, length: u32) -> bool {
+        match self.offset.checked_add(self.length) {
+            Some(end) => end <= length,
+            None      => false,
+        }
+    }
+}
+
+/// Return the entry for the asset `name` in the bundle `data` in memory, e.g. the bundle compiled into the
+/// firmware, together with the bytes of the asset. Returns `None` if `data` is not a bundle or has no such asset.
+pub fn find_in(data: &[u8], name: &[u8]) -> Option<(BundleEntry, &[u8])> {
+    let header = BundleHeader::parse(data, data.len() as u32) ? ;
+    for i in 0..header.count as usize {
+        let start = BUNDLE_HEADER_SIZE + i * BUNDLE_ENTRY_SIZE;
+        let entry = BundleEntry::parse(&data[start..start + BUNDLE_ENTRY_SIZE]);
+        if entry.name() == name && entry.fits(header.length) {
+            let asset = &data[entry.offset as usize..(entry.offset + entry.length) as usize];
+            return Some((entry, asset));
+        }
+    }
+    None
+}
+
+/// Return true if `data` is a bundle with a valid header and CRC32
+pub fn is_bundle(data: &[u8]) -> bool {
+    match BundleHeader::parse(data, data.len() as u32) {
+        Some(header) => crc::crc32(&data[BUNDLE_HEADER_SIZE..header.length as usize]) == header.checksum,
+        None => false,
+    }
+}
+
+/// Read the header of the bundle in SPI Flash. Returns `None` if no bundle has been flashed.
+/// The CRC32 is not checked, call `verify_bundle()` to check.
+pub fn read_header() -> MynewtResult<Option<BundleHeader>> {
+    let mut buf = [0u8; BUNDLE_HEADER_SIZE];
+    BUNDLE_REGION.read(0, &mut buf) ? ;
+    Ok(BundleHeader::parse(&buf, BUNDLE_REGION.size))
+}
+
+/// Return the number of assets in the bundle in SPI Flash, 0 if no bundle has been flashed
+pub fn count() -> MynewtResult<usize> {
+    Ok(match read_header() ? {
+        Some(header) => header.count as usize,
+        None => 0,
+    })
+}
+
+/// Return entry `i` in the table of contents of the bundle in SPI Flash, or `None` if there is no such entry
+pub fn entry(i: usize) -> MynewtResult<Option<BundleEntry>> {
+    let header = match read_header() ? {
+        Some(header) => header,
+        None => return Ok(None),
+    };
+    if i >= header.count as usize { return Ok(None); }
+    let mut buf = [0u8; BUNDLE_ENTRY_SIZE];
+    BUNDLE_REGION.read((BUNDLE_HEADER_SIZE + i * BUNDLE_ENTRY_SIZE) as u32, &mut buf) ? ;
+    let entry = BundleEntry::parse(&buf);
+    if !entry.fits(header.length) { return Err(MynewtError::SYS_EINVAL); }  //  Corrupted table of contents
+    Ok(Some(entry))
+}
+
+/// Call `f` for each entry in the table of contents of the bundle in SPI Flash
+pub fn for_each_entry<F>(mut f: F) -> MynewtResult<()>
+where F: FnMut(&BundleEntry) {
+    for i in 0..count() ? {
+        if let Some(entry) = entry(i) ? { f(&entry); }
+    }
+    Ok(())
+}
+
+/// Return the entry for the asset `name` in the bundle in SPI Flash, or `None` if not found
+pub fn find(name: &[u8]) -> MynewtResult<Option<BundleEntry>> {
+    for i in 0..count() ? {
+        match entry(i) ? {
+            Some(entry) if entry.name() == name => return Ok(Some(entry)),
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Read `buf.len()` bytes of the asset at `offset` from the start of the asset into `buf`.
+/// Returns the number of bytes read, which is less than `buf.len()` at the end of the asset.
+pub fn load(entry: &BundleEntry, offset: u32, buf: &mut [u8]) -> MynewtResult<usize> {
+    if offset >= entry.length { return Ok(0); }
+    let len = core::cmp::min(buf.len(), (entry.length - offset) as usize);
+    BUNDLE_REGION.read(entry.offset + offset, &mut buf[..len]) ? ;
+    Ok(len)
+}
+
+/// Read the asset and check its CRC32. Returns `Ok(true)` if the asset is intact.
+pub fn verify_asset(entry: &BundleEntry) -> MynewtResult<bool> {
+    Ok(region_checksum(entry.offset, entry.length) ? == entry.checksum)
+}
+
+/// Read the bundle in SPI Flash and check its CRC32. Returns `Ok(true)` if the bundle is intact.
+pub fn verify_bundle() -> MynewtResult<bool> {
+    match read_header() ? {
+        Some(header) => Ok(region_checksum(BUNDLE_HEADER_SIZE as u32, header.length - BUNDLE_HEADER_SIZE as u32) ? == header.checksum),
+        None => Ok(false),
+    }
+}
+
+/// Write the `bundle` to SPI Flash, then read it back and verify the CRC32. Sectors that already contain
+/// the same data are not erased and written. Returns `Ok(true)` if the written bundle has been verified.
+pub fn flash_bundle(bundle: &[u8]) -> MynewtResult<bool> {
+    if !is_bundle(bundle) || bundle.len() as u32 > BUNDLE_REGION.size { return Err(MynewtError::SYS_EINVAL); }
+    //  Never overwrite a firmware image.
+    if crate::mcuboot::would_overwrite_image(&BUNDLE_REGION, 0, bundle.len() as u32) ? {
+        return Err(MynewtError::SYS_EACCES);
+    }
+    console::print("Writing asset bundle...\n"); console::flush();
+    let total = bundle.len();
+    let mut offset: usize = 0;
+    let mut skipped: usize = 0;
+    while offset < total {
+        //  Erase and write the sector, unless it's unchanged.
+        let len = core::cmp::min(BATCH_SIZE, total - offset);
+        let data = &bundle[offset..offset + len];
+        if region_matches(offset as u32, data) ? {
+            skipped += 1;
+        } else {
+            crate::power_fail::check(offset as u32) ? ;  //  Stop at a sector boundary if the power may be lost
+            BUNDLE_REGION.erase(offset as u32, BATCH_SIZE as u32) ? ;
+            BUNDLE_REGION.write(offset as u32, data) ? ;
+        }
+        offset += len;
+        show_progress(offset, total);
+    }
+    console::print("Bundle written to flash, unchanged sectors skipped: ");
+    console::printint(skipped as i32); console::print("\n"); console::flush();
+
+    //  Read back the bundle and check the CRC32.
+    let verified = verify_bundle() ? ;
+    console::print(if verified { "Bundle CRC OK\n" } else { "Bundle CRC FAILED\n" }); console::flush();
+    Ok(verified)
+}
+
+/// Display the table of contents of the bundle in SPI Flash on the console
+pub fn show_bundle() -> MynewtResult<()> {
+    let header = match read_header() ? {
+        Some(header) => header,
+        None => {
+            console::print("No asset bundle\n"); console::flush();
+            return Ok(());
+        }
+    };
+    console::print("Asset bundle: "); console::printint(header.count as i32);
+    console::print(" assets, len "); console::printint(header.length as i32); console::print("\n");
+    for_each_entry(|entry| {
+        console::print("  "); console::buffer(core::str::from_utf8(entry.name()).unwrap_or("?"));
+        console::print(match entry.kind {
+            ASSET_IMAGE     => " image",
+            ASSET_FONT      => " font",
+            ASSET_ANIMATION => " animation",
+            _               => " unknown",
+        });
+        console::print(", len "); console::printint(entry.length as i32);
+        console::print(", crc "); print_hex32(entry.checksum); console::print("\n");
+    }) ? ;
+    console::flush();
+    Ok(())
+}
+
+/// Return true if the bundle region at `offset` already contains the bytes in `data`
+fn region_matches(offset: u32, data: &[u8]) -> MynewtResult<bool> {
+    let mut done: usize = 0;
+    while done < data.len() {
+        let len = core::cmp::min(READ_SIZE, data.len() - done);
+        let buf = unsafe { &mut READ_BUFFER[..len] };
+        BUNDLE_REGION.read(offset + done as u32, buf) ? ;
+        if buf != &data[done..done + len] { return Ok(false); }
+        done += len;
+    }
+    Ok(true)
+}
+
+/// Return the CRC32 of the `len` bytes of the bundle region at `offset`. The bytes are read in chunks.
+fn region_checksum(offset: u32, len: u32) -> MynewtResult<u32> {
+    let mut crc = Crc32::new();
+    let mut done: u32 = 0;
+    while done < len {
+        let size = core::cmp::min(READ_SIZE as u32, len - done) as usize;
+        let buf = unsafe { &mut READ_BUFFER[..size] };
+        BUNDLE_REGION.read(offset + done, buf) ? ;
+        crc.update(buf);
+        done += size as u32;
+    }
+    Ok(crc.finish())
+}
+
+/// Return the little-endian `u16` at `offset` in `data`
+fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([ data[offset], data[offset + 1] ])
+}
+
+/// Return the little-endian `u32` at `offset` in `data`
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([ data[offset], data[offset + 1], data[offset + 2], data[offset + 3] ])
+}
@@ -0,0 +1,67 @@
+//!  Convert logos that are uploaded as 24-bit RGB888 pixels to RGB565 as they arrive, with optional Floyd–Steinberg
+//!  dithering so that gradients in the logo don't show banding on the panel. The conversion is done by
+//!  `mynewt::util::rgb565`. Chunks may end in the middle of a row, so the bytes of the current row are kept until
+//!  the row is complete. The converter takes about 4 KB of RAM: the row, the converted row and two rows of errors.
+
+use mynewt::{
+    result::*,
+    util::rgb565::Rgb565Converter,
+};
+use super::header::{ LOGO_WIDTH, LOGO_HEIGHT };
+
+/// Number of bytes in a row of RGB888 pixels
+const SRC_ROW_SIZE: usize = LOGO_WIDTH as usize * 3;
+
+/// Number of bytes in a row of RGB565 pixels
+const DEST_ROW_SIZE: usize = LOGO_WIDTH as usize * 2;
+
+/// Number of bytes in a 240 x 240 RGB888 logo, as uploaded
+pub const RGB888_LOGO_SIZE: u32 = LOGO_HEIGHT as u32 * SRC_ROW_SIZE as u32;
+
+/// State of the conversion in progress
+struct Conversion {
+    /// Converts each row and keeps the dithering errors for the next row
+    converter: Rgb565Converter,
+    /// Bytes of the current row received so far
+    row:       [u8; SRC_ROW_SIZE],
+    /// Number of bytes in `row`
+    len:       usize,
+    /// Current row converted to RGB565
+    converted: [u8; DEST_ROW_SIZE],
+}
+
+/// The conversion in progress. Only one upload at a time.
+static mut CONVERSION: Conversion = Conversion {
+    converter: Rgb565Converter::new(false),
+    row:       [0; SRC_ROW_SIZE],
+    len:       0,
+    converted: [0; DEST_ROW_SIZE],
+};
+
+/// Start converting a logo at the first row. If `dither` is true, Floyd–Steinberg dithering is applied.
+pub fn start(dither: bool) {
+    let conversion = unsafe { &mut CONVERSION };
+    conversion.converter.reset(dither);
+    conversion.len = 0;
+}
+
+/// Add the RGB888 bytes in `data` to the logo being converted. For each row that is completed, call `write_row()`
+/// with the RGB565 pixels of the row.
+pub fn convert<F>(mut data: &[u8], mut write_row: F) -> MynewtResult<()>
+where F: FnMut(&[u8]) -> MynewtResult<()> {
+    let conversion = unsafe { &mut CONVERSION };
+    while !data.is_empty() {
+        //  Fill the current row.
+        let len = core::cmp::min(SRC_ROW_SIZE - conversion.len, data.len());
+        conversion.row[conversion.len..conversion.len + len].copy_from_slice(&data[..len]);
+        conversion.len += len;
+        data = &data[len..];
+        if conversion.len < SRC_ROW_SIZE { break; }
+
+        //  Convert and write the completed row.
+        conversion.converter.convert_row(&conversion.row, &mut conversion.converted);
+        conversion.len = 0;
+        write_row(&conversion.converted) ? ;
+    }
+    Ok(())
+}
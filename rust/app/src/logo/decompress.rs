@@ -0,0 +1,75 @@
+//!  Decompress logos that are stored in SPI Flash with heatshrink (`LOGO_FORMAT_HEATSHRINK`), so that more assets
+//!  fit in the 4 MB SPI Flash. Calls the streaming decoder in `libs/pinetime_logo`, which is shared with the
+//!  bootloader. The decoder reads 64 compressed bytes at a time and keeps a 256-byte window of decompressed
+//!  bytes, so the logo is decompressed in small chunks without buffering the whole frame.
+
+use mynewt::{
+    result::*,
+};
+use super::LOGO_REGION;
+
+/// Number of compressed bytes read from SPI Flash in a batch. Must sync with `PINETIME_LOGO_DECODER_INPUT_SIZE` in C.
+const DECODER_INPUT_SIZE: usize = 64;
+
+/// Number of decompressed bytes kept for back-references. Must sync with `PINETIME_LOGO_WINDOW_SIZE` in C.
+const DECODER_WINDOW_SIZE: usize = 256;
+
+/// State of the streaming decoder. Must sync with `struct pinetime_logo_decoder` in C.
+#[repr(C)]
+struct Decoder {
+    offset:        u32,
+    end:           u32,
+    input_len:     u16,
+    input_pos:     u16,
+    head:          u16,
+    backref_index: u16,
+    backref_count: u16,
+    flash_id:      u8,
+    bit_mask:      u8,
+    current_byte:  u8,
+    reserved:      [u8; 3],
+    input:         [u8; DECODER_INPUT_SIZE],
+    window:        [u8; DECODER_WINDOW_SIZE],
+}
+
+/// The decoder for the logo being decompressed. Only one logo at a time.
+static mut DECODER: Decoder = Decoder {
+    offset:        0,
+    end:           0,
+    input_len:     0,
+    input_pos:     0,
+    head:          0,
+    backref_index: 0,
+    backref_count: 0,
+    flash_id:      0,
+    bit_mask:      0,
+    current_byte:  0,
+    reserved:      [0; 3],
+    input:         [0; DECODER_INPUT_SIZE],
+    window:        [0; DECODER_WINDOW_SIZE],
+};
+
+/// Start decompressing the `length` bytes of compressed logo in SPI Flash at `base`
+pub fn start(base: u32, length: u32) {
+    unsafe { pinetime_logo_decoder_init(&mut DECODER, LOGO_REGION.flash_id, LOGO_REGION.offset + base, length); }
+}
+
+/// Decompress the next bytes of the logo into `buf`. Returns the number of bytes decompressed,
+/// which is less than the size of `buf` only at the end of the logo.
+pub fn read(buf: &mut [u8]) -> MynewtResult<usize> {
+    assert!(buf.len() <= u16::max_value() as usize, "decompress buf");
+    let rc = unsafe { pinetime_logo_decoder_read(&mut DECODER, buf.as_mut_ptr(), buf.len() as u16) };
+    if rc < 0 { return Err(MynewtError::SYS_EIO); }
+    Ok(rc as usize)
+}
+
+///  Import the logo decoder from `libs/pinetime_logo`
+extern {
+    ///  Start decoding the `length` bytes of compressed logo in flash device `flash_id` at `offset`.
+    ///  C API: `void pinetime_logo_decoder_init(struct pinetime_logo_decoder *decoder, uint8_t flash_id, uint32_t offset, uint32_t length)`
+    fn pinetime_logo_decoder_init(decoder: *mut Decoder, flash_id: u8, offset: u32, length: u32);
+
+    ///  Decompress up to `len` bytes into `out`. Returns the number of bytes decompressed, or a negative error code.
+    ///  C API: `int pinetime_logo_decoder_read(struct pinetime_logo_decoder *decoder, uint8_t *out, uint16_t len)`
+    fn pinetime_logo_decoder_read(decoder: *mut Decoder, out: *mut u8, len: u16) -> i32;
+}
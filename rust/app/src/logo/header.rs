@@ -0,0 +1,169 @@
+//!  Logo header that is written at the end of each logo slot, so that the bootloader can validate the logo
+//!  before displaying it. The header and the validator are defined in `libs/pinetime_logo`, which is shared
+//!  with the bootloader. Must sync with `libs/pinetime_logo/include/pinetime_logo/pinetime_logo.h`
+
+use mynewt::{
+    result::*,
+    sys::console,
+};
+use super::{
+    LOGO_REGION, BATCH_SIZE,
+    index::LOGO_SLOT_SIZE,
+    relocate,
+};
+
+/// Magic number that marks a valid logo header: `LGHD`
+const LOGO_HEADER_MAGIC: u32 = 0x4448_474c;
+
+/// Version of the logo header format
+const LOGO_HEADER_VERSION: u16 = 1;
+
+/// Size of the logo header. The header is stored in the last bytes of the logo slot.
+pub const LOGO_HEADER_SIZE: u32 = 32;
+
+/// Logo format: Raw RGB565 pixels, 2 bytes per pixel, row by row
+pub const LOGO_FORMAT_RGB565: u16 = 1;
+
+/// Logo format: RGB565 pixels compressed with heatshrink (window 2^8, lookahead 2^4), decompressed by `logo/decompress.rs`
+pub const LOGO_FORMAT_HEATSHRINK: u16 = 2;
+
+/// Logo width in pixels
+pub const LOGO_WIDTH: u16 = 240;
+
+/// Logo height in pixels
+pub const LOGO_HEIGHT: u16 = 240;
+
+/// Logo header stored at the end of the logo slot. Must sync with `struct pinetime_logo_header` in C.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+pub struct LogoHeader {
+    /// Must be `LOGO_HEADER_MAGIC`
+    pub magic:    u32,
+    /// Must be `LOGO_HEADER_VERSION`
+    pub version:  u16,
+    /// `LOGO_FORMAT_RGB565` or `LOGO_FORMAT_HEATSHRINK`
+    pub format:   u16,
+    /// Width of the logo in pixels
+    pub width:    u16,
+    /// Height of the logo in pixels
+    pub height:   u16,
+    /// Length of the logo in bytes, as stored in SPI Flash
+    pub length:   u32,
+    /// CRC32 of the logo, as stored in SPI Flash
+    pub checksum: u32,
+    /// Reserved, set to 0
+    pub reserved: [u32; 3],
+}
+
+impl LogoHeader {
+    /// Return the header for a 240 x 240 RGB565 `logo`
+    pub fn new(logo: &[u8]) -> Self {
+        Self::with_checksum(logo.len() as u32, super::checksum(logo))
+    }
+
+    /// Return the header for a 240 x 240 RGB565 logo with `length` bytes and CRC32 `checksum`
+    pub fn with_checksum(length: u32, checksum: u32) -> Self {
+        Self::with_format(LOGO_FORMAT_RGB565, length, checksum)
+    }
+
+    /// Return the header for a 240 x 240 logo in `format` with `length` bytes and CRC32 `checksum`, as stored in SPI Flash
+    pub fn with_format(format: u16, length: u32, checksum: u32) -> Self {
+        LogoHeader {
+            magic:    LOGO_HEADER_MAGIC,
+            version:  LOGO_HEADER_VERSION,
+            format,
+            width:    LOGO_WIDTH,
+            height:   LOGO_HEIGHT,
+            length,
+            checksum,
+            reserved: [0; 3],
+        }
+    }
+
+    /// Return the header as bytes for writing to SPI Flash
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const LogoHeader as *const u8,
+                core::mem::size_of::<LogoHeader>()
+            )
+        }
+    }
+
+    /// Return the header as mutable bytes for reading from SPI Flash
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self as *mut LogoHeader as *mut u8,
+                core::mem::size_of::<LogoHeader>()
+            )
+        }
+    }
+}
+
+/// Return the offset in SPI Flash of the header for the logo slot at `base`
+pub fn header_offset(base: u32) -> u32 {
+    base + LOGO_SLOT_SIZE - LOGO_HEADER_SIZE
+}
+
+/// Read the header of the logo slot at `base`. The header has not been validated.
+pub fn read_header(base: u32) -> MynewtResult<LogoHeader> {
+    let mut header = LogoHeader::with_checksum(0, 0);
+    relocate::read(header_offset(base), header.as_bytes_mut()) ? ;
+    Ok(header)
+}
+
+/// Write the header for `logo` into the logo slot at `base`. The logo must have been written with `flash_logo()`.
+/// If the header is unchanged, nothing is written. Since the header shares the last sector of the slot with
+/// the end of the logo, that part of the logo is written again after erasing the sector.
+pub fn write_header(base: u32, logo: &[u8]) -> MynewtResult<()> {
+    let header = LogoHeader::new(logo);
+    let offset = header_offset(base);
+
+    //  Read the existing header. Skip the write if unchanged.
+    let mut old = header;
+    relocate::read(offset, old.as_bytes_mut()) ? ;
+    if old == header { return Ok(()); }
+
+    //  If the header area is not blank, erase the last sector and write back the end of the logo.
+    if old.as_bytes().iter().any(|b| *b != 0xff) {
+        let sector = base + LOGO_SLOT_SIZE - BATCH_SIZE as u32;
+        relocate::erase_sector(sector) ? ;
+        let tail_start = (sector - base) as usize;
+        if logo.len() > tail_start {
+            relocate::write(sector, &logo[tail_start..]) ? ;
+        }
+    }
+    write_erased_header(base, &header)
+}
+
+/// Write `header` into the logo slot at `base`. The header area must have been erased.
+pub fn write_erased_header(base: u32, header: &LogoHeader) -> MynewtResult<()> {
+    relocate::write(header_offset(base), header.as_bytes())
+}
+
+/// Validate the logo slot at `base` with the validator shared with the bootloader.
+/// Checks the header and the CRC32 of the logo. Returns `Ok(true)` if the bootloader will display the logo.
+pub fn validate(base: u32) -> MynewtResult<bool> {
+    let rc = unsafe { pinetime_logo_validate(
+        LOGO_REGION.flash_id,                       //  Flash device
+        LOGO_REGION.offset + base,                  //  Absolute offset of logo
+        LOGO_REGION.offset + header_offset(base)    //  Absolute offset of header
+    ) };
+    if rc == PINETIME_LOGO_EREAD { return Err(MynewtError::SYS_EIO); }
+    console::print("Logo header ");
+    console::print(if rc == 0 { "OK\n" } else { "INVALID\n" });
+    console::flush();
+    Ok(rc == 0)
+}
+
+/// Result code from `pinetime_logo_validate()` when flash read fails
+const PINETIME_LOGO_EREAD: i32 = -1;
+
+///  Import the logo validator from `libs/pinetime_logo`
+extern {
+    ///  Validate the logo in flash device `flash_id` at `logo_offset`, with the header at `header_offset`.
+    ///  Returns 0 if the logo is valid.
+    ///  C API: `int pinetime_logo_validate(uint8_t flash_id, uint32_t logo_offset, uint32_t header_offset)`
+    fn pinetime_logo_validate(flash_id: u8, logo_offset: u32, header_offset: u32) -> i32;
+}
@@ -0,0 +1,300 @@
+//!  Index table for storing multiple logos in External SPI Flash. Each logo is stored in a slot.
+//!  The index table records the name, offset, length and checksum of each slot, and the slot
+//!  that will be displayed by the bootloader. Must sync with `libs/pinetime_boot/include/pinetime_boot/pinetime_boot.h`
+//!
+//!  SPI Flash layout for the Bootloader Assets area (256 KB), before the Standby Firmware Image at 0x40000:
+//!  ```text
+//!  0x00000  Slot 0 (default logo), logo header at 0x1CFE0
+//!  0x1D000  Slot 1, logo header at 0x39FE0
+//!  0x3A000  Index table (1 sector)
+//!  0x3B000  Journal for resuming an interrupted flash (1 sector)
+//!  0x3C000  Spare sectors for relocating bad sectors (4 sectors)
+//!  ```
+
+use mynewt::{
+    result::*,
+    hw::flash::map::Storage,
+    sys::console,
+};
+use super::{
+    LOGO_REGION,
+    header, journal,
+    manifest::LogoManifest,
+    relocate::{ self, LogoRelocation, MAX_RELOCATIONS },
+    upload,
+    flash_checksum, flash_logo, show_progress, verify_logo, BATCH_SIZE,
+};
+
+/// Max number of logo slots. Must sync with `LOGO_MGMT_MAX_SLOTS` in `logo_mgmt.c`.
+pub const MAX_LOGO_SLOTS: usize = 2;
+
+/// Slot for the built-in default logo
+pub const DEFAULT_SLOT: u8 = 0;
+
+/// Size of each logo slot: 29 sectors of 4 KB, enough for a 240 x 240 RGB565 logo (115,200 bytes)
+/// plus the logo header in the last bytes of the slot
+pub const LOGO_SLOT_SIZE: u32 = 0x1D000;
+
+/// Offset of the index table in SPI Flash, after the last logo slot
+pub const LOGO_INDEX_OFFSET: u32 = LOGO_SLOT_SIZE * MAX_LOGO_SLOTS as u32;
+
+/// Size of the index table sector
+const LOGO_INDEX_SECTOR_SIZE: u32 = 4096;
+
+/// Magic number that marks a valid index table: `LOGO`
+const LOGO_INDEX_MAGIC: u32 = 0x4c4f_474f;
+
+/// Max length of a logo name, including the terminating null
+pub const LOGO_NAME_SIZE: usize = 16;
+
+/// Index table stored in SPI Flash. Must sync with `struct pinetime_boot_logo_index` in C.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LogoIndex {
+    /// Must be `LOGO_INDEX_MAGIC`
+    pub magic:  u32,
+    /// Slot to be displayed by the bootloader
+    pub active: u8,
+    /// Reserved, set to 0
+    pub reserved: [u8; 3],
+    /// Metadata for each slot
+    pub slots:  [LogoSlot; MAX_LOGO_SLOTS],
+    /// Manifest for each slot
+    pub manifests: [LogoManifest; MAX_LOGO_SLOTS],
+    /// Bad sectors that have been relocated to spare sectors
+    pub relocations: [LogoRelocation; MAX_RELOCATIONS],
+}
+
+/// Metadata for a logo slot. Must sync with `struct pinetime_boot_logo_slot` in C and `struct logo_slot` in `logo_mgmt.c`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct LogoSlot {
+    /// Null-terminated name of the logo. Empty if the slot is unused.
+    pub name:     [u8; LOGO_NAME_SIZE],
+    /// Offset of the logo in SPI Flash
+    pub offset:   u32,
+    /// Length of the logo in bytes
+    pub length:   u32,
+    /// CRC32 of the logo
+    pub checksum: u32,
+}
+
+impl LogoSlot {
+    /// Return true if the slot contains a logo
+    pub fn is_used(&self) -> bool {
+        self.name[0] != 0 && self.name[0] != 0xff && self.length > 0
+    }
+}
+
+impl LogoIndex {
+    /// Return an empty index table with all slots unused
+    pub fn new() -> Self {
+        let mut index = LogoIndex {
+            magic:    LOGO_INDEX_MAGIC,
+            active:   DEFAULT_SLOT,
+            reserved: [0; 3],
+            slots:    [ LogoSlot { name: [0; LOGO_NAME_SIZE], offset: 0, length: 0, checksum: 0 }; MAX_LOGO_SLOTS ],
+            manifests: [ LogoManifest::empty(); MAX_LOGO_SLOTS ],
+            relocations: [ LogoRelocation::unused(); MAX_RELOCATIONS ],
+        };
+        for (i, slot) in index.slots.iter_mut().enumerate() {
+            slot.offset = slot_offset(i as u8);
+        }
+        index
+    }
+
+    /// Return the index table as bytes for writing to SPI Flash
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            core::slice::from_raw_parts(
+                self as *const LogoIndex as *const u8,
+                core::mem::size_of::<LogoIndex>()
+            )
+        }
+    }
+
+    /// Return the index table as mutable bytes for reading from SPI Flash
+    fn as_bytes_mut(&mut self) -> &mut [u8] {
+        unsafe {
+            core::slice::from_raw_parts_mut(
+                self as *mut LogoIndex as *mut u8,
+                core::mem::size_of::<LogoIndex>()
+            )
+        }
+    }
+}
+
+/// Return the offset in SPI Flash of the logo slot
+pub fn slot_offset(slot: u8) -> u32 {
+    LOGO_SLOT_SIZE * slot as u32
+}
+
+/// Read the index table from SPI Flash. If the index table has not been written, return an empty index table.
+pub fn read_index() -> MynewtResult<LogoIndex> {
+    let mut index = LogoIndex::new();
+    LOGO_REGION.read(LOGO_INDEX_OFFSET, index.as_bytes_mut()) ? ;
+    if index.magic != LOGO_INDEX_MAGIC || index.active as usize >= MAX_LOGO_SLOTS {
+        let index = LogoIndex::new();
+        relocate::load(&index.relocations);
+        return Ok(index);
+    }
+    relocate::load(&index.relocations);
+    Ok(index)
+}
+
+/// Write the index table to SPI Flash
+pub fn write_index(index: &LogoIndex) -> MynewtResult<()> {
+    LOGO_REGION.erase(LOGO_INDEX_OFFSET, LOGO_INDEX_SECTOR_SIZE) ? ;
+    LOGO_REGION.write(LOGO_INDEX_OFFSET, index.as_bytes())
+}
+
+/// Clear the index table: all slots become unused and the bootloader will display the logo in the default slot.
+/// Relocated sectors are kept, since the bad sectors remain bad.
+pub fn clear_index() -> MynewtResult<()> {
+    let relocations = read_index() ? .relocations;
+    let mut index = LogoIndex::new();
+    index.relocations = relocations;
+    write_index(&index)
+}
+
+/// Record the relocated sectors in the index table
+pub fn save_relocations(relocations: [LogoRelocation; MAX_RELOCATIONS]) -> MynewtResult<()> {
+    let mut index = read_index() ? ;
+    index.relocations = relocations;
+    write_index(&index) ? ;
+    relocate::load(&relocations);
+    Ok(())
+}
+
+/// Select the logo slot to be displayed by the bootloader. The slot must contain a logo.
+pub fn select_slot(slot: u8) -> MynewtResult<()> {
+    if slot as usize >= MAX_LOGO_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    let mut index = read_index() ? ;
+    if !index.slots[slot as usize].is_used() { return Err(MynewtError::SYS_ENOENT); }
+    if index.active == slot { return Ok(()); }  //  Already selected
+    index.active = slot;
+    write_index(&index)
+}
+
+/// Erase the logo slot and mark it unused in the index table. If the slot was selected for display, the default slot
+/// is selected instead. Any upload in progress is abandoned, since it may be writing to the slot.
+pub fn erase_slot(slot: u8) -> MynewtResult<()> {
+    if slot as usize >= MAX_LOGO_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    upload::abort();
+    erase_sectors(slot) ? ;
+
+    //  Don't resume an interrupted flash into the erased slot.
+    if let Some((pending, _, _)) = journal::pending() ? {
+        if pending == slot { journal::complete() ? ; }
+    }
+    let mut index = read_index() ? ;
+    index.slots[slot as usize] = LogoSlot { name: [0; LOGO_NAME_SIZE], offset: slot_offset(slot), length: 0, checksum: 0 };
+    index.manifests[slot as usize] = LogoManifest::empty();
+    if index.active == slot { index.active = DEFAULT_SLOT; }
+    write_index(&index)
+}
+
+/// Erase every sector of the logo slot, not just the logo, so that no trace of the old logo remains
+pub fn erase_sectors(slot: u8) -> MynewtResult<()> {
+    let base = slot_offset(slot);
+    let mut offset: u32 = 0;
+    while offset < LOGO_SLOT_SIZE {
+        relocate::erase_sector(base + offset) ? ;
+        offset += BATCH_SIZE as u32;
+    }
+    Ok(())
+}
+
+/// Read back the logo in the slot and compare the CRC32 with the checksum in the index table, then check the logo
+/// header with the validator shared with the bootloader. Returns the CRC32 of the logo in SPI Flash, and true if the
+/// logo is intact. Returns `SYS_ENOENT` if the slot is empty.
+pub fn verify_slot(slot: u8) -> MynewtResult<(u32, bool)> {
+    if slot as usize >= MAX_LOGO_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    let entry = read_index() ? .slots[slot as usize];
+    if !entry.is_used() { return Err(MynewtError::SYS_ENOENT); }
+    let base = slot_offset(slot);
+    let crc = flash_checksum(base, entry.length as usize) ? ;
+    let valid = crc == entry.checksum && header::validate(base) ? ;
+    Ok((crc, valid))
+}
+
+/// Write `logo` into the logo slot and record `name`, length, checksum and the manifest with image `version` and
+/// `timestamp` in the index table. Returns `Ok(true)` if the written logo has been verified.
+pub fn store_logo(slot: u8, name: &[u8], version: &[u8], timestamp: u32, logo: &[u8]) -> MynewtResult<bool> {
+    if slot as usize >= MAX_LOGO_SLOTS || logo.len() as u32 > LOGO_SLOT_SIZE - header::LOGO_HEADER_SIZE {
+        return Err(MynewtError::SYS_EINVAL);
+    }
+    let base = slot_offset(slot);
+    //  Never overwrite a firmware image.
+    if crate::mcuboot::would_overwrite_image(&LOGO_REGION, base, LOGO_SLOT_SIZE) ? {
+        return Err(MynewtError::SYS_EACCES);
+    }
+    console::print("Writing logo to slot ");
+    console::printint(slot as i32); console::print("...\n"); console::flush();
+
+    //  Resume from the last completed sector if the previous write of the same logo was interrupted.
+    let checksum = super::checksum(logo);
+    let resume = journal::start(slot, logo.len() as u32, checksum) ? ;
+    let skipped = flash_logo(base, logo, resume as usize, |done, total| {
+        show_progress(done, total);
+        //  If the journal can't be updated, we will just resume from an earlier sector.
+        journal::mark_progress(done, total).ok();
+    }) ? ;
+    console::print("Logo written to flash, unchanged sectors skipped: ");
+    console::printint(skipped as i32); console::print("\n"); console::flush();
+
+    //  Read back the logo and check that it matches the source.
+    let verified = verify_logo(base, logo) ? ;
+    if !verified { return Ok(false); }
+
+    //  Write the logo header and check that the bootloader will accept the logo.
+    header::write_header(base, logo) ? ;
+    let valid = header::validate(base) ? ;
+    if !valid { return Ok(false); }
+
+    //  Record the slot in the index table.
+    record_slot(slot, name, logo.len() as u32, checksum, &LogoManifest::new(version, checksum, timestamp)) ? ;
+    journal::complete() ? ;
+    Ok(true)
+}
+
+/// Record `name`, `length`, `checksum` and `manifest` of the logo slot in the index table. The index table is not written if unchanged.
+pub fn record_slot(slot: u8, name: &[u8], length: u32, checksum: u32, manifest: &LogoManifest) -> MynewtResult<()> {
+    if slot as usize >= MAX_LOGO_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    let mut index = read_index() ? ;
+    let mut entry = LogoSlot {
+        name:     [0; LOGO_NAME_SIZE],
+        offset:   slot_offset(slot),
+        length,
+        checksum,
+    };
+    let len = core::cmp::min(name.len(), LOGO_NAME_SIZE - 1);  //  Leave space for the terminating null
+    entry.name[..len].copy_from_slice(&name[..len]);
+    let old = index.slots[slot as usize];
+    if old.name == entry.name && old.length == entry.length && old.checksum == entry.checksum
+        && index.manifests[slot as usize] == *manifest {
+        return Ok(());  //  Index table is unchanged
+    }
+    index.slots[slot as usize] = entry;
+    index.manifests[slot as usize] = *manifest;
+    write_index(&index)
+}
+
+/// Display the index table on the console
+pub fn show_index() -> MynewtResult<()> {
+    let index = read_index() ? ;
+    for (i, slot) in index.slots.iter().enumerate() {
+        console::print(if i == index.active as usize { "* " } else { "  " });
+        console::printint(i as i32); console::print(": ");
+        if slot.is_used() {
+            let len = slot.name.iter().position(|c| *c == 0).unwrap_or(LOGO_NAME_SIZE);
+            console::buffer(core::str::from_utf8(&slot.name[..len]).unwrap_or("?"));
+            console::print(", len "); console::printint(slot.length as i32);
+        } else {
+            console::print("(empty)");
+        }
+        console::print("\n");
+    }
+    console::flush();
+    Ok(())
+}
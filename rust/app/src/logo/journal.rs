@@ -0,0 +1,134 @@
+//!  Journal that records which sectors of a logo have been written, so that flashing resumes from the last
+//!  completed sector after the battery dies or the watch resets mid-flash. The journal occupies one sector
+//!  after the index table. Progress marks are written without erasing: each mark is a byte that changes from
+//!  `0xff` (erased) to `0x00` (sector done), which NOR flash allows at any time.
+
+use mynewt::{
+    result::*,
+    hw::flash::map::Storage,
+    sys::console,
+};
+use super::{
+    LOGO_REGION, BATCH_SIZE,
+    index::{ LOGO_INDEX_OFFSET, LOGO_SLOT_SIZE },
+};
+
+/// Offset of the journal in SPI Flash, in the sector after the index table
+pub const LOGO_JOURNAL_OFFSET: u32 = LOGO_INDEX_OFFSET + 0x1000;
+
+/// Size of the journal sector
+const LOGO_JOURNAL_SECTOR_SIZE: u32 = 4096;
+
+/// Magic number that marks a journal in progress: `LGJN`
+const LOGO_JOURNAL_MAGIC: u32 = 0x4e4a_474c;
+
+/// Max number of sectors in a logo slot
+const MAX_SECTORS: usize = (LOGO_SLOT_SIZE as usize + BATCH_SIZE - 1) / BATCH_SIZE;
+
+/// Journal header in SPI Flash, followed by one progress mark per sector
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct JournalHeader {
+    /// Must be `LOGO_JOURNAL_MAGIC`
+    magic:    u32,
+    /// Logo slot being written
+    slot:     u8,
+    /// Reserved, set to 0
+    reserved: [u8; 3],
+    /// Length of the logo in bytes
+    length:   u32,
+    /// CRC32 of the logo
+    checksum: u32,
+}
+
+/// Offset of the progress marks in the journal
+const MARKS_OFFSET: u32 = LOGO_JOURNAL_OFFSET + core::mem::size_of::<JournalHeader>() as u32;
+
+/// Value of a progress mark for a completed sector
+const MARK_DONE: u8 = 0x00;
+
+/// Number of sectors marked as done in the journal in progress
+static mut MARKED: usize = 0;
+
+/// Start or resume flashing a logo with `length` bytes and CRC32 `checksum` into `slot`.
+/// If the journal records an interrupted flash of the same logo, returns the offset to resume from.
+/// Otherwise starts a new journal and returns 0.
+pub fn start(slot: u8, length: u32, checksum: u32) -> MynewtResult<u32> {
+    let mut header = JournalHeader { magic: 0, slot: 0, reserved: [0; 3], length: 0, checksum: 0 };
+    LOGO_REGION.read(LOGO_JOURNAL_OFFSET, as_bytes_mut(&mut header)) ? ;
+    if header.magic == LOGO_JOURNAL_MAGIC && header.slot == slot
+        && header.length == length && header.checksum == checksum {
+        //  Same logo: Count the consecutive sectors that have been completed.
+        let mut marks = [0xffu8; MAX_SECTORS];
+        LOGO_REGION.read(MARKS_OFFSET, &mut marks) ? ;
+        let done = marks.iter().take_while(|m| **m == MARK_DONE).count();
+        unsafe { MARKED = done; }
+        let resume = core::cmp::min(done as u32 * BATCH_SIZE as u32, length);
+        if resume > 0 {
+            console::print("Logo journal: resuming at ");
+            console::printint(resume as i32); console::print("\n"); console::flush();
+        }
+        return Ok(resume);
+    }
+    //  Different logo: Start a new journal.
+    let header = JournalHeader {
+        magic: LOGO_JOURNAL_MAGIC,
+        slot,
+        reserved: [0; 3],
+        length,
+        checksum,
+    };
+    LOGO_REGION.erase(LOGO_JOURNAL_OFFSET, LOGO_JOURNAL_SECTOR_SIZE) ? ;
+    LOGO_REGION.write(LOGO_JOURNAL_OFFSET, as_bytes(&header)) ? ;
+    unsafe { MARKED = 0; }
+    Ok(0)
+}
+
+/// Record that `bytes_done` bytes of the logo with `total` bytes have been written.
+/// Every sector that has been completely written is marked as done.
+pub fn mark_progress(bytes_done: usize, total: usize) -> MynewtResult<()> {
+    let completed =
+        if bytes_done >= total { (total + BATCH_SIZE - 1) / BATCH_SIZE }  //  Last sector may be partial
+        else { bytes_done / BATCH_SIZE };
+    let completed = core::cmp::min(completed, MAX_SECTORS);
+    let marked = unsafe { &mut MARKED };
+    while *marked < completed {
+        LOGO_REGION.write(MARKS_OFFSET + *marked as u32, &[MARK_DONE]) ? ;
+        *marked += 1;
+    }
+    Ok(())
+}
+
+/// Erase the journal when the logo has been written and verified
+pub fn complete() -> MynewtResult<()> {
+    unsafe { MARKED = 0; }
+    LOGO_REGION.erase(LOGO_JOURNAL_OFFSET, LOGO_JOURNAL_SECTOR_SIZE)
+}
+
+/// Return the slot, length and CRC32 of the logo that was being flashed when interrupted, or `None` if no flashing was interrupted
+pub fn pending() -> MynewtResult<Option<(u8, u32, u32)>> {
+    let mut header = JournalHeader { magic: 0, slot: 0, reserved: [0; 3], length: 0, checksum: 0 };
+    LOGO_REGION.read(LOGO_JOURNAL_OFFSET, as_bytes_mut(&mut header)) ? ;
+    if header.magic != LOGO_JOURNAL_MAGIC { return Ok(None); }
+    Ok(Some((header.slot, header.length, header.checksum)))
+}
+
+/// Return the journal header as bytes for writing to SPI Flash
+fn as_bytes(header: &JournalHeader) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            header as *const JournalHeader as *const u8,
+            core::mem::size_of::<JournalHeader>()
+        )
+    }
+}
+
+/// Return the journal header as mutable bytes for reading from SPI Flash
+fn as_bytes_mut(header: &mut JournalHeader) -> &mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            header as *mut JournalHeader as *mut u8,
+            core::mem::size_of::<JournalHeader>()
+        )
+    }
+}
@@ -0,0 +1,174 @@
+//!  Manifest that is recorded in the index table with each logo: format version, image version string, CRC32
+//!  of the logo and timestamp. Tooling queries the manifest over newtmgr / SMP (`logo/serial.rs`) and the
+//!  server receives it over CoAP, to tell which logo a device has installed.
+
+use mynewt::{
+    result::*,
+    hw::sensor::{
+        SensorValue, SensorValueType,
+    },
+    sys::console,
+    encoding::coap_context::*,
+    libs::sensor_network,
+    coap, d, Strn,
+};
+use mynewt_macros::{ init_strn, strn };
+use crate::settings;
+use super::{
+    checksum,
+    index::{ self, MAX_LOGO_SLOTS },
+};
+
+/// Version of the manifest format
+pub const MANIFEST_FORMAT_VERSION: u16 = 1;
+
+/// Max length of the image version string, including the terminating null
+pub const MANIFEST_VERSION_SIZE: usize = 16;
+
+/// Manifest for a logo slot, stored in the index table. Must sync with `struct pinetime_boot_logo_manifest` in C.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+pub struct LogoManifest {
+    /// Must be `MANIFEST_FORMAT_VERSION`
+    pub format_version: u16,
+    /// Reserved, set to 0
+    pub reserved:       u16,
+    /// Null-terminated image version string, e.g. `1.2.0`
+    pub version:        [u8; MANIFEST_VERSION_SIZE],
+    /// CRC32 of the logo
+    pub logo_crc:       u32,
+    /// Time that the logo was created, in seconds since 1970. 0 if unknown.
+    pub timestamp:      u32,
+    /// CRC32 of the fields above
+    pub manifest_crc:   u32,
+}
+
+impl LogoManifest {
+    /// Return the manifest for a logo with CRC32 `logo_crc`, image version string `version` and `timestamp`
+    pub fn new(version: &[u8], logo_crc: u32, timestamp: u32) -> Self {
+        let mut manifest = Self::empty();
+        manifest.format_version = MANIFEST_FORMAT_VERSION;
+        manifest.logo_crc  = logo_crc;
+        manifest.timestamp = timestamp;
+        let len = core::cmp::min(version.len(), MANIFEST_VERSION_SIZE - 1);  //  Leave space for the terminating null
+        manifest.version[..len].copy_from_slice(&version[..len]);
+        manifest.manifest_crc = manifest.compute_crc();
+        manifest
+    }
+
+    /// Return an empty manifest, for slots without a manifest
+    pub const fn empty() -> Self {
+        LogoManifest {
+            format_version: 0,
+            reserved:       0,
+            version:        [0; MANIFEST_VERSION_SIZE],
+            logo_crc:       0,
+            timestamp:      0,
+            manifest_crc:   0,
+        }
+    }
+
+    /// Return true if the manifest has a supported format version and the manifest CRC32 matches
+    pub fn is_valid(&self) -> bool {
+        self.format_version == MANIFEST_FORMAT_VERSION && self.manifest_crc == self.compute_crc()
+    }
+
+    /// Return the image version string
+    pub fn version_str(&self) -> &str {
+        let len = self.version.iter().position(|c| *c == 0).unwrap_or(MANIFEST_VERSION_SIZE);
+        core::str::from_utf8(&self.version[..len]).unwrap_or("?")
+    }
+
+    /// Compute the CRC32 of the fields before `manifest_crc`
+    fn compute_crc(&self) -> u32 {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                self as *const LogoManifest as *const u8,
+                core::mem::size_of::<LogoManifest>() - core::mem::size_of::<u32>()
+            )
+        };
+        checksum(bytes)
+    }
+}
+
+/// Return the manifest of the logo in the slot, or `None` if the slot has no valid manifest
+pub fn read_manifest(slot: u8) -> MynewtResult<Option<LogoManifest>> {
+    if slot as usize >= MAX_LOGO_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    let index = index::read_index() ? ;
+    let entry = &index.slots[slot as usize];
+    let manifest = index.manifests[slot as usize];
+    if !entry.is_used() || !manifest.is_valid() || manifest.logo_crc != entry.checksum { return Ok(None); }
+    Ok(Some(manifest))
+}
+
+/// Return the manifest of the logo that will be displayed by the bootloader, or `None` if there is no valid manifest
+pub fn active_manifest() -> MynewtResult<Option<LogoManifest>> {
+    let index = index::read_index() ? ;
+    read_manifest(index.active)
+}
+
+/// Display the manifest of each slot on the console
+pub fn show_manifests() -> MynewtResult<()> {
+    for slot in 0..MAX_LOGO_SLOTS as u8 {
+        console::printint(slot as i32); console::print(": ");
+        match read_manifest(slot) ? {
+            None => { console::print("no manifest"); }
+            Some(manifest) => {
+                console::print("ver ");  console::buffer(manifest.version_str());
+                console::print(", crc "); for b in manifest.logo_crc.to_be_bytes().iter() { console::printhex(*b); }
+                console::print(", ts ");  console::printint(manifest.timestamp as i32);
+            }
+        }
+        console::print("\n");
+    }
+    console::flush();
+    Ok(())
+}
+
+/// Buffer for the image version string to be transmitted. Must be null-terminated.
+static mut VERSION_BUF: [u8; MANIFEST_VERSION_SIZE] = [0; MANIFEST_VERSION_SIZE];
+
+/// Key for transmitting the logo CRC32
+static LOGO_CRC_KEY: Strn = init_strn!("logo_crc");
+/// Key for transmitting the logo timestamp
+static LOGO_TS_KEY: Strn  = init_strn!("logo_ts");
+
+/// Send the manifest of the active logo to the CoAP server as `logo_ver`, `logo_crc` and `logo_ts`.
+/// Returns `SYS_ENOENT` if the active logo has no manifest, `SYS_EAGAIN` if network is not ready yet.
+pub fn send_manifest() -> MynewtResult<()> {
+    let manifest = match active_manifest() ? {
+        Some(manifest) => manifest,
+        None           => return Err(MynewtError::SYS_ENOENT),
+    };
+    unsafe { VERSION_BUF = manifest.version; }
+    let version = Strn::from_cstr(unsafe { VERSION_BUF.as_ptr() });
+    let crc = SensorValue {
+        key:   &LOGO_CRC_KEY,
+        value: SensorValueType::Uint(manifest.logo_crc),
+        geo:   SensorValueType::None,
+    };
+    let ts = SensorValue {
+        key:   &LOGO_TS_KEY,
+        value: SensorValueType::Uint(manifest.timestamp),
+        geo:   SensorValueType::None,
+    };
+
+    //  Get a randomly-generated device ID that changes each time we restart the device.
+    let device_id = sensor_network::get_device_id() ? ;
+
+    //  Start composing the CoAP Server message.
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;  //  Empty URI means use default CoAP URI in `syscfg.yml`
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    //  Compose the CoAP Payload with the manifest.
+    let _payload = coap!( @json {
+        "logo_ver": &version,
+        crc,
+        ts,
+        "device": &device_id,
+    });
+
+    //  Post the CoAP Server message to the CoAP Background Task for transmission.
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
@@ -0,0 +1,226 @@
+//!  Handle SPI Flash sectors that fail to erase or program. Every erase is checked by reading back the sector
+//!  and every write is verified, with retries. If a sector in a logo slot is persistently bad, its data is
+//!  relocated to a spare sector and the relocation is noted in the index table, so that the flash continues
+//!  instead of aborting. Reads of the logo follow the relocations, here and in the bootloader, through
+//!  `pinetime_logo_read()` in `libs/pinetime_logo`.
+
+use mynewt::{
+    result::*,
+    hw::flash::map::Storage,
+    sys::console,
+};
+use super::{
+    LOGO_REGION, BATCH_SIZE, READ_SIZE,
+    index, journal,
+};
+
+/// Max number of bad sectors that may be relocated. Must sync with `PINETIME_LOGO_MAX_RELOCATIONS` in C.
+pub const MAX_RELOCATIONS: usize = 4;
+
+/// Offset of the spare sectors in SPI Flash, after the journal
+pub const SPARE_OFFSET: u32 = journal::LOGO_JOURNAL_OFFSET + SECTOR_SIZE;
+
+/// Number of spare sectors, up to the end of the Bootloader Assets area
+const SPARE_SECTORS: u32 = 4;
+
+/// Size of a sector, the unit of relocation. Must sync with `PINETIME_LOGO_SECTOR_SIZE` in C.
+const SECTOR_SIZE: u32 = BATCH_SIZE as u32;
+
+/// Number of attempts to erase or program a sector before relocating it
+const MAX_ATTEMPTS: usize = 3;
+
+/// Offset of an unused relocation entry
+const UNUSED: u32 = 0xffff_ffff;
+
+/// Bad sector that has been relocated to a spare sector. Stored in the index table.
+/// Must sync with `struct pinetime_logo_relocation` in C.
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq)]
+pub struct LogoRelocation {
+    /// Absolute offset of the bad sector in SPI Flash. `UNUSED` if the entry is unused.
+    pub bad_offset:   u32,
+    /// Absolute offset of the spare sector that holds the data
+    pub spare_offset: u32,
+}
+
+impl LogoRelocation {
+    /// Return an unused relocation entry
+    pub const fn unused() -> Self {
+        LogoRelocation { bad_offset: UNUSED, spare_offset: UNUSED }
+    }
+
+    /// Return true if the entry relocates a sector
+    pub fn is_used(&self) -> bool {
+        self.bad_offset != UNUSED
+    }
+}
+
+/// Relocations from the index table. Shared with the logo validator and decoder in C.
+static mut RELOCATIONS: [LogoRelocation; MAX_RELOCATIONS] = [LogoRelocation::unused(); MAX_RELOCATIONS];
+
+/// True if `RELOCATIONS` has been loaded from the index table
+static mut LOADED: bool = false;
+
+/// Buffer for verifying and copying sectors
+static mut VERIFY_BUFFER: [u8; READ_SIZE] = [0; READ_SIZE];
+
+/// Use the relocations from the index table for reads and writes. Called by `index::read_index()`.
+pub fn load(relocations: &[LogoRelocation; MAX_RELOCATIONS]) {
+    unsafe {
+        RELOCATIONS = *relocations;
+        LOADED = true;
+        pinetime_logo_set_relocations(RELOCATIONS.as_ptr(), MAX_RELOCATIONS as i32);
+    }
+}
+
+/// Return the relocations, loading them from the index table if needed
+fn relocations() -> MynewtResult<&'static mut [LogoRelocation; MAX_RELOCATIONS]> {
+    if unsafe { !LOADED } { index::read_index() ? ; }
+    Ok(unsafe { &mut RELOCATIONS })
+}
+
+/// Return the offset in SPI Flash that holds the data for `offset`, after relocation
+pub fn map(offset: u32) -> MynewtResult<u32> {
+    let sector = offset - offset % SECTOR_SIZE;
+    let absolute = LOGO_REGION.offset + sector;
+    for r in relocations() ? .iter() {
+        if r.bad_offset == absolute { return Ok(r.spare_offset - LOGO_REGION.offset + offset - sector); }
+    }
+    Ok(offset)
+}
+
+/// Read `buf.len()` bytes of logo data at `offset` into `buf`, following relocations
+pub fn read(offset: u32, buf: &mut [u8]) -> MynewtResult<()> {
+    let mut done: usize = 0;
+    while done < buf.len() {
+        //  Read up to the end of the sector.
+        let addr = offset + done as u32;
+        let len = core::cmp::min((SECTOR_SIZE - addr % SECTOR_SIZE) as usize, buf.len() - done);
+        LOGO_REGION.read(map(addr) ? , &mut buf[done..done + len]) ? ;
+        done += len;
+    }
+    Ok(())
+}
+
+/// Erase the sector at `sector` and check that it's blank. If the sector can't be erased after retries,
+/// relocate it to an erased spare sector. Returns `SYS_EAGAIN` without erasing if the power may be lost.
+pub fn erase_sector(sector: u32) -> MynewtResult<()> {
+    crate::power_fail::check(sector) ? ;
+    let physical = map(sector) ? ;
+    for _ in 0..MAX_ATTEMPTS {
+        if LOGO_REGION.erase(physical, SECTOR_SIZE).is_ok() && is_blank(physical, SECTOR_SIZE) ? {
+            return Ok(());
+        }
+    }
+    relocate_sector(sector, 0)
+}
+
+/// Write `data` at `offset` and verify the written data, following relocations. The sectors must have been erased
+/// with `erase_sector()`. If a sector can't be programmed after retries, relocate it to a spare sector.
+pub fn write(offset: u32, data: &[u8]) -> MynewtResult<()> {
+    let mut done: usize = 0;
+    while done < data.len() {
+        //  Write up to the end of the sector.
+        let addr = offset + done as u32;
+        let len = core::cmp::min((SECTOR_SIZE - addr % SECTOR_SIZE) as usize, data.len() - done);
+        let chunk = &data[done..done + len];
+        if !program(map(addr) ? , chunk) ? {
+            //  Sector is bad: Move the bytes written so far in this sector to a spare sector and write there.
+            relocate_sector(addr - addr % SECTOR_SIZE, addr % SECTOR_SIZE) ? ;
+            if !program(map(addr) ? , chunk) ? { return Err(MynewtError::SYS_EIO); }
+        }
+        done += len;
+    }
+    Ok(())
+}
+
+/// Write `data` at `physical` with retries. Returns `Ok(true)` if the written data has been verified.
+fn program(physical: u32, data: &[u8]) -> MynewtResult<bool> {
+    for _ in 0..MAX_ATTEMPTS {
+        //  Programming the same data again only clears the bits that failed to clear.
+        if LOGO_REGION.write(physical, data).is_ok() && matches(physical, data) ? { return Ok(true); }
+    }
+    Ok(false)
+}
+
+/// Relocate the bad `sector` to an erased spare sector, copying the first `keep` bytes from the bad sector,
+/// and record the relocation in the index table. Returns `SYS_ENOMEM` if there are no usable spare sectors.
+fn relocate_sector(sector: u32, keep: u32) -> MynewtResult<()> {
+    let old = map(sector) ? ;
+    let table = relocations() ? ;
+    for i in 0..SPARE_SECTORS {
+        //  Skip the spare sectors that are in use.
+        let spare = SPARE_OFFSET + i * SECTOR_SIZE;
+        let absolute = LOGO_REGION.offset + spare;
+        if spare == old || table.iter().any(|r| r.is_used() && r.spare_offset == absolute) { continue; }
+
+        //  Erase the spare sector and copy the bytes that have been written.
+        if LOGO_REGION.erase(spare, SECTOR_SIZE).is_err() || !is_blank(spare, SECTOR_SIZE) ? { continue; }
+        if !copy(old, spare, keep) ? { continue; }
+
+        //  Record the relocation, replacing the entry if the sector was relocated before.
+        let bad = LOGO_REGION.offset + sector;
+        let entry = match table.iter().position(|r| r.bad_offset == bad) {
+            Some(e) => e,
+            None    => match table.iter().position(|r| !r.is_used()) {
+                Some(e) => e,
+                None    => return Err(MynewtError::SYS_ENOMEM),  //  Relocation table is full
+            },
+        };
+        table[entry] = LogoRelocation { bad_offset: bad, spare_offset: absolute };
+        index::save_relocations(*table) ? ;
+        console::print("Logo sector "); console::printint(sector as i32);
+        console::print(" relocated to "); console::printint(spare as i32);
+        console::print("\n"); console::flush();
+        return Ok(());
+    }
+    console::print("Logo sector "); console::printint(sector as i32);
+    console::print(" is bad, no spare sectors\n"); console::flush();
+    Err(MynewtError::SYS_ENOMEM)
+}
+
+/// Copy `len` bytes from `from` to the erased `to`. Returns `Ok(true)` if the copy has been verified.
+fn copy(from: u32, to: u32, len: u32) -> MynewtResult<bool> {
+    let mut offset: u32 = 0;
+    while offset < len {
+        let size = core::cmp::min(READ_SIZE as u32, len - offset) as usize;
+        let mut buf = [0u8; READ_SIZE];  //  `VERIFY_BUFFER` is used by `program()`
+        LOGO_REGION.read(from + offset, &mut buf[..size]) ? ;
+        if !program(to + offset, &buf[..size]) ? { return Ok(false); }
+        offset += size as u32;
+    }
+    Ok(true)
+}
+
+/// Return true if the SPI Flash at `physical` contains the bytes in `data`
+fn matches(physical: u32, data: &[u8]) -> MynewtResult<bool> {
+    let mut offset: usize = 0;
+    while offset < data.len() {
+        let len = core::cmp::min(READ_SIZE, data.len() - offset);
+        let buf = unsafe { &mut VERIFY_BUFFER[..len] };
+        LOGO_REGION.read(physical + offset as u32, buf) ? ;
+        if buf != &data[offset..offset + len] { return Ok(false); }
+        offset += len;
+    }
+    Ok(true)
+}
+
+/// Return true if the `len` bytes of SPI Flash at `physical` have been erased
+fn is_blank(physical: u32, len: u32) -> MynewtResult<bool> {
+    let mut offset: u32 = 0;
+    while offset < len {
+        let size = core::cmp::min(READ_SIZE as u32, len - offset) as usize;
+        let buf = unsafe { &mut VERIFY_BUFFER[..size] };
+        LOGO_REGION.read(physical + offset, buf) ? ;
+        if buf.iter().any(|b| *b != 0xff) { return Ok(false); }
+        offset += size as u32;
+    }
+    Ok(true)
+}
+
+///  Import the relocation table setter from `libs/pinetime_logo`
+extern {
+    ///  Set the table of `count` relocated sectors used by the logo validator and decoder.
+    ///  C API: `void pinetime_logo_set_relocations(const struct pinetime_logo_relocation *relocations, int count)`
+    fn pinetime_logo_set_relocations(relocations: *const LogoRelocation, count: i32);
+}
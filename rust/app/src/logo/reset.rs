@@ -0,0 +1,65 @@
+//!  Factory reset for the logo region, for recovering from corrupted or unwanted logos. Erases every logo slot,
+//!  the index table and the journal, then restores the built-in default logo. Relocated bad sectors are kept. Triggered by the shell command
+//!  `logo_reset` (`apps/my_sensor_app/src/logo_mgmt.c`), the Bluetooth LE Reset command (`logo/ble.rs`),
+//!  or by holding the watch button for `RESET_HOLD_MS` milliseconds.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    sys::console,
+};
+use super::{
+    index::{ self, MAX_LOGO_SLOTS },
+    journal, upload,
+};
+use crate::button::{ self, ButtonEvent };
+
+/// How long the button must be held to trigger a factory reset, in milliseconds
+const RESET_HOLD_MS: u32 = 5000;
+
+/// Erase every logo slot, the index table and the journal, then restore the built-in default logo.
+/// Any upload in progress is abandoned. Returns `Ok(true)` if the built-in logo has been restored and verified.
+/// If the firmware has no built-in logo, the bootloader will display its fallback image.
+pub fn factory_reset() -> MynewtResult<bool> {
+    console::print("Logo factory reset\n"); console::flush();
+    upload::abort();
+
+    for slot in 0..MAX_LOGO_SLOTS as u8 {
+        index::erase_sectors(slot) ? ;
+    }
+    index::clear_index() ? ;
+    journal::complete() ? ;
+
+    //  Restore the built-in logo.
+    #[cfg(feature = "write_graphic")]  //  If writing of boot graphic is enabled...
+    return super::write_logo();
+
+    #[cfg(not(feature = "write_graphic"))]  //  If writing of boot graphic is disabled...
+    Ok(false)
+}
+
+/// Perform a factory reset of the logo region. Returns 0 if successful, else a Mynewt error code.
+/// Called by the `logo_reset` shell command in `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_factory_reset() -> i32 {
+    match factory_reset() {
+        Ok(true)  => 0,
+        Ok(false) => MynewtError::SYS_EIO.into(),  //  Built-in logo could not be restored
+        Err(err)  => err.into(),
+    }
+}
+
+/// Perform a factory reset when the button is held for `RESET_HOLD_MS` milliseconds. The button must have been
+/// started with `button::start()`.
+pub fn start_button_reset() -> MynewtResult<()> {
+    button::register(handle_button)
+}
+
+/// Perform the factory reset after a long press, in the default event queue
+fn handle_button(event: ButtonEvent) {
+    if let ButtonEvent::LongPress { held } = event {
+        if held >= Duration::from_millis(RESET_HOLD_MS as u64) {
+            factory_reset().expect("logo reset fail");
+        }
+    }
+}
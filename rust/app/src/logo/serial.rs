@@ -0,0 +1,145 @@
+//!  Manage the logos from a computer or a phone with newtmgr / SMP: list the logo slots, upload a logo, select the
+//!  slot to be displayed, verify and erase a slot. The SMP command group is defined in
+//!  `apps/my_sensor_app/src/logo_mgmt.c`, which decodes the CBOR requests and calls the functions below.
+//!  Shares the chunked write and verification with the Bluetooth LE upload in `logo/ble.rs`.
+
+use mynewt::{
+    result::*,
+    util::crc,
+};
+use super::{
+    show_progress,
+    upload,
+    index::{ self, LogoSlot, MAX_LOGO_SLOTS },
+    manifest::{ self, LogoManifest },
+};
+
+/// Start uploading a logo with `length` bytes and CRC32 `checksum` into `slot`. Returns 0 if successful, else a Mynewt error code.
+/// Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_begin(slot: u8, name: *const u8, name_len: u16, length: u32, checksum: u32) -> i32 {
+    let name = unsafe { core::slice::from_raw_parts(name, name_len as usize) };
+    to_rc(upload::begin(slot, name, length, checksum))
+}
+
+/// Set the image `version` string and `timestamp` for the manifest of the upload in progress.
+/// Returns 0 if successful, else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_set_manifest(version: *const u8, version_len: u16, timestamp: u32) -> i32 {
+    let version = unsafe { core::slice::from_raw_parts(version, version_len as usize) };
+    to_rc(upload::set_manifest(version, timestamp))
+}
+
+/// Set the logo `format` of the upload in progress: 1 for RGB565, 2 for heatshrink, 3 for RGB888, 4 for RGB888
+/// with dithering. Returns 0 if successful, else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_set_format(format: u16) -> i32 {
+    to_rc(upload::set_format(format))
+}
+
+/// Copy the manifest of the logo in `slot` to `dest`. Returns 0 if successful, `SYS_ENOENT` if the slot has no manifest,
+/// else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_get_manifest(slot: u8, dest: *mut LogoManifest) -> i32 {
+    match manifest::read_manifest(slot) {
+        Ok(Some(m)) => { unsafe { *dest = m; } 0 }
+        Ok(None)    => MynewtError::SYS_ENOENT.into(),
+        Err(err)    => err.into(),
+    }
+}
+
+/// Write the chunk with `len` bytes at `offset` from the start of the logo. Returns 0 if successful, else a Mynewt error code.
+/// Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_chunk(offset: u32, data: *const u8, len: u16) -> i32 {
+    let chunk = unsafe { core::slice::from_raw_parts(data, len as usize) };
+    let result = upload::write_chunk(offset, chunk, |done, total| {
+        //  Show the progress on the console after every 10 KB and at the end.
+        if done % 10240 < chunk.len() || done == total { show_progress(done, total); }
+    });
+    if result.is_err() { upload::abort(); }
+    to_rc(result.map(|_| ()))
+}
+
+/// Check the CRC16-CCITT `sum` of the chunk with `len` bytes, sent with the chunk to detect corruption on the serial
+/// port. Returns 0 if the CRC16 matches, else `SYS_EIO`. Called by `logo_mgmt.c` before `logo_serial_chunk()`.
+#[no_mangle]
+extern "C" fn logo_serial_check_chunk(data: *const u8, len: u16, sum: u16) -> i32 {
+    let chunk = unsafe { core::slice::from_raw_parts(data, len as usize) };
+    if crc::crc16(chunk) == sum { 0 } else { MynewtError::SYS_EIO.into() }
+}
+
+/// Complete the upload. Returns 0 if the logo is valid and has been selected for display, else a Mynewt error code.
+/// Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_finish() -> i32 {
+    match upload::finish() {
+        Ok(true)  => 0,
+        Ok(false) => MynewtError::SYS_EIO.into(),  //  CRC32 or header is invalid
+        Err(err)  => err.into(),
+    }
+}
+
+/// Return the number of bytes received so far. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_received() -> u32 {
+    upload::status().0
+}
+
+/// Copy the index table entry of `slot` to `dest`. Returns 0 if the slot contains a logo, `SYS_ENOENT` if the slot
+/// is empty, `SYS_EINVAL` if there is no such slot, else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_get_slot(slot: u8, dest: *mut LogoSlot) -> i32 {
+    if slot as usize >= MAX_LOGO_SLOTS { return MynewtError::SYS_EINVAL.into(); }
+    match index::read_index() {
+        Ok(index) => {
+            let entry = index.slots[slot as usize];
+            unsafe { *dest = entry; }
+            if entry.is_used() { 0 } else { MynewtError::SYS_ENOENT.into() }
+        }
+        Err(err) => err.into(),
+    }
+}
+
+/// Return the slot selected for display by the bootloader, else a negative Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_active() -> i32 {
+    match index::read_index() {
+        Ok(index) => index.active as i32,
+        Err(err)  => err.into(),
+    }
+}
+
+/// Select the logo in `slot` for display by the bootloader. Returns 0 if successful, `SYS_ENOENT` if the slot is
+/// empty, else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_select(slot: u8) -> i32 {
+    to_rc(index::select_slot(slot))
+}
+
+/// Verify the logo in `slot` and set `crc` to the CRC32 of the logo in SPI Flash. Returns 0 if the logo is intact,
+/// `SYS_EIO` if corrupted, `SYS_ENOENT` if the slot is empty, else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_verify(slot: u8, crc: *mut u32) -> i32 {
+    match index::verify_slot(slot) {
+        Ok((checksum, valid)) => {
+            unsafe { *crc = checksum; }
+            if valid { 0 } else { MynewtError::SYS_EIO.into() }
+        }
+        Err(err) => err.into(),
+    }
+}
+
+/// Erase the logo in `slot`. Returns 0 if successful, else a Mynewt error code. Called by `logo_mgmt.c`.
+#[no_mangle]
+extern "C" fn logo_serial_erase(slot: u8) -> i32 {
+    to_rc(index::erase_slot(slot))
+}
+
+/// Convert the result to a Mynewt error code
+fn to_rc(result: MynewtResult<()>) -> i32 {
+    match result {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
@@ -0,0 +1,261 @@
+//!  Upload a logo into a logo slot in chunks, e.g. from a phone app over Bluetooth LE or from a computer over serial.
+//!  The logo is too large to be buffered in RAM, so each chunk is written to SPI Flash as it arrives.
+//!  Sectors are erased just before the first chunk that touches them. When all chunks have been received,
+//!  the CRC32 of the logo is verified, the logo header is written and the index table is updated.
+//!  Completed sectors are recorded in the journal, so that an interrupted upload of the same logo
+//!  resumes from the last completed sector: the uploader should continue from the offset in the response.
+//!  A logo may also be uploaded as RGB888 pixels, which are converted to RGB565 by `logo/convert.rs` as they arrive.
+//!  Such an upload can't be resumed, since the dithering errors of the converted rows are not kept.
+
+use mynewt::{
+    result::*,
+    kernel::task::Task,
+    sys::console,
+    util::crc::Crc32,
+};
+use crate::{ haptics::{ self, HapticEvent }, settings };
+use super::{
+    LOGO_REGION, BATCH_SIZE,
+    convert, flash_checksum, journal, relocate,
+    header::{ self, LogoHeader, LOGO_HEADER_SIZE, LOGO_FORMAT_RGB565, LOGO_FORMAT_HEATSHRINK },
+    index::{ self, LOGO_NAME_SIZE, LOGO_SLOT_SIZE, MAX_LOGO_SLOTS },
+    manifest::{ LogoManifest, MANIFEST_VERSION_SIZE },
+};
+
+/// Upload format: 240 x 240 RGB888 pixels, 3 bytes per pixel, row by row, stored as `LOGO_FORMAT_RGB565`
+pub const UPLOAD_FORMAT_RGB888: u16 = 3;
+
+/// Upload format: 240 x 240 RGB888 pixels, stored as `LOGO_FORMAT_RGB565` with Floyd–Steinberg dithering
+pub const UPLOAD_FORMAT_RGB888_DITHERED: u16 = 4;
+
+/// Priority of the uploading task while writing to flash: above the CHIP8 emulator (20), below the SPI task (10)
+const FLASH_WRITE_PRIO: u8 = 15;
+
+/// State of the logo upload in progress
+struct Upload {
+    /// True if an upload is in progress
+    active:   bool,
+    /// Logo slot being written
+    slot:     u8,
+    /// Name of the logo
+    name:     [u8; LOGO_NAME_SIZE],
+    /// Total length of the logo in bytes
+    length:   u32,
+    /// Expected CRC32 of the logo
+    checksum: u32,
+    /// Number of bytes received
+    received: u32,
+    /// Number of bytes from the start of the slot that have been erased
+    erased:   u32,
+    /// Image version string for the manifest
+    version:  [u8; MANIFEST_VERSION_SIZE],
+    /// Timestamp for the manifest
+    timestamp: u32,
+    /// Upload format: `LOGO_FORMAT_RGB565`, `LOGO_FORMAT_HEATSHRINK`, `UPLOAD_FORMAT_RGB888` or
+    /// `UPLOAD_FORMAT_RGB888_DITHERED`
+    format:   u16,
+    /// Number of converted bytes written, for an RGB888 upload
+    written:  u32,
+    /// CRC32 of the bytes received, for an RGB888 upload
+    crc:      Crc32,
+}
+
+/// The logo upload in progress. Only one upload at a time.
+static mut UPLOAD: Upload = Upload {
+    active:   false,
+    slot:     0,
+    name:     [0; LOGO_NAME_SIZE],
+    length:   0,
+    checksum: 0,
+    received: 0,
+    erased:   0,
+    version:  [0; MANIFEST_VERSION_SIZE],
+    timestamp: 0,
+    format:   LOGO_FORMAT_RGB565,
+    written:  0,
+    crc:      Crc32::new(),
+};
+
+/// Start uploading a logo with `length` bytes and CRC32 `checksum` into the logo slot.
+/// Any upload in progress is abandoned. If an upload of the same logo was interrupted, the upload resumes
+/// from the last completed sector: call `status()` for the offset of the next chunk.
+pub fn begin(slot: u8, name: &[u8], length: u32, checksum: u32) -> MynewtResult<()> {
+    //  The format is set after `begin()`, so the length of an RGB888 logo is checked by `set_format()`.
+    if slot as usize >= MAX_LOGO_SLOTS || length == 0
+        || !(length_fits(LOGO_FORMAT_RGB565, length) || length_fits(UPLOAD_FORMAT_RGB888, length)) {
+        return Err(MynewtError::SYS_EINVAL);
+    }
+    //  Never overwrite a firmware image.
+    if crate::mcuboot::would_overwrite_image(&LOGO_REGION, index::slot_offset(slot), LOGO_SLOT_SIZE) ? {
+        return Err(MynewtError::SYS_EACCES);
+    }
+    let resume = journal::start(slot, length, checksum) ? ;
+    let upload = unsafe { &mut UPLOAD };
+    upload.active   = true;
+    upload.slot     = slot;
+    upload.name     = [0; LOGO_NAME_SIZE];
+    upload.length   = length;
+    upload.checksum = checksum;
+    upload.received = resume;
+    upload.erased   = resume;  //  Resume offset is at a sector boundary
+    upload.version  = [0; MANIFEST_VERSION_SIZE];
+    upload.timestamp = 0;
+    upload.format   = LOGO_FORMAT_RGB565;
+    let len = core::cmp::min(name.len(), LOGO_NAME_SIZE - 1);  //  Leave space for the terminating null
+    upload.name[..len].copy_from_slice(&name[..len]);
+    console::print("Logo upload to slot ");
+    console::printint(slot as i32); console::print(", len ");
+    console::printint(length as i32); console::print("\n"); console::flush();
+    Ok(())
+}
+
+/// Set the image `version` string and `timestamp` to be recorded in the manifest of the upload in progress.
+/// If not set, the manifest has an empty version and timestamp 0.
+pub fn set_manifest(version: &[u8], timestamp: u32) -> MynewtResult<()> {
+    let upload = unsafe { &mut UPLOAD };
+    if !upload.active { return Err(MynewtError::SYS_EINVAL); }
+    let len = core::cmp::min(version.len(), MANIFEST_VERSION_SIZE - 1);  //  Leave space for the terminating null
+    upload.version = [0; MANIFEST_VERSION_SIZE];
+    upload.version[..len].copy_from_slice(&version[..len]);
+    upload.timestamp = timestamp;
+    Ok(())
+}
+
+/// Set the `format` of the upload in progress: `LOGO_FORMAT_RGB565` (default) for raw pixels,
+/// `LOGO_FORMAT_HEATSHRINK` for compressed pixels, or `UPLOAD_FORMAT_RGB888` and `UPLOAD_FORMAT_RGB888_DITHERED`
+/// for RGB888 pixels that are converted to RGB565. Length and CRC32 refer to the bytes uploaded. An RGB888 upload
+/// restarts at offset 0.
+pub fn set_format(format: u16) -> MynewtResult<()> {
+    let upload = unsafe { &mut UPLOAD };
+    if !upload.active { return Err(MynewtError::SYS_EINVAL); }
+    if format != LOGO_FORMAT_RGB565 && format != LOGO_FORMAT_HEATSHRINK && !is_converted(format) {
+        return Err(MynewtError::SYS_ENOTSUP);
+    }
+    if !length_fits(format, upload.length) { return Err(MynewtError::SYS_EINVAL); }
+    upload.format = format;
+    if is_converted(format) {
+        upload.received = 0;
+        upload.erased   = 0;
+        upload.written  = 0;
+        upload.crc      = Crc32::new();
+        convert::start(format == UPLOAD_FORMAT_RGB888_DITHERED);
+    }
+    Ok(())
+}
+
+/// Write the chunk `data` at `offset` from the start of the logo. Chunks must be written in order.
+/// After writing, call `progress(bytes_done, total)`. Returns the number of bytes received so far.
+pub fn write_chunk<F>(offset: u32, data: &[u8], mut progress: F) -> MynewtResult<u32>
+where F: FnMut(usize, usize) {
+    let upload = unsafe { &mut UPLOAD };
+    if !upload.active { return Err(MynewtError::SYS_EINVAL); }
+    if !length_fits(upload.format, upload.length) { return Err(MynewtError::SYS_EINVAL); }  //  Format not set
+    if offset != upload.received || offset + data.len() as u32 > upload.length {
+        return Err(MynewtError::SYS_ERANGE);  //  Chunk is out of order or too long
+    }
+    let base = index::slot_offset(upload.slot);
+
+    //  Don't let other tasks delay the flash writes. The priority is restored when `_boost` is dropped.
+    //  Ignore the error if another task has the boosted priority.
+    let _boost = Task::current().boost(FLASH_WRITE_PRIO).ok();
+    let end = offset + data.len() as u32;
+    if is_converted(upload.format) {
+        //  Convert the RGB888 pixels and write each completed row of RGB565 pixels.
+        convert::convert(data, |row| {
+            let written = upload.written + row.len() as u32;
+            erase_to(upload, base, written) ? ;
+            relocate::write(base + upload.written, row) ? ;
+            upload.written = written;
+            Ok(())
+        }) ? ;
+        upload.crc.update(data);
+    } else {
+        //  Erase the sectors that will be touched by this chunk, then write and verify the chunk. Bad sectors are
+        //  relocated.
+        erase_to(upload, base, end) ? ;
+        relocate::write(base + offset, data) ? ;
+        journal::mark_progress(end as usize, upload.length as usize) ? ;
+    }
+    upload.received = end;
+    progress(upload.received as usize, upload.length as usize);
+    Ok(upload.received)
+}
+
+/// Complete the upload: verify the CRC32 of the logo, write the logo header, update the index table
+/// and select the slot for display by the bootloader. Returns `Ok(true)` if the logo is valid.
+pub fn finish() -> MynewtResult<bool> {
+    let upload = unsafe { &mut UPLOAD };
+    if !upload.active { return Err(MynewtError::SYS_EINVAL); }
+    upload.active = false;
+    if upload.received != upload.length { return Err(MynewtError::SYS_ERANGE); }
+    let base = index::slot_offset(upload.slot);
+    let _boost = Task::current().boost(FLASH_WRITE_PRIO).ok();  //  Restored when dropped
+
+    //  Check the CRC32 of the logo. An RGB888 logo is stored converted, so the CRC32 of the bytes received was
+    //  computed as they arrived. Else read back the logo.
+    let crc =
+        if is_converted(upload.format) { upload.crc.finish() }
+        else { flash_checksum(base, upload.length as usize) ? };
+    if crc != upload.checksum {
+        console::print("Logo upload CRC FAILED\n"); console::flush();
+        return Ok(false);
+    }
+
+    //  An RGB888 logo is recorded with the length and CRC32 of the converted logo.
+    let (format, length, checksum) =
+        if is_converted(upload.format) {
+            (LOGO_FORMAT_RGB565, upload.written, flash_checksum(base, upload.written as usize) ? )
+        } else { (upload.format, upload.length, upload.checksum) };
+
+    //  Erase the last sector for the header, unless the logo has already touched it.
+    let last_sector = LOGO_SLOT_SIZE - BATCH_SIZE as u32;
+    if upload.erased <= last_sector {
+        relocate::erase_sector(base + last_sector) ? ;
+    }
+
+    //  Write the header and check that the bootloader will accept the logo.
+    header::write_erased_header(base, &LogoHeader::with_format(format, length, checksum)) ? ;
+    if !header::validate(base) ? { return Ok(false); }
+
+    //  Record the logo in the index table and display it at the next boot.
+    let len = upload.version.iter().position(|c| *c == 0).unwrap_or(MANIFEST_VERSION_SIZE);
+    let manifest = LogoManifest::new(&upload.version[..len], checksum, upload.timestamp);
+    index::record_slot(upload.slot, &upload.name, length, checksum, &manifest) ? ;
+    index::select_slot(upload.slot) ? ;
+    settings::LOGO_SLOT.set(upload.slot) ? ;  //  Keep the new logo selected after restarting
+    journal::complete() ? ;
+    console::print("Logo upload OK\n"); console::flush();
+    haptics::play(HapticEvent::Flash);
+    Ok(true)
+}
+
+/// Abandon the upload in progress. The slot will not be displayed by the bootloader until a logo is uploaded successfully.
+pub fn abort() {
+    unsafe { UPLOAD.active = false; }
+}
+
+/// Return the number of bytes received and the total length of the upload in progress
+pub fn status() -> (u32, u32) {
+    unsafe { (UPLOAD.received, UPLOAD.length) }
+}
+
+/// Erase the sectors of the upload in slot `base` up to offset `end`, if they have not been erased
+fn erase_to(upload: &mut Upload, base: u32, end: u32) -> MynewtResult<()> {
+    while upload.erased < end {
+        relocate::erase_sector(base + upload.erased) ? ;
+        upload.erased += BATCH_SIZE as u32;
+    }
+    Ok(())
+}
+
+/// Return true if the upload `format` is RGB888, which is converted to RGB565 before writing
+fn is_converted(format: u16) -> bool {
+    format == UPLOAD_FORMAT_RGB888 || format == UPLOAD_FORMAT_RGB888_DITHERED
+}
+
+/// Return true if an upload of `length` bytes in `format` fits in a logo slot. An RGB888 logo must have all the
+/// pixels, since it's converted row by row.
+fn length_fits(format: u16, length: u32) -> bool {
+    if is_converted(format) { length == convert::RGB888_LOGO_SIZE }
+    else { length <= LOGO_SLOT_SIZE - LOGO_HEADER_SIZE }
+}
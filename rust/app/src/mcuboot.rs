@@ -0,0 +1,326 @@
+//!  Parse and validate the MCUBoot image headers, TLVs and trailers in the Active and Standby Firmware slots.
+//!  Used by the loader to verify that it's not overwriting an application image, and to report the
+//!  firmware versions on the screen and to the CoAP server.
+//!  Flash layout is defined in `hw/bsp/nrf52/bsp.yml`. Image format is defined in
+//!  https://github.com/JuulLabs/mcuboot/blob/master/boot/bootutil/include/bootutil/image.h
+
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use mynewt::{
+    result::*,
+    hw::flash::map::{ self, Region, Storage },
+    hw::sensor::{
+        SensorValue, SensorValueType,
+    },
+    kernel::reset,
+    sys::console,
+    encoding::coap_context::*,
+    libs::sensor_network,
+    coap, d, Strn,
+};
+use mynewt_macros::{ init_strn, strn };
+use crate::settings;
+
+/// Magic number at the start of an MCUBoot image header
+const IMAGE_MAGIC: u32 = 0x96f3_b83d;
+
+/// Magic number for the unprotected TLV area
+const IMAGE_TLV_INFO_MAGIC: u16 = 0x6907;
+
+/// Magic number for the protected TLV area
+const IMAGE_TLV_PROT_INFO_MAGIC: u16 = 0x6908;
+
+/// TLV types
+const IMAGE_TLV_SHA256: u8        = 0x10;
+const IMAGE_TLV_RSA2048_PSS: u8   = 0x20;
+const IMAGE_TLV_ECDSA224: u8      = 0x21;
+const IMAGE_TLV_ECDSA256: u8      = 0x22;
+const IMAGE_TLV_RSA3072_PSS: u8   = 0x23;
+const IMAGE_TLV_ED25519: u8       = 0x24;
+
+/// Magic number at the end of the slot trailer, set when a swap has been requested
+const BOOT_IMG_MAGIC: [u32; 4] = [ 0xf395_c277, 0x7fef_d260, 0x0f50_5235, 0x8079_b62c ];
+
+/// Firmware slots
+#[derive(Clone, Copy, PartialEq)]
+pub enum Slot {
+    /// Active Firmware Image in Internal Flash ROM
+    Active,
+    /// Standby Firmware Image in External SPI Flash
+    Standby,
+}
+
+impl Slot {
+    /// Return the flash region of the slot
+    pub fn region(self) -> &'static Region {
+        match self {
+            Slot::Active  => &map::IMAGE_0,
+            Slot::Standby => &map::IMAGE_1,
+        }
+    }
+}
+
+/// MCUBoot image header. Must sync with `struct image_header` in MCUBoot.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct ImageHeader {
+    pub ih_magic:            u32,
+    pub ih_load_addr:        u32,
+    pub ih_hdr_size:         u16,
+    pub ih_protect_tlv_size: u16,
+    pub ih_img_size:         u32,
+    pub ih_flags:            u32,
+    pub ih_ver:              ImageVersion,
+    pub _pad1:               u32,
+}
+
+/// MCUBoot image version. Must sync with `struct image_version` in MCUBoot.
+#[repr(C)]
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct ImageVersion {
+    pub iv_major:     u8,
+    pub iv_minor:     u8,
+    pub iv_revision:  u16,
+    pub iv_build_num: u32,
+}
+
+impl ImageVersion {
+    /// Pack the version into a 32-bit number `major.minor.revision` for transmission
+    pub fn packed(&self) -> u32 {
+        ((self.iv_major as u32) << 24) | ((self.iv_minor as u32) << 16) | (self.iv_revision as u32)
+    }
+}
+
+/// Signature found in the TLV area of the image. The signature is reported, not verified.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Signature {
+    /// No signature TLV
+    None,
+    RSA2048,
+    ECDSA224,
+    ECDSA256,
+    RSA3072,
+    ED25519,
+}
+
+/// Status of a firmware slot
+#[derive(Clone, Copy)]
+pub struct ImageInfo {
+    /// Image header
+    pub header:    ImageHeader,
+    /// First 8 bytes of the SHA256 hash TLV, if present
+    pub hash:      Option<[u8; 8]>,
+    /// Type of signature TLV found
+    pub signature: Signature,
+    /// True if the trailer magic has been set, i.e. a swap has been requested
+    pub magic_set: bool,
+    /// True if the image has been confirmed
+    pub image_ok:  bool,
+}
+
+/// Read and parse the image header, TLVs and trailer in the slot.
+/// Returns `Ok(None)` if the slot doesn't contain a valid image.
+pub fn read_image_info(slot: Slot) -> MynewtResult<Option<ImageInfo>> {
+    let region = slot.region();
+    let slot_size = region.size;
+
+    //  Read the image header.
+    let mut header = ImageHeader::default();
+    region.read(0, as_bytes_mut(&mut header)) ? ;
+    if header.ih_magic != IMAGE_MAGIC { return Ok(None); }
+    if header.ih_hdr_size as u32 + header.ih_img_size > slot_size { return Ok(None); }
+
+    //  Skip the protected TLV area, if any.
+    let mut offset = header.ih_hdr_size as u32 + header.ih_img_size;
+    let (magic, len) = read_tlv_info(region, offset) ? ;
+    if magic == IMAGE_TLV_PROT_INFO_MAGIC {
+        offset += len as u32;
+    }
+
+    //  Walk the unprotected TLV area for the hash and signature.
+    let mut info = ImageInfo {
+        header,
+        hash:      None,
+        signature: Signature::None,
+        magic_set: false,
+        image_ok:  false,
+    };
+    let (magic, tlv_tot) = read_tlv_info(region, offset) ? ;
+    if magic == IMAGE_TLV_INFO_MAGIC {
+        let end = offset + tlv_tot as u32;
+        let mut off = offset + 4;  //  Skip the TLV info
+        while off + 4 <= end {
+            //  Each TLV entry has type (1 byte), padding (1 byte), length (2 bytes) followed by the value.
+            let mut tlv = [0u8; 4];
+            region.read(off, &mut tlv) ? ;
+            let tlv_type = tlv[0];
+            let tlv_len = u16::from_le_bytes([tlv[2], tlv[3]]) as u32;
+            match tlv_type {
+                IMAGE_TLV_SHA256 => {
+                    let mut hash = [0u8; 8];
+                    region.read(off + 4, &mut hash) ? ;
+                    info.hash = Some(hash);
+                }
+                IMAGE_TLV_RSA2048_PSS => { info.signature = Signature::RSA2048; }
+                IMAGE_TLV_ECDSA224    => { info.signature = Signature::ECDSA224; }
+                IMAGE_TLV_ECDSA256    => { info.signature = Signature::ECDSA256; }
+                IMAGE_TLV_RSA3072_PSS => { info.signature = Signature::RSA3072; }
+                IMAGE_TLV_ED25519     => { info.signature = Signature::ED25519; }
+                _ => {}
+            }
+            off += 4 + tlv_len;
+        }
+    }
+
+    //  Read the trailer at the end of the slot: Image OK flag (8 bytes before magic), Magic (last 16 bytes).
+    let mut trailer_magic = [0u32; 4];
+    region.read(slot_size - 16, as_bytes_mut(&mut trailer_magic)) ? ;
+    info.magic_set = trailer_magic == BOOT_IMG_MAGIC;
+    let mut image_ok = [0u8; 1];
+    region.read(slot_size - 24, &mut image_ok) ? ;
+    info.image_ok = image_ok[0] == 0x01;
+    Ok(Some(info))
+}
+
+/// Read the TLV info at `offset` of the slot region. Returns the magic number and the total length.
+fn read_tlv_info(region: &Region, offset: u32) -> MynewtResult<(u16, u16)> {
+    let mut tlv_info = [0u8; 4];
+    region.read(offset, &mut tlv_info) ? ;
+    Ok((
+        u16::from_le_bytes([tlv_info[0], tlv_info[1]]),
+        u16::from_le_bytes([tlv_info[2], tlv_info[3]])
+    ))
+}
+
+/// Return true if writing `len` bytes to `offset` in the flash region would overwrite a valid firmware image
+pub fn would_overwrite_image(region: &Region, offset: u32, len: u32) -> MynewtResult<bool> {
+    let addr = region.address(offset, len) ? ;
+    for slot in [Slot::Active, Slot::Standby].iter() {
+        if slot.region().overlaps(region.flash_id, addr, len) && read_image_info(*slot)?.is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Display the image info for both slots on the console
+pub fn show_image_info() -> MynewtResult<()> {
+    for slot in [Slot::Active, Slot::Standby].iter() {
+        console::print(if *slot == Slot::Active { "Slot 0: " } else { "Slot 1: " });
+        match read_image_info(*slot) ? {
+            None => { console::print("no image\n"); }
+            Some(info) => {
+                let ver = info.header.ih_ver;
+                console::printint(ver.iv_major as i32);    console::print(".");
+                console::printint(ver.iv_minor as i32);    console::print(".");
+                console::printint(ver.iv_revision as i32); console::print(".");
+                console::printint(ver.iv_build_num as i32);
+                console::print(" hash ");
+                match info.hash {
+                    Some(hash) => { for b in hash.iter() { console::printhex(*b); } }
+                    None       => { console::print("none"); }
+                }
+                console::print(if info.signature == Signature::None { " unsigned" } else { " signed" });
+                if info.magic_set { console::print(" pending"); }
+                if info.image_ok  { console::print(" confirmed"); }
+                console::print("\n");
+            }
+        }
+    }
+    console::flush();
+    Ok(())
+}
+
+/// Display the firmware version of the Active Firmware Image and the reason for the last reset on the screen. `start_display()` must have been called earlier.
+pub fn show_version_on_screen() -> MynewtResult<()> {
+    let mut buf: heapless::String<heapless::consts::U32> = heapless::String::new();
+    match read_image_info(Slot::Active) ? {
+        None => { buf.push_str(" Firmware: none ").ok(); }
+        Some(info) => {
+            let ver = info.header.ih_ver;
+            core::fmt::write(&mut buf, format_args!(" Firmware {}.{}.{} ",
+                ver.iv_major, ver.iv_minor, ver.iv_revision)).ok();
+        }
+    }
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(&buf)                                     //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0xff, 0xff )) ) )  //  White text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, 220 ));                    //  Shift the text to the bottom of the screen
+    druid::draw_to_display(text);
+
+    //  Show the reason for the last reset above the firmware version.
+    let mut buf: heapless::String<heapless::consts::U32> = heapless::String::new();
+    core::fmt::write(&mut buf, format_args!(" Reset: {} ", reset::reason().name())).ok();
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(&buf)                                     //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0xff, 0xff )) ) )  //  White text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, 200 ));                    //  Shift the text above the firmware version
+    druid::draw_to_display(text);
+    Ok(())
+}
+
+/// Send the firmware versions of both slots to the CoAP server as `img0` and `img1`, and the `ResetReason` code as `reset`.
+/// Versions are packed as `major << 24 | minor << 16 | revision`. Returns `SYS_EAGAIN` if network is not ready yet.
+pub fn send_image_info() -> MynewtResult<()> {
+    let img0 = version_value(&IMG0_KEY, Slot::Active) ? ;
+    let img1 = version_value(&IMG1_KEY, Slot::Standby) ? ;
+    let reset = SensorValue {
+        key:   &RESET_KEY,
+        value: SensorValueType::Uint(reset::reason() as u32),
+        geo:   SensorValueType::None,
+    };
+
+    //  Get a randomly-generated device ID that changes each time we restart the device.
+    let device_id = sensor_network::get_device_id() ? ;
+
+    //  Start composing the CoAP Server message.
+    let rc = sensor_network::init_server_post( &settings::server_uri() ) ? ;  //  Empty URI means use default CoAP URI in `syscfg.yml`
+    if !rc { return Err(MynewtError::SYS_EAGAIN); }
+
+    //  Compose the CoAP Payload with the firmware versions.
+    let _payload = coap!( @json {
+        img0,
+        img1,
+        reset,
+        "device": &device_id,
+    });
+
+    //  Post the CoAP Server message to the CoAP Background Task for transmission.
+    sensor_network::do_server_post() ? ;
+    Ok(())
+}
+
+/// Key for transmitting the Active Firmware version
+static IMG0_KEY: Strn = init_strn!("img0");
+/// Key for transmitting the Standby Firmware version
+static IMG1_KEY: Strn = init_strn!("img1");
+/// Key for transmitting the reason for the last reset
+static RESET_KEY: Strn = init_strn!("reset");
+
+/// Return the packed firmware version of the slot as a `SensorValue`. Version is 0 if there's no image.
+fn version_value(key: &'static Strn, slot: Slot) -> MynewtResult<SensorValue> {
+    let version = match read_image_info(slot) ? {
+        Some(info) => info.header.ih_ver.packed(),
+        None       => 0,
+    };
+    Ok(SensorValue {
+        key,
+        value: SensorValueType::Uint(version),
+        geo:   SensorValueType::None,
+    })
+}
+
+/// Return the struct as mutable bytes for reading from flash
+fn as_bytes_mut<T>(val: &mut T) -> &mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            val as *mut T as *mut u8,
+            core::mem::size_of::<T>()
+        )
+    }
+}
@@ -0,0 +1,45 @@
+//!  Report the memory usage measured by `mynewt::diag::memory` every `REPORT_PERIOD`, on the console and to the
+//!  CoAP server, so that leaks and fragmentation are spotted before they stop a watch that has been running for
+//!  weeks. The shell command `mem [send]` in `apps/my_sensor_app/src/memory_shell.c` shows and sends a report now.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    kernel::timer::Callout,
+    diag::memory,
+};
+use crate::app_network;
+
+///  Interval between reports
+const REPORT_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+///  Timer that sends the reports
+static REPORT_TIMER: Callout<fn()> = Callout::new(report);
+
+///  Start reporting the memory usage. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    REPORT_TIMER.reset(REPORT_PERIOD)
+}
+
+///  Show the memory usage and send it to the CoAP server, then report again after the period
+fn report() {
+    memory::show();
+    if let Err(err) = app_network::send_memory_stats() { log::warn!("memory stats post fail {:?}", err); }
+    REPORT_TIMER.reset(REPORT_PERIOD).expect("memory timer fail");
+}
+
+///  Shell command `mem`: Show the heap, mbuf and memory pool usage
+#[no_mangle]
+extern "C" fn memory_shell_show() -> i32 {
+    memory::show();
+    0
+}
+
+///  Shell command `mem send`: Send the memory usage to the CoAP server
+#[no_mangle]
+extern "C" fn memory_shell_send() -> i32 {
+    match app_network::send_memory_stats() {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
@@ -0,0 +1,146 @@
+//!  Orientation of the watch (pitch and roll) computed from the BMA421 accelerometer samples with a complementary
+//!  filter. PineTime has no gyroscope, so the filter blends the previous estimate with the tilt measured by the
+//!  accelerometer, and trusts the accelerometer less while the watch is accelerating, e.g. swinging the arm while
+//!  walking. The pedometer task passes each calibrated sample to `update()`. The orientation is exposed as the virtual
+//!  sensor `orientation_0`, so that UI features like auto-rotate may subscribe to it with a sensor listener.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hw::sensor::{
+        self,
+        poller,
+        sensor_orientation_data, VirtualSensor,
+    },
+    kernel::os,
+    sys::console,
+    Strn,
+};
+use mynewt_macros::{ init_strn };
+
+///  Name of the virtual sensor for the orientation
+pub static ORIENTATION_DEVICE: Strn = init_strn!("orientation_0");
+
+///  Virtual sensor that returns the latest orientation
+static ORIENTATION_SENSOR: VirtualSensor<sensor_orientation_data> =
+    VirtualSensor::new(sensor::SENSOR_TYPE_ORIENTATION, sensor::SENSOR_VALUE_TYPE_INT32, read_orientation);
+
+///  Interval for polling the orientation sensor, which calls the listeners
+const ORIENTATION_POLL_TIME: Duration = Duration::from_secs(1);
+
+///  Acceleration of 1 g in raw units, at the ±2g range of the accelerometer
+pub const ONE_G: i32 = 1024;
+
+///  Weight of the previous estimate in parts per thousand, when the watch is not accelerating. The estimate follows
+///  the accelerometer with a time constant of about 0.4 seconds at 25 Hz.
+const STEADY_WEIGHT: i32 = 900;
+
+///  Weight of the previous estimate in parts per thousand, when the watch is accelerating. The accelerometer tilt is
+///  distorted by the acceleration, so the estimate changes slowly.
+const MOVING_WEIGHT: i32 = 980;
+
+///  Max difference of the acceleration magnitude from 1 g for the watch to be considered not accelerating
+const STEADY_MAX_DEVIATION: i32 = 150;
+
+///  Pitch and roll in hundredths of a degree
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Orientation {
+    ///  Rotation around the Y axis, from -9000 to 9000. 0 when the watch face is level.
+    pub pitch: i32,
+    ///  Rotation around the X axis, from -18000 to 18000. 0 when the watch face is up.
+    pub roll: i32,
+}
+
+///  Latest orientation, or `None` until the first sample
+static mut ORIENTATION: Option<Orientation> = None;
+
+///  Register the virtual sensor and poll it for the listeners
+pub fn start_orientation() -> MynewtResult<()> {
+    console::print("Rust orientation\n");
+    ORIENTATION_SENSOR.create(&ORIENTATION_DEVICE) ? ;
+    poller::add(&ORIENTATION_DEVICE, sensor::SENSOR_TYPE_ORIENTATION, ORIENTATION_POLL_TIME)
+}
+
+///  Return the latest orientation, or `None` until the accelerometer has been sampled
+pub fn orientation() -> Option<Orientation> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let orientation = unsafe { ORIENTATION };
+    unsafe { os::os_arch_restore_sr(sr) };
+    orientation
+}
+
+///  Update the orientation with the calibrated accelerometer sample `[x, y, z]` in raw units
+pub fn update(sample: &[i32; 3]) {
+    let (x, y, z) = (sample[0], sample[1], sample[2]);
+    let measured = Orientation {
+        pitch: atan2(-x, isqrt(y as i64 * y as i64 + z as i64 * z as i64)),
+        roll:  atan2(y, z),
+    };
+    //  Trust the accelerometer less when the magnitude is far from 1 g.
+    let magnitude = isqrt(x as i64 * x as i64 + y as i64 * y as i64 + z as i64 * z as i64);
+    let weight = if (magnitude - ONE_G).abs() <= STEADY_MAX_DEVIATION { STEADY_WEIGHT } else { MOVING_WEIGHT };
+
+    let sr = unsafe { os::os_arch_save_sr() };
+    let filtered = match unsafe { ORIENTATION } {
+        None => measured,
+        Some(previous) => Orientation {
+            pitch: blend(previous.pitch, measured.pitch, weight),
+            roll:  blend(previous.roll,  measured.roll,  weight),
+        },
+    };
+    unsafe { ORIENTATION = Some(filtered) };
+    unsafe { os::os_arch_restore_sr(sr) };
+}
+
+///  Called by the Sensor Manager to read the virtual sensor
+fn read_orientation() -> Option<sensor_orientation_data> {
+    let Orientation { pitch, roll } = orientation() ? ;
+    Some(sensor_orientation_data {
+        sod_pitch: pitch,
+        sod_roll: roll,
+        sod_is_valid: 1,
+    })
+}
+
+///  Blend the angles in hundredths of a degree, with `weight` parts per thousand for `previous`. Takes the shorter
+///  way around the circle, so that a roll of 17900 and -17900 blend to 18000, not 0.
+fn blend(previous: i32, measured: i32, weight: i32) -> i32 {
+    let diff = wrap(measured - previous);
+    wrap(previous + diff * (1000 - weight) / 1000)
+}
+
+///  Wrap the angle in hundredths of a degree to -18000..18000
+fn wrap(angle: i32) -> i32 {
+    if angle > 18000 { angle - 36000 }
+    else if angle <= -18000 { angle + 36000 }
+    else { angle }
+}
+
+///  Return the angle of `(x, y)` from the X axis in hundredths of a degree, from -18000 to 18000. Integer only, since
+///  floating-point is disabled. Error is within 0.3 degrees.
+fn atan2(y: i32, x: i32) -> i32 {
+    if x == 0 && y == 0 { return 0; }
+    let (ax, ay) = (x.abs() as i64, y.abs() as i64);
+    //  Angle in the first octant from the ratio of the smaller to the larger value, in 1/1024 units:
+    //  atan(r) ≈ 45° r + 15.64° r (1 - r)
+    let (small, large) = if ay <= ax { (ay, ax) } else { (ax, ay) };
+    let r = small * 1024 / large;
+    let octant = (4500 * r + 1564 * r * (1024 - r) / 1024) / 1024;
+    //  Map the octant to the quadrant.
+    let angle = if ay <= ax { octant } else { 9000 - octant };
+    let angle = if x < 0 { 18000 - angle } else { angle };
+    (if y < 0 { -angle } else { angle }) as i32
+}
+
+///  Return the integer square root of `n`
+pub fn isqrt(n: i64) -> i32 {
+    if n <= 0 { return 0; }
+    //  Newton's method, starting above the root
+    let mut root = n;
+    let mut next = (n + 1) / 2;
+    while next < root {
+        root = next;
+        next = (root + n / root) / 2;
+    }
+    root as i32
+}
@@ -0,0 +1,52 @@
+//!  Show the Bluetooth LE pairing code on the screen. The watch has no keyboard, so it pairs as a display-only
+//!  device: `apps/my_sensor_app/src/ble_main.c` generates a random 6-digit passkey for each pairing and calls
+//!  `pairing_show_passkey()`, and the user types the passkey on the phone. The bonding keys are persisted by NimBLE
+//!  in the config store, so the phone reconnects without pairing again.
+
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use crate::power::{ self, WakeReason };
+
+///  Line of text on the screen
+type Line = heapless::String<heapless::consts::U20>;
+
+///  Show the passkey that the user must type on the phone. Returns 0 if successful.
+///  Called by `ble_main.c` from the NimBLE host task.
+#[no_mangle]
+extern "C" fn pairing_show_passkey(passkey: u32) -> i32 {
+    //  Ignore the error if the display can't be woken, the passkey is also logged.
+    power::wake(WakeReason::Pairing).ok();
+    log::info!("pairing passkey {:06}", passkey);
+    let mut code = Line::new();
+    core::fmt::write(&mut code, format_args!(" {:06} ", passkey)).ok();
+    show_line(" Pairing code  ", 80);
+    show_line(&code, 110);
+    0
+}
+
+///  Show the result of the pairing: `status` is 0 if the link is now encrypted. Returns 0 if successful.
+///  Called by `ble_main.c` from the NimBLE host task.
+#[no_mangle]
+extern "C" fn pairing_show_result(status: i32) -> i32 {
+    if status == 0 {
+        show_line(" Paired        ", 80);
+    } else {
+        log::warn!("pairing failed {}", status);
+        show_line(" Pairing failed", 80);
+    }
+    show_line("        ", 110);  //  Erase the passkey
+    0
+}
+
+///  Render the line in white on black at row `y`
+fn show_line(text: &str, y: i32) {
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(text)                                     //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0xff, 0xff )) ) )  //  White text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, y ));                      //  Shift the text to the row
+    druid::draw_to_display(text);
+}
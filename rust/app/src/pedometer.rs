@@ -0,0 +1,311 @@
+//!  Pedometer that counts steps from the BMA421 accelerometer samples. A background task samples the accelerometer
+//!  at 25 Hz and detects steps as peaks in the magnitude of the acceleration, with a threshold that adapts to the
+//!  recent swing of the signal. The daily step count is saved to flash with the settings, so that it survives reboots,
+//!  and is reset when the date changes (once the time has been set). The step count is exposed as the virtual sensor
+//!  `pedometer_0`, so it's polled and sent to the CoAP server like the other sensors. Each sample is also passed to
+//!  the orientation filter in `orientation.rs`, and the motion of each sample is reported by `app_sensor.rs`
+//!  after downsampling. While the watch is idle or sleeping, the accelerometer samples in low-power mode, which is
+//!  noisier but still good enough for counting steps and detecting the wrist raise.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hw::sensor::{
+        self,
+        sensor_steps_data, VirtualSensor,
+    },
+    kernel::{
+        device::Device,
+        os,
+        schedule::{ self, WallClockTime },
+        supervisor,
+        task,
+        time::{ self, Instant },
+    },
+    sys::console,
+    Strn,
+};
+use mynewt_macros::{ init_strn };
+use crate::{
+    app_sensor, orientation, settings,
+    power::manager::{ self, PowerHooks, SystemState },
+};
+
+///  Name of the virtual sensor for the step count
+pub static PEDOMETER_DEVICE: Strn = init_strn!("pedometer_0");
+
+///  Accelerometer that is sampled
+pub static ACCEL_DEVICE: Strn = init_strn!("bma421_0");
+
+///  Virtual sensor that returns the daily step count
+static PEDOMETER_SENSOR: VirtualSensor<sensor_steps_data> =
+    VirtualSensor::new(sensor::SENSOR_TYPE_STEPS, sensor::SENSOR_VALUE_TYPE_INT32, read_steps);
+
+///  Interval between accelerometer samples: 25 Hz
+const SAMPLE_INTERVAL_MS: u64 = 40;
+
+///  Number of samples for updating the adaptive threshold: 2 seconds
+const THRESHOLD_WINDOW: u32 = 50;
+
+///  Min swing of the acceleration magnitude for counting steps, in raw units (1 g = 1024 at ±2g).
+///  Smaller movements are ignored, e.g. typing or hand tremor.
+const MIN_SWING: i32 = 100;
+
+///  Min number of samples between steps: 200 milliseconds, i.e. 5 steps per second
+const MIN_STEP_SAMPLES: u32 = 5;
+
+///  Max number of samples between steps: 2 seconds. Longer gaps restart the counting.
+const MAX_STEP_SAMPLES: u32 = 50;
+
+///  Number of consecutive steps that must be detected before counting, to ignore random movements
+const MIN_CONSECUTIVE_STEPS: u32 = 4;
+
+///  Save the step count after this number of new steps, to limit flash writes
+const SAVE_EVERY_STEPS: u32 = 100;
+
+///  Local time for saving the step count every night, since up to `SAVE_EVERY_STEPS` steps are not saved yet
+const SAVE_TIME: WallClockTime = WallClockTime::new(23, 55);
+
+///  Size of the pedometer task stack, in 4-byte units. The deepest call is saving the step count to flash through
+///  the settings. The high-water mark reported by `task::stats()` must stay below 75% of the stack, as checked by
+///  the device test `spawned_stacks_have_headroom`.
+const PEDOMETER_TASK_STACK_SIZE: usize = 512;
+
+///  Hooks for the accelerometer, which samples in low-power mode unless the watch is active
+static ACCEL_HOOKS: PowerHooks = PowerHooks { name: "accel", enter: enter_accel, exit: exit_accel };
+
+///  Steps counted today
+static mut STEPS: u32 = 0;
+
+///  Step count when the steps were last saved
+static mut SAVED_STEPS: u32 = 0;
+
+///  Register the virtual sensor and start counting steps in a background task. Returns `SYS_ENODEV` if
+///  the accelerometer is not enabled in `syscfg.yml`.
+pub fn start_pedometer() -> MynewtResult<()> {
+    console::print("Rust pedometer\n");
+    //  Check the accelerometer before starting.
+    Device::open(&ACCEL_DEVICE, Duration::from_secs(1)) ? ;
+
+    //  Restore the step count saved today.
+    unsafe {
+        STEPS = settings::STEPS.get();
+        SAVED_STEPS = STEPS;
+    }
+    roll_over_day() ? ;
+
+    //  Save the step count every night before the day rolls over.
+    schedule::at("steps_save", SAVE_TIME, save_job) ? ;
+
+    PEDOMETER_SENSOR.create(&PEDOMETER_DEVICE) ? ;
+    manager::register(&ACCEL_HOOKS) ? ;
+    task::spawn(
+        &init_strn!( "pedometer" ),  //  Name of task
+        150,   //  Task priority: highest is 0, lowest is 255 (main task is 127)
+        PEDOMETER_TASK_STACK_SIZE,   //  Size of the stack (in 4-byte units)
+        pedometer_task_func          //  Function to execute when task starts
+    ) ? ;
+    Ok(())
+}
+
+///  Return the steps counted today
+pub fn steps() -> u32 {
+    unsafe { STEPS }
+}
+
+///  Called by the Sensor Manager to read the virtual sensor
+fn read_steps() -> Option<sensor_steps_data> {
+    Some(sensor_steps_data {
+        ssd_steps: steps(),
+        ssd_steps_is_valid: 1,
+    })
+}
+
+///  Sample the accelerometer and count the steps, forever
+fn pedometer_task_func() {
+    let supervised = supervisor::register("pedometer", Duration::from_secs(10))
+        .expect("supervisor fail");
+    let dev = Device::open(&ACCEL_DEVICE, Duration::from_secs(1))
+        .expect("pedometer accel fail");
+    let mut detector = StepDetector::new();
+    let mut deadline = Instant::now();
+    loop {
+        supervised.checkin();
+        deadline = deadline + Duration::from_millis(SAMPLE_INTERVAL_MS);
+        //  If the task has fallen behind, e.g. blocked on the I2C bus, skip the missed samples instead of
+        //  reading them back to back, which would keep the CPU awake.
+        let now = Instant::now();
+        if deadline.checked_duration_since(now).is_none() {
+            deadline = now + Duration::from_millis(SAMPLE_INTERVAL_MS);
+        }
+        time::sleep_until(deadline);
+
+        //  Skip the sample if the accelerometer is busy, e.g. the I2C bus is used by the touch controller.
+        let (x, y, z) = match read_accel(&dev) { Ok(xyz) => xyz, Err(_) => continue };
+        let sample = [
+            settings::ACCEL_X_CALIBRATION.apply(x),
+            settings::ACCEL_Y_CALIBRATION.apply(y),
+            settings::ACCEL_Z_CALIBRATION.apply(z),
+        ];
+
+        orientation::update(&sample);
+        app_sensor::record_motion(&sample);
+        let new_steps = detector.update(&sample);
+        if new_steps > 0 {
+            add_steps(new_steps).ok();  //  Try again at the next step if the step count can't be saved
+        }
+    }
+}
+
+///  Return the uncalibrated acceleration `(x, y, z)` from the accelerometer `dev`, in raw units (1 g = 1024)
+pub fn read_accel(dev: &Device) -> MynewtResult<(i32, i32, i32)> {
+    let mut sample = bma421_sample { x: 0, y: 0, z: 0 };
+    check(unsafe { bma421_get_raw_xyz(dev.as_driver(), &mut sample) }) ? ;
+    Ok((sample.x as i32, sample.y as i32, sample.z as i32))
+}
+
+///  Switch the accelerometer to low-power mode when the watch is idle or sleeping, and back to normal mode when active
+fn enter_accel(state: SystemState) -> MynewtResult<()> {
+    let dev = Device::open(&ACCEL_DEVICE, Duration::from_secs(1)) ? ;
+    check(unsafe { bma421_set_low_power(dev.as_driver(), (state != SystemState::Active) as i32) })
+}
+
+///  Nothing to do when leaving a state, the mode is set when entering the next state
+fn exit_accel(_state: SystemState) -> MynewtResult<()> {
+    Ok(())
+}
+
+///  Save the steps counted since the last save, e.g. before shutting down. Does nothing if there are none.
+pub fn save_steps() -> MynewtResult<()> {
+    let steps = steps();
+    if steps == unsafe { SAVED_STEPS } { return Ok(()); }
+    unsafe { SAVED_STEPS = steps };
+    settings::STEPS.set(steps)
+}
+
+///  Save the step count. Called by the scheduler every night at `SAVE_TIME`.
+fn save_job() {
+    if let Err(err) = save_steps() { log::warn!("steps save fail {:?}", err); }
+}
+
+///  Count the new steps and save the step count every `SAVE_EVERY_STEPS` steps
+fn add_steps(new_steps: u32) -> MynewtResult<()> {
+    roll_over_day() ? ;
+    let steps = unsafe { STEPS += new_steps; STEPS };
+    if steps.wrapping_sub(unsafe { SAVED_STEPS }) < SAVE_EVERY_STEPS { return Ok(()); }
+    unsafe { SAVED_STEPS = steps };
+    settings::STEPS.set(steps)
+}
+
+///  If the date has changed since the steps were saved, reset the step count. Does nothing until the time is set.
+fn roll_over_day() -> MynewtResult<()> {
+    let today = match today() { Some(day) => day, None => return Ok(()) };
+    if today == settings::STEPS_DAY.get() { return Ok(()); }
+    unsafe {
+        STEPS = 0;
+        SAVED_STEPS = 0;
+    }
+    settings::STEPS_DAY.set(today) ? ;
+    settings::STEPS.set(0)
+}
+
+///  Return the number of days since 1970 in local time, or `None` if the time has not been set
+fn today() -> Option<u32> {
+    if !unsafe { os::os_time_is_set() } { return None; }
+    let mut tv = os::os_timeval { tv_sec: 0, tv_usec: 0 };
+    let mut tz = os::os_timezone { tz_minuteswest: 0, tz_dsttime: 0 };
+    if unsafe { os::os_gettimeofday(&mut tv, &mut tz) } != 0 { return None; }
+    let local = tv.tv_sec - tz.tz_minuteswest as i64 * 60;
+    Some((local / 86_400) as u32)
+}
+
+///  Detects steps as peaks in the acceleration magnitude. Integer only, since floating-point is disabled.
+struct StepDetector {
+    ///  Last 4 magnitudes, for smoothing
+    history: [i32; 4],
+    ///  Index of the next magnitude in `history`
+    next: usize,
+    ///  Previous smoothed magnitude
+    last: i32,
+    ///  Min and max smoothed magnitude in the current window
+    window_min: i32,
+    window_max: i32,
+    ///  Number of samples in the current window
+    window_samples: u32,
+    ///  Threshold for detecting peaks: midpoint of the previous window. 0 until the first window is done.
+    threshold: i32,
+    ///  True if the swing in the previous window is large enough for steps
+    active: bool,
+    ///  Number of samples since the last step
+    since_step: u32,
+    ///  Number of consecutive steps detected, before the steps are counted
+    consecutive: u32,
+}
+
+impl StepDetector {
+    ///  Create a detector with no history
+    fn new() -> Self {
+        StepDetector {
+            history: [0; 4], next: 0, last: 0,
+            window_min: i32::max_value(), window_max: i32::min_value(), window_samples: 0,
+            threshold: 0, active: false,
+            since_step: MAX_STEP_SAMPLES, consecutive: 0,
+        }
+    }
+
+    ///  Add the calibrated sample `[x, y, z]`. Return the number of steps to be counted.
+    fn update(&mut self, sample: &[i32; 3]) -> u32 {
+        //  Magnitude of the acceleration. The sum of absolute values avoids the square root and is good enough for peaks.
+        let magnitude = sample.iter().map(|a| a.abs()).sum::<i32>();
+        self.history[self.next] = magnitude;
+        self.next = (self.next + 1) % self.history.len();
+        let smoothed = self.history.iter().sum::<i32>() / self.history.len() as i32;
+
+        //  Update the threshold at the end of each window.
+        if smoothed < self.window_min { self.window_min = smoothed; }
+        if smoothed > self.window_max { self.window_max = smoothed; }
+        self.window_samples += 1;
+        if self.window_samples >= THRESHOLD_WINDOW {
+            self.threshold = (self.window_min + self.window_max) / 2;
+            self.active = self.window_max - self.window_min >= MIN_SWING;
+            self.window_min = i32::max_value();
+            self.window_max = i32::min_value();
+            self.window_samples = 0;
+        }
+
+        //  A step is detected when the smoothed magnitude falls through the threshold after a peak.
+        let last = self.last;
+        self.last = smoothed;
+        if self.since_step < MAX_STEP_SAMPLES { self.since_step += 1; }
+        else { self.consecutive = 0; }  //  Too long since the last step, start counting again
+        if !self.active || self.threshold == 0 { return 0; }
+        if !(last > self.threshold && smoothed <= self.threshold) { return 0; }
+        if self.since_step < MIN_STEP_SAMPLES { return 0; }  //  Too soon, probably noise
+
+        //  Count the steps once we have seen enough consecutive steps. Include the steps that were held back.
+        self.since_step = 0;
+        self.consecutive += 1;
+        if self.consecutive < MIN_CONSECUTIVE_STEPS { 0 }
+        else if self.consecutive == MIN_CONSECUTIVE_STEPS { MIN_CONSECUTIVE_STEPS }
+        else { 1 }
+    }
+}
+
+///  Raw accelerometer sample: 12-bit signed acceleration for each axis. From `libs/bma421/include/bma421/bma421.h`
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+pub struct bma421_sample {
+    pub x: i16,
+    pub y: i16,
+    pub z: i16,
+}
+
+extern "C" {
+    ///  Get the latest raw sample. C API: `int bma421_get_raw_xyz(struct bma421 *dev, struct bma421_sample *sample)`
+    fn bma421_get_raw_xyz(dev: *mut ::cty::c_void, sample: *mut bma421_sample) -> ::cty::c_int;
+
+    ///  Switch between low-power mode (1) and normal mode (0).
+    ///  C API: `int bma421_set_low_power(struct bma421 *dev, int enable)`
+    fn bma421_set_low_power(dev: *mut ::cty::c_void, enable: ::cty::c_int) -> ::cty::c_int;
+}
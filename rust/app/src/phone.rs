@@ -0,0 +1,82 @@
+//!  React to the phone connecting and disconnecting over Bluetooth LE. While a phone is connected and syncing,
+//!  e.g. reading the sensors or uploading a logo or firmware, the CoAP posts in `app_network.rs` are paused and
+//!  retried later by the callers. The events are delivered on the default event queue by `mynewt::ble::lifecycle`.
+//!  The connectable advertising is paused while the watch is in Deep Sleep, but the connections are kept.
+
+use mynewt::{
+    result::*,
+    ble::{
+        gap::ConnHandle,
+        lifecycle::{ self, ConnHandler },
+        BleError,
+    },
+};
+use crate::{
+    app_network,
+    power::manager::{ self, PowerHooks, SystemState },
+};
+
+///  Handler for the connections of the phones
+struct PhoneHandler;
+
+///  Number of phones connected. Updated only on the default event queue.
+static mut CONNECTED: u8 = 0;
+
+///  Registered with `mynewt::ble::lifecycle`
+static PHONE_HANDLER: PhoneHandler = PhoneHandler;
+
+///  Hooks for the radio, which stops advertising in Deep Sleep. Registered with the power manager.
+static RADIO_HOOKS: PowerHooks = PowerHooks { name: "radio", enter: enter_radio, exit: exit_radio };
+
+///  Follow the phone connections. Called by main() in `lib.rs` before the Bluetooth LE host starts.
+pub fn start() -> MynewtResult<()> {
+    manager::register(&RADIO_HOOKS) ? ;
+    match lifecycle::register(&PHONE_HANDLER) {
+        Err(BleError::ENOTSUP) => Ok(()),  //  Bluetooth LE is disabled
+        result => result.map_err(|err| err.into()),
+    }
+}
+
+///  Return true if a phone is connected
+pub fn is_connected() -> bool {
+    unsafe { CONNECTED > 0 }
+}
+
+///  Pause the advertising in Deep Sleep
+fn enter_radio(state: SystemState) -> MynewtResult<()> {
+    if state != SystemState::DeepSleep { return Ok(()); }
+    check(unsafe { ble_pause_advertising(1) })
+}
+
+///  Resume the advertising after Deep Sleep
+fn exit_radio(state: SystemState) -> MynewtResult<()> {
+    if state != SystemState::DeepSleep { return Ok(()); }
+    check(unsafe { ble_pause_advertising(0) })
+}
+
+impl ConnHandler for PhoneHandler {
+    ///  Pause the CoAP posts when the first phone connects
+    fn on_connect(&self, conn: ConnHandle) {
+        log::info!("phone connected {}", conn);
+        unsafe { CONNECTED += 1 };
+        app_network::pause(true);
+    }
+
+    ///  Resume the CoAP posts when the last phone disconnects
+    fn on_disconnect(&self, conn: ConnHandle, reason: i32) {
+        log::info!("phone disconnected {} reason {}", conn, reason);
+        unsafe { CONNECTED = CONNECTED.saturating_sub(1) };
+        if unsafe { CONNECTED } == 0 { app_network::pause(false); }
+    }
+
+    ///  Log the MTU negotiated by the phone
+    fn on_mtu_changed(&self, conn: ConnHandle, mtu: u16) {
+        log::info!("phone {} mtu {}", conn, mtu);
+    }
+}
+
+extern "C" {
+    ///  Stop the connectable advertising if `pause` is 1, or restart it if 0. Defined in `ble_main.c`.
+    ///  C API: `int ble_pause_advertising(int pause)`
+    fn ble_pause_advertising(pause: i32) -> i32;
+}
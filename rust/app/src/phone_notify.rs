@@ -0,0 +1,156 @@
+//!  Show the notifications of the phone on the watch, e.g. messages and missed calls. When a bonded phone connects,
+//!  `apps/my_sensor_app/src/ble_notify_client.c` subscribes to its notifications: the Apple Notification Center
+//!  Service (ANCS) on iOS, or the Companion Notification Service hosted by the companion app on other phones.
+//!  The title of each new notification is passed to `phone_notify_show()`, queued and shown one at a time for
+//!  `SHOW_TIME`, with the haptic pattern chosen in the settings.
+
+use core::time::Duration;
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use mynewt::{
+    result::*,
+    kernel::{ channel::Channel, os, timer::Callout },
+};
+use crate::{
+    haptics::{ self, HapticEvent },
+    power::{ self, WakeReason },
+};
+
+///  Max length of a title in bytes. Must sync with `NOTIFY_TITLE_MAX` in `ble_notify_client.c`.
+type MaxTitle = heapless::consts::U32;
+
+///  Max number of characters in a line of text on the screen
+const LINE_CHARS: usize = 16;
+
+///  Line of text on the screen
+type Line = heapless::String<heapless::consts::U20>;
+
+///  Show each notification for this time before the next one
+const SHOW_TIME: Duration = Duration::from_secs(4);
+
+///  Notification received from the phone
+#[derive(Clone, Debug)]
+struct PhoneNotification {
+    ///  ANCS CategoryID, e.g. 1 for an incoming call
+    category: u8,
+    ///  Title of the notification, e.g. the sender of a message
+    title: heapless::String<MaxTitle>,
+}
+
+///  Notifications pushed by the NimBLE host and popped on the default event queue
+static NOTIFICATIONS: Channel<PhoneNotification> = Channel::new();
+
+///  True while a notification is shown
+static mut SHOWING: bool = false;
+
+///  Timer that shows the next notification
+static SHOW_TIMER: Callout<fn()> = Callout::new(show_next);
+
+///  Show the queued notifications on the default event queue. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    NOTIFICATIONS.notify(os::eventq_dflt_get() ? , handle_notifications);
+    Ok(())
+}
+
+///  Queue the notification with ANCS category `category` and UTF-8 title `title` of `len` bytes. Returns 0 if
+///  successful. Called by `ble_notify_client.c` from the NimBLE host task.
+#[no_mangle]
+extern "C" fn phone_notify_show(category: u8, title: *const u8, len: u16) -> i32 {
+    assert!(!title.is_null(), "null title");
+    let bytes = unsafe { core::slice::from_raw_parts(title, len as usize) };
+    //  The title may have been truncated by the phone in the middle of a character.
+    let text = match core::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(err) => unsafe { core::str::from_utf8_unchecked(&bytes[..err.valid_up_to()]) },
+    };
+    let mut notification = PhoneNotification { category, title: heapless::String::new() };
+    //  The font has only ASCII characters.
+    for ch in text.chars() {
+        if notification.title.push(if ch.is_ascii() && !ch.is_ascii_control() { ch } else { '?' }).is_err() { break; }
+    }
+    log::info!("phone notification {}: {}", category_label(category), notification.title);
+    //  Drop the notification if `CHANNEL_SIZE` notifications are waiting to be shown.
+    if NOTIFICATIONS.push(notification).is_err() { return -1; }
+    0
+}
+
+///  Show the next notification unless one is shown. Called by the default event queue after each push.
+extern "C" fn handle_notifications(_ev: *mut os::os_event) {
+    if unsafe { SHOWING } { return; }
+    show_next();
+}
+
+///  Show the next queued notification, or erase the notification when there are no more.
+///  Called when a notification is pushed, and when a notification has been shown for `SHOW_TIME`.
+fn show_next() {
+    let notification = match NOTIFICATIONS.pop() {
+        Some(notification) => notification,
+        None => {
+            if unsafe { SHOWING } { erase(); }
+            unsafe { SHOWING = false };
+            return;
+        }
+    };
+    unsafe { SHOWING = true };
+    //  Ignore the errors, the notification has been logged.
+    power::wake(WakeReason::Notification).ok();
+    show(&notification);
+    haptics::play(HapticEvent::Notification);
+    SHOW_TIMER.reset(SHOW_TIME).expect("notify timer fail");
+}
+
+///  Show the category and the title of the notification, wrapped on two lines
+fn show(notification: &PhoneNotification) {
+    let mut label = Line::new();
+    core::fmt::write(&mut label, format_args!(" {:width$}", category_label(notification.category), width = LINE_CHARS - 1)).ok();
+    show_line(&label, 80);
+    let title = notification.title.as_str();
+    let (first, rest) = title.split_at(core::cmp::min(title.len(), LINE_CHARS));
+    show_line(&pad(first), 110);
+    show_line(&pad(&rest[..core::cmp::min(rest.len(), LINE_CHARS)]), 130);
+}
+
+///  Erase the notification
+fn erase() {
+    for y in [80, 110, 130].iter() {
+        show_line(&pad(""), *y);
+    }
+}
+
+///  Pad the text with spaces to `LINE_CHARS`, to overwrite the previous line
+fn pad(text: &str) -> Line {
+    let mut line = Line::new();
+    core::fmt::write(&mut line, format_args!("{:width$}", text, width = LINE_CHARS)).ok();
+    line
+}
+
+///  Return the label of the ANCS CategoryID
+fn category_label(category: u8) -> &'static str {
+    match category {
+        1  => "Call",
+        2  => "Missed call",
+        3  => "Voicemail",
+        4  => "Social",
+        5  => "Schedule",
+        6  => "Email",
+        7  => "News",
+        8  => "Fitness",
+        9  => "Finance",
+        10 => "Location",
+        11 => "Entertainment",
+        _  => "Notification",
+    }
+}
+
+///  Render the line in white on black at row `y`
+fn show_line(text: &str, y: i32) {
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(text)                                     //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0xff, 0xff )) ) )  //  White text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, y ));                      //  Shift the text to the row
+    druid::draw_to_display(text);
+}
@@ -0,0 +1,228 @@
+//!  Power manager for the display. The display and backlight use most of the power of the watch, so they are
+//!  switched off while the watch is not being looked at, and switched on again when the wrist is raised. `wake()`
+//!  and `sleep()` may be called by any task, e.g. by the wrist-raise detection in `wrist.rs`. Observers are notified
+//!  of each change of the power state through `POWER_EVENTS`, e.g. so that the UI stops rendering while asleep.
+//!  `sleep_for()` switches off the display for a long time, e.g. overnight, and wakes it with the low-power RTC
+//!  timer, which doesn't keep the high-frequency clock running. `hold_awake()` pauses the automatic sleep, e.g. by
+//!  the wrist drop, while the watch is powered by the charger. Waking the display counts as activity for the system
+//!  power manager in `power/manager.rs`, which switches off the display after `settings::DISPLAY_TIMEOUT` without
+//!  activity. `auto_sleep()` also steps the manager down, so the rest of the system sleeps with the display.
+//!  After a low-battery shutdown, `wake()` leaves the display off until the charger is connected.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    board::pinetime::{ self, Backlight },
+    kernel::{
+        event::EventQueue,
+        lptimer::Alarm,
+        os,
+        timer::Callout,
+    },
+    spi,
+    sys::power_profile::{ self, Subsystem },
+};
+use manager::{ PowerHooks, SystemState };
+
+///  Battery charge level along the LiPo discharge curve
+pub mod gauge;  //  Export `power/gauge.rs` as Rust module `power::gauge`
+
+///  Battery samples recorded on flash for analysing the discharge curve
+pub mod history;  //  Export `power/history.rs` as Rust module `power::history`
+
+///  Low-battery warnings and shutdown
+pub mod low_battery;  //  Export `power/low_battery.rs` as Rust module `power::low_battery`
+
+///  Active, Idle and Deep Sleep states of the whole watch
+pub mod manager;  //  Export `power/manager.rs` as Rust module `power::manager`
+
+///  Periodic reports of the charge drawn by each subsystem
+pub mod profile;  //  Export `power/profile.rs` as Rust module `power::profile`
+
+///  Power state of the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerState {
+    ///  Display and backlight are on
+    Awake,
+    ///  Display controller is sleeping and the backlight is off
+    Sleeping,
+}
+
+///  Reason for waking the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WakeReason {
+    ///  Wrist was raised to look at the watch
+    WristRaise,
+    ///  Screen was touched
+    Touch,
+    ///  Watch button was pressed
+    Button,
+    ///  Sensor alert is shown
+    Alert,
+    ///  Bluetooth LE pairing code is shown
+    Pairing,
+    ///  Firmware update progress is shown
+    Update,
+    ///  Phone notification is shown
+    Notification,
+    ///  Sleep requested by `sleep_for()` has ended, or a daily alarm of `alarms.rs` is ringing
+    Alarm,
+    ///  Charger was connected or disconnected
+    Charger,
+    ///  Low-battery warning is shown
+    LowBattery,
+}
+
+///  Change of power state delivered to observers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PowerEvent {
+    ///  Display was switched on for the reason
+    Woken(WakeReason),
+    ///  Display was switched off
+    Slept,
+}
+
+///  Power events for the UI. Call `POWER_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static POWER_EVENTS: EventQueue<PowerEvent> = EventQueue::new();
+
+///  ST7789 display controller commands
+const SLPIN: u8   = 0x10;  //  Sleep in
+const SLPOUT: u8  = 0x11;  //  Sleep out
+const DISPOFF: u8 = 0x28;  //  Display off
+const DISPON: u8  = 0x29;  //  Display on
+
+///  Low-power alarm that ends the sleep requested by `sleep_for()`
+static WAKE_ALARM: Alarm = Alarm::new(handle_wake_alarm);
+
+///  Retry the wake at the end of `sleep_for()` after this time if the display couldn't be switched on, e.g. when
+///  the SPI requests can't be queued
+const WAKE_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+///  Timer that retries the wake at the end of `sleep_for()`
+static WAKE_RETRY_TIMER: Callout<fn()> = Callout::new(handle_wake_alarm);
+
+///  Current power state. The display is switched on at startup.
+static mut STATE: PowerState = PowerState::Awake;
+
+///  True if the automatic sleep is paused by `hold_awake()`
+static mut HOLD_AWAKE: bool = false;
+
+///  Hooks for the display, which is switched off in Idle and Deep Sleep. Registered by `manager::start()`.
+static DISPLAY_HOOKS: PowerHooks = PowerHooks { name: "display", enter: enter_display, exit: exit_display };
+
+///  Return the current power state
+pub fn state() -> PowerState {
+    unsafe { STATE }
+}
+
+///  Return true if the display is on
+pub fn is_awake() -> bool {
+    state() == PowerState::Awake
+}
+
+///  Switch on the display and backlight, and report the activity to the power manager. Does nothing else if
+///  already awake. Does nothing while the watch is shut down for low battery. If the display can't be switched on,
+///  the state stays Sleeping, so that the next `wake()` tries again.
+pub fn wake(reason: WakeReason) -> MynewtResult<()> {
+    if manager::is_shut_down() { return Ok(()); }
+    manager::activity();
+    if !set_state(PowerState::Awake) { return Ok(()); }
+    WAKE_ALARM.stop();  //  Woken before the end of `sleep_for()`
+    WAKE_RETRY_TIMER.stop();
+    if let Err(err) = switch_on() {
+        set_state(PowerState::Sleeping);
+        return Err(err);
+    }
+    power_profile::begin(Subsystem::Display);
+    POWER_EVENTS.post(PowerEvent::Woken(reason)).ok();  //  Drop the event if nobody is receiving events
+    Ok(())
+}
+
+///  Switch off the backlight and put the display controller to sleep. Does nothing if already sleeping.
+pub fn sleep() -> MynewtResult<()> {
+    WAKE_RETRY_TIMER.stop();  //  Don't retry a failed wake at the end of `sleep_for()`
+    if !set_state(PowerState::Sleeping) { return Ok(()); }
+    set_backlight(false) ? ;
+    write_command(DISPOFF) ? ;
+    write_command(SLPIN) ? ;
+    power_profile::end(Subsystem::Display);
+    POWER_EVENTS.post(PowerEvent::Slept).ok();  //  Drop the event if nobody is receiving events
+    Ok(())
+}
+
+///  Switch off the display for power saving, e.g. when the wrist drops, unless paused by `hold_awake()`. The power
+///  manager steps down to Idle.
+pub fn auto_sleep() -> MynewtResult<()> {
+    if unsafe { HOLD_AWAKE } { return Ok(()); }
+    sleep() ? ;
+    manager::idle();
+    Ok(())
+}
+
+///  Pause the automatic sleep by `auto_sleep()` if `hold` is true, e.g. while the charger supplies power, or
+///  resume it if false. `sleep()` still switches off the display.
+pub fn hold_awake(hold: bool) {
+    unsafe { HOLD_AWAKE = hold };
+}
+
+///  Return true if the automatic sleep is paused by `hold_awake()`
+pub fn is_held_awake() -> bool {
+    unsafe { HOLD_AWAKE }
+}
+
+///  Switch off the display, and switch it on again after `duration`, up to `lptimer::MAX_ALARM` (4.5 hours).
+///  The display may be woken earlier by `wake()`, e.g. when the button is pressed.
+pub fn sleep_for(duration: Duration) -> MynewtResult<()> {
+    sleep() ? ;
+    WAKE_ALARM.start(duration)
+}
+
+///  Switch on the display at the end of `sleep_for()`. If the display can't be switched on, log the error and try
+///  again after `WAKE_RETRY_DELAY`. Called by the default event queue.
+fn handle_wake_alarm() {
+    if let Err(err) = wake(WakeReason::Alarm) {
+        log::warn!("wake fail {:?}, retrying", err);
+        if let Err(err) = WAKE_RETRY_TIMER.reset(WAKE_RETRY_DELAY) { log::warn!("wake retry fail {:?}", err); }
+    }
+}
+
+///  Send the commands that switch on the display controller and switch on the backlight
+fn switch_on() -> MynewtResult<()> {
+    //  The ST7789 needs 5 milliseconds after sleep out, which is covered by the queued SPI requests.
+    write_command(SLPOUT) ? ;
+    write_command(DISPON) ? ;
+    set_backlight(true)
+}
+
+///  Switch off the display when the watch becomes idle. The display is switched on again by `wake()`, which
+///  returns the watch to Active.
+fn enter_display(state: SystemState) -> MynewtResult<()> {
+    if state == SystemState::Active { return Ok(()); }
+    sleep()
+}
+
+///  Nothing to do when leaving a state
+fn exit_display(_state: SystemState) -> MynewtResult<()> {
+    Ok(())
+}
+
+///  Change the power state with interrupts disabled. Return true if the state has changed.
+fn set_state(state: PowerState) -> bool {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let changed = unsafe { STATE != state };
+    unsafe { STATE = state };
+    unsafe { os::os_arch_restore_sr(sr) };
+    changed
+}
+
+///  Switch the backlight on or off
+fn set_backlight(on: bool) -> MynewtResult<()> {
+    pinetime::backlight(Backlight::High, on) ? ;
+    Ok(())
+}
+
+///  Send a command without parameters to the display controller, without waiting for the SPI transfer
+fn write_command(cmd: u8) -> MynewtResult<()> {
+    spi::spi_noblock_write_command(cmd) ? ;
+    spi::spi_noblock_write_flush()
+}
@@ -0,0 +1,111 @@
+//!  Battery fuel gauge. The battery driver in `libs/battery` maps the voltage linearly to a charge level, but a LiPo
+//!  cell discharges along a curve that is flat in the middle, so the linear level drops quickly from 100% and then
+//!  lingers. The gauge maps the calibrated voltage along the discharge curve in `CURVE` instead, after smoothing
+//!  the voltage to hide the dips caused by the radio and the backlight. While charging, the charger raises the
+//!  voltage by `CHARGING_OFFSET`, which is subtracted. While discharging, the level never rises, so that it doesn't
+//!  wobble when the load changes. Each change of the level is posted to `GAUGE_EVENTS` for the UI, and the level
+//!  replaces the driver's level in the readings sent to the Battery Service, the beacon and the CoAP server. The
+//!  low-battery warnings in `low_battery.rs` are checked at each change.
+
+use mynewt::{
+    kernel::event::EventQueue,
+    hw::sensor::{ MilliVolts, Reading },
+};
+
+///  Battery voltage in millivolts and charge level in percent of a LiPo cell discharging at a light load, from
+///  full to empty. The level between two points is interpolated.
+const CURVE: [(u32, u8); 12] = [
+    (4180, 100),
+    (4100,  90),
+    (4020,  80),
+    (3950,  70),
+    (3880,  60),
+    (3830,  50),
+    (3790,  40),
+    (3750,  30),
+    (3710,  20),
+    (3670,  10),
+    (3600,   5),
+    (3400,   0),
+];
+
+///  Voltage added by the charger while charging, in millivolts
+const CHARGING_OFFSET: u32 = 100;
+
+///  Weight of a new reading in the smoothed voltage is `1 / SMOOTHING`
+const SMOOTHING: u32 = 4;
+
+///  Charge level of the battery, posted when it changes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GaugeEvent {
+    ///  Charge level in percent, from 0 to 100
+    pub percent: u8,
+    ///  True if the battery is charging
+    pub charging: bool,
+}
+
+///  Gauge events for the UI. Call `GAUGE_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static GAUGE_EVENTS: EventQueue<GaugeEvent> = EventQueue::new();
+
+///  Smoothed battery voltage in millivolts, 0 before the first reading. Only updated by the sensor listener.
+static mut SMOOTHED_MV: u32 = 0;
+
+///  Last charge level, `None` before the first reading. Only updated by the sensor listener.
+static mut LAST: Option<GaugeEvent> = None;
+
+///  Return the last charge level, or `None` if the battery has not been read yet
+pub fn level() -> Option<GaugeEvent> {
+    unsafe { LAST }
+}
+
+///  Update the gauge with the calibrated battery `reading`, and return the reading with the charge level of the
+///  gauge. Other readings are returned unchanged. Called by the battery sensor listener.
+pub fn update(reading: &Reading) -> Reading {
+    let (mv, charging) = match *reading {
+        Reading::Battery { mv: MilliVolts(mv), charging, .. } => (mv, charging),
+        other => return other,
+    };
+    let smoothed = smooth(mv, charging);
+    let measured = percent(if charging { smoothed.saturating_sub(CHARGING_OFFSET) } else { smoothed });
+    let percent = match unsafe { LAST } {
+        //  While discharging, keep the level from rising with the load.
+        Some(last) if !charging && !last.charging && measured > last.percent => last.percent,
+        _ => measured,
+    };
+    let event = GaugeEvent { percent, charging };
+    if unsafe { LAST } != Some(event) {
+        unsafe { LAST = Some(event) };
+        GAUGE_EVENTS.post(event).ok();  //  Drop the event if the UI is not receiving events
+        super::low_battery::update(event);
+    }
+    Reading::Battery { mv: MilliVolts(mv), percent, charging }
+}
+
+///  Add the voltage `mv` to the smoothed voltage and return the smoothed voltage. The smoothing restarts when the
+///  charging state changes, since the voltage jumps by `CHARGING_OFFSET`.
+fn smooth(mv: u32, charging: bool) -> u32 {
+    let restart = match unsafe { LAST } {
+        Some(last) => last.charging != charging,
+        None => true,
+    };
+    let smoothed = unsafe {
+        if restart || SMOOTHED_MV == 0 { mv }
+        else { (SMOOTHED_MV * (SMOOTHING - 1) + mv) / SMOOTHING }
+    };
+    unsafe { SMOOTHED_MV = smoothed };
+    smoothed
+}
+
+///  Return the charge level in percent for the voltage `mv` on the discharge curve
+fn percent(mv: u32) -> u8 {
+    let (full_mv, full) = CURVE[0];
+    if mv >= full_mv { return full; }
+    for pair in CURVE.windows(2) {
+        let ((high_mv, high), (low_mv, low)) = (pair[0], pair[1]);
+        if mv >= low_mv {
+            //  Interpolate between the two points.
+            return low + ((mv - low_mv) * (high - low) as u32 / (high_mv - low_mv)) as u8;
+        }
+    }
+    0
+}
@@ -0,0 +1,286 @@
+//!  Battery history for analysing the discharge curve and comparing the battery life between firmware versions.
+//!  Every `SAMPLE_PERIOD`, the battery voltage, the charge level of the fuel gauge, the charging state, the time
+//!  and the firmware version are recorded as a `BatterySample` in a circular buffer on the External SPI Flash,
+//!  in the region `map::BATTERY_LOG`. The buffer is written in sectors: when the newest sector is full, the oldest
+//!  sector is erased and reused, so the history keeps the last 3 to 4 sectors of samples, about 10 days. The samples
+//!  are numbered, so the newest sample is found again after a restart. Every `UPLOAD_PERIOD`, the samples recorded
+//!  since the last upload are sent to the CoAP server in batches of `MAX_BATCH`. The shell command `battery` in
+//!  `apps/my_sensor_app/src/battery_shell.c` shows, uploads or clears the history. All functions are called on the
+//!  default event queue, by the battery sensor listener, the scheduler and the shell.
+
+use core::{
+    fmt::Write,
+    time::Duration,
+};
+use mynewt::{
+    result::*,
+    hw::{
+        flash::map::{ self, Region, Storage },
+        sensor::{ MilliVolts, Reading },
+    },
+    kernel::{
+        schedule,
+        time::{ self, Instant },
+    },
+    sys::console,
+};
+use crate::{
+    app_network,
+    mcuboot::{ self, Slot },
+};
+
+///  Flash region of the circular buffer
+const HISTORY_REGION: Region = map::BATTERY_LOG;
+
+///  Size of a sector, the unit of erasing
+const SECTOR_SIZE: u32 = 4096;
+
+///  Number of sectors in the circular buffer
+const NUM_SECTORS: u32 = HISTORY_REGION.size / SECTOR_SIZE;
+
+///  Size of a sample in flash
+const SAMPLE_SIZE: u32 = core::mem::size_of::<BatterySample>() as u32;
+
+///  Minimum interval between samples. The battery is read every 5 minutes by `app_sensor.rs`.
+const SAMPLE_PERIOD: Duration = Duration::from_secs(15 * 60);
+
+///  Interval between uploads to the CoAP server
+const UPLOAD_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+///  Sequence number of an erased sample
+const ERASED: u32 = 0xffff_ffff;
+
+///  Number of samples shown by `battery history` without a count
+const DEFAULT_SHOW_COUNT: u32 = 20;
+
+///  Max number of samples in each CoAP message. Must match `MaxBatch`.
+pub const MAX_BATCH: usize = 8;
+type MaxBatch = heapless::consts::U8;
+
+///  Battery sample in the circular buffer
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct BatterySample {
+    ///  Sequence number, counting from 0 after the history is cleared. `ERASED` if unused.
+    pub seq:      u32,
+    ///  Unix time of the sample, 0 if the wall clock was not set
+    pub time:     u32,
+    ///  Battery voltage in millivolts
+    pub mv:       u16,
+    ///  Charge level from the fuel gauge, in percent
+    pub percent:  u8,
+    ///  1 if the battery was charging
+    pub charging: u8,
+    ///  Firmware version that recorded the sample, packed as `major << 24 | minor << 16 | revision`
+    pub version:  u32,
+}
+
+///  Sequence number of the next sample
+static mut NEXT_SEQ: u32 = 0;
+
+///  Offset of the next sample in the region
+static mut NEXT_OFFSET: u32 = 0;
+
+///  Samples before this sequence number have been uploaded to the CoAP server
+static mut UPLOADED: u32 = 0;
+
+///  Time of the last sample, `None` before the first sample
+static mut LAST_SAMPLE: Option<Instant> = None;
+
+///  Packed version of the running firmware
+static mut VERSION: u32 = 0;
+
+///  Find the newest sample in the circular buffer and schedule the uploads. The samples recorded before the
+///  restart are not uploaded again. Must be called before the battery listener starts. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    if let Some(info) = mcuboot::read_image_info(Slot::Active) ? {
+        unsafe { VERSION = info.header.ih_ver.packed() };
+    }
+    let (seq, offset) = find_next() ? ;
+    unsafe {
+        NEXT_SEQ = seq;
+        NEXT_OFFSET = offset;
+        UPLOADED = seq;
+    }
+    schedule::every("bat_upload", UPLOAD_PERIOD, upload_job) ? ;
+    Ok(())
+}
+
+///  Record the calibrated battery `reading` with the charge level of the fuel gauge, unless a sample was recorded
+///  less than `SAMPLE_PERIOD` ago. Other readings are ignored. Called by the battery sensor listener.
+pub fn record(reading: &Reading) -> MynewtResult<()> {
+    let (mv, percent, charging) = match *reading {
+        Reading::Battery { mv: MilliVolts(mv), percent, charging } => (mv, percent, charging),
+        _ => return Ok(()),
+    };
+    let now = Instant::now();
+    if let Some(last) = unsafe { LAST_SAMPLE } {
+        if now.duration_since(last) < SAMPLE_PERIOD { return Ok(()); }
+    }
+    unsafe { LAST_SAMPLE = Some(now) };
+    let sample = BatterySample {
+        seq:      unsafe { NEXT_SEQ },
+        time:     time::wall_clock().unwrap_or(0) as u32,
+        mv:       mv as u16,
+        percent,
+        charging: charging as u8,
+        version:  unsafe { VERSION },
+    };
+    let offset = unsafe { NEXT_OFFSET };
+    //  At the start of a sector, erase the oldest samples.
+    if offset % SECTOR_SIZE == 0 { HISTORY_REGION.erase(offset, SECTOR_SIZE) ? ; }
+    HISTORY_REGION.write(offset, as_bytes(&sample)) ? ;
+    unsafe {
+        NEXT_SEQ += 1;
+        NEXT_OFFSET = (offset + SAMPLE_SIZE) % HISTORY_REGION.size;
+    }
+    Ok(())
+}
+
+///  Call `f` with each sample in the history, from the oldest to the newest. Stops at the first error.
+pub fn for_each<F: FnMut(&BatterySample) -> MynewtResult<()>>(mut f: F) -> MynewtResult<()> {
+    //  The oldest samples are in the sector after the newest sample, unless that sector has not been used yet.
+    let newest = (unsafe { NEXT_OFFSET } + HISTORY_REGION.size - SAMPLE_SIZE) % HISTORY_REGION.size;
+    let start = (newest / SECTOR_SIZE + 1) % NUM_SECTORS * SECTOR_SIZE;
+    let mut offset = start;
+    loop {
+        let sample = read_sample(offset) ? ;
+        if sample.seq != ERASED { f(&sample) ? ; }
+        offset = (offset + SAMPLE_SIZE) % HISTORY_REGION.size;
+        if offset == start { return Ok(()); }
+    }
+}
+
+///  Send the samples recorded since the last upload to the CoAP server. Stops at the first post that fails, the
+///  remaining samples are sent at the next upload.
+pub fn upload() -> MynewtResult<()> {
+    let mut batch = heapless::Vec::<BatterySample, MaxBatch>::new();
+    for_each(|sample| {
+        if sample.seq < unsafe { UPLOADED } { return Ok(()); }
+        batch.push(*sample).ok();  //  Never full, the batch is sent when full
+        if batch.len() == MAX_BATCH { send_batch(&mut batch) ? ; }
+        Ok(())
+    }) ? ;
+    if !batch.is_empty() { send_batch(&mut batch) ? ; }
+    Ok(())
+}
+
+///  Erase the history. The sequence numbers restart from 0.
+pub fn clear() -> MynewtResult<()> {
+    HISTORY_REGION.erase(0, HISTORY_REGION.size) ? ;
+    unsafe {
+        NEXT_SEQ = 0;
+        NEXT_OFFSET = 0;
+        UPLOADED = 0;
+        LAST_SAMPLE = None;
+    }
+    Ok(())
+}
+
+///  Send the samples in the batch to the CoAP server and empty the batch
+fn send_batch(batch: &mut heapless::Vec<BatterySample, MaxBatch>) -> MynewtResult<()> {
+    app_network::send_battery_history(batch) ? ;
+    if let Some(last) = batch.last() { unsafe { UPLOADED = last.seq + 1 }; }
+    batch.clear();
+    Ok(())
+}
+
+///  Upload the new samples. Called by the scheduler every `UPLOAD_PERIOD`.
+fn upload_job() {
+    if let Err(err) = upload() { log::warn!("battery history post fail {:?}", err); }
+}
+
+///  Return the sequence number and the offset of the next sample after the newest sample in the circular buffer
+fn find_next() -> MynewtResult<(u32, u32)> {
+    //  Find the sector that starts with the newest sample.
+    let mut newest: Option<(u32, u32)> = None;
+    for sector in 0..NUM_SECTORS {
+        let first = read_sample(sector * SECTOR_SIZE) ? ;
+        if first.seq == ERASED { continue; }
+        match newest {
+            Some((seq, _)) if seq > first.seq => {}
+            _ => newest = Some((first.seq, sector * SECTOR_SIZE)),
+        }
+    }
+    let (mut seq, start) = match newest {
+        Some(newest) => newest,
+        None => return Ok((0, 0)),  //  History is empty
+    };
+    //  Find the first unused sample in that sector.
+    let mut offset = start;
+    while offset < start + SECTOR_SIZE {
+        let sample = read_sample(offset) ? ;
+        if sample.seq == ERASED { break; }
+        seq = sample.seq + 1;
+        offset += SAMPLE_SIZE;
+    }
+    Ok((seq, offset % HISTORY_REGION.size))
+}
+
+///  Read the sample at `offset` in the region
+fn read_sample(offset: u32) -> MynewtResult<BatterySample> {
+    let mut sample = BatterySample::default();
+    HISTORY_REGION.read(offset, as_bytes_mut(&mut sample)) ? ;
+    Ok(sample)
+}
+
+///  Shell command `battery history [count]`: Print the newest `count` samples, oldest first
+#[no_mangle]
+extern "C" fn battery_shell_history(count: u32) -> i32 {
+    let count = if count == 0 { DEFAULT_SHOW_COUNT } else { count };
+    let first = unsafe { NEXT_SEQ }.saturating_sub(count);
+    console::print("  seq       time   mV   %  version\n");
+    let result = for_each(|sample| {
+        if sample.seq < first { return Ok(()); }
+        let mut line = heapless::String::<heapless::consts::U64>::new();
+        write!(line, "{:5} {:10} {:4} {:3}  {}.{}.{}{}\n",
+            sample.seq, sample.time, sample.mv, sample.percent,
+            sample.version >> 24, (sample.version >> 16) & 0xff, sample.version & 0xffff,
+            if sample.charging != 0 { " charging" } else { "" }).ok();  //  Truncated if too long
+        console::print(&line);
+        Ok(())
+    });
+    console::flush();
+    to_rc(result)
+}
+
+///  Shell command `battery send`: Send the entire history to the CoAP server
+#[no_mangle]
+extern "C" fn battery_shell_send() -> i32 {
+    unsafe { UPLOADED = 0 };
+    to_rc(upload())
+}
+
+///  Shell command `battery clear`: Erase the history
+#[no_mangle]
+extern "C" fn battery_shell_clear() -> i32 {
+    to_rc(clear())
+}
+
+///  Convert the result to a shell return code
+fn to_rc(result: MynewtResult<()>) -> i32 {
+    match result {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
+
+///  Return the sample as bytes for writing to SPI Flash
+fn as_bytes(sample: &BatterySample) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(
+            sample as *const BatterySample as *const u8,
+            SAMPLE_SIZE as usize
+        )
+    }
+}
+
+///  Return the sample as mutable bytes for reading from SPI Flash
+fn as_bytes_mut(sample: &mut BatterySample) -> &mut [u8] {
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            sample as *mut BatterySample as *mut u8,
+            SAMPLE_SIZE as usize
+        )
+    }
+}
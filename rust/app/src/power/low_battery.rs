@@ -0,0 +1,148 @@
+//!  Low-battery warnings and safe shutdown. Each change of the charge level from the fuel gauge in `gauge.rs` is
+//!  checked against the thresholds in the settings. When the level falls below `settings::BATTERY_WARN` percent,
+//!  and again below `settings::BATTERY_CRITICAL` percent, the display is switched on with the warning, the vibration
+//!  motor plays `settings::HAPTIC_LOW_BATTERY`, the warning is sent to the CoAP server and posted to
+//!  `BATTERY_EVENTS` for the UI. Below `settings::BATTERY_SHUTDOWN` percent, the watch shows the warning for
+//!  `SHUTDOWN_DELAY`, then shuts down in order: the step count is saved, the flashing of the logo stops at the next
+//!  sector boundary with the completed sectors in the logo journal, and the power manager blanks the display and
+//!  enters Deep Sleep until the charger is connected. Each warning is given once, and again only after the level
+//!  has risen `HYSTERESIS` percent above its threshold, e.g. after charging.
+//!  `newtmgr config app/bat_warn 30` then `newtmgr config save` changes a threshold, and 0 disables it.
+
+use core::time::Duration;
+use embedded_graphics::{
+    prelude::*,
+    fonts,
+    pixelcolor::Rgb565,
+};
+use mynewt::{
+    kernel::{ event::EventQueue, timer::Callout },
+    sys::config::Setting,
+};
+use crate::{
+    app_network,
+    data_log,
+    haptics::{ self, HapticEvent },
+    pedometer,
+    settings,
+};
+use super::{ gauge::GaugeEvent, manager, WakeReason };
+
+///  Show the shutdown warning for this time before shutting down
+const SHUTDOWN_DELAY: Duration = Duration::from_secs(5);
+
+///  A warning is given again after the level has risen this many percent above its threshold
+const HYSTERESIS: u8 = 5;
+
+///  Row of the screen for showing the warning
+const WARNING_ROW: i32 = 190;
+
+///  Low-battery warning, in increasing order of severity
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum BatteryWarning {
+    ///  Level below `settings::BATTERY_WARN`
+    Low,
+    ///  Level below `settings::BATTERY_CRITICAL`
+    Critical,
+    ///  Level below `settings::BATTERY_SHUTDOWN`, the watch is shutting down
+    Shutdown,
+}
+
+impl BatteryWarning {
+    ///  Return the name of the warning, e.g. for the CoAP server
+    pub fn name(self) -> &'static str {
+        match self {
+            BatteryWarning::Low      => "low",
+            BatteryWarning::Critical => "critical",
+            BatteryWarning::Shutdown => "shutdown",
+        }
+    }
+}
+
+///  Warning posted when the charge level crosses a threshold
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BatteryEvent {
+    ///  Threshold that was crossed
+    pub warning: BatteryWarning,
+    ///  Charge level in percent
+    pub percent: u8,
+}
+
+///  Battery warnings for the UI. Call `BATTERY_EVENTS.receive()` in the UI task. Events are dropped when the queue
+///  is full.
+pub static BATTERY_EVENTS: EventQueue<BatteryEvent> = EventQueue::new();
+
+///  Most severe warning given since the level was last above the thresholds. Only updated by the sensor listener.
+static mut WARNED: Option<BatteryWarning> = None;
+
+///  Timer that shuts down the watch on the default event queue after the shutdown warning
+static SHUTDOWN_TIMER: Callout<fn()> = Callout::new(shut_down);
+
+///  Check the charge level `event` against the thresholds, and warn or shut down when a threshold is crossed.
+///  Called by the fuel gauge when the level changes.
+pub fn update(event: GaugeEvent) {
+    if event.charging {
+        //  Charging rearms all warnings. The charger resumes the watch if it was shut down.
+        unsafe { WARNED = None };
+        SHUTDOWN_TIMER.stop();
+        return;
+    }
+    //  Rearm the warnings whose thresholds the level has risen well above.
+    let rearmed = warning(event.percent.saturating_sub(HYSTERESIS));
+    unsafe { if rearmed < WARNED { WARNED = rearmed; } }
+    let warning = match warning(event.percent) {
+        Some(warning) if Some(warning) > unsafe { WARNED } => warning,
+        _ => return,
+    };
+    unsafe { WARNED = Some(warning) };
+    warn(BatteryEvent { warning, percent: event.percent });
+}
+
+///  Return the most severe warning for the charge level `percent`, or `None` if above all thresholds
+fn warning(percent: u8) -> Option<BatteryWarning> {
+    let below = |setting: &'static Setting<u8>| { let limit = setting.get(); limit > 0 && percent < limit };
+    if below(&settings::BATTERY_SHUTDOWN)      { Some(BatteryWarning::Shutdown) }
+    else if below(&settings::BATTERY_CRITICAL) { Some(BatteryWarning::Critical) }
+    else if below(&settings::BATTERY_WARN)     { Some(BatteryWarning::Low) }
+    else { None }
+}
+
+///  Show the warning, vibrate, send the warning to the CoAP server and notify the UI. Schedule the shutdown for
+///  the shutdown warning.
+fn warn(event: BatteryEvent) {
+    mynewt::log_kv!(log::Level::Warn, "battery warning"; warning = event.warning.name(), percent = event.percent);
+    data_log::record_battery_warning(&event);
+    if let Err(err) = super::wake(WakeReason::LowBattery) { log::warn!("battery wake fail {:?}", err); }
+    show_warning(&event);
+    haptics::play(HapticEvent::LowBattery);
+    if let Err(err) = app_network::send_battery_warning(&event) { log::warn!("battery send fail {:?}", err); }
+    BATTERY_EVENTS.post(event).ok();  //  Drop the event if the UI is not receiving events
+    if event.warning == BatteryWarning::Shutdown {
+        if let Err(err) = SHUTDOWN_TIMER.reset(SHUTDOWN_DELAY) { log::warn!("battery shutdown fail {:?}", err); }
+    }
+}
+
+///  Save the state and enter Deep Sleep until the charger is connected. Called by the default event queue.
+fn shut_down() {
+    log::warn!("battery shutdown");
+    //  Save the steps counted since the last save, which are lost if the battery runs out.
+    if let Err(err) = pedometer::save_steps() { log::warn!("battery steps fail {:?}", err); }
+    manager::shut_down();
+}
+
+///  Show the warning at the bottom of the screen
+fn show_warning(event: &BatteryEvent) {
+    let mut line = heapless::String::<heapless::consts::U20>::new();
+    let label = match event.warning {
+        BatteryWarning::Low      => "Battery low",
+        BatteryWarning::Critical => "Charge now",
+        BatteryWarning::Shutdown => "Power off",
+    };
+    core::fmt::write(&mut line, format_args!(" {:11}{:3}% ", label, event.percent)).ok();
+    let text = fonts::Font12x16::<Rgb565>
+        ::render_str(&line)                                    //  Text to be rendered
+        .stroke( Some( Rgb565::from(( 0xff, 0x00, 0x00 )) ) )  //  Red text
+        .fill(   Some( Rgb565::from(( 0x00, 0x00, 0x00 )) ) )  //  Black background
+        .translate( Coord::new( 20, WARNING_ROW ));            //  Shift the text to the row
+    druid::draw_to_display(text);
+}
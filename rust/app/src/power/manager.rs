@@ -0,0 +1,193 @@
+//!  System power manager. The watch is in one of three states:
+//!  - Active: the watch is being used, all subsystems run at full speed.
+//!  - Idle: after `settings::DISPLAY_TIMEOUT` without activity, or when the wrist drops, the display is switched
+//!    off, the touch controller sleeps, the accelerometer samples in low-power mode and the sensors are polled less
+//!    often.
+//!  - Deep Sleep: after `settings::DEEP_SLEEP_TIME` in Idle, the connectable advertising is stopped, the sensors
+//!    are paused, the heart rate LED is switched off and the External SPI Flash is powered down. The RAM is
+//!    retained, so the watch resumes at once.
+//!  Each subsystem registers `PowerHooks` with `register()`. On each change of state, the `exit` hooks are called
+//!  with the old state in reverse order of registration, then the `enter` hooks with the new state in order of
+//!  registration, on the default event queue. A hook that fails is logged and doesn't stop the change of state.
+//!  `activity()` returns the watch to Active and restarts the inactivity timer. It's called by `power::wake()`,
+//!  so the button, the wrist raise, the alerts, the notifications and the charger all count as activity, and by
+//!  the touches while the display is on. `idle()` steps down to Idle at once, e.g. when the wrist drops.
+//!  Deep Sleep is not entered while a phone is connected, or while `power::hold_awake()` holds the watch awake.
+//!  `shut_down()` enters Deep Sleep at once when the battery is nearly empty, and ignores all activity until
+//!  `resume()` is called when the charger is connected.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hw::flash,
+    kernel::{ event::EventQueue, timer::Callout },
+    sys::power_profile::{ self, Subsystem },
+};
+use crate::{ phone, settings };
+
+///  Power state of the whole watch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SystemState {
+    ///  Watch is being used
+    Active,
+    ///  Display and touch controller are off, and the sensors are polled less often
+    Idle,
+    ///  Advertising, sensors and heart rate LED are stopped, flash is powered down, RAM is retained
+    DeepSleep,
+}
+
+///  Hooks of a subsystem, called on each change of state on the default event queue
+pub struct PowerHooks {
+    ///  Name of the subsystem for the logs
+    pub name: &'static str,
+    ///  Called with the new state after the change
+    pub enter: fn(SystemState) -> MynewtResult<()>,
+    ///  Called with the old state before the change
+    pub exit: fn(SystemState) -> MynewtResult<()>,
+}
+
+///  Max number of subsystems that may register hooks. Must match `MaxHooks`.
+pub const MAX_HOOKS: usize = 8;
+type MaxHooks = heapless::consts::U8;
+
+///  State changes for the UI. Call `MANAGER_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static MANAGER_EVENTS: EventQueue<SystemState> = EventQueue::new();
+
+///  Hooks registered by the subsystems
+static mut HOOKS: heapless::Vec<&'static PowerHooks, MaxHooks> = heapless::Vec(heapless::i::Vec::new());
+
+///  Current state. Only changed on the default event queue.
+static mut STATE: SystemState = SystemState::Active;
+
+///  True after `start()`, when the timers may be used by any task
+static mut STARTED: bool = false;
+
+///  True after `shut_down()`, until `resume()`
+static mut SHUT_DOWN: bool = false;
+
+///  Timer that switches off the display and steps down to Idle, then to Deep Sleep, when there is no activity
+static INACTIVITY_TIMER: Callout<fn()> = Callout::new(handle_inactivity);
+
+///  Timer that returns to Active on the default event queue after `activity()` in another task
+static ACTIVITY_TIMER: Callout<fn()> = Callout::new(handle_activity);
+
+///  Hooks for the External SPI Flash, which is powered down in Deep Sleep
+static FLASH_HOOKS: PowerHooks = PowerHooks { name: "flash", enter: enter_flash, exit: exit_flash };
+
+///  Register the hooks of the display and the flash, and start the inactivity timer. The other subsystems register
+///  their own hooks when they start. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    register(&super::DISPLAY_HOOKS) ? ;
+    register(&FLASH_HOOKS) ? ;
+    //  The display is switched on at startup, before it's profiled by `power::wake()`.
+    if super::is_awake() { power_profile::begin(Subsystem::Display); }
+    //  Initialise the timers before other tasks may reset them.
+    ACTIVITY_TIMER.reset(Duration::from_millis(0)) ? ;
+    INACTIVITY_TIMER.reset(display_timeout()) ? ;
+    unsafe { STARTED = true };
+    Ok(())
+}
+
+///  Call the `hooks` on each change of state. Returns `SYS_ENOMEM` if there are more than `MAX_HOOKS` subsystems.
+///  Must be called before the state changes, e.g. in `main()`.
+pub fn register(hooks: &'static PowerHooks) -> MynewtResult<()> {
+    unsafe { HOOKS.push(hooks) }.map_err(|_| MynewtError::SYS_ENOMEM)
+}
+
+///  Return the current state
+pub fn state() -> SystemState {
+    unsafe { STATE }
+}
+
+///  Return to Active if idle or sleeping, and restart the inactivity timer. Does nothing while shut down. May be
+///  called by any task.
+pub fn activity() {
+    if !unsafe { STARTED } || is_shut_down() { return; }
+    //  Ignore the timer errors, the timers have been initialised by `start()`.
+    if state() != SystemState::Active { ACTIVITY_TIMER.reset(Duration::from_millis(0)).ok(); }
+    INACTIVITY_TIMER.reset(display_timeout()).ok();
+}
+
+///  Step down to Idle now, unless the watch is held awake by `power::hold_awake()`. Does nothing if already idle
+///  or sleeping. May be called by any task.
+pub fn idle() {
+    if !unsafe { STARTED } || state() != SystemState::Active { return; }
+    INACTIVITY_TIMER.reset(Duration::from_millis(0)).ok();  //  Ignore the error, the timer has been initialised
+}
+
+///  Enter Deep Sleep now and stay there until `resume()`, e.g. when the battery is nearly empty. Must be called
+///  on the default event queue.
+pub fn shut_down() {
+    unsafe { SHUT_DOWN = true };
+    INACTIVITY_TIMER.stop();
+    change_state(SystemState::DeepSleep);
+}
+
+///  Return to Active after `shut_down()`, e.g. when the charger is connected. Does nothing if not shut down.
+///  May be called by any task.
+pub fn resume() {
+    if !is_shut_down() { return; }
+    log::info!("power resume");
+    unsafe { SHUT_DOWN = false };
+    activity();
+}
+
+///  Return true if the watch has been shut down by `shut_down()`
+pub fn is_shut_down() -> bool {
+    unsafe { SHUT_DOWN }
+}
+
+///  Return to Active after `activity()`, unless shut down in the meantime. Called by the default event queue.
+fn handle_activity() {
+    if is_shut_down() { return; }
+    change_state(SystemState::Active);
+}
+
+///  Step down to Idle, then to Deep Sleep, unless the watch is held awake. Called by the default event queue.
+fn handle_inactivity() {
+    let (next, wait) = match state() {
+        _ if super::is_held_awake() => (SystemState::Active, display_timeout()),
+        SystemState::Active => (SystemState::Idle, deep_sleep_time()),
+        SystemState::Idle if phone::is_connected() => (SystemState::Idle, deep_sleep_time()),
+        SystemState::Idle | SystemState::DeepSleep => (SystemState::DeepSleep, Duration::from_millis(0)),
+    };
+    change_state(next);
+    if next != SystemState::DeepSleep { INACTIVITY_TIMER.reset(wait).expect("power timer fail"); }
+}
+
+///  Call the hooks and post the change of state. Does nothing if the state is unchanged.
+fn change_state(state: SystemState) {
+    let old = unsafe { STATE };
+    if old == state { return; }
+    log::info!("power {:?} -> {:?}", old, state);
+    let hooks = unsafe { &HOOKS };
+    for hook in hooks.iter().rev() {
+        if let Err(err) = (hook.exit)(old) { log::warn!("power {} exit fail {:?}", hook.name, err); }
+    }
+    unsafe { STATE = state };
+    for hook in hooks.iter() {
+        if let Err(err) = (hook.enter)(state) { log::warn!("power {} enter fail {:?}", hook.name, err); }
+    }
+    MANAGER_EVENTS.post(state).ok();  //  Drop the event if the UI is not receiving events
+}
+
+///  Power down the External SPI Flash in Deep Sleep
+fn enter_flash(state: SystemState) -> MynewtResult<()> {
+    if state != SystemState::DeepSleep { return Ok(()); }
+    flash::power_down()
+}
+
+///  Nothing to do when leaving a state, the flash is released by the next flash operation
+fn exit_flash(_state: SystemState) -> MynewtResult<()> {
+    Ok(())
+}
+
+///  Return the inactivity before the display is switched off in the settings
+fn display_timeout() -> Duration {
+    Duration::from_millis(settings::DISPLAY_TIMEOUT.get() as u64)
+}
+
+///  Return the inactivity before Deep Sleep in the settings
+fn deep_sleep_time() -> Duration {
+    Duration::from_millis(settings::DEEP_SLEEP_TIME.get() as u64)
+}
@@ -0,0 +1,29 @@
+//!  Report the power profile measured by `mynewt::sys::power_profile` every `REPORT_PERIOD`, on the console and to
+//!  the CoAP server, for tuning the battery life. The totals are kept since startup, so a report that can't be posted,
+//!  e.g. while a phone is syncing, is covered by the next one.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    kernel::timer::Callout,
+    sys::power_profile,
+};
+use crate::app_network;
+
+///  Interval between reports
+const REPORT_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+///  Timer that sends the reports
+static REPORT_TIMER: Callout<fn()> = Callout::new(report);
+
+///  Start reporting the power profile. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    REPORT_TIMER.reset(REPORT_PERIOD)
+}
+
+///  Show the power profile and send it to the CoAP server, then report again after the period
+fn report() {
+    power_profile::show();
+    if let Err(err) = app_network::send_power_profile() { log::warn!("power profile post fail {:?}", err); }
+    REPORT_TIMER.reset(REPORT_PERIOD).expect("profile timer fail");
+}
@@ -0,0 +1,91 @@
+//!  Power-failure warning. When the battery is nearly empty, the supply voltage falls below `THRESHOLD` before the
+//!  watch loses power, and the nRF52 POF comparator interrupts. The backlight, which draws most of the current, is
+//!  switched off in the interrupt, and the display is blanked in the default event queue. Flashing of the logo
+//!  calls `check()` before erasing each sector, so it stops at a sector boundary, with the completed sectors recorded
+//!  in the logo journal, instead of losing power in the middle of an erase. It stops the same way after a low-battery
+//!  shutdown by `power/low_battery.rs`. The warning and the last sector started are recorded in RAM that is not
+//!  cleared at startup, and shown at the next startup if the watch restarted on a brown-out.
+
+use mynewt::{
+    result::*,
+    board::pinetime::{ self, Backlight },
+    hal::pof::{ self, Threshold },
+    kernel::{ channel::Channel, os },
+    sys::{ console, noinit::{ NoInit, NoInitValue } },
+};
+use crate::power::{ self, manager };
+
+///  Supply voltage that triggers the warning. The SPI Flash needs at least 2.7 V.
+const THRESHOLD: Threshold = Threshold::V28;
+
+///  Value of `sector` when no flash sector has been started
+const NO_SECTOR: u32 = 0xffff_ffff;
+
+///  Power-failure warning before the last restart
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct PowerFailRecord {
+    ///  Offset of the last flash sector started before the warning, or `NO_SECTOR`
+    sector: u32,
+}
+
+unsafe impl NoInitValue for PowerFailRecord {
+    const MAGIC: u32 = 0x5746_4f50;  //  `POFW`
+}
+
+///  Power-failure warning before the last restart, stored in the Mynewt section `.bss.core.nz` that is not cleared
+///  at startup
+#[link_section = ".bss.core.nz"]
+static mut LAST_POWER_FAIL: NoInit<PowerFailRecord> = NoInit::new(PowerFailRecord { sector: 0 });
+
+///  Offset of the last flash sector started, set by `check()`
+static mut SECTOR: u32 = NO_SECTOR;
+
+///  Warnings passed from the interrupt to the default event queue
+static WARNINGS: Channel<()> = Channel::new();
+
+///  Show the warning before the last restart, if any, and start watching the supply voltage. Called by main() in
+///  `lib.rs` before the logo is flashed.
+pub fn start() -> MynewtResult<()> {
+    show_last();
+    WARNINGS.notify(os::eventq_dflt_get() ? , handle_warnings);
+    pof::start(THRESHOLD, handle_warning)
+}
+
+///  Return `SYS_EAGAIN` if the power may be lost or the watch has been shut down for low battery, so that no flash
+///  erase is started, else record `sector` as the sector being flashed. Called before erasing each sector of the
+///  logo or the asset bundle.
+pub fn check(sector: u32) -> MynewtResult<()> {
+    if pof::is_warning() || manager::is_shut_down() { return Err(MynewtError::SYS_EAGAIN); }
+    unsafe { SECTOR = sector };
+    Ok(())
+}
+
+///  Switch off the backlight and record the warning. Called by the POF interrupt, so the display is blanked later.
+fn handle_warning() {
+    for level in &[ Backlight::Low, Backlight::Mid, Backlight::High ] {
+        pinetime::backlight(*level, false).ok();
+    }
+    unsafe { LAST_POWER_FAIL.save(PowerFailRecord { sector: SECTOR }) };
+    WARNINGS.push(()).ok();  //  Already blanking if full
+}
+
+///  Put the display to sleep after a warning. Called by the default event queue.
+extern "C" fn handle_warnings(_ev: *mut os::os_event) {
+    while WARNINGS.pop().is_some() {}
+    power::sleep().ok();  //  Backlight is already off
+    console::print("power fail warning, flash writes stopped\n"); console::flush();
+}
+
+///  Display the warning before the last restart, if any, and clear the record
+fn show_last() {
+    let record = match unsafe { LAST_POWER_FAIL.take() } {
+        Some(record) => record,
+        None => return,
+    };
+    console::print("last restart after power fail warning");
+    if record.sector != NO_SECTOR {
+        console::print(", last flash sector at "); console::printint(record.sector as i32);
+    }
+    console::print("\n"); console::flush();
+}
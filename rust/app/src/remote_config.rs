@@ -0,0 +1,75 @@
+//!  Remote configuration of the settings over CoAP. The current values of the settings in `settings.rs` are sent to
+//!  the CoAP server after startup and every `SYNC_PERIOD`. The server may respond to each post with new values in
+//!  a CBOR map, e.g. `{"poll_ms": 10000, "hap_mute": true}`, which are checked against the type of each setting
+//!  and saved to flash by `config::import()`. Unknown names and invalid values are logged and skipped. Like the
+//!  changes over newtmgr, most settings take effect at the next restart. The CoAP client discards the responses to
+//!  the other posts.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    kernel::timer::Callout,
+    sys::config,
+    Strn,
+};
+use crate::app_network;
+
+///  Delay after startup before the first sync, for the network to be ready
+const FIRST_SYNC_DELAY: Duration = Duration::from_secs(90);
+
+///  Interval between syncs
+const SYNC_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+///  Timer that sends the settings
+static SYNC_TIMER: Callout<fn()> = Callout::new(sync_timer);
+
+///  Start the remote configuration and log the changes of the settings. Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    config::on_change(log_change) ? ;
+    unsafe { sensor_coap_set_response_handler(Some(import_setting)) };
+    SYNC_TIMER.reset(FIRST_SYNC_DELAY)
+}
+
+///  Send the current values of all settings to the CoAP server, a few settings per message
+pub fn sync() -> MynewtResult<()> {
+    let mut next = 0;
+    while next < config::count() {
+        next = app_network::send_settings(next) ? ;
+    }
+    Ok(())
+}
+
+///  Send the settings, then sync again after the period. Called by the default event queue.
+fn sync_timer() {
+    if let Err(err) = sync() { log::warn!("settings post fail {:?}", err); }
+    SYNC_TIMER.reset(SYNC_PERIOD).expect("sync timer fail");
+}
+
+///  Log the change of a setting, from the server, newtmgr or the app
+fn log_change(name: &str) {
+    log::info!("setting {} changed", name);
+}
+
+///  Save the new value of a setting received from the CoAP server. Called by `libs/sensor_coap` for each item in
+///  the response to `app_network::send_settings()`.
+extern "C" fn import_setting(name: *const ::cty::c_char, value: *const ::cty::c_char) {
+    let (name, value) = match (cstr_to_str(name), cstr_to_str(value)) {
+        (Some(name), Some(value)) => (name, value),
+        _ => return,
+    };
+    if let Err(err) = config::import(name, value) { log::warn!("setting {} import fail {:?}", name, err); }
+}
+
+///  Convert a null-terminated C string to `&str`. Returns `None` if null or not UTF-8.
+fn cstr_to_str<'a>(cstr: *const ::cty::c_char) -> Option<&'a str> {
+    if cstr.is_null() { return None; }
+    let len = Strn::from_cstr(cstr as *const u8).len();
+    let bytes = unsafe { core::slice::from_raw_parts(cstr as *const u8, len) };
+    core::str::from_utf8(bytes).ok()
+}
+
+extern "C" {
+    ///  Set the handler for the payload of the CoAP responses. C API: `libs/sensor_coap`
+    fn sensor_coap_set_response_handler(
+        func: Option<extern "C" fn(name: *const ::cty::c_char, value: *const ::cty::c_char)>);
+}
@@ -0,0 +1,90 @@
+//!  Shell commands for inspecting the sensors over the serial console during bring-up, registered by
+//!  `apps/my_sensor_app/src/sensor_shell.c`: `sensor list`, `sensor read <name>` and `sensor poll <name> <ms>`.
+//!  Polling changes are not saved, the poll intervals are restored at the next restart.
+
+use core::{
+    fmt::Write,
+    time::Duration,
+};
+use mynewt::{
+    result::*,
+    hw::{
+        sensor::{ poller, sensor_ptr },
+        sensor_mgr,
+    },
+    sys::console,
+    Strn,
+};
+
+///  Max time to wait for a sensor to be read
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+///  Line of shell output
+type Line = heapless::String<heapless::consts::U80>;
+
+///  Shell command `sensor list`: Print the name, types and poll interval of each sensor
+#[no_mangle]
+extern "C" fn sensor_shell_list() -> i32 {
+    for sensor in sensor_mgr::all() {
+        let devname = sensor_mgr::devname(sensor);
+        let mut line = Line::new();
+        write!(line, " types 0x{:08x}", sensor_mgr::types(sensor)).ok();
+        let written = match poller::interval(&devname) {
+            Some(interval) => write!(line, " poll {} ms\n", interval.as_millis()),
+            None           => write!(line, " not polled\n"),
+        };
+        written.ok();  //  Truncated if too long
+        console::print_strn(&devname);
+        console::print(&line);
+    }
+    console::flush();
+    0
+}
+
+///  Shell command `sensor read <name>`: Read the sensor once and print the readings.
+///  Returns `SYS_ENODEV` if there is no such sensor.
+#[no_mangle]
+extern "C" fn sensor_shell_read(name: *const u8) -> i32 {
+    let result = find(name).and_then(|sensor| {
+        sensor_mgr::read(sensor, sensor_mgr::types(sensor), |reading| {
+            let mut line = Line::new();
+            write!(line, "{:?}\n", reading).ok();  //  Truncated if too long
+            console::print(&line);
+        }, READ_TIMEOUT)
+    });
+    console::flush();
+    to_rc(result)
+}
+
+///  Shell command `sensor poll <name> <ms>`: Poll the sensor every `ms` milliseconds, or stop polling if `ms` is 0.
+///  A sensor that is not polled yet is polled for all its types. Returns `SYS_ENODEV` if there is no such sensor.
+#[no_mangle]
+extern "C" fn sensor_shell_poll(name: *const u8, ms: u32) -> i32 {
+    let result = find(name).and_then(|sensor| {
+        //  The poller keeps the name, so use the name of the sensor device instead of the shell argument.
+        let devname = sensor_mgr::devname(sensor);
+        if ms == 0 { return poller::enable(&devname, false); }
+        let interval = Duration::from_millis(ms as u64);
+        match poller::set_interval(&devname, interval) {
+            Err(MynewtError::SYS_ENOENT) => poller::add(&devname, sensor_mgr::types(sensor), interval),
+            result => result.and_then(|_| poller::enable(&devname, true)),
+        }
+    });
+    to_rc(result)
+}
+
+///  Return the sensor with device name `name`, or `SYS_ENODEV` if there is no such sensor
+fn find(name: *const u8) -> MynewtResult<sensor_ptr> {
+    if name.is_null() { return Err(MynewtError::SYS_EINVAL); }
+    sensor_mgr::find_bydevname(&Strn::from_cstr(name))
+        .next()
+        .ok_or(MynewtError::SYS_ENODEV)
+}
+
+///  Convert the result to a shell return code
+fn to_rc(result: MynewtResult<()>) -> i32 {
+    match result {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
@@ -0,0 +1,134 @@
+//!  Application settings persisted with Mynewt `sys/config`, instead of being fixed at compile time.
+//!  Registered and loaded during `sysinit()`. The settings may be changed over newtmgr, e.g.
+//!  `newtmgr config app/poll_ms 10000` then `newtmgr config save`, or by the CoAP server, see `remote_config.rs`,
+//!  and take effect at the next restart.
+
+use mynewt::{
+    result::*,
+    hw::sensor::Calibration,
+    sys::config::{ self, ConfigString, Setting },
+    Strn,
+};
+
+///  Interval for polling the sensor, in milliseconds
+pub static POLL_TIME: Setting<u32> = Setting::new("poll_ms", "30000");
+
+///  URI for posting to the CoAP server. Empty for the default `COAP_URI` in `syscfg.yml`.
+pub static SERVER_URI: Setting<ConfigString> = Setting::new("server_uri", "");
+
+///  Logo slot chosen for display by the bootloader
+pub static LOGO_SLOT: Setting<u8> = Setting::new("logo_slot", "0");
+
+///  Steps counted on the day `STEPS_DAY`. Saved every 100 steps by the pedometer.
+pub static STEPS: Setting<u32> = Setting::new("steps", "0");
+
+///  Day of the saved step count, in days since 1970. 0 if the time was not set.
+pub static STEPS_DAY: Setting<u32> = Setting::new("steps_day", "0");
+
+///  Calibration of the raw temperature, in degrees Celsius times 100
+pub static TEMP_CALIBRATION: Calibration = Calibration::new("temp_off", "temp_scale");
+
+///  Calibration of the battery voltage, in millivolts, for the tolerance of the voltage divider
+pub static BATTERY_CALIBRATION: Calibration = Calibration::new("bat_off", "bat_scale");
+
+///  Calibration of the accelerometer axes in raw units. Set by the flat calibration in `calibration.rs`.
+pub static ACCEL_X_CALIBRATION: Calibration = Calibration::new("accx_off", "accx_scale");
+pub static ACCEL_Y_CALIBRATION: Calibration = Calibration::new("accy_off", "accy_scale");
+pub static ACCEL_Z_CALIBRATION: Calibration = Calibration::new("accz_off", "accz_scale");
+
+///  Alert thresholds, checked at every reading by `alerts.rs`. 0 disables the alert.
+///  Heart rate above or below the limits, in beats per minute
+pub static HR_HIGH_ALERT: Setting<u32> = Setting::new("hr_high", "150");
+pub static HR_LOW_ALERT: Setting<u32> = Setting::new("hr_low", "40");
+///  Battery voltage below the limit, in millivolts
+pub static BATTERY_LOW_ALERT: Setting<u32> = Setting::new("bat_low", "3400");
+///  Temperature change since the last alert, in degrees Celsius times 100
+pub static TEMP_DELTA_ALERT: Setting<u32> = Setting::new("temp_delta", "500");
+
+///  Haptic feedback, played by `haptics.rs`. The pattern of each event is a `haptics::Pattern` number: 0 for none,
+///  1 for a tap, 2 for a double pulse, 3 for a long pulse, 4 for three pulses.
+///  True to silence all patterns
+pub static HAPTIC_MUTE: Setting<bool> = Setting::new("hap_mute", "0");
+pub static HAPTIC_BUTTON: Setting<u8> = Setting::new("hap_button", "1");
+pub static HAPTIC_FLASH: Setting<u8> = Setting::new("hap_flash", "2");
+pub static HAPTIC_ALERT: Setting<u8> = Setting::new("hap_alert", "4");
+pub static HAPTIC_NOTIFY: Setting<u8> = Setting::new("hap_notify", "2");
+pub static HAPTIC_LOW_BATTERY: Setting<u8> = Setting::new("hap_lowbat", "3");
+pub static HAPTIC_ALARM: Setting<u8> = Setting::new("hap_alarm", "4");
+
+///  Daily alarms rung by `alarms.rs`, as local times separated by commas, e.g. `07:30,22:00`. Empty for no alarms.
+pub static ALARMS: Setting<ConfigString> = Setting::new("alarms", "");
+
+///  Inactivity before the display is switched off and the power manager in `power/manager.rs` enters Idle, in
+///  milliseconds. Restarted by the touches, the button and the wrist raise.
+pub static DISPLAY_TIMEOUT: Setting<u32> = Setting::new("disp_ms", "15000");
+///  Inactivity in Idle before the power manager enters Deep Sleep, in milliseconds
+pub static DEEP_SLEEP_TIME: Setting<u32> = Setting::new("sleep_ms", "600000");
+
+///  Low-battery thresholds, checked at every change of the charge level by `power/low_battery.rs`, in percent.
+///  0 disables the threshold.
+///  Warn below the levels
+pub static BATTERY_WARN: Setting<u8> = Setting::new("bat_warn", "20");
+pub static BATTERY_CRITICAL: Setting<u8> = Setting::new("bat_crit", "10");
+///  Shut down below the level, until the charger is connected
+pub static BATTERY_SHUTDOWN: Setting<u8> = Setting::new("bat_shut", "3");
+
+///  Console sink for the console output at startup, e.g. `uart`, `rtt` or `nus`, see `console_sinks.rs`. Empty for
+///  the UART if the UART shell is enabled, or Semihosting otherwise.
+pub static CONSOLE: Setting<ConfigString> = Setting::new("console", "");
+
+///  Per-module log levels applied at startup, e.g. `app::power=debug,mynewt::spi=warn`, see `logger::set_filters()`
+///  in `rust/mynewt/src/sys/logger.rs`. Empty to log all modules at the default level.
+pub static LOG_FILTER: Setting<ConfigString> = Setting::new("log_filter", "");
+
+///  Null-terminated copy of `SERVER_URI`. The CoAP message refers to the URI until the message is posted. One byte
+///  longer than a `ConfigString`, so that a URI of the max length still has room for the null.
+static mut SERVER_URI_BUF: heapless::String<heapless::consts::U65> = heapless::String(heapless::i::String::new());
+
+//  Load the settings during `sysinit()`, before the application starts.
+mynewt::init_hook!(mynewt::sys::init::STAGE_APP, SETTINGS_HOOK, init);
+
+///  Register the settings and load the saved values from flash
+fn init() -> MynewtResult<()> {
+    POLL_TIME.register() ? ;
+    SERVER_URI.register() ? ;
+    LOGO_SLOT.register() ? ;
+    STEPS.register() ? ;
+    STEPS_DAY.register() ? ;
+    TEMP_CALIBRATION.register() ? ;
+    BATTERY_CALIBRATION.register() ? ;
+    ACCEL_X_CALIBRATION.register() ? ;
+    ACCEL_Y_CALIBRATION.register() ? ;
+    ACCEL_Z_CALIBRATION.register() ? ;
+    HR_HIGH_ALERT.register() ? ;
+    HR_LOW_ALERT.register() ? ;
+    BATTERY_LOW_ALERT.register() ? ;
+    TEMP_DELTA_ALERT.register() ? ;
+    HAPTIC_MUTE.register() ? ;
+    HAPTIC_BUTTON.register() ? ;
+    HAPTIC_FLASH.register() ? ;
+    HAPTIC_ALERT.register() ? ;
+    HAPTIC_NOTIFY.register() ? ;
+    HAPTIC_LOW_BATTERY.register() ? ;
+    HAPTIC_ALARM.register() ? ;
+    ALARMS.register() ? ;
+    DISPLAY_TIMEOUT.register() ? ;
+    DEEP_SLEEP_TIME.register() ? ;
+    BATTERY_WARN.register() ? ;
+    BATTERY_CRITICAL.register() ? ;
+    BATTERY_SHUTDOWN.register() ? ;
+    CONSOLE.register() ? ;
+    LOG_FILTER.register() ? ;
+    config::load() ? ;
+    let uri = unsafe { &mut SERVER_URI_BUF };
+    uri.push_str(&SERVER_URI.get()).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    uri.push('\0').map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    Ok(())
+}
+
+///  Return the URI for posting to the CoAP server, as loaded at startup. Empty for the default URI.
+pub fn server_uri() -> Strn {
+    let uri = unsafe { &SERVER_URI_BUF };
+    if uri.is_empty() { return Strn::new(b"\0"); }  //  Not loaded yet
+    Strn::from_cstr(uri.as_ptr())
+}
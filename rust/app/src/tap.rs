@@ -0,0 +1,185 @@
+//!  Tap and double-tap detection with the BMA421 accelerometer, so that the watch may be woken or a dialog confirmed
+//!  without touching the screen. The tap engine of the BMA421 needs the feature configuration file from Bosch, so the
+//!  accelerometer samples at 200 Hz into its FIFO instead, and raises the INT1 interrupt when the FIFO watermark is
+//!  reached. The interrupt handler notifies the Default Event Queue, which reads the batch of samples over I2C and
+//!  detects taps as sharp spikes in the acceleration. Taps are delivered as `TapEvent` through `TAP_EVENTS`.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    kernel::{
+        channel::Channel,
+        device::Device,
+        event::EventQueue,
+        os::{ self, os_event },
+    },
+    sys::console,
+};
+use crate::pedometer::{ self, bma421_sample };
+
+///  Tap detected by the accelerometer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TapEvent {
+    ///  One tap, without a second tap within `DOUBLE_TAP_SAMPLES`
+    SingleTap,
+    ///  Two taps in quick succession
+    DoubleTap,
+}
+
+///  Tap events for the UI. Call `TAP_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static TAP_EVENTS: EventQueue<TapEvent> = EventQueue::new();
+
+///  Output data rate for tap detection: 200 Hz. From `libs/bma421/include/bma421/bma421.h`
+const BMA421_ODR_200HZ: u8 = 0x09;
+
+///  Interrupt on FIFO watermark. From `libs/bma421/include/bma421/bma421.h`
+const BMA421_INT_FIFO_WM: u8 = 0x02;
+
+///  Size of each FIFO frame in bytes: 2 bytes for each axis
+const FIFO_FRAME_SIZE: u16 = 6;
+
+///  Number of samples in the FIFO that trigger the interrupt: 80 milliseconds at 200 Hz
+const FIFO_WATERMARK_SAMPLES: u16 = 16;
+
+///  Max number of samples read from the FIFO at a time
+const MAX_BATCH_SAMPLES: usize = 32;
+
+///  Min jerk for a tap: sum of the changes in acceleration on the 3 axes between consecutive samples, in raw units
+///  (1 g = 1024 at ±2g). Wrist movements and steps change the acceleration much slower.
+const TAP_THRESHOLD: i32 = 1200;
+
+///  Number of samples without spikes that must come before a tap: 50 milliseconds. Spikes within this time are
+///  the watch ringing from the previous shock, or shaking.
+const SHOCK_SAMPLES: u32 = 10;
+
+///  Max number of samples between the first and second taps of a double tap: 300 milliseconds
+const DOUBLE_TAP_SAMPLES: u32 = 60;
+
+///  Notified by the accelerometer interrupt when the FIFO has samples to be read
+static TAP_CHANNEL: Channel<()> = Channel::new();
+
+///  Samples read from the FIFO
+static mut SAMPLES: [bma421_sample; MAX_BATCH_SAMPLES] = [bma421_sample { x: 0, y: 0, z: 0 }; MAX_BATCH_SAMPLES];
+
+///  Tap detector for the samples read from the FIFO
+static mut DETECTOR: TapDetector = TapDetector::new();
+
+///  Sample the accelerometer into the FIFO and start detecting taps. Returns `SYS_ENODEV` if the accelerometer
+///  is not enabled in `syscfg.yml`.
+pub fn start_tap_detection() -> MynewtResult<()> {
+    console::print("Rust tap detection\n");
+    let dev = Device::open(&pedometer::ACCEL_DEVICE, Duration::from_secs(1)) ? ;
+
+    //  Call `tap_event_callback()` in the Default Event Queue when the interrupt is pushed to the channel.
+    TAP_CHANNEL.notify(os::eventq_dflt_get() ? , tap_event_callback);
+
+    //  Sample at 200 Hz into the FIFO. The pedometer still reads the latest sample at its own rate.
+    check(unsafe { bma421_set_fifo(dev.as_driver(), BMA421_ODR_200HZ,
+        FIFO_WATERMARK_SAMPLES * FIFO_FRAME_SIZE) }) ? ;
+
+    //  Call `tap_interrupt_handler()` when the FIFO watermark is reached.
+    check(unsafe { bma421_set_int_handler(dev.as_driver(), BMA421_INT_FIFO_WM,
+        Some(tap_interrupt_handler), core::ptr::null_mut()) }) ? ;
+    Ok(())
+}
+
+///  Interrupt handler for the accelerometer, triggered when the FIFO watermark is reached
+extern "C" fn tap_interrupt_handler(_arg: *mut core::ffi::c_void) {
+    //  Read the FIFO in the Default Event Queue, since I2C can't be used here. Drop the interrupt if the channel
+    //  is full: the pending callback will read all the samples anyway.
+    TAP_CHANNEL.push(()).ok();
+}
+
+///  Callback for the accelerometer interrupt. Read the samples in the FIFO and detect taps.
+extern "C" fn tap_event_callback(_event: *mut os_event) {
+    //  Take all pending interrupts. The FIFO keeps all the samples, so read it once.
+    let mut pending = false;
+    while TAP_CHANNEL.pop().is_some() { pending = true; }
+    if !pending { return; }
+
+    //  Skip the samples if the accelerometer is busy, e.g. the I2C bus is used by the touch controller.
+    let dev = match Device::open(&pedometer::ACCEL_DEVICE, Duration::from_millis(100)) {
+        Ok(dev) => dev,
+        Err(_) => return,
+    };
+    loop {
+        let count = unsafe { bma421_read_fifo(dev.as_driver(), SAMPLES.as_mut_ptr(), MAX_BATCH_SAMPLES as i32) };
+        if count <= 0 { break; }
+        for i in 0..count as usize {
+            let sample = unsafe { SAMPLES[i] };
+            let event = unsafe { DETECTOR.update(&[sample.x as i32, sample.y as i32, sample.z as i32]) };
+            if let Some(event) = event {
+                //  Drop the event if the UI is not receiving events.
+                TAP_EVENTS.post(event).ok();
+            }
+        }
+        if (count as usize) < MAX_BATCH_SAMPLES { break; }  //  FIFO is empty
+    }
+}
+
+///  Detects taps as spikes in the change of acceleration between consecutive samples. Integer only, since
+///  floating-point is disabled.
+struct TapDetector {
+    ///  Previous sample
+    last: [i32; 3],
+    ///  Number of samples since the last spike, up to `SHOCK_SAMPLES`
+    since_spike: u32,
+    ///  Number of samples since the first tap, if we are waiting for the second tap of a double tap
+    since_tap: Option<u32>,
+}
+
+impl TapDetector {
+    ///  Create a detector with no history
+    const fn new() -> Self {
+        TapDetector { last: [0; 3], since_spike: SHOCK_SAMPLES, since_tap: None }
+    }
+
+    ///  Add the sample `[x, y, z]` at 200 Hz. Return the tap event that has been detected, if any.
+    fn update(&mut self, sample: &[i32; 3]) -> Option<TapEvent> {
+        //  Jerk between consecutive samples. The sum of absolute values avoids the square root and is good enough.
+        let jerk = (0..3).map(|i| (sample[i] - self.last[i]).abs()).sum::<i32>();
+        self.last = *sample;
+        if self.since_spike < SHOCK_SAMPLES { self.since_spike += 1; }
+        if let Some(since_tap) = self.since_tap.as_mut() { *since_tap += 1; }
+
+        //  A tap is a spike after a quiet period. Spikes while the watch is still ringing belong to the same tap,
+        //  and continuous spikes from shaking the watch are not taps.
+        if jerk >= TAP_THRESHOLD {
+            let quiet = self.since_spike >= SHOCK_SAMPLES;
+            self.since_spike = 0;
+            if quiet {
+                match self.since_tap {
+                    //  Second tap in time: double tap.
+                    Some(since_tap) if since_tap <= DOUBLE_TAP_SAMPLES => {
+                        self.since_tap = None;
+                        return Some(TapEvent::DoubleTap);
+                    }
+                    //  First tap: wait for the second tap.
+                    _ => self.since_tap = Some(0),
+                }
+            }
+        }
+
+        //  No second tap in time: single tap.
+        if let Some(since_tap) = self.since_tap {
+            if since_tap > DOUBLE_TAP_SAMPLES {
+                self.since_tap = None;
+                return Some(TapEvent::SingleTap);
+            }
+        }
+        None
+    }
+}
+
+extern "C" {
+    ///  Map interrupt sources to pin INT1 and call the handler when the interrupt fires.
+    ///  C API: `int bma421_set_int_handler(struct bma421 *dev, uint8_t int_map, hal_gpio_irq_handler_t handler, void *arg)`
+    fn bma421_set_int_handler(dev: *mut ::cty::c_void, int_map: u8,
+        handler: mynewt::hw::hal::hal_gpio_irq_handler_t, arg: *mut ::cty::c_void) -> ::cty::c_int;
+    ///  Change the output data rate and FIFO watermark, then enable the FIFO.
+    ///  C API: `int bma421_set_fifo(struct bma421 *dev, uint8_t odr, uint16_t fifo_watermark)`
+    fn bma421_set_fifo(dev: *mut ::cty::c_void, odr: u8, fifo_watermark: u16) -> ::cty::c_int;
+    ///  Read the samples stored in the FIFO, oldest first. Returns the number of samples read, or negative error code.
+    ///  C API: `int bma421_read_fifo(struct bma421 *dev, struct bma421_sample *samples, int max_samples)`
+    fn bma421_read_fifo(dev: *mut ::cty::c_void, samples: *mut bma421_sample, max_samples: ::cty::c_int) -> ::cty::c_int;
+}
@@ -1,25 +1,28 @@
 use embedded_hal::{
     self,
-    blocking::delay::DelayMs,
+    blocking::{ delay::DelayMs, i2c::{ Write, WriteRead } },
     digital::v2::OutputPin,
 };
 use mynewt::{
     self,
     result::*,
+    board::pinetime,
+    hal::{
+        gpio::PinEvent,
+        i2c::I2cBus,
+    },
     hw::hal,
-    kernel::os::{
-        self,
-        os_event,
+    kernel::{
+        event::EventQueue,
+        time::Instant,
     },
     sys::console,
     fill_zero,
 };
-
-/// Reset Pin for touch controller. Note: NFC antenna pins must be reassigned as GPIO pins for this to work.
-const TOUCH_RESET_PIN: i32 = 10;  //  P0.10/NFC2: TP_RESET
-
-/// Interrupt Pin for touch controller. We listen for the touch controller interrupt and trigger an event.
-const TOUCH_INTERRUPT_PIN: i32 = 28;  //  P0.28/AIN4: TP_INT
+use crate::power::{
+    self,
+    manager::{ self, PowerHooks, SystemState },
+};
 
 /// Reset GPIO Pin
 static mut TOUCH_RESET: MynewtGPIO =  fill_zero!(MynewtGPIO);
@@ -27,6 +30,24 @@ static mut TOUCH_DELAY: MynewtDelay = fill_zero!(MynewtDelay);
 type MynewtGPIO = mynewt::GPIO;
 type MynewtDelay = mynewt::Delay;
 
+/// Hooks for the touch controller, which sleeps while the display is off
+static TOUCH_HOOKS: PowerHooks = PowerHooks { name: "touch", enter: enter_touch, exit: exit_touch };
+
+/// True while the touch controller is in deep sleep
+static mut TOUCH_ASLEEP: bool = false;
+
+/// CST816S register that puts the touch controller into deep sleep, and the command written to it.
+/// Based on https://github.com/InfiniTimeOrg/InfiniTime/blob/develop/src/drivers/Cst816s.cpp
+const CST816S_SLEEP_REGISTER: u8 = 0xA5;
+const CST816S_SLEEP_COMMAND: u8  = 0x03;
+
+/// Hold the Reset Pin low for this time, then wait this time after the reset, in milliseconds
+const RESET_LOW_MS: u8  = 5;
+const RESET_WAIT_MS: u8 = 50;
+
+//  Start the touch sensor during `sysinit()`, after the display, so that the first touch may be drawn.
+mynewt::init_hook!(mynewt::sys::init::STAGE_INPUT, TOUCH_HOOK, start_touch_sensor);
+
 /// Initialise the touch controller. NFC antenna pins must already be reassigned as GPIO pins:
 /// Set `NFC_PINS_AS_GPIO: 1` in hw/bsp/nrf52/syscfg.yml.  To check whether whether NFC antenna 
 /// pins have been correctly reassigned as GPIO pins, use the `nrf52` crate and check that the output is `fe`:
@@ -39,7 +60,7 @@ pub fn start_touch_sensor() -> MynewtResult<()> {
     console::print("Rust touch sensor\n");
 
     //  Init GPIO for the Reset Pin
-    unsafe { TOUCH_RESET.init(TOUCH_RESET_PIN) ? };
+    unsafe { TOUCH_RESET.init(pinetime::TOUCH_RESET.number()) ? };
 
     //  Reset the touch controller by switching the Reset Pin low then high with pauses. Based on https://github.com/lupyuen/hynitron_i2c_cst0xxse/blob/master/cst0xx_core.c#L1017-L1167
     unsafe {
@@ -49,38 +70,66 @@ pub fn start_touch_sensor() -> MynewtResult<()> {
         TOUCH_DELAY.delay_ms(200); TOUCH_DELAY.delay_ms(200);    
     };
 
-    //  Initialise the touch event with the callback function
-    unsafe { TOUCH_EVENT.ev_cb = Some( touch_event_callback ) };
-
-    //  Configure the touch controller interrupt (active when low) to trigger a touch event
-    let rc = unsafe { hal::hal_gpio_irq_init(
-        TOUCH_INTERRUPT_PIN,              //  GPIO pin to be configured
-        Some( touch_interrupt_handler ),  //  Call `touch_interrupt_handler()` upon detecting interrupt
-        core::ptr::null_mut(),            //  No arguments for `touch_interrupt_handler()`
-        hal::hal_gpio_irq_trigger_HAL_GPIO_TRIG_FALLING,  //  Trigger when interrupt goes from high to low
-        hal::hal_gpio_pull_HAL_GPIO_PULL_UP               //  Pull up the GPIO pin
-    ) };
-    assert_eq!(rc, 0, "IRQ init fail");
+    //  Call `touch_event_callback()` in the Default Event Queue when the touch controller interrupt (active when
+    //  low) goes from high to low. TODO: Use dedicated Event Queue for higher priority processing.
+    let mut interrupt = pinetime::touch_interrupt(touch_event_callback) ? ;
 
     //  Start monitoring for touch controller interrupts
-    unsafe { hal::hal_gpio_irq_enable(TOUCH_INTERRUPT_PIN) };
+    interrupt.enable();
+
+    //  Put the touch controller to sleep while the display is off.
+    manager::register(&TOUCH_HOOKS) ? ;
     Ok(())
 }
 
-/// Interrupt handler for the touch controller, triggered when a touch is detected
-extern "C" fn touch_interrupt_handler(arg: *mut core::ffi::c_void) {
-    //  We forward a touch event to the Default Event Queue for deferred processing.  Don't do any processing here.
-    unsafe { TOUCH_EVENT.ev_arg = arg };
-    //  Fetch the Default Event Queue. TODO: Use dedicated Event Queue for higher priority processing.
-    let queue = os::eventq_dflt_get()
-        .expect("GET fail");
-    unsafe { os::os_eventq_put(queue, &mut TOUCH_EVENT) };  //  Trigger the callback function `touch_event_callback()`
-    //console::print("touch\n"); ////
+/// Put the touch controller into deep sleep when the display is switched off, and wake it when the watch is active
+fn enter_touch(state: SystemState) -> MynewtResult<()> {
+    let asleep = state != SystemState::Active;
+    if asleep == unsafe { TOUCH_ASLEEP } { return Ok(()); }
+    if asleep { sleep() } else { wake() }
 }
 
-/// Callback for the touch event that is triggered when a touch is detected
-extern "C" fn touch_event_callback(_event: *mut os_event) {
-    //  console::printhex(unsafe { os::os_time_get() } as u8); console::print(" touch\n");
+/// Nothing to do when leaving a state, the touch controller is switched when entering the next state
+fn exit_touch(_state: SystemState) -> MynewtResult<()> {
+    Ok(())
+}
+
+/// Put the touch controller into deep sleep, which cuts its current from about 100 microamps to a few. In deep sleep
+/// the touch controller doesn't detect touches or answer on the I2C bus, so it's reset first to be sure that it's
+/// listening, and it's woken by `wake()`.
+fn sleep() -> MynewtResult<()> {
+    reset() ? ;
+    I2cBus::new(pinetime::TOUCH_I2C_NUM)
+        .write(pinetime::TOUCH_I2C_ADDRESS, &[CST816S_SLEEP_REGISTER, CST816S_SLEEP_COMMAND]) ? ;
+    unsafe { TOUCH_ASLEEP = true };
+    Ok(())
+}
+
+/// Wake the touch controller from deep sleep by resetting it. The touch in progress is discarded.
+fn wake() -> MynewtResult<()> {
+    reset() ? ;
+    unsafe {
+        TOUCH_ASLEEP = false;
+        TOUCH_START = None;
+    }
+    Ok(())
+}
+
+/// Reset the touch controller by switching the Reset Pin low then high, and wait until it's ready
+fn reset() -> MynewtResult<()> {
+    unsafe {
+        TOUCH_RESET.set_low() ? ;
+        TOUCH_DELAY.delay_ms(RESET_LOW_MS);
+        TOUCH_RESET.set_high() ? ;
+        TOUCH_DELAY.delay_ms(RESET_WAIT_MS);
+    }
+    Ok(())
+}
+
+/// Callback for the touch event that is triggered when a touch is detected. Pending touch interrupts are merged
+/// into the latest, since the touch controller only keeps the latest touch data.
+fn touch_event_callback(event: PinEvent) {
+    let touched_at = event.at;
     unsafe { 
         //  Fetch the touch data from the touch controller
         read_touchdata(&mut TOUCH_DATA)
@@ -90,8 +139,19 @@ extern "C" fn touch_event_callback(_event: *mut os_event) {
             let TouchInfo{ x, y, action, .. } = TOUCH_DATA.touches[i];
             //  Skip invalid responses (see note below)
             if x == 0 && y == 0 { continue; }
+            //  Decode the gesture when the finger is lifted
+            if action == ACTION_UP {
+                if let Some(gesture) = decode_gesture(TOUCH_DATA.gesture_id, x, y, touched_at) {
+                    post_touch_event(TouchEvent::Gesture { gesture, x, y });
+                }
+                continue;
+            }
             //  Handle only touch down and contact actions, not touch up (see note below)
-            if action != 0 && action != 2 { continue; }
+            if action != ACTION_DOWN && action != ACTION_CONTACT { continue; }
+            if action == ACTION_DOWN || TOUCH_START.is_none() { TOUCH_START = Some((x, y, touched_at)); }
+            //  Keep the display on while it's being touched. Touches on the blank screen don't wake the watch.
+            if power::is_awake() { power::manager::activity(); }
+            post_touch_event(TouchEvent::Touch { x, y });
             //  Handle the touch data in the UI        
             super::handle_touch(x, y);
 
@@ -128,6 +188,85 @@ extern "C" fn touch_event_callback(_event: *mut os_event) {
     } */
 }
 
+/// Touch event delivered to the UI
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TouchEvent {
+    /// Finger is touching the screen at `(x, y)`
+    Touch { x: u16, y: u16 },
+    /// Gesture completed when the finger was lifted at `(x, y)`
+    Gesture { gesture: Gesture, x: u16, y: u16 },
+}
+
+/// Gesture decoded from a touch, from touch down to touch up
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    /// Short touch without moving
+    Tap,
+    /// Finger moved up
+    SwipeUp,
+    /// Finger moved down
+    SwipeDown,
+    /// Finger moved left
+    SwipeLeft,
+    /// Finger moved right
+    SwipeRight,
+    /// Touch held without moving for `LONG_PRESS_MS`
+    LongPress,
+}
+
+/// Touch events for the UI. Call `TOUCH_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static TOUCH_EVENTS: EventQueue<TouchEvent> = EventQueue::new();
+
+/// Post the touch event to the UI. Drop the event if the UI is not receiving events.
+fn post_touch_event(event: TouchEvent) {
+    TOUCH_EVENTS.post(event).ok();
+}
+
+/// Min distance in pixels that the finger must move for a swipe
+const SWIPE_MIN_DISTANCE: u16 = 40;
+
+/// Min duration of a touch for a long press, in milliseconds
+const LONG_PRESS_MS: u64 = 600;
+
+/// Touch action: Finger touched the screen
+const ACTION_DOWN: u8 = 0;
+/// Touch action: Finger was lifted
+const ACTION_UP: u8 = 1;
+/// Touch action: Finger is still touching the screen
+const ACTION_CONTACT: u8 = 2;
+
+/// Position and time of the touch down, for decoding the gesture
+static mut TOUCH_START: Option<(u16, u16, Instant)> = None;
+
+/// Return the gesture for the touch that was lifted at `(x, y)` at time `now`. Use the gesture reported by the
+/// CST816S in register 0x01 if any, else decode the gesture from the touch down position and time.
+fn decode_gesture(gesture_id: u8, x: u16, y: u16, now: Instant) -> Option<Gesture> {
+    let start = unsafe { TOUCH_START.take() };
+    match gesture_id {
+        GESTURE_SLIDE_DOWN  => return Some(Gesture::SwipeDown),
+        GESTURE_SLIDE_UP    => return Some(Gesture::SwipeUp),
+        GESTURE_SLIDE_LEFT  => return Some(Gesture::SwipeLeft),
+        GESTURE_SLIDE_RIGHT => return Some(Gesture::SwipeRight),
+        GESTURE_LONG_PRESS  => return Some(Gesture::LongPress),
+        GESTURE_SINGLE_CLICK | GESTURE_DOUBLE_CLICK => return Some(Gesture::Tap),
+        _ => {}  //  No gesture reported, decode ourselves
+    }
+    let (x0, y0, start_time) = start ? ;
+    let dx = x as i32 - x0 as i32;
+    let dy = y as i32 - y0 as i32;
+    let min = SWIPE_MIN_DISTANCE as i32;
+    if dx.abs() >= min || dy.abs() >= min {
+        //  Swipe in the direction that moved the most. Y increases downwards.
+        return Some(
+            if dx.abs() > dy.abs() { if dx > 0 { Gesture::SwipeRight } else { Gesture::SwipeLeft } }
+            else if dy > 0 { Gesture::SwipeDown } else { Gesture::SwipeUp }
+        );
+    }
+    let held = now.checked_duration_since(start_time).unwrap_or_default();
+    if held.as_millis() as u64 >= LONG_PRESS_MS { Some(Gesture::LongPress) }
+    else { Some(Gesture::Tap) }
+}
+
 /// Touch data will be populated here
 static mut TOUCH_DATA: TouchEventInfo = fill_zero!(TouchEventInfo);
 
@@ -135,12 +274,13 @@ static mut TOUCH_DATA: TouchEventInfo = fill_zero!(TouchEventInfo);
 /// Ported from https://github.com/lupyuen/hynitron_i2c_cst0xxse/blob/master/cst0xx_core.c#L407-L466
 fn read_touchdata(data: &mut TouchEventInfo) -> MynewtResult<()> {
     read_register_range(           //  Read the range of I2C registers...
-        TOUCH_CONTROLLER_ADDRESS,  //  From the touch controller
+        pinetime::TOUCH_I2C_ADDRESS,  //  From the touch controller
         0,                         //  Starting from register 0
         POINT_READ_BUF as u8,      //  Number of registers to read
         unsafe { &mut BUF }        //  Save the read data into `buf`
     ).expect("read touchdata fail");
     *data = fill_zero!(TouchEventInfo);
+    data.gesture_id = unsafe { BUF[CST816S_GESTURE_ID] };
     data.point_num = unsafe { BUF[FT_TOUCH_POINT_NUM] & 0x0F };
     data.count     = 0;
 
@@ -183,9 +323,6 @@ fn read_touchdata(data: &mut TouchEventInfo) -> MynewtResult<()> {
 /// Buffer for raw touch data
 static mut BUF: [u8; POINT_READ_BUF] = [0; POINT_READ_BUF];
 
-/// Touch Controller I2C Address: https://github.com/lupyuen/hynitron_i2c_cst0xxse
-const TOUCH_CONTROLLER_ADDRESS: u8 = 0x15;
-
 /// Touch Event Info for multiple touches. Based on https://github.com/lupyuen/hynitron_i2c_cst0xxse/blob/master/cst0xx_core.h#L104-L115
 struct TouchEventInfo {
     /// Array of touch points
@@ -193,6 +330,8 @@ struct TouchEventInfo {
     /// How many touch points
     count:     u8,
     point_num: u8,
+    /// Gesture reported by the CST816S, e.g. `GESTURE_SLIDE_UP`
+    gesture_id: u8,
 }
 
 /// Touch Info for a single touch. Based on https://github.com/lupyuen/hynitron_i2c_cst0xxse/blob/master/cst0xx_core.h#L104-L115
@@ -233,46 +372,34 @@ const HYN_TOUCH_XY_POS: usize    = 7;
 const HYN_TOUCH_MISC: usize      = 8;
 const POINT_READ_BUF: usize      = 3 + ( HYN_TOUCH_STEP * HYN_MAX_POINTS );
 
-/// Event that will be forwarded to the Event Queue when a touch interrupt is triggered
-static mut TOUCH_EVENT: os_event = fill_zero!(os_event);  //  Init all fields to 0 or NULL
+//  CST816S gesture register and gesture IDs. Based on https://github.com/lupyuen/hynitron_i2c_cst0xxse/blob/master/cst0xx_core.c
+
+/// Register for the gesture ID
+const CST816S_GESTURE_ID: usize   = 1;
+const GESTURE_SLIDE_DOWN: u8      = 0x01;
+const GESTURE_SLIDE_UP: u8        = 0x02;
+const GESTURE_SLIDE_LEFT: u8      = 0x03;
+const GESTURE_SLIDE_RIGHT: u8     = 0x04;
+const GESTURE_SINGLE_CLICK: u8    = 0x05;
+const GESTURE_DOUBLE_CLICK: u8    = 0x0B;
+const GESTURE_LONG_PRESS: u8      = 0x0C;
 
 /// Read a range of I2C registers from the I2C address `addr` (7-bit address), starting at `start_register` for count `num_registers`. Save into `buffer`.
 fn read_register_range(addr: u8, start_register: u8, num_registers: u8, buffer: &mut[u8]) -> MynewtResult<()> {
     assert!(buffer.len() >= num_registers as usize, "i2c buf");  //  Buffer too small
     assert!(start_register + num_registers < 128, "i2c addr");   //  Not 7-bit address
-    //  Step 1: Prepare to read I2C Device Registers:
-    //    System sends Clock Signal on SCL to sync Microcontroller with I2C Device
-    //  Step 2: Transmit the I2C Address and the starting Register Number:
-    //    Send the Start Condition (High to Low SDA Transition)...
-    //    Followed by I2C Address (7 bits)...
-    //    Followed by Write Mode (1 bit, value 0)...
-    //    Followed by starting Register Number (8 bits)
-    unsafe { 
-        I2C_BUFFER[0] = start_register;  //  I2C Packet buffer contains starting Register Number (1 byte)
-        I2C_DATA.address = addr;         //  I2C Packet address (7 bits)
-        I2C_DATA.len = I2C_BUFFER.len() as u16;     //  I2C Packet data size is 1 byte
-        I2C_DATA.buffer = I2C_BUFFER.as_mut_ptr();  //  I2C Packet data points to packet buffer
-    };
-    let _rc1 = unsafe { hal::hal_i2c_master_write(1, &mut I2C_DATA, 1000, 0) };  //  No stop yet, must continue even if we hit an error
-    //  Step 3: Prepare to receive the stream of I2C Device Register values...
-    //    Send the Start Condition (High to Low SDA Transition)...
-    //    Followed by I2C Address (7 bits)...
-    //    Followed by Read Mode (1 bit, value 1)
-    unsafe { 
-        I2C_BUFFER[0] = 0x00;     //  I2C Packet buffer should be empty (provided by caller)
-        I2C_DATA.address = addr;  //  I2C Packet address (7 bits)
-        I2C_DATA.len = num_registers as u16;    //  I2C Packet data size is number of Registers to read
-        I2C_DATA.buffer = buffer.as_mut_ptr();  //  I2C Packet data points to packet buffer
-    };
-    //  Step 4: Receive the requested number of Register values from I2C Device (1 byte per register)
-    //  Step 5: Send the Stop Condition (Low to High SDA Transition)
-    let rc2 = unsafe { hal::hal_i2c_master_read(1, &mut I2C_DATA, 1000, 1) };
-    if rc2 == hal::HAL_I2C_ERR_ADDR_NACK as i32 {
-        ////assert!(false, "i2c fail");  //  I2C read failed
-        console::print("i2c fail\n"); ////
-        return Ok(());               //  TODO: Return an error
+    //  Transmit the starting Register Number, then receive the requested number of Register values after
+    //  a repeated start (1 byte per register), then send the Stop Condition.
+    let result = I2cBus::new(pinetime::TOUCH_I2C_NUM)
+        .write_read(addr, &[start_register], &mut buffer[..num_registers as usize]);
+    match result {
+        //  The touch controller doesn't answer while it's asleep.
+        Err(MynewtError::HAL_I2C_ERR_ADDR_NACK) | Err(MynewtError::HAL_I2C_ERR_DATA_NACK) => {
+            console::print("i2c fail\n");
+            Ok(())
+        }
+        result => result,
     }
-    Ok(())
 }
 
 /// Read the I2C register for the specified I2C address (7-bit address)
@@ -0,0 +1,64 @@
+//!  Shell over the UART console, for debugging when no debugger or phone is connected. The lines entered on the
+//!  UART are split into arguments and executed by the Mynewt shell on the default event queue, like the Bluetooth LE
+//!  console in `ble_nus.c`. The UART is registered as the console sink `uart`, which receives the console output
+//!  unless another sink is selected in `console_sinks.rs`. Needs `UART_SHELL: 1` and `UART_0: 1` in `syscfg.yml`,
+//!  with the UART pins of the dev kit.
+
+use mynewt::{
+    result::*,
+    board::pinetime,
+    kernel::os,
+    sys::{ console, console_sink, uart_console::{ self, MAX_LINE } },
+};
+
+///  Baud rate of the UART
+const BAUD: u32 = 115_200;
+
+///  Max number of arguments in a line, including the command
+const MAX_ARGS: usize = 8;
+
+///  Start the UART console, register it as a console sink and execute the lines entered. Called by main() in
+///  `lib.rs` before `console_sinks::start()`.
+pub fn start() -> MynewtResult<()> {
+    uart_console::start(pinetime::UART_NUM, BAUD, os::eventq_dflt_get() ? , exec) ? ;
+    console_sink::register(&uart_console::UART_SINK) ? ;
+    uart_console::print("\r\nuart shell\r\n");
+    Ok(())
+}
+
+///  Split the line into arguments separated by spaces and execute the shell command. The output of the command is
+///  sent to the selected console sink.
+fn exec(line: &str) {
+    //  The shell needs null-terminated arguments, so copy the line and replace the spaces by nulls.
+    let mut buf = [0u8; MAX_LINE + 1];
+    buf[..line.len()].copy_from_slice(line.as_bytes());
+    let mut argv: [*const u8; MAX_ARGS + 1] = [core::ptr::null(); MAX_ARGS + 1];
+    let mut argc = 0;
+    let mut start = None;
+    for i in 0..=line.len() {
+        let space = buf[i] == b' ' || buf[i] == 0;
+        match (start, space) {
+            (None, false) => start = Some(i),
+            (Some(arg), true) => {
+                if argc == MAX_ARGS { console::print("uart: too many arguments\n"); console::flush(); return; }
+                buf[i] = 0;
+                argv[argc] = &buf[arg];
+                argc += 1;
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if argc == 0 { return; }
+    let rc = unsafe { shell_exec(argc as i32, argv.as_ptr()) };
+    if rc != 0 {
+        console::print("FAILED "); console::printint(rc); console::print("\n");
+    }
+    console::flush();
+}
+
+extern "C" {
+    ///  Execute the shell command with the null-terminated arguments `argv`, ending with null.
+    ///  C API: `int shell_exec(int argc, char **argv)`
+    fn shell_exec(argc: i32, argv: *const *const u8) -> i32;
+}
@@ -21,6 +21,7 @@ use druid::{
     env::Env,
 };
 use mynewt::sys::console;
+use crate::haptics::{ self, HapticEvent };
 
 /// The Application State consists of 1 value: `count` of type `u32` (32-bit unsigned int)
 #[derive(Clone, Data, Default)]
@@ -81,6 +82,8 @@ fn on_label_show(state: &State, _env: &Env) -> ArgValue {
 
 ///  Callback function that will be called when the button is tapped
 fn on_button_press(_ctx: &mut EventCtx<State>, state: &mut State, _env: &Env) {
+    //  Tap the wrist to confirm the press
+    haptics::play(HapticEvent::Button);
     //  We increment the counter
     state.count += 1  //  Changes the application state
 }
@@ -0,0 +1,77 @@
+//!  Firmware and build information, so that deployments can verify exactly what each watch runs: the semantic
+//!  version from `Cargo.toml`, the git commit and the build time embedded by `build.rs`, and the MCUBoot version,
+//!  hash and state of the images in both firmware slots. The information is shown by the shell command `version`,
+//!  published as the Software Revision String of the Bluetooth LE Device Information Service by `ble_main.c`, and
+//!  posted to the CoAP server after startup.
+
+use mynewt::{
+    result::*,
+    kernel::timer::Callout,
+    sys::{ console, shell::{ self, Args } },
+};
+use core::time::Duration;
+use crate::{ app_network, mcuboot };
+
+///  Semantic version of the firmware, e.g. `0.1.0`
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+///  Git commit of the sources, e.g. `1b84bea3`, with `-dirty` if the sources were changed
+pub const GIT_HASH: &str = env!("BUILD_GIT_HASH");
+
+///  Build time in UTC, e.g. `2020-05-01T12:34:56Z`
+pub const BUILD_TIME: &str = env!("BUILD_TIME");
+
+///  Null-terminated build string `<version>+<git hash> <build time>`, also for the C code
+static BUILD_STRING: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("BUILD_GIT_HASH"), " ",
+    env!("BUILD_TIME"), "\0");
+
+///  Delay after startup before posting the information, for the network to be ready
+const FIRST_SEND_DELAY: Duration = Duration::from_secs(30);
+
+///  Interval between posts while the post fails
+const RETRY_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+///  Timer that posts the information to the CoAP server
+static SEND_TIMER: Callout<fn()> = Callout::new(send_timer);
+
+///  Return the build string, e.g. `0.1.0+1b84bea3 2020-05-01T12:34:56Z`
+pub fn build_string() -> &'static str {
+    &BUILD_STRING[..BUILD_STRING.len() - 1]
+}
+
+///  Register the shell command `version` and start posting the firmware and build information to the CoAP server.
+///  Called by main() in `lib.rs`.
+pub fn start() -> MynewtResult<()> {
+    shell::register("version", "version [send]", shell_command) ? ;
+    SEND_TIMER.reset(FIRST_SEND_DELAY)
+}
+
+///  Show the build string and the images in both firmware slots on the console
+pub fn show() -> MynewtResult<()> {
+    console::print("firmware ");
+    console::print(build_string());
+    console::print("\n");
+    mcuboot::show_image_info()
+}
+
+///  Post the information, and try again after the period if the post failed. Called by the default event queue.
+fn send_timer() {
+    if let Err(err) = app_network::send_version() {
+        log::warn!("version post fail {:?}", err);
+        SEND_TIMER.reset(RETRY_PERIOD).expect("version timer fail");
+    }
+}
+
+///  Return the null-terminated build string for the Device Information Service. Called by `ble_main.c`.
+#[no_mangle]
+extern "C" fn version_build_string() -> *const u8 {
+    BUILD_STRING.as_ptr()
+}
+
+///  Shell command `version [send]`: Show the build string and the firmware slots, or post the firmware and build
+///  information to the CoAP server
+fn shell_command(args: &mut Args) -> MynewtResult<()> {
+    let send = args.take("send");
+    args.end() ? ;
+    if send { app_network::send_version() } else { show() }
+}
@@ -0,0 +1,124 @@
+//!  Wrist-raise detection with the BMA421 accelerometer. A timer samples the accelerometer at a low rate (10 Hz) and
+//!  tracks the tilt of the watch face. When the wrist is raised to look at the watch, the face turns up and stays up:
+//!  `WristEvent::WristRaised` is emitted and the power manager switches on the display. When the wrist drops, the
+//!  face turns away: `WristEvent::WristDropped` is emitted and the display is switched off again, unless the watch
+//!  is on the charger.
+
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    kernel::{
+        device::Device,
+        event::EventQueue,
+        timer::Callout,
+    },
+    sys::console,
+};
+use crate::{ pedometer, power, settings };
+
+///  Wrist gesture detected by the accelerometer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WristEvent {
+    ///  Wrist was raised to look at the watch
+    WristRaised,
+    ///  Wrist was dropped, the watch face is turned away
+    WristDropped,
+}
+
+///  Wrist events for the UI. Call `WRIST_EVENTS.receive()` in the UI task. Events are dropped when the queue is full.
+pub static WRIST_EVENTS: EventQueue<WristEvent> = EventQueue::new();
+
+///  Interval between accelerometer samples: 10 Hz
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+///  Min acceleration on the Z axis when the watch face is up, in raw units (1 g = 1024 at ±2g): about 35 degrees
+///  above vertical
+const RAISED_MIN_Z: i32 = 600;
+
+///  Max acceleration on the X axis when the watch face is up. The forearm is roughly level when looking at the watch.
+const RAISED_MAX_X: i32 = 600;
+
+///  Max acceleration on the Z axis when the watch face is turned away: about 15 degrees above vertical.
+///  Lower than `RAISED_MIN_Z`, so that small wobbles don't switch the display on and off.
+const DROPPED_MAX_Z: i32 = 250;
+
+///  Number of consecutive samples with the face up before the wrist is raised: 300 milliseconds
+const RAISE_SAMPLES: u32 = 3;
+
+///  Number of consecutive samples with the face away before the wrist is dropped: 1 second
+const DROP_SAMPLES: u32 = 10;
+
+///  Timer that samples the accelerometer
+static SAMPLE_TIMER: Callout<fn()> = Callout::new(sample_accel);
+
+///  Wrist-raise detector for the samples
+static mut DETECTOR: WristDetector = WristDetector::new();
+
+///  Start sampling the accelerometer for wrist raises. Returns `SYS_ENODEV` if the accelerometer is not enabled
+///  in `syscfg.yml`.
+pub fn start_wrist_detection() -> MynewtResult<()> {
+    console::print("Rust wrist raise\n");
+    //  Check the accelerometer before starting.
+    Device::open(&pedometer::ACCEL_DEVICE, Duration::from_secs(1)) ? ;
+    SAMPLE_TIMER.reset(SAMPLE_INTERVAL)
+}
+
+///  Called by the timer to sample the accelerometer and detect wrist raises
+fn sample_accel() {
+    //  Skip the sample if the accelerometer is busy, e.g. the I2C bus is used by the touch controller.
+    let xyz = Device::open(&pedometer::ACCEL_DEVICE, Duration::from_millis(10))
+        .and_then(|dev| pedometer::read_accel(&dev));
+    if let Ok((x, y, z)) = xyz {
+        let sample = [
+            settings::ACCEL_X_CALIBRATION.apply(x),
+            settings::ACCEL_Y_CALIBRATION.apply(y),
+            settings::ACCEL_Z_CALIBRATION.apply(z),
+        ];
+        if let Some(event) = unsafe { DETECTOR.update(&sample) } {
+            handle_wrist_event(event);
+        }
+    }
+    SAMPLE_TIMER.reset(SAMPLE_INTERVAL).expect("wrist fail");
+}
+
+///  Switch the display on or off and notify the UI
+fn handle_wrist_event(event: WristEvent) {
+    let result = match event {
+        WristEvent::WristRaised  => power::wake(power::WakeReason::WristRaise),
+        WristEvent::WristDropped => power::auto_sleep(),
+    };
+    if let Err(err) = result { log::warn!("wrist power fail {:?}", err); }
+    WRIST_EVENTS.post(event).ok();  //  Drop the event if the UI is not receiving events
+}
+
+///  Detects wrist raises from the tilt of the watch face, with hysteresis. Integer only, since floating-point
+///  is disabled.
+struct WristDetector {
+    ///  True if the wrist is raised
+    raised: bool,
+    ///  Number of consecutive samples that disagree with `raised`
+    pending: u32,
+}
+
+impl WristDetector {
+    ///  Create a detector for a dropped wrist
+    const fn new() -> Self {
+        WristDetector { raised: false, pending: 0 }
+    }
+
+    ///  Add the calibrated sample `[x, y, z]`. Return the wrist event that has been detected, if any.
+    fn update(&mut self, sample: &[i32; 3]) -> Option<WristEvent> {
+        let (x, z) = (sample[0], sample[2]);
+        //  Count the samples that would change the state, and start again if a sample doesn't.
+        let (changing, needed) =
+            if self.raised { (z <= DROPPED_MAX_Z, DROP_SAMPLES) }
+            else { (z >= RAISED_MIN_Z && x.abs() <= RAISED_MAX_X, RAISE_SAMPLES) };
+        if !changing { self.pending = 0; return None; }
+        self.pending += 1;
+        if self.pending < needed { return None; }
+
+        self.pending = 0;
+        self.raised = !self.raised;
+        Some(if self.raised { WristEvent::WristRaised } else { WristEvent::WristDropped })
+    }
+}
@@ -232,3 +232,41 @@ pub fn try_cbor(item: TokenStream) -> TokenStream {
     //  Return the expanded tokens back to the compiler.
     TokenStream::from(expanded)
 }
+
+/// Register a function `fn name() -> MynewtResult<()>` as an on-target test, run by `mynewt::sys::device_test`.
+/// The test is compiled only when the `device_test` feature of the calling crate is enabled, e.g.
+/// ```
+/// #[device_test]
+/// fn crc32_check() -> MynewtResult<()> { ... }
+/// ```
+/// expands to
+/// ```
+/// #[cfg(feature = "device_test")]
+/// fn crc32_check() -> MynewtResult<()> { ... }
+/// #[cfg(feature = "device_test")]
+/// #[used]
+/// #[link_section = ".device_tests"]
+/// static DEVICE_TEST_CRC32_CHECK: mynewt::sys::device_test::DeviceTest = mynewt::sys::device_test::DeviceTest {
+///     name: concat!(module_path!(), "::", "crc32_check"), func: crc32_check };
+/// ```
+#[proc_macro_attribute]
+pub fn device_test(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    //  Parse the macro input as a function definition.
+    let input = parse_macro_input!(item as syn::ItemFn);
+    let func = &input.sig.ident;
+    let name = func.to_string();
+    let test = syn::Ident::new(&format!("DEVICE_TEST_{}", name.to_uppercase()), func.span());
+    let expanded = quote! {
+        #[cfg(feature = "device_test")]
+        #input
+        #[cfg(feature = "device_test")]
+        #[used]
+        #[link_section = ".device_tests"]
+        static #test: mynewt::sys::device_test::DeviceTest = mynewt::sys::device_test::DeviceTest {
+            name: concat!(module_path!(), "::", #name),
+            func: #func,
+        };
+    };
+    //  Return the expanded tokens back to the compiler.
+    TokenStream::from(expanded)
+}
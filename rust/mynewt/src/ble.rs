@@ -0,0 +1,92 @@
+//! Safe wrappers for the NimBLE host API, so that Bluetooth LE features don't need to declare their own `extern`
+//! functions. Covers the host callbacks, advertising and connections (GAP), GATT services with notifications, and
+//! L2CAP channels.
+//! The NimBLE structs and unions that are awkward to access from Rust are handled by `libs/mynewt_rust/src/ble_helper.c`.
+//! Requires `BLUETOOTH_LE: 1` in `syscfg.yml`.
+//! ```
+//! ble::host::on_sync(start_advertising);
+//! fn start_advertising() {
+//!     ble::gap::advertise(&strn!("pinetime"), &[0x180D], None, handle_gap_event).expect("adv fail");
+//! }
+//! ```
+
+use crate::result::*;
+
+/// Host configuration and callbacks
+pub mod host;  // Export `ble/host.rs` as Rust module `mynewt::ble::host`
+
+/// Advertising and connections
+pub mod gap;   // Export `ble/gap.rs` as Rust module `mynewt::ble::gap`
+
+/// Configurable advertising
+pub mod adv;   // Export `ble/adv.rs` as Rust module `mynewt::ble::adv`
+
+/// Connection parameters, PHY and MTU
+pub mod conn;  // Export `ble/conn.rs` as Rust module `mynewt::ble::conn`
+
+/// GATT services, characteristics and notifications
+pub mod gatt;  // Export `ble/gatt.rs` as Rust module `mynewt::ble::gatt`
+
+/// Per-connection state of the connected centrals
+pub mod conns;  // Export `ble/conns.rs` as Rust module `mynewt::ble::conns`
+
+/// Streaming of notifications with flow control
+pub mod notify;  // Export `ble/notify.rs` as Rust module `mynewt::ble::notify`
+
+/// Connection lifecycle callbacks on the default event queue
+pub mod lifecycle;  // Export `ble/lifecycle.rs` as Rust module `mynewt::ble::lifecycle`
+
+/// L2CAP connection-oriented channels for bulk transfers
+pub mod l2cap;  // Export `ble/l2cap.rs` as Rust module `mynewt::ble::l2cap`
+
+/// Builder for GATT services with characteristics backed by Rust closures
+pub mod builder;  // Export `ble/builder.rs` as Rust module `mynewt::ble::builder`
+
+/// Result of a NimBLE host function
+pub type BleResult<T> = ::core::result::Result<T, BleError>;
+
+/// NimBLE host error code `BLE_HS_E*`, or an ATT / HCI error code with its NimBLE base added.
+/// Converts to `MynewtError`, so that `?` works in functions that return `MynewtResult`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BleError(pub i32);
+
+impl BleError {
+    pub const EAGAIN:   BleError = BleError(1);
+    pub const EALREADY: BleError = BleError(2);
+    pub const EINVAL:   BleError = BleError(3);
+    pub const EMSGSIZE: BleError = BleError(4);
+    pub const ENOENT:   BleError = BleError(5);
+    pub const ENOMEM:   BleError = BleError(6);
+    pub const ENOTCONN: BleError = BleError(7);
+    pub const ENOTSUP:  BleError = BleError(8);
+    pub const ETIMEOUT: BleError = BleError(13);
+    pub const EBUSY:    BleError = BleError(15);
+    pub const EAUTHEN:  BleError = BleError(16);
+    pub const EENCRYPT: BleError = BleError(18);
+    pub const ENOTSYNCED: BleError = BleError(22);
+}
+
+/// Convert the NimBLE error to the nearest `MynewtError`
+impl From<BleError> for MynewtError {
+    fn from(err: BleError) -> Self {
+        match err {
+            BleError::EAGAIN | BleError::ENOTSYNCED => MynewtError::SYS_EAGAIN,
+            BleError::EALREADY => MynewtError::SYS_EALREADY,
+            BleError::EINVAL   => MynewtError::SYS_EINVAL,
+            BleError::EMSGSIZE => MynewtError::SYS_ERANGE,
+            BleError::ENOENT   => MynewtError::SYS_ENOENT,
+            BleError::ENOMEM   => MynewtError::SYS_ENOMEM,
+            BleError::ENOTSUP  => MynewtError::SYS_ENOTSUP,
+            BleError::ETIMEOUT => MynewtError::SYS_ETIMEOUT,
+            BleError::EBUSY    => MynewtError::SYS_EBUSY,
+            BleError::EAUTHEN | BleError::EENCRYPT => MynewtError::SYS_EACCES,
+            BleError::ENOTCONN => MynewtError::SYS_EIO,
+            _                  => MynewtError::SYS_EUNKNOWN,
+        }
+    }
+}
+
+/// Return `Ok()` if the NimBLE return code `rc` is 0, else the error
+fn check_ble(rc: i32) -> BleResult<()> {
+    if rc == 0 { Ok(()) } else { Err(BleError(rc)) }
+}
@@ -0,0 +1,166 @@
+//! Configurable Bluetooth LE advertising. `start()` advertises with an `AdvConfig`: device name, service UUIDs,
+//! manufacturer-specific data, transmit power, advertising intervals and connectable or non-connectable mode.
+//! Advertising may be restarted automatically when the connection is terminated, since NimBLE stops advertising
+//! when a central connects.
+//! ```
+//! static NAME: Strn = strn!("pinetime");
+//! adv::start(AdvConfig {
+//!     uuids16:  &[0x180D],
+//!     mfg_data: &[0xff, 0xff, 0x01],  //  Company identifier 0xFFFF for testing, then the data
+//!     interval: Some((Duration::from_millis(100), Duration::from_millis(150))),
+//!     ..AdvConfig::new(&NAME)
+//! }) ? ;
+//! ```
+
+use core::time::Duration;
+use crate::{
+    ble::{
+        check_ble,
+        gap::{ self, GapEvent, MAX_ADV_UUIDS16 },
+        BleError, BleResult,
+    },
+    Strn,
+};
+
+/// Shortest advertising interval allowed by the Bluetooth Core Specification
+pub const MIN_INTERVAL: Duration = Duration::from_micros(20_000);
+
+/// Longest advertising interval allowed by the Bluetooth Core Specification
+pub const MAX_INTERVAL: Duration = Duration::from_micros(10_240_000);
+
+/// Advertising configuration. Create with `AdvConfig::new()` and override the fields as needed.
+#[derive(Clone, Copy)]
+pub struct AdvConfig {
+    /// Device name
+    pub name: &'static Strn,
+    /// 16-bit service UUIDs, up to `MAX_ADV_UUIDS16`
+    pub uuids16: &'static [u16],
+    /// Manufacturer-specific data, starting with the 2-byte company identifier in little endian. Empty if none.
+    pub mfg_data: &'static [u8],
+    /// Transmit power in dBm, or `None` for the controller default
+    pub tx_power: Option<i8>,
+    /// Min and max advertising intervals, or `None` for the NimBLE defaults
+    pub interval: Option<(Duration, Duration)>,
+    /// True if centrals may connect, false for a beacon
+    pub connectable: bool,
+    /// How long to advertise, or `None` to advertise until stopped
+    pub duration: Option<Duration>,
+    /// True if advertising should restart when a connection is terminated
+    pub restart_on_disconnect: bool,
+    /// Function called with the GAP events of the advertising and of the connections
+    pub on_event: Option<fn(&GapEvent)>,
+}
+
+impl AdvConfig {
+    /// Return the default configuration for the device name: connectable, advertising until stopped and
+    /// restarting after each disconnect, at the default power and intervals
+    pub const fn new(name: &'static Strn) -> Self {
+        AdvConfig {
+            name,
+            uuids16:  &[],
+            mfg_data: &[],
+            tx_power: None,
+            interval: None,
+            connectable: true,
+            duration: None,
+            restart_on_disconnect: true,
+            on_event: None,
+        }
+    }
+}
+
+/// Configuration of the current advertising, `None` if stopped with `stop()`
+static mut ADV_CONFIG: Option<AdvConfig> = None;
+
+/// Start advertising with the configuration. The host must be synced. Returns `EINVAL` if there are more than
+/// `MAX_ADV_UUIDS16` UUIDs or the intervals are out of range, `EMSGSIZE` if the fields don't fit in the
+/// advertising data.
+pub fn start(config: AdvConfig) -> BleResult<()> {
+    config.name.validate();
+    if config.uuids16.len() > MAX_ADV_UUIDS16 || config.mfg_data.len() > u8::max_value() as usize {
+        return Err(BleError::EINVAL);
+    }
+    let (itvl_min, itvl_max) = match config.interval {
+        Some((min, max)) => (interval_to_units(min) ? , interval_to_units(max) ? ),
+        None => (0, 0),
+    };
+    if itvl_min > itvl_max { return Err(BleError::EINVAL); }
+    let cfg = ble_helper_adv_cfg {
+        name:                config.name.as_cstr(),
+        uuids16:             config.uuids16.as_ptr(),
+        num_uuids16:         config.uuids16.len() as u8,
+        mfg_data:            if config.mfg_data.is_empty() { core::ptr::null() } else { config.mfg_data.as_ptr() },
+        mfg_data_len:        config.mfg_data.len() as u8,
+        tx_power_is_present: config.tx_power.is_some() as u8,
+        tx_power:            config.tx_power.unwrap_or(0),
+        itvl_min,
+        itvl_max,
+        connectable:         config.connectable as u8,
+        duration_ms:         gap::duration_to_ms(config.duration) ? ,
+    };
+    unsafe { ADV_CONFIG = Some(config) };
+    gap::set_event_func(handle_event);
+    check_ble(unsafe { ble_helper_advertise_cfg(&cfg, gap::event_trampoline, core::ptr::null_mut()) })
+}
+
+/// Stop advertising, and don't restart after the next disconnect. Returns `EALREADY` if not advertising.
+pub fn stop() -> BleResult<()> {
+    unsafe { ADV_CONFIG = None };
+    check_ble(unsafe { ble_gap_adv_stop() })
+}
+
+/// Return the transmit power on the advertising channels in dBm, as set by `AdvConfig::tx_power`. The advertising
+/// includes this power, so that scanners may compute the path loss from the signal strength that they receive.
+pub fn tx_power() -> BleResult<i8> {
+    let mut tx_power: i8 = 0;
+    check_ble(unsafe { ble_helper_adv_tx_power(&mut tx_power) }) ? ;
+    Ok(tx_power)
+}
+
+/// Handle the GAP event: Restart advertising after a disconnect if configured, then pass the event to the
+/// configured function
+fn handle_event(event: &GapEvent) {
+    let config = match unsafe { ADV_CONFIG } { Some(config) => config, None => return };
+    if let GapEvent::Disconnect { .. } = event {
+        if config.restart_on_disconnect && !gap::is_advertising() {
+            //  Ignore the error, the central may connect again after the next restart.
+            start(config).ok();
+        }
+    }
+    if let Some(func) = config.on_event { func(event); }
+}
+
+/// Convert the advertising interval to units of 0.625 ms. Returns `EINVAL` if out of range.
+fn interval_to_units(interval: Duration) -> BleResult<u16> {
+    if interval < MIN_INTERVAL || interval > MAX_INTERVAL { return Err(BleError::EINVAL); }
+    Ok((interval.as_micros() / 625) as u16)
+}
+
+/// Advertising configuration for `ble_helper.c`. Must sync with `struct ble_helper_adv_cfg` in `ble_helper.h`.
+#[repr(C)]
+struct ble_helper_adv_cfg {
+    name:                *const u8,
+    uuids16:             *const u16,
+    num_uuids16:         u8,
+    mfg_data:            *const u8,
+    mfg_data_len:        u8,
+    tx_power_is_present: u8,
+    tx_power:            i8,
+    itvl_min:            u16,
+    itvl_max:            u16,
+    connectable:         u8,
+    duration_ms:         i32,
+}
+
+extern "C" {
+    /// Start advertising with the configuration.
+    /// C API: `int ble_helper_advertise_cfg(const struct ble_helper_adv_cfg *cfg, ble_helper_gap_fn *cb, void *arg)`
+    fn ble_helper_advertise_cfg(cfg: *const ble_helper_adv_cfg,
+        cb: extern "C" fn(*const gap::ble_helper_gap_info, *mut ::cty::c_void) -> i32, arg: *mut ::cty::c_void) -> i32;
+    /// Read the transmit power on the advertising channels.
+    /// C API: `int ble_helper_adv_tx_power(int8_t *tx_power)`
+    fn ble_helper_adv_tx_power(tx_power: *mut i8) -> i32;
+    /// Stop advertising.
+    /// C API: `int ble_gap_adv_stop(void)`
+    fn ble_gap_adv_stop() -> i32;
+}
@@ -0,0 +1,306 @@
+//! Builder for GATT services whose characteristics are backed by Rust closures. The builder sets the characteristic
+//! flags for the closures, and copies the values from and to the NimBLE mbufs, so the closures only see byte slices.
+//! Services are registered one at a time before the host starts, e.g. in an `init_hook!()`.
+//! ```
+//! let svc = ServiceBuilder::new(0x180D)
+//!     .characteristic(CharacteristicBuilder::new(0x2A37)  //  Heart Rate Measurement: Notify only
+//!         .on_notify(|buf| { buf[0] = 0x06; buf[1] = heart_rate(); Ok(2) }))
+//!     .characteristic(CharacteristicBuilder::new(0x2A39)  //  Heart Rate Control Point: Write only
+//!         .on_write(|data| reset_energy(data)))
+//!     .register() ? ;
+//! svc.characteristic(0x2A37).expect("no hrm").updated();
+//! ```
+//! The closures are moved into a static arena of `ARENA_SIZE` bytes, since there is no heap. Closures that
+//! capture nothing take no space.
+
+use core::mem;
+use crate::ble::{
+    gap::ConnHandle,
+    gatt::{
+        self, ble_gatt_access_ctxt, ble_gatt_chr_def, ble_gatt_svc_def, to_att_rc, Access, AccessOp, AccessResult,
+        AttError, Uuid16,
+    },
+    BleError, BleResult,
+};
+
+/// Max number of services that may be registered with the builder
+pub const MAX_SERVICES: usize = 4;
+
+/// Max number of characteristics per service. Must match `MaxCharacteristics`.
+pub const MAX_CHARACTERISTICS: usize = 6;
+type MaxCharacteristics = heapless::consts::U6;
+
+/// Max length of a characteristic value that is read or written
+pub const MAX_VALUE_LEN: usize = 64;
+
+/// Size in bytes of the arena that stores the closures
+pub const ARENA_SIZE: usize = 256;
+
+/// Closure that fills the buffer with the characteristic value and returns the length
+type ReadFn = dyn Fn(&mut [u8]) -> Result<usize, AttError> + Sync;
+
+/// Closure that receives the value written to the characteristic
+type WriteFn = dyn Fn(&[u8]) -> AccessResult + Sync;
+
+/// Builder for a characteristic with a 16-bit UUID
+#[derive(Clone, Copy)]
+pub struct CharacteristicBuilder {
+    /// UUID of the characteristic
+    uuid: u16,
+    /// Characteristic flags `gatt::F_*`
+    flags: u16,
+    /// True if the peer must encrypt the link to read or write
+    encrypted: bool,
+    /// Closure that returns the value for reads and notifications
+    on_read: Option<&'static ReadFn>,
+    /// Closure that receives the written value
+    on_write: Option<&'static WriteFn>,
+}
+
+impl CharacteristicBuilder {
+    /// Create a characteristic with the 16-bit UUID `uuid`. Add the closures with `on_read()`, `on_write()` or
+    /// `on_notify()`.
+    pub fn new(uuid: u16) -> Self {
+        CharacteristicBuilder { uuid, flags: 0, encrypted: false, on_read: None, on_write: None }
+    }
+
+    /// Allow peers to read the value returned by `func`. `func` fills the buffer and returns the length.
+    pub fn on_read<F>(mut self, func: F) -> Self
+    where F: Fn(&mut [u8]) -> Result<usize, AttError> + Sync + 'static {
+        self.on_read = Some(store(func));
+        self.flags |= gatt::F_READ;
+        self
+    }
+
+    /// Allow peers to subscribe to notifications of the value returned by `func`, without reading it.
+    /// `func` fills the buffer and returns the length.
+    pub fn on_notify<F>(mut self, func: F) -> Self
+    where F: Fn(&mut [u8]) -> Result<usize, AttError> + Sync + 'static {
+        self.on_read = Some(store(func));
+        self.flags |= gatt::F_NOTIFY;
+        self
+    }
+
+    /// Allow peers to write the value, which is passed to `func`
+    pub fn on_write<F>(mut self, func: F) -> Self
+    where F: Fn(&[u8]) -> AccessResult + Sync + 'static {
+        self.on_write = Some(store(func));
+        self.flags |= gatt::F_WRITE;
+        self
+    }
+
+    /// Allow peers to subscribe to notifications of the value returned by `on_read()`
+    pub fn notify(mut self) -> Self {
+        self.flags |= gatt::F_NOTIFY;
+        self
+    }
+
+    /// Allow peers to write the value without a response, in addition to `on_write()`
+    pub fn write_no_response(mut self) -> Self {
+        self.flags |= gatt::F_WRITE_NO_RSP;
+        self
+    }
+
+    /// Require an encrypted link to read or write the value
+    pub fn encrypted(mut self) -> Self {
+        self.encrypted = true;
+        self
+    }
+
+    /// Return the characteristic flags for NimBLE
+    fn flags(&self) -> u16 {
+        let mut flags = self.flags;
+        if self.encrypted && flags & gatt::F_READ != 0 { flags |= gatt::F_READ_ENC; }
+        if self.encrypted && flags & gatt::F_WRITE != 0 { flags |= gatt::F_WRITE_ENC; }
+        flags
+    }
+}
+
+/// Builder for a primary service with a 16-bit UUID
+pub struct ServiceBuilder {
+    /// UUID of the service
+    uuid: u16,
+    /// Characteristics of the service
+    characteristics: heapless::Vec<CharacteristicBuilder, MaxCharacteristics>,
+    /// True if more than `MAX_CHARACTERISTICS` characteristics were added
+    overflow: bool,
+}
+
+impl ServiceBuilder {
+    /// Create a primary service with the 16-bit UUID `uuid`
+    pub fn new(uuid: u16) -> Self {
+        ServiceBuilder { uuid, characteristics: heapless::Vec::new(), overflow: false }
+    }
+
+    /// Add the characteristic to the service
+    pub fn characteristic(mut self, chr: CharacteristicBuilder) -> Self {
+        if self.characteristics.push(chr).is_err() { self.overflow = true; }
+        self
+    }
+
+    /// Register the service with NimBLE. Must be called before the host starts. Returns `ENOMEM` if there are more
+    /// than `MAX_SERVICES` services or `MAX_CHARACTERISTICS` characteristics.
+    pub fn register(self) -> BleResult<Service> {
+        let index = unsafe { NUM_SERVICES };
+        if index >= MAX_SERVICES || self.overflow { return Err(BleError::ENOMEM); }
+        unsafe {
+            //  NimBLE keeps pointers to the UUIDs, definitions and value handles, so they are stored in statics.
+            SERVICE_UUIDS[index] = Uuid16::new(self.uuid);
+            for (i, chr) in self.characteristics.iter().enumerate() {
+                SLOTS[index][i] = Slot { uuid: chr.uuid, on_read: chr.on_read, on_write: chr.on_write, val_handle: 0 };
+                CHR_UUIDS[index][i] = Uuid16::new(chr.uuid);
+                CHR_DEFS[index][i] = ble_gatt_chr_def {
+                    uuid:         &CHR_UUIDS[index][i],
+                    access_cb:    Some(access_trampoline),
+                    arg:          &mut SLOTS[index][i] as *mut Slot as *mut ::cty::c_void,
+                    descriptors:  core::ptr::null_mut(),
+                    flags:        chr.flags(),
+                    min_key_size: 0,
+                    val_handle:   &mut SLOTS[index][i].val_handle,
+                };
+            }
+            CHR_DEFS[index][self.characteristics.len()] = ble_gatt_chr_def::END;
+            SVC_DEFS[index] = [
+                ble_gatt_svc_def {
+                    type_:           gatt::BLE_GATT_SVC_TYPE_PRIMARY,
+                    uuid:            &SERVICE_UUIDS[index],
+                    includes:        core::ptr::null_mut(),
+                    characteristics: CHR_DEFS[index].as_ptr(),
+                },
+                ble_gatt_svc_def::END,
+            ];
+            gatt::register_services(&SVC_DEFS[index]) ? ;
+            NUM_SERVICES += 1;
+        }
+        Ok(Service { index, len: self.characteristics.len() })
+    }
+}
+
+/// Service registered by `ServiceBuilder::register()`
+#[derive(Clone, Copy)]
+pub struct Service {
+    /// Index of the service in the statics
+    index: usize,
+    /// Number of characteristics
+    len: usize,
+}
+
+impl Service {
+    /// Return the characteristic with the 16-bit UUID `uuid`, or `None` if not found
+    pub fn characteristic(&self, uuid: u16) -> Option<CharacteristicRef> {
+        let slots = unsafe { &SLOTS[self.index][..self.len] };
+        slots.iter()
+            .find(|slot| slot.uuid == uuid)
+            .map(|slot| CharacteristicRef { slot })
+    }
+}
+
+/// Characteristic registered by `ServiceBuilder::register()`, for sending notifications
+#[derive(Clone, Copy)]
+pub struct CharacteristicRef {
+    /// State of the characteristic
+    slot: &'static Slot,
+}
+
+impl CharacteristicRef {
+    /// Return the handle of the characteristic value
+    pub fn val_handle(&self) -> u16 {
+        self.slot.val_handle
+    }
+
+    /// Notify the subscribed peers that the value has changed. The value is returned by the read closure.
+    pub fn updated(&self) {
+        gatt::chr_updated(self.slot.val_handle);
+    }
+
+    /// Send a notification with the value `data` to the peer on the connection
+    pub fn notify(&self, conn: ConnHandle, data: &[u8]) -> BleResult<()> {
+        gatt::notify(conn, self.slot.val_handle, data)
+    }
+}
+
+/// State of a registered characteristic, passed to the access callback
+#[derive(Clone, Copy)]
+struct Slot {
+    /// UUID of the characteristic
+    uuid: u16,
+    /// Closure that returns the value for reads and notifications
+    on_read: Option<&'static ReadFn>,
+    /// Closure that receives the written value
+    on_write: Option<&'static WriteFn>,
+    /// Handle of the characteristic value, set by NimBLE
+    val_handle: u16,
+}
+
+/// Unused characteristic state
+const EMPTY_SLOT: Slot = Slot { uuid: 0, on_read: None, on_write: None, val_handle: 0 };
+
+/// Number of services registered
+static mut NUM_SERVICES: usize = 0;
+
+/// UUIDs of the services
+static mut SERVICE_UUIDS: [Uuid16; MAX_SERVICES] = [Uuid16::new(0); MAX_SERVICES];
+
+/// UUIDs of the characteristics of each service
+static mut CHR_UUIDS: [[Uuid16; MAX_CHARACTERISTICS]; MAX_SERVICES] =
+    [[Uuid16::new(0); MAX_CHARACTERISTICS]; MAX_SERVICES];
+
+/// State of the characteristics of each service
+static mut SLOTS: [[Slot; MAX_CHARACTERISTICS]; MAX_SERVICES] = [[EMPTY_SLOT; MAX_CHARACTERISTICS]; MAX_SERVICES];
+
+/// Characteristic definitions of each service, terminated by `ble_gatt_chr_def::END`
+static mut CHR_DEFS: [[ble_gatt_chr_def; MAX_CHARACTERISTICS + 1]; MAX_SERVICES] =
+    [[ble_gatt_chr_def::END; MAX_CHARACTERISTICS + 1]; MAX_SERVICES];
+
+/// Service definitions, each terminated by `ble_gatt_svc_def::END`
+static mut SVC_DEFS: [[ble_gatt_svc_def; 2]; MAX_SERVICES] = [[ble_gatt_svc_def::END; 2]; MAX_SERVICES];
+
+/// Arena for the closures, aligned for any closure up to 8-byte alignment
+static mut ARENA: [u64; ARENA_SIZE / 8] = [0; ARENA_SIZE / 8];
+
+/// Number of bytes used in the arena
+static mut ARENA_USED: usize = 0;
+
+/// Move the closure into the arena and return a static reference. The closures are never dropped, since the
+/// services are never unregistered. Panics if the arena is full.
+fn store<F: 'static>(func: F) -> &'static F {
+    let size  = mem::size_of::<F>();
+    let align = mem::align_of::<F>();
+    assert!(align <= mem::align_of::<u64>(), "closure align");
+    unsafe {
+        let offset = (ARENA_USED + align - 1) / align * align;
+        assert!(offset + size <= ARENA_SIZE, "gatt arena full");
+        let ptr = (ARENA.as_mut_ptr() as *mut u8).add(offset) as *mut F;
+        core::ptr::write(ptr, func);
+        ARENA_USED = offset + size;
+        &*ptr
+    }
+}
+
+/// Called by NimBLE to read or write a characteristic. `arg` is the `Slot` of the characteristic.
+extern "C" fn access_trampoline(conn_handle: u16, attr_handle: u16, ctxt: *mut ble_gatt_access_ctxt,
+    arg: *mut ::cty::c_void) -> i32 {
+    assert!(!ctxt.is_null() && !arg.is_null(), "null gatt access");
+    let slot = unsafe { &*(arg as *const Slot) };
+    let mut access = unsafe { Access::from_ctxt(conn_handle, attr_handle, ctxt) };
+    to_att_rc(handle_access(slot, &mut access))
+}
+
+/// Call the closure for the read or write, and copy the value to or from the mbuf
+fn handle_access(slot: &Slot, access: &mut Access) -> AccessResult {
+    let mut buf = [0u8; MAX_VALUE_LEN];
+    match access.op {
+        AccessOp::ReadChr => {
+            let on_read = slot.on_read.ok_or(AttError::READ_NOT_PERMITTED) ? ;
+            let len = on_read(&mut buf) ? ;
+            if len > buf.len() { return Err(AttError::UNLIKELY); }
+            access.append(&buf[..len])
+        }
+        AccessOp::WriteChr => {
+            let on_write = slot.on_write.ok_or(AttError::WRITE_NOT_PERMITTED) ? ;
+            let len = access.read_into(&mut buf) ? ;
+            on_write(&buf[..len])
+        }
+        AccessOp::Other(_) => Err(AttError::UNLIKELY),
+    }
+}
@@ -0,0 +1,174 @@
+//! Connection parameters of Bluetooth LE connections. The peripheral may ask the central for new parameters after
+//! connecting, e.g. `ConnParams::FAST` while uploading a logo and `ConnParams::SLOW` when idle to save power.
+//! The central may accept or reject the request, and the result is passed to the function set by `on_update()`.
+//! ```
+//! conn::on_update(|conn, result| { if result.is_err() { /* Central rejected the parameters */ } }) ? ;
+//! conn::request(conn, &ConnParams::FAST) ? ;
+//! ```
+//! For bulk transfers, `request_high_throughput()` also asks for the 2M PHY, longer link layer packets and a larger
+//! ATT MTU. `max_write_len()` returns the largest write that fits in one ATT packet with the negotiated MTU.
+//! `rssi()` returns the signal strength of the connection, for diagnosing slow or failed transfers.
+
+use core::time::Duration;
+use crate::ble::{ check_ble, gap::ConnHandle, BleError, BleResult };
+
+/// Requested connection parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnParams {
+    /// Shortest acceptable connection interval, at least 7.5 ms
+    pub interval_min: Duration,
+    /// Longest acceptable connection interval, at most 4 s
+    pub interval_max: Duration,
+    /// Number of connection events that the peripheral may skip, at most 499
+    pub latency: u16,
+    /// Time without packets before the connection is dropped, from 100 ms to 32 s
+    pub supervision_timeout: Duration,
+}
+
+impl ConnParams {
+    /// Short interval for transferring data quickly, e.g. a logo or firmware upload
+    pub const FAST: ConnParams = ConnParams {
+        interval_min:        Duration::from_micros(15_000),
+        interval_max:        Duration::from_micros(30_000),
+        latency:             0,
+        supervision_timeout: Duration::from_millis(2_000),
+    };
+
+    /// Long interval with slave latency for saving power when idle
+    pub const SLOW: ConnParams = ConnParams {
+        interval_min:        Duration::from_millis(400),
+        interval_max:        Duration::from_millis(500),
+        latency:             2,
+        supervision_timeout: Duration::from_millis(6_000),
+    };
+}
+
+/// Current parameters of a connection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnInfo {
+    /// Connection interval
+    pub interval: Duration,
+    /// Number of connection events that the peripheral may skip
+    pub latency: u16,
+    /// Time without packets before the connection is dropped
+    pub supervision_timeout: Duration,
+}
+
+/// Connection interval unit of 1.25 ms
+const INTERVAL_UNIT_US: u64 = 1_250;
+
+/// Supervision timeout unit of 10 ms
+const TIMEOUT_UNIT_US: u64 = 10_000;
+
+/// Max slave latency allowed by the Bluetooth Core Specification
+const MAX_LATENCY: u16 = 499;
+
+/// ATT MTU before the MTU exchange
+pub const DEFAULT_MTU: u16 = 23;
+
+/// Size of the ATT header of a write or notification: opcode and attribute handle
+const ATT_HEADER_LEN: u16 = 3;
+
+/// Largest characteristic value in one ATT packet before the MTU exchange
+pub const DEFAULT_WRITE_LEN: u16 = DEFAULT_MTU - ATT_HEADER_LEN;
+
+/// Function called with the result of each parameter update
+static mut UPDATE_FUNC: Option<fn(ConnHandle, BleResult<()>)> = None;
+
+/// Ask the central to change the parameters of the connection. The result is passed to the function set by
+/// `on_update()`. Returns `EINVAL` if the parameters are out of range, `ENOTCONN` if not connected.
+pub fn request(conn: ConnHandle, params: &ConnParams) -> BleResult<()> {
+    let itvl_min = to_units(params.interval_min, INTERVAL_UNIT_US, 6, 3_200) ? ;
+    let itvl_max = to_units(params.interval_max, INTERVAL_UNIT_US, 6, 3_200) ? ;
+    let timeout  = to_units(params.supervision_timeout, TIMEOUT_UNIT_US, 10, 3_200) ? ;
+    if itvl_min > itvl_max || params.latency > MAX_LATENCY { return Err(BleError::EINVAL); }
+    //  The supervision timeout must be longer than the time taken by the skipped connection events.
+    let min_timeout_us = 2 * (1 + params.latency as u64) * itvl_max as u64 * INTERVAL_UNIT_US;
+    if timeout as u64 * TIMEOUT_UNIT_US <= min_timeout_us { return Err(BleError::EINVAL); }
+    check_ble(unsafe { ble_helper_update_params(conn, itvl_min, itvl_max, params.latency, timeout) })
+}
+
+/// Call `func` with the connection and the result of each parameter update: `Ok` if the central accepted the
+/// parameters, else the error. Receives the updates of all connections, including updates started by the central.
+pub fn on_update(func: fn(ConnHandle, BleResult<()>)) -> BleResult<()> {
+    unsafe { UPDATE_FUNC = Some(func) };
+    check_ble(unsafe { ble_helper_set_conn_update_cb(update_trampoline) })
+}
+
+/// Return the current parameters of the connection. Returns `ENOTCONN` if not connected.
+pub fn current(conn: ConnHandle) -> BleResult<ConnInfo> {
+    let mut itvl: u16 = 0;
+    let mut latency: u16 = 0;
+    let mut timeout: u16 = 0;
+    check_ble(unsafe { ble_helper_conn_params(conn, &mut itvl, &mut latency, &mut timeout) }) ? ;
+    Ok(ConnInfo {
+        interval: Duration::from_micros(itvl as u64 * INTERVAL_UNIT_US),
+        latency,
+        supervision_timeout: Duration::from_micros(timeout as u64 * TIMEOUT_UNIT_US),
+    })
+}
+
+/// Ask the peer for a faster link: the 2M PHY, the longest link layer packets and the largest ATT MTU in
+/// `syscfg.yml`. Requests not supported by the controller are skipped, and the peer may refuse them. The negotiated
+/// MTU is reported by `GapEvent::Mtu` and returned by `mtu()`. Returns `ENOTCONN` if not connected.
+pub fn request_high_throughput(conn: ConnHandle) -> BleResult<()> {
+    check_ble(unsafe { ble_helper_high_throughput(conn) })
+}
+
+/// Return the ATT MTU of the connection. Returns `ENOTCONN` if not connected.
+pub fn mtu(conn: ConnHandle) -> BleResult<u16> {
+    match unsafe { ble_helper_att_mtu(conn) } {
+        0   => Err(BleError::ENOTCONN),
+        mtu => Ok(mtu),
+    }
+}
+
+/// Return the signal strength of the last packet received from the peer on the connection, in dBm, e.g. -50 when
+/// close and -90 at the edge of the range. Returns `ENOTCONN` if not connected.
+pub fn rssi(conn: ConnHandle) -> BleResult<i8> {
+    let mut rssi: i8 = 0;
+    check_ble(unsafe { ble_helper_conn_rssi(conn, &mut rssi) }) ? ;
+    Ok(rssi)
+}
+
+/// Return the largest characteristic value that can be written or notified in one ATT packet on the connection,
+/// e.g. `DEFAULT_WRITE_LEN` before the MTU exchange. Returns `ENOTCONN` if not connected.
+pub fn max_write_len(conn: ConnHandle) -> BleResult<u16> {
+    let mtu = mtu(conn) ? ;
+    Ok(core::cmp::max(mtu, DEFAULT_MTU) - ATT_HEADER_LEN)
+}
+
+/// Convert the duration to a count of `unit_us` microseconds. Returns `EINVAL` if outside `min..=max`.
+fn to_units(duration: Duration, unit_us: u64, min: u16, max: u16) -> BleResult<u16> {
+    let units = duration.as_micros() / unit_us as u128;
+    if units < min as u128 || units > max as u128 { return Err(BleError::EINVAL); }
+    Ok(units as u16)
+}
+
+/// Called by `ble_helper.c` with each connection parameter update
+extern "C" fn update_trampoline(conn_handle: u16, status: i32) {
+    let result = if status == 0 { Ok(()) } else { Err(BleError(status)) };
+    if let Some(func) = unsafe { UPDATE_FUNC } { func(conn_handle, result); }
+}
+
+extern "C" {
+    /// Ask the central to update the connection parameters.
+    /// C API: `int ble_helper_update_params(uint16_t conn_handle, uint16_t itvl_min, uint16_t itvl_max, uint16_t latency, uint16_t supervision_timeout)`
+    fn ble_helper_update_params(conn_handle: u16, itvl_min: u16, itvl_max: u16, latency: u16,
+        supervision_timeout: u16) -> i32;
+    /// Set the callback for connection parameter updates.
+    /// C API: `int ble_helper_set_conn_update_cb(void (*cb)(uint16_t conn_handle, int status))`
+    fn ble_helper_set_conn_update_cb(cb: extern "C" fn(u16, i32)) -> i32;
+    /// Get the current connection parameters.
+    /// C API: `int ble_helper_conn_params(uint16_t conn_handle, uint16_t *itvl, uint16_t *latency, uint16_t *supervision_timeout)`
+    fn ble_helper_conn_params(conn_handle: u16, itvl: *mut u16, latency: *mut u16, supervision_timeout: *mut u16) -> i32;
+    /// Ask for the 2M PHY, data length extension and a larger ATT MTU.
+    /// C API: `int ble_helper_high_throughput(uint16_t conn_handle)`
+    fn ble_helper_high_throughput(conn_handle: u16) -> i32;
+    /// Return the ATT MTU of the connection, or 0 if not connected.
+    /// C API: `uint16_t ble_helper_att_mtu(uint16_t conn_handle)`
+    fn ble_helper_att_mtu(conn_handle: u16) -> u16;
+    /// Read the signal strength of the last packet received on the connection.
+    /// C API: `int ble_helper_conn_rssi(uint16_t conn_handle, int8_t *rssi)`
+    fn ble_helper_conn_rssi(conn_handle: u16, rssi: *mut i8) -> i32;
+}
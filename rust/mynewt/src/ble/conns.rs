@@ -0,0 +1,187 @@
+//! Per-connection state of the connected centrals, e.g. a phone and a collection gateway connected at the same time
+//! (`BLE_MAX_CONNECTIONS` in `syscfg.yml`). Each connection has its own security level, ATT MTU and subscriptions
+//! to notifications, so that a feature may serve each central by what it negotiated, e.g. `notify::Stream` sends
+//! the notifications to every subscriber, and uploads are refused on a connection that is not authenticated.
+//! The state is updated by the GAP events of all connections.
+//! ```
+//! conns::start() ? ;
+//! conns::require(conn, Security::Authenticated) ? ;  //  Fails and asks the central to pair if not authenticated
+//! for conn in conns::subscribers(val_handle).iter() { gatt::notify(*conn, val_handle, &data).ok(); }
+//! ```
+
+use crate::{
+    ble::{
+        check_ble,
+        conn::DEFAULT_MTU,
+        gap::{ self, ConnHandle, GapEvent },
+        BleError, BleResult,
+    },
+    kernel::os,
+};
+
+/// Max number of connections that are tracked. Must match `MaxConns`.
+pub const MAX_CONNS: usize = 4;
+pub type MaxConns = heapless::consts::U4;
+
+/// Max number of characteristics that each connection may subscribe to. Must match `MaxSubscriptions`.
+pub const MAX_SUBSCRIPTIONS: usize = 8;
+pub type MaxSubscriptions = heapless::consts::U8;
+
+/// Security level of a connection, from the lowest to the highest
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Security {
+    /// Link is not encrypted
+    Open,
+    /// Link is encrypted with a key that was not authenticated, e.g. Just Works pairing
+    Encrypted,
+    /// Link is encrypted with a key authenticated by a passkey
+    Authenticated,
+}
+
+/// State of a connection
+#[derive(Clone, Debug)]
+pub struct ConnState {
+    /// Connection handle
+    pub conn: ConnHandle,
+    /// Security level of the link
+    pub security: Security,
+    /// True if the central is bonded, so it reconnects without pairing
+    pub bonded: bool,
+    /// ATT MTU negotiated with the central, `DEFAULT_MTU` until the MTU exchange
+    pub mtu: u16,
+    /// Characteristic values whose notifications the central has subscribed to
+    pub subscriptions: heapless::Vec<u16, MaxSubscriptions>,
+}
+
+/// States of the connections, accessed in critical sections
+static mut CONNS: heapless::Vec<ConnState, MaxConns> = heapless::Vec(heapless::i::Vec::new());
+
+/// True if the GAP events are followed
+static mut STARTED: bool = false;
+
+/// Follow the GAP events of all connections. Must be called before the host starts. Does nothing if already
+/// started. Returns `ENOTSUP` if Bluetooth LE is disabled.
+pub fn start() -> BleResult<()> {
+    if unsafe { STARTED } { return Ok(()); }
+    gap::listen(handle_event) ? ;
+    unsafe { STARTED = true };
+    Ok(())
+}
+
+/// Return the number of connections
+pub fn count() -> usize {
+    with_conns(|conns| conns.len())
+}
+
+/// Return a copy of the state of the connection, or `None` if not connected
+pub fn get(conn: ConnHandle) -> Option<ConnState> {
+    with_conns(|conns| conns.iter().find(|c| c.conn == conn).cloned())
+}
+
+/// Return the handles of the connections
+pub fn handles() -> heapless::Vec<ConnHandle, MaxConns> {
+    with_conns(|conns| conns.iter().map(|c| c.conn).collect())
+}
+
+/// Return the security level of the connection. Returns `ENOTCONN` if not connected.
+pub fn security(conn: ConnHandle) -> BleResult<Security> {
+    get(conn).map(|c| c.security).ok_or(BleError::ENOTCONN)
+}
+
+/// Return `Ok` if the connection has at least the security level. Otherwise ask the central to pair, or to encrypt
+/// the link with the bond, and return `EAUTHEN` if encrypted without authentication, else `EENCRYPT`.
+/// Returns `ENOTCONN` if not connected.
+pub fn require(conn: ConnHandle, level: Security) -> BleResult<()> {
+    let security = security(conn) ? ;
+    if security >= level { return Ok(()); }
+    //  Ignore the error if pairing is already in progress.
+    unsafe { ble_helper_security_initiate(conn) };
+    if security == Security::Encrypted { Err(BleError::EAUTHEN) } else { Err(BleError::EENCRYPT) }
+}
+
+/// Return the connections that have subscribed to the notifications of the characteristic value `attr`
+pub fn subscribers(attr: u16) -> heapless::Vec<ConnHandle, MaxConns> {
+    with_conns(|conns| conns.iter()
+        .filter(|c| c.subscriptions.contains(&attr))
+        .map(|c| c.conn)
+        .collect())
+}
+
+/// Update the state of the connections with the GAP event. Called by the NimBLE host.
+fn handle_event(event: &GapEvent) {
+    match *event {
+        GapEvent::Connect { conn, status: 0 } => {
+            //  The link is encrypted later, by an encryption change event.
+            let state = ConnState {
+                conn, security: Security::Open, bonded: false, mtu: DEFAULT_MTU,
+                subscriptions: heapless::Vec::new(),
+            };
+            //  Don't track the connection if there are more than `MAX_CONNS`.
+            with_conns(|conns| { conns.push(state).ok(); });
+        }
+        GapEvent::Disconnect { conn, .. } => {
+            with_conns(|conns| {
+                if let Some(i) = conns.iter().position(|c| c.conn == conn) { conns.swap_remove(i); }
+            });
+        }
+        GapEvent::EncChange { conn, .. } => {
+            //  Read the security from NimBLE, since the link may remain encrypted after a failed change.
+            //  Skip the update if the central has disconnected in the meantime.
+            if let Ok((security, bonded)) = read_security(conn) {
+                with_state(conn, |state| { state.security = security; state.bonded = bonded; });
+            }
+        }
+        GapEvent::Mtu { conn, mtu } => {
+            with_state(conn, |state| state.mtu = mtu);
+        }
+        GapEvent::Subscribe { conn, attr, notify } => {
+            with_state(conn, |state| {
+                let subscribed = state.subscriptions.iter().position(|a| *a == attr);
+                match (notify, subscribed) {
+                    //  Ignore the subscription if the connection has subscribed to more than `MAX_SUBSCRIPTIONS`.
+                    (true, None)     => { state.subscriptions.push(attr).ok(); }
+                    (false, Some(i)) => { state.subscriptions.swap_remove(i); }
+                    _ => {}
+                }
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Return the security level of the connection and whether the central is bonded, as reported by NimBLE
+fn read_security(conn: ConnHandle) -> BleResult<(Security, bool)> {
+    let mut encrypted: u8 = 0;
+    let mut authenticated: u8 = 0;
+    let mut bonded: u8 = 0;
+    check_ble(unsafe { ble_helper_conn_security(conn, &mut encrypted, &mut authenticated, &mut bonded) }) ? ;
+    let security =
+        if encrypted == 0          { Security::Open }
+        else if authenticated == 0 { Security::Encrypted }
+        else                       { Security::Authenticated };
+    Ok((security, bonded != 0))
+}
+
+/// Call `func` with the state of the connection, if connected, with interrupts disabled
+fn with_state<F: FnOnce(&mut ConnState)>(conn: ConnHandle, func: F) {
+    with_conns(|conns| {
+        if let Some(state) = conns.iter_mut().find(|c| c.conn == conn) { func(state); }
+    })
+}
+
+/// Call `func` with the states of the connections, with interrupts disabled
+fn with_conns<R, F: FnOnce(&mut heapless::Vec<ConnState, MaxConns>) -> R>(func: F) -> R {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let result = func(unsafe { &mut CONNS });
+    unsafe { os::os_arch_restore_sr(sr) };
+    result
+}
+
+extern "C" {
+    /// Get the security state of the connection.
+    /// C API: `int ble_helper_conn_security(uint16_t conn_handle, uint8_t *encrypted, uint8_t *authenticated, uint8_t *bonded)`
+    fn ble_helper_conn_security(conn_handle: u16, encrypted: *mut u8, authenticated: *mut u8, bonded: *mut u8) -> i32;
+    /// Ask the central to pair, or to encrypt the link with the bond.
+    /// C API: `int ble_helper_security_initiate(uint16_t conn_handle)`
+    fn ble_helper_security_initiate(conn_handle: u16) -> i32;
+}
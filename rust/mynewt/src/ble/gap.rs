@@ -0,0 +1,181 @@
+//! Bluetooth LE advertising and connections (GAP). `advertise()` starts general discoverable, connectable
+//! advertising, and the GAP events of the advertising and connections are passed to a Rust function.
+
+use core::time::Duration;
+use crate::{
+    ble::{ check_ble, BleError, BleResult },
+    Strn,
+};
+
+/// Max number of 16-bit service UUIDs in the advertisement
+pub const MAX_ADV_UUIDS16: usize = 4;
+
+/// Handle of a connection
+pub type ConnHandle = u16;
+
+/// HCI reason for disconnecting: Remote User Terminated Connection
+pub const REASON_REMOTE_USER_TERM: u8 = 0x13;
+
+/// GAP event from NimBLE
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GapEvent {
+    /// Connection established if `status` is 0, else failed
+    Connect { conn: ConnHandle, status: i32 },
+    /// Connection terminated for the reason
+    Disconnect { conn: ConnHandle, reason: i32 },
+    /// Connection parameters updated if `status` is 0, else rejected by the central
+    ConnUpdate { conn: ConnHandle, status: i32 },
+    /// Advertising stopped for the reason, e.g. the duration has elapsed
+    AdvComplete { reason: i32 },
+    /// Link encrypted after pairing or with the bond if `status` is 0, else failed
+    EncChange { conn: ConnHandle, status: i32 },
+    /// Notification for the characteristic value `attr` sent to the link layer if `status` is 0, else failed
+    NotifyTx { conn: ConnHandle, attr: u16, status: i32 },
+    /// Peer subscribed to or unsubscribed from the notifications of the characteristic value `attr`
+    Subscribe { conn: ConnHandle, attr: u16, notify: bool },
+    /// ATT MTU of the connection negotiated with the peer
+    Mtu { conn: ConnHandle, mtu: u16 },
+    /// Other event, by `BLE_GAP_EVENT_*` type
+    Other(u8),
+}
+
+/// `BLE_GAP_EVENT_*` types from `host/ble_gap.h`
+const BLE_GAP_EVENT_CONNECT:      u8 = 0;
+const BLE_GAP_EVENT_DISCONNECT:   u8 = 1;
+const BLE_GAP_EVENT_CONN_UPDATE:  u8 = 3;
+const BLE_GAP_EVENT_ADV_COMPLETE: u8 = 9;
+const BLE_GAP_EVENT_ENC_CHANGE:   u8 = 10;
+const BLE_GAP_EVENT_NOTIFY_TX:    u8 = 13;
+const BLE_GAP_EVENT_SUBSCRIBE:    u8 = 14;
+const BLE_GAP_EVENT_MTU:          u8 = 15;
+
+/// Function called with each GAP event
+static mut EVENT_FUNC: Option<fn(&GapEvent)> = None;
+
+/// Max number of functions that may listen to the GAP events of all connections. Must match `MaxListeners`.
+pub const MAX_LISTENERS: usize = 4;
+type MaxListeners = heapless::consts::U4;
+
+/// Functions called with the GAP events of all connections
+static mut LISTENERS: heapless::Vec<fn(&GapEvent), MaxListeners> = heapless::Vec(heapless::i::Vec::new());
+
+/// Set the device name in the GAP service, e.g. `pinetime`
+pub fn set_device_name(name: &Strn) -> BleResult<()> {
+    name.validate();
+    check_ble(unsafe { ble_svc_gap_device_name_set(name.as_cstr()) })
+}
+
+/// Advertise the device name `name` and the 16-bit service UUIDs `uuids16`, for `duration` or forever if `None`.
+/// `func` is called with the GAP events of the advertising and of the connections. The host must be synced.
+/// Returns `EINVAL` if there are more than `MAX_ADV_UUIDS16` UUIDs.
+pub fn advertise(name: &Strn, uuids16: &[u16], duration: Option<Duration>, func: fn(&GapEvent)) -> BleResult<()> {
+    name.validate();
+    if uuids16.len() > MAX_ADV_UUIDS16 { return Err(BleError::EINVAL); }
+    let duration_ms = duration_to_ms(duration) ? ;
+    set_event_func(func);
+    check_ble(unsafe { ble_helper_advertise(name.as_cstr(), uuids16.as_ptr(), uuids16.len() as u8, duration_ms,
+        event_trampoline, core::ptr::null_mut()) })
+}
+
+/// Convert the advertising duration to milliseconds for `ble_helper.c`, or -1 to advertise forever
+pub(crate) fn duration_to_ms(duration: Option<Duration>) -> BleResult<i32> {
+    match duration {
+        Some(duration) if duration.as_millis() < i32::max_value() as u128 => Ok(duration.as_millis() as i32),
+        Some(_) => Err(BleError::EINVAL),
+        None => Ok(-1),
+    }
+}
+
+/// Stop advertising. Returns `EALREADY` if not advertising.
+pub fn stop_advertising() -> BleResult<()> {
+    check_ble(unsafe { ble_gap_adv_stop() })
+}
+
+/// Return true if advertising
+pub fn is_advertising() -> bool {
+    unsafe { ble_gap_adv_active() != 0 }
+}
+
+/// Terminate the connection with the HCI reason, e.g. `REASON_REMOTE_USER_TERM`
+pub fn disconnect(conn: ConnHandle, reason: u8) -> BleResult<()> {
+    check_ble(unsafe { ble_gap_terminate(conn, reason) })
+}
+
+/// Call `func` with each GAP event. Used by `adv::start()` to restart advertising after a disconnect.
+pub(crate) fn set_event_func(func: fn(&GapEvent)) {
+    unsafe { EVENT_FUNC = Some(func) };
+}
+
+/// Call `func` with the GAP events of all connections, including the connections of the advertising started in C.
+/// Used by `conns`, `notify::Stream` and `lifecycle`. Returns `ENOMEM` if there are more than `MAX_LISTENERS` listeners.
+pub(crate) fn listen(func: fn(&GapEvent)) -> BleResult<()> {
+    unsafe { LISTENERS.push(func) }.map_err(|_| BleError::ENOMEM) ? ;
+    check_ble(unsafe { ble_helper_set_event_listener(listener_trampoline, core::ptr::null_mut()) })
+}
+
+/// Called by `ble_helper.c` with the flattened GAP events of all connections
+extern "C" fn listener_trampoline(info: *const ble_helper_gap_info, _arg: *mut ::cty::c_void) -> i32 {
+    assert!(!info.is_null(), "null gap event");
+    let event = to_event(unsafe { &*info });
+    for func in unsafe { LISTENERS.iter() } { func(&event); }
+    0
+}
+
+/// Called by `ble_helper.c` with each flattened GAP event
+pub(crate) extern "C" fn event_trampoline(info: *const ble_helper_gap_info, _arg: *mut ::cty::c_void) -> i32 {
+    assert!(!info.is_null(), "null gap event");
+    let event = to_event(unsafe { &*info });
+    if let Some(func) = unsafe { EVENT_FUNC } { func(&event); }
+    0
+}
+
+/// Convert the GAP event flattened by `ble_helper.c`
+pub(crate) fn to_event(info: &ble_helper_gap_info) -> GapEvent {
+    match info.type_ {
+        BLE_GAP_EVENT_CONNECT      => GapEvent::Connect { conn: info.conn_handle, status: info.status },
+        BLE_GAP_EVENT_DISCONNECT   => GapEvent::Disconnect { conn: info.conn_handle, reason: info.status },
+        BLE_GAP_EVENT_CONN_UPDATE  => GapEvent::ConnUpdate { conn: info.conn_handle, status: info.status },
+        BLE_GAP_EVENT_ADV_COMPLETE => GapEvent::AdvComplete { reason: info.status },
+        BLE_GAP_EVENT_ENC_CHANGE   => GapEvent::EncChange { conn: info.conn_handle, status: info.status },
+        BLE_GAP_EVENT_NOTIFY_TX    =>
+            GapEvent::NotifyTx { conn: info.conn_handle, attr: info.attr_handle, status: info.status },
+        BLE_GAP_EVENT_SUBSCRIBE    =>
+            GapEvent::Subscribe { conn: info.conn_handle, attr: info.attr_handle, notify: info.notify != 0 },
+        BLE_GAP_EVENT_MTU          => GapEvent::Mtu { conn: info.conn_handle, mtu: info.mtu },
+        other => GapEvent::Other(other),
+    }
+}
+
+/// GAP event flattened by `ble_helper.c`. Must sync with `struct ble_helper_gap_info` in `ble_helper.h`.
+#[repr(C)]
+pub(crate) struct ble_helper_gap_info {
+    type_:       u8,
+    conn_handle: u16,
+    status:      i32,
+    attr_handle: u16,
+    notify:      u8,
+    mtu:         u16,
+}
+
+extern "C" {
+    /// Start advertising with the device name and 16-bit service UUIDs.
+    /// C API: `int ble_helper_advertise(const char *name, const uint16_t *uuids16, uint8_t num_uuids16, int32_t duration_ms, ble_helper_gap_fn *cb, void *arg)`
+    fn ble_helper_advertise(name: *const u8, uuids16: *const u16, num_uuids16: u8, duration_ms: i32,
+        cb: extern "C" fn(*const ble_helper_gap_info, *mut ::cty::c_void) -> i32, arg: *mut ::cty::c_void) -> i32;
+    /// Set the callback for the GAP events of all connections.
+    /// C API: `int ble_helper_set_event_listener(ble_helper_gap_fn *cb, void *arg)`
+    fn ble_helper_set_event_listener(cb: extern "C" fn(*const ble_helper_gap_info, *mut ::cty::c_void) -> i32,
+        arg: *mut ::cty::c_void) -> i32;
+    /// Set the device name in the GAP service.
+    /// C API: `int ble_svc_gap_device_name_set(const char *name)`
+    fn ble_svc_gap_device_name_set(name: *const u8) -> i32;
+    /// Stop advertising.
+    /// C API: `int ble_gap_adv_stop(void)`
+    fn ble_gap_adv_stop() -> i32;
+    /// Return 1 if advertising.
+    /// C API: `int ble_gap_adv_active(void)`
+    fn ble_gap_adv_active() -> i32;
+    /// Terminate the connection.
+    /// C API: `int ble_gap_terminate(uint16_t conn_handle, uint8_t hci_reason)`
+    fn ble_gap_terminate(conn_handle: u16, hci_reason: u8) -> i32;
+}
@@ -0,0 +1,300 @@
+//! GATT services for the NimBLE host. Services and characteristics are declared as `static` definitions, like the
+//! `ble_gatt_svc_def` arrays in C, and registered with `register_services()` before the host starts. Each
+//! characteristic calls a Rust function to read or write its value.
+//! ```
+//! static HR_SVC_UUID: Uuid16 = Uuid16::new(0x180D);
+//! static HRM_CHR: Characteristic = Characteristic::new(0x2A37, gatt::F_NOTIFY, read_heart_rate);
+//! static HR_CHRS: [ble_gatt_chr_def; 2] = [HRM_CHR.def(), ble_gatt_chr_def::END];
+//! static SERVICES: [ble_gatt_svc_def; 2] = [ble_gatt_svc_def::primary(&HR_SVC_UUID, &HR_CHRS), ble_gatt_svc_def::END];
+//! gatt::register_services(&SERVICES) ? ;
+//! HRM_CHR.updated();  //  Notify the subscribed peers
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    ble::{ check_ble, gap::ConnHandle, BleError, BleResult },
+    kernel::os::{ os_mbuf, os_mbuf_append },
+    sys::power_profile,
+};
+
+/// Characteristic flags `BLE_GATT_CHR_F_*`
+pub const F_READ:         u16 = 0x0002;
+pub const F_WRITE_NO_RSP: u16 = 0x0004;
+pub const F_WRITE:        u16 = 0x0008;
+pub const F_NOTIFY:       u16 = 0x0010;
+pub const F_INDICATE:     u16 = 0x0020;
+pub const F_READ_ENC:     u16 = 0x0200;
+pub const F_READ_AUTHEN:  u16 = 0x0400;
+pub const F_WRITE_ENC:    u16 = 0x1000;
+pub const F_WRITE_AUTHEN: u16 = 0x2000;
+
+/// ATT error returned by an access function, e.g. `AttError::UNLIKELY`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AttError(pub u8);
+
+impl AttError {
+    pub const READ_NOT_PERMITTED:     AttError = AttError(0x02);
+    pub const WRITE_NOT_PERMITTED:    AttError = AttError(0x03);
+    pub const INVALID_ATTR_VALUE_LEN: AttError = AttError(0x0d);
+    pub const UNLIKELY:               AttError = AttError(0x0e);
+    pub const INSUFFICIENT_RES:       AttError = AttError(0x11);
+}
+
+/// Result of an access function
+pub type AccessResult = ::core::result::Result<(), AttError>;
+
+/// Operation requested of an access function
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessOp {
+    /// Read the characteristic value, or get the value for a notification
+    ReadChr,
+    /// Write the characteristic value
+    WriteChr,
+    /// Read or write a descriptor
+    Other(u8),
+}
+
+/// Read or write of a characteristic, passed to the access function
+pub struct Access {
+    /// Connection of the peer, or `BLE_HS_CONN_HANDLE_NONE` for a notification
+    pub conn: ConnHandle,
+    /// Attribute handle of the characteristic value
+    pub attr: u16,
+    /// Requested operation
+    pub op: AccessOp,
+    /// Value to be read or written
+    om: *mut os_mbuf,
+}
+
+impl Access {
+    /// Append `data` to the value being read
+    pub fn append(&mut self, data: &[u8]) -> AccessResult {
+        let rc = unsafe { os_mbuf_append(self.om, data.as_ptr() as *const ::cty::c_void, data.len() as u16) };
+        if rc == 0 { Ok(()) } else { Err(AttError::INSUFFICIENT_RES) }
+    }
+
+    /// Copy the value being written to `buf`. Returns the length of the value, or `INVALID_ATTR_VALUE_LEN` if the
+    /// value is longer than `buf`.
+    pub fn read_into(&self, buf: &mut [u8]) -> ::core::result::Result<usize, AttError> {
+        let mut len: u16 = 0;
+        let max_len = if buf.len() > u16::max_value() as usize { u16::max_value() } else { buf.len() as u16 };
+        let rc = unsafe { ble_hs_mbuf_to_flat(self.om, buf.as_mut_ptr(), max_len, &mut len) };
+        if rc != 0 { return Err(AttError::INVALID_ATTR_VALUE_LEN); }
+        Ok(len as usize)
+    }
+}
+
+/// 16-bit UUID of a service or characteristic. Same layout as `ble_uuid16_t`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Uuid16 {
+    /// `BLE_UUID_TYPE_16`
+    type_: u8,
+    /// The UUID
+    value: u16,
+}
+
+impl Uuid16 {
+    /// Create the 16-bit UUID `value`, e.g. `0x180D` for the Heart Rate Service
+    pub const fn new(value: u16) -> Self {
+        Uuid16 { type_: BLE_UUID_TYPE_16, value }
+    }
+}
+
+/// `BLE_UUID_TYPE_16`
+const BLE_UUID_TYPE_16: u8 = 16;
+
+/// Characteristic with a 16-bit UUID and a Rust access function. Must be declared `static`, since NimBLE keeps
+/// pointers to the UUID and the value handle.
+pub struct Characteristic {
+    /// UUID of the characteristic
+    uuid: Uuid16,
+    /// Characteristic flags, e.g. `F_READ | F_NOTIFY`
+    flags: u16,
+    /// Function called to read or write the value
+    access: fn(&mut Access) -> AccessResult,
+    /// Handle of the characteristic value, set by NimBLE when the service is registered
+    val_handle: UnsafeCell<u16>,
+}
+
+/// `Characteristic` may be shared between tasks
+unsafe impl Sync for Characteristic {}
+
+impl Characteristic {
+    /// Create a characteristic with the 16-bit UUID `uuid`, the flags `F_*` and the access function
+    pub const fn new(uuid: u16, flags: u16, access: fn(&mut Access) -> AccessResult) -> Self {
+        Characteristic { uuid: Uuid16::new(uuid), flags, access, val_handle: UnsafeCell::new(0) }
+    }
+
+    /// Return the definition of the characteristic, for the `ble_gatt_chr_def` array of a service
+    pub const fn def(&'static self) -> ble_gatt_chr_def {
+        ble_gatt_chr_def {
+            uuid:         &self.uuid as *const Uuid16,
+            access_cb:    Some(access_trampoline),
+            arg:          self as *const Characteristic as *mut ::cty::c_void,
+            descriptors:  core::ptr::null_mut(),
+            flags:        self.flags,
+            min_key_size: 0,
+            val_handle:   self.val_handle.get(),
+        }
+    }
+
+    /// Return the handle of the characteristic value, 0 if the service has not been registered
+    pub fn val_handle(&self) -> u16 {
+        unsafe { *self.val_handle.get() }
+    }
+
+    /// Notify or indicate the subscribed peers that the value has changed. The value is read with the access function.
+    pub fn updated(&self) {
+        chr_updated(self.val_handle());
+    }
+
+    /// Send a notification with the value `data` to the peer on the connection, without calling the access function
+    pub fn notify(&self, conn: ConnHandle, data: &[u8]) -> BleResult<()> {
+        notify(conn, self.val_handle(), data)
+    }
+}
+
+/// Notify or indicate the subscribed peers that the characteristic value `val_handle` has changed
+pub(crate) fn chr_updated(val_handle: u16) {
+    unsafe { ble_gatts_chr_updated(val_handle) };
+}
+
+/// Send a notification with the value `data` for the characteristic value `val_handle` to the peer on the connection
+pub(crate) fn notify(conn: ConnHandle, val_handle: u16, data: &[u8]) -> BleResult<()> {
+    if data.len() > u16::max_value() as usize { return Err(BleError::EINVAL); }
+    check_ble(unsafe { ble_helper_notify(conn, val_handle, data.as_ptr(), data.len() as u16) }) ? ;
+    power_profile::add_radio(data.len());
+    Ok(())
+}
+
+/// Called by NimBLE to read or write a characteristic. `arg` is the `Characteristic`.
+extern "C" fn access_trampoline(conn_handle: u16, attr_handle: u16, ctxt: *mut ble_gatt_access_ctxt,
+    arg: *mut ::cty::c_void) -> i32 {
+    assert!(!ctxt.is_null() && !arg.is_null(), "null gatt access");
+    let chr = unsafe { &*(arg as *const Characteristic) };
+    let mut access = unsafe { Access::from_ctxt(conn_handle, attr_handle, ctxt) };
+    to_att_rc((chr.access)(&mut access))
+}
+
+impl Access {
+    /// Wrap the access context passed by NimBLE to an access callback
+    pub(crate) unsafe fn from_ctxt(conn: ConnHandle, attr: u16, ctxt: *mut ble_gatt_access_ctxt) -> Self {
+        let ctxt = &*ctxt;
+        Access {
+            conn,
+            attr,
+            op: match ctxt.op {
+                BLE_GATT_ACCESS_OP_READ_CHR  => AccessOp::ReadChr,
+                BLE_GATT_ACCESS_OP_WRITE_CHR => AccessOp::WriteChr,
+                other => AccessOp::Other(other),
+            },
+            om: ctxt.om,
+        }
+    }
+}
+
+/// Convert the result of an access function to the return code for NimBLE
+pub(crate) fn to_att_rc(result: AccessResult) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(AttError(err)) => err as i32,
+    }
+}
+
+/// Register the services. The last service must be `ble_gatt_svc_def::END`. Call before the host starts.
+pub fn register_services(services: &'static [ble_gatt_svc_def]) -> BleResult<()> {
+    assert!(services.last().map(|svc| svc.type_) == Some(0), "no svc end");
+    check_ble(unsafe { ble_gatts_count_cfg(services.as_ptr()) }) ? ;
+    check_ble(unsafe { ble_gatts_add_svcs(services.as_ptr()) })
+}
+
+/// `BLE_GATT_ACCESS_OP_*` operations
+const BLE_GATT_ACCESS_OP_READ_CHR:  u8 = 0;
+const BLE_GATT_ACCESS_OP_WRITE_CHR: u8 = 1;
+
+/// `BLE_GATT_SVC_TYPE_PRIMARY`
+pub(crate) const BLE_GATT_SVC_TYPE_PRIMARY: u8 = 1;
+
+/// Definition of a characteristic. Same layout as `struct ble_gatt_chr_def`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ble_gatt_chr_def {
+    pub(crate) uuid:         *const Uuid16,
+    pub(crate) access_cb:    Option<AccessCallback>,
+    pub(crate) arg:          *mut ::cty::c_void,
+    pub(crate) descriptors:  *mut ::cty::c_void,
+    pub(crate) flags:        u16,
+    pub(crate) min_key_size: u8,
+    pub(crate) val_handle:   *mut u16,
+}
+
+/// Access callback called by NimBLE. Same as `ble_gatt_access_fn`.
+pub(crate) type AccessCallback = extern "C" fn(u16, u16, *mut ble_gatt_access_ctxt, *mut ::cty::c_void) -> i32;
+
+/// The definitions are static and not changed after registering
+unsafe impl Sync for ble_gatt_chr_def {}
+
+impl ble_gatt_chr_def {
+    /// Terminates the characteristics of a service
+    pub const END: ble_gatt_chr_def = ble_gatt_chr_def {
+        uuid: core::ptr::null(), access_cb: None, arg: core::ptr::null_mut(), descriptors: core::ptr::null_mut(),
+        flags: 0, min_key_size: 0, val_handle: core::ptr::null_mut(),
+    };
+}
+
+/// Definition of a service. Same layout as `struct ble_gatt_svc_def`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct ble_gatt_svc_def {
+    pub(crate) type_:           u8,
+    pub(crate) uuid:            *const Uuid16,
+    pub(crate) includes:        *mut *const ble_gatt_svc_def,
+    pub(crate) characteristics: *const ble_gatt_chr_def,
+}
+
+/// The definitions are static and not changed after registering
+unsafe impl Sync for ble_gatt_svc_def {}
+
+impl ble_gatt_svc_def {
+    /// Terminates the services
+    pub const END: ble_gatt_svc_def = ble_gatt_svc_def {
+        type_: 0, uuid: core::ptr::null(), includes: core::ptr::null_mut(), characteristics: core::ptr::null(),
+    };
+
+    /// Define a primary service with the characteristics, which must end with `ble_gatt_chr_def::END`
+    pub const fn primary(uuid: &'static Uuid16, characteristics: &'static [ble_gatt_chr_def]) -> Self {
+        ble_gatt_svc_def {
+            type_: BLE_GATT_SVC_TYPE_PRIMARY,
+            uuid,
+            includes: core::ptr::null_mut(),
+            characteristics: characteristics.as_ptr(),
+        }
+    }
+}
+
+/// Context of a characteristic access. Same layout as `struct ble_gatt_access_ctxt`.
+#[repr(C)]
+pub(crate) struct ble_gatt_access_ctxt {
+    op:  u8,
+    om:  *mut os_mbuf,
+    chr: *const ble_gatt_chr_def,
+}
+
+extern "C" {
+    /// Count the attributes of the services, for allocating the attribute table.
+    /// C API: `int ble_gatts_count_cfg(const struct ble_gatt_svc_def *defs)`
+    fn ble_gatts_count_cfg(defs: *const ble_gatt_svc_def) -> i32;
+    /// Add the services to the attribute table.
+    /// C API: `int ble_gatts_add_svcs(const struct ble_gatt_svc_def *svcs)`
+    fn ble_gatts_add_svcs(svcs: *const ble_gatt_svc_def) -> i32;
+    /// Notify or indicate the subscribed peers that the characteristic value has changed.
+    /// C API: `void ble_gatts_chr_updated(uint16_t chr_val_handle)`
+    fn ble_gatts_chr_updated(chr_val_handle: u16);
+    /// Send a notification with the data.
+    /// C API: `int ble_helper_notify(uint16_t conn_handle, uint16_t attr_handle, const uint8_t *data, uint16_t len)`
+    fn ble_helper_notify(conn_handle: u16, attr_handle: u16, data: *const u8, len: u16) -> i32;
+    /// Copy the mbuf chain to a flat buffer.
+    /// C API: `int ble_hs_mbuf_to_flat(const struct os_mbuf *om, void *flat, uint16_t max_len, uint16_t *out_copy_len)`
+    fn ble_hs_mbuf_to_flat(om: *const os_mbuf, flat: *mut u8, max_len: u16, out_copy_len: *mut u16) -> i32;
+}
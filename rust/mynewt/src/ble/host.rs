@@ -0,0 +1,51 @@
+//! NimBLE host configuration and callbacks. The callbacks must be set before the host starts, i.e. before
+//! `sysinit()` returns, e.g. in an `init_hook!()`.
+
+/// Function called when the host and controller are synced and ready, e.g. to start advertising
+static mut SYNC_FUNC: Option<fn()> = None;
+
+/// Function called with the reason when the host resets, e.g. after a controller error
+static mut RESET_FUNC: Option<fn(i32)> = None;
+
+/// Call `func` when the host and controller are synced. Advertising and connections must wait for the sync.
+pub fn on_sync(func: fn()) {
+    unsafe {
+        SYNC_FUNC = Some(func);
+        ble_helper_set_sync_cb(sync_trampoline);
+    }
+}
+
+/// Call `func` with the reason when the host resets. The host syncs again after the reset.
+pub fn on_reset(func: fn(i32)) {
+    unsafe {
+        RESET_FUNC = Some(func);
+        ble_helper_set_reset_cb(reset_trampoline);
+    }
+}
+
+/// Return true if the host and controller are synced
+pub fn is_synced() -> bool {
+    unsafe { ble_hs_synced() != 0 }
+}
+
+/// Called by NimBLE when the host is synced
+extern "C" fn sync_trampoline() {
+    if let Some(func) = unsafe { SYNC_FUNC } { func(); }
+}
+
+/// Called by NimBLE when the host resets
+extern "C" fn reset_trampoline(reason: i32) {
+    if let Some(func) = unsafe { RESET_FUNC } { func(reason); }
+}
+
+extern "C" {
+    /// Set the callback for the host sync.
+    /// C API: `void ble_helper_set_sync_cb(void (*cb)(void))`
+    fn ble_helper_set_sync_cb(cb: extern "C" fn());
+    /// Set the callback for the host reset.
+    /// C API: `void ble_helper_set_reset_cb(void (*cb)(int reason))`
+    fn ble_helper_set_reset_cb(cb: extern "C" fn(i32));
+    /// Return 1 if the host and controller are synced.
+    /// C API: `int ble_hs_synced(void)`
+    fn ble_hs_synced() -> i32;
+}
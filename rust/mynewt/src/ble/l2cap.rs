@@ -0,0 +1,120 @@
+//! L2CAP connection-oriented channels with credit-based flow control, for bulk transfers that are faster than
+//! GATT writes, e.g. logo and firmware uploads. The phone connects a channel to the PSM passed to `listen()`, and
+//! the channel is read and written like a stream. Each SDU sent by the phone is kept until it has been read, and
+//! the phone is given the credits for the next SDU only after that, so a slow reader throttles the phone.
+//! Requires `BLE_L2CAP_COC_MAX_NUM: 1` in `syscfg.yml`. One channel may be connected at a time.
+//! ```
+//! l2cap::listen(0x0080, 512, handle_coc_event) ? ;
+//! fn handle_coc_event(event: CocEvent) {
+//!     if let CocEvent::Readable(chan) = event {
+//!         let mut buf = [0u8; 64];
+//!         let len = chan.read(&mut buf).expect("read fail");
+//!         chan.write(&buf[..len]).ok();  //  Echo the data
+//!     }
+//! }
+//! ```
+
+use crate::{
+    ble::{ check_ble, gap::ConnHandle, BleError, BleResult },
+    sys::power_profile,
+};
+
+/// Connected L2CAP channel, identified by its connection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Channel {
+    /// Connection of the channel
+    pub conn: ConnHandle,
+}
+
+/// Event of an L2CAP channel, passed to the function set by `listen()` in the NimBLE host task
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CocEvent {
+    /// Phone has connected the channel
+    Connected(Channel),
+    /// Channel has been disconnected. The channel may not be read or written.
+    Disconnected(Channel),
+    /// Phone has sent data, read it with `Channel::read()`
+    Readable(Channel),
+    /// Phone has given credits, the data written before has been sent and the channel may be written again
+    Writable(Channel),
+}
+
+/// Function called with the channel events
+static mut EVENT_FUNC: Option<fn(CocEvent)> = None;
+
+/// Accept L2CAP channels on the PSM and call `func` with their events. The phone may send up to `mtu` bytes in
+/// each SDU. Returns `ENOTSUP` if L2CAP channels or Bluetooth LE are disabled in `syscfg.yml`.
+pub fn listen(psm: u16, mtu: u16, func: fn(CocEvent)) -> BleResult<()> {
+    unsafe { EVENT_FUNC = Some(func) };
+    check_ble(unsafe { ble_helper_coc_listen(psm, mtu, coc_trampoline, core::ptr::null_mut()) })
+}
+
+impl Channel {
+    /// Copy the received data to `buf` and return the number of bytes copied, 0 if there is no data to read.
+    /// The rest of the data is returned by the next read. Returns `ENOTCONN` if the channel is not connected.
+    pub fn read(&self, buf: &mut [u8]) -> BleResult<usize> {
+        let len = core::cmp::min(buf.len(), u16::max_value() as usize) as u16;
+        let mut read_len: u16 = 0;
+        check_ble(unsafe { ble_helper_coc_read(self.conn, buf.as_mut_ptr(), len, &mut read_len) }) ? ;
+        Ok(read_len as usize)
+    }
+
+    /// Send `data` as one SDU, at most the MTU of the phone, and return the number of bytes written. Returns `EBUSY`
+    /// if the data written before has not been sent yet, so write again after `CocEvent::Writable`.
+    /// Returns `ENOTCONN` if the channel is not connected.
+    pub fn write(&self, data: &[u8]) -> BleResult<usize> {
+        if data.len() > u16::max_value() as usize { return Err(BleError::EMSGSIZE); }
+        check_ble(unsafe { ble_helper_coc_write(self.conn, data.as_ptr(), data.len() as u16) }) ? ;
+        power_profile::add_radio(data.len());
+        Ok(data.len())
+    }
+
+    /// Disconnect the channel. `CocEvent::Disconnected` is passed to the function set by `listen()` when done.
+    pub fn close(&self) -> BleResult<()> {
+        check_ble(unsafe { ble_helper_coc_close(self.conn) })
+    }
+}
+
+/// L2CAP event types. Must sync with `BLE_HELPER_COC_*` in `ble_helper.h`.
+const BLE_HELPER_COC_CONNECTED:    u8 = 0;
+const BLE_HELPER_COC_DISCONNECTED: u8 = 1;
+const BLE_HELPER_COC_READABLE:     u8 = 2;
+const BLE_HELPER_COC_WRITABLE:     u8 = 3;
+
+/// Called by `ble_helper.c` with each flattened L2CAP event
+extern "C" fn coc_trampoline(info: *const ble_helper_coc_info, _arg: *mut ::cty::c_void) {
+    assert!(!info.is_null(), "null coc event");
+    let info = unsafe { &*info };
+    let chan = Channel { conn: info.conn_handle };
+    let event = match info.type_ {
+        BLE_HELPER_COC_CONNECTED    => CocEvent::Connected(chan),
+        BLE_HELPER_COC_DISCONNECTED => CocEvent::Disconnected(chan),
+        BLE_HELPER_COC_READABLE     => CocEvent::Readable(chan),
+        BLE_HELPER_COC_WRITABLE     => CocEvent::Writable(chan),
+        _ => return,
+    };
+    if let Some(func) = unsafe { EVENT_FUNC } { func(event); }
+}
+
+/// L2CAP event flattened by `ble_helper.c`. Must sync with `struct ble_helper_coc_info` in `ble_helper.h`.
+#[repr(C)]
+struct ble_helper_coc_info {
+    type_:       u8,
+    conn_handle: u16,
+}
+
+extern "C" {
+    /// Accept L2CAP connection-oriented channels on the PSM.
+    /// C API: `int ble_helper_coc_listen(uint16_t psm, uint16_t mtu, ble_helper_coc_fn *cb, void *arg)`
+    fn ble_helper_coc_listen(psm: u16, mtu: u16, cb: extern "C" fn(*const ble_helper_coc_info, *mut ::cty::c_void),
+        arg: *mut ::cty::c_void) -> i32;
+    /// Copy the received SDU and give credits to the peer when the SDU has been read.
+    /// C API: `int ble_helper_coc_read(uint16_t conn_handle, uint8_t *buf, uint16_t len, uint16_t *read_len)`
+    fn ble_helper_coc_read(conn_handle: u16, buf: *mut u8, len: u16, read_len: *mut u16) -> i32;
+    /// Send the data as one SDU.
+    /// C API: `int ble_helper_coc_write(uint16_t conn_handle, const uint8_t *data, uint16_t len)`
+    fn ble_helper_coc_write(conn_handle: u16, data: *const u8, len: u16) -> i32;
+    /// Disconnect the channel.
+    /// C API: `int ble_helper_coc_close(uint16_t conn_handle)`
+    fn ble_helper_coc_close(conn_handle: u16) -> i32;
+}
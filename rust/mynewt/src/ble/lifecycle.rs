@@ -0,0 +1,85 @@
+//! Connection lifecycle callbacks, so that the UI and network layers can react when a phone connects, disconnects
+//! or negotiates the ATT MTU, e.g. to pause the CoAP posts while a phone is syncing. The GAP events are queued by the
+//! NimBLE host, and the registered `ConnHandler`s are called later on the default event queue, outside the host
+//! callback, so they may call the host API, draw on the display or post network requests.
+//! ```
+//! struct PhoneHandler;
+//! impl ConnHandler for PhoneHandler {
+//!     fn on_connect(&self, conn: ConnHandle) { /* Show the Bluetooth icon */ }
+//!     fn on_disconnect(&self, conn: ConnHandle, reason: i32) { /* Hide the Bluetooth icon */ }
+//! }
+//! static PHONE_HANDLER: PhoneHandler = PhoneHandler;
+//! lifecycle::register(&PHONE_HANDLER) ? ;
+//! ```
+
+use crate::{
+    ble::{
+        gap::{ self, ConnHandle, GapEvent },
+        BleError, BleResult,
+    },
+    kernel::{ channel::Channel, os },
+};
+
+/// Max number of handlers that may be registered. Must match `MaxHandlers`.
+pub const MAX_HANDLERS: usize = 4;
+type MaxHandlers = heapless::consts::U4;
+
+/// Handler for the connection lifecycle events, called on the default event queue. The methods do nothing by
+/// default, so a handler implements only the events it needs.
+pub trait ConnHandler: Sync {
+    /// Called when a phone has connected
+    fn on_connect(&self, _conn: ConnHandle) {}
+    /// Called when the connection has been terminated for the reason, a HCI error code with the NimBLE base added
+    fn on_disconnect(&self, _conn: ConnHandle, _reason: i32) {}
+    /// Called when the ATT MTU of the connection has been negotiated with the phone
+    fn on_mtu_changed(&self, _conn: ConnHandle, _mtu: u16) {}
+}
+
+/// Connection lifecycle event queued for the handlers
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ConnEvent {
+    Connect(ConnHandle),
+    Disconnect(ConnHandle, i32),
+    MtuChanged(ConnHandle, u16),
+}
+
+/// Events pushed by the NimBLE host and popped on the default event queue
+static EVENTS: Channel<ConnEvent> = Channel::new();
+
+/// Registered handlers
+static mut HANDLERS: heapless::Vec<&'static dyn ConnHandler, MaxHandlers> = heapless::Vec(heapless::i::Vec::new());
+
+/// Call `handler` with the lifecycle events of all connections. Must be called before the host starts, e.g. in
+/// `main()`. Returns `ENOMEM` if there are more than `MAX_HANDLERS` handlers, `ENOTSUP` if Bluetooth LE is disabled.
+pub fn register(handler: &'static dyn ConnHandler) -> BleResult<()> {
+    let first = unsafe { HANDLERS.is_empty() };
+    unsafe { HANDLERS.push(handler) }.map_err(|_| BleError::ENOMEM) ? ;
+    if !first { return Ok(()); }
+    EVENTS.notify(os::eventq_dflt_get().expect("eventq fail"), dispatch_events);
+    gap::listen(queue_event)
+}
+
+/// Queue the connection lifecycle events. Called by the NimBLE host with the GAP events of all connections.
+fn queue_event(event: &GapEvent) {
+    let event = match *event {
+        GapEvent::Connect { conn, status: 0 } => ConnEvent::Connect(conn),
+        GapEvent::Disconnect { conn, reason } => ConnEvent::Disconnect(conn, reason),
+        GapEvent::Mtu { conn, mtu }           => ConnEvent::MtuChanged(conn, mtu),
+        _ => return,
+    };
+    //  Drop the event if the default event queue has fallen behind by `CHANNEL_SIZE` events.
+    EVENTS.push(event).ok();
+}
+
+/// Pass the queued events to the handlers. Called by the default event queue after each push.
+extern "C" fn dispatch_events(_ev: *mut os::os_event) {
+    while let Some(event) = EVENTS.pop() {
+        for handler in unsafe { HANDLERS.iter() } {
+            match event {
+                ConnEvent::Connect(conn)            => handler.on_connect(conn),
+                ConnEvent::Disconnect(conn, reason) => handler.on_disconnect(conn, reason),
+                ConnEvent::MtuChanged(conn, mtu)    => handler.on_mtu_changed(conn, mtu),
+            }
+        }
+    }
+}
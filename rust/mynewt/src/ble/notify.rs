@@ -0,0 +1,231 @@
+//! Stream readings to the connected phones as notifications of a characteristic. A `Stream` follows the
+//! subscriptions to the characteristic in `conns`, queues the records pushed by the sensors, and sends them as
+//! notifications to every subscriber while the link layer has buffers for them. The buffers are shared by all
+//! connections, so the notifications in flight are counted across the subscribers. When the link is congested, records are packed into the queued notifications, and
+//! the oldest notification is dropped when the queue is full, since the latest readings matter more for streaming.
+//! ```
+//! static ACCEL_STREAM: Stream = Stream::new(accel_val_handle, 0);  //  0 to use the number of link layer buffers
+//! ACCEL_STREAM.register() ? ;
+//! ACCEL_STREAM.push(&record).ok();  //  Ignore the error if no phone has subscribed
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    ble::{
+        conns,
+        gap::{ self, GapEvent },
+        gatt, BleError, BleResult,
+    },
+    kernel::os,
+};
+
+/// Max size of a notification: the ATT payload for the default MTU of 23 bytes
+pub const MAX_PACKET: usize = 20;
+
+/// Max number of notifications queued per stream
+pub const QUEUE_LEN: usize = 8;
+
+/// Max number of streams that may be registered. Must match `MaxStreams`.
+pub const MAX_STREAMS: usize = 4;
+type MaxStreams = heapless::consts::U4;
+
+/// Notifications queued for a subscribed connection
+pub struct Stream {
+    /// Function that returns the handle of the characteristic value, which is known after the host starts
+    val_handle: fn() -> u16,
+    /// Queue and flow control state, accessed in a critical section
+    state: UnsafeCell<State>,
+}
+
+/// State of a `Stream`
+struct State {
+    /// Max number of notifications in the link layer, 0 to use the number of ACL buffers
+    max_in_flight: u8,
+    /// Number of notifications in the link layer that have not been reported by `NotifyTx`
+    in_flight: u8,
+    /// Queued notifications, a ring buffer of `count` notifications from `head`
+    packets: [[u8; MAX_PACKET]; QUEUE_LEN],
+    /// Length of each queued notification
+    lens: [u8; QUEUE_LEN],
+    /// Index of the oldest queued notification
+    head: usize,
+    /// Number of queued notifications
+    count: usize,
+    /// Number of notifications dropped because the queue was full
+    dropped: u32,
+}
+
+/// `Stream` is accessed by the sensor tasks and the NimBLE host task in critical sections
+unsafe impl Sync for Stream {}
+
+/// Streams that receive the GAP events
+static mut STREAMS: heapless::Vec<&'static Stream, MaxStreams> = heapless::Vec(heapless::i::Vec::new());
+
+impl Stream {
+    /// Create a stream for the characteristic whose value handle is returned by `val_handle`. At most
+    /// `max_in_flight` notifications are given to the link layer at a time, or the number of link layer buffers if 0.
+    pub const fn new(val_handle: fn() -> u16, max_in_flight: u8) -> Self {
+        Stream {
+            val_handle,
+            state: UnsafeCell::new(State {
+                max_in_flight,
+                in_flight: 0,
+                packets: [[0; MAX_PACKET]; QUEUE_LEN],
+                lens: [0; QUEUE_LEN],
+                head: 0,
+                count: 0,
+                dropped: 0,
+            }),
+        }
+    }
+
+    /// Register the stream to follow the subscriptions and the notifications sent. Returns `ENOMEM` if there are
+    /// more than `MAX_STREAMS` streams, `ENOTSUP` if Bluetooth LE is disabled.
+    pub fn register(&'static self) -> BleResult<()> {
+        self.with_state(|state| {
+            if state.max_in_flight == 0 { state.max_in_flight = unsafe { ble_helper_acl_buf_count() }; }
+        });
+        unsafe { STREAMS.push(self) }.map_err(|_| BleError::ENOMEM) ? ;
+        //  Follow the subscriptions before the streams see the GAP events.
+        conns::start() ? ;
+        //  Listen to the GAP events once for all streams.
+        if unsafe { STREAMS.len() } > 1 { return Ok(()); }
+        gap::listen(handle_events)
+    }
+
+    /// Queue the record and send the queued notifications. The record is appended to the last queued notification
+    /// if it fits. Returns `ENOTCONN` if no phone has subscribed, `EINVAL` if the record is longer than `MAX_PACKET`.
+    pub fn push(&self, record: &[u8]) -> BleResult<()> {
+        if record.len() > MAX_PACKET { return Err(BleError::EINVAL); }
+        if !self.is_subscribed() { return Err(BleError::ENOTCONN); }
+        self.with_state(|state| {
+            if state.count > 0 {
+                let tail = (state.head + state.count - 1) % QUEUE_LEN;
+                let len = state.lens[tail] as usize;
+                if len + record.len() <= MAX_PACKET {
+                    state.packets[tail][len..len + record.len()].copy_from_slice(record);
+                    state.lens[tail] = (len + record.len()) as u8;
+                    return Ok(());
+                }
+            }
+            if state.count == QUEUE_LEN {
+                //  Drop the oldest notification.
+                state.head = (state.head + 1) % QUEUE_LEN;
+                state.count -= 1;
+                state.dropped += 1;
+            }
+            let tail = (state.head + state.count) % QUEUE_LEN;
+            state.packets[tail][..record.len()].copy_from_slice(record);
+            state.lens[tail] = record.len() as u8;
+            state.count += 1;
+            Ok(())
+        }) ? ;
+        self.send_queued();
+        Ok(())
+    }
+
+    /// Return the number of notifications dropped because the queue was full
+    pub fn dropped(&self) -> u32 {
+        self.with_state(|state| state.dropped)
+    }
+
+    /// Return true if a phone has subscribed to the notifications
+    pub fn is_subscribed(&self) -> bool {
+        !conns::subscribers((self.val_handle)()).is_empty()
+    }
+
+    /// Send the queued notifications to the subscribers while the link layer has buffers. A notification that can't
+    /// be sent to any subscriber for lack of buffers is queued again and sent after the next `NotifyTx` event.
+    fn send_queued(&self) {
+        let val_handle = (self.val_handle)();
+        let subscribers = conns::subscribers(val_handle);
+        if subscribers.is_empty() { return; }
+        loop {
+            //  Take the oldest notification, if the link layer has a buffer for it.
+            let mut packet = [0u8; MAX_PACKET];
+            let taken = self.with_state(|state| {
+                if state.count == 0 || state.in_flight >= state.max_in_flight { return None; }
+                let len = state.lens[state.head] as usize;
+                packet[..len].copy_from_slice(&state.packets[state.head][..len]);
+                state.head = (state.head + 1) % QUEUE_LEN;
+                state.count -= 1;
+                state.in_flight += subscribers.len() as u8;
+                Some(len)
+            });
+            let len = match taken { Some(len) => len, None => return };
+            //  Send outside the critical section, since NimBLE may block.
+            let mut sent = 0;
+            let mut busy = false;
+            for conn in subscribers.iter() {
+                match gatt::notify(*conn, val_handle, &packet[..len]) {
+                    Ok(()) => sent += 1,
+                    Err(BleError::ENOMEM) | Err(BleError::EAGAIN) | Err(BleError::EBUSY) => busy = true,
+                    Err(_) => {}  //  Skip the phone if it has disconnected
+                }
+            }
+            let unsent = subscribers.len() - sent;
+            self.with_state(|state| {
+                state.in_flight = state.in_flight.saturating_sub(unsent as u8);
+                if unsent > 0 && !(sent == 0 && busy) { state.dropped += 1; }
+            });
+            if sent == 0 && busy { self.requeue(&packet[..len]); }
+            if unsent > 0 { return; }
+        }
+    }
+
+    /// Put the notification back at the front of the queue, unless the queue has filled up in the meantime
+    fn requeue(&self, packet: &[u8]) {
+        self.with_state(|state| {
+            if state.count == QUEUE_LEN { state.dropped += 1; return; }
+            state.head = (state.head + QUEUE_LEN - 1) % QUEUE_LEN;
+            state.packets[state.head][..packet.len()].copy_from_slice(packet);
+            state.lens[state.head] = packet.len() as u8;
+            state.count += 1;
+        });
+    }
+
+    /// Clear the queue when the last subscriber leaves, and release the link layer buffers of the notifications sent.
+    /// The subscriptions have been updated by `conns` before.
+    fn handle_event(&self, event: &GapEvent) {
+        let val_handle = (self.val_handle)();
+        match *event {
+            GapEvent::Subscribe { attr, .. } if attr == val_handle => self.clear_if_unsubscribed(),
+            GapEvent::Disconnect { .. } => self.clear_if_unsubscribed(),
+            GapEvent::NotifyTx { attr, .. } if attr == val_handle => {
+                self.with_state(|state| state.in_flight = state.in_flight.saturating_sub(1));
+                self.send_queued();
+            }
+            _ => {}
+        }
+    }
+
+    /// Drop the queued notifications if no phone has subscribed
+    fn clear_if_unsubscribed(&self) {
+        if self.is_subscribed() { return; }
+        self.with_state(|state| {
+            state.in_flight = 0;
+            state.count = 0;
+        });
+    }
+
+    /// Call `func` with the state, with interrupts disabled
+    fn with_state<R, F: FnOnce(&mut State) -> R>(&self, func: F) -> R {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let result = func(unsafe { &mut *self.state.get() });
+        unsafe { os::os_arch_restore_sr(sr) };
+        result
+    }
+}
+
+/// Pass the GAP events of all connections to the registered streams
+fn handle_events(event: &GapEvent) {
+    for stream in unsafe { STREAMS.iter() } {
+        stream.handle_event(event);
+    }
+}
+
+extern "C" {
+    /// Return the number of ACL data buffers in the controller.
+    /// C API: `uint8_t ble_helper_acl_buf_count(void)`
+    fn ble_helper_acl_buf_count() -> u8;
+}
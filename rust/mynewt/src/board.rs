@@ -0,0 +1,5 @@
+//! Board support: the pin map and peripheral ports of each supported board, so that the drivers take their pins
+//! from one place instead of numeric literals.
+
+/// PineTime smart watch by PINE64
+pub mod pinetime;  // Export `board/pinetime.rs` as Rust module `mynewt::board::pinetime`
@@ -0,0 +1,156 @@
+//! PineTime pin map, from the PineTime schematic and `hw/bsp/nrf52`. Every pin is on GPIO port 0 of the nRF52832,
+//! so `P0.23` is Mynewt pin 23. The drivers take their pins from here, either as a typed `Pin` or configured by the
+//! constructors below, so that the pin and its active level are defined once.
+//! ```
+//! let mut vibrator = pinetime::vibrator() ? ;   //  Configured off
+//! vibrator.set_level(pinetime::VIBRATOR_ON);    //  Start vibrating
+//! let mut button = pinetime::button(handle_button) ? ;
+//! button.enable();
+//! ```
+//! The C drivers are configured in `hw/bsp/nrf52/syscfg.yml`, which must match this map: `SPIFLASH_SPI_CS_PIN` is
+//! `FLASH_CS`, and `BATTERY_CHARGE_PIN` is `CHARGE_DETECT`.
+
+use crate::{
+    hal::{
+        gpio::{ Edge, Input, Interrupt, Level, Output, PinEvent, Pull },
+        spi::{ Mode, SpiConfig },
+    },
+    result::*,
+};
+
+/// nRF52832 GPIO pin `P0.nn` of the PineTime. Only defined by this module.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pin(i32);
+
+impl Pin {
+    /// Return the Mynewt GPIO pin number, e.g. 23 for `P0.23`
+    pub const fn number(self) -> i32 { self.0 }
+}
+
+impl From<Pin> for i32 {
+    fn from(pin: Pin) -> i32 { pin.0 }
+}
+
+/// SPI port shared by the ST7789 display controller and the SPI Flash: SPIM0. Use `hal::spi::bus(SPI_NUM)` to
+/// share it.
+pub const SPI_NUM: i32 = 0;
+/// SPI_SCK (P0.02): SPI clock
+pub const SPI_SCK:  Pin = Pin(2);
+/// SPI_MOSI (P0.03): SPI data out
+pub const SPI_MOSI: Pin = Pin(3);
+/// SPI_MISO (P0.04): SPI data in, from the Flash only
+pub const SPI_MISO: Pin = Pin(4);
+
+/// LCD_CS (P0.25): Display chip select, active when low
+pub const LCD_CS:    Pin = Pin(25);
+/// LCD_RS (P0.18): Display command when low, data when high
+pub const LCD_DC:    Pin = Pin(18);
+/// LCD_RESET (P0.26): Display reset, active when low
+pub const LCD_RESET: Pin = Pin(26);
+
+/// SPI configuration of the display: mode 3 at 8 MHz, the fastest on nRF52832. Mode 0 won't work.
+pub const DISPLAY_SPI_CONFIG: SpiConfig = SpiConfig { mode: Mode::Mode3, freq_khz: 8000, lsb_first: false };
+
+/// SPI-CE# (P0.05): SPI Flash chip select, active when low
+pub const FLASH_CS: Pin = Pin(5);
+
+/// SPI configuration of the SPI Flash, as configured by the Mynewt SPI Flash driver: mode 3 at `SPIFLASH_BAUDRATE`
+pub const FLASH_SPI_CONFIG: SpiConfig = SpiConfig { mode: Mode::Mode3, freq_khz: 8000, lsb_first: false };
+
+/// Backlight brightness. Each level has its own pin, and the levels may be combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backlight {
+    /// LCD_BACKLIGHT_LOW (P0.14)
+    Low,
+    /// LCD_BACKLIGHT_MID (P0.22)
+    Mid,
+    /// LCD_BACKLIGHT_HIGH (P0.23)
+    High,
+}
+
+impl Backlight {
+    /// Return the pin of the brightness level
+    pub const fn pin(self) -> Pin {
+        match self {
+            Backlight::Low  => Pin(14),
+            Backlight::Mid  => Pin(22),
+            Backlight::High => Pin(23),
+        }
+    }
+}
+
+/// Levels that switch a backlight pin on and off
+pub const BACKLIGHT_ON:  Level = Level::Low;
+pub const BACKLIGHT_OFF: Level = Level::High;
+
+/// Touch controller I2C port: TWI1
+pub const TOUCH_I2C_NUM: u8 = 1;
+/// Touch controller I2C address: https://github.com/lupyuen/hynitron_i2c_cst0xxse
+pub const TOUCH_I2C_ADDRESS: u8 = 0x15;
+/// TP_INT (P0.28/AIN4): Touch interrupt, pulsed low for each touch
+pub const TOUCH_INT:   Pin = Pin(28);
+/// TP_RESET (P0.10/NFC2): Touch controller reset, active when low. Needs `NFC_PINS_AS_GPIO: 1` in `syscfg.yml`.
+pub const TOUCH_RESET: Pin = Pin(10);
+
+/// PUSH_BUTTON_IN (P0.13): High when the button is pressed, floating when released
+pub const BUTTON_IN:  Pin = Pin(13);
+/// PUSH_BUTTON_OUT (P0.15): Must be high to power the button
+pub const BUTTON_OUT: Pin = Pin(15);
+
+/// MOTOR (P0.16): Vibration motor
+pub const VIBRATOR: Pin = Pin(16);
+/// Levels that switch the vibration motor on and off
+pub const VIBRATOR_ON:  Level = Level::Low;
+pub const VIBRATOR_OFF: Level = Level::High;
+
+/// UART port for the debug console in `sys/uart_console.rs`: UARTE0. The PineTime has no UART connector, so
+/// `UART_0_PIN_TX` and `UART_0_PIN_RX` must be set to free pins of a dev kit before enabling `UART_0`.
+pub const UART_NUM: i32 = 0;
+
+/// CHARGE INDICATION (P0.12): Open drain, low while the battery is charging
+pub const CHARGE_DETECT: Pin = Pin(12);
+/// POWER PRESENCE INDICATION (P0.19): Low while the charger supplies power, e.g. on the cradle
+pub const POWER_PRESENT: Pin = Pin(19);
+
+/// Configure the backlight pin of `level`, switched on or off
+pub fn backlight(level: Backlight, on: bool) -> MynewtResult<Output> {
+    Output::new(level.pin().number(), if on { BACKLIGHT_ON } else { BACKLIGHT_OFF })
+}
+
+/// Configure the vibration motor, switched off
+pub fn vibrator() -> MynewtResult<Output> {
+    Output::new(VIBRATOR.number(), VIBRATOR_OFF)
+}
+
+/// Configure the touch controller reset pin, not resetting
+pub fn touch_reset() -> MynewtResult<Output> {
+    Output::new(TOUCH_RESET.number(), Level::High)
+}
+
+/// Attach `handler` to the touch interrupt. Call `enable()` on the returned `Interrupt` to start.
+pub fn touch_interrupt(handler: fn(PinEvent)) -> MynewtResult<Interrupt> {
+    Interrupt::attach(TOUCH_INT.number(), Edge::Falling, Pull::Up, handler)
+}
+
+/// Power the button and attach `handler` to its presses and releases. Call `enable()` on the returned `Interrupt`
+/// to start. The power pin stays high after the `Output` is dropped.
+pub fn button(handler: fn(PinEvent)) -> MynewtResult<Interrupt> {
+    Output::new(BUTTON_OUT.number(), Level::High) ? ;
+    Interrupt::attach(BUTTON_IN.number(), Edge::Both, Pull::Down, handler)
+}
+
+/// Configure the charge indicator, with a pull up since it's open drain
+pub fn charge_detect() -> MynewtResult<Input> {
+    Input::new(CHARGE_DETECT.number(), Pull::Up)
+}
+
+/// Attach `handler` to the changes of the charge indicator. Call `enable()` on the returned `Interrupt` to start.
+pub fn charge_interrupt(handler: fn(PinEvent)) -> MynewtResult<Interrupt> {
+    Interrupt::attach(CHARGE_DETECT.number(), Edge::Both, Pull::Up, handler)
+}
+
+/// Attach `handler` to the changes of the power presence indicator. Call `enable()` on the returned `Interrupt`
+/// to start.
+pub fn power_present_interrupt(handler: fn(PinEvent)) -> MynewtResult<Interrupt> {
+    Interrupt::attach(POWER_PRESENT.number(), Edge::Both, Pull::Up, handler)
+}
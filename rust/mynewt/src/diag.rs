@@ -0,0 +1,4 @@
+//! Mynewt Diagnostics API for Rust, for checking the health of long-running devices in the field
+
+/// Heap, mbuf and memory pool usage
+pub mod memory;  // Export `diag/memory.rs` as Rust module `mynewt::diag::memory`
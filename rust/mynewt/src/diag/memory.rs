@@ -0,0 +1,157 @@
+//! Runtime memory introspection, for spotting leaks and fragmentation before they stop a long-running device.
+//! - `heap()` returns the free bytes and the largest free block of the Mynewt heap used by `os_malloc()`. baselibc
+//!   only takes memory from `_sbrk()` when no free block fits, so the space above the break is counted as free.
+//!   A large free total with a small largest block means that the heap is fragmented.
+//! - `msys()` returns the usage of the system mbuf pools, which carry the Bluetooth LE and CoAP packets.
+//! - `pools()` returns the occupancy of each Mynewt memory pool, including the lowest number of free blocks since
+//!   startup, which shows how close the pool came to running out.
+//! - With the `alloc` feature, `kernel::heap::heap_stats()` returns the bytes allocated by Rust.
+//!
+//! `show()` prints all of them on the console, e.g. for the shell command `mem` in
+//! `apps/my_sensor_app/src/memory_shell.c`. `rust/app/src/memory_report.rs` sends them to the CoAP server.
+
+use core::fmt::Write;
+use crate::{
+    kernel::os,
+    sys::console,
+};
+
+/// Max number of memory pools returned by `pools()`. Must match `MaxPools`.
+pub const MAX_POOLS: usize = 12;
+type MaxPools = heapless::consts::U12;
+
+/// Max length of a memory pool name, from `OS_MEMPOOL_INFO_NAME_LEN`
+type MaxPoolName = heapless::consts::U32;
+
+/// Usage of the Mynewt heap
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapInfo {
+    /// Size of the heap between `__HeapBase` and `__HeapLimit`, in bytes
+    pub size: usize,
+    /// Free bytes, including the space that has not been taken with `_sbrk()`
+    pub free: usize,
+    /// Size of the largest free block, the largest allocation that will succeed
+    pub largest_free: usize,
+}
+
+impl HeapInfo {
+    /// Return the bytes in use, including the overhead of the allocator
+    pub fn used(&self) -> usize {
+        self.size.saturating_sub(self.free)
+    }
+
+    /// Return the fragmentation in percent: 0 if all free bytes are in one block, near 100 if the free bytes are
+    /// scattered in small blocks
+    pub fn fragmentation(&self) -> u8 {
+        if self.free == 0 { return 0; }
+        (100 - self.largest_free * 100 / self.free) as u8
+    }
+}
+
+/// Usage of the system mbuf pools
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MsysInfo {
+    /// Number of mbufs in all system mbuf pools
+    pub blocks: u32,
+    /// Number of free mbufs
+    pub free: u32,
+}
+
+/// Occupancy of a Mynewt memory pool
+#[derive(Clone, Debug, Default)]
+pub struct PoolInfo {
+    /// Name of the pool, e.g. `msys_1`
+    pub name: heapless::String<MaxPoolName>,
+    /// Size of each block, in bytes
+    pub block_size: u32,
+    /// Number of blocks in the pool
+    pub blocks: u32,
+    /// Number of free blocks
+    pub free: u32,
+    /// Lowest number of free blocks since startup
+    pub min_free: u32,
+}
+
+/// Return the usage of the Mynewt heap
+pub fn heap() -> HeapInfo {
+    let (mut free, mut largest_free) = (0, 0);
+    unsafe { get_malloc_memory_status(&mut free, &mut largest_free) };
+    let (base, limit) = unsafe { (&__HeapBase as *const u8 as usize, &__HeapLimit as *const u8 as usize) };
+    let brk = unsafe { _sbrk(0) } as usize;
+    let top = limit.saturating_sub(brk);  //  Not taken by baselibc yet
+    HeapInfo {
+        size:         limit - base,
+        free:         free + top,
+        largest_free: core::cmp::max(largest_free, top),
+    }
+}
+
+/// Return the usage of the system mbuf pools
+pub fn msys() -> MsysInfo {
+    unsafe {
+        MsysInfo {
+            blocks: os::os_msys_count() as u32,
+            free:   os::os_msys_num_free() as u32,
+        }
+    }
+}
+
+/// Return the occupancy of the Mynewt memory pools, in order of creation. Pools after the first `MAX_POOLS` pools
+/// are not returned.
+pub fn pools() -> heapless::Vec<PoolInfo, MaxPools> {
+    let mut pools = heapless::Vec::new();
+    let mut pool: *mut os::os_mempool = core::ptr::null_mut();
+    loop {
+        let mut info = os::os_mempool_info::default();
+        pool = unsafe { os::os_mempool_info_get_next(pool, &mut info) };
+        if pool.is_null() { break; }
+        let mut name = heapless::String::new();
+        for c in info.omi_name.iter().take_while(|c| **c != 0) {
+            if name.push(*c as u8 as char).is_err() { break; }
+        }
+        let info = PoolInfo {
+            name,
+            block_size: info.omi_block_size as u32,
+            blocks:     info.omi_num_blocks as u32,
+            free:       info.omi_num_free as u32,
+            min_free:   info.omi_min_free as u32,
+        };
+        if pools.push(info).is_err() { break; }
+    }
+    pools
+}
+
+/// Display the heap, mbuf and memory pool usage on the console
+pub fn show() {
+    let heap = heap();
+    let msys = msys();
+    let mut line = heapless::String::<heapless::consts::U80>::new();
+    write!(line, "os heap: {} used, {} free, largest {}, frag {}%\n",
+        heap.used(), heap.free, heap.largest_free, heap.fragmentation()).ok();
+    console::print(&line);
+    #[cfg(feature = "alloc")]  //  If the global allocator is enabled...
+    crate::kernel::heap::heap_stats().show();
+    line.clear();
+    write!(line, "msys: {} of {} mbufs free\n", msys.free, msys.blocks).ok();
+    console::print(&line);
+    for pool in pools().iter() {
+        line.clear();
+        write!(line, "pool {}: {} of {} free, min {}, {} bytes\n",
+            pool.name.as_str(), pool.free, pool.blocks, pool.min_free, pool.block_size).ok();  //  Truncated if too long
+        console::print(&line);
+    }
+    console::flush();
+}
+
+extern "C" {
+    /// Return the free bytes and the largest free block of the baselibc heap, locking the heap.
+    /// C API: `void get_malloc_memory_status(size_t *free_bytes, size_t *largest_block)` in `libc/baselibc`
+    fn get_malloc_memory_status(free_bytes: *mut usize, largest_block: *mut usize);
+    /// Move the end of the heap by `incr` bytes and return the previous end. `_sbrk(0)` returns the end.
+    /// C API: `void *_sbrk(int incr)` in `hw/mcu/nordic/nrf52xxx/src/sbrk.c`
+    fn _sbrk(incr: i32) -> *mut u8;
+    /// Start of the heap, defined in `hw/mcu/nordic/nrf52xxx/nrf52.ld`
+    static __HeapBase: u8;
+    /// End of the heap, defined in `hw/mcu/nordic/nrf52xxx/nrf52.ld`
+    static __HeapLimit: u8;
+}
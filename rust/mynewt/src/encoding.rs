@@ -14,7 +14,7 @@ pub mod tinycbor;         //  Export `tinycbor.rs` as Rust module `mynewt::encod
 pub mod coap_context;     //  Export `coap_context.rs` as Rust module `mynewt::encoding::coap_context`
 
 /// CBOR encoders defined in repos/apache-mynewt-core/net/oic/src/api/oc_rep.c
-#[link(name = "net_oic")]
+#[cfg_attr(not(feature = "sim"), link(name = "net_oic"))]  //  Mocked by `mock.rs` for host testing
 extern {
     /// Global CBOR encoder
     pub static mut g_encoder: tinycbor::CborEncoder;
@@ -6,7 +6,7 @@ use crate::{
     sys::console,
     encoding::{
         //json,                   //  Mynewt JSON encoding library
-        tinycbor::CborEncoder,  //  Mynewt CBOR encoding library
+        tinycbor::{ self, CborEncoder },  //  Mynewt CBOR encoding library
     },
     libs::mynewt_rust,          //  JSON encoding helper library
     //libs::sensor_coap,
@@ -86,6 +86,62 @@ impl CoapContext {
         };
     }
 
+    ///  Encode a sensor value of any type into the current JSON document with the specified key.
+    ///  Byte strings are encoded as hex strings, since JSON has no byte strings.
+    pub fn json_set_value(&mut self, key: &[u8], value: SensorValueType) {
+        let notused = self.to_void_ptr();
+        let key_cstr = self.key_to_cstr(key) as *const c_char;
+        match value {
+            SensorValueType::Uint(val) => unsafe { mynewt_rust::json_helper_set_uint(notused, key_cstr, val as u64) },
+            //  Mynewt JSON encodes the int as signed 64-bit.
+            SensorValueType::Int(val)  => unsafe { mynewt_rust::json_helper_set_int(notused, key_cstr, val as i64 as u64) },
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            SensorValueType::Float(val) => unsafe { mynewt_rust::json_helper_set_float(notused, key_cstr, val) },
+            SensorValueType::Str(val) => {
+                let value_cstr = self.value_strn_to_cstr(val) as *const c_char;
+                unsafe { mynewt_rust::json_helper_set_text_string(notused, key_cstr, value_cstr) };
+            }
+            SensorValueType::Bytes(val) => {
+                let value_cstr = self.value_to_hex_cstr(val) as *const c_char;
+                unsafe { mynewt_rust::json_helper_set_text_string(notused, key_cstr, value_cstr) };
+            }
+            _ => self.fail(CoapError::VALUE_NOT_SUPPORTED),  //  No value, or geolocation which is encoded as `geo`
+        }
+    }
+
+    ///  Encode a sensor value of any type with the specified key into the CBOR map `encoder`
+    pub fn cbor_set_value(&mut self, encoder: *mut CborEncoder, key: &[u8], value: SensorValueType) {
+        let key_cstr = self.key_to_cstr(key) as *const c_char;
+        let res = unsafe { tinycbor::cbor_encode_text_string(encoder, key_cstr, self.cstr_len(key)) };
+        self.check_result(res);
+        let res = match value {
+            SensorValueType::Uint(val) => unsafe { tinycbor::cbor_encode_uint(encoder, val as u64) },
+            SensorValueType::Int(val)  => unsafe { tinycbor::cbor_encode_int(encoder, val as i64) },
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            SensorValueType::Float(val) => unsafe { tinycbor::cbor_encode_floating_point(
+                encoder, tinycbor::CborType_CborFloatType, &val as *const f32 as *const c_void) },
+            SensorValueType::Str(val) => unsafe {
+                tinycbor::cbor_encode_text_string(encoder, val.as_ptr() as *const c_char, val.len()) },
+            SensorValueType::Bytes(val) => unsafe {
+                tinycbor::cbor_encode_byte_string(encoder, val.as_ptr(), val.len()) },
+            _ => { self.fail(CoapError::VALUE_NOT_SUPPORTED); 0 }  //  No value, or geolocation
+        };
+        self.check_result(res);
+    }
+
+    /// Given a byte string `bytes`, return a `*char` pointer to the bytes as a null-terminated hex string in the
+    /// static value buffer. Used for encoding byte strings in JSON.
+    fn value_to_hex_cstr(&mut self, bytes: &[u8]) -> *const u8 {
+        const HEX: &[u8] = b"0123456789abcdef";
+        assert!(bytes.len() * 2 < COAP_VALUE_SIZE, "big value");  //  Value too long
+        for (i, b) in bytes.iter().enumerate() {
+            self.value_buffer[i * 2]     = HEX[(b >> 4) as usize];
+            self.value_buffer[i * 2 + 1] = HEX[(b & 0xf) as usize];
+        }
+        self.value_buffer[bytes.len() * 2] = 0;
+        self.value_buffer.as_ptr() as *const u8
+    }
+
     /// Given a Strn key `key`, return a `*char` pointer that is null-terminated. Used for encoding COAP keys.
     /// If `key` is null-terminated, return it as a pointer. Else copy `key` to the static key buffer,
     /// append null and return the static key buffer as a pointer.
@@ -194,6 +250,8 @@ pub enum CoapError {
     OK = 0,
     /// Encoded value is not unsigned integer
     VALUE_NOT_UINT = 1,
+    /// Encoded value has no encoding, e.g. `SensorValueType::None`
+    VALUE_NOT_SUPPORTED = 2,
 }
 
 /// Implement formatted output for CoapError
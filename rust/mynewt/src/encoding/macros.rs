@@ -155,7 +155,7 @@ macro_rules! parse {
   // JSON Encoding: Encode as `{key:..., value:...}`. 
   (@json @object $object:ident ($($key:tt)+) () $copy:tt) => {
     "--------------------";
-    $crate::coap_item_int_val!(@json
+    $crate::coap_item_val!(@json
       $object,  //  _object, 
       $($key)+  //  _sensor_value
     );
@@ -165,7 +165,7 @@ macro_rules! parse {
   // CBOR Encoding: Encode as `{key:..., value:...}`. 
   (@cbor @object $object:ident ($($key:tt)+) () $copy:tt) => {
     "--------------------";
-    $crate::coap_item_int_val!(@cbor
+    $crate::coap_item_val!(@cbor
       $object,  //  _object, 
       $($key)+  //  _sensor_value
     );
@@ -175,7 +175,7 @@ macro_rules! parse {
   // CBOR Minimal Encoding: Encode as `{key: value}`. 
   (@cbormin @object $object:ident ($($key:tt)+) () $copy:tt) => {
     "--------------------";
-    $crate::coap_set_int_val!(@cbor
+    $crate::coap_set_val!(@cbor
       $object,  //  _object, 
       $($key)+  //  _sensor_value
     );
@@ -205,7 +205,7 @@ macro_rules! parse {
   // JSON Encoding: Encode as `{key:..., value:...}`. 
   (@json @object $object:ident ($($key:tt)*) (, $($rest:tt)*) ($comma:tt $($copy:tt)*)) => {
     "--------------------";
-    $crate::coap_item_int_val!(@json
+    $crate::coap_item_val!(@json
       $object,  //  _object, 
       $($key)*  //  _sensor_value
     );
@@ -217,7 +217,7 @@ macro_rules! parse {
   // CBOR Encoding: Encode as `{key:..., value:...}`. 
   (@cbor @object $object:ident ($($key:tt)*) (, $($rest:tt)*) ($comma:tt $($copy:tt)*)) => {
     "--------------------";
-    $crate::coap_item_int_val!(@cbor
+    $crate::coap_item_val!(@cbor
       $object,  //  _object, 
       $($key)*  //  _sensor_value
     );
@@ -229,7 +229,7 @@ macro_rules! parse {
   // CBOR Minimal Encoding: Encode as `{key: value}`. 
   (@cbormin @object $object:ident ($($key:tt)*) (, $($rest:tt)*) ($comma:tt $($copy:tt)*)) => {
     "--------------------";
-    $crate::coap_set_int_val!(@cbor
+    $crate::coap_set_val!(@cbor
       $object,  //  _object, 
       $($key)*  //  _sensor_value
     );
@@ -589,54 +589,58 @@ macro_rules! coap_item {
   }};
 }
 
-///  Given an object parent and an integer Sensor Value `val`, set the `val`'s key/value in the object.
+///  Given an object parent and a Sensor Value `val` of any type, set the `val`'s key/value in the object.
 #[macro_export]
-macro_rules! coap_set_int_val {
+macro_rules! coap_set_val {
   (@cbor $context:ident, $val0:expr) => {{  //  CBOR
-    d!(begin cbor coap_set_int_val, c: $context, val: $val0);
-    if let SensorValueType::Uint(val) = $val0.value {
-      $crate::oc_rep_set_int!($context, $val0.key, val);
-    } else {
-      unsafe { COAP_CONTEXT.fail(CoapError::VALUE_NOT_UINT) };  //  Value not uint
-    }
-    d!(end cbor coap_set_int_val);
+    d!(begin cbor coap_set_val, c: $context, val: $val0);
+    let val = $val0;
+    unsafe {
+      let encoder = COAP_CONTEXT.encoder(stringify!($context), _MAP);
+      COAP_CONTEXT.cbor_set_value(encoder, val.key.to_bytes_optional_nul(), val.value);
+    };
+    d!(end cbor coap_set_val);
   }};
 
   (@json $context:ident, $val0:expr) => {{  //  JSON
-    d!(begin json coap_set_int_val, c: $context, val: $val0);
-    if let SensorValueType::Uint(val) = $val0.value {
-      $crate::json_rep_set_int!($context, $val0.key, val);
-    } else {
-      unsafe { COAP_CONTEXT.fail(CoapError::VALUE_NOT_UINT) };  //  Value not uint
-    }
-    d!(end json coap_set_int_val);
+    d!(begin json coap_set_val, c: $context, val: $val0);
+    let val = $val0;
+    unsafe { COAP_CONTEXT.json_set_value(val.key.to_bytes_optional_nul(), val.value) };
+    d!(end json coap_set_val);
   }};
 }
 
-///  Encode Integer Sensor Value: Create a new Item object in the parent array and set the Sensor Value's key/value (integer).
+///  Encode Sensor Value of any type: Create a new Item object in the parent array and set the Sensor Value's key/value.
 ///  ` { ..., val0 } --> { values: [ ... , { key: val0.key, value: val0.value, geo: val0.geo }] } `
+///  The value may be a signed or unsigned integer, float, text string or byte string.
 #[macro_export]
-macro_rules! coap_item_int_val {
+macro_rules! coap_item_val {
   (@cbor $context:ident, $val0:expr) => {{  //  CBOR
-    d!(begin cbor coap_item_int_val, c: $context, val: $val0);
-    let geo = $val0.geo;
-    if let SensorValueType::Uint(val) = $val0.value {
-      $crate::coap_item_int!(@cbor $context, $val0.key, val, geo);
-    } else {
-      unsafe { COAP_CONTEXT.fail(CoapError::VALUE_NOT_UINT) };  //  Value not uint
-    }
-    d!(end cbor coap_item_int_val);
+    d!(begin cbor coap_item_val, c: $context, val: $val0);
+    let val = $val0;
+    $crate::coap_item!(@cbor $context, {
+      //  Set key and value: ` "key": <key0>, "value": <value0> `
+      $crate::oc_rep_set_text_string!($context, "key", val.key);
+      unsafe {
+        let encoder = COAP_CONTEXT.encoder(stringify!($context), _MAP);
+        COAP_CONTEXT.cbor_set_value(encoder, b"value", val.value);
+      };
+      //  TODO: Set geolocation: ` "geo": { "lat" : 41.4121132, "long" : 2.2199454 } `
+    });
+    d!(end cbor coap_item_val);
   }};
 
   (@json $context:ident, $val0:expr) => {{  //  JSON
-    d!(begin json coap_item_int_val, c: $context, val: $val0);
-    let geo = $val0.geo;
-    if let SensorValueType::Uint(val) = $val0.value {
-      $crate::coap_item_int!(@json $context, $val0.key, val, geo);
-    } else {
-      unsafe { COAP_CONTEXT.fail(CoapError::VALUE_NOT_UINT) };  //  Value not uint
-    }
-    d!(end json coap_item_int_val);
+    d!(begin json coap_item_val, c: $context, val: $val0);
+    let val = $val0;
+    $crate::coap_item!(@json $context, {
+      //  Set key and value: ` "key": <key0>, "value": <value0> `
+      $crate::json_rep_set_text_string!($context, "key", val.key);
+      unsafe { $context.json_set_value(b"value", val.value) };
+      //  Set geolocation: ` "geo": { "lat" : 41.4121132, "long" : 2.2199454 } `
+      unsafe { $context.json_set_geolocation(strn!("geo"), strn!("lat"), strn!("long"), val.geo) };
+    });
+    d!(end json coap_item_val);
   }};
 }
 
@@ -890,7 +894,7 @@ macro_rules! json_rep_set_text_string {
 macro_rules! oc_rep_start_root_object {
   ($obj:ident) => {{
     d!(begin oc_rep_start_root_object);
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let encoder = COAP_CONTEXT.encoder(_ROOT, _MAP);
       //  Previously: g_err |= cbor_encoder_create_map(&g_encoder, &root_map, CborIndefiniteLength)
       cbor_encoder_create_map(
@@ -907,7 +911,7 @@ macro_rules! oc_rep_start_root_object {
 macro_rules! oc_rep_end_root_object {
   ($obj:ident) => {{
     d!(begin oc_rep_end_root_object);
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let encoder = COAP_CONTEXT.encoder(_ROOT, _MAP);
       //  Previously: g_err |= cbor_encoder_close_container(&g_encoder, &root_map)
       cbor_encoder_close_container(
@@ -928,7 +932,7 @@ macro_rules! oc_rep_start_object {
       ", key: ",    stringify!($key),
       ", child: ",  stringify!($key), "_map"  //  key##_map
     );
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let parent_encoder = COAP_CONTEXT.encoder(
         stringify!($parent), 
         stringify!($parent_suffix)
@@ -958,7 +962,7 @@ macro_rules! oc_rep_end_object {
       ", key: ",    stringify!($key),
       ", child: ",  stringify!($key), "_map"  //  key##_map
     );
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let parent_encoder = COAP_CONTEXT.encoder(
         stringify!($parent), 
         stringify!($parent_suffix)
@@ -986,7 +990,7 @@ macro_rules! oc_rep_start_array {
       ", key: ",    stringify!($key),
       ", child: ",  stringify!($key), "_array"  //  key##_array
     );
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let parent_encoder = COAP_CONTEXT.encoder(
         stringify!($parent), 
         stringify!($parent_suffix)
@@ -1016,7 +1020,7 @@ macro_rules! oc_rep_end_array {
       ", key: ",    stringify!($key),
       ", child: ",  stringify!($key), "_array"  //  key##_array
     );
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let parent_encoder = COAP_CONTEXT.encoder(
         stringify!($parent), 
         stringify!($parent_suffix)
@@ -1050,7 +1054,7 @@ macro_rules! oc_rep_set_array {
     );
     //  Convert key to char array, which may or may not be null-terminated.
     let key_with_opt_null:   &[u8] = stringify!($key).to_bytes_optional_nul();
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let encoder = COAP_CONTEXT.encoder(
         stringify!($object), 
         _MAP
@@ -1058,7 +1062,7 @@ macro_rules! oc_rep_set_array {
       //  Previously: g_err |= cbor_encode_text_string(&object##_map, #key, strlen(#key))
       cbor_encode_text_string(
         encoder, 
-        COAP_CONTEXT.key_to_cstr(key_with_opt_null), 
+        COAP_CONTEXT.key_to_cstr(key_with_opt_null) as *const _,  //  `c_char` is `i8` on the host
         COAP_CONTEXT.cstr_len(key_with_opt_null)
       );
     });
@@ -1136,7 +1140,7 @@ macro_rules! oc_rep_set_int {
     //  Convert key to null-terminated char array. If key is `t`, convert to `"t\u{0}"`
     let key_with_null: &str = $crate::stringify_null!($key);
     let value = $value as i64;
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let encoder = COAP_CONTEXT.encoder(
         stringify!($obj), 
         _MAP
@@ -1144,7 +1148,7 @@ macro_rules! oc_rep_set_int {
       //  Previously: g_err |= cbor_encode_text_string(&object##_map, #key, strlen(#key))
       cbor_encode_text_string(
         encoder,
-        COAP_CONTEXT.key_to_cstr(key_with_null.as_bytes()),
+        COAP_CONTEXT.key_to_cstr(key_with_null.as_bytes()) as *const _,  //  `c_char` is `i8` on the host
         COAP_CONTEXT.cstr_len(key_with_null.as_bytes())
       );
       //  Previously: g_err |= cbor_encode_int(&object##_map, value)
@@ -1165,7 +1169,7 @@ macro_rules! oc_rep_set_int {
     //  Convert key to char array, which may or may not be null-terminated.
     let key_with_opt_null: &[u8] = $key.to_bytes_optional_nul();
     let value = $value as i64;
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let encoder = COAP_CONTEXT.encoder(
         stringify!($obj), 
         _MAP
@@ -1173,7 +1177,7 @@ macro_rules! oc_rep_set_int {
       //  Previously: g_err |= cbor_encode_text_string(&object##_map, #key, strlen(#key))
       cbor_encode_text_string(
         encoder,
-        COAP_CONTEXT.key_to_cstr(key_with_opt_null),
+        COAP_CONTEXT.key_to_cstr(key_with_opt_null) as *const _,  //  `c_char` is `i8` on the host
         COAP_CONTEXT.cstr_len(   key_with_opt_null)
       );
       //  Previously: g_err |= cbor_encode_int(&object##_map, value)
@@ -1199,7 +1203,7 @@ macro_rules! oc_rep_set_text_string {
     //  Convert key and value to char array, which may or may not be null-terminated.
     let key_with_opt_null:   &[u8] = $key.to_bytes_optional_nul();
     let value_with_opt_null: &[u8] = $value.to_bytes_optional_nul();
-    mynewt_macros::try_cbor!({
+    $crate::mynewt_macros::try_cbor!({
       let encoder = COAP_CONTEXT.encoder(
         stringify!($obj), 
         _MAP
@@ -1207,13 +1211,13 @@ macro_rules! oc_rep_set_text_string {
       //  Previously: g_err |= cbor_encode_text_string(&object##_map, #key, strlen(#key))
       cbor_encode_text_string(
         encoder, 
-        COAP_CONTEXT.key_to_cstr(key_with_opt_null), 
+        COAP_CONTEXT.key_to_cstr(key_with_opt_null) as *const _,  //  `c_char` is `i8` on the host
         COAP_CONTEXT.cstr_len(   key_with_opt_null)
       );
       //  Previously: g_err |= cbor_encode_text_string(&object##_map, value, strlen(value))
       cbor_encode_text_string(
         encoder, 
-        COAP_CONTEXT.value_to_cstr(value_with_opt_null), 
+        COAP_CONTEXT.value_to_cstr(value_with_opt_null) as *const _,  //  `c_char` is `i8` on the host
         COAP_CONTEXT.cstr_len(     value_with_opt_null)
       );
     });
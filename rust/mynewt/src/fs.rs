@@ -0,0 +1,305 @@
+//! File System API for the user area of the External SPI Flash, so that logos, fonts, settings and histories are
+//! managed as files instead of raw offsets. The region `map::USER_FS` is formatted with littlefs, which survives
+//! power loss in the middle of a write and spreads the erases over the sectors. `mount()` mounts the file system at
+//! startup and formats it on first use. littlefs is not thread-safe, so all operations are serialised by a mutex.
+//! ```
+//! fs::write("/logo.bin", &logo) ? ;
+//! let len = fs::read("/logo.bin", &mut buf) ? ;
+//! fs::open("/history.bin", OpenMode::Append, |file| file.write(&sample)) ? ;
+//! fs::for_each_entry("/", |entry| { console::print(entry.name()); Ok(()) }) ? ;
+//! ```
+//! Errors from littlefs are converted to `MynewtError`: `SYS_ENOENT` if the file doesn't exist, `SYS_ENOMEM` if
+//! the file system is full, `SYS_EIO` if the flash failed or the file system is corrupted.
+
+use littlefs2::{
+    consts::{ U16, U256 },
+    driver,
+    fs::{ Allocation, Filesystem },
+    io::{ self, Read, Seek, SeekFrom, Write },
+    path::PathBuf,
+};
+use crate::{
+    result::*,
+    hw::flash::map::{ self, Region, Storage },
+    kernel::sync::Mutex,
+};
+
+/// Size of a littlefs block. Equals the sector size of the SPI Flash, the unit of erasing.
+const BLOCK_SIZE: usize = 4096;
+
+/// Max length of a path. Must match `LFS_NAME_MAX` in littlefs.
+pub const PATH_MAX: usize = 255;
+
+/// Max length of a file name returned by `for_each_entry()`. Must match `PATH_MAX`.
+type MaxName = heapless::consts::U255;
+
+/// littlefs storage driver for a flash region
+pub struct FlashStorage {
+    /// Flash region of the file system
+    region: Region,
+}
+
+impl driver::Storage for FlashStorage {
+    const READ_SIZE:    usize = 16;
+    const WRITE_SIZE:   usize = 256;  //  SPI Flash page
+    const BLOCK_SIZE:   usize = BLOCK_SIZE;
+    const BLOCK_COUNT:  usize = map::USER_FS.size as usize / BLOCK_SIZE;
+    const BLOCK_CYCLES: isize = 500;  //  Move the metadata after 500 erases, to spread the wear
+    type CACHE_SIZE     = U256;
+    type LOOKAHEAD_SIZE = U16;
+
+    fn read(&mut self, off: usize, buf: &mut [u8]) -> io::Result<usize> {
+        self.region.read(off as u32, buf).map_err(|_| io::Error::Io) ? ;
+        Ok(buf.len())
+    }
+
+    fn write(&mut self, off: usize, data: &[u8]) -> io::Result<usize> {
+        self.region.write(off as u32, data).map_err(|_| io::Error::Io) ? ;
+        Ok(data.len())
+    }
+
+    fn erase(&mut self, off: usize, len: usize) -> io::Result<usize> {
+        self.region.erase(off as u32, len as u32).map_err(|_| io::Error::Io) ? ;
+        Ok(len)
+    }
+}
+
+/// How `open()` opens a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OpenMode {
+    /// Read the file. Fails with `SYS_ENOENT` if the file doesn't exist.
+    Read,
+    /// Create the file, or truncate it if it exists, and write from the start
+    Write,
+    /// Create the file if it doesn't exist, and write at the end
+    Append,
+    /// Create the file if it doesn't exist, and read and write from the start
+    ReadWrite,
+}
+
+/// File opened by `open()`
+pub struct File<'f, 'a, 'b> {
+    /// littlefs file
+    file: &'f littlefs2::fs::File<'a, 'b, FlashStorage>,
+}
+
+impl<'f, 'a, 'b> File<'f, 'a, 'b> {
+    /// Read into `buf` from the current position. Returns the number of bytes read, 0 at the end of the file.
+    pub fn read(&mut self, buf: &mut [u8]) -> MynewtResult<usize> {
+        Ok(self.file.read(buf) ? )
+    }
+
+    /// Write `data` at the current position
+    pub fn write(&mut self, data: &[u8]) -> MynewtResult<()> {
+        self.file.write_all(data) ? ;
+        Ok(())
+    }
+
+    /// Move the current position to `offset` bytes from the start
+    pub fn seek(&mut self, offset: u32) -> MynewtResult<()> {
+        self.file.seek(SeekFrom::Start(offset)) ? ;
+        Ok(())
+    }
+
+    /// Return the size of the file in bytes
+    pub fn len(&self) -> MynewtResult<u32> {
+        Ok(self.file.len() ? as u32)
+    }
+}
+
+/// Entry returned by `for_each_entry()`
+pub struct DirEntry {
+    /// Name of the file or directory, without the path
+    name: heapless::String<MaxName>,
+    /// Size of the file in bytes, 0 for a directory
+    pub size: u32,
+    /// True for a directory
+    pub is_dir: bool,
+}
+
+impl DirEntry {
+    /// Return the name of the file or directory, without the path
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Flash region of the file system
+static mut STORAGE: FlashStorage = FlashStorage { region: map::USER_FS };
+
+/// Buffers of littlefs, allocated by `mount()`
+static mut ALLOCATION: Option<Allocation<FlashStorage>> = None;
+
+/// Mounted file system, `None` before `mount()`
+static mut FILESYSTEM: Option<Filesystem<'static, FlashStorage>> = None;
+
+/// Serialises the operations on the file system
+static FS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Mount the file system, formatting it if the region doesn't hold a valid littlefs file system, e.g. on first use.
+/// Called by main() in `lib.rs` after `sysinit()`.
+pub fn mount() -> MynewtResult<()> {
+    let _lock = FS_LOCK.lock() ? ;
+    unsafe {
+        if FILESYSTEM.is_some() { return Ok(()); }
+        if !Filesystem::is_mountable(&mut STORAGE) {
+            log::warn!("fs: formatting");
+            Filesystem::format(&mut STORAGE) ? ;
+        }
+        let allocation = ALLOCATION.get_or_insert_with(Filesystem::allocate);
+        FILESYSTEM = Some(Filesystem::mount(allocation, &mut STORAGE) ? );
+    }
+    Ok(())
+}
+
+/// Erase all files and mount the empty file system
+pub fn format() -> MynewtResult<()> {
+    let _lock = FS_LOCK.lock() ? ;
+    unsafe {
+        FILESYSTEM = None;  //  Unmount
+        Filesystem::format(&mut STORAGE) ? ;
+        let allocation = ALLOCATION.get_or_insert_with(Filesystem::allocate);
+        FILESYSTEM = Some(Filesystem::mount(allocation, &mut STORAGE) ? );
+    }
+    Ok(())
+}
+
+/// Open the file at `path` and call `f` with the file, which is closed when `f` returns
+pub fn open<R, F>(path: &str, mode: OpenMode, f: F) -> MynewtResult<R>
+where F: FnOnce(&mut File) -> MynewtResult<R> {
+    let path = to_path(path) ? ;
+    with_fs(|fs| {
+        let mut result = None;
+        fs.open_file_with_options_and_then(
+            |options| match mode {
+                OpenMode::Read      => options.read(true),
+                OpenMode::Write     => options.write(true).create(true).truncate(true),
+                OpenMode::Append    => options.write(true).create(true).append(true),
+                OpenMode::ReadWrite => options.read(true).write(true).create(true),
+            },
+            &path,
+            |file| {
+                result = Some(f(&mut File { file }));
+                Ok(())
+            }
+        ) ? ;
+        result.unwrap_or(Err(MynewtError::SYS_EUNKNOWN))
+    })
+}
+
+/// Read the start of the file at `path` into `buf`. Returns the number of bytes read, less than `buf.len()` if the
+/// file is shorter.
+pub fn read(path: &str, buf: &mut [u8]) -> MynewtResult<usize> {
+    open(path, OpenMode::Read, |file| {
+        let mut len = 0;
+        while len < buf.len() {
+            let count = file.read(&mut buf[len..]) ? ;
+            if count == 0 { break; }  //  End of file
+            len += count;
+        }
+        Ok(len)
+    })
+}
+
+/// Replace the contents of the file at `path` by `data`, creating the file if it doesn't exist
+pub fn write(path: &str, data: &[u8]) -> MynewtResult<()> {
+    open(path, OpenMode::Write, |file| file.write(data))
+}
+
+/// Rename the file or directory at `from` to `to`, replacing the file at `to` if it exists. The rename is atomic,
+/// so write a new version of a file to a temporary file, then rename it over the old version.
+pub fn rename(from: &str, to: &str) -> MynewtResult<()> {
+    let (from, to) = (to_path(from) ? , to_path(to) ? );
+    with_fs(|fs| Ok(fs.rename(&from, &to) ? ))
+}
+
+/// Remove the file or the empty directory at `path`
+pub fn remove(path: &str) -> MynewtResult<()> {
+    let path = to_path(path) ? ;
+    with_fs(|fs| Ok(fs.remove(&path) ? ))
+}
+
+/// Create the directory at `path`. The parent directory must exist.
+pub fn create_dir(path: &str) -> MynewtResult<()> {
+    let path = to_path(path) ? ;
+    with_fs(|fs| Ok(fs.create_dir(&path) ? ))
+}
+
+/// Return the size of the file at `path` in bytes, or `SYS_ENOENT` if it doesn't exist
+pub fn size(path: &str) -> MynewtResult<u32> {
+    let path = to_path(path) ? ;
+    with_fs(|fs| Ok(fs.metadata(&path) ? .len() as u32))
+}
+
+/// Return true if a file or directory exists at `path`
+pub fn exists(path: &str) -> MynewtResult<bool> {
+    let path = to_path(path) ? ;
+    with_fs(|fs| Ok(fs.exists(&path)))
+}
+
+/// Call `f` with each file and directory in the directory at `path`, except `.` and `..`.
+/// Stops at the first error returned by `f`.
+pub fn for_each_entry<F>(path: &str, mut f: F) -> MynewtResult<()>
+where F: FnMut(&DirEntry) -> MynewtResult<()> {
+    let path = to_path(path) ? ;
+    with_fs(|fs| {
+        let mut result = Ok(());
+        fs.read_dir_and_then(&path, |entries| {
+            for entry in entries {
+                let entry = entry ? ;
+                let name = entry.file_name().as_str();
+                if name == "." || name == ".." { continue; }
+                let metadata = entry.metadata();
+                let mut dir_entry = DirEntry {
+                    name:   heapless::String::new(),
+                    size:   metadata.len() as u32,
+                    is_dir: metadata.is_dir(),
+                };
+                dir_entry.name.push_str(name).ok();  //  Never too long, names are limited to `PATH_MAX`
+                result = f(&dir_entry);
+                if result.is_err() { break; }
+            }
+            Ok(())
+        }) ? ;
+        result
+    })
+}
+
+/// Return the size of the file system and the free space, in bytes
+pub fn usage() -> MynewtResult<(u32, u32)> {
+    with_fs(|fs| Ok((fs.total_space() as u32, fs.available_space() ? as u32)))
+}
+
+/// Call `f` with the mounted file system, locked. Returns `SYS_ENODEV` if the file system is not mounted.
+fn with_fs<R, F>(f: F) -> MynewtResult<R>
+where F: FnOnce(&Filesystem<'static, FlashStorage>) -> MynewtResult<R> {
+    let _lock = FS_LOCK.lock() ? ;
+    let fs = unsafe { FILESYSTEM.as_ref() }.ok_or(MynewtError::SYS_ENODEV) ? ;
+    f(fs)
+}
+
+/// Convert `path` to a littlefs path. Returns `SYS_EINVAL` if the path is empty, too long or contains a null.
+fn to_path(path: &str) -> MynewtResult<PathBuf> {
+    if path.is_empty() || path.len() > PATH_MAX || path.contains('\0') { return Err(MynewtError::SYS_EINVAL); }
+    Ok(PathBuf::from(path))
+}
+
+/// Convert a littlefs error to a Mynewt error
+impl From<io::Error> for MynewtError {
+    fn from(err: io::Error) -> Self {
+        match err {
+            io::Error::NoSuchEntry         => MynewtError::SYS_ENOENT,
+            io::Error::EntryAlreadyExisted => MynewtError::SYS_EALREADY,
+            io::Error::NoSpace             => MynewtError::SYS_ENOMEM,
+            io::Error::NoMemory            => MynewtError::SYS_ENOMEM,
+            io::Error::Io                  => MynewtError::SYS_EIO,
+            io::Error::Corruption          => MynewtError::SYS_EIO,
+            io::Error::PathNotDir          => MynewtError::SYS_EINVAL,
+            io::Error::PathIsDir           => MynewtError::SYS_EINVAL,
+            io::Error::DirNotEmpty         => MynewtError::SYS_EBUSY,
+            io::Error::FilenameTooLong     => MynewtError::SYS_EINVAL,
+            io::Error::Invalid             => MynewtError::SYS_EINVAL,
+            _                              => MynewtError::SYS_EUNKNOWN,
+        }
+    }
+}
@@ -1,6 +1,37 @@
-use crate::{hw::hal, kernel::os, result::*};
+//! Rust Embedded HAL interface for the Mynewt GPIO, SPI, I2C and delay functions, so that the drivers in this
+//! firmware and external driver crates may use the Mynewt hardware. Also the nRF52 SAADC, watchdog, random number
+//! generator, clocks, EasyDMA and power-failure comparator.
+
+use crate::{hw::hal, kernel::{ hires, time }, result::*};
 use embedded_hal;
 
+/// GPIO input and output pins
+pub mod gpio;  // Export `hal/gpio.rs` as Rust module `mynewt::hal::gpio`
+
+/// SPI master with a configuration for each device
+pub mod spi;   // Export `hal/spi.rs` as Rust module `mynewt::hal::spi`
+
+/// EasyDMA transfers on the SPI ports
+pub mod dma;   // Export `hal/dma.rs` as Rust module `mynewt::hal::dma`
+
+/// I2C master shared by the drivers on a port
+pub mod i2c;   // Export `hal/i2c.rs` as Rust module `mynewt::hal::i2c`
+
+/// nRF52 SAADC with oversampling, one-shot and buffered modes
+pub mod saadc; // Export `hal/saadc.rs` as Rust module `mynewt::hal::saadc`
+
+/// nRF52 hardware watchdog with an early warning hook
+pub mod watchdog; // Export `hal/watchdog.rs` as Rust module `mynewt::hal::watchdog`
+
+/// nRF52 hardware random number generator for `rand_core`
+pub mod rng;   // Export `hal/rng.rs` as Rust module `mynewt::hal::rng`
+
+/// nRF52 HF crystal requests and LF clock source
+pub mod clock; // Export `hal/clock.rs` as Rust module `mynewt::hal::clock`
+
+/// nRF52 power-failure comparator with a warning hook
+pub mod pof;   // Export `hal/pof.rs` as Rust module `mynewt::hal::pof`
+
 /// Rust Embedded HAL interface for Mynewt I2C
 impl I2C {
     /// Create a new I2C port
@@ -16,9 +47,9 @@ impl I2C {
         operation_timeout_in_ticks: u32,
     ) -> MynewtResult<()> {
         let rc = unsafe { hal::hal_i2c_config(i2c_num, i2c_settings) };
-        check_i2c_return_code(rc)?;
+        i2c::check_i2c(rc)?;
         let rc = unsafe { hal::hal_i2c_enable(i2c_num) };
-        check_i2c_return_code(rc)?;
+        i2c::check_i2c(rc)?;
         self.i2c_num = i2c_num;
         self.timeout = operation_timeout_in_ticks;
         Ok(())
@@ -33,7 +64,7 @@ impl embedded_hal::blocking::i2c::Write for I2C {
             buffer: data.as_ptr() as *mut u8,
         };
         let rc = unsafe { hal::hal_i2c_master_write(self.i2c_num, &mut master_data, self.timeout, 1) };
-        check_i2c_return_code(rc)
+        i2c::check_i2c(rc)
     }
 
     type Error = crate::result::MynewtError;
@@ -47,7 +78,7 @@ impl embedded_hal::blocking::i2c::Read for I2C {
             buffer: data.as_mut_ptr(),
         };
         let rc = unsafe { hal::hal_i2c_master_read(self.i2c_num, &mut master_data, self.timeout, 1) };
-        check_i2c_return_code(rc)
+        i2c::check_i2c(rc)
     }
 
     type Error = crate::result::MynewtError;
@@ -69,26 +100,13 @@ impl embedded_hal::blocking::i2c::WriteRead for I2C {
         master_data.len = data_read.len() as u16;
         master_data.buffer = data_read.as_mut_ptr();
         let rc_read = unsafe { hal::hal_i2c_master_read(self.i2c_num, &mut master_data, self.timeout, 1) };
-        check_i2c_return_code(rc_write)?;
-        check_i2c_return_code(rc_read)
+        i2c::check_i2c(rc_write)?;
+        i2c::check_i2c(rc_read)
     }
 
     type Error = crate::result::MynewtError;
 }
 
-fn check_i2c_return_code(rc: i32) -> crate::result::MynewtResult<()> {
-    type E=crate::result::MynewtError;
-    match rc as u32 {
-        0=> Ok(()),
-        hal::HAL_I2C_ERR_UNKNOWN => Err(E::HAL_I2C_ERR_UNKNOWN),
-        hal::HAL_I2C_ERR_INVAL => Err(E::HAL_I2C_ERR_INVAL),
-        hal::HAL_I2C_ERR_TIMEOUT => Err(E::HAL_I2C_ERR_TIMEOUT),
-        hal::HAL_I2C_ERR_ADDR_NACK => Err(E::HAL_I2C_ERR_ADDR_NACK),
-        hal::HAL_I2C_ERR_DATA_NACK => Err(E::HAL_I2C_ERR_DATA_NACK),
-        _ => Err(E::HAL_I2C_ERR_UNKNOWN),
-    }
-}
-
 /// Rust Embedded HAL interface for Mynewt SPI
 impl SPI {
     /// Create a new SPI port
@@ -103,20 +121,16 @@ impl SPI {
     pub fn init(&mut self, spi_num: i32, cs_pin: i32, spi_settings: *mut hal::hal_spi_settings) 
         -> MynewtResult<()> {
         //  Disable the SPI port in case External SPI Flash driver has already enabled it.
-        let rc = unsafe { hal::hal_spi_disable(spi_num) };
-        assert_eq!(rc, 0, "spi disable fail");
+        check_hal(unsafe { hal::hal_spi_disable(spi_num) }) ? ;
 
         //  Configure the SPI port.
-        let rc = unsafe { hal::hal_spi_config(spi_num, spi_settings) };
-        assert_eq!(rc, 0, "spi config fail");
+        check_hal(unsafe { hal::hal_spi_config(spi_num, spi_settings) }) ? ;
 
         //  Enable the SPI port.
-        let rc = unsafe { hal::hal_spi_enable(spi_num) };
-        assert_eq!(rc, 0, "spi enable fail");
+        check_hal(unsafe { hal::hal_spi_enable(spi_num) }) ? ;
 
         //  Set the CS Pin to low only when transmitting.
-        let rc = unsafe { hal::hal_gpio_init_out(cs_pin, 1) };
-        assert_eq!(rc, 0, "spi init fail");
+        check_hal(unsafe { hal::hal_gpio_init_out(cs_pin, 1) }) ? ;
         self.spi_num = spi_num;
         self.cs_pin  = cs_pin;
         Ok(())
@@ -130,13 +144,13 @@ impl embedded_hal::blocking::spi::Write<u8> for SPI {
         //  Select the device
         unsafe { hal::hal_gpio_write(self.cs_pin, 0) };
         //  Send the data
-        unsafe { hal::hal_spi_txrx(self.spi_num, 
+        let rc = unsafe { hal::hal_spi_txrx(self.spi_num, 
             core::mem::transmute(words.as_ptr()),  //  TX Buffer
             core::ptr::null_mut(),                 //  RX Buffer (don't receive)
             words.len() as i32) };                 //  Length
         //  De-select the device
         unsafe { hal::hal_gpio_write(self.cs_pin, 1) };
-        Ok(())
+        check_hal(rc)
     }
 
     /// Reuse Mynewt error codes
@@ -156,8 +170,7 @@ impl GPIO {
     pub fn init(&mut self, pin: i32) -> MynewtResult<()> {
         //  TODO: let dc = pins.d0.into_push_pull_output(&mut pins.port);
         //  TODO: let rst = pins.d1.into_push_pull_output(&mut pins.port);
-        let rc = unsafe { hal::hal_gpio_init_out(pin, 0) };
-        assert_eq!(rc, 0, "gpio fail");
+        check_hal(unsafe { hal::hal_gpio_init_out(pin, 0) }) ? ;
         self.pin = pin;
         Ok(())
     }
@@ -194,10 +207,15 @@ impl Delay {
 impl embedded_hal::blocking::delay::DelayMs<u8> for Delay {
     /// Sleep for the specified number of milliseconds
     fn delay_ms(&mut self, ms: u8) {
-        //  TODO: Get this constant from Mynewt
-        const OS_TICKS_PER_SEC: u32 = 1000;
-        let delay_ticks = (ms as u32) * OS_TICKS_PER_SEC / 1000;
-        unsafe { os::os_time_delay(delay_ticks) };
+        time::sleep_ms(ms as u32);
+    }
+}
+
+/// Rust Embedded HAL interface for Mynewt Delay
+impl embedded_hal::blocking::delay::DelayUs<u32> for Delay {
+    /// Busy-wait for the specified number of microseconds, e.g. for display reset pulses
+    fn delay_us(&mut self, us: u32) {
+        hires::delay_us(us);
     }
 }
 
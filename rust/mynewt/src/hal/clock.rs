@@ -0,0 +1,155 @@
+//! nRF52 clock management. The 64 MHz high-frequency clock `HFCLK` runs from the internal RC oscillator unless a
+//! driver needs the accuracy of the 32 MHz crystal oscillator (HFXO), e.g. the radio, or a UART at high baud rates.
+//! The HFXO draws about 250 microamps, so it's reference counted: each driver holds an `HfxoRequest` while it needs
+//! the crystal, and the crystal stops when the last request is dropped. The count is shared with the NimBLE
+//! controller, which requests the HFXO through the same Mynewt functions before each radio event.
+//!
+//! The 32.768 kHz low-frequency clock `LFCLK` drives the OS tick, the RTC timers and the watchdog. Its source is
+//! selected at startup by `MCU_LFCLK_SOURCE` in `syscfg.yml`, and may be changed with `select_lf_source()`.
+//! ```
+//! let hfxo = clock::request_hfxo();        //  Start the crystal
+//! hfxo.wait(Duration::from_millis(2)) ? ;  //  Wait until it's stable
+//! //  Do the timing-critical work
+//! drop(hfxo);                              //  Stop the crystal if nobody else needs it
+//! ```
+
+use core::{ ptr, time::Duration };
+use crate::{
+    kernel::{ hires, os },
+    result::*,
+};
+
+/// Address of the nRF52 `CLOCK` registers. From nRF52832 Product Specification, section 20.9
+const CLOCK_BASE: usize = 0x4000_0000;
+const CLOCK_TASKS_LFCLKSTART:    *mut u32 = (CLOCK_BASE + 0x008) as *mut u32;
+const CLOCK_TASKS_LFCLKSTOP:     *mut u32 = (CLOCK_BASE + 0x00C) as *mut u32;
+const CLOCK_EVENTS_LFCLKSTARTED: *mut u32 = (CLOCK_BASE + 0x104) as *mut u32;
+const CLOCK_HFCLKSTAT:           *mut u32 = (CLOCK_BASE + 0x40C) as *mut u32;
+const CLOCK_LFCLKSTAT:           *mut u32 = (CLOCK_BASE + 0x418) as *mut u32;
+const CLOCK_LFCLKSRC:            *mut u32 = (CLOCK_BASE + 0x518) as *mut u32;
+
+/// `HFCLKSTAT` bits: clock source is the crystal, and clock is running
+const HFCLKSTAT_SRC_XTAL: u32 = 1 << 0;
+const HFCLKSTAT_RUNNING:  u32 = 1 << 16;
+
+/// `LFCLKSTAT` bits: clock source, and clock is running
+const LFCLKSTAT_SRC_MASK: u32 = 0b11;
+const LFCLKSTAT_RUNNING:  u32 = 1 << 16;
+
+/// Max time for the LF crystal to start. The nRF52832 Product Specification gives 0.25 seconds typical.
+const LFXO_START_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Source of the low-frequency clock `LFCLK`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum LfSource {
+    /// Internal RC oscillator, ±500 ppm after calibration. No crystal needed.
+    Rc    = 0,
+    /// 32.768 kHz crystal oscillator (LFXO), ±20 ppm on PineTime
+    Xtal  = 1,
+    /// Synthesised from `HFCLK`, which keeps the HFXO running
+    Synth = 2,
+}
+
+/// Request for the HF crystal oscillator. The crystal keeps running until every request has been dropped.
+#[derive(Debug)]
+pub struct HfxoRequest {
+    /// Prevent construction outside `request_hfxo()`
+    _private: (),
+}
+
+/// Request the HF crystal oscillator and return without waiting for it to start. Call `wait()` on the request
+/// if the work needs the crystal to be stable.
+pub fn request_hfxo() -> HfxoRequest {
+    unsafe { nrf52_clock_hfxo_request() };
+    HfxoRequest { _private: () }
+}
+
+impl HfxoRequest {
+    /// Wait up to `timeout` for the crystal to run. The crystal takes about 360 microseconds to start. Returns
+    /// `SYS_ETIMEOUT` if the crystal isn't running in time, e.g. it's missing.
+    pub fn wait(&self, timeout: Duration) -> MynewtResult<()> {
+        wait_until(timeout, is_hfxo_running)
+    }
+}
+
+impl Drop for HfxoRequest {
+    /// Release the request. The crystal stops if this was the last request.
+    fn drop(&mut self) {
+        unsafe { nrf52_clock_hfxo_release() };
+    }
+}
+
+/// Return true if `HFCLK` is running from the crystal oscillator
+pub fn is_hfxo_running() -> bool {
+    let stat = unsafe { ptr::read_volatile(CLOCK_HFCLKSTAT) };
+    stat & (HFCLKSTAT_SRC_XTAL | HFCLKSTAT_RUNNING) == HFCLKSTAT_SRC_XTAL | HFCLKSTAT_RUNNING
+}
+
+/// Return the source of the running `LFCLK`
+pub fn lf_source() -> LfSource {
+    match unsafe { ptr::read_volatile(CLOCK_LFCLKSTAT) } & LFCLKSTAT_SRC_MASK {
+        1 => LfSource::Xtal,
+        2 => LfSource::Synth,
+        _ => LfSource::Rc,
+    }
+}
+
+/// HFXO request held while `LFCLK` is synthesised from `HFCLK`
+static mut SYNTH_REQUEST: Option<HfxoRequest> = None;
+
+/// Switch `LFCLK` to `source`, e.g. to `Rc` if the LF crystal has failed. The OS tick, RTC timers and watchdog stop
+/// while the new source starts, up to 1 second for the crystal, so call this at startup or while idle. Returns
+/// `SYS_ETIMEOUT` if the new source doesn't start, after switching back to the previous source.
+pub fn select_lf_source(source: LfSource) -> MynewtResult<()> {
+    let previous = lf_source();
+    if source == previous { return Ok(()); }
+    if source == LfSource::Synth {
+        let hfxo = request_hfxo();
+        hfxo.wait(Duration::from_millis(2)) ? ;
+        unsafe { SYNTH_REQUEST = Some(hfxo) };
+    }
+    let result = start_lf_clock(source);
+    if result.is_err() {
+        start_lf_clock(previous) ? ;  //  Clock stays stopped if the previous source fails too
+    }
+    //  Release the HFXO if `LFCLK` is no longer synthesised from it.
+    if lf_source() != LfSource::Synth { unsafe { SYNTH_REQUEST = None }; }
+    result
+}
+
+/// Stop `LFCLK`, then start it from `source` and wait until it runs
+fn start_lf_clock(source: LfSource) -> MynewtResult<()> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe {
+        ptr::write_volatile(CLOCK_TASKS_LFCLKSTOP, 1);
+        while ptr::read_volatile(CLOCK_LFCLKSTAT) & LFCLKSTAT_RUNNING != 0 {}
+        ptr::write_volatile(CLOCK_LFCLKSRC, source as u32);
+        ptr::write_volatile(CLOCK_EVENTS_LFCLKSTARTED, 0);
+        ptr::write_volatile(CLOCK_TASKS_LFCLKSTART, 1);
+        os::os_arch_restore_sr(sr);
+    }
+    wait_until(LFXO_START_TIMEOUT, || unsafe { ptr::read_volatile(CLOCK_EVENTS_LFCLKSTARTED) } != 0) ? ;
+    unsafe { ptr::write_volatile(CLOCK_EVENTS_LFCLKSTARTED, 0) };
+    Ok(())
+}
+
+/// Poll `done()` with the microsecond timer until it returns true. Returns `SYS_ETIMEOUT` after `timeout`.
+/// The OS tick may be stopped, so the OS time can't be used.
+fn wait_until<F: Fn() -> bool>(timeout: Duration, done: F) -> MynewtResult<()> {
+    let timeout_us = timeout.as_micros() as u32;
+    let start = hires::now_us();
+    while !done() {
+        if hires::elapsed_us(start) >= timeout_us { return Err(MynewtError::SYS_ETIMEOUT); }
+    }
+    Ok(())
+}
+
+extern "C" {
+    /// Request the HF crystal oscillator, reference counted. Returns 1 if the crystal was started.
+    /// C API: `int nrf52_clock_hfxo_request(void)`
+    fn nrf52_clock_hfxo_request() -> i32;
+    /// Release the HF crystal oscillator. Returns 1 if the crystal was stopped.
+    /// C API: `int nrf52_clock_hfxo_release(void)`
+    fn nrf52_clock_hfxo_release() -> i32;
+}
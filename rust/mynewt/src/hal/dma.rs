@@ -0,0 +1,134 @@
+//! EasyDMA transfers on the nRF52 SPI master (SPIM). EasyDMA moves the bytes between RAM and the SPI port without
+//! the CPU, so the other tasks run during the transfer, and the calling task waits on a semaphore given by the SPI
+//! interrupt when the transfer completes. The nRF52832 EasyDMA has two restrictions, handled here:
+//! - EasyDMA can only access RAM. Bytes in Flash ROM, e.g. a `const` bitmap, are copied to a bounce buffer in RAM.
+//! - The byte counters `MAXCNT` are 8 bits, so transfers are split into chunks of up to `MAX_CHUNK` bytes.
+//!
+//! The functions borrow the buffers until EasyDMA has finished with them: they return when the transfer has
+//! completed, or after aborting it on timeout, so a buffer can't be dropped or changed while EasyDMA uses it. The
+//! caller holds the `SpiBusLock` of the port and selects the device.
+//! ```
+//! let bus = spi::bus(pinetime::SPI_NUM) ? .lock(&pinetime::DISPLAY_SPI_CONFIG) ? ;
+//! dma::spi_write(&bus, &pixels) ? ;  //  With the display selected
+//! ```
+//! This module sets the `hal_spi` transfer callback of the ports that it uses, so other callbacks must not be set
+//! on these ports.
+
+use core::time::Duration;
+use crate::{
+    hal::spi::{ SpiBusLock, MAX_SPI_PORTS },
+    hw::hal,
+    kernel::sync::Semaphore,
+    result::*,
+};
+
+/// Max number of bytes in an EasyDMA transfer of the nRF52832 SPIM: `MAXCNT` is 8 bits
+pub const MAX_CHUNK: usize = 255;
+
+/// RAM that is accessible by EasyDMA: the 64 KB of data RAM of the nRF52832
+const RAM_START: usize = 0x2000_0000;
+const RAM_END:   usize = 0x2001_0000;
+
+/// Max time for a chunk. A chunk takes 2 milliseconds at 1 MHz.
+const CHUNK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Byte sent while receiving with `spi_read()`
+const FILL_BYTE: u8 = 0xff;
+
+/// Semaphore of each SPI port, given by the SPI interrupt when a chunk has been sent
+static DONE: [Semaphore; MAX_SPI_PORTS] = [ Semaphore::new(0), Semaphore::new(0), Semaphore::new(0) ];
+
+/// True if the transfer callback has been set for the SPI port
+static mut READY: [bool; MAX_SPI_PORTS] = [false; MAX_SPI_PORTS];
+
+/// Bounce buffer of each SPI port, for bytes outside RAM and for the fill bytes. Used while the bus is locked.
+static mut BOUNCE: [[u8; MAX_CHUNK]; MAX_SPI_PORTS] = [[0; MAX_CHUNK]; MAX_SPI_PORTS];
+
+/// Return true if EasyDMA can access `buf`, i.e. it's in RAM
+pub fn in_ram(buf: &[u8]) -> bool {
+    let start = buf.as_ptr() as usize;
+    start >= RAM_START && start + buf.len() <= RAM_END
+}
+
+/// Send `tx` to the selected device on the SPI port of `bus`, ignoring the received bytes
+pub fn spi_write(bus: &SpiBusLock, tx: &[u8]) -> MynewtResult<()> {
+    init(bus) ? ;
+    for chunk in tx.chunks(MAX_CHUNK) {
+        transfer_chunk(bus.spi_num(), chunk, core::ptr::null_mut()) ? ;
+    }
+    Ok(())
+}
+
+/// Receive `rx.len()` bytes into `rx` from the selected device on the SPI port of `bus`, sending `0xff`
+pub fn spi_read(bus: &SpiBusLock, rx: &mut [u8]) -> MynewtResult<()> {
+    init(bus) ? ;
+    let port = bus.spi_num() as usize;
+    for chunk in rx.chunks_mut(MAX_CHUNK) {
+        //  The master must send a byte for each byte received.
+        let fill = unsafe { &mut BOUNCE[port][..chunk.len()] };
+        for byte in fill.iter_mut() { *byte = FILL_BYTE; }
+        transfer_chunk(bus.spi_num(), fill, chunk.as_mut_ptr()) ? ;
+    }
+    Ok(())
+}
+
+/// Send `tx` to the selected device on the SPI port of `bus`, receiving the same number of bytes into `rx`.
+/// Returns `SYS_EINVAL` if the lengths differ.
+pub fn spi_transfer(bus: &SpiBusLock, tx: &[u8], rx: &mut [u8]) -> MynewtResult<()> {
+    if tx.len() != rx.len() { return Err(MynewtError::SYS_EINVAL); }
+    init(bus) ? ;
+    for (tx_chunk, rx_chunk) in tx.chunks(MAX_CHUNK).zip(rx.chunks_mut(MAX_CHUNK)) {
+        transfer_chunk(bus.spi_num(), tx_chunk, rx_chunk.as_mut_ptr()) ? ;
+    }
+    Ok(())
+}
+
+/// Set the transfer callback of the SPI port if this is the first transfer. The callback may only be set while
+/// the port is disabled, and it's kept when the bus reconfigures the port.
+fn init(bus: &SpiBusLock) -> MynewtResult<()> {
+    let spi_num = bus.spi_num();
+    if unsafe { READY[spi_num as usize] } { return Ok(()); }
+    //  Pass the port to the callback as the argument.
+    unsafe { hal::hal_spi_disable(spi_num) };
+    check_hal(unsafe { hal::hal_spi_set_txrx_cb(spi_num, Some(handle_done), spi_num as usize as *mut ::cty::c_void) }) ? ;
+    check_hal(unsafe { hal::hal_spi_enable(spi_num) }) ? ;
+    unsafe { READY[spi_num as usize] = true };
+    Ok(())
+}
+
+/// Send the chunk `tx` of up to `MAX_CHUNK` bytes and receive into `rx` unless null, then wait for the SPI interrupt
+fn transfer_chunk(spi_num: i32, tx: &[u8], rx: *mut u8) -> MynewtResult<()> {
+    let len = tx.len();
+    assert!(len <= MAX_CHUNK, "dma chunk");
+    if len == 0 { return Ok(()); }
+    if len == 1 {
+        //  nRF52832 SPIM clocks out an additional byte when sending 1 byte, so use the blocking transfer, which
+        //  runs the port in SPI mode without EasyDMA. See `hal_spi.c` in Mynewt.
+        return check_hal(unsafe { hal::hal_spi_txrx(spi_num,
+            tx.as_ptr() as *mut ::cty::c_void, rx as *mut ::cty::c_void, 1) });
+    }
+    let port = spi_num as usize;
+    let tx = if in_ram(tx) { tx.as_ptr() } else {
+        let bounce = unsafe { &mut BOUNCE[port][..len] };
+        bounce.copy_from_slice(tx);
+        bounce.as_ptr()
+    };
+    //  Discard the signal of a chunk that completed after its timeout.
+    while DONE[port].try_take().is_ok() {}
+    check_hal(unsafe { hal::hal_spi_txrx_noblock(spi_num,
+        tx as *mut ::cty::c_void,  //  TX Buffer
+        rx as *mut ::cty::c_void,  //  RX Buffer
+        len as i32) }) ? ;         //  Length
+    if let Err(err) = DONE[port].take(CHUNK_TIMEOUT) {
+        //  Stop EasyDMA before returning, since the buffers are no longer borrowed after returning.
+        unsafe { hal::hal_spi_abort(spi_num) };
+        return Err(err.into());  //  `SYS_ETIMEOUT` if timed out
+    }
+    Ok(())
+}
+
+/// Called by the SPI interrupt when a chunk has been sent. `arg` is the SPI port.
+extern "C" fn handle_done(arg: *mut ::cty::c_void, _len: i32) {
+    let port = arg as usize;
+    if port < MAX_SPI_PORTS { DONE[port].give().ok(); }
+}
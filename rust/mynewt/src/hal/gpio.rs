@@ -0,0 +1,287 @@
+//! GPIO input and output pins over the Mynewt `hal_gpio` API, implementing the `embedded-hal` digital traits so that
+//! the drivers in this firmware and external driver crates share the same pin types. A pin is configured when it is
+//! created, and keeps its configuration when dropped, e.g. the backlight stays on.
+//! ```
+//! let mut backlight = Output::new(23, Level::High) ? ;  //  LCD_BACKLIGHT_HIGH (P0.23), off when high
+//! backlight.set_low() ? ;                               //  Switch on the backlight
+//! let button = Input::new(13, Pull::None) ? ;           //  PUSH_BUTTON_IN (P0.13)
+//! if button.is_high() ? { /* Button is pressed */ }
+//! ```
+//! Rust handlers may be attached to pin interrupts with `Interrupt::attach()`, e.g. for the touch controller. The
+//! interrupt handler only records the edge in a channel, and the Rust handler is called later by the default event
+//! queue, so it may use I2C, SPI and other blocking APIs.
+//! ```
+//! fn handle_touch(event: PinEvent) { /* Read the touch data */ }
+//! let mut touch = Interrupt::attach(28, Edge::Falling, Pull::Up, handle_touch) ? ;  //  TP_INT (P0.28)
+//! touch.enable();
+//! ```
+
+use embedded_hal::digital::v2::{ toggleable, InputPin, OutputPin, StatefulOutputPin };
+use crate::{
+    hw::hal,
+    kernel::{ channel::Channel, os, time::Instant },
+    result::*,
+};
+
+/// Max number of pins that may have Rust interrupt handlers
+pub const MAX_INTERRUPTS: usize = 8;
+
+/// Logic level of a pin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    /// Pin is low (0)
+    Low,
+    /// Pin is high (1)
+    High,
+}
+
+/// Pull resistor of an input pin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pull {
+    /// Pin is floating
+    None,
+    /// Pin is pulled up
+    Up,
+    /// Pin is pulled down
+    Down,
+}
+
+/// Edge of an input pin that triggers the interrupt
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    /// Low to high
+    Rising,
+    /// High to low
+    Falling,
+    /// Both rising and falling
+    Both,
+}
+
+/// Interrupt of a pin, passed to the Rust handler
+#[derive(Clone, Copy, Debug)]
+pub struct PinEvent {
+    /// Mynewt GPIO pin number
+    pub pin: i32,
+    /// Level of the pin in the interrupt handler, which may have bounced since the edge
+    pub level: Level,
+    /// Time of the interrupt
+    pub at: Instant,
+}
+
+/// GPIO pin configured as input
+#[derive(Debug)]
+pub struct Input {
+    /// Mynewt GPIO pin number
+    pin: i32,
+}
+
+/// GPIO pin configured as output. The level that was last written is remembered, since it can't be read back from
+/// an output pin on all MCUs.
+#[derive(Debug)]
+pub struct Output {
+    /// Mynewt GPIO pin number
+    pin: i32,
+    /// Level that was last written
+    level: Level,
+}
+
+/// GPIO input pin with a Rust interrupt handler. The interrupt is disabled until `enable()` is called.
+#[derive(Debug)]
+pub struct Interrupt {
+    /// Mynewt GPIO pin number
+    pin: i32,
+}
+
+impl Input {
+    /// Configure the pin as input with the pull resistor. Returns `SYS_EINVAL` if the pin doesn't exist.
+    pub fn new(pin: i32, pull: Pull) -> MynewtResult<Self> {
+        let pull = match pull {
+            Pull::None => hal::hal_gpio_pull_HAL_GPIO_PULL_NONE,
+            Pull::Up   => hal::hal_gpio_pull_HAL_GPIO_PULL_UP,
+            Pull::Down => hal::hal_gpio_pull_HAL_GPIO_PULL_DOWN,
+        };
+        check_hal(unsafe { hal::hal_gpio_init_in(pin, pull) }) ? ;
+        Ok(Input { pin })
+    }
+
+    /// Return the Mynewt GPIO pin number
+    pub fn pin(&self) -> i32 { self.pin }
+
+    /// Return the level of the pin
+    pub fn level(&self) -> Level {
+        if unsafe { hal::hal_gpio_read(self.pin) } != 0 { Level::High } else { Level::Low }
+    }
+}
+
+impl Output {
+    /// Configure the pin as output, initially at the level. Returns `SYS_EINVAL` if the pin doesn't exist.
+    pub fn new(pin: i32, level: Level) -> MynewtResult<Self> {
+        check_hal(unsafe { hal::hal_gpio_init_out(pin, (level == Level::High) as i32) }) ? ;
+        Ok(Output { pin, level })
+    }
+
+    /// Return the Mynewt GPIO pin number
+    pub fn pin(&self) -> i32 { self.pin }
+
+    /// Return the level that was last written
+    pub fn level(&self) -> Level { self.level }
+
+    /// Set the pin to the level
+    pub fn set_level(&mut self, level: Level) {
+        unsafe { hal::hal_gpio_write(self.pin, (level == Level::High) as i32) };
+        self.level = level;
+    }
+}
+
+impl Interrupt {
+    /// Configure the pin as input with the pull resistor, and call `handler` in the default event queue after each
+    /// `edge`. If several interrupts of the pin are waiting, `handler` is called once with the latest, e.g. the touch
+    /// controller keeps only the latest touch. Returns `SYS_ENOMEM` if `MAX_INTERRUPTS` pins have handlers, and
+    /// `SYS_EINVAL` if the pin doesn't exist or already has an interrupt.
+    pub fn attach(pin: i32, edge: Edge, pull: Pull, handler: fn(PinEvent)) -> MynewtResult<Self> {
+        let trigger = match edge {
+            Edge::Rising  => hal::hal_gpio_irq_trigger_HAL_GPIO_TRIG_RISING,
+            Edge::Falling => hal::hal_gpio_irq_trigger_HAL_GPIO_TRIG_FALLING,
+            Edge::Both    => hal::hal_gpio_irq_trigger_HAL_GPIO_TRIG_BOTH,
+        };
+        let pull = match pull {
+            Pull::None => hal::hal_gpio_pull_HAL_GPIO_PULL_NONE,
+            Pull::Up   => hal::hal_gpio_pull_HAL_GPIO_PULL_UP,
+            Pull::Down => hal::hal_gpio_pull_HAL_GPIO_PULL_DOWN,
+        };
+        let index = unsafe { HANDLERS.iter().position(|h| h.is_none()) }
+            .ok_or(MynewtError::SYS_ENOMEM) ? ;
+        PIN_EVENTS.notify(os::eventq_dflt_get() ? , handle_pin_events);
+        //  Pass the pin number to the trampoline as the argument.
+        check_hal(unsafe { hal::hal_gpio_irq_init(
+            pin, Some(irq_trampoline), pin as usize as *mut core::ffi::c_void, trigger, pull
+        ) }) ? ;
+        unsafe { HANDLERS[index] = Some((pin, handler)) };
+        Ok(Interrupt { pin })
+    }
+
+    /// Return the Mynewt GPIO pin number
+    pub fn pin(&self) -> i32 { self.pin }
+
+    /// Return the level of the pin
+    pub fn level(&self) -> Level {
+        if unsafe { hal::hal_gpio_read(self.pin) } != 0 { Level::High } else { Level::Low }
+    }
+
+    /// Start calling the handler after each edge
+    pub fn enable(&mut self) {
+        unsafe { hal::hal_gpio_irq_enable(self.pin) };
+    }
+
+    /// Stop calling the handler, e.g. while the sensor is powered off. Interrupts that are waiting are still handled.
+    pub fn disable(&mut self) {
+        unsafe { hal::hal_gpio_irq_disable(self.pin) };
+    }
+
+    /// Disable the interrupt and remove the handler, so that the pin may be configured again
+    pub fn detach(self) {
+        unsafe {
+            hal::hal_gpio_irq_release(self.pin);
+            for handler in HANDLERS.iter_mut() {
+                if let Some((pin, _)) = handler {
+                    if *pin == self.pin { *handler = None; }
+                }
+            }
+        }
+    }
+}
+
+/// Rust handlers for the pins with interrupts
+static mut HANDLERS: [Option<(i32, fn(PinEvent))>; MAX_INTERRUPTS] = [None; MAX_INTERRUPTS];
+
+/// Pin interrupts forwarded to the default event queue. The Mynewt GPIOTE interrupt handler calls the trampolines of
+/// all pins, so there is a single producer.
+static PIN_EVENTS: Channel<PinEvent> = Channel::new();
+
+/// Called by the Mynewt GPIOTE interrupt handler with the pin number as the argument
+extern "C" fn irq_trampoline(arg: *mut core::ffi::c_void) {
+    let pin = arg as usize as i32;
+    let level = if unsafe { hal::hal_gpio_read(pin) } != 0 { Level::High } else { Level::Low };
+    //  Drop the interrupt if the channel is full.
+    PIN_EVENTS.push(PinEvent { pin, level, at: Instant::now() }).ok();
+}
+
+/// Call the Rust handler of each pin with the latest waiting interrupt. Called by the default event queue.
+extern "C" fn handle_pin_events(_ev: *mut os::os_event) {
+    let mut latest: [Option<PinEvent>; MAX_INTERRUPTS] = [None; MAX_INTERRUPTS];
+    while let Some(event) = PIN_EVENTS.pop() {
+        let index = unsafe { HANDLERS.iter().position(|h| h.map(|(pin, _)| pin) == Some(event.pin)) };
+        if let Some(index) = index { latest[index] = Some(event); }  //  Skip the pins that have been detached
+    }
+    for (index, event) in latest.iter().enumerate() {
+        if let (Some(event), Some((_, handler))) = (event, unsafe { HANDLERS[index] }) {
+            handler(*event);
+        }
+    }
+}
+
+/// Rust Embedded HAL interface for Mynewt GPIO input
+impl InputPin for Input {
+    /// Return true if the pin is high
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.level() == Level::High)
+    }
+
+    /// Return true if the pin is low
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.level() == Level::Low)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Rust Embedded HAL interface for Mynewt GPIO interrupt pin
+impl InputPin for Interrupt {
+    /// Return true if the pin is high
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.level() == Level::High)
+    }
+
+    /// Return true if the pin is low
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.level() == Level::Low)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Rust Embedded HAL interface for Mynewt GPIO output
+impl OutputPin for Output {
+    /// Set the pin to low
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.set_level(Level::Low);
+        Ok(())
+    }
+
+    /// Set the pin to high
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.set_level(Level::High);
+        Ok(())
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Rust Embedded HAL interface for Mynewt GPIO output
+impl StatefulOutputPin for Output {
+    /// Return true if the pin was last set to high
+    fn is_set_high(&self) -> Result<bool, Self::Error> {
+        Ok(self.level == Level::High)
+    }
+
+    /// Return true if the pin was last set to low
+    fn is_set_low(&self) -> Result<bool, Self::Error> {
+        Ok(self.level == Level::Low)
+    }
+}
+
+/// Toggle the output pin by setting the opposite of the level that was last written
+impl toggleable::Default for Output {}
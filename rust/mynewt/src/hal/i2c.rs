@@ -0,0 +1,135 @@
+//! I2C master over the Mynewt `hal_i2c` API (TWIM on nRF52), implementing the `embedded-hal` blocking I2C traits so
+//! that the accelerometer, heart rate and touch drivers, and external driver crates, may share an I2C port.
+//! The transactions on a port are serialised by a mutex, so an `I2cBus` may be created by each driver, even in
+//! different tasks. The port must have been initialised by the BSP, e.g. `I2C_0: 1` in `syscfg.yml`.
+//! Errors are returned as the `HAL_I2C_ERR_*` codes, e.g. `HAL_I2C_ERR_ADDR_NACK` if no device answers at the
+//! address, so a driver may retry after `HAL_I2C_ERR_TIMEOUT` but give up on a missing device.
+//! ```
+//! let mut i2c = I2cBus::new(1);                  //  TWI port 1 (I2C_1)
+//! let mut chip_id = [0u8; 1];
+//! i2c.write_read(0x18, &[0x00], &mut chip_id) ? ;  //  Read the BMA421 chip ID
+//! ```
+
+use core::time::Duration;
+use embedded_hal::blocking::i2c::{ Read, Write, WriteRead };
+use crate::{
+    hw::hal,
+    kernel::{ sync::Mutex, time::duration_to_ticks },
+    result::*,
+};
+
+/// Number of Mynewt I2C ports that may be used by `I2cBus`, i.e. `I2C_0` and `I2C_1` on nRF52
+pub const MAX_I2C_PORTS: usize = 2;
+
+/// Timeout of each transaction if not set with `with_timeout()`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Master on an I2C port
+#[derive(Clone, Copy, Debug)]
+pub struct I2cBus {
+    /// Mynewt I2C port number
+    i2c_num: u8,
+    /// Timeout of each read or write in ticks
+    timeout: u32,
+}
+
+/// Locks that serialise the transactions on each port
+static PORTS: [Mutex<()>; MAX_I2C_PORTS] = [Mutex::new(()), Mutex::new(())];
+
+impl I2cBus {
+    /// Create a master on the I2C port `i2c_num`. The transactions fail with `HAL_I2C_ERR_INVAL` if the port is not
+    /// one of the first `MAX_I2C_PORTS`.
+    pub fn new(i2c_num: u8) -> Self {
+        I2cBus { i2c_num, timeout: duration_to_ticks(DEFAULT_TIMEOUT) }
+    }
+
+    /// Return the master with the timeout for each read or write, instead of `DEFAULT_TIMEOUT`
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        I2cBus { timeout: duration_to_ticks(timeout), ..self }
+    }
+
+    /// Set the clock frequency of the port in kHz, e.g. 400 for fast mode. Affects all the devices on the port.
+    pub fn set_frequency(&mut self, freq_khz: u32) -> MynewtResult<()> {
+        let _lock = self.lock() ? ;
+        let settings = hal::hal_i2c_settings { frequency: freq_khz };
+        check_i2c(unsafe { hal::hal_i2c_config(self.i2c_num, &settings) })
+    }
+
+    /// Return the mutex of the port, locked
+    fn lock(&self) -> MynewtResult<crate::kernel::sync::MutexGuard<()>> {
+        let port = PORTS.get(self.i2c_num as usize).ok_or(MynewtError::HAL_I2C_ERR_INVAL) ? ;
+        port.lock()
+    }
+
+    /// Write `data` to the device at the 7-bit address, then send a stop condition if `last`
+    fn master_write(&self, addr: u8, data: &[u8], last: bool) -> MynewtResult<()> {
+        let mut master_data = hal::hal_i2c_master_data {
+            address: addr,
+            len: data.len() as u16,
+            buffer: data.as_ptr() as *mut u8,
+        };
+        check_i2c(unsafe { hal::hal_i2c_master_write(self.i2c_num, &mut master_data, self.timeout, last as u8) })
+    }
+
+    /// Read into `data` from the device at the 7-bit address, then send a stop condition
+    fn master_read(&self, addr: u8, data: &mut [u8]) -> MynewtResult<()> {
+        let mut master_data = hal::hal_i2c_master_data {
+            address: addr,
+            len: data.len() as u16,
+            buffer: data.as_mut_ptr(),
+        };
+        check_i2c(unsafe { hal::hal_i2c_master_read(self.i2c_num, &mut master_data, self.timeout, 1) })
+    }
+}
+
+/// Rust Embedded HAL interface for Mynewt I2C
+impl Write for I2cBus {
+    /// Write `data` to the device at the 7-bit address
+    fn write(&mut self, addr: u8, data: &[u8]) -> Result<(), Self::Error> {
+        let _lock = self.lock() ? ;
+        self.master_write(addr, data, true)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Rust Embedded HAL interface for Mynewt I2C
+impl Read for I2cBus {
+    /// Read into `data` from the device at the 7-bit address
+    fn read(&mut self, addr: u8, data: &mut [u8]) -> Result<(), Self::Error> {
+        let _lock = self.lock() ? ;
+        self.master_read(addr, data)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Rust Embedded HAL interface for Mynewt I2C
+impl WriteRead for I2cBus {
+    /// Write `data_write` to the device at the 7-bit address, then read into `data_read` after a repeated start,
+    /// e.g. to read a register
+    fn write_read(&mut self, addr: u8, data_write: &[u8], data_read: &mut [u8]) -> Result<(), Self::Error> {
+        let _lock = self.lock() ? ;
+        //  Don't read if the device has not accepted the register address.
+        self.master_write(addr, data_write, false) ? ;
+        self.master_read(addr, data_read)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Convert the return code of a `hal_i2c` function to a `MynewtResult`. The `HAL_I2C_ERR_*` codes are positive,
+/// other errors are negative `SYS_E*` codes.
+pub(crate) fn check_i2c(rc: i32) -> MynewtResult<()> {
+    if rc <= 0 { return check_hal(rc); }
+    match rc as u32 {
+        hal::HAL_I2C_ERR_INVAL     => Err(MynewtError::HAL_I2C_ERR_INVAL),
+        hal::HAL_I2C_ERR_TIMEOUT   => Err(MynewtError::HAL_I2C_ERR_TIMEOUT),
+        hal::HAL_I2C_ERR_ADDR_NACK => Err(MynewtError::HAL_I2C_ERR_ADDR_NACK),
+        hal::HAL_I2C_ERR_DATA_NACK => Err(MynewtError::HAL_I2C_ERR_DATA_NACK),
+        _                          => Err(MynewtError::HAL_I2C_ERR_UNKNOWN),
+    }
+}
@@ -0,0 +1,124 @@
+//! nRF52 power-failure comparator (POF). The comparator watches the supply voltage VDD, and interrupts when it falls
+//! below the threshold, while there is still time to finish or stop the work that would be corrupted by a power loss,
+//! e.g. a flash erase. On PineTime VDD comes from a 3.3 V regulator, so VDD falls when the battery is nearly empty.
+//!
+//! The warning hook set by `start()` is called by the interrupt, so it may only do quick work that is safe in an
+//! interrupt handler, like switching off a GPIO. The warning is latched, so that the tasks may poll `is_warning()`
+//! before each step of a long operation.
+//! ```
+//! pof::start(Threshold::V28, handle_power_fail) ? ;  //  Warn when VDD falls below 2.8 V
+//! if pof::is_warning() { return Err(MynewtError::SYS_EAGAIN); }
+//! ```
+//! The interrupt is shared with the `CLOCK` peripheral. Mynewt polls the clock events, so the interrupt handler is
+//! replaced without chaining.
+
+use core::ptr;
+use crate::{
+    kernel::os,
+    result::*,
+};
+
+/// Address of the nRF52 `POWER` registers. From nRF52832 Product Specification, section 18.8
+const POWER_BASE: usize = 0x4000_0000;
+const POWER_EVENTS_POFWARN: *mut u32 = (POWER_BASE + 0x108) as *mut u32;
+const POWER_INTENSET:       *mut u32 = (POWER_BASE + 0x304) as *mut u32;
+const POWER_INTENCLR:       *mut u32 = (POWER_BASE + 0x308) as *mut u32;
+const POWER_POFCON:         *mut u32 = (POWER_BASE + 0x510) as *mut u32;
+
+/// `INTENSET` and `INTENCLR` bit of `POFWARN`
+const INTEN_POFWARN: u32 = 1 << 2;
+
+/// `POFCON` bit that enables the comparator. The threshold is in bits 1 to 4.
+const POFCON_POF: u32 = 1 << 0;
+
+/// Interrupt number of `POWER_CLOCK` and the ARM Cortex-M registers for enabling it
+const POWER_CLOCK_IRQN: usize = 0;
+const SCB_VTOR:   *mut u32 = 0xE000_ED08 as *mut u32;
+const NVIC_ISER0: *mut u32 = 0xE000_E100 as *mut u32;
+const NVIC_IPR:   *mut u8  = 0xE000_E400 as *mut u8;
+
+/// Supply voltage below which the comparator warns
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u32)]
+pub enum Threshold {
+    /// 1.7 V
+    V17 = 4,
+    /// 1.8 V
+    V18 = 5,
+    /// 1.9 V
+    V19 = 6,
+    /// 2.0 V
+    V20 = 7,
+    /// 2.1 V
+    V21 = 8,
+    /// 2.2 V
+    V22 = 9,
+    /// 2.3 V
+    V23 = 10,
+    /// 2.4 V
+    V24 = 11,
+    /// 2.5 V
+    V25 = 12,
+    /// 2.6 V
+    V26 = 13,
+    /// 2.7 V
+    V27 = 14,
+    /// 2.8 V
+    V28 = 15,
+}
+
+/// Hook called by the interrupt when VDD falls below the threshold
+static mut WARNING_HOOK: Option<fn()> = None;
+
+/// True if VDD has fallen below the threshold since `start()` or `clear_warning()`
+static mut WARNING: bool = false;
+
+/// Start the comparator at `threshold`, and call `hook` in the interrupt when VDD falls below the threshold.
+/// Replaces the previous threshold and hook.
+pub fn start(threshold: Threshold, hook: fn()) -> MynewtResult<()> {
+    unsafe {
+        let sr = os::os_arch_save_sr();
+        WARNING_HOOK = Some(hook);
+        WARNING = false;
+        ptr::write_volatile(POWER_POFCON, POFCON_POF | (threshold as u32) << 1);
+        //  The interrupt vectors follow the 16 Cortex-M exception vectors, in the table relocated to RAM.
+        let vectors = ptr::read_volatile(SCB_VTOR) as *mut usize;
+        ptr::write_volatile(vectors.add(16 + POWER_CLOCK_IRQN), handle_pofwarn as usize);
+        //  Highest priority, since the power may be lost within milliseconds.
+        ptr::write_volatile(NVIC_IPR.add(POWER_CLOCK_IRQN), 0);
+        ptr::write_volatile(POWER_EVENTS_POFWARN, 0);
+        ptr::write_volatile(POWER_INTENSET, INTEN_POFWARN);
+        ptr::write_volatile(NVIC_ISER0, 1 << POWER_CLOCK_IRQN);
+        os::os_arch_restore_sr(sr);
+    }
+    Ok(())
+}
+
+/// Stop the comparator
+pub fn stop() {
+    unsafe {
+        ptr::write_volatile(POWER_INTENCLR, INTEN_POFWARN);
+        ptr::write_volatile(POWER_POFCON, 0);
+    }
+}
+
+/// Return true if VDD has fallen below the threshold since `start()` or `clear_warning()`
+pub fn is_warning() -> bool {
+    unsafe { WARNING }
+}
+
+/// Clear the latched warning, e.g. when the charger has been connected. The hook is called again if VDD falls
+/// below the threshold again.
+pub fn clear_warning() {
+    unsafe { WARNING = false };
+}
+
+/// `POWER_CLOCK` interrupt handler. Latch the warning and call the hook.
+extern "C" fn handle_pofwarn() {
+    unsafe {
+        if ptr::read_volatile(POWER_EVENTS_POFWARN) == 0 { return; }
+        ptr::write_volatile(POWER_EVENTS_POFWARN, 0);
+        WARNING = true;
+        if let Some(hook) = WARNING_HOOK { hook(); }
+    }
+}
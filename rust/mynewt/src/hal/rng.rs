@@ -0,0 +1,150 @@
+//! nRF52 hardware random number generator (RNG), implementing `rand_core::RngCore` for DTLS and COSE nonces, CoAP
+//! message IDs and Bluetooth LE privacy. The RNG generates true random bytes from thermal noise, with bias correction,
+//! in about 120 microseconds per byte. While the RNG starts up, and in case it's stuck, the bytes are taken from an
+//! entropy pool instead, seeded with the device ID and the timers and stirred with every hardware byte, so `Rng`
+//! never blocks for long. The NimBLE controller also uses the RNG, with an interrupt handler: the RNG interrupt is
+//! masked while a byte is read, so that the controller doesn't take it.
+//! ```
+//! let mut rng = Rng::new();
+//! let mut nonce = [0u8; 13];
+//! rng.fill_bytes(&mut nonce);
+//! let message_id = rng.next_u32() as u16;
+//! ```
+
+use core::ptr;
+use rand_core::{ impls, CryptoRng, Error };
+use crate::kernel::{ hires, os };
+
+/// Export `RngCore` so that the callers don't need to depend on `rand_core`
+pub use rand_core::RngCore;
+
+/// Address of the nRF52 `RNG` registers. From nRF52832 Product Specification, section 26.12
+const RNG_BASE: usize = 0x4000_D000;
+const RNG_TASKS_START:   *mut u32 = (RNG_BASE + 0x000) as *mut u32;
+const RNG_TASKS_STOP:    *mut u32 = (RNG_BASE + 0x004) as *mut u32;
+const RNG_EVENTS_VALRDY: *mut u32 = (RNG_BASE + 0x100) as *mut u32;
+const RNG_INTENSET:      *mut u32 = (RNG_BASE + 0x304) as *mut u32;
+const RNG_INTENCLR:      *mut u32 = (RNG_BASE + 0x308) as *mut u32;
+const RNG_CONFIG:        *mut u32 = (RNG_BASE + 0x504) as *mut u32;
+const RNG_VALUE:         *mut u32 = (RNG_BASE + 0x508) as *mut u32;
+
+/// `CONFIG` bit that enables the bias correction
+const CONFIG_DERCEN: u32 = 1 << 0;
+
+/// Address of the nRF52 `FICR.DEVICEID` registers: 64-bit unique device ID
+const FICR_DEVICEID: *const u32 = 0x1000_0060 as *const u32;
+
+/// Max time to wait for each hardware byte before taking the byte from the entropy pool
+const WARMUP_TIMEOUT_US: u32 = 1000;
+
+/// Entropy pool, `0` until seeded
+static mut POOL: u64 = 0;
+
+/// Number of bytes taken from the entropy pool because the RNG was not ready
+static mut POOL_BYTES: u32 = 0;
+
+/// Handle for the hardware random number generator. All handles share the RNG and the entropy pool.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rng;
+
+impl Rng {
+    /// Return a handle for the RNG
+    pub fn new() -> Self { Rng }
+
+    /// Return the number of bytes that were taken from the entropy pool because the RNG was not ready, for
+    /// diagnostics. Should stay at a few bytes after startup.
+    pub fn pool_bytes() -> u32 {
+        unsafe { POOL_BYTES }
+    }
+}
+
+impl RngCore for Rng {
+    fn next_u32(&mut self) -> u32 {
+        impls::next_u32_via_fill(self)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_fill(self)
+    }
+
+    /// Fill `dest` with random bytes from the RNG, or from the entropy pool if the RNG is not ready
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest.iter_mut() {
+            *byte = match read_hardware_byte() {
+                Some(value) => { stir(value as u64); value }
+                None => {
+                    unsafe { POOL_BYTES = POOL_BYTES.wrapping_add(1) };
+                    pool_byte()
+                }
+            };
+        }
+    }
+
+    /// Fill `dest` with random bytes. Never fails, since the entropy pool is used when the RNG is not ready.
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// The bytes come from a true random number generator, and from the entropy pool only while it starts up
+impl CryptoRng for Rng {}
+
+/// Read a byte from the RNG, waiting up to `WARMUP_TIMEOUT_US`. Returns `None` if the RNG is not ready.
+fn read_hardware_byte() -> Option<u8> {
+    //  Mask the interrupt of the NimBLE controller, if any, so that it doesn't take the byte.
+    let sr = unsafe { os::os_arch_save_sr() };
+    let inten = unsafe { ptr::read_volatile(RNG_INTENSET) };
+    unsafe {
+        ptr::write_volatile(RNG_INTENCLR, inten);
+        ptr::write_volatile(RNG_CONFIG, CONFIG_DERCEN);
+        ptr::write_volatile(RNG_EVENTS_VALRDY, 0);
+        ptr::write_volatile(RNG_TASKS_START, 1);
+        os::os_arch_restore_sr(sr);
+    }
+    //  Wait with interrupts enabled, so that the radio is not delayed.
+    let start = hires::now_us();
+    let mut ready = false;
+    while hires::elapsed_us(start) < WARMUP_TIMEOUT_US {
+        if unsafe { ptr::read_volatile(RNG_EVENTS_VALRDY) } != 0 { ready = true; break; }
+    }
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe {
+        let value = if ready { Some(ptr::read_volatile(RNG_VALUE) as u8) } else { None };
+        ptr::write_volatile(RNG_EVENTS_VALRDY, 0);
+        //  Keep the RNG running if the NimBLE controller is waiting for its interrupt, else stop it to save power.
+        if inten != 0 { ptr::write_volatile(RNG_INTENSET, inten); }
+        else { ptr::write_volatile(RNG_TASKS_STOP, 1); }
+        os::os_arch_restore_sr(sr);
+        value
+    }
+}
+
+/// Take a byte from the entropy pool and advance the pool
+fn pool_byte() -> u8 {
+    stir(0);
+    (unsafe { POOL } >> 56) as u8
+}
+
+/// Mix `value` into the entropy pool with the SplitMix64 finaliser, seeding the pool on first use
+fn stir(value: u64) {
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe {
+        if POOL == 0 { POOL = seed(); }
+        let mut z = POOL.wrapping_add(0x9E37_79B9_7F4A_7C15) ^ value;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        POOL = z ^ (z >> 31);
+        os::os_arch_restore_sr(sr);
+    }
+}
+
+/// Return the initial entropy: the device ID, which differs between watches, and the timers, which differ between
+/// restarts
+fn seed() -> u64 {
+    let id = unsafe {
+        (ptr::read_volatile(FICR_DEVICEID) as u64) << 32 | ptr::read_volatile(FICR_DEVICEID.add(1)) as u64
+    };
+    let time = (hires::now_us() as u64) << 32 | unsafe { os::os_time_get() } as u64;
+    (id ^ time) | 1  //  Never 0, which means not seeded
+}
@@ -0,0 +1,311 @@
+//! nRF52 SAADC (successive approximation ADC) opened through the Mynewt ADC driver, for the battery voltage and
+//! analog add-ons. The resolution and oversampling are set when the SAADC is opened, and apply to all channels.
+//! With oversampling, each result is the average of 2 to 256 samples taken in a burst, which reduces the noise
+//! without waking the CPU for each sample. `read_average_mv()` also averages several results in software.
+//! One-shot mode reads a channel and waits for the result. Buffered mode fills two buffers alternately with the
+//! samples taken at each `sample()`, e.g. from a timer, and passes each full buffer to a handler.
+//! The offset error of the SAADC drifts with the temperature, so `calibrate_offset()` should be called at startup and
+//! when the temperature has changed by 10 degrees Celsius. The calibration is kept until the next restart.
+//! The SAADC is locked while a `Saadc` is open, so the battery sensor (`libs/battery`) waits until it's dropped.
+//! Requires `ADC_0: 1` in `syscfg.yml`. Implemented by `libs/mynewt_rust/src/adc_helper.c`.
+//! ```
+//! let mut adc = Saadc::open(SaadcConfig { oversample: Oversample::X8, ..SaadcConfig::new() }, Duration::from_secs(1)) ? ;
+//! adc.configure(0, &ChannelConfig { input: AnalogInput::Ain7, gain: Gain::Gain1_5, ..ChannelConfig::new() }) ? ;
+//! let mv = adc.read_average_mv(0, 4) ? ;  //  Voltage at P0.31 in millivolts
+//! ```
+
+use core::time::Duration;
+use crate as mynewt;
+use crate::{
+    kernel::device::Device,
+    result::*,
+    Strn,
+};
+use mynewt_macros::init_strn;
+
+/// Name of the SAADC device, created by the BSP when `ADC_0` is 1
+static ADC_DEVICE: Strn = init_strn!("adc0");
+
+/// Number of SAADC channels
+pub const NUM_CHANNELS: u8 = 8;
+
+/// Resolution of the results
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Bits8  = 0,
+    Bits10 = 1,
+    Bits12 = 2,
+    /// 14 bits, only with oversampling
+    Bits14 = 3,
+}
+
+/// Number of samples averaged by the SAADC for each result
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Oversample {
+    None = 0,
+    X2   = 1,
+    X4   = 2,
+    X8   = 3,
+    X16  = 4,
+    X32  = 5,
+    X64  = 6,
+    X128 = 7,
+    X256 = 8,
+}
+
+/// Analog input of a channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnalogInput {
+    Ain0 = 1,
+    Ain1 = 2,
+    Ain2 = 3,
+    Ain3 = 4,
+    Ain4 = 5,
+    Ain5 = 6,
+    Ain6 = 7,
+    Ain7 = 8,
+    /// Supply voltage of the nRF52
+    Vdd  = 9,
+}
+
+/// Gain of a channel. The input range is the reference divided by the gain, e.g. 3.0 V with `Gain1_5` and the
+/// internal reference.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gain {
+    Gain1_6 = 0,
+    Gain1_5 = 1,
+    Gain1_4 = 2,
+    Gain1_3 = 3,
+    Gain1_2 = 4,
+    Gain1   = 5,
+    Gain2   = 6,
+    Gain4   = 7,
+}
+
+/// Reference voltage of a channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reference {
+    /// Internal 0.6 V reference
+    Internal = 0,
+    /// A quarter of the supply voltage
+    Vdd4     = 1,
+}
+
+/// Acquisition time of a channel. Sources with a high impedance, like a voltage divider, need a longer time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AcqTime {
+    Us3  = 0,
+    Us5  = 1,
+    Us10 = 2,
+    Us15 = 3,
+    Us20 = 4,
+    Us40 = 5,
+}
+
+/// Pull resistor on the input of a channel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pull {
+    None     = 0,
+    Down     = 1,
+    Up       = 2,
+    /// Pulled to half the supply voltage
+    HalfVdd  = 3,
+}
+
+/// Configuration of the SAADC, for all channels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SaadcConfig {
+    pub resolution: Resolution,
+    pub oversample: Oversample,
+}
+
+impl SaadcConfig {
+    /// Return the default configuration: 12 bits without oversampling
+    pub const fn new() -> Self {
+        SaadcConfig { resolution: Resolution::Bits12, oversample: Oversample::None }
+    }
+}
+
+/// Configuration of a channel, measuring a single-ended input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelConfig {
+    pub input: AnalogInput,
+    pub gain: Gain,
+    pub reference: Reference,
+    pub acq_time: AcqTime,
+    pub pull: Pull,
+}
+
+impl ChannelConfig {
+    /// Return the default configuration: AIN0 with gain 1/6 and the internal reference, i.e. 0 to 3.6 V
+    pub const fn new() -> Self {
+        ChannelConfig {
+            input: AnalogInput::Ain0, gain: Gain::Gain1_6, reference: Reference::Internal,
+            acq_time: AcqTime::Us10, pull: Pull::None,
+        }
+    }
+}
+
+/// Handler for buffered sampling, called in interrupt context with each full buffer
+pub type BufferHandler = fn(&[i16]);
+
+/// Handler for buffered sampling, set by `start_buffered()`
+static mut BUFFER_HANDLER: Option<BufferHandler> = None;
+
+/// SAADC opened through the Mynewt ADC driver. Closed when dropped.
+pub struct Saadc {
+    /// The opened ADC device
+    dev: Device,
+    /// Oversampling of all channels
+    oversample: Oversample,
+    /// True if buffered sampling has been started
+    buffered: bool,
+}
+
+impl Saadc {
+    /// Open the SAADC with the configuration, waiting up to `timeout` if it's used, e.g. by the battery sensor.
+    /// Returns `SYS_ETIMEOUT` if the SAADC couldn't be opened in time, `SYS_ENOTSUP` if `ADC_0` is disabled.
+    pub fn open(config: SaadcConfig, timeout: Duration) -> MynewtResult<Self> {
+        let cfg = unsafe { adc_helper_dev_cfg(config.resolution as u8, config.oversample as u8) };
+        if cfg.is_null() { return Err(MynewtError::SYS_ENOTSUP); }
+        let dev = Device::open_with_arg(&ADC_DEVICE, timeout, cfg) ? ;
+        Ok(Saadc { dev, oversample: config.oversample, buffered: false })
+    }
+
+    /// Configure the channel `chan`, from 0 to `NUM_CHANNELS - 1`
+    pub fn configure(&mut self, chan: u8, config: &ChannelConfig) -> MynewtResult<()> {
+        if chan >= NUM_CHANNELS { return Err(MynewtError::SYS_EINVAL); }
+        let cfg = adc_helper_chan_cfg {
+            input:     config.input as u8,
+            gain:      config.gain as u8,
+            reference: config.reference as u8,
+            acq_time:  config.acq_time as u8,
+            resistor:  config.pull as u8,
+            //  Take all the oversamples for a result at one trigger.
+            burst:     (self.oversample != Oversample::None) as u8,
+        };
+        check(unsafe { adc_helper_chan_config(self.adc(), chan, &cfg) })
+    }
+
+    /// Sample the channel once and return the raw result. Single-ended results may be slightly negative near 0 V.
+    pub fn read(&mut self, chan: u8) -> MynewtResult<i16> {
+        let mut raw: i32 = 0;
+        check(unsafe { adc_helper_read(self.adc(), chan, &mut raw) }) ? ;
+        Ok(raw as i16)
+    }
+
+    /// Sample the channel once and return the voltage at the input in millivolts
+    pub fn read_mv(&mut self, chan: u8) -> MynewtResult<i32> {
+        let raw = self.read(chan) ? ;
+        Ok(self.to_mv(chan, raw))
+    }
+
+    /// Sample the channel `count` times and return the average voltage at the input in millivolts
+    pub fn read_average_mv(&mut self, chan: u8, count: u16) -> MynewtResult<i32> {
+        if count == 0 { return Err(MynewtError::SYS_EINVAL); }
+        let mut sum: i32 = 0;
+        for _ in 0..count {
+            sum += self.read(chan) ? as i32;
+        }
+        Ok(self.to_mv(chan, (sum / count as i32) as i16))
+    }
+
+    /// Convert the raw result of the channel to millivolts at the input, with the gain and reference of the channel
+    pub fn to_mv(&self, chan: u8, raw: i16) -> i32 {
+        unsafe { adc_helper_result_mv(self.adc(), chan, raw as i32) }
+    }
+
+    /// Start buffered sampling into `buf1` and `buf2` alternately, which must have the same length, a multiple of
+    /// the number of configured channels. `handler` is called in interrupt context with each full buffer. The samples
+    /// are taken by `sample()`. Only one `Saadc` may be open, so there is one handler.
+    pub fn start_buffered(&mut self, buf1: &'static mut [i16], buf2: &'static mut [i16], handler: BufferHandler)
+        -> MynewtResult<()> {
+        if buf1.len() != buf2.len() || buf1.is_empty() { return Err(MynewtError::SYS_EINVAL); }
+        unsafe { BUFFER_HANDLER = Some(handler) };
+        check(unsafe { adc_helper_buf_start(self.adc(), buf1.as_mut_ptr(), buf2.as_mut_ptr(), buf1.len() as i32,
+            handle_buffer, core::ptr::null_mut()) }) ? ;
+        self.buffered = true;
+        Ok(())
+    }
+
+    /// Take the next sample of the configured channels into the current buffer
+    pub fn sample(&mut self) -> MynewtResult<()> {
+        if !self.buffered { return Err(MynewtError::SYS_EINVAL); }
+        check(unsafe { adc_helper_sample(self.adc()) })
+    }
+
+    /// Calibrate the offset error of the SAADC, which drifts with the temperature. Takes a few hundred microseconds.
+    /// Returns `SYS_EBUSY` if buffered sampling has been started.
+    pub fn calibrate_offset(&mut self) -> MynewtResult<()> {
+        if self.buffered { return Err(MynewtError::SYS_EBUSY); }
+        check(unsafe { adc_helper_calibrate(self.adc()) })
+    }
+
+    /// Return the Mynewt ADC device
+    fn adc(&self) -> *mut adc_dev {
+        unsafe { self.dev.as_driver::<adc_dev>() }
+    }
+}
+
+impl Drop for Saadc {
+    /// Stop the buffered sampling. The device is closed when `dev` is dropped.
+    fn drop(&mut self) {
+        if self.buffered {
+            unsafe { adc_helper_buf_stop(self.adc()) };
+            unsafe { BUFFER_HANDLER = None };
+        }
+    }
+}
+
+/// Pass the full buffer to the handler. Called by the ADC driver in interrupt context.
+extern "C" fn handle_buffer(_arg: *mut ::cty::c_void, samples: *const i16, count: i32) {
+    if samples.is_null() || count <= 0 { return; }
+    if let Some(handler) = unsafe { BUFFER_HANDLER } {
+        handler(unsafe { core::slice::from_raw_parts(samples, count as usize) });
+    }
+}
+
+/// Mynewt ADC device, only passed to `adc_helper.c`
+#[allow(non_camel_case_types)]
+#[repr(C)]
+struct adc_dev { _private: [u8; 0] }
+
+/// Settings of a SAADC channel. Must sync with `struct adc_helper_chan_cfg` in `adc_helper.h`.
+#[allow(non_camel_case_types)]
+#[repr(C)]
+struct adc_helper_chan_cfg {
+    input: u8,
+    gain: u8,
+    reference: u8,
+    acq_time: u8,
+    resistor: u8,
+    burst: u8,
+}
+
+extern "C" {
+    /// Return the SAADC configuration for `os_dev_open()`, or NULL if the SAADC is disabled.
+    /// C API: `void *adc_helper_dev_cfg(uint8_t resolution, uint8_t oversample)`
+    fn adc_helper_dev_cfg(resolution: u8, oversample: u8) -> *mut ::cty::c_void;
+    /// Configure the channel.
+    /// C API: `int adc_helper_chan_config(struct adc_dev *adc, uint8_t chan, const struct adc_helper_chan_cfg *cfg)`
+    fn adc_helper_chan_config(adc: *mut adc_dev, chan: u8, cfg: *const adc_helper_chan_cfg) -> i32;
+    /// Sample the channel once and wait for the raw result.
+    /// C API: `int adc_helper_read(struct adc_dev *adc, uint8_t chan, int *raw)`
+    fn adc_helper_read(adc: *mut adc_dev, chan: u8, raw: *mut i32) -> i32;
+    /// Convert the raw result of the channel to millivolts.
+    /// C API: `int adc_helper_result_mv(struct adc_dev *adc, uint8_t chan, int raw)`
+    fn adc_helper_result_mv(adc: *mut adc_dev, chan: u8, raw: i32) -> i32;
+    /// Prepare buffered sampling into two buffers.
+    /// C API: `int adc_helper_buf_start(struct adc_dev *adc, int16_t *buf1, int16_t *buf2, int count, adc_helper_buf_fn *cb, void *arg)`
+    fn adc_helper_buf_start(adc: *mut adc_dev, buf1: *mut i16, buf2: *mut i16, count: i32,
+        cb: extern "C" fn(*mut ::cty::c_void, *const i16, i32), arg: *mut ::cty::c_void) -> i32;
+    /// Stop calling the callback of buffered sampling.
+    /// C API: `int adc_helper_buf_stop(struct adc_dev *adc)`
+    fn adc_helper_buf_stop(adc: *mut adc_dev) -> i32;
+    /// Take the next sample into the current buffer.
+    /// C API: `int adc_helper_sample(struct adc_dev *adc)`
+    fn adc_helper_sample(adc: *mut adc_dev) -> i32;
+    /// Calibrate the offset and wait until done.
+    /// C API: `int adc_helper_calibrate(struct adc_dev *adc)`
+    fn adc_helper_calibrate(adc: *mut adc_dev) -> i32;
+}
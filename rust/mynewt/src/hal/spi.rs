@@ -0,0 +1,232 @@
+//! SPI master over the Mynewt `hal_spi` API, implementing the `embedded-hal` blocking SPI traits so that external
+//! display and flash driver crates may run on the Mynewt SPI ports. Each `SpiDevice` has its own chip select pin and
+//! its own mode and frequency: the port is reconfigured when a transfer is for a device with another configuration.
+//!
+//! On PineTime the display and the SPI Flash share SPI port 0, so each port is a `SpiBus` that hands out the devices
+//! and serialises their transfers with a mutex. A driver that needs the bus for a sequence of transfers, like the
+//! non-blocking display task in `spi.rs` or the SPI Flash driver called by `hw::flash`, holds a `SpiBusLock`
+//! instead, so the display refresh and the flash writes interleave between requests but never within one. While
+//! holding the lock, a `SpiDevice` transfers with `write_locked()` and `transfer_locked()`, since the
+//! `embedded-hal` traits lock the bus for each transfer.
+//! ```
+//! let config = SpiConfig { mode: Mode::Mode3, freq_khz: 8000, ..SpiConfig::new() };
+//! let mut flash = spi::bus(pinetime::SPI_NUM) ? .device(pinetime::FLASH_CS.number(), config) ? ;
+//! let mut id = [0x9f, 0, 0, 0];                    //  Read JEDEC ID
+//! flash.transfer(&mut id) ? ;
+//! ```
+//! C callers of the SPI Flash driver, e.g. the DFU image upload, take the bus lock through the wrappers of the Flash
+//! HAL in `apps/my_sensor_app/src/flash_power.c`, which call `flash_bus_lock()` in `hw::flash`.
+
+use embedded_hal::blocking::spi::{ Transfer, Write };
+use crate::{
+    hal::gpio::{ Level, Output },
+    hw::hal,
+    kernel::sync::{ Mutex, MutexGuard },
+    result::*,
+};
+
+/// Number of Mynewt SPI ports that may be used by `SpiDevice`, i.e. `SPI_0` to `SPI_2` on nRF52
+pub const MAX_SPI_PORTS: usize = 3;
+
+/// Clock polarity and phase
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Clock idle low, sample on the leading edge
+    Mode0,
+    /// Clock idle low, sample on the trailing edge
+    Mode1,
+    /// Clock idle high, sample on the leading edge
+    Mode2,
+    /// Clock idle high, sample on the trailing edge
+    Mode3,
+}
+
+/// SPI configuration of a device
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpiConfig {
+    /// Clock polarity and phase
+    pub mode: Mode,
+    /// Clock frequency in kHz, e.g. 8000 for 8 MHz, the fastest on nRF52832
+    pub freq_khz: u32,
+    /// True if the least significant bit is sent first
+    pub lsb_first: bool,
+}
+
+impl SpiConfig {
+    /// Return the default configuration: mode 0 at 1 MHz, most significant bit first
+    pub const fn new() -> Self {
+        SpiConfig { mode: Mode::Mode0, freq_khz: 1000, lsb_first: false }
+    }
+}
+
+/// SPI port shared by the devices on it. The mutex serialises the transfers, and remembers the configuration
+/// applied to the port so that it's only reconfigured for a device with another configuration.
+pub struct SpiBus {
+    /// Mynewt SPI port number
+    spi_num: i32,
+    /// Configuration applied to the port, `None` until the first transfer
+    applied: Mutex<Option<SpiConfig>>,
+}
+
+/// Exclusive use of a `SpiBus`, configured for one device. The other devices wait until the lock is dropped, so the
+/// holder may send several transfers, e.g. a display command followed by its data. The holder uses its `SpiDevice`
+/// with `write_locked()` and `transfer_locked()`, since the bus can't be locked again until the lock is dropped.
+pub struct SpiBusLock {
+    /// Locked bus
+    bus: &'static SpiBus,
+    /// Guard of the bus mutex with the configuration applied to the port, released when dropped
+    applied: MutexGuard<'static, Option<SpiConfig>>,
+}
+
+/// Device on a SPI bus, selected by its chip select pin (active low)
+pub struct SpiDevice {
+    /// Bus of the device
+    bus: &'static SpiBus,
+    /// Chip select pin
+    cs: Output,
+    /// Configuration of the port for this device
+    config: SpiConfig,
+}
+
+/// Bus of each SPI port
+static BUSES: [SpiBus; MAX_SPI_PORTS] = [ SpiBus::new(0), SpiBus::new(1), SpiBus::new(2) ];
+
+/// Return the bus of SPI port `spi_num`. Returns `SYS_EINVAL` if the port is not one of the first `MAX_SPI_PORTS`.
+pub fn bus(spi_num: i32) -> MynewtResult<&'static SpiBus> {
+    if spi_num < 0 || spi_num as usize >= MAX_SPI_PORTS { return Err(MynewtError::SYS_EINVAL); }
+    Ok(&BUSES[spi_num as usize])
+}
+
+impl SpiBus {
+    /// Create the bus of SPI port `spi_num`
+    const fn new(spi_num: i32) -> Self {
+        SpiBus { spi_num, applied: Mutex::new(None) }
+    }
+
+    /// Return the Mynewt SPI port number
+    pub fn spi_num(&self) -> i32 { self.spi_num }
+
+    /// Create a device on the bus with chip select pin `cs_pin`. The port is configured at the first transfer.
+    pub fn device(&'static self, cs_pin: i32, config: SpiConfig) -> MynewtResult<SpiDevice> {
+        let cs = Output::new(cs_pin, Level::High) ? ;  //  Deselect the device
+        Ok(SpiDevice { bus: self, cs, config })
+    }
+
+    /// Lock the bus for a sequence of transfers with `config`, waiting until the other devices have finished their
+    /// transfers. The port is reconfigured if the previous device had another configuration. Returns `SYS_EBUSY` if
+    /// the calling task already holds the lock.
+    pub fn lock(&'static self, config: &SpiConfig) -> MynewtResult<SpiBusLock> {
+        let applied = self.applied.lock() ? ;
+        let mut lock = SpiBusLock { bus: self, applied };
+        lock.apply(config) ? ;
+        Ok(lock)
+    }
+}
+
+impl SpiBusLock {
+    /// Return the Mynewt SPI port number, for the `hal_spi` functions
+    pub fn spi_num(&self) -> i32 { self.bus.spi_num }
+
+    /// Reconfigure the port for `config` if the previous transfer had another configuration
+    fn apply(&mut self, config: &SpiConfig) -> MynewtResult<()> {
+        if *self.applied == Some(*config) { return Ok(()); }
+        //  Forget the configuration if it fails, so that it's applied again at the next transfer.
+        *self.applied = None;
+        configure(self.bus.spi_num, config) ? ;
+        *self.applied = Some(*config);
+        Ok(())
+    }
+}
+
+impl SpiDevice {
+    /// Create a device on the SPI port `spi_num` with chip select pin `cs_pin`. Same as
+    /// `bus(spi_num)?.device(cs_pin, config)`. Returns `SYS_EINVAL` if the port is not one of the first
+    /// `MAX_SPI_PORTS`.
+    pub fn new(spi_num: i32, cs_pin: i32, config: SpiConfig) -> MynewtResult<Self> {
+        bus(spi_num) ? .device(cs_pin, config)
+    }
+
+    /// Lock the bus of the device for a sequence of transfers, e.g. to keep the other devices from changing the
+    /// configuration between a flash command and the status polling. Transfer with `write_locked()` and
+    /// `transfer_locked()` while holding the lock.
+    pub fn lock(&self) -> MynewtResult<SpiBusLock> {
+        self.bus.lock(&self.config)
+    }
+
+    /// Write to the device on the bus locked by `bus`. Returns `SYS_EINVAL` if `bus` is the lock of another bus.
+    pub fn write_locked(&mut self, bus: &mut SpiBusLock, words: &[u8]) -> MynewtResult<()> {
+        self.txrx(bus, words.as_ptr(), core::ptr::null_mut(), words.len())  //  Don't receive
+    }
+
+    /// Write `words` to the device on the bus locked by `bus`, replacing them by the bytes received. Returns
+    /// `SYS_EINVAL` if `bus` is the lock of another bus.
+    pub fn transfer_locked<'w>(&mut self, bus: &mut SpiBusLock, words: &'w mut [u8]) -> MynewtResult<&'w [u8]> {
+        //  `hal_spi_txrx()` supports the same buffer for sending and receiving.
+        self.txrx(bus, words.as_ptr(), words.as_mut_ptr(), words.len()) ? ;
+        Ok(words)
+    }
+
+    /// Return the configuration of the device
+    pub fn config(&self) -> SpiConfig { self.config }
+
+    /// Change the configuration of the device, e.g. a slower clock while a flash chip is powering up.
+    /// Applied at the next transfer.
+    pub fn set_config(&mut self, config: SpiConfig) { self.config = config; }
+
+    /// Send `len` bytes from `tx` and receive them into `rx`, unless null, with the device selected on the bus
+    /// locked by `bus`. `rx` may be the same buffer as `tx`.
+    fn txrx(&mut self, bus: &mut SpiBusLock, tx: *const u8, rx: *mut u8, len: usize) -> MynewtResult<()> {
+        if bus.spi_num() != self.bus.spi_num { return Err(MynewtError::SYS_EINVAL); }
+        bus.apply(&self.config) ? ;
+        self.cs.set_level(Level::Low);
+        let rc = unsafe { hal::hal_spi_txrx(bus.spi_num(),
+            tx as *mut ::cty::c_void,  //  TX Buffer
+            rx as *mut ::cty::c_void,  //  RX Buffer
+            len as i32) };             //  Length
+        self.cs.set_level(Level::High);
+        check_hal(rc)
+    }
+}
+
+/// Rust Embedded HAL interface for Mynewt SPI
+impl Write<u8> for SpiDevice {
+    /// Write to the device
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        let mut bus = self.lock() ? ;
+        self.write_locked(&mut bus, words)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Rust Embedded HAL interface for Mynewt SPI
+impl Transfer<u8> for SpiDevice {
+    /// Write `words` to the device, replacing them by the bytes received
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        let mut bus = self.lock() ? ;
+        self.transfer_locked(&mut bus, words)
+    }
+
+    /// Reuse Mynewt error codes
+    type Error = MynewtError;
+}
+
+/// Configure the SPI port. The port must be disabled while configuring.
+fn configure(spi_num: i32, config: &SpiConfig) -> MynewtResult<()> {
+    let mut settings = hal::hal_spi_settings {
+        data_mode: match config.mode {
+            Mode::Mode0 => hal::HAL_SPI_MODE0,
+            Mode::Mode1 => hal::HAL_SPI_MODE1,
+            Mode::Mode2 => hal::HAL_SPI_MODE2,
+            Mode::Mode3 => hal::HAL_SPI_MODE3,
+        } as u8,
+        data_order: (if config.lsb_first { hal::HAL_SPI_LSB_FIRST } else { hal::HAL_SPI_MSB_FIRST }) as u8,
+        word_size:  hal::HAL_SPI_WORD_SIZE_8BIT as u8,
+        baudrate:   config.freq_khz,
+    };
+    //  Ignore the error if the port is already disabled.
+    unsafe { hal::hal_spi_disable(spi_num) };
+    check_hal(unsafe { hal::hal_spi_config(spi_num, &mut settings) }) ? ;
+    check_hal(unsafe { hal::hal_spi_enable(spi_num) })
+}
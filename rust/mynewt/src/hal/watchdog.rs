@@ -0,0 +1,133 @@
+//! nRF52 hardware watchdog (WDT). Once started, the watchdog can't be stopped or reconfigured, and it restarts the
+//! device unless `feed()` is called within the timeout. The timeout is checked when the `WatchdogConfig` is built,
+//! so a `const` configuration with an invalid timeout fails to compile. Just before the restart, the watchdog
+//! interrupt calls the early warning hook set by `set_early_warning()`, e.g. to record which task was running.
+//! The hook has 2 cycles of the 32.768 kHz clock (61 microseconds), so it may only write to RAM that is not cleared
+//! at startup, not to the console or Flash.
+//! ```
+//! const WATCHDOG: WatchdogConfig = WatchdogConfig::new(30_000);  //  Restart after 30 seconds without feeding
+//! watchdog::start(WATCHDOG) ? ;
+//! loop {
+//!     watchdog::feed();
+//!     //  Do some work
+//! }
+//! ```
+//! The task supervisor `mynewt::kernel::supervisor` owns the watchdog in this firmware. Mynewt must not use the
+//! watchdog: set `WATCHDOG_INTERVAL: 0` in `syscfg.yml`.
+
+use core::{ ptr, time::Duration };
+use crate::result::*;
+
+/// Address of the nRF52 `WDT` registers. From nRF52832 Product Specification, section 34.5
+const WDT_BASE: usize = 0x4001_0000;
+const WDT_TASKS_START:    *mut u32 = (WDT_BASE + 0x000) as *mut u32;
+const WDT_EVENTS_TIMEOUT: *mut u32 = (WDT_BASE + 0x100) as *mut u32;
+const WDT_INTENSET:       *mut u32 = (WDT_BASE + 0x304) as *mut u32;
+const WDT_RUNSTATUS:      *mut u32 = (WDT_BASE + 0x400) as *mut u32;
+const WDT_CRV:            *mut u32 = (WDT_BASE + 0x504) as *mut u32;
+const WDT_RREN:           *mut u32 = (WDT_BASE + 0x508) as *mut u32;
+const WDT_CONFIG:         *mut u32 = (WDT_BASE + 0x50C) as *mut u32;
+const WDT_RR0:            *mut u32 = (WDT_BASE + 0x600) as *mut u32;
+
+/// `CONFIG` bits: keep counting while the CPU sleeps, and while it is halted by the debugger
+const CONFIG_SLEEP_RUN: u32 = 1 << 0;
+const CONFIG_HALT_RUN:  u32 = 1 << 3;
+
+/// Value written to `RR[0]` to feed the watchdog
+const RELOAD_VALUE: u32 = 0x6E52_4635;
+
+/// Interrupt number of the `WDT` and the ARM Cortex-M registers for enabling it
+const WDT_IRQN: usize = 16;
+const SCB_VTOR:   *mut u32 = 0xE000_ED08 as *mut u32;
+const NVIC_ISER0: *mut u32 = 0xE000_E100 as *mut u32;
+const NVIC_IPR:   *mut u8  = 0xE000_E400 as *mut u8;
+
+/// Frequency of the watchdog clock `LFCLK` in Hz
+const LFCLK_HZ: u64 = 32_768;
+
+/// Shortest and longest timeouts in milliseconds. `CRV` must be at least 15, and fit into 32 bits.
+pub const MIN_TIMEOUT_MS: u32 = 1;
+pub const MAX_TIMEOUT_MS: u32 = 131_071_999;
+
+/// Configuration of the watchdog, checked when built
+#[derive(Clone, Copy, Debug)]
+pub struct WatchdogConfig {
+    /// Counter reload value: the timeout in `LFCLK` cycles, minus 1
+    crv: u32,
+    /// Value of the `CONFIG` register
+    config: u32,
+}
+
+impl WatchdogConfig {
+    /// Restart the device if the watchdog isn't fed within `timeout_ms` milliseconds, from `MIN_TIMEOUT_MS` to
+    /// `MAX_TIMEOUT_MS`. The watchdog keeps counting while the CPU sleeps, and pauses while the debugger halts the
+    /// CPU. An invalid timeout fails to compile in a `const`, and panics otherwise.
+    pub const fn new(timeout_ms: u32) -> Self {
+        //  Index out of bounds if the timeout is invalid. Panicking is not allowed in a `const fn`.
+        let invalid = (timeout_ms < MIN_TIMEOUT_MS) as usize | (timeout_ms > MAX_TIMEOUT_MS) as usize;
+        let timeout_ms: u32 = [timeout_ms][invalid];
+        WatchdogConfig {
+            crv: (timeout_ms as u64 * LFCLK_HZ / 1000 - 1) as u32,
+            config: CONFIG_SLEEP_RUN,
+        }
+    }
+
+    /// Return the configuration that keeps counting while the debugger halts the CPU, so that a device halted for
+    /// too long restarts. Default is to pause.
+    pub const fn run_when_halted(self) -> Self {
+        WatchdogConfig { config: self.config | CONFIG_HALT_RUN, ..self }
+    }
+
+    /// Return the timeout, rounded to the `LFCLK` cycle
+    pub fn timeout(&self) -> Duration {
+        Duration::from_micros((self.crv as u64 + 1) * 1_000_000 / LFCLK_HZ)
+    }
+}
+
+/// Hook called by the watchdog interrupt just before the restart
+static mut EARLY_WARNING: Option<fn()> = None;
+
+/// Start the watchdog with the configuration. Returns `SYS_EALREADY` if the watchdog is running, since it can't be
+/// reconfigured until the next restart.
+pub fn start(config: WatchdogConfig) -> MynewtResult<()> {
+    unsafe {
+        if ptr::read_volatile(WDT_RUNSTATUS) != 0 { return Err(MynewtError::SYS_EALREADY); }
+        ptr::write_volatile(WDT_CONFIG, config.config);
+        ptr::write_volatile(WDT_CRV, config.crv);
+        ptr::write_volatile(WDT_RREN, 1);  //  Feed through `RR[0]` only
+        //  Replace the Mynewt handler, which asserts, in the vector table relocated to RAM.
+        //  The interrupt vectors follow the 16 Cortex-M exception vectors.
+        let vectors = ptr::read_volatile(SCB_VTOR) as *mut usize;
+        ptr::write_volatile(vectors.add(16 + WDT_IRQN), handle_timeout as usize);
+        //  Highest priority, so that the hook runs before the restart even if other interrupts are busy.
+        ptr::write_volatile(NVIC_IPR.add(WDT_IRQN), 0);
+        ptr::write_volatile(WDT_EVENTS_TIMEOUT, 0);
+        ptr::write_volatile(WDT_INTENSET, 1);
+        ptr::write_volatile(NVIC_ISER0, 1 << WDT_IRQN);
+        ptr::write_volatile(WDT_TASKS_START, 1);
+    }
+    Ok(())
+}
+
+/// Feed the watchdog, so that it restarts the timeout
+pub fn feed() {
+    unsafe { ptr::write_volatile(WDT_RR0, RELOAD_VALUE) };
+}
+
+/// Return true if the watchdog has been started
+pub fn is_running() -> bool {
+    unsafe { ptr::read_volatile(WDT_RUNSTATUS) != 0 }
+}
+
+/// Call `hook` in the watchdog interrupt just before the watchdog restarts the device. Replaces the previous hook.
+pub fn set_early_warning(hook: fn()) {
+    unsafe { EARLY_WARNING = Some(hook) };
+}
+
+/// Watchdog interrupt handler. Call the early warning hook. The device restarts 2 `LFCLK` cycles after the timeout.
+extern "C" fn handle_timeout() {
+    unsafe {
+        ptr::write_volatile(WDT_EVENTS_TIMEOUT, 0);
+        if let Some(hook) = EARLY_WARNING { hook(); }
+    }
+}
@@ -5,3 +5,5 @@ pub mod hal;         // Export `hw/hal.rs` as Rust module `mynewt::hw::hal`
 pub mod sensor;      // Export `hw/sensor.rs` as Rust module `mynewt::hw::sensor`
 
 pub mod sensor_mgr;  // Export `hw/sensor_mgr.rs` as Rust module `mynewt::hw::sensor_mgr`
+
+pub mod flash;       // Export `hw/flash.rs` as Rust module `mynewt::hw::flash`
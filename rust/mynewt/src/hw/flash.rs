@@ -0,0 +1,292 @@
+//! Contains the Mynewt Flash HAL API for Rust, including the safe version of the API.
+//! Flash device 0 is the nRF52 Internal Flash ROM, flash device 1 is the External SPI Flash. The External SPI Flash
+//! shares SPI port 0 with the display, so its operations lock the SPI bus. Reads of the External SPI Flash are sent
+//! by EasyDMA instead of the SPI Flash driver, which reads one byte at a time with the CPU. The External SPI Flash
+//! is put into Deep Power-Down after `POWER_DOWN_DELAY` without operations, and by `power_down()` while the watch
+//! sleeps. The next operation releases it, including the operations of the C callers of the SPI Flash driver,
+//! through the wrappers of the Flash HAL in `apps/my_sensor_app/src/flash_power.c`. The wrappers also lock the SPI
+//! bus for the C callers with `flash_bus_lock()`, so that the DFU image upload doesn't corrupt a display refresh.
+
+use core::{
+    sync::atomic::{ AtomicBool, Ordering },
+    time::Duration,
+};
+use crate::{
+    result::*,
+    board::pinetime,
+    hal::{ dma, spi::{ self, SpiBusLock } },
+    hw::hal::hal_gpio_write,
+    kernel::timer::Callout,
+    sys::{ console, power_profile::{ self, Subsystem } },
+};
+
+/// Named flash regions with a common read / write / erase API
+pub mod map;  //  Export `hw/flash/map.rs` as Rust module `mynewt::hw::flash::map`
+
+/// Benchmark flash read, program and erase
+pub mod bench;  //  Export `hw/flash/bench.rs` as Rust module `mynewt::hw::flash::bench`
+
+/// Circular buffer of records in a flash region
+pub mod fcb;  //  Export `hw/flash/fcb.rs` as Rust module `mynewt::hw::flash::fcb`
+
+/// Flash device ID for Internal Flash ROM
+pub const INTERNAL_FLASH: u8 = 0;
+
+/// Flash device ID for External SPI Flash
+pub const EXTERNAL_FLASH: u8 = 1;
+
+/// SPI Flash command that reads data from a 24-bit address, supported by all SPI NOR chips
+const READ_DATA: u8 = 0x03;
+
+/// Power down the External SPI Flash after this time without operations. Releasing the chip takes 30 microseconds,
+/// so a burst of operations, e.g. a logo upload, keeps the chip awake.
+const POWER_DOWN_DELAY: Duration = Duration::from_millis(100);
+
+/// Timer that powers down the External SPI Flash when no operation is pending
+static POWER_DOWN_TIMER: Callout<fn()> = Callout::new(auto_power_down);
+
+/// True after `init()`, when the operations restart the power-down timer
+static AUTO_POWER_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// SPI bus locked by `flash_bus_lock()` for a C caller of the SPI Flash driver. Only the task that holds the bus
+/// lock sets or clears it.
+#[cfg(not(feature = "sim"))]  //  If the Mynewt functions are not simulated...
+static mut C_BUS_LOCK: Option<SpiBusLock> = None;
+
+/// External SPI Flash chip detected by JEDEC ID at startup. Must sync with `struct bsp_spiflash_chip` in `hw/bsp/nrf52/include/bsp/bsp.h`
+#[repr(C)]
+pub struct FlashChip {
+    /// Null-terminated name of the chip
+    pub name:         *const u8,
+    /// JEDEC manufacturer ID
+    pub manufacturer: u8,
+    /// JEDEC memory type
+    pub memory_type:  u8,
+    /// JEDEC capacity: size is 2 ^ capacity bytes
+    pub capacity:     u8,
+    /// Number of bytes erased at a time
+    pub sector_size:  u32,
+    /// Number of bytes programmed at a time
+    pub page_size:    u32,
+    /// 1 if the chip supports Fast Read at the max baudrate
+    pub fast_read:    u8,
+    /// Max SPI baudrate for reads, in kHz
+    pub max_baudrate: u32,
+}
+
+impl FlashChip {
+    /// Return the size of the chip in bytes
+    pub fn size(&self) -> u32 {
+        1 << self.capacity
+    }
+}
+
+/// Return the External SPI Flash chip detected at startup, or `None` if the chip is unknown
+/// and the SPI Flash Driver uses the defaults in `hw/bsp/nrf52/syscfg.yml`
+pub fn external_chip() -> Option<&'static FlashChip> {
+    unsafe { bsp_spiflash_chip().as_ref() }
+}
+
+/// Display the External SPI Flash chip detected at startup on the console
+pub fn show_external_chip() {
+    match external_chip() {
+        None => { console::print("SPI Flash unknown, using defaults\n"); }
+        Some(chip) => {
+            console::print("SPI Flash ");
+            let mut p = chip.name;
+            while unsafe { *p } != 0 {
+                let c = [ unsafe { *p } ];
+                console::buffer(core::str::from_utf8(&c).unwrap_or("?"));
+                p = unsafe { p.add(1) };
+            }
+            console::print(", JEDEC ");
+            console::printhex(chip.manufacturer); console::printhex(chip.memory_type); console::printhex(chip.capacity);
+            console::print(", ");  console::printint((chip.size() / 1024) as i32);
+            console::print(" KB\n");
+        }
+    }
+    console::flush();
+}
+
+//  Show the External SPI Flash chip that was detected by JEDEC ID at startup, and start the automatic power-down.
+//  Not registered on the host, since the hook would link the SPI Flash driver, which is not simulated.
+#[cfg(not(feature = "sim"))]  //  If the Mynewt functions are not simulated...
+crate::init_hook!(crate::sys::init::STAGE_FLASH, FLASH_HOOK, init);
+
+/// Called during `sysinit()` after the SPI Flash Driver has probed the chip
+#[cfg(not(feature = "sim"))]  //  If the Mynewt functions are not simulated...
+fn init() -> MynewtResult<()> {
+    show_external_chip();
+    AUTO_POWER_DOWN.store(true, Ordering::Relaxed);
+    POWER_DOWN_TIMER.reset(POWER_DOWN_DELAY)
+}
+
+/// Read `buf.len()` bytes from the flash device `flash_id` at `offset` into `buf`.
+/// Returns `Err(SYS_EIO)` if the flash driver reports an error.
+pub fn read(flash_id: u8, offset: u32, buf: &mut [u8]) -> MynewtResult<()> {
+    let bus = lock_bus(flash_id) ? ;
+    if let (Some(bus), Some(chip)) = (&bus, external_chip()) {
+        if offset as u64 + buf.len() as u64 > chip.size() as u64 { return Err(MynewtError::SYS_EIO); }
+        return read_external(bus, offset, buf);
+    }
+    let rc = unsafe { hal_flash_read(flash_id, offset, buf.as_mut_ptr(), buf.len() as u32) };
+    if rc != 0 { return Err(MynewtError::SYS_EIO); }
+    Ok(())
+}
+
+/// Write the bytes in `buf` to the flash device `flash_id` at `offset`. The flash must have been erased.
+/// Returns `Err(SYS_EIO)` if the flash driver reports an error.
+pub fn write(flash_id: u8, offset: u32, buf: &[u8]) -> MynewtResult<()> {
+    let bus = lock_bus(flash_id) ? ;
+    let rc = profile(&bus, || unsafe { hal_flash_write(flash_id, offset, buf.as_ptr(), buf.len() as u32) });
+    if rc != 0 { return Err(MynewtError::SYS_EIO); }
+    Ok(())
+}
+
+/// Erase `len` bytes of the flash device `flash_id` at `offset`. All sectors touched by the range will be erased.
+/// Returns `Err(SYS_EIO)` if the flash driver reports an error.
+pub fn erase(flash_id: u8, offset: u32, len: u32) -> MynewtResult<()> {
+    let bus = lock_bus(flash_id) ? ;
+    let rc = profile(&bus, || unsafe { hal_flash_erase(flash_id, offset, len) });
+    if rc != 0 { return Err(MynewtError::SYS_EIO); }
+    Ok(())
+}
+
+/// Erase the sector of the flash device `flash_id` that starts at `sector_address`.
+/// Returns `Err(SYS_EIO)` if the flash driver reports an error.
+pub fn erase_sector(flash_id: u8, sector_address: u32) -> MynewtResult<()> {
+    let bus = lock_bus(flash_id) ? ;
+    let rc = profile(&bus, || unsafe { hal_flash_erase_sector(flash_id, sector_address) });
+    if rc != 0 { return Err(MynewtError::SYS_EIO); }
+    Ok(())
+}
+
+/// Put the External SPI Flash into Deep Power-Down now, which cuts its standby current from tens of microamps to
+/// about one. The chip ignores all commands except the release, so the next operation releases it first. Returns
+/// `SYS_EBUSY` if a C caller of the SPI Flash driver is in the middle of an operation, and `SYS_ENOTSUP` if
+/// `FLASH_POWER_DOWN` is disabled in `apps/my_sensor_app/syscfg.yml`.
+pub fn power_down() -> MynewtResult<()> {
+    let _bus = spi::bus(pinetime::SPI_NUM) ? .lock(&pinetime::FLASH_SPI_CONFIG) ? ;
+    check(unsafe { flash_power_down() })
+}
+
+/// Return true if the External SPI Flash is in Deep Power-Down
+pub fn is_powered_down() -> bool {
+    unsafe { flash_power_is_down() != 0 }
+}
+
+/// Power down the External SPI Flash after `POWER_DOWN_DELAY` without operations. Called by the default event queue.
+fn auto_power_down() {
+    match power_down() {
+        //  A C caller is busy, try again after the operation.
+        Err(MynewtError::SYS_EBUSY) => { POWER_DOWN_TIMER.reset(POWER_DOWN_DELAY).ok(); }
+        //  Ignore the other errors, the chip stays in standby until the next operation.
+        _ => {}
+    }
+}
+
+/// Restart the power-down timer after an operation on the External SPI Flash. Called by the wrappers of the Flash
+/// HAL in `flash_power.c` and by `lock_bus()`, from any task.
+#[no_mangle]
+extern "C" fn flash_power_activity() {
+    if !AUTO_POWER_DOWN.load(Ordering::Relaxed) { return; }
+    POWER_DOWN_TIMER.reset(POWER_DOWN_DELAY).ok();  //  Ignore the error, the timer has been initialised by `init()`
+}
+
+/// Lock the SPI bus for a C caller of the SPI Flash driver, e.g. the DFU image upload, and restore the configuration
+/// of the driver. Called by the wrappers of the Flash HAL in `flash_power.c` before an operation on the External SPI
+/// Flash. Returns 0 if the bus was locked, to be unlocked by `flash_bus_unlock()`. Returns `SYS_EBUSY` if the
+/// calling task already holds the bus, i.e. the operation was called by `write()` or `erase()`, and another error
+/// before the OS has started, when no other task may use the bus. The bus is not locked in both cases.
+#[cfg(not(feature = "sim"))]  //  If the Mynewt functions are not simulated...
+#[no_mangle]
+extern "C" fn flash_bus_lock() -> i32 {
+    match spi::bus(pinetime::SPI_NUM).and_then(|bus| bus.lock(&pinetime::FLASH_SPI_CONFIG)) {
+        Ok(bus) => { unsafe { C_BUS_LOCK = Some(bus) }; 0 }
+        Err(err) => err as i32,
+    }
+}
+
+/// Unlock the SPI bus locked by `flash_bus_lock()`. Called by the wrappers of the Flash HAL in `flash_power.c` after
+/// the operation.
+#[cfg(not(feature = "sim"))]  //  If the Mynewt functions are not simulated...
+#[no_mangle]
+extern "C" fn flash_bus_unlock() {
+    unsafe { C_BUS_LOCK = None };  //  Drop the lock
+}
+
+/// Read `buf.len()` bytes from the External SPI Flash at `offset` into `buf` by EasyDMA. The SPI Flash driver
+/// leaves the chip ready after each operation, so the chip may be read without the driver.
+fn read_external(bus: &SpiBusLock, offset: u32, buf: &mut [u8]) -> MynewtResult<()> {
+    let cmd = [ READ_DATA, (offset >> 16) as u8, (offset >> 8) as u8, offset as u8 ];
+    let cs = pinetime::FLASH_CS.number();
+    unsafe { hal_gpio_write(cs, 0) };  //  Select the chip
+    let result = dma::spi_write(bus, &cmd)
+        .and_then(|_| dma::spi_read(bus, buf));
+    unsafe { hal_gpio_write(cs, 1) };  //  Deselect the chip
+    result
+}
+
+/// Call the flash driver with `f` and return its result. Operations on the External SPI Flash, locked by `bus`, are
+/// recorded by the power profiler.
+fn profile<F: FnOnce() -> i32>(bus: &Option<SpiBusLock>, f: F) -> i32 {
+    if bus.is_none() { return f(); }  //  Internal Flash ROM
+    power_profile::begin(Subsystem::Flash);
+    let rc = f();
+    power_profile::end(Subsystem::Flash);
+    rc
+}
+
+/// Lock the SPI bus for the External SPI Flash, so that the display task doesn't select the display in the middle
+/// of a flash operation. The bus is restored to the configuration of the SPI Flash driver, and the chip is released
+/// from Deep Power-Down. The chip is not powered down until the bus is unlocked. Returns `None` for the Internal
+/// Flash ROM.
+fn lock_bus(flash_id: u8) -> MynewtResult<Option<SpiBusLock>> {
+    if flash_id != EXTERNAL_FLASH { return Ok(None); }
+    let bus = spi::bus(pinetime::SPI_NUM) ? .lock(&pinetime::FLASH_SPI_CONFIG) ? ;
+    unsafe { flash_power_release() };
+    flash_power_activity();
+    Ok(Some(bus))
+}
+
+///  Import the Mynewt Flash HAL API, located at `hw/hal`
+extern {
+    ///  Read `num_bytes` from flash at `address` into `dst`. Return 0 if successful.
+    ///  C API: `int hal_flash_read(uint8_t flash_id, uint32_t address, void *dst, uint32_t num_bytes)`
+    pub fn hal_flash_read(flash_id: u8, address: u32, dst: *mut u8, num_bytes: u32) -> i32;
+
+    ///  Write `num_bytes` from `src` to flash at `address`. Return 0 if successful.
+    ///  C API: `int hal_flash_write(uint8_t flash_id, uint32_t address, const void *src, uint32_t num_bytes)`
+    pub fn hal_flash_write(flash_id: u8, address: u32, src: *const u8, num_bytes: u32) -> i32;
+
+    ///  Erase `num_bytes` of flash at `address`. Return 0 if successful.
+    ///  C API: `int hal_flash_erase(uint8_t flash_id, uint32_t address, uint32_t num_bytes)`
+    pub fn hal_flash_erase(flash_id: u8, address: u32, num_bytes: u32) -> i32;
+
+    ///  Erase the flash sector that starts at `sector_address`. Return 0 if successful.
+    ///  C API: `int hal_flash_erase_sector(uint8_t flash_id, uint32_t sector_address)`
+    pub fn hal_flash_erase_sector(flash_id: u8, sector_address: u32) -> i32;
+}
+
+///  Import the SPI Flash probe from `hw/bsp/nrf52/src/spiflash_probe.c`
+extern {
+    ///  Return the SPI Flash chip detected by JEDEC ID, or null if unknown.
+    ///  C API: `const struct bsp_spiflash_chip *bsp_spiflash_chip(void)`
+    fn bsp_spiflash_chip() -> *const FlashChip;
+}
+
+///  Import the Deep Power-Down from `apps/my_sensor_app/src/flash_power.c`
+extern {
+    ///  Put the External SPI Flash into Deep Power-Down. The SPI bus must be locked. Returns `SYS_EBUSY` if an
+    ///  operation is in progress.
+    ///  C API: `int flash_power_down(void)`
+    fn flash_power_down() -> i32;
+
+    ///  Release the External SPI Flash from Deep Power-Down and wait until it's ready.
+    ///  C API: `void flash_power_release(void)`
+    fn flash_power_release();
+
+    ///  Return 1 if the External SPI Flash is in Deep Power-Down.
+    ///  C API: `int flash_power_is_down(void)`
+    fn flash_power_is_down() -> i32;
+}
@@ -0,0 +1,149 @@
+//! Benchmark the flash devices: sequential read, page program and sector erase throughput and latency,
+//! printed to the console. Used for evaluating changes to the SPI clock and the SPI Flash Driver, like
+//! DMA and Fast Read commands. Program and erase are destructive, so run them only on a scratch area.
+
+use crate::{
+    result::*,
+    hw::flash::{ self, map::Region },
+    kernel::os,
+    sys::console,
+};
+
+/// Frequency of the CPU timer in Hz. Must sync with `OS_CPUTIME_FREQ` in `hw/bsp/nrf52/syscfg.yml`
+const CPUTIME_FREQ: u64 = 32768;
+
+/// Number of bytes read or programmed at a time. Equals the SPI Flash page size.
+const BENCH_PAGE_SIZE: usize = 256;
+
+/// Size of a sector for erase and program, unless the detected SPI Flash chip says otherwise
+const DEFAULT_SECTOR_SIZE: u32 = 4096;
+
+/// Buffer for the bytes read and programmed
+static mut BENCH_BUFFER: [u8; BENCH_PAGE_SIZE] = [0; BENCH_PAGE_SIZE];
+
+/// Result of a benchmark: number of operations, bytes transferred and time taken
+#[derive(Clone, Copy, Default)]
+pub struct BenchResult {
+    /// Number of operations: reads, page programs or sector erases
+    pub count:    u32,
+    /// Number of bytes read, programmed or erased
+    pub bytes:    u32,
+    /// Total time in microseconds
+    pub total_us: u32,
+    /// Time of the slowest operation in microseconds
+    pub max_us:   u32,
+}
+
+impl BenchResult {
+    /// Return the throughput in bytes per second
+    pub fn bytes_per_sec(&self) -> u32 {
+        if self.total_us == 0 { return 0; }
+        (self.bytes as u64 * 1_000_000 / self.total_us as u64) as u32
+    }
+
+    /// Return the average time per operation in microseconds
+    pub fn avg_us(&self) -> u32 {
+        if self.count == 0 { return 0; }
+        self.total_us / self.count
+    }
+
+    /// Record an operation on `bytes` bytes that took `us` microseconds
+    fn add(&mut self, bytes: u32, us: u32) {
+        self.count    += 1;
+        self.bytes    += bytes;
+        self.total_us += us;
+        if us > self.max_us { self.max_us = us; }
+    }
+
+    /// Display the result on the console
+    pub fn show(&self, name: &str) {
+        console::print("Flash "); console::print(name);
+        console::print(": ");      console::printint((self.bytes_per_sec() / 1024) as i32);
+        console::print(" KB/s, avg "); console::printint(self.avg_us() as i32);
+        console::print(" us, max ");   console::printint(self.max_us as i32);
+        console::print(" us, count ");  console::printint(self.count as i32);
+        console::print("\n"); console::flush();
+    }
+}
+
+/// Measure sequential reads of `len` bytes from flash device `flash_id` at `offset`, one page at a time
+pub fn bench_read(flash_id: u8, offset: u32, len: u32) -> MynewtResult<BenchResult> {
+    let mut result = BenchResult::default();
+    let mut done: u32 = 0;
+    while done < len {
+        let size = core::cmp::min(BENCH_PAGE_SIZE as u32, len - done);
+        let buf = unsafe { &mut BENCH_BUFFER[..size as usize] };
+        let start = now();
+        flash::read(flash_id, offset + done, buf) ? ;
+        result.add(size, elapsed_us(start));
+        done += size;
+    }
+    Ok(result)
+}
+
+/// Measure page programs of `len` bytes to flash device `flash_id` at `offset`. The flash must have been erased.
+pub fn bench_program(flash_id: u8, offset: u32, len: u32) -> MynewtResult<BenchResult> {
+    //  Program a pattern that clears most bits, so that the timing is realistic.
+    for (i, b) in unsafe { BENCH_BUFFER.iter_mut() }.enumerate() { *b = i as u8; }
+    let mut result = BenchResult::default();
+    let mut done: u32 = 0;
+    while done < len {
+        let size = core::cmp::min(BENCH_PAGE_SIZE as u32, len - done);
+        let buf = unsafe { &BENCH_BUFFER[..size as usize] };
+        let start = now();
+        flash::write(flash_id, offset + done, buf) ? ;
+        result.add(size, elapsed_us(start));
+        done += size;
+    }
+    Ok(result)
+}
+
+/// Measure sector erases of `len` bytes of flash device `flash_id` at `offset`, one sector at a time
+pub fn bench_erase(flash_id: u8, offset: u32, len: u32) -> MynewtResult<BenchResult> {
+    let sector_size = sector_size(flash_id);
+    let mut result = BenchResult::default();
+    let mut done: u32 = 0;
+    while done < len {
+        let start = now();
+        flash::erase_sector(flash_id, offset + done) ? ;
+        result.add(sector_size, elapsed_us(start));
+        done += sector_size;
+    }
+    Ok(result)
+}
+
+/// Run all benchmarks on the `len` bytes at `offset` of the flash `region` and display the results on the console.
+/// The contents of the area are destroyed: the area is erased, programmed, read and erased again.
+pub fn run(region: &Region, offset: u32, len: u32) -> MynewtResult<()> {
+    let addr = region.address(offset, len) ? ;
+    let sector_size = sector_size(region.flash_id);
+    if addr % sector_size != 0 || len % sector_size != 0 { return Err(MynewtError::SYS_EINVAL); }
+    console::print("Flash benchmark on "); console::print(region.name);
+    console::print(", len "); console::printint(len as i32); console::print("\n"); console::flush();
+
+    bench_erase(region.flash_id, addr, len) ? .show("erase");
+    bench_program(region.flash_id, addr, len) ? .show("program");
+    bench_read(region.flash_id, addr, len) ? .show("read");
+
+    //  Leave the area erased.
+    flash::erase(region.flash_id, addr, len)
+}
+
+/// Return the sector size of the flash device
+fn sector_size(flash_id: u8) -> u32 {
+    match flash::external_chip() {
+        Some(chip) if flash_id == flash::EXTERNAL_FLASH => chip.sector_size,
+        _ => DEFAULT_SECTOR_SIZE,
+    }
+}
+
+/// Return the current CPU time in ticks
+fn now() -> u32 {
+    unsafe { os::os_cputime_get32() }
+}
+
+/// Return the microseconds elapsed since the CPU time `start`
+fn elapsed_us(start: u32) -> u32 {
+    let ticks = now().wrapping_sub(start) as u64;
+    (ticks * 1_000_000 / CPUTIME_FREQ) as u32
+}
@@ -0,0 +1,288 @@
+//! Flash circular buffer for data logs that must survive restarts, e.g. sensor readings and events. `FlashLog` wraps
+//! the Mynewt Flash Circular Buffer `fs/fcb` over a flash region from `map`. The region is split into sectors and
+//! the records are appended to the newest sector. Each record has a length and a CRC, so a record that was being
+//! written when the power failed is skipped. When the region is full, `append()` erases the oldest sector to make
+//! room, so the log keeps the newest records. Records are read by `for_each()`, oldest first.
+//! ```
+//! static EVENT_LOG: FlashLog = FlashLog::new(map::EVENT_LOG, 4096);
+//! EVENT_LOG.init() ? ;
+//! EVENT_LOG.append(b"button") ? ;
+//! EVENT_LOG.for_each(|record| { console::dump(record.as_ptr(), record.len() as u32); Ok(()) }) ? ;
+//! ```
+//! The region must not be used by anything else. A region that doesn't hold a circular buffer is erased by `init()`.
+
+use core::cell::UnsafeCell;
+use crate::{
+    result::*,
+    hw::flash::map::{ Region, Storage },
+    kernel::os,
+};
+
+/// Max number of sectors in a `FlashLog`
+const MAX_SECTORS: usize = 16;
+
+/// Max size of a record in bytes. Longer records are rejected by `append()`.
+pub const MAX_RECORD_SIZE: usize = 128;
+
+/// Marks the sectors of a `FlashLog`: `FLOG`
+const LOG_MAGIC: u32 = 0x474f_4c46;
+
+/// Version of the record format. Sectors with another version are erased by `init()`.
+const LOG_VERSION: u8 = 1;
+
+/// Error codes from `fs/fcb/include/fcb/fcb.h` in Mynewt 1.7
+const FCB_ERR_ARGS: i32    = -1;
+const FCB_ERR_FLASH: i32   = -2;
+const FCB_ERR_NOVAR: i32   = -3;
+const FCB_ERR_NOSPACE: i32 = -4;
+const FCB_ERR_NOMEM: i32   = -5;
+const FCB_ERR_CRC: i32     = -6;
+const FCB_ERR_MAGIC: i32   = -7;
+const FCB_ERR_VERSION: i32 = -8;
+
+/// Circular buffer of records in a flash region. Must be declared `static`, since the buffer refers to its sectors.
+pub struct FlashLog {
+    /// Flash region of the buffer
+    region: Region,
+    /// Size of a sector, the unit of erasing
+    sector_size: u32,
+    /// Mynewt circular buffer and its sectors, set up by `init()`
+    state: UnsafeCell<State>,
+}
+
+/// Mynewt circular buffer of a `FlashLog`
+struct State {
+    /// True after `init()`
+    initialised: bool,
+    /// Mynewt circular buffer
+    fcb: fcb,
+    /// Sectors of the region
+    sectors: [flash_area; MAX_SECTORS],
+}
+
+/// `FlashLog` may be shared between tasks. Appends and reads are locked by the mutex in `fcb`.
+unsafe impl Sync for FlashLog {}
+
+impl FlashLog {
+    /// Create a circular buffer in `region`, erased in sectors of `sector_size` bytes. The region must have at least
+    /// 2 sectors and at most `MAX_SECTORS`.
+    pub const fn new(region: Region, sector_size: u32) -> Self {
+        FlashLog {
+            region,
+            sector_size,
+            state: UnsafeCell::new(State {
+                initialised: false,
+                fcb: fcb {
+                    f_magic:       LOG_MAGIC,
+                    f_version:     LOG_VERSION,
+                    f_sector_cnt:  0,
+                    f_scratch_cnt: 0,
+                    f_sectors:     core::ptr::null_mut(),
+                    f_mtx: os::os_mutex {
+                        mu_head:  os::os_mutex__bindgen_ty_1 { slh_first: core::ptr::null_mut() },
+                        _pad:     0,
+                        mu_prio:  0,
+                        mu_level: 0,
+                        mu_owner: core::ptr::null_mut(),
+                    },
+                    f_oldest:      core::ptr::null_mut(),
+                    f_active:      fcb_entry::EMPTY,
+                    f_active_id:   0,
+                    f_align:       0,
+                },
+                sectors: [flash_area::EMPTY; MAX_SECTORS],
+            }),
+        }
+    }
+
+    /// Find the oldest and newest records in the region. Erases the region if it doesn't hold a circular buffer of
+    /// this version. Must be called before the other functions, e.g. at startup.
+    pub fn init(&'static self) -> MynewtResult<()> {
+        let count = self.region.size / self.sector_size;
+        if count < 2 || count as usize > MAX_SECTORS || self.region.size % self.sector_size != 0 {
+            return Err(MynewtError::SYS_EINVAL);
+        }
+        let state = unsafe { &mut *self.state.get() };
+        if state.initialised { return Ok(()); }
+        for (i, sector) in state.sectors.iter_mut().take(count as usize).enumerate() {
+            *sector = flash_area {
+                fa_id:        0xff,  //  Not in the flash map
+                fa_device_id: self.region.flash_id,
+                pad16:        0,
+                fa_off:       self.region.offset + i as u32 * self.sector_size,
+                fa_size:      self.sector_size,
+            };
+        }
+        state.fcb.f_sector_cnt = count as u8;
+        state.fcb.f_sectors = state.sectors.as_mut_ptr();
+        let rc = unsafe { fcb_init(&mut state.fcb) };
+        if rc == FCB_ERR_MAGIC || rc == FCB_ERR_VERSION {
+            //  Region was used for something else, or by an older version.
+            self.region.erase(0, self.region.size) ? ;
+            fcb_result(unsafe { fcb_init(&mut state.fcb) }) ? ;
+        } else {
+            fcb_result(rc) ? ;
+        }
+        state.initialised = true;
+        Ok(())
+    }
+
+    /// Append the record `data` after the newest record. If the region is full, the oldest sector is erased first,
+    /// which drops the oldest records. Returns `SYS_EINVAL` if the record is empty or longer than `MAX_RECORD_SIZE`.
+    pub fn append(&'static self, data: &[u8]) -> MynewtResult<()> {
+        if data.is_empty() || data.len() > MAX_RECORD_SIZE { return Err(MynewtError::SYS_EINVAL); }
+        let fcb = self.fcb() ? ;
+        let mut loc = fcb_entry::EMPTY;
+        let mut rc = unsafe { fcb_append(fcb, data.len() as u16, &mut loc) };
+        if rc == FCB_ERR_NOSPACE {
+            //  Evict the oldest records.
+            self.rotate() ? ;
+            rc = unsafe { fcb_append(fcb, data.len() as u16, &mut loc) };
+        }
+        fcb_result(rc) ? ;
+        let rc = unsafe {
+            flash_area_write(loc.fe_area, loc.fe_data_off, data.as_ptr() as *const ::cty::c_void, data.len() as u32)
+        };
+        if rc != 0 { return Err(MynewtError::SYS_EIO); }
+        fcb_result(unsafe { fcb_append_finish(fcb, &mut loc) })
+    }
+
+    /// Call `f` with each record, from the oldest to the newest. Records with a bad CRC are skipped. Stops at the
+    /// first error returned by `f`.
+    pub fn for_each<F: FnMut(&[u8]) -> MynewtResult<()>>(&'static self, mut f: F) -> MynewtResult<()> {
+        let fcb = self.fcb() ? ;
+        let mut loc = fcb_entry::EMPTY;  //  Start from the oldest record
+        let mut buf = [0u8; MAX_RECORD_SIZE];
+        loop {
+            match unsafe { fcb_getnext(fcb, &mut loc) } {
+                0 => {}
+                FCB_ERR_NOVAR => return Ok(()),  //  No more records
+                rc => return fcb_result(rc),
+            }
+            let len = core::cmp::min(loc.fe_data_len as usize, MAX_RECORD_SIZE);
+            let rc = unsafe {
+                flash_area_read(loc.fe_area, loc.fe_data_off, buf.as_mut_ptr() as *mut ::cty::c_void, len as u32)
+            };
+            if rc != 0 { return Err(MynewtError::SYS_EIO); }
+            f(&buf[..len]) ? ;
+        }
+    }
+
+    /// Return the number of records
+    pub fn len(&'static self) -> MynewtResult<usize> {
+        let mut count = 0;
+        self.for_each(|_| { count += 1; Ok(()) }) ? ;
+        Ok(count)
+    }
+
+    /// Return true if there are no records
+    pub fn is_empty(&'static self) -> MynewtResult<bool> {
+        Ok(unsafe { fcb_is_empty(self.fcb() ? ) } != 0)
+    }
+
+    /// Erase the oldest sector, which drops the oldest records
+    pub fn rotate(&'static self) -> MynewtResult<()> {
+        fcb_result(unsafe { fcb_rotate(self.fcb() ? ) })
+    }
+
+    /// Erase all records
+    pub fn clear(&'static self) -> MynewtResult<()> {
+        fcb_result(unsafe { fcb_clear(self.fcb() ? ) })
+    }
+
+    /// Return the Mynewt circular buffer, or `SYS_EAGAIN` before `init()`
+    fn fcb(&'static self) -> MynewtResult<*mut fcb> {
+        let state = unsafe { &mut *self.state.get() };
+        if !state.initialised { return Err(MynewtError::SYS_EAGAIN); }
+        Ok(&mut state.fcb as *mut fcb)
+    }
+}
+
+/// Convert an FCB error code to a `MynewtResult`
+fn fcb_result(rc: i32) -> MynewtResult<()> {
+    match rc {
+        0               => Ok(()),
+        FCB_ERR_ARGS    => Err(MynewtError::SYS_EINVAL),
+        FCB_ERR_FLASH   => Err(MynewtError::SYS_EIO),
+        FCB_ERR_NOVAR   => Err(MynewtError::SYS_ENOENT),
+        FCB_ERR_NOSPACE => Err(MynewtError::SYS_ENOMEM),
+        FCB_ERR_NOMEM   => Err(MynewtError::SYS_ENOMEM),
+        FCB_ERR_CRC     => Err(MynewtError::SYS_EIO),
+        FCB_ERR_MAGIC   => Err(MynewtError::SYS_EINVAL),
+        FCB_ERR_VERSION => Err(MynewtError::SYS_EINVAL),
+        _               => Err(MynewtError::SYS_EUNKNOWN),
+    }
+}
+
+/// Sector of a flash device. From `sys/flash_map/include/flash_map/flash_map.h` in Mynewt 1.7
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(non_camel_case_types)]
+struct flash_area {
+    fa_id:        u8,
+    fa_device_id: u8,
+    pad16:        u16,
+    fa_off:       u32,
+    fa_size:      u32,
+}
+
+impl flash_area {
+    /// Unused sector
+    const EMPTY: flash_area = flash_area { fa_id: 0, fa_device_id: 0, pad16: 0, fa_off: 0, fa_size: 0 };
+}
+
+/// Location of a record. From `fs/fcb/include/fcb/fcb.h` in Mynewt 1.7
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct fcb_entry {
+    fe_area:     *mut flash_area,
+    fe_elem_off: u32,
+    fe_data_off: u32,
+    fe_data_len: u16,
+}
+
+impl fcb_entry {
+    /// No location. `fcb_getnext()` returns the oldest record.
+    const EMPTY: fcb_entry = fcb_entry {
+        fe_area: core::ptr::null_mut(), fe_elem_off: 0, fe_data_off: 0, fe_data_len: 0,
+    };
+}
+
+/// Flash circular buffer. From `fs/fcb/include/fcb/fcb.h` in Mynewt 1.7
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct fcb {
+    f_magic:       u32,
+    f_version:     u8,
+    f_sector_cnt:  u8,
+    f_scratch_cnt: u8,
+    f_sectors:     *mut flash_area,
+    f_mtx:         os::os_mutex,
+    f_oldest:      *mut flash_area,
+    f_active:      fcb_entry,
+    f_active_id:   u16,
+    f_align:       u8,
+}
+
+extern "C" {
+    /// Find the oldest and newest records. C API: `int fcb_init(struct fcb *fcb)`
+    fn fcb_init(fcb: *mut fcb) -> ::cty::c_int;
+    /// Reserve space for a record. C API: `int fcb_append(struct fcb *, uint16_t len, struct fcb_entry *loc)`
+    fn fcb_append(fcb: *mut fcb, len: u16, loc: *mut fcb_entry) -> ::cty::c_int;
+    /// Write the CRC of the record. C API: `int fcb_append_finish(struct fcb *, struct fcb_entry *append_loc)`
+    fn fcb_append_finish(fcb: *mut fcb, append_loc: *mut fcb_entry) -> ::cty::c_int;
+    /// Move to the next record. C API: `int fcb_getnext(struct fcb *, struct fcb_entry *loc)`
+    fn fcb_getnext(fcb: *mut fcb, loc: *mut fcb_entry) -> ::cty::c_int;
+    /// Erase the oldest sector. C API: `int fcb_rotate(struct fcb *)`
+    fn fcb_rotate(fcb: *mut fcb) -> ::cty::c_int;
+    /// Erase all sectors. C API: `int fcb_clear(struct fcb *fcb)`
+    fn fcb_clear(fcb: *mut fcb) -> ::cty::c_int;
+    /// Return nonzero if there are no records. C API: `int fcb_is_empty(struct fcb *fcb)`
+    fn fcb_is_empty(fcb: *mut fcb) -> ::cty::c_int;
+    /// Read from a sector.
+    /// C API: `int flash_area_read(const struct flash_area *, uint32_t off, void *dst, uint32_t len)`
+    fn flash_area_read(fa: *const flash_area, off: u32, dst: *mut ::cty::c_void, len: u32) -> ::cty::c_int;
+    /// Write to a sector.
+    /// C API: `int flash_area_write(const struct flash_area *, uint32_t off, const void *src, uint32_t len)`
+    fn flash_area_write(fa: *const flash_area, off: u32, src: *const ::cty::c_void, len: u32) -> ::cty::c_int;
+}
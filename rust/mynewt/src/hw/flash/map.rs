@@ -0,0 +1,122 @@
+//! Named flash regions across the nRF52 Internal Flash ROM and the External SPI Flash, with a common
+//! read / write / erase API. Higher layers use the regions instead of hard-coding absolute addresses.
+//! Offsets are relative to the start of each region and are checked against the region size.
+//! Must sync with the flash map in `hw/bsp/nrf52/bsp.yml`.
+
+use crate::{
+    result::*,
+    hw::flash::{ self, INTERNAL_FLASH, EXTERNAL_FLASH },
+};
+
+/// Common API for reading, writing and erasing flash storage
+pub trait Storage {
+    /// Read `buf.len()` bytes at `offset` into `buf`
+    fn read(&self, offset: u32, buf: &mut [u8]) -> MynewtResult<()>;
+    /// Write the bytes in `buf` at `offset`. The flash must have been erased.
+    fn write(&self, offset: u32, buf: &[u8]) -> MynewtResult<()>;
+    /// Erase `len` bytes at `offset`. All sectors touched by the range will be erased.
+    fn erase(&self, offset: u32, len: u32) -> MynewtResult<()>;
+    /// Return the size of the storage in bytes
+    fn size(&self) -> u32;
+}
+
+/// A named region of a flash device
+#[derive(Clone, Copy)]
+pub struct Region {
+    /// Name of the region
+    pub name:     &'static str,
+    /// Flash device: `INTERNAL_FLASH` or `EXTERNAL_FLASH`
+    pub flash_id: u8,
+    /// Absolute offset of the region in the flash device
+    pub offset:   u32,
+    /// Size of the region in bytes
+    pub size:     u32,
+}
+
+/// MCUBoot Bootloader
+pub const BOOTLOADER: Region = Region { name: "boot",   flash_id: INTERNAL_FLASH, offset: 0x0000_0000, size: 24 * 1024 };
+
+/// Reboot log, for logging debug messages during startup
+pub const REBOOT_LOG: Region = Region { name: "reboot", flash_id: INTERNAL_FLASH, offset: 0x0000_6000, size: 8 * 1024 };
+
+/// Active Firmware Image
+pub const IMAGE_0: Region    = Region { name: "image0", flash_id: INTERNAL_FLASH, offset: 0x0000_8000, size: 464 * 1024 };
+
+/// Scratch area used by MCUBoot for swapping Active and Standby Firmware
+pub const SCRATCH: Region    = Region { name: "scratch", flash_id: INTERNAL_FLASH, offset: 0x0007_c000, size: 4 * 1024 };
+
+/// Crash dumps, written by `sys::crash_dump` on panic and fault, in the Internal Flash ROM after the Scratch area
+pub const CRASH_LOG: Region  = Region { name: "crash",  flash_id: INTERNAL_FLASH, offset: 0x0007_d000, size: 4 * 1024 };
+
+/// Bootloader Assets, like the Boot Logo
+pub const LOGO: Region       = Region { name: "logo",   flash_id: EXTERNAL_FLASH, offset: 0x0000_0000, size: 256 * 1024 };
+
+/// Standby Firmware Image
+pub const IMAGE_1: Region    = Region { name: "image1", flash_id: EXTERNAL_FLASH, offset: 0x0004_0000, size: 464 * 1024 };
+
+/// Asset bundle with images, fonts and animations
+pub const ASSETS: Region     = Region { name: "assets", flash_id: EXTERNAL_FLASH, offset: 0x000b_4000, size: 512 * 1024 };
+
+/// Settings, the circular buffer of Mynewt `sys/config` at the start of the user area
+pub const SETTINGS: Region   = Region { name: "config", flash_id: EXTERNAL_FLASH, offset: 0x0013_4000, size: 64 * 1024 };
+
+/// User file system, formatted with littlefs by the Rust `fs` module
+pub const USER_FS: Region    = Region { name: "userfs", flash_id: EXTERNAL_FLASH, offset: 0x0014_4000, size: 2592 * 1024 };
+
+/// Sensor log, a `fcb::FlashLog` of the sensor readings that survives restarts
+pub const SENSOR_LOG: Region = Region { name: "senlog", flash_id: EXTERNAL_FLASH, offset: 0x003c_c000, size: 64 * 1024 };
+
+/// Event log, a `fcb::FlashLog` of the alerts and warnings that survives restarts
+pub const EVENT_LOG: Region  = Region { name: "evlog",  flash_id: EXTERNAL_FLASH, offset: 0x003d_c000, size: 64 * 1024 };
+
+/// Spare area for destructive tests like the flash benchmark, erased
+pub const SPARE: Region      = Region { name: "spare",  flash_id: EXTERNAL_FLASH, offset: 0x003e_c000, size: 64 * 1024 };
+
+/// Battery history, a circular buffer of battery samples at the end of the External SPI Flash
+pub const BATTERY_LOG: Region = Region { name: "batlog", flash_id: EXTERNAL_FLASH, offset: 0x003f_c000, size: 16 * 1024 };
+
+/// All flash regions
+pub const REGIONS: [Region; 14] = [
+    BOOTLOADER, REBOOT_LOG, IMAGE_0, SCRATCH, CRASH_LOG, LOGO, IMAGE_1, ASSETS, SETTINGS, USER_FS, SENSOR_LOG, EVENT_LOG,
+    SPARE, BATTERY_LOG,
+];
+
+/// Return the flash region with the name, or `None` if not found
+pub fn find(name: &str) -> Option<&'static Region> {
+    REGIONS.iter().find(|r| r.name == name)
+}
+
+impl Region {
+    /// Return the absolute offset in the flash device for `offset` in the region.
+    /// Returns `Err(SYS_ERANGE)` if `len` bytes at `offset` don't fit in the region.
+    pub fn address(&self, offset: u32, len: u32) -> MynewtResult<u32> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size => Ok(self.offset + offset),
+            _ => Err(MynewtError::SYS_ERANGE),
+        }
+    }
+
+    /// Return true if `len` bytes at the absolute `offset` in flash device `flash_id` overlap this region
+    pub fn overlaps(&self, flash_id: u8, offset: u32, len: u32) -> bool {
+        self.flash_id == flash_id && offset < self.offset + self.size && self.offset < offset + len
+    }
+}
+
+impl Storage for Region {
+    fn read(&self, offset: u32, buf: &mut [u8]) -> MynewtResult<()> {
+        let addr = self.address(offset, buf.len() as u32) ? ;
+        flash::read(self.flash_id, addr, buf)
+    }
+
+    fn write(&self, offset: u32, buf: &[u8]) -> MynewtResult<()> {
+        let addr = self.address(offset, buf.len() as u32) ? ;
+        flash::write(self.flash_id, addr, buf)
+    }
+
+    fn erase(&self, offset: u32, len: u32) -> MynewtResult<()> {
+        let addr = self.address(offset, len) ? ;
+        flash::erase(self.flash_id, addr, len)
+    }
+
+    fn size(&self) -> u32 { self.size }
+}
@@ -20,6 +20,51 @@ mod bindings;  //  Import `bindings.rs` containing the bindings
 /// Export all bindings. TODO: Export only the API bindings.
 pub use self::bindings::*;
 
+/// Typed units for sensor readings
+pub mod units;  //  Export `units.rs` as Rust module `mynewt::hw::sensor::units`
+
+/// Export the units as `mynewt::hw::sensor::MilliCelsius`, ...
+pub use self::units::{ Bpm, MilliCelsius, MilliG, MilliVolts };
+
+/// Sensor listeners with Rust callbacks
+pub mod listener;  //  Export `listener.rs` as Rust module `mynewt::hw::sensor::listener`
+
+/// Export the listener API as `mynewt::hw::sensor::Listener`
+pub use self::listener::{ Listener, Reading };
+
+/// Virtual sensors with readings computed in Rust
+pub mod virtual_sensor;  //  Export `virtual_sensor.rs` as Rust module `mynewt::hw::sensor::virtual_sensor`
+
+/// Export the virtual sensor API as `mynewt::hw::sensor::VirtualSensor`
+pub use self::virtual_sensor::VirtualSensor;
+
+/// Sensor polling with an interval per sensor
+pub mod poller;  //  Export `poller.rs` as Rust module `mynewt::hw::sensor::poller`
+
+/// Sensor readings kept on the device
+pub mod history;  //  Export `history.rs` as Rust module `mynewt::hw::sensor::history`
+
+/// Export the history API as `mynewt::hw::sensor::History`
+pub use self::history::History;
+
+/// Sensor calibration persisted as settings
+pub mod calibration;  //  Export `calibration.rs` as Rust module `mynewt::hw::sensor::calibration`
+
+/// Export the calibration API as `mynewt::hw::sensor::Calibration`
+pub use self::calibration::Calibration;
+
+/// Threshold alerts for sensor readings
+pub mod alert;  //  Export `alert.rs` as Rust module `mynewt::hw::sensor::alert`
+
+/// Export the alert API as `mynewt::hw::sensor::Alert`
+pub use self::alert::{ Alert, AlertEvent, Threshold };
+
+/// Downsampling of sensor readings before transmission
+pub mod aggregate;  //  Export `aggregate.rs` as Rust module `mynewt::hw::sensor::aggregate`
+
+/// Export the aggregation API as `mynewt::hw::sensor::Aggregator`
+pub use self::aggregate::{ Aggregation, Aggregator };
+
 ///  Convert the sensor data received from Mynewt into a `SensorValue` for transmission, which includes the sensor data key. 
 ///  `sensor_type` indicates the type of data in `sensor_data`.
 #[allow(non_snake_case, unused_variables)]
@@ -218,7 +263,7 @@ fn null_sensor_value_func(
     { Ok(()) }
 
 ///  Import the custom interop helper library at `libs/mynewt_rust`
-#[link(name = "libs_mynewt_rust")]  //  Functions below are located in the Mynewt build output `libs_mynewt_rust.a`
+#[cfg_attr(not(feature = "sim"), link(name = "libs_mynewt_rust"))]  //  Functions below are located in the Mynewt build output `libs_mynewt_rust.a`
 extern {
     ///  Interpret `sensor_data` as a `sensor_temp_raw_data` struct that contains raw temp.
     ///  Copy the sensor data into `dest`.  Return 0 if successful.
@@ -252,12 +297,22 @@ extern {
     pub fn is_null_sensor_data(sensor_data: sensor_data_ptr) -> bool;
 }
 
-///  Sensor type for raw temperature sensor and geolocation.
+///  Sensor type for raw temperature sensor, geolocation, heart rate, battery, step count and orientation.
 ///  Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
 pub const SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW: sensor_type_t = 
     crate::libs::mynewt_rust::sensor_type_t_SENSOR_TYPE_USER_DEFINED_1;
 pub const SENSOR_TYPE_GEOLOCATION: sensor_type_t =
     crate::libs::mynewt_rust::sensor_type_t_SENSOR_TYPE_USER_DEFINED_2;
+pub const SENSOR_TYPE_HEART_RATE: sensor_type_t =
+    crate::libs::mynewt_rust::sensor_type_t_SENSOR_TYPE_USER_DEFINED_3;
+pub const SENSOR_TYPE_BATTERY: sensor_type_t =
+    crate::libs::mynewt_rust::sensor_type_t_SENSOR_TYPE_USER_DEFINED_4;
+pub const SENSOR_TYPE_STEPS: sensor_type_t =
+    crate::libs::mynewt_rust::sensor_type_t_SENSOR_TYPE_USER_DEFINED_5;
+pub const SENSOR_TYPE_ORIENTATION: sensor_type_t =
+    crate::libs::mynewt_rust::sensor_type_t_SENSOR_TYPE_USER_DEFINED_6;
+///  The user-defined sensor types are used up, so this is the unassigned standard type below them
+pub const SENSOR_TYPE_LINK_QUALITY: sensor_type_t = 1 << 25;
 
 ///  Represents a decoded sensor data value. Since temperature may be integer (raw)
 ///  or float (computed), we use the struct to return both integer and float values.
@@ -288,16 +343,76 @@ impl Default for SensorValue {
 pub enum SensorValueType {
     ///  No value.
     None,
+    ///  32-bit signed integer, e.g. a temperature offset that may be negative
+    Int(i32),
     ///  32-bit unsigned integer. For raw temp, contains the raw temp integer value
     Uint(u32),
     ///  32-bit float. For computed temp, contains the computed temp float value
     #[cfg(feature = "use_float")]  //  If floating-point is enabled...
     Float(f32),
+    ///  Text string, e.g. a firmware version. Encoded as a JSON or CBOR text string.
+    Str(&'static Strn),
+    ///  Byte string, e.g. a hash. Encoded as a hex string in JSON and a byte string in CBOR.
+    Bytes(&'static [u8]),
     ///  Geolocation
     #[cfg(feature = "use_float")]  //  If floating-point is enabled...
     Geolocation { latitude: f64, longitude: f64, altitude: f64 },
 }
 
+///  Default sensor value type is `None`
+impl Default for SensorValueType {
+    fn default() -> Self { SensorValueType::None }
+}
+
+impl From<i32> for SensorValueType {
+    fn from(value: i32) -> Self { SensorValueType::Int(value) }
+}
+
+impl From<u32> for SensorValueType {
+    fn from(value: u32) -> Self { SensorValueType::Uint(value) }
+}
+
+#[cfg(feature = "use_float")]  //  If floating-point is enabled...
+impl From<f32> for SensorValueType {
+    fn from(value: f32) -> Self { SensorValueType::Float(value) }
+}
+
+impl From<&'static Strn> for SensorValueType {
+    fn from(value: &'static Strn) -> Self { SensorValueType::Str(value) }
+}
+
+impl From<&'static [u8]> for SensorValueType {
+    fn from(value: &'static [u8]) -> Self { SensorValueType::Bytes(value) }
+}
+
+impl SensorValueType {
+    ///  Return the value as an unsigned integer, or `None` if the value is not an integer or is negative
+    pub fn as_uint(&self) -> Option<u32> {
+        match *self {
+            SensorValueType::Uint(value) => Some(value),
+            SensorValueType::Int(value) if value >= 0 => Some(value as u32),
+            _ => None,
+        }
+    }
+
+    ///  Return the value as a signed integer, or `None` if the value is not an integer or is too large
+    pub fn as_int(&self) -> Option<i32> {
+        match *self {
+            SensorValueType::Int(value) => Some(value),
+            SensorValueType::Uint(value) if value <= i32::max_value() as u32 => Some(value as i32),
+            _ => None,
+        }
+    }
+
+    ///  Return true if there is no value
+    pub fn is_none(&self) -> bool {
+        match *self {
+            SensorValueType::None => true,
+            _ => false,
+        }
+    }
+}
+
 ///  Represents a single temperature sensor raw value.
 ///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
 #[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
@@ -308,6 +423,64 @@ pub struct sensor_temp_raw_data {
     pub strd_temp_raw_is_valid: u8,  
 }
 
+///  Represents a heart rate.
+///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
+#[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
+pub struct sensor_heart_rate_data {   
+    ///  Heart rate (beats per minute)
+    pub shrd_bpm: u32,          
+    ///  1 if heart rate is valid
+    pub shrd_bpm_is_valid: u8,  
+}
+
+///  Represents the battery voltage and charge level.
+///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
+#[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
+pub struct sensor_battery_data {   
+    ///  Battery voltage (millivolts)
+    pub sbd_mv: u32,          
+    ///  Charge level (percent)
+    pub sbd_percent: u8,
+    ///  1 if charging
+    pub sbd_charging: u8,
+    ///  1 if battery voltage is valid
+    pub sbd_mv_is_valid: u8,  
+}
+
+///  Represents the step count of the pedometer.
+///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
+#[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
+pub struct sensor_steps_data {   
+    ///  Steps counted today
+    pub ssd_steps: u32,          
+    ///  1 if step count is valid
+    pub ssd_steps_is_valid: u8,  
+}
+
+///  Represents the orientation of the watch: pitch and roll.
+///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
+#[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
+pub struct sensor_orientation_data {   
+    ///  Pitch (hundredths of a degree, -9000 to 9000)
+    pub sod_pitch: i32,
+    ///  Roll (hundredths of a degree, -18000 to 18000)
+    pub sod_roll: i32,
+    ///  1 if orientation is valid
+    pub sod_is_valid: u8,  
+}
+
+///  Represents the Bluetooth LE link quality: signal strength of the connection and advertising transmit power.
+///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
+#[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
+pub struct sensor_link_quality_data {   
+    ///  Signal strength of the weakest connection (dBm)
+    pub slqd_rssi: i32,
+    ///  Transmit power on the advertising channels (dBm)
+    pub slqd_adv_tx_power: i32,
+    ///  1 if the signal strength is valid, i.e. a phone is connected
+    pub slqd_rssi_is_valid: u8,  
+}
+
 ///  Represents a GPS Geolocation.
 ///  TODO: Must sync with libs/custom_sensor/include/custom_sensor/custom_sensor.h
 #[repr(C, packed)]  //  Common to C and Rust. Declare as packed because the C struct is packed.
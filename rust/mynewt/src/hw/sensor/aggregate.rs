@@ -0,0 +1,141 @@
+//! Downsampling of sensor readings before transmission. An `Aggregator` collects the integer readings of a sensor over
+//! a time window and returns one value for the window: the mean, min, max or last reading. High-rate sensors like the
+//! accelerometer may be sampled often on the device but reported rarely, so that the radio is used less.
+//! ```
+//! static ACCEL_AGGREGATOR: Aggregator = Aggregator::new(&ACCEL_SENSOR_KEY, Aggregation::Mean, Duration::from_secs(10));
+//! if let Some(value) = ACCEL_AGGREGATOR.add(&SensorValueType::Int(1024)) { HISTORY.record(value); }
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    time::Duration,
+};
+use crate::{
+    kernel::{
+        os,
+        time::Instant,
+    },
+    hw::sensor::SensorValueType,
+    Strn,
+};
+
+/// Value returned for each window of readings
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Aggregation {
+    /// Mean of the readings, rounded towards zero
+    Mean,
+    /// Smallest reading
+    Min,
+    /// Largest reading
+    Max,
+    /// Latest reading
+    Last,
+}
+
+/// Aggregates the readings of a sensor over a time window. Must be declared `static`, since the readings may be added
+/// and the aggregation changed by different tasks.
+pub struct Aggregator {
+    /// Key (field name) of the sensor
+    key: &'static Strn,
+    /// Window state, accessed with interrupts disabled
+    window: UnsafeCell<Window>,
+}
+
+/// Readings in the current window of an `Aggregator`
+struct Window {
+    /// Value returned for each window
+    aggregation: Aggregation,
+    /// Duration of each window
+    length: Duration,
+    /// OS time in ticks of the first reading in the window. Only valid if `count` is non-zero.
+    start: os::os_time_t,
+    /// Number of readings in the window
+    count: u32,
+    /// Sum of the readings
+    sum: i64,
+    /// Smallest reading
+    min: i32,
+    /// Largest reading
+    max: i32,
+    /// Latest reading
+    last: i32,
+}
+
+/// `Aggregator` may be shared between tasks
+unsafe impl Sync for Aggregator {}
+
+impl Aggregator {
+    /// Create an aggregator for the sensor with key `key` that returns one value for every `window` of readings
+    pub const fn new(key: &'static Strn, aggregation: Aggregation, window: Duration) -> Self {
+        Aggregator {
+            key,
+            window: UnsafeCell::new(Window {
+                aggregation,
+                length: window,
+                start: 0, count: 0, sum: 0, min: 0, max: 0, last: 0,
+            }),
+        }
+    }
+
+    /// Return the key (field name) of the sensor
+    pub fn key(&self) -> &'static Strn {
+        self.key
+    }
+
+    /// Change the value returned for each window. The readings in the current window are kept.
+    pub fn set_aggregation(&self, aggregation: Aggregation) {
+        self.with_window(|window| window.aggregation = aggregation)
+    }
+
+    /// Add the reading `value` at the current time. When the window has ended, return the value for the window and
+    /// start a new window with the reading. Readings that are not integers are ignored.
+    pub fn add(&self, value: &SensorValueType) -> Option<SensorValueType> {
+        let value = value.as_int() ? ;
+        let now = Instant::now();
+        self.with_window(|window| {
+            let ended = now.duration_since(Instant::from_ticks(window.start)) >= window.length;
+            let result = if window.count > 0 && ended { window.result() } else { None };
+            if result.is_some() || window.count == 0 {
+                *window = Window { start: now.ticks(), count: 0, sum: 0, min: value, max: value, ..*window };
+            }
+            window.count += 1;
+            window.sum += value as i64;
+            if value < window.min { window.min = value; }
+            if value > window.max { window.max = value; }
+            window.last = value;
+            result
+        })
+    }
+
+    /// Return the value for the readings in the current window and start a new window, e.g. before switching off the
+    /// sensor. Returns `None` if there are no readings.
+    pub fn flush(&self) -> Option<SensorValueType> {
+        self.with_window(|window| {
+            let result = window.result();
+            window.count = 0;
+            result
+        })
+    }
+
+    /// Call `func` with the window, with interrupts disabled
+    fn with_window<R, F: FnOnce(&mut Window) -> R>(&self, func: F) -> R {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let result = func(unsafe { &mut *self.window.get() });
+        unsafe { os::os_arch_restore_sr(sr) };
+        result
+    }
+}
+
+impl Window {
+    /// Return the value for the readings in the window, or `None` if there are no readings
+    fn result(&self) -> Option<SensorValueType> {
+        if self.count == 0 { return None; }
+        let value = match self.aggregation {
+            Aggregation::Mean => (self.sum / self.count as i64) as i32,
+            Aggregation::Min  => self.min,
+            Aggregation::Max  => self.max,
+            Aggregation::Last => self.last,
+        };
+        Some(SensorValueType::Int(value))
+    }
+}
@@ -0,0 +1,135 @@
+//! Threshold alerts for sensor readings. An `Alert` watches the readings of one sensor, identified by its key (field
+//! name), and fires once when a reading crosses its threshold: above a limit, below a limit, or changed by more than
+//! a delta since the last alert. Above and below alerts are armed again when the readings return past the limit by
+//! the hysteresis, so that a reading that hovers at the limit doesn't fire repeatedly. The caller decides what to do
+//! with the `AlertEvent`, e.g. send it to the CoAP server at once, without waiting for the next report.
+//! ```
+//! static HR_HIGH: Alert = Alert::new(&HR_SENSOR_KEY, Threshold::Above(150), 5);
+//! if let Some(event) = HR_HIGH.check(&SensorValueType::Uint(160)) { ... }
+//! HR_HIGH.set_threshold(Threshold::Disabled);
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    kernel::os,
+    hw::sensor::SensorValueType,
+    Strn,
+};
+
+/// Condition that fires an alert
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Threshold {
+    /// Never fires
+    Disabled,
+    /// Fires when the reading rises above the limit
+    Above(i32),
+    /// Fires when the reading falls below the limit
+    Below(i32),
+    /// Fires when the reading differs from the reading at the last alert by more than the delta.
+    /// The first reading is the reference.
+    Delta(u32),
+}
+
+/// Alert fired by a reading
+#[derive(Clone, Copy)]
+pub struct AlertEvent {
+    /// Key (field name) of the sensor, e.g. `hr` for heart rate
+    pub key: &'static Strn,
+    /// Threshold that was crossed
+    pub threshold: Threshold,
+    /// Reading that crossed the threshold
+    pub value: i32,
+}
+
+/// Alert for the readings of a sensor. Must be declared `static`, since the threshold may be changed by another task.
+pub struct Alert {
+    /// Key (field name) of the sensor
+    key: &'static Strn,
+    /// Threshold and state, accessed with interrupts disabled
+    state: UnsafeCell<State>,
+}
+
+/// Threshold and state of an `Alert`
+struct State {
+    /// Condition that fires the alert
+    threshold: Threshold,
+    /// Distance that the readings must return past the limit to arm the alert again
+    hysteresis: u32,
+    /// True if the alert has fired and is not armed
+    fired: bool,
+    /// Reading at the last alert, for `Delta`
+    reference: Option<i32>,
+}
+
+/// `Alert` may be shared between tasks
+unsafe impl Sync for Alert {}
+
+impl Alert {
+    /// Create an alert for the sensor with key `key`
+    pub const fn new(key: &'static Strn, threshold: Threshold, hysteresis: u32) -> Self {
+        Alert {
+            key,
+            state: UnsafeCell::new(State { threshold, hysteresis, fired: false, reference: None }),
+        }
+    }
+
+    /// Return the key (field name) of the sensor
+    pub fn key(&self) -> &'static Strn {
+        self.key
+    }
+
+    /// Return the threshold
+    pub fn threshold(&self) -> Threshold {
+        self.with_state(|state| state.threshold)
+    }
+
+    /// Change the threshold and arm the alert
+    pub fn set_threshold(&self, threshold: Threshold) {
+        self.with_state(|state| {
+            state.threshold = threshold;
+            state.fired = false;
+            state.reference = None;
+        })
+    }
+
+    /// Check the reading `value` of the sensor. Return the alert event if the threshold has been crossed.
+    /// Readings that are not integers are ignored.
+    pub fn check(&self, value: &SensorValueType) -> Option<AlertEvent> {
+        let value = value.as_int() ? ;
+        let threshold = self.with_state(|state| {
+            let fire = match state.threshold {
+                Threshold::Disabled => false,
+                Threshold::Above(limit) => crossed(&mut state.fired, value > limit,
+                    (value as i64) < limit as i64 - state.hysteresis as i64),
+                Threshold::Below(limit) => crossed(&mut state.fired, value < limit,
+                    value as i64 > limit as i64 + state.hysteresis as i64),
+                Threshold::Delta(delta) => match state.reference {
+                    None => { state.reference = Some(value); false }
+                    Some(reference) => {
+                        let fire = (value as i64 - reference as i64).abs() > delta as i64;
+                        if fire { state.reference = Some(value); }
+                        fire
+                    }
+                },
+            };
+            if fire { Some(state.threshold) } else { None }
+        }) ? ;
+        Some(AlertEvent { key: self.key, threshold, value })
+    }
+
+    /// Call `func` with the state, with interrupts disabled
+    fn with_state<R, F: FnOnce(&mut State) -> R>(&self, func: F) -> R {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let result = func(unsafe { &mut *self.state.get() });
+        unsafe { os::os_arch_restore_sr(sr) };
+        result
+    }
+}
+
+/// Return true if the alert should fire: the reading is beyond the limit and the alert is armed.
+/// Arm the alert again when the reading is back past the hysteresis.
+fn crossed(fired: &mut bool, beyond: bool, rearmed: bool) -> bool {
+    if beyond && !*fired { *fired = true; return true; }
+    if rearmed { *fired = false; }
+    false
+}
@@ -0,0 +1,92 @@
+//! Sensor calibration persisted with `sys/config`. `Calibration` holds the offset and scale of a sensor value as two
+//! settings, e.g. `app/temp_off` and `app/temp_scale`, so that the calibration survives restarts and may be changed over
+//! newtmgr. The calibrated value is `value * scale / 1000 + offset`. A `Listener` created with `with_calibration()`
+//! applies the calibration to each reading before calling the Rust function.
+//! ```
+//! static TEMP_CALIBRATION: Calibration = Calibration::new("temp_off", "temp_scale");
+//! TEMP_CALIBRATION.register() ? ;  //  Before `config::load()`
+//! TEMP_CALIBRATION.set(-150, 1000) ? ;  //  Saved to flash
+//! static TEMP_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> = Listener::with_calibration(send_temperature, &TEMP_CALIBRATION);
+//! ```
+
+use crate::{
+    result::*,
+    hw::sensor::{
+        Reading,
+        units::{ MilliCelsius, MilliVolts },
+    },
+    sys::config::Setting,
+};
+
+/// Scale that leaves the value unchanged, in parts per thousand
+pub const UNIT_SCALE: u32 = 1000;
+
+/// Offset and scale of a sensor value, persisted as settings
+pub struct Calibration {
+    /// Offset added after scaling, in the units of the sensor value
+    offset: Setting<i32>,
+    /// Scale in parts per thousand
+    scale: Setting<u32>,
+}
+
+impl Calibration {
+    /// Create a calibration stored in the settings `offset_name` and `scale_name`. Defaults to no calibration.
+    pub const fn new(offset_name: &'static str, scale_name: &'static str) -> Self {
+        Calibration {
+            offset: Setting::new(offset_name, "0"),
+            scale:  Setting::new(scale_name, "1000"),
+        }
+    }
+
+    /// Register the settings, so that `config::load()` restores the saved calibration
+    pub fn register(&'static self) -> MynewtResult<()> {
+        self.offset.register() ? ;
+        self.scale.register()
+    }
+
+    /// Return the offset and the scale in parts per thousand
+    pub fn get(&'static self) -> (i32, u32) {
+        (self.offset.get(), self.scale.get())
+    }
+
+    /// Change the offset and scale and save them to flash
+    pub fn set(&'static self, offset: i32, scale: u32) -> MynewtResult<()> {
+        self.offset.set(offset) ? ;
+        self.scale.set(scale)
+    }
+
+    /// Remove the calibration and delete the saved settings
+    pub fn reset(&'static self) -> MynewtResult<()> {
+        self.offset.reset() ? ;
+        self.scale.reset()
+    }
+
+    /// Return the calibrated value
+    pub fn apply(&'static self, value: i32) -> i32 {
+        let (offset, scale) = self.get();
+        let scaled = value as i64 * scale as i64 / UNIT_SCALE as i64 + offset as i64;
+        if scaled > i32::max_value() as i64 { i32::max_value() }
+        else if scaled < i32::min_value() as i64 { i32::min_value() }
+        else { scaled as i32 }
+    }
+
+    /// Return the reading with the calibrated value. Temperature is calibrated in hundredths of a degree Celsius and
+    /// battery voltage in millivolts, clamped at 0. Readings without a single integer value, e.g. acceleration, are
+    /// not changed.
+    pub fn apply_reading(&'static self, reading: &Reading) -> Reading {
+        match *reading {
+            Reading::TempRaw(temp) =>
+                Reading::TempRaw(MilliCelsius::from_centi_celsius(self.apply(temp.centi_celsius()))),
+            Reading::Battery { mv: MilliVolts(mv), percent, charging } =>
+                Reading::Battery { mv: MilliVolts(self.apply_unsigned(mv)), percent, charging },
+            other => other,
+        }
+    }
+
+    /// Return the calibrated unsigned value, clamped at 0
+    fn apply_unsigned(&'static self, value: u32) -> u32 {
+        let value = if value > i32::max_value() as u32 { i32::max_value() } else { value as i32 };
+        let calibrated = self.apply(value);
+        if calibrated < 0 { 0 } else { calibrated as u32 }
+    }
+}
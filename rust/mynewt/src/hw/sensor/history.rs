@@ -0,0 +1,191 @@
+//! Sensor history kept on the device. `History` is a fixed-capacity ring buffer that keeps the last `HISTORY_SIZE`
+//! timestamped readings of a sensor, overwriting the oldest reading when full. Readings that could not be sent, e.g.
+//! while the network is down, are tracked as unsent so that they may be uploaded in one batch when the network is back.
+//! ```
+//! static TEMP_HISTORY: History = History::new(&TEMP_SENSOR_KEY);
+//! TEMP_HISTORY.record(SensorValueType::Uint(2870));
+//! if let Some(summary) = TEMP_HISTORY.summary() { ... }
+//! TEMP_HISTORY.for_each_unsent(MAX_BATCH, |sample| { ... });
+//! TEMP_HISTORY.mark_sent(count);
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    kernel::{
+        os,
+        time::Instant,
+    },
+    hw::sensor::SensorValueType,
+    Strn,
+};
+
+/// Number of readings kept for each sensor
+pub const HISTORY_SIZE: usize = 32;
+
+/// Reading of a sensor and the time it was recorded
+#[derive(Clone, Copy)]
+pub struct Sample {
+    /// OS time in ticks when the reading was recorded
+    ticks: os::os_time_t,
+    /// The sensor value
+    pub value: SensorValueType,
+}
+
+impl Sample {
+    /// Return the time when the reading was recorded
+    pub fn time(&self) -> Instant {
+        Instant::from_ticks(self.ticks)
+    }
+}
+
+/// Min, max and mean of the integer readings in a `History`
+#[derive(Clone, Copy, Debug)]
+pub struct Summary {
+    /// Number of integer readings
+    pub count: usize,
+    /// Smallest reading
+    pub min: u32,
+    /// Largest reading
+    pub max: u32,
+    /// Mean of the readings, rounded down
+    pub mean: u32,
+}
+
+/// Ring buffer with the last `HISTORY_SIZE` readings of a sensor. Must be declared `static`, since the readings are
+/// recorded and uploaded by different tasks.
+pub struct History {
+    /// Key (field name) of the sensor, e.g. `t` for raw temperature
+    key: &'static Strn,
+    /// Ring buffer state, accessed with interrupts disabled
+    ring: UnsafeCell<Ring>,
+}
+
+/// Readings in a `History`
+struct Ring {
+    /// The readings. Only the `len` readings before `next` are valid.
+    samples: [Sample; HISTORY_SIZE],
+    /// Index of the next reading to be recorded
+    next: usize,
+    /// Number of valid readings
+    len: usize,
+    /// Number of the latest readings that have not been sent
+    unsent: usize,
+}
+
+/// `History` may be shared between tasks
+unsafe impl Sync for History {}
+
+impl History {
+    /// Create an empty history for the sensor with key `key`
+    pub const fn new(key: &'static Strn) -> Self {
+        History {
+            key,
+            ring: UnsafeCell::new(Ring {
+                samples: [Sample { ticks: 0, value: SensorValueType::None }; HISTORY_SIZE],
+                next: 0,
+                len: 0,
+                unsent: 0,
+            }),
+        }
+    }
+
+    /// Return the key (field name) of the sensor
+    pub fn key(&self) -> &'static Strn {
+        self.key
+    }
+
+    /// Record the reading `value` at the current time as unsent. Overwrites the oldest reading if the history is full.
+    pub fn record(&self, value: SensorValueType) {
+        let sample = Sample { ticks: Instant::now().ticks(), value };
+        self.with_ring(|ring| {
+            ring.samples[ring.next] = sample;
+            ring.next = (ring.next + 1) % HISTORY_SIZE;
+            if ring.len < HISTORY_SIZE { ring.len += 1; }
+            if ring.unsent < HISTORY_SIZE { ring.unsent += 1; }
+        })
+    }
+
+    /// Return the number of readings in the history
+    pub fn len(&self) -> usize {
+        self.with_ring(|ring| ring.len)
+    }
+
+    /// Return true if there are no readings
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the number of readings that have not been sent
+    pub fn unsent(&self) -> usize {
+        self.with_ring(|ring| ring.unsent)
+    }
+
+    /// Mark the oldest `count` unsent readings as sent, e.g. after uploading them with `for_each_unsent()`
+    pub fn mark_sent(&self, count: usize) {
+        self.with_ring(|ring| ring.unsent -= core::cmp::min(count, ring.unsent))
+    }
+
+    /// Remove all readings
+    pub fn clear(&self) {
+        self.with_ring(|ring| { ring.len = 0; ring.unsent = 0; })
+    }
+
+    /// Call `func` with each reading, oldest first
+    pub fn for_each<F: FnMut(&Sample)>(&self, func: F) {
+        self.for_each_latest(false, HISTORY_SIZE, func);
+    }
+
+    /// Call `func` with the oldest unsent readings, up to `max` readings. Returns the number of readings passed to `func`.
+    pub fn for_each_unsent<F: FnMut(&Sample)>(&self, max: usize, func: F) -> usize {
+        self.for_each_latest(true, max, func)
+    }
+
+    /// Return the min, max and mean of the integer readings, or `None` if there are no integer readings
+    pub fn summary(&self) -> Option<Summary> {
+        let mut count = 0;
+        let mut min = u32::max_value();
+        let mut max = 0;
+        let mut sum: u64 = 0;
+        self.for_each(|sample| {
+            if let SensorValueType::Uint(value) = sample.value {
+                count += 1;
+                if value < min { min = value; }
+                if value > max { max = value; }
+                sum += value as u64;
+            }
+        });
+        if count == 0 { return None; }
+        Some(Summary { count, min, max, mean: (sum / count as u64) as u32 })
+    }
+
+    /// Call `func` with up to `max` readings, starting from the oldest unsent reading if `unsent_only`, else the
+    /// oldest reading. Each reading is copied with interrupts disabled, so `func` may take its time.
+    fn for_each_latest<F: FnMut(&Sample)>(&self, unsent_only: bool, max: usize, mut func: F) -> usize {
+        //  Index of the first reading. Readings recorded while we are iterating are appended after the last reading.
+        let (latest, start) = self.with_ring(|ring| {
+            let latest = if unsent_only { ring.unsent } else { ring.len };
+            (latest, (ring.next + HISTORY_SIZE - latest) % HISTORY_SIZE)
+        });
+        let count = core::cmp::min(latest, max);
+        for i in 0..count {
+            //  Stop if the readings were cleared while we were iterating.
+            let sample = self.with_ring(|ring| {
+                if ring.len < latest { return None; }
+                Some(ring.samples[(start + i) % HISTORY_SIZE])
+            });
+            match sample {
+                Some(sample) => func(&sample),
+                None => return i,
+            }
+        }
+        count
+    }
+
+    /// Call `func` with the ring buffer, with interrupts disabled
+    fn with_ring<R, F: FnOnce(&mut Ring) -> R>(&self, func: F) -> R {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let result = func(unsafe { &mut *self.ring.get() });
+        unsafe { os::os_arch_restore_sr(sr) };
+        result
+    }
+}
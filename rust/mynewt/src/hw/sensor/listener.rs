@@ -0,0 +1,247 @@
+//! Sensor listeners with Rust callbacks. `Listener` owns the Mynewt `sensor_listener` and calls a Rust function or
+//! closure with a typed `Reading` each time the sensor is polled, through a C callback trampoline.
+//! Replaces `new_sensor_listener()` and `register_listener()`, which are limited to 2 listeners in a static table.
+//! ```
+//! static TEMP_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> = Listener::new(handle_temp);
+//! TEMP_LISTENER.register(&strn!("temp_stub_0"), SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW) ? ;
+//! ```
+
+use core::cell::UnsafeCell;
+use crate::{
+    result::*,
+    defmt_trace,
+    hw::{
+        sensor::{
+            self,
+            Calibration,
+            sensor_data_ptr, sensor_listener, sensor_listener__bindgen_ty_1, sensor_ptr, sensor_type_t,
+            sensor_battery_data, sensor_heart_rate_data, sensor_link_quality_data, sensor_orientation_data,
+            sensor_steps_data, sensor_temp_raw_data,
+            SensorValue, SensorValueType,
+            units::{ Bpm, MilliCelsius, MilliVolts },
+            SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW, SENSOR_TYPE_BATTERY, SENSOR_TYPE_HEART_RATE, SENSOR_TYPE_LINK_QUALITY,
+            SENSOR_TYPE_ORIENTATION, SENSOR_TYPE_STEPS,
+        },
+        sensor_mgr,
+    },
+    Strn,
+};
+#[cfg(feature = "use_float")]  //  If floating-point is enabled...
+use crate::hw::sensor::{
+    sensor_accel_data, sensor_geolocation_data, sensor_temp_data,
+    sensor_type_t_SENSOR_TYPE_ACCELEROMETER, sensor_type_t_SENSOR_TYPE_AMBIENT_TEMPERATURE,
+    SENSOR_TYPE_GEOLOCATION,
+};
+
+/// Sensor data converted from Mynewt `sensor_data`
+#[derive(Clone, Copy, Debug)]
+pub enum Reading {
+    /// Temperature from the raw temperature sensor, which returns hundredths of a degree Celsius
+    TempRaw(MilliCelsius),
+    /// Heart rate
+    HeartRate(Bpm),
+    /// Battery voltage, charge level in percent, and whether the battery is charging
+    Battery { mv: MilliVolts, percent: u8, charging: bool },
+    /// Steps counted today
+    Steps(u32),
+    /// Pitch and roll in hundredths of a degree
+    Orientation { pitch: i32, roll: i32 },
+    /// Signal strength of the weakest Bluetooth LE connection and transmit power on the advertising channels, in dBm
+    LinkQuality { rssi: i32, adv_tx_power: i32 },
+    /// Temperature in degrees Celsius
+    #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+    Temp(f32),
+    /// Acceleration in m/s²
+    #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+    Accel { x: f32, y: f32, z: f32 },
+    /// GPS geolocation
+    #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+    Geolocation { latitude: f64, longitude: f64, altitude: f64 },
+    /// Sensor type that is not converted
+    Other(sensor_type_t),
+}
+
+impl Reading {
+    /// Convert the sensor data of type `sensor_type`. Returns `None` if the data is not valid, e.g. GPS not ready.
+    pub fn from_sensor_data(sensor_data: sensor_data_ptr, sensor_type: sensor_type_t) -> Option<Reading> {
+        if sensor_data.is_null() { return None; }
+        match sensor_type {
+            SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_temp_raw_data) };
+                if data.strd_temp_raw_is_valid == 0 { return None; }
+                Some(Reading::TempRaw(MilliCelsius::from_centi_celsius(data.strd_temp_raw as i32)))
+            }
+            SENSOR_TYPE_HEART_RATE => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_heart_rate_data) };
+                if data.shrd_bpm_is_valid == 0 { return None; }
+                Some(Reading::HeartRate(Bpm(data.shrd_bpm)))
+            }
+            SENSOR_TYPE_BATTERY => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_battery_data) };
+                if data.sbd_mv_is_valid == 0 { return None; }
+                Some(Reading::Battery { mv: MilliVolts(data.sbd_mv), percent: data.sbd_percent, charging: data.sbd_charging != 0 })
+            }
+            SENSOR_TYPE_STEPS => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_steps_data) };
+                if data.ssd_steps_is_valid == 0 { return None; }
+                Some(Reading::Steps(data.ssd_steps))
+            }
+            SENSOR_TYPE_ORIENTATION => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_orientation_data) };
+                if data.sod_is_valid == 0 { return None; }
+                Some(Reading::Orientation { pitch: data.sod_pitch, roll: data.sod_roll })
+            }
+            SENSOR_TYPE_LINK_QUALITY => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_link_quality_data) };
+                if data.slqd_rssi_is_valid == 0 { return None; }
+                Some(Reading::LinkQuality { rssi: data.slqd_rssi, adv_tx_power: data.slqd_adv_tx_power })
+            }
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            sensor_type_t_SENSOR_TYPE_AMBIENT_TEMPERATURE => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_temp_data) };
+                if data.std_temp_is_valid() == 0 { return None; }
+                Some(Reading::Temp(data.std_temp))
+            }
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            sensor_type_t_SENSOR_TYPE_ACCELEROMETER => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_accel_data) };
+                if data.sad_x_is_valid() == 0 || data.sad_y_is_valid() == 0 || data.sad_z_is_valid() == 0 { return None; }
+                Some(Reading::Accel { x: data.sad_x, y: data.sad_y, z: data.sad_z })
+            }
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            SENSOR_TYPE_GEOLOCATION => {
+                let data = unsafe { core::ptr::read(sensor_data as *const sensor_geolocation_data) };
+                if data.sgd_latitude_is_valid == 0 || data.sgd_longitude_is_valid == 0 || data.sgd_altitude_is_valid == 0 { return None; }
+                Some(Reading::Geolocation { latitude: data.sgd_latitude, longitude: data.sgd_longitude, altitude: data.sgd_altitude })
+            }
+            _ => Some(Reading::Other(sensor_type)),
+        }
+    }
+
+    /// Convert the reading to a `SensorValue` with field name `key`, for sending with `coap!()`. Temperature is sent
+    /// in hundredths of a degree Celsius, heart rate in beats per minute and battery voltage in millivolts.
+    pub fn to_sensor_value(&self, key: &'static Strn) -> SensorValue {
+        let value = match *self {
+            Reading::TempRaw(temp) => SensorValueType::Int(temp.centi_celsius()),
+            Reading::HeartRate(Bpm(bpm)) => SensorValueType::Uint(bpm),
+            Reading::Battery { mv: MilliVolts(mv), .. } => SensorValueType::Uint(mv),
+            Reading::Steps(steps) => SensorValueType::Uint(steps),
+            Reading::LinkQuality { rssi, .. } => SensorValueType::Int(rssi),
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            Reading::Temp(temp) => SensorValueType::Float(temp),
+            #[cfg(feature = "use_float")]  //  If floating-point is enabled...
+            Reading::Geolocation { latitude, longitude, altitude } =>
+                SensorValueType::Geolocation { latitude, longitude, altitude },
+            _ => SensorValueType::None,  //  No CoAP encoding for this type
+        };
+        SensorValue { key, value, geo: SensorValueType::None }
+    }
+}
+
+/// Listener that calls `F` with each reading of a sensor. Must be declared `static`, since Mynewt keeps a pointer
+/// to the `sensor_listener`.
+pub struct Listener<F> {
+    /// The Mynewt listener
+    listener: UnsafeCell<sensor_listener>,
+    /// Sensor that the listener is registered with, or null
+    sensor: UnsafeCell<sensor_ptr>,
+    /// Function or closure to be called with each reading
+    func: UnsafeCell<F>,
+    /// Calibration applied to each reading before calling the function, if any
+    calibration: Option<&'static Calibration>,
+}
+
+/// `Listener` may be shared between tasks
+unsafe impl<F: Send> Sync for Listener<F> {}
+
+impl<F> Listener<F> {
+    /// Create an unregistered listener that will call `func` with each reading
+    pub const fn new(func: F) -> Self {
+        Listener {
+            listener: UnsafeCell::new(sensor_listener {
+                sl_sensor_type: 0,
+                sl_func:        None,
+                sl_arg:         core::ptr::null_mut(),
+                sl_next:        sensor_listener__bindgen_ty_1 { sle_next: core::ptr::null_mut() },
+            }),
+            sensor: UnsafeCell::new(core::ptr::null_mut()),
+            func:   UnsafeCell::new(func),
+            calibration: None,
+        }
+    }
+
+    /// Create an unregistered listener that will call `func` with each reading, after applying `calibration`
+    pub const fn with_calibration(func: F, calibration: &'static Calibration) -> Self {
+        Listener {
+            listener: UnsafeCell::new(sensor_listener {
+                sl_sensor_type: 0,
+                sl_func:        None,
+                sl_arg:         core::ptr::null_mut(),
+                sl_next:        sensor_listener__bindgen_ty_1 { sle_next: core::ptr::null_mut() },
+            }),
+            sensor: UnsafeCell::new(core::ptr::null_mut()),
+            func:   UnsafeCell::new(func),
+            calibration: Some(calibration),
+        }
+    }
+}
+
+impl<F: FnMut(&Reading) -> MynewtResult<()> + Send> Listener<F> {
+    /// Call the function with the readings of the sensor named `devname` that match `type_mask`,
+    /// e.g. `SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW`. Returns `SYS_ENODEV` if there is no such sensor,
+    /// `SYS_EALREADY` if the listener is already registered.
+    pub fn register(&'static self, devname: &Strn, type_mask: sensor_type_t) -> MynewtResult<()> {
+        let sensor = sensor_mgr::find_bydevname(devname)
+            .next()
+            .ok_or(MynewtError::SYS_ENODEV) ? ;
+        self.register_sensor(sensor, type_mask)
+    }
+
+    /// Call the function with the readings of `sensor` that match `type_mask`
+    pub fn register_sensor(&'static self, sensor: sensor_ptr, type_mask: sensor_type_t) -> MynewtResult<()> {
+        if unsafe { !(*self.sensor.get()).is_null() } { return Err(MynewtError::SYS_EALREADY); }
+        unsafe {
+            let listener = &mut *self.listener.get();
+            listener.sl_sensor_type = type_mask;
+            listener.sl_func        = Some(listener_trampoline::<F>);
+            listener.sl_arg         = self as *const Self as *mut ::cty::c_void;
+            *self.sensor.get() = sensor;
+        }
+        let rc = unsafe { sensor::sensor_register_listener(sensor, self.listener.get()) };
+        if rc != 0 { unsafe { *self.sensor.get() = core::ptr::null_mut() }; }
+        check(rc)
+    }
+
+    /// Stop calling the function. Does nothing if the listener is not registered.
+    pub fn unregister(&'static self) -> MynewtResult<()> {
+        let sensor = unsafe { *self.sensor.get() };
+        if sensor.is_null() { return Ok(()); }
+        check(unsafe { sensor::sensor_unregister_listener(sensor, self.listener.get()) }) ? ;
+        unsafe { *self.sensor.get() = core::ptr::null_mut() };
+        Ok(())
+    }
+}
+
+/// Called by the Sensor Manager with the sensor data. `arg` is the `Listener`.
+extern "C" fn listener_trampoline<F: FnMut(&Reading) -> MynewtResult<()> + Send>(
+    _sensor:     sensor_ptr,
+    arg:         *mut ::cty::c_void,
+    sensor_data: sensor_data_ptr,
+    sensor_type: sensor_type_t
+) -> i32 {
+    let listener = unsafe { &*(arg as *const Listener<F>) };
+    let reading = match Reading::from_sensor_data(sensor_data, sensor_type) {
+        Some(reading) => reading,
+        None => return MynewtError::SYS_EINVAL as i32,  //  Sensor not ready
+    };
+    let reading = match listener.calibration {
+        Some(calibration) => calibration.apply_reading(&reading),
+        None => reading,
+    };
+    let rc = match unsafe { (*listener.func.get())(&reading) } {
+        Ok(()) => 0,
+        Err(err) => err as i32,
+    };
+    defmt_trace!("sensor type {=i64:x} rc {=i32}", sensor_type, rc);
+    rc
+}
@@ -0,0 +1,178 @@
+//! Central sensor polling with an interval per sensor. Instead of setting the Mynewt poll rate of each sensor, which
+//! polls every sensor in the Sensor Manager task, `poller::add()` registers the sensor with its own interval and a
+//! single shared timer reads each sensor when it's due. Reading a sensor calls its listeners, e.g. a `Listener`
+//! that sends the readings to the CoAP server. Sensors may be disabled to stop polling, e.g. while the watch sleeps.
+//! ```
+//! poller::add(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW, Duration::from_secs(30)) ? ;
+//! poller::add(&BATTERY_DEVICE, SENSOR_TYPE_BATTERY, Duration::from_secs(300)) ? ;
+//! poller::enable(&BATTERY_DEVICE, false) ? ;
+//! ```
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{
+        os,
+        time::{ self, Instant },
+        timer::Callout,
+    },
+    hw::{
+        sensor::{ self, sensor_ptr, sensor_type_t },
+        sensor_mgr,
+    },
+    Strn,
+};
+
+/// Max number of polled sensors
+type MaxPolled = heapless::consts::U8;
+
+/// Max time to wait for a sensor to be read, in milliseconds
+const READ_TIMEOUT_MS: u32 = 1000;
+
+/// Sensor that is polled at a fixed interval
+struct Polled {
+    /// Name of the sensor device
+    devname: Strn,
+    /// The Mynewt sensor
+    sensor: sensor_ptr,
+    /// Sensor types to be read
+    sensor_type: sensor_type_t,
+    /// Interval between reads
+    interval: Duration,
+    /// Time of the next read
+    next_read: Instant,
+    /// False if polling is disabled
+    enabled: bool,
+}
+
+/// Polled sensors
+static mut POLLED: heapless::Vec<Polled, MaxPolled> = heapless::Vec(heapless::i::Vec::new());
+
+/// Shared timer that reads the sensors that are due
+static POLL_TIMER: Callout<fn()> = Callout::new(poll_sensors);
+
+/// Read the sensor named `devname` every `interval`, starting now. The sensor types in `type_mask`, e.g.
+/// `SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW`, are passed to the listeners. If the sensor is already polled, the type and
+/// interval are updated. Returns `SYS_ENODEV` if there is no such sensor, `SYS_ENOMEM` if too many sensors are polled.
+pub fn add(devname: &Strn, type_mask: sensor_type_t, interval: Duration) -> MynewtResult<()> {
+    check_interval(interval) ? ;
+    let sensor = sensor_mgr::find_bydevname(devname)
+        .next()
+        .ok_or(MynewtError::SYS_ENODEV) ? ;
+    let entry = Polled {
+        devname: *devname,
+        sensor,
+        sensor_type: type_mask,
+        interval,
+        next_read: Instant::now(),
+        enabled: true,
+    };
+    let sr = unsafe { os::os_arch_save_sr() };
+    let polled = unsafe { &mut POLLED };
+    let result = match polled.iter_mut().find(|p| p.sensor == sensor) {
+        Some(existing) => { *existing = entry; Ok(()) }
+        None => polled.push(entry).map_err(|_| MynewtError::SYS_ENOMEM),
+    };
+    unsafe { os::os_arch_restore_sr(sr) };
+    result ? ;
+    schedule()
+}
+
+/// Change the interval for polling the sensor named `devname`. The next read is due after `interval` from now.
+/// Returns `SYS_ENOENT` if the sensor has not been added.
+pub fn set_interval(devname: &Strn, interval: Duration) -> MynewtResult<()> {
+    check_interval(interval) ? ;
+    update(devname, |p| {
+        p.interval = interval;
+        p.next_read = Instant::now() + interval;
+    }) ? ;
+    schedule()
+}
+
+/// Start or stop polling the sensor named `devname`. When enabled, the sensor is read at once.
+/// Returns `SYS_ENOENT` if the sensor has not been added.
+pub fn enable(devname: &Strn, enabled: bool) -> MynewtResult<()> {
+    update(devname, |p| {
+        if enabled && !p.enabled { p.next_read = Instant::now(); }
+        p.enabled = enabled;
+    }) ? ;
+    schedule()
+}
+
+/// Return the interval for polling the sensor named `devname`, or `None` if the sensor is not polled or disabled
+pub fn interval(devname: &Strn) -> Option<Duration> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let interval = unsafe { POLLED.iter() }
+        .find(|p| p.devname == *devname && p.enabled)
+        .map(|p| p.interval);
+    unsafe { os::os_arch_restore_sr(sr) };
+    interval
+}
+
+/// Return `SYS_EINVAL` if `interval` is zero or too long to be measured by the OS ticks
+fn check_interval(interval: Duration) -> MynewtResult<()> {
+    if interval.as_millis() == 0 { return Err(MynewtError::SYS_EINVAL); }
+    Instant::now().checked_add(interval).ok_or(MynewtError::SYS_EINVAL) ? ;
+    Ok(())
+}
+
+/// Update the polled sensor named `devname` with interrupts disabled
+fn update<F: FnOnce(&mut Polled)>(devname: &Strn, func: F) -> MynewtResult<()> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let found = unsafe { POLLED.iter_mut() }.find(|p| p.devname == *devname);
+    let result = match found {
+        Some(p) => { func(p); Ok(()) }
+        None => Err(MynewtError::SYS_ENOENT),
+    };
+    unsafe { os::os_arch_restore_sr(sr) };
+    result
+}
+
+/// Called by the timer to read the sensors that are due, then wait for the next sensor
+fn poll_sensors() {
+    let now = Instant::now();
+    let count = unsafe { POLLED.len() };
+    for i in 0..count {
+        //  Take the sensor that is due, with interrupts disabled in case another task is changing the sensors.
+        let sr = unsafe { os::os_arch_save_sr() };
+        let due = match unsafe { POLLED.get_mut(i) } {
+            Some(p) if p.enabled && is_due(p.next_read, now) => {
+                //  Catch up without reading repeatedly if we are late, e.g. after a long read.
+                while is_due(p.next_read, now) { p.next_read = p.next_read + p.interval; }
+                Some((p.sensor, p.sensor_type))
+            }
+            _ => None,
+        };
+        unsafe { os::os_arch_restore_sr(sr) };
+
+        //  Read the sensor. The Sensor Manager calls the listeners with the readings.
+        if let Some((sensor, sensor_type)) = due {
+            if let Err(err) = sensor::read(sensor, sensor_type, None, core::ptr::null_mut(),
+                time::ms_to_ticks(READ_TIMEOUT_MS)) {
+                log::warn!("read {} fail {:?}", sensor_type, err);
+            }
+        }
+    }
+    schedule().expect("poll fail");
+}
+
+/// Restart the timer for the next sensor that is due. Stop the timer if no sensors are enabled.
+fn schedule() -> MynewtResult<()> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let now = Instant::now();
+    //  Time to wait for each sensor, zero if overdue. Compare durations since the tick counter may wrap around.
+    let wait = unsafe { POLLED.iter() }
+        .filter(|p| p.enabled)
+        .map(|p| p.next_read.duration_since(now))
+        .min();
+    unsafe { os::os_arch_restore_sr(sr) };
+    match wait {
+        Some(wait) => POLL_TIMER.reset(wait),
+        None => { POLL_TIMER.stop(); Ok(()) }
+    }
+}
+
+/// Return true if `next_read` is at or before `now`
+fn is_due(next_read: Instant, now: Instant) -> bool {
+    now.checked_duration_since(next_read).is_some()
+}
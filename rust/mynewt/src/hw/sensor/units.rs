@@ -0,0 +1,61 @@
+//! Typed units for sensor readings. Each unit is a newtype over an integer, so that a raw value like an ADC count or
+//! an accelerometer count can't be passed or transmitted where a physical value is expected. Values are converted
+//! explicitly with the functions below, e.g. `MilliCelsius::from_centi_celsius()` for the nRF52 temperature sensor.
+//! ```
+//! let temp = MilliCelsius::from_centi_celsius(2875);
+//! let value = SensorValueType::Int(temp.centi_celsius());  //  Transmitted as `t` in degrees Celsius times 100
+//! let accel = MilliG::from_counts(512, BMA421_COUNTS_PER_G);
+//! ```
+
+/// Temperature in thousandths of a degree Celsius
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct MilliCelsius(pub i32);
+
+/// Acceleration in thousandths of the standard gravity g
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct MilliG(pub i32);
+
+/// Voltage in millivolts
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct MilliVolts(pub u32);
+
+/// Heart rate in beats per minute
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Bpm(pub u32);
+
+/// Accelerometer counts per g of the BMA421 at the ±2g range with 12-bit samples
+pub const BMA421_COUNTS_PER_G: i32 = 1024;
+
+impl MilliCelsius {
+    /// Convert from hundredths of a degree Celsius, as returned by the nRF52 temperature sensor driver
+    pub const fn from_centi_celsius(centi: i32) -> Self {
+        MilliCelsius(centi * 10)
+    }
+
+    /// Return the temperature in hundredths of a degree Celsius, rounded towards zero.
+    /// This is the unit of the `t` field sent to the CoAP server.
+    pub const fn centi_celsius(self) -> i32 {
+        self.0 / 10
+    }
+}
+
+impl MilliG {
+    /// Convert from accelerometer counts, for an accelerometer range with `counts_per_g` counts per g,
+    /// e.g. `BMA421_COUNTS_PER_G`
+    pub fn from_counts(counts: i32, counts_per_g: i32) -> Self {
+        MilliG((counts as i64 * 1000 / counts_per_g as i64) as i32)
+    }
+
+    /// Return the acceleration in accelerometer counts, for an accelerometer range with `counts_per_g` counts per g
+    pub fn counts(self, counts_per_g: i32) -> i32 {
+        (self.0 as i64 * counts_per_g as i64 / 1000) as i32
+    }
+}
+
+impl MilliVolts {
+    /// Convert from an ADC count of `resolution_bits` bits with a full scale of `full_scale` at the ADC input,
+    /// measured through a voltage divider that divides by `divider`
+    pub fn from_adc(counts: u32, resolution_bits: u32, full_scale: MilliVolts, divider: u32) -> Self {
+        MilliVolts((counts as u64 * full_scale.0 as u64 * divider as u64 >> resolution_bits) as u32)
+    }
+}
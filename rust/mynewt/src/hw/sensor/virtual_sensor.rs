@@ -0,0 +1,143 @@
+//! Virtual sensors implemented in Rust. `VirtualSensor<T>` registers a Mynewt device and sensor whose readings are
+//! computed by a Rust function, e.g. the step count of a pedometer, instead of being read from hardware. The sensor may
+//! be polled and listened to like the sensors in `libs`, so the readings flow through the same listener and CoAP pipeline.
+//! `T` is the C sensor data struct that is passed to the listeners, e.g. `sensor_steps_data`.
+//! ```
+//! static STEPS: VirtualSensor<sensor_steps_data> = VirtualSensor::new(SENSOR_TYPE_STEPS, SENSOR_VALUE_TYPE_INT32, read_steps);
+//! STEPS.create(&strn!("pedometer_0")) ? ;
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+};
+use crate::{
+    result::*,
+    kernel::os,
+    hw::sensor::{
+        self,
+        sensor_cfg, sensor_data_func_t, sensor_type_t,
+    },
+    Strn,
+};
+
+/// Sensor whose readings of type `T` are returned by a Rust function. Must be declared `static`, since Mynewt keeps
+/// pointers to the device and sensor.
+#[repr(C)]  //  The device must be the first field: Mynewt passes the device to the driver functions
+pub struct VirtualSensor<T> {
+    /// The Mynewt device and sensor. Zeroed by `create()`.
+    inner: UnsafeCell<MaybeUninit<Inner>>,
+    /// Driver functions for the sensor
+    driver: UnsafeCell<sensor::sensor_driver>,
+    /// Sensor type of the readings, e.g. `SENSOR_TYPE_STEPS`
+    sensor_type: sensor_type_t,
+    /// Type of the values in the readings, e.g. `SENSOR_VALUE_TYPE_INT32`
+    value_type: u32,
+    /// Function that returns the current reading, or `None` if not available
+    read: fn() -> Option<T>,
+}
+
+/// Mynewt device and sensor, like the device struct of the drivers in `libs`
+#[repr(C)]
+struct Inner {
+    /// The Mynewt device
+    dev: os::os_dev,
+    /// The Mynewt sensor
+    sensor: sensor::sensor,
+}
+
+/// `VirtualSensor` may be shared between tasks
+unsafe impl<T> Sync for VirtualSensor<T> {}
+
+impl<T> VirtualSensor<T> {
+    /// Create a virtual sensor that returns readings of `sensor_type` computed by `read`
+    pub const fn new(sensor_type: sensor_type_t, value_type: u32, read: fn() -> Option<T>) -> Self {
+        VirtualSensor {
+            inner:  UnsafeCell::new(MaybeUninit::uninit()),
+            driver: UnsafeCell::new(sensor::sensor_driver {
+                sd_read:                      None,
+                sd_get_config:                None,
+                sd_set_config:                None,
+                sd_set_trigger_thresh:        None,
+                sd_clear_low_trigger_thresh:  None,
+                sd_clear_high_trigger_thresh: None,
+                sd_set_notification:          None,
+                sd_unset_notification:        None,
+                sd_handle_interrupt:          None,
+                sd_reset:                     None,
+            }),
+            sensor_type,
+            value_type,
+            read,
+        }
+    }
+
+    /// Register the device named `devname` and the sensor with the Sensor Manager. `devname` must be a static string.
+    pub fn create(&'static self, devname: &'static Strn) -> MynewtResult<()> {
+        unsafe {
+            //  Zero the device and sensor, like the static device instances in C.
+            core::ptr::write_bytes(self.inner.get(), 0, 1);
+            let driver = &mut *self.driver.get();
+            driver.sd_read       = Some(read_trampoline::<T>);
+            driver.sd_get_config = Some(get_config_trampoline::<T>);
+        }
+        //  Mynewt calls `init_trampoline()` to init the sensor.
+        check(unsafe { os::os_dev_create(
+            self.inner.get() as *mut os::os_dev,
+            devname.as_cstr() as *const ::cty::c_char,
+            os::OS_DEV_INIT_PRIMARY as u8, 0,
+            Some(init_trampoline::<T>),
+            core::ptr::null_mut()
+        ) })
+    }
+
+    /// Return the Mynewt sensor, for polling and listening
+    pub fn as_sensor(&'static self) -> sensor::sensor_ptr {
+        unsafe { &mut (*(*self.inner.get()).as_mut_ptr()).sensor }
+    }
+}
+
+/// Called by `os_dev_create()` to init the sensor and register it with the Sensor Manager
+unsafe extern "C" fn init_trampoline<T: 'static>(dev: *mut os::os_dev, _arg: *mut ::cty::c_void) -> ::cty::c_int {
+    let virtual_sensor = &*(dev as *const VirtualSensor<T>);
+    let sensor = virtual_sensor.as_sensor();
+    let rc = sensor::sensor_init(sensor, dev);
+    if rc != 0 { return rc; }
+    //  Same as `sensor_set_driver()` and `sensor_set_type_mask()`, which are inline functions in C.
+    (*sensor).s_funcs = virtual_sensor.driver.get();
+    (*sensor).s_types = virtual_sensor.sensor_type;
+    (*sensor).s_mask  = virtual_sensor.sensor_type;
+    sensor::sensor_mgr_register(sensor)
+}
+
+/// Called by the Sensor Manager to read the sensor. Passes the reading to `data_func`.
+unsafe extern "C" fn read_trampoline<T>(
+    sensor:      *mut sensor::sensor,
+    sensor_type: sensor_type_t,
+    data_func:   sensor_data_func_t,
+    data_arg:    *mut ::cty::c_void,
+    _timeout:    u32
+) -> ::cty::c_int {
+    let virtual_sensor = &*((*sensor).s_dev as *const VirtualSensor<T>);
+    if sensor_type & virtual_sensor.sensor_type == 0 { return MynewtError::SYS_EINVAL as ::cty::c_int; }
+    let mut data = match (virtual_sensor.read)() {
+        Some(data) => data,
+        None => return MynewtError::SYS_EAGAIN as ::cty::c_int,  //  No reading yet
+    };
+    match data_func {
+        Some(data_func) => data_func(sensor, data_arg, &mut data as *mut T as *mut ::cty::c_void, virtual_sensor.sensor_type),
+        None => 0,
+    }
+}
+
+/// Called by the Sensor Manager to get the type of the values
+unsafe extern "C" fn get_config_trampoline<T>(
+    sensor:      *mut sensor::sensor,
+    sensor_type: sensor_type_t,
+    cfg:         *mut sensor_cfg
+) -> ::cty::c_int {
+    let virtual_sensor = &*((*sensor).s_dev as *const VirtualSensor<T>);
+    if sensor_type & virtual_sensor.sensor_type == 0 { return MynewtError::SYS_EINVAL as ::cty::c_int; }
+    (*cfg).sc_valtype = virtual_sensor.value_type as u8;
+    0
+}
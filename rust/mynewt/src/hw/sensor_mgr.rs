@@ -19,10 +19,14 @@
 //! Contains the Mynewt Sensor Manager API for Rust, including the safe version of the API.
 
 use crate::{
+    result::*,
     hw::sensor::{
+        self,
         mgr_find_next_bydevname,
-        sensor_ptr,
+        sensor_data_ptr, sensor_ptr, sensor_type_t,
+        Reading,
     },
+    kernel::time,
     Strn,
 };
 
@@ -60,4 +64,68 @@ pub struct SensorsByDevname {
     devname: Strn,
     /// Last sensor that was returned
     previous: sensor_ptr,
-}
\ No newline at end of file
+}
+
+/// Returns an iterator of all sensors registered with the Sensor Manager
+pub fn all() -> Sensors {
+    Sensors { previous: core::ptr::null_mut() }
+}
+
+/// Implement the iterator for all sensors
+impl Iterator for Sensors {
+    /// Iterator returns a pointer to a sensor
+    type Item = sensor_ptr;
+
+    /// Return the next sensor in the Sensor Manager list
+    fn next(&mut self) -> Option<sensor_ptr> {
+        let sensor = unsafe { sensor::sensor_mgr_find_next(Some(match_all), core::ptr::null_mut(), self.previous) };
+        if sensor.is_null() { None }
+        else {
+            self.previous = sensor;
+            Some(sensor)
+        }
+    }
+}
+
+/// State for iterating all sensors
+pub struct Sensors {
+    /// Last sensor that was returned
+    previous: sensor_ptr,
+}
+
+/// Compare function for `sensor_mgr_find_next()` that matches every sensor
+extern "C" fn match_all(_sensor: *mut sensor::sensor, _arg: *mut ::cty::c_void) -> ::cty::c_int {
+    1
+}
+
+/// Return the device name of the sensor, e.g. `temp_nrf52_0`
+pub fn devname(sensor: sensor_ptr) -> Strn {
+    assert!(!sensor.is_null(), "null sensor");
+    Strn::from_cstr(unsafe { (*(*sensor).s_dev).od_name } as *const u8)
+}
+
+/// Return the sensor types supported by the sensor, as a mask of `SENSOR_TYPE_...` bits
+pub fn types(sensor: sensor_ptr) -> sensor_type_t {
+    assert!(!sensor.is_null(), "null sensor");
+    unsafe { (*sensor).s_types }
+}
+
+/// Read the sensor once and call `func` with each reading of the types in `type_mask`, without waiting for the
+/// poller. The listeners of the sensor are also called. Waits up to `timeout` for the sensor to be read.
+pub fn read<F: FnMut(&Reading)>(sensor: sensor_ptr, type_mask: sensor_type_t, mut func: F,
+    timeout: core::time::Duration) -> MynewtResult<()> {
+    let arg = &mut func as *mut F as *mut ::cty::c_void;
+    sensor::read(sensor, type_mask, Some(read_trampoline::<F>), arg, time::duration_to_ticks(timeout))
+}
+
+/// Called by `sensor_read()` with each reading. Converts the reading and calls the closure in `arg`.
+extern "C" fn read_trampoline<F: FnMut(&Reading)>(
+    _sensor:     sensor_ptr,
+    arg:         *mut ::cty::c_void,
+    sensor_data: sensor_data_ptr,
+    sensor_type: sensor_type_t
+) -> i32 {
+    let func = unsafe { &mut *(arg as *mut F) };
+    if let Some(reading) = Reading::from_sensor_data(sensor_data, sensor_type) { func(&reading); }
+    0
+}
@@ -7,3 +7,65 @@ use crate::{
 
 /// Contains Rust bindings for Mynewt OS API `kernel/os`
 pub mod os;  // Export `kernel/os.rs` as Rust module `mynewt::kernel::os`
+
+/// Safe API for spawning Mynewt tasks
+pub mod task;  // Export `kernel/task.rs` as Rust module `mynewt::kernel::task`
+
+/// Mutex and other synchronisation primitives for sharing state between tasks
+pub mod sync;  // Export `kernel/sync.rs` as Rust module `mynewt::kernel::sync`
+
+/// Typed event queue for messaging between tasks
+pub mod event;  // Export `kernel/event.rs` as Rust module `mynewt::kernel::event`
+
+/// Timers with Rust callbacks
+pub mod timer;  // Export `kernel/timer.rs` as Rust module `mynewt::kernel::timer`
+
+/// Scheduler for periodic jobs and daily jobs at a wall clock time
+pub mod schedule;  // Export `kernel/schedule.rs` as Rust module `mynewt::kernel::schedule`
+
+/// Safe wrapper for mbuf chains
+pub mod mbuf;  // Export `kernel/mbuf.rs` as Rust module `mynewt::kernel::mbuf`
+
+/// Task-liveness supervisor that owns the watchdog
+pub mod supervisor;  // Export `kernel/supervisor.rs` as Rust module `mynewt::kernel::supervisor`
+
+/// Lock-free channel from interrupt handlers to tasks
+pub mod channel;  // Export `kernel/channel.rs` as Rust module `mynewt::kernel::channel`
+
+/// Critical sections for `critical-section` crate users
+#[cfg(feature = "critical_section")]
+pub mod critical;  // Export `kernel/critical.rs` as Rust module `mynewt::kernel::critical`
+
+/// Conversions between OS ticks, milliseconds and `Duration`, and `Instant` for measuring time
+pub mod time;  // Export `kernel/time.rs` as Rust module `mynewt::kernel::time`
+
+/// Cooperative delays, exported as `mynewt::kernel::sleep_ms()` etc.
+pub use time::{ sleep, sleep_ms, sleep_until, yield_now };
+
+/// Microsecond timer, busy-wait delays and one-shot timer callbacks
+pub mod hires;  // Export `kernel/hires.rs` as Rust module `mynewt::kernel::hires`
+
+/// Low-power RTC alarms for long sleeps, without the high-frequency clock
+pub mod lptimer;  // Export `kernel/lptimer.rs` as Rust module `mynewt::kernel::lptimer`
+
+/// Reason for the last reset
+pub mod reset;  // Export `kernel/reset.rs` as Rust module `mynewt::kernel::reset`
+
+/// Safe handle for Mynewt devices with typed downcasts
+pub mod device;  // Export `kernel/device.rs` as Rust module `mynewt::kernel::device`
+
+/// Soft reset now or after a delay, recording the reason for the next boot
+pub mod reboot;  // Export `kernel/reboot.rs` as Rust module `mynewt::kernel::reboot`
+
+/// Fault handlers that record the registers and the fault status for the next boot
+pub mod fault;  // Export `kernel/fault.rs` as Rust module `mynewt::kernel::fault`
+
+/// Stack overflow detection with canaries at the bottom of the task stacks
+pub mod stack_guard;  // Export `kernel/stack_guard.rs` as Rust module `mynewt::kernel::stack_guard`
+
+/// Event loops that sleep until the next event instead of polling, and the monitor of the time asleep
+pub mod idle;  // Export `kernel/idle.rs` as Rust module `mynewt::kernel::idle`
+
+/// Global allocator backed by the Mynewt heap
+#[cfg(feature = "alloc")]
+pub mod heap;  // Export `kernel/heap.rs` as Rust module `mynewt::kernel::heap`
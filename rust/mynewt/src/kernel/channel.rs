@@ -0,0 +1,107 @@
+//! Lock-free channel for passing values from an interrupt handler to a task. `Channel<T>` is a fixed-capacity
+//! single-producer, single-consumer ring buffer: one interrupt handler (e.g. touch, GPIO or DMA complete) calls `push()`,
+//! and one task calls `pop()`. Neither side disables interrupts or blocks. If `notify()` has been called, each push
+//! also posts an event to an event queue, so that the task wakes up to pop the values.
+//! ```
+//! static TOUCHES: Channel<Instant> = Channel::new();
+//! extern "C" fn touch_irq(_arg: *mut c_void) { TOUCHES.push(Instant::now()).ok(); }
+//! extern "C" fn touch_event(_ev: *mut os_event) { while let Some(at) = TOUCHES.pop() { ... } }
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{ AtomicUsize, Ordering },
+};
+use crate::kernel::os;
+
+/// Number of values that each channel can hold
+pub const CHANNEL_SIZE: usize = 16;
+
+/// Channel that carries values of type `T` from one producer to one consumer. May be declared `static`.
+pub struct Channel<T> {
+    /// Ring buffer of values. Slot `i % CHANNEL_SIZE` is initialised if `head <= i < tail`.
+    buffer: UnsafeCell<MaybeUninit<[T; CHANNEL_SIZE]>>,
+    /// Count of values popped. Updated only by the consumer.
+    head: AtomicUsize,
+    /// Count of values pushed. Updated only by the producer.
+    tail: AtomicUsize,
+    /// Event posted after each push, if `notify()` has been called
+    event: UnsafeCell<os::os_event>,
+    /// Event queue for posting the event, or null if none
+    queue: UnsafeCell<*mut os::os_eventq>,
+}
+
+/// `Channel` may be shared between an interrupt handler and a task
+unsafe impl<T: Send> Sync for Channel<T> {}
+
+impl<T> Channel<T> {
+    /// Create an empty channel
+    pub const fn new() -> Self {
+        Channel {
+            buffer: UnsafeCell::new(MaybeUninit::uninit()),
+            head:   AtomicUsize::new(0),
+            tail:   AtomicUsize::new(0),
+            event:  UnsafeCell::new(os::os_event {
+                ev_queued: 0,
+                ev_cb:     None,
+                ev_arg:    core::ptr::null_mut(),
+                ev_next:   os::os_event__bindgen_ty_1 { stqe_next: core::ptr::null_mut() },
+            }),
+            queue:  UnsafeCell::new(core::ptr::null_mut()),
+        }
+    }
+
+    /// After each push, post an event to `queue` that calls `callback` in the task that processes the queue.
+    /// Must be called before the producer starts pushing, e.g. before enabling the interrupt.
+    pub fn notify(&'static self, queue: *mut os::os_eventq, callback: extern "C" fn(*mut os::os_event)) {
+        unsafe {
+            (*self.event.get()).ev_cb  = Some(callback);
+            (*self.event.get()).ev_arg = self as *const Self as *mut ::cty::c_void;
+            *self.queue.get() = queue;
+        }
+    }
+
+    /// Push `value` into the channel. Called by the producer only, usually an interrupt handler.
+    /// Returns `Err(value)` if the channel is full.
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let tail = self.tail.load(Ordering::Relaxed);  //  Only the producer updates `tail`
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) >= CHANNEL_SIZE { return Err(value); }  //  Channel is full
+        unsafe { self.slot(tail).write(value) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);  //  Publish the value to the consumer
+
+        //  Wake up the consumer. If the event is already queued, it's not queued again.
+        let queue = unsafe { *self.queue.get() };
+        if !queue.is_null() { unsafe { os::os_eventq_put(queue, self.event.get()) }; }
+        Ok(())
+    }
+
+    /// Pop the oldest value from the channel. Called by the consumer only, usually a task.
+    /// Returns `None` if the channel is empty.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);  //  Only the consumer updates `head`
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail { return None; }  //  Channel is empty
+        let value = unsafe { self.slot(head).read() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);  //  Free the slot for the producer
+        Some(value)
+    }
+
+    /// Return the number of values in the channel
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        let head = self.head.load(Ordering::Acquire);
+        tail.wrapping_sub(head)
+    }
+
+    /// Return true if the channel is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Return the slot for the value with count `i`
+    fn slot(&self, i: usize) -> *mut T {
+        unsafe { (*self.buffer.get()).as_mut_ptr() as *mut T }.wrapping_add(i % CHANNEL_SIZE)
+    }
+}
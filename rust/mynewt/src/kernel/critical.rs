@@ -0,0 +1,28 @@
+//! Implementation of the `critical-section` crate for Mynewt, enabled by the `critical_section` feature.
+//! Crates like `heapless` and `once_cell` call `critical_section::with()` to protect shared state. The critical section
+//! disables interrupts with `os_arch_save_sr()` (same as `OS_ENTER_CRITICAL` in C), so it's safe against interrupt
+//! handlers and task switches. Critical sections may be nested. Keep them short, since interrupts are delayed.
+//! ```
+//! let count = mynewt::kernel::critical::with(|_cs| { COUNT += 1; COUNT });
+//! ```
+
+use crate::kernel::os;
+
+pub use critical_section::{ with, CriticalSection };
+
+/// Mynewt implementation of `critical-section`
+struct MynewtCriticalSection;
+
+critical_section::set_impl!(MynewtCriticalSection);
+
+unsafe impl critical_section::Impl for MynewtCriticalSection {
+    /// Disable interrupts and return the previous interrupt state. Same as `OS_ENTER_CRITICAL` in C.
+    unsafe fn acquire() -> critical_section::RawRestoreState {
+        os::os_arch_save_sr()
+    }
+
+    /// Restore the interrupt state returned by `acquire()`. Same as `OS_EXIT_CRITICAL` in C.
+    unsafe fn release(sr: critical_section::RawRestoreState) {
+        os::os_arch_restore_sr(sr)
+    }
+}
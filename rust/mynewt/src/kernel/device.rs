@@ -0,0 +1,134 @@
+//! Safe handle for Mynewt devices. `Device::open()` wraps `os_dev_open()` with a timeout, and the device is closed
+//! with `os_dev_close()` when the `Device` is dropped. `downcast()` returns the typed handle for known device classes:
+//! sensors, SPI ports and flash devices.
+//! ```
+//! let dev = Device::open(&strn!("temp_stub_0"), Duration::from_secs(1)) ? ;
+//! let sensor = dev.downcast::<SensorClass>() ? ;
+//! ```
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{ os, time },
+    hw::{
+        flash,
+        sensor::{ self, sensor_ptr },
+        sensor_mgr,
+    },
+    Strn,
+};
+
+/// Mynewt device that was opened with `os_dev_open()`. Closed when dropped.
+pub struct Device {
+    /// The opened device
+    dev: *mut os::os_dev,
+    /// Name of the device
+    name: Strn,
+}
+
+impl Device {
+    /// Open the device named `name`, waiting up to `timeout` for the device to be available.
+    /// Returns `SYS_ENODEV` if there is no such device, `SYS_ETIMEOUT` if the device couldn't be opened in time.
+    pub fn open(name: &Strn, timeout: Duration) -> MynewtResult<Device> {
+        Device::open_with_arg(name, timeout, core::ptr::null_mut())
+    }
+
+    /// Open the device named `name`, passing `arg` to the open handler of the driver
+    pub fn open_with_arg(name: &Strn, timeout: Duration, arg: *mut ::cty::c_void) -> MynewtResult<Device> {
+        let devname = name.as_cstr() as *const ::cty::c_char;
+        if unsafe { os::os_dev_lookup(devname) }.is_null() { return Err(MynewtError::SYS_ENODEV); }
+        let dev = unsafe { os::os_dev_open(devname, time::duration_to_ticks(timeout), arg) };
+        //  The open handler failed, or the device is locked by another task.
+        if dev.is_null() { return Err(MynewtError::SYS_ETIMEOUT); }
+        Ok(Device { dev, name: *name })
+    }
+
+    /// Return the name of the device
+    pub fn name(&self) -> &Strn {
+        &self.name
+    }
+
+    /// Return the Mynewt device
+    pub fn as_ptr(&self) -> *mut os::os_dev {
+        self.dev
+    }
+
+    /// Return the typed handle of the device for the device class `C`. Returns `SYS_EINVAL` if the device
+    /// doesn't belong to the class.
+    pub fn downcast<C: DeviceClass>(&self) -> MynewtResult<C::Handle> {
+        C::from_device(self).ok_or(MynewtError::SYS_EINVAL)
+    }
+
+    /// Return the driver struct of the device. The driver struct must start with `struct os_dev`,
+    /// like the drivers in `libs`.
+    pub unsafe fn as_driver<T>(&self) -> *mut T {
+        self.dev as *mut T
+    }
+}
+
+impl Drop for Device {
+    /// Close the device
+    fn drop(&mut self) {
+        unsafe { os::os_dev_close(self.dev) };
+    }
+}
+
+/// Class of devices that `Device::downcast()` may convert to a typed handle
+pub trait DeviceClass {
+    /// Typed handle for the device
+    type Handle;
+    /// Return the handle for `dev`, or `None` if `dev` doesn't belong to this class
+    fn from_device(dev: &Device) -> Option<Self::Handle>;
+}
+
+/// Sensor devices registered with the Sensor Manager. The handle is the sensor.
+pub struct SensorClass;
+
+impl DeviceClass for SensorClass {
+    type Handle = sensor_ptr;
+
+    /// Return the sensor whose device is `dev`
+    fn from_device(dev: &Device) -> Option<sensor_ptr> {
+        sensor_mgr::find_bydevname(dev.name())
+            .find(|s| unsafe { sensor::sensor_get_device(*s) } as usize == dev.as_ptr() as usize)
+    }
+}
+
+/// SPI ports, named `spi0`, `spi1`, ... by the Mynewt bus driver. The handle is the SPI port number for `hal_spi`.
+pub struct SpiClass;
+
+impl DeviceClass for SpiClass {
+    type Handle = i32;
+
+    /// Return the SPI port number of `dev`
+    fn from_device(dev: &Device) -> Option<i32> {
+        unit_number(dev.name(), b"spi").map(|n| n as i32)
+    }
+}
+
+/// SPI flash devices, named `spiflash0` by the Mynewt bus driver. The handle is the flash device ID for `hal_flash`.
+pub struct FlashClass;
+
+impl DeviceClass for FlashClass {
+    type Handle = u8;
+
+    /// Return the flash device ID of `dev`
+    fn from_device(dev: &Device) -> Option<u8> {
+        match unit_number(dev.name(), b"spiflash") {
+            Some(0) => Some(flash::EXTERNAL_FLASH),  //  PineTime has one SPI flash
+            _ => None,
+        }
+    }
+}
+
+/// If `name` is `prefix` followed by a single digit, return the digit, e.g. `spi1` returns 1
+fn unit_number(name: &Strn, prefix: &[u8]) -> Option<u8> {
+    let len = name.len();
+    if len != prefix.len() + 1 { return None; }
+    let bytes = unsafe { core::slice::from_raw_parts(name.as_ptr(), len) };
+    if &bytes[..prefix.len()] != prefix { return None; }
+    match bytes[prefix.len()] {
+        b @ b'0'..=b'9' => Some(b - b'0'),
+        _ => None,
+    }
+}
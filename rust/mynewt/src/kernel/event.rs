@@ -0,0 +1,159 @@
+//! Typed event queue for messaging between tasks. `EventQueue<E>` wraps a Mynewt `os_eventq`: events of type `E`
+//! (usually a Rust enum) are posted and received by value, instead of casting the `ev_arg` pointer of an `os_event`.
+//! There is no heap, so each queue has a fixed pool of `EVENT_QUEUE_SIZE` event slots that hold the payloads.
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    time::Duration,
+};
+use crate::{
+    result::*,
+    kernel::{ os, time },
+};
+
+/// Max number of events that may be pending in each queue
+pub const EVENT_QUEUE_SIZE: usize = 8;
+
+/// Event queue that carries payloads of type `E`. May be declared `static`: the `os_eventq` is initialised on first use.
+/// Events may be posted by any task or interrupt handler, but should be received by one task only.
+pub struct EventQueue<E> {
+    /// The Mynewt event queue and event slots
+    inner: UnsafeCell<Inner<E>>,
+}
+
+/// Mynewt event queue and the event slots
+struct Inner<E> {
+    /// The Mynewt event queue
+    queue: os::os_eventq,
+    /// True if `os_eventq_init()` has been called
+    initialised: bool,
+    /// Event slots
+    events: [os::os_event; EVENT_QUEUE_SIZE],
+    /// Payload of each event slot
+    payloads: [MaybeUninit<E>; EVENT_QUEUE_SIZE],
+    /// True if the event slot is in use
+    in_use: [bool; EVENT_QUEUE_SIZE],
+}
+
+/// `EventQueue` may be shared between tasks and interrupt handlers
+unsafe impl<E: Send> Sync for EventQueue<E> {}
+
+/// `EventQueue` may be sent to another task
+unsafe impl<E: Send> Send for EventQueue<E> {}
+
+impl<E> EventQueue<E> {
+    /// Create an empty event queue
+    pub const fn new() -> Self {
+        EventQueue {
+            inner: UnsafeCell::new(Inner {
+                queue: os::os_eventq {
+                    evq_owner: core::ptr::null_mut(),
+                    evq_task:  core::ptr::null_mut(),
+                    evq_list:  os::os_eventq__bindgen_ty_1 {
+                        stqh_first: core::ptr::null_mut(),
+                        stqh_last:  core::ptr::null_mut(),  //  Set by `os_eventq_init()`
+                    },
+                },
+                initialised: false,
+                events: [
+                    new_event(), new_event(), new_event(), new_event(),
+                    new_event(), new_event(), new_event(), new_event(),
+                ],
+                payloads: [
+                    MaybeUninit::uninit(), MaybeUninit::uninit(), MaybeUninit::uninit(), MaybeUninit::uninit(),
+                    MaybeUninit::uninit(), MaybeUninit::uninit(), MaybeUninit::uninit(), MaybeUninit::uninit(),
+                ],
+                in_use: [false; EVENT_QUEUE_SIZE],
+            }),
+        }
+    }
+
+    /// Post `event` to the queue. May be called in an interrupt handler.
+    /// Returns `SYS_ENOMEM` if `EVENT_QUEUE_SIZE` events are already pending.
+    pub fn post(&self, event: E) -> MynewtResult<()> {
+        self.init();
+        let inner = self.inner.get();
+        //  Allocate an event slot with interrupts disabled, since an interrupt handler may also be posting.
+        let sr = unsafe { os::os_arch_save_sr() };
+        let slot = unsafe { (*inner).in_use.iter().position(|used| !used) };
+        if let Some(i) = slot {
+            unsafe {
+                (*inner).in_use[i] = true;
+                (*inner).payloads[i] = MaybeUninit::new(event);
+            }
+        }
+        unsafe { os::os_arch_restore_sr(sr) };
+        match slot {
+            Some(i) => {
+                unsafe { os::os_eventq_put(&mut (*inner).queue, &mut (*inner).events[i]) };
+                Ok(())
+            }
+            None => Err(MynewtError::SYS_ENOMEM),  //  Queue is full
+        }
+    }
+
+    /// Receive the next event, waiting forever until an event is posted
+    pub fn receive(&self) -> E {
+        self.init();
+        let ev = unsafe { os::os_eventq_get(&mut (*self.inner.get()).queue) };
+        self.take(ev).expect("eventq fail")
+    }
+
+    /// Receive the next event, waiting up to `timeout` for an event to be posted. Returns `None` if no event was posted.
+    pub fn receive_timeout(&self, timeout: Duration) -> Option<E> {
+        self.init();
+        let mut queue: *mut os::os_eventq = unsafe { &mut (*self.inner.get()).queue };
+        let ev = unsafe { os::os_eventq_poll(&mut queue, 1, time::duration_to_ticks(timeout)) };
+        self.take(ev)
+    }
+
+    /// Receive the next event if one is pending, without waiting. Returns `None` if no event is pending.
+    pub fn try_receive(&self) -> Option<E> {
+        self.init();
+        let ev = unsafe { os::os_eventq_get_no_wait(&mut (*self.inner.get()).queue) };
+        self.take(ev)
+    }
+
+    /// Return the payload of the event `ev` taken from the queue and free the event slot. Returns `None` if `ev` is null.
+    fn take(&self, ev: *mut os::os_event) -> Option<E> {
+        if ev.is_null() { return None; }
+        let inner = self.inner.get();
+        //  Find the slot of the event.
+        let first = unsafe { (*inner).events.as_ptr() } as usize;
+        let i = (ev as usize).wrapping_sub(first) / core::mem::size_of::<os::os_event>();
+        assert!(i < EVENT_QUEUE_SIZE, "bad event");
+        let sr = unsafe { os::os_arch_save_sr() };
+        let payload = unsafe {
+            let payload = core::ptr::read((*inner).payloads[i].as_ptr());
+            (*inner).in_use[i] = false;
+            payload
+        };
+        unsafe { os::os_arch_restore_sr(sr) };
+        Some(payload)
+    }
+
+    /// Initialise the `os_eventq` if this is the first use. Interrupts are disabled in case two tasks post at the same time.
+    fn init(&self) {
+        let inner = self.inner.get();
+        unsafe {
+            if (*inner).initialised { return; }
+            let sr = os::os_arch_save_sr();
+            if !(*inner).initialised {
+                os::os_eventq_init(&mut (*inner).queue);
+                (*inner).initialised = true;
+            }
+            os::os_arch_restore_sr(sr);
+        }
+    }
+}
+
+/// Return an event for an event slot. The event has no callback, so it's returned by `os_eventq_get()`.
+const fn new_event() -> os::os_event {
+    os::os_event {
+        ev_queued: 0,
+        ev_cb:     None,
+        ev_arg:    core::ptr::null_mut(),
+        ev_next:   os::os_event__bindgen_ty_1 { stqe_next: core::ptr::null_mut() },
+    }
+}
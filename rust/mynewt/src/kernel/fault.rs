@@ -0,0 +1,194 @@
+//! Fault handlers for HardFault, MemManage, BusFault and UsageFault. `start()` replaces the Mynewt fault handlers in
+//! the vector table in RAM by `rust_fault_entry()` in `libs/mynewt_rust/src/fault_handler.c`, which passes the stack
+//! frame to `rust_fault_handler()`. The handler captures the registers stacked by the CPU, the fault status registers
+//! and the faulting address, prints them on the console sink that is selected, saves them in RAM that is not cleared
+//! at startup, pauses in the debugger if attached, then restarts the device. After restarting, `show_last()` displays
+//! the fault, so that a lockup in the field becomes a report with the faulting `pc`.
+//! Look up the `pc` and `lr` with `arm-none-eabi-addr2line -e my_sensor_app.elf 0x...`. The status bits are
+//! described in the Cortex-M4 Devices Generic User Guide, section 4.3.10 "Configurable Fault Status Register".
+
+use core::fmt::Write;
+use crate::{
+    result::*,
+    sys::{ console, crash_dump::{ self, CrashReason }, noinit::{ NoInit, NoInitValue }, panic },
+};
+
+/// Exception numbers of the faults in the vector table
+const HARD_FAULT:  usize = 3;
+const USAGE_FAULT: usize = 6;
+
+/// System Control Block registers. From the Cortex-M4 Devices Generic User Guide.
+const ICSR:  *const u32 = 0xE000_ED04 as *const u32;  //  Interrupt Control and State: active exception number
+const VTOR:  *const u32 = 0xE000_ED08 as *const u32;  //  Vector Table Offset
+const SHCSR: *mut u32   = 0xE000_ED24 as *mut u32;    //  System Handler Control and State: enables the faults
+const CFSR:  *const u32 = 0xE000_ED28 as *const u32;  //  Configurable Fault Status
+const HFSR:  *const u32 = 0xE000_ED2C as *const u32;  //  HardFault Status
+const MMFAR: *const u32 = 0xE000_ED34 as *const u32;  //  MemManage Fault Address
+const BFAR:  *const u32 = 0xE000_ED38 as *const u32;  //  BusFault Address
+const DHCSR: *const u32 = 0xE000_EDF0 as *const u32;  //  Debug Halting Control and Status: debugger attached
+
+/// `SHCSR` bits that enable the MemManage, BusFault and UsageFault handlers, instead of escalating to HardFault
+const SHCSR_FAULTS_ENA: u32 = (1 << 16) | (1 << 17) | (1 << 18);
+
+/// `CFSR` bits that mark `MMFAR` and `BFAR` as valid
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+
+/// RAM of the nRF52832. A stack frame outside the RAM, e.g. after a stack overflow, is not read.
+const RAM_START: u32 = 0x2000_0000;
+const RAM_END:   u32 = 0x2001_0000;
+
+/// Size of the basic stack frame, and of the extended stack frame with the FPU registers
+const FRAME_SIZE:     u32 = 8 * 4;
+const FRAME_SIZE_FPU: u32 = 26 * 4;
+
+/// Fault that was recorded before the last restart
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct FaultRecord {
+    /// Exception number: 3 for HardFault, 4 for MemManage, 5 for BusFault, 6 for UsageFault
+    pub exception: u32,
+    /// Registers stacked by the CPU on entry. 0 if the stack was invalid.
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    /// Return address of the function that faulted
+    pub lr: u32,
+    /// Address of the faulting instruction
+    pub pc: u32,
+    pub xpsr: u32,
+    /// Stack pointer before the fault
+    pub sp: u32,
+    /// Configurable Fault Status Register: MemManage, BusFault and UsageFault status bits
+    pub cfsr: u32,
+    /// HardFault Status Register
+    pub hfsr: u32,
+    /// Faulting data address from `MMFAR` or `BFAR`, if `cfsr` has the bit `MMARVALID` or `BFARVALID`, else 0
+    pub address: u32,
+}
+
+impl FaultRecord {
+    /// Return the name of the fault, for display
+    pub fn name(&self) -> &'static str {
+        match self.exception {
+            3 => "HardFault",
+            4 => "MemManage",
+            5 => "BusFault",
+            6 => "UsageFault",
+            _ => "Fault",
+        }
+    }
+}
+
+unsafe impl NoInitValue for FaultRecord {
+    const MAGIC: u32 = 0x544c_4146;  //  `FALT`
+}
+
+/// Fault record, stored in the Mynewt section `.bss.core.nz` that is not cleared at startup
+#[link_section = ".bss.core.nz"]
+static mut FAULT_RECORD: NoInit<FaultRecord> = NoInit::new(FaultRecord {
+    exception: 0,
+    r0: 0, r1: 0, r2: 0, r3: 0, r12: 0, lr: 0, pc: 0, xpsr: 0, sp: 0,
+    cfsr: 0, hfsr: 0, address: 0,
+});
+
+/// Install the fault handlers in the vector table and enable the MemManage, BusFault and UsageFault handlers.
+/// Returns `SYS_ENOTSUP` if the vector table has not been relocated to RAM by Mynewt. Called by main() in `lib.rs`
+/// after `sysinit()`.
+pub fn start() -> MynewtResult<()> {
+    let vectors = unsafe { core::ptr::read_volatile(VTOR) };
+    if vectors < RAM_START || vectors >= RAM_END { return Err(MynewtError::SYS_ENOTSUP); }
+    let vectors = vectors as *mut u32;
+    unsafe {
+        for exception in HARD_FAULT..=USAGE_FAULT {
+            core::ptr::write_volatile(vectors.add(exception), rust_fault_entry as usize as u32);
+        }
+        core::ptr::write_volatile(SHCSR, core::ptr::read_volatile(SHCSR) | SHCSR_FAULTS_ENA);
+    }
+    Ok(())
+}
+
+/// Return true if a fault was recorded before the last restart and has not been taken
+pub fn has_last() -> bool {
+    unsafe { FAULT_RECORD.is_valid() }
+}
+
+/// Return the fault that was recorded before the last restart, and clear the record. Returns `None` if there was
+/// no fault.
+pub fn take_last() -> Option<FaultRecord> {
+    unsafe { FAULT_RECORD.take() }
+}
+
+/// Display the fault that was recorded before the last restart, and clear the record
+pub fn show_last() {
+    if let Some(record) = take_last() {
+        console::print("last ");
+        show(&record);
+    }
+}
+
+/// Record and display the fault, then restart the device. Called by `rust_fault_entry()` in `fault_handler.c` with
+/// the stack frame and the `EXC_RETURN` value in `lr`.
+#[no_mangle]
+extern "C" fn rust_fault_handler(frame: *const u32, exc_return: u32) -> ! {
+    let cfsr = unsafe { core::ptr::read_volatile(CFSR) };
+    let address =
+        if cfsr & CFSR_MMARVALID != 0      { unsafe { core::ptr::read_volatile(MMFAR) } }
+        else if cfsr & CFSR_BFARVALID != 0 { unsafe { core::ptr::read_volatile(BFAR) } }
+        else { 0 };
+    //  Bit 4 of `EXC_RETURN` is clear if the FPU registers were stacked too.
+    let frame_size = if exc_return & (1 << 4) == 0 { FRAME_SIZE_FPU } else { FRAME_SIZE };
+    let sp = frame as u32;
+    let stacked = |index: usize| {
+        if sp < RAM_START || sp + FRAME_SIZE > RAM_END { return 0; }  //  Don't fault again on an invalid stack
+        unsafe { core::ptr::read_volatile(frame.add(index)) }
+    };
+    let record = FaultRecord {
+        exception: unsafe { core::ptr::read_volatile(ICSR) } & 0x1ff,
+        r0:   stacked(0),
+        r1:   stacked(1),
+        r2:   stacked(2),
+        r3:   stacked(3),
+        r12:  stacked(4),
+        lr:   stacked(5),
+        pc:   stacked(6),
+        xpsr: stacked(7),
+        sp:   sp + frame_size,
+        cfsr,
+        hfsr: unsafe { core::ptr::read_volatile(HFSR) },
+        address,
+    };
+    unsafe { FAULT_RECORD.save(record) };
+    show(&record);
+    let mut detail = heapless::String::<heapless::consts::U48>::new();
+    write!(detail, "{} cfsr {:08x} addr {:08x}", record.name(), record.cfsr, record.address).ok();
+    crash_dump::record(CrashReason::Fault, record.pc, record.lr, None, &detail).ok();
+    //  Pause in the debugger. Without a debugger, `bkpt` would cause a lockup.
+    if unsafe { core::ptr::read_volatile(DHCSR) } & 1 != 0 { cortex_m::asm::bkpt(); }
+    panic::reset()
+}
+
+/// Display the fault registers on the console
+fn show(record: &FaultRecord) {
+    let mut line = heapless::String::<heapless::consts::U64>::new();
+    write!(line, "{} pc {:08x} lr {:08x} sp {:08x}\n", record.name(), record.pc, record.lr, record.sp).ok();
+    console::buffer(&line);
+    line.clear();
+    write!(line, "r0 {:08x} r1 {:08x} r2 {:08x} r3 {:08x}\n", record.r0, record.r1, record.r2, record.r3).ok();
+    console::buffer(&line);
+    line.clear();
+    write!(line, "r12 {:08x} xpsr {:08x} hfsr {:08x}\n", record.r12, record.xpsr, record.hfsr).ok();
+    console::buffer(&line);
+    line.clear();
+    write!(line, "cfsr {:08x} addr {:08x}\n", record.cfsr, record.address).ok();
+    console::buffer(&line);
+    console::flush();
+}
+
+extern "C" {
+    /// Fault handler that calls `rust_fault_handler()` with the stack frame.
+    /// C API: `void rust_fault_entry(void)` in `libs/mynewt_rust/src/fault_handler.c`
+    fn rust_fault_entry();
+}
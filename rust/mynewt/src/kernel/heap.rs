@@ -0,0 +1,168 @@
+//! Global allocator backed by the Mynewt heap, enabled by the `alloc` feature. With this allocator,
+//! `alloc::vec::Vec`, `alloc::string::String` and `alloc::boxed::Box` may be used for payloads and assets whose size
+//! is only known at runtime. Memory comes from `os_malloc()`, which is thread-safe, so `Vec` may be used in any task.
+//! Don't allocate in interrupt handlers. The heap shares the 64 KB of RAM with the stacks and statics, so prefer
+//! `heapless` for fixed-size buffers.
+//!
+//! The allocator tracks the current and peak number of bytes allocated, which may be queried with `heap_stats()`
+//! to monitor memory pressure at runtime. When an allocation fails, the out-of-memory hook set by
+//! `set_alloc_failure_hook()` is called before the allocator returns null, e.g. to free a cache or log the failure.
+//! If the caller can't handle the failure (like `Vec::push()`), `alloc_error()` displays the heap usage and panics.
+
+use core::alloc::{ GlobalAlloc, Layout };
+use crate::{
+    kernel::os,
+    sys::console,
+};
+
+/// Alignment of the blocks returned by `os_malloc()`
+const MALLOC_ALIGN: usize = 8;
+
+/// Allocator that allocates from the Mynewt heap with `os_malloc()`
+pub struct MynewtHeap;
+
+/// The global allocator for `alloc`
+#[global_allocator]
+static HEAP: MynewtHeap = MynewtHeap;
+
+/// Heap usage by the global allocator. Sizes are the bytes requested, excluding the overhead of `os_malloc()`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapStats {
+    /// Number of bytes currently allocated
+    pub current: usize,
+    /// Highest number of bytes allocated at the same time
+    pub peak: usize,
+    /// Number of blocks currently allocated
+    pub blocks: usize,
+    /// Number of allocations that have failed
+    pub failures: usize,
+}
+
+impl HeapStats {
+    /// Display the heap usage on the console
+    pub fn show(&self) {
+        console::print("Heap: ");      console::printint(self.current as i32);
+        console::print(" bytes, peak "); console::printint(self.peak as i32);
+        console::print(", blocks ");   console::printint(self.blocks as i32);
+        console::print(", failures "); console::printint(self.failures as i32);
+        console::print("\n"); console::flush();
+    }
+}
+
+/// Heap usage, updated with interrupts disabled
+static mut HEAP_STATS: HeapStats = HeapStats { current: 0, peak: 0, blocks: 0, failures: 0 };
+
+/// Function called when an allocation fails
+static mut ALLOC_FAILURE_HOOK: Option<fn(Layout, &HeapStats)> = None;
+
+/// Return the current heap usage
+pub fn heap_stats() -> HeapStats {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let stats = unsafe { HEAP_STATS };
+    unsafe { os::os_arch_restore_sr(sr) };
+    stats
+}
+
+/// Reset the peak heap usage to the current usage, e.g. before measuring the peak usage of an operation
+pub fn reset_peak() {
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe { HEAP_STATS.peak = HEAP_STATS.current };
+    unsafe { os::os_arch_restore_sr(sr) };
+}
+
+/// Call `hook` whenever an allocation fails, with the layout that couldn't be allocated and the heap usage.
+/// The hook may be called in any task, so it should be quick.
+pub fn set_alloc_failure_hook(hook: fn(Layout, &HeapStats)) {
+    unsafe { ALLOC_FAILURE_HOOK = Some(hook) };
+}
+
+/// Remove the allocation failure hook
+pub fn clear_alloc_failure_hook() {
+    unsafe { ALLOC_FAILURE_HOOK = None };
+}
+
+/// Count a failed allocation for `layout` and call the allocation failure hook, if any
+fn alloc_failed(layout: Layout) {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let stats = unsafe {
+        HEAP_STATS.failures += 1;
+        HEAP_STATS
+    };
+    unsafe { os::os_arch_restore_sr(sr) };
+    if let Some(hook) = unsafe { ALLOC_FAILURE_HOOK } { hook(layout, &stats); }
+}
+
+/// Update the heap usage for a block of `size` bytes that was allocated (`blocks` is 1), freed (`blocks` is -1)
+/// or resized from `old_size` bytes (`blocks` is 0)
+fn track(old_size: usize, size: usize, blocks: isize) {
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe {
+        HEAP_STATS.current = HEAP_STATS.current - old_size + size;
+        HEAP_STATS.blocks = (HEAP_STATS.blocks as isize + blocks) as usize;
+        if HEAP_STATS.current > HEAP_STATS.peak { HEAP_STATS.peak = HEAP_STATS.current; }
+        os::os_arch_restore_sr(sr);
+    }
+}
+
+unsafe impl GlobalAlloc for MynewtHeap {
+    /// Allocate a block for `layout`. Returns null if the heap is exhausted.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr =
+            if layout.align() <= MALLOC_ALIGN { os::os_malloc(layout.size()) as *mut u8 }
+            else { alloc_aligned(layout) };
+        if ptr.is_null() { alloc_failed(layout); }
+        else { track(0, layout.size(), 1); }
+        ptr
+    }
+
+    /// Free the block at `ptr` that was allocated for `layout`
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if layout.align() <= MALLOC_ALIGN { os::os_free(ptr as *mut ::cty::c_void); }
+        else { os::os_free(*(ptr as *mut *mut u8).offset(-1) as *mut ::cty::c_void); }
+        track(layout.size(), 0, -1);
+    }
+
+    /// Resize the block at `ptr` to `new_size` bytes. Returns null if the heap is exhausted, leaving the block unchanged.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.align() > MALLOC_ALIGN {
+            //  `os_realloc()` won't keep the alignment, so allocate a new block and copy.
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            let new_ptr = self.alloc(new_layout);
+            if !new_ptr.is_null() {
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, core::cmp::min(layout.size(), new_size));
+                self.dealloc(ptr, layout);
+            }
+            return new_ptr;
+        }
+        let new_ptr = os::os_realloc(ptr as *mut ::cty::c_void, new_size) as *mut u8;
+        if new_ptr.is_null() {
+            alloc_failed(Layout::from_size_align_unchecked(new_size, layout.align()));
+        } else {
+            track(layout.size(), new_size, 0);
+        }
+        new_ptr
+    }
+}
+
+/// Allocate a block for `layout`, whose alignment is larger than `MALLOC_ALIGN`. We allocate extra space for
+/// aligning the block, and save the pointer returned by `os_malloc()` just before the aligned block.
+unsafe fn alloc_aligned(layout: Layout) -> *mut u8 {
+    let size = match layout.size().checked_add(layout.align()) {
+        Some(size) => size,
+        None => return core::ptr::null_mut(),
+    };
+    let raw = os::os_malloc(size) as *mut u8;
+    if raw.is_null() { return raw; }
+    //  Skip at least `MALLOC_ALIGN` bytes so there is room for the saved pointer.
+    let offset = layout.align() - (raw as usize & (layout.align() - 1));
+    let ptr = raw.add(offset);
+    *(ptr as *mut *mut u8).offset(-1) = raw;
+    ptr
+}
+
+/// Called when an allocation fails and the caller can't handle the failure, e.g. `Vec::push()` when the heap is full
+#[alloc_error_handler]
+fn alloc_error(_layout: Layout) -> ! {
+    heap_stats().show();
+    panic!("alloc fail");
+}
@@ -0,0 +1,183 @@
+//! High-resolution timer with microsecond resolution, wrapping the Mynewt `hal_timer` API. OS ticks are 1 millisecond,
+//! too coarse for display reset pulses and SPI Flash timing margins. The timer runs on nRF52 TIMER1 at 1 MHz,
+//! enabled by `TIMER_1: 1` in `hw/bsp/nrf52/syscfg.yml`. The counter is 32 bits, so it wraps around every 71 minutes.
+//!
+//! `OneShot` calls a Rust function when the timer reaches a compare value. The function runs in the timer
+//! interrupt handler, so it must be quick and must not block: post an event or push to a `Channel` instead.
+
+use core::cell::UnsafeCell;
+use crate::{
+    result::*,
+    kernel::os,
+};
+
+/// Mynewt timer number for nRF52 TIMER1
+pub const HIRES_TIMER_NUM: i32 = 1;
+
+/// Frequency of the timer: 1 tick per microsecond
+pub const HIRES_FREQ: u32 = 1_000_000;
+
+/// True if `hal_timer_config()` has been called
+static mut CONFIGURED: bool = false;
+
+/// Return the current timer value in microseconds. Wraps around after `2^32` microseconds.
+pub fn now_us() -> u32 {
+    init();
+    unsafe { hal_timer_read(HIRES_TIMER_NUM) }
+}
+
+/// Return the number of microseconds since the timer value `since`, returned by `now_us()`
+pub fn elapsed_us(since: u32) -> u32 {
+    now_us().wrapping_sub(since)
+}
+
+/// Wait for `us` microseconds by polling the timer. Other tasks won't run while waiting,
+/// so use `kernel::sleep()` for delays of a millisecond or more.
+pub fn delay_us(us: u32) {
+    init();
+    let rc = unsafe { hal_timer_delay(HIRES_TIMER_NUM, us) };
+    assert!(rc == 0, "hires delay fail");
+}
+
+/// Configure the timer to run at 1 MHz if this is the first use
+fn init() {
+    unsafe {
+        if CONFIGURED { return; }
+        let sr = os::os_arch_save_sr();
+        if !CONFIGURED {
+            let rc = hal_timer_config(HIRES_TIMER_NUM, HIRES_FREQ);
+            assert!(rc == 0, "hires config fail");  //  TIMER_1 is not enabled in syscfg.yml
+            CONFIGURED = true;
+        }
+        os::os_arch_restore_sr(sr);
+    }
+}
+
+/// One-shot timer that calls `F` in the timer interrupt handler when it expires.
+/// Must be declared `static`, since the `hal_timer` refers to the `OneShot`.
+pub struct OneShot<F> {
+    /// The Mynewt timer
+    timer: UnsafeCell<hal_timer>,
+    /// True if `hal_timer_set_cb()` has been called
+    initialised: UnsafeCell<bool>,
+    /// Function or closure to be called when the timer expires
+    func: UnsafeCell<F>,
+}
+
+/// `OneShot` may be shared between tasks and the timer interrupt handler
+unsafe impl<F: Send> Sync for OneShot<F> {}
+
+impl<F> OneShot<F> {
+    /// Create a stopped timer that will call `func` when it expires
+    pub const fn new(func: F) -> Self {
+        OneShot {
+            timer: UnsafeCell::new(hal_timer {
+                bsp_timer: core::ptr::null_mut(),
+                cb_func:   None,
+                cb_arg:    core::ptr::null_mut(),
+                expiry:    0,
+                link:      hal_timer_link {
+                    tqe_next: core::ptr::null_mut(),
+                    tqe_prev: core::ptr::null_mut(),
+                },
+            }),
+            initialised: UnsafeCell::new(false),
+            func: UnsafeCell::new(func),
+        }
+    }
+}
+
+impl<F: FnMut() + Send> OneShot<F> {
+    /// Start the timer so that it expires after `us` microseconds. Stop the timer before restarting it.
+    pub fn start(&'static self, us: u32) -> MynewtResult<()> {
+        self.init() ? ;
+        check_hal(unsafe { hal_timer_start(self.timer.get(), us) })
+    }
+
+    /// Start the timer so that it expires when the timer value reaches `at_us`, returned by `now_us()`
+    pub fn start_at(&'static self, at_us: u32) -> MynewtResult<()> {
+        self.init() ? ;
+        check_hal(unsafe { hal_timer_start_at(self.timer.get(), at_us) })
+    }
+
+    /// Stop the timer. The function won't be called if the timer has not expired.
+    pub fn stop(&'static self) {
+        if unsafe { !*self.initialised.get() } { return; }  //  Never started
+        unsafe { hal_timer_stop(self.timer.get()) };
+    }
+
+    /// Set the callback of the `hal_timer` if this is the first use
+    fn init(&'static self) -> MynewtResult<()> {
+        init();
+        if unsafe { *self.initialised.get() } { return Ok(()); }
+        let sr = unsafe { os::os_arch_save_sr() };
+        let rc = unsafe {
+            if *self.initialised.get() { 0 }
+            else {
+                let rc = hal_timer_set_cb(
+                    HIRES_TIMER_NUM,                //  Timer number
+                    self.timer.get(),               //  Timer to be initialised
+                    Some(oneshot_trampoline::<F>),  //  Call the Rust callback
+                    self as *const Self as *mut ::cty::c_void  //  Argument: this `OneShot`
+                );
+                if rc == 0 { *self.initialised.get() = true; }
+                rc
+            }
+        };
+        unsafe { os::os_arch_restore_sr(sr) };
+        check_hal(rc)
+    }
+}
+
+/// Called in the timer interrupt handler when the timer expires. `arg` is the `OneShot`.
+extern "C" fn oneshot_trampoline<F: FnMut() + Send>(arg: *mut ::cty::c_void) {
+    let oneshot = unsafe { &*(arg as *const OneShot<F>) };
+    let func = unsafe { &mut *oneshot.func.get() };
+    func();
+}
+
+/// Mynewt timer. Must sync with `struct hal_timer` in `hal/hal_timer.h`
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct hal_timer {
+    /// Internal platform specific pointer
+    bsp_timer: *mut ::cty::c_void,
+    /// Callback function
+    cb_func: Option<unsafe extern "C" fn(arg: *mut ::cty::c_void)>,
+    /// Callback argument
+    cb_arg: *mut ::cty::c_void,
+    /// Tick at which timer should expire
+    expiry: u32,
+    /// Queue linked list structure
+    link: hal_timer_link,
+}
+
+/// Links of the timer queue in `struct hal_timer`
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct hal_timer_link {
+    tqe_next: *mut hal_timer,
+    tqe_prev: *mut *mut hal_timer,
+}
+
+extern "C" {
+    /// Set the frequency of timer `timer_num` in Hz. C API: `int hal_timer_config(int timer_num, uint32_t freq_hz)`
+    fn hal_timer_config(timer_num: i32, freq_hz: u32) -> i32;
+    /// Return the current value of timer `timer_num`. C API: `uint32_t hal_timer_read(int timer_num)`
+    fn hal_timer_read(timer_num: i32) -> u32;
+    /// Wait for `ticks` timer ticks by polling. C API: `int hal_timer_delay(int timer_num, uint32_t ticks)`
+    fn hal_timer_delay(timer_num: i32, ticks: u32) -> i32;
+    /// Set the callback of `timer`. C API: `int hal_timer_set_cb(int timer_num, struct hal_timer *timer, hal_timer_cb cb_func, void *arg)`
+    fn hal_timer_set_cb(
+        timer_num: i32,
+        timer: *mut hal_timer,
+        cb_func: Option<unsafe extern "C" fn(arg: *mut ::cty::c_void)>,
+        arg: *mut ::cty::c_void,
+    ) -> i32;
+    /// Start `timer` to expire after `ticks` ticks. C API: `int hal_timer_start(struct hal_timer *, uint32_t ticks)`
+    fn hal_timer_start(timer: *mut hal_timer, ticks: u32) -> i32;
+    /// Start `timer` to expire at tick `tick`. C API: `int hal_timer_start_at(struct hal_timer *, uint32_t tick)`
+    fn hal_timer_start_at(timer: *mut hal_timer, tick: u32) -> i32;
+    /// Stop `timer`. C API: `int hal_timer_stop(struct hal_timer *)`
+    fn hal_timer_stop(timer: *mut hal_timer) -> i32;
+}
@@ -0,0 +1,177 @@
+//! Event loops that let a task sleep until the next event, instead of polling. While every task is blocked on its
+//! event queue, Mynewt runs the idle task, which sleeps the CPU in `os_tick_idle()` until the next timer expires or an
+//! interrupt arrives. The nRF52 port is tickless: the RTC wakes the CPU for the next timer only, not every tick.
+//! So a task that waits with `run()` or `run_once()` costs no CPU time while idle, but a task that polls with
+//! `os_time_delay()` wakes the CPU at every poll.
+//! ```
+//! //  Main event loop
+//! idle::run_default()
+//! ```
+//! `stats()` returns the time that the CPU has slept in `os_tick_idle()`, measured by `apps/my_sensor_app/src/idle_stats.c`
+//! when `IDLE_STATS: 1`. `start_idle_monitor()` checks periodically that the CPU sleeps, and logs the busiest task
+//! when it doesn't, e.g. a task that polls in a loop. In debug builds, the monitor panics if the CPU never slept.
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{
+        os, stack_guard, time,
+        task::{ self, MaxTaskStats, TaskStats },
+        timer::Callout,
+    },
+    sys::console,
+};
+
+/// Frequency of the RTC that measures the time asleep
+const RTC_FREQ: u64 = 32_768;
+
+/// Idle statistics since startup, returned by `stats()`. Must sync with `struct idle_stats` in
+/// `apps/my_sensor_app/src/idle_stats.c`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct IdleStats {
+    /// Number of times the CPU went to sleep. 0 if the statistics are disabled.
+    pub sleeps: u32,
+    /// Total time asleep, in RTC ticks at 32768 Hz
+    pub asleep_rtc: u64,
+}
+
+impl IdleStats {
+    /// Return the total time asleep
+    pub fn asleep(&self) -> Duration {
+        Duration::from_micros(self.asleep_rtc * 1_000_000 / RTC_FREQ)
+    }
+}
+
+/// Timer that checks the time asleep periodically
+static IDLE_MONITOR: Callout<fn()> = Callout::new(check_idle);
+
+/// Percentage of time asleep below which the idle monitor logs the busiest task
+static mut IDLE_THRESHOLD: u8 = 0;
+
+/// Interval between checks by the idle monitor
+static mut IDLE_CHECK_PERIOD: Duration = Duration::from_secs(0);
+
+/// Time, idle statistics and task statistics at the last check of the idle monitor
+static mut LAST_CHECK: Option<(time::Instant, IdleStats, heapless::Vec<TaskStats, MaxTaskStats>)> = None;
+
+/// Percentage of time asleep during the last period of the idle monitor
+static mut ASLEEP_PERCENT: Option<u8> = None;
+
+/// Process the events posted to `queue` forever. The task sleeps while the queue is empty. The stack canaries of
+/// the task are checked after each event.
+pub fn run(queue: *mut os::os_eventq) -> ! {
+    loop {
+        os::eventq_run(queue).expect("eventq fail");
+        stack_guard::check_current();
+    }
+}
+
+/// Process the events posted to the default event queue forever. Called by the main task.
+pub fn run_default() -> ! {
+    let queue = os::eventq_dflt_get().expect("eventq fail");
+    run(queue)
+}
+
+/// Wait up to `timeout` for an event posted to `queue`, and process the event. The task sleeps while waiting.
+/// Returns `Ok(true)` if an event was processed, `Ok(false)` if the timeout expired.
+pub fn run_once(queue: *mut os::os_eventq, timeout: Duration) -> MynewtResult<bool> {
+    let mut queues = [ queue ];
+    let ev = unsafe { os::os_eventq_poll(queues.as_mut_ptr(), 1, time::duration_to_ticks(timeout)) };
+    if ev.is_null() { return Ok(false); }
+    unsafe {
+        match (*ev).ev_cb {
+            Some(cb) => cb(ev),
+            None => return Err(MynewtError::SYS_EINVAL),  //  Event without callback
+        }
+    }
+    Ok(true)
+}
+
+/// Process the events posted to `queue` until `done()` returns true or `timeout` expires, e.g. to wait for
+/// a background transfer without polling. `done()` is checked after each event. Returns `SYS_ETIMEOUT` on timeout.
+pub fn run_until<F: FnMut() -> bool>(queue: *mut os::os_eventq, timeout: Duration, mut done: F) -> MynewtResult<()> {
+    let start = time::Instant::now();
+    while !done() {
+        let elapsed = start.elapsed();
+        if elapsed >= timeout { return Err(MynewtError::SYS_ETIMEOUT); }
+        run_once(queue, timeout - elapsed) ? ;
+    }
+    Ok(())
+}
+
+/// Return the idle statistics since startup
+pub fn stats() -> IdleStats {
+    let mut stats = IdleStats::default();
+    unsafe { idle_stats_get(&mut stats) };
+    stats
+}
+
+/// Return the percentage of time that the CPU slept during the last period of the idle monitor, or `None` if the
+/// monitor has not completed a period or the idle statistics are disabled
+pub fn asleep_percent() -> Option<u8> {
+    unsafe { ASLEEP_PERCENT }
+}
+
+/// Check the time asleep every `period`, and log the busiest task if the CPU slept less than `threshold` percent
+/// of the period. The check runs in the default event queue.
+pub fn start_idle_monitor(threshold: u8, period: Duration) -> MynewtResult<()> {
+    unsafe {
+        IDLE_THRESHOLD = threshold;
+        IDLE_CHECK_PERIOD = period;
+        LAST_CHECK = None;
+    }
+    check_idle();
+    Ok(())
+}
+
+/// Stop checking the time asleep
+pub fn stop_idle_monitor() {
+    IDLE_MONITOR.stop();
+}
+
+/// Compute the time asleep since the last check, and log the task that ran the longest if the CPU slept too little
+fn check_idle() {
+    let now = time::Instant::now();
+    let idle = stats();
+    let tasks = task::stats();
+    if let Some((then, last, last_tasks)) = unsafe { LAST_CHECK.take() } {
+        let elapsed_ms = now.duration_since(then).as_millis() as u64;
+        if idle.sleeps > 0 && elapsed_ms > 0 {
+            //  The idle task never ran, so a task is running without sleeping.
+            debug_assert!(idle.sleeps != last.sleeps, "cpu never idle");
+            let asleep_ms = (idle.asleep_rtc - last.asleep_rtc) * 1000 / RTC_FREQ;
+            let percent = core::cmp::min(asleep_ms * 100 / elapsed_ms, 100) as u8;
+            unsafe { ASLEEP_PERCENT = Some(percent) };
+            if percent < unsafe { IDLE_THRESHOLD } { show_busiest(percent, &tasks, &last_tasks); }
+        }
+    }
+    unsafe { LAST_CHECK = Some((now, idle, tasks)) };
+    IDLE_MONITOR.reset(unsafe { IDLE_CHECK_PERIOD }).expect("idle monitor fail");
+}
+
+/// Log the task, other than the idle task, whose run time increased the most since `last_tasks`
+fn show_busiest(percent: u8, tasks: &[TaskStats], last_tasks: &[TaskStats]) {
+    let busiest = tasks.iter()
+        .filter(|t| t.name() != "idle")
+        .map(|t| {
+            let last = last_tasks.iter().find(|l| l.id == t.id).map(|l| l.run_time).unwrap_or(0);
+            (t, t.run_time.wrapping_sub(last))
+        })
+        .max_by_key(|(_, run_time)| *run_time);
+    console::print("idle low ");  console::printint(percent as i32);
+    console::print("%");
+    if let Some((stats, run_time)) = busiest {
+        console::print(", busiest "); console::buffer(stats.name());
+        console::print(" ");          console::printint(time::ticks_to_ms(run_time) as i32);
+        console::print(" ms");
+    }
+    console::print("\n");
+    console::flush();
+}
+
+extern "C" {
+    /// Copy the idle statistics to `out`. Defined in `apps/my_sensor_app/src/idle_stats.c`.
+    /// C API: `void idle_stats_get(struct idle_stats *out)`
+    fn idle_stats_get(out: *mut IdleStats);
+}
@@ -0,0 +1,204 @@
+//! Low-power timer on the nRF52 RTC2, for long sleeps. The RTC counts the 32.768 kHz `LFCLK`, so unlike
+//! `hires` (TIMER1) and `hal_timer`, it doesn't keep the 64 MHz `HFCLK` running while the CPU sleeps. RTC0 is used by
+//! `os_cputime` for Bluetooth LE and RTC1 by the OS tick, so RTC2 is free. The counter runs at `LP_FREQ`, 1024 ticks
+//! per second, and is 24 bits, so it wraps around every 4.5 hours and alarms may be up to `MAX_ALARM` in the future.
+//!
+//! `Alarm` calls a Rust function in the default event queue when the RTC reaches a compare value. Up to
+//! `MAX_ALARMS` alarms may be pending, one per RTC compare channel.
+//! ```
+//! static WAKE_ALARM: Alarm = Alarm::new(wake_up);
+//! WAKE_ALARM.start(Duration::from_secs(3600)) ? ;  //  Call `wake_up()` in an hour
+//! ```
+
+use core::{ cell::UnsafeCell, ptr, time::Duration };
+use crate::{
+    result::*,
+    kernel::os,
+};
+
+/// Frequency of the timer: `LFCLK` divided by `PRESCALER` + 1
+pub const LP_FREQ: u32 = 1024;
+
+/// Number of alarms that may be pending at the same time, i.e. the number of RTC2 compare channels
+pub const MAX_ALARMS: usize = 4;
+
+/// Longest alarm. The counter is 24 bits, and a compare value must be at least 2 ticks ahead of the counter.
+pub const MAX_ALARM: Duration = Duration::from_secs(((COUNTER_MASK - 2) / LP_FREQ) as u64);
+
+/// Address of the nRF52 `RTC2` registers. From nRF52832 Product Specification, section 24.9
+const RTC2_BASE: usize = 0x4002_4000;
+const RTC2_TASKS_START:    *mut u32 = (RTC2_BASE + 0x000) as *mut u32;
+const RTC2_EVENTS_COMPARE: *mut u32 = (RTC2_BASE + 0x140) as *mut u32;
+const RTC2_INTENSET:       *mut u32 = (RTC2_BASE + 0x304) as *mut u32;
+const RTC2_INTENCLR:       *mut u32 = (RTC2_BASE + 0x308) as *mut u32;
+const RTC2_COUNTER:        *mut u32 = (RTC2_BASE + 0x504) as *mut u32;
+const RTC2_PRESCALER:      *mut u32 = (RTC2_BASE + 0x508) as *mut u32;
+const RTC2_CC:             *mut u32 = (RTC2_BASE + 0x540) as *mut u32;
+
+/// `INTENSET` and `INTENCLR` bit of compare channel 0. Channel `n` is `INTEN_COMPARE0 << n`.
+const INTEN_COMPARE0: u32 = 1 << 16;
+
+/// Counter and compare values are 24 bits
+const COUNTER_MASK: u32 = 0x00FF_FFFF;
+
+/// Interrupt number of `RTC2` and the ARM Cortex-M registers for enabling it
+const RTC2_IRQN: usize = 36;
+const SCB_VTOR:   *mut u32 = 0xE000_ED08 as *mut u32;
+const NVIC_ISER1: *mut u32 = 0xE000_E104 as *mut u32;
+const NVIC_IPR:   *mut u8  = 0xE000_E400 as *mut u8;
+
+/// Lowest nRF52 interrupt priority, since the alarm functions run later in the default event queue anyway
+const RTC2_PRIO: u8 = 7 << 5;
+
+/// True if the RTC has been started
+static mut STARTED: bool = false;
+
+/// Alarm waiting on each compare channel
+static mut CHANNELS: [Option<&'static Alarm>; MAX_ALARMS] = [None; MAX_ALARMS];
+
+/// Return the current counter value in ticks of `1 / LP_FREQ` seconds. Wraps around after `2^24` ticks.
+pub fn now_ticks() -> u32 {
+    init();
+    unsafe { ptr::read_volatile(RTC2_COUNTER) }
+}
+
+/// Return the number of ticks since the counter value `since`, returned by `now_ticks()`
+pub fn elapsed_ticks(since: u32) -> u32 {
+    now_ticks().wrapping_sub(since) & COUNTER_MASK
+}
+
+/// Convert a duration to ticks, rounded up so that an alarm never expires early
+pub fn duration_to_ticks(duration: Duration) -> u32 {
+    let ticks = duration.as_secs() * LP_FREQ as u64
+        + (duration.subsec_micros() as u64 * LP_FREQ as u64 + 999_999) / 1_000_000;
+    if ticks > COUNTER_MASK as u64 { COUNTER_MASK } else { ticks as u32 }
+}
+
+/// Start the RTC at `LP_FREQ` if this is the first use. `LFCLK` has been started by the OS tick.
+fn init() {
+    unsafe {
+        if STARTED { return; }
+        let sr = os::os_arch_save_sr();
+        if !STARTED {
+            ptr::write_volatile(RTC2_PRESCALER, 32_768 / LP_FREQ - 1);
+            //  The interrupt vectors follow the 16 Cortex-M exception vectors, in the table relocated to RAM.
+            let vectors = ptr::read_volatile(SCB_VTOR) as *mut usize;
+            ptr::write_volatile(vectors.add(16 + RTC2_IRQN), handle_compare as usize);
+            ptr::write_volatile(NVIC_IPR.add(RTC2_IRQN), RTC2_PRIO);
+            ptr::write_volatile(NVIC_ISER1, 1 << (RTC2_IRQN - 32));
+            ptr::write_volatile(RTC2_TASKS_START, 1);
+            STARTED = true;
+        }
+        os::os_arch_restore_sr(sr);
+    }
+}
+
+/// Alarm that calls a Rust function in the default event queue when it expires. Must be declared `static`, since
+/// the compare channel refers to the `Alarm`.
+pub struct Alarm {
+    /// Event posted to the default event queue when the alarm expires
+    event: UnsafeCell<os::os_event>,
+    /// Compare channel of the pending alarm, or `None` if stopped
+    channel: UnsafeCell<Option<usize>>,
+    /// Function to be called when the alarm expires
+    func: fn(),
+}
+
+/// `Alarm` may be shared between tasks and the RTC interrupt handler
+unsafe impl Sync for Alarm {}
+
+impl Alarm {
+    /// Create a stopped alarm that will call `func` when it expires
+    pub const fn new(func: fn()) -> Self {
+        Alarm {
+            event: UnsafeCell::new(os::os_event {
+                ev_queued: 0,
+                ev_cb:     None,
+                ev_arg:    ptr::null_mut(),
+                ev_next:   os::os_event__bindgen_ty_1 { stqe_next: ptr::null_mut() },
+            }),
+            channel: UnsafeCell::new(None),
+            func,
+        }
+    }
+
+    /// Start the alarm so that it expires after `timeout`, replacing the previous expiry. Returns `SYS_EINVAL` if
+    /// `timeout` is longer than `MAX_ALARM`, and `SYS_ENOMEM` if `MAX_ALARMS` other alarms are pending.
+    pub fn start(&'static self, timeout: Duration) -> MynewtResult<()> {
+        if timeout > MAX_ALARM { return Err(MynewtError::SYS_EINVAL); }
+        init();
+        //  The compare event is missed if the compare value is less than 2 ticks ahead of the counter.
+        let ticks = core::cmp::max(duration_to_ticks(timeout), 2);
+        let queue = os::eventq_dflt_get() ? ;
+        let sr = unsafe { os::os_arch_save_sr() };
+        let result = unsafe {
+            ALARM_QUEUE = queue;
+            (*self.event.get()).ev_cb  = Some(handle_alarm_event);
+            (*self.event.get()).ev_arg = self as *const Self as *mut ::cty::c_void;
+            //  Reuse the channel of the pending alarm, else take a free channel.
+            let channel = (*self.channel.get())
+                .or_else(|| CHANNELS.iter().position(|c| c.is_none()));
+            match channel {
+                Some(n) => {
+                    CHANNELS[n] = Some(self);
+                    *self.channel.get() = Some(n);
+                    let counter = ptr::read_volatile(RTC2_COUNTER);
+                    ptr::write_volatile(RTC2_EVENTS_COMPARE.add(n), 0);
+                    ptr::write_volatile(RTC2_CC.add(n), counter.wrapping_add(ticks) & COUNTER_MASK);
+                    ptr::write_volatile(RTC2_INTENSET, INTEN_COMPARE0 << n);
+                    Ok(())
+                }
+                None => Err(MynewtError::SYS_ENOMEM),
+            }
+        };
+        unsafe { os::os_arch_restore_sr(sr) };
+        result
+    }
+
+    /// Stop the alarm. The function won't be called if the alarm has not expired.
+    pub fn stop(&'static self) {
+        let sr = unsafe { os::os_arch_save_sr() };
+        unsafe {
+            release(self);
+            if let Ok(queue) = os::eventq_dflt_get() { os::os_eventq_remove(queue, self.event.get()); }
+            os::os_arch_restore_sr(sr);
+        }
+    }
+
+    /// Return true if the alarm has been started and has not expired
+    pub fn is_pending(&'static self) -> bool {
+        unsafe { (*self.channel.get()).is_some() }
+    }
+}
+
+/// Event queue for posting the expired alarms, set by `Alarm::start()`
+static mut ALARM_QUEUE: *mut os::os_eventq = ptr::null_mut();
+
+/// Free the compare channel of the alarm and disable its interrupt. Must be called with interrupts disabled.
+unsafe fn release(alarm: &'static Alarm) {
+    if let Some(n) = (*alarm.channel.get()).take() {
+        ptr::write_volatile(RTC2_INTENCLR, INTEN_COMPARE0 << n);
+        ptr::write_volatile(RTC2_EVENTS_COMPARE.add(n), 0);
+        CHANNELS[n] = None;
+    }
+}
+
+/// RTC2 interrupt handler. Post the expired alarms to the default event queue.
+extern "C" fn handle_compare() {
+    for n in 0..MAX_ALARMS {
+        unsafe {
+            if ptr::read_volatile(RTC2_EVENTS_COMPARE.add(n)) == 0 { continue; }
+            ptr::write_volatile(RTC2_EVENTS_COMPARE.add(n), 0);
+            if let Some(alarm) = CHANNELS[n] {
+                release(alarm);
+                if !ALARM_QUEUE.is_null() { os::os_eventq_put(ALARM_QUEUE, alarm.event.get()); }
+            }
+        }
+    }
+}
+
+/// Call the function of the expired alarm. Called by the default event queue. `ev_arg` is the `Alarm`.
+extern "C" fn handle_alarm_event(event: *mut os::os_event) {
+    let alarm = unsafe { &*((*event).ev_arg as *const Alarm) };
+    (alarm.func)();
+}
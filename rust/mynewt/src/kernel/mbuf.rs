@@ -0,0 +1,136 @@
+//! Safe wrapper for Mynewt mbuf chains. `Mbuf` owns an `os_mbuf` chain and frees it when dropped,
+//! so packet buffers can be built with `append()` and inspected with `segments()` without walking raw pointers.
+//! Mbufs are allocated from the system mbuf pools (msys), like the buffers used by the CoAP transmit path.
+
+use core::{
+    marker::PhantomData,
+    mem::ManuallyDrop,
+};
+use crate::{
+    result::*,
+    kernel::os,
+    libs::sensor_coap,
+};
+
+/// Chain of mbufs that is freed when dropped
+pub struct Mbuf {
+    /// First mbuf of the chain, with the packet header. Never null.
+    om: *mut os::os_mbuf,
+}
+
+/// `Mbuf` may be sent to another task
+unsafe impl Send for Mbuf {}
+
+impl Mbuf {
+    /// Allocate an empty mbuf chain with a packet header, with room for `len` bytes in the first mbuf.
+    /// More mbufs are added to the chain when appending beyond the first mbuf. Returns `SYS_ENOMEM` if msys is exhausted.
+    pub fn new(len: u16) -> MynewtResult<Mbuf> {
+        let om = unsafe { os::os_msys_get_pkthdr(len, 0) };
+        if om.is_null() { return Err(MynewtError::SYS_ENOMEM); }
+        Ok(Mbuf { om })
+    }
+
+    /// Take ownership of the mbuf chain `om`, e.g. one returned by `os_mqueue_get()`. The chain will be freed when dropped.
+    /// Returns `None` if `om` is null.
+    pub unsafe fn from_raw(om: *mut os::os_mbuf) -> Option<Mbuf> {
+        if om.is_null() { return None; }
+        Some(Mbuf { om })
+    }
+
+    /// Give up ownership of the mbuf chain and return it, e.g. for passing to `os_mqueue_put()` which frees the chain
+    pub fn into_raw(self) -> *mut os::os_mbuf {
+        let om = self.om;
+        core::mem::forget(self);
+        om
+    }
+
+    /// Return the mbuf chain, for passing to Mynewt APIs. The chain is still owned by this `Mbuf`.
+    pub fn as_ptr(&self) -> *mut os::os_mbuf {
+        self.om
+    }
+
+    /// Append `data` to the end of the chain, adding mbufs to the chain if needed. Returns `SYS_ENOMEM` if msys is exhausted,
+    /// in which case some of the data may have been appended.
+    pub fn append(&mut self, data: &[u8]) -> MynewtResult<()> {
+        for chunk in data.chunks(u16::max_value() as usize) {
+            let rc = unsafe { os::os_mbuf_append(
+                self.om,
+                chunk.as_ptr() as *const ::cty::c_void,
+                chunk.len() as u16
+            ) };
+            if rc != 0 { return Err(MynewtError::SYS_ENOMEM); }
+        }
+        Ok(())
+    }
+
+    /// Return the number of bytes in the chain
+    pub fn len(&self) -> usize {
+        unsafe { os::os_mbuf_len(self.om) as usize }
+    }
+
+    /// Return true if the chain contains no data
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copy the bytes starting at `offset` into `buf`. Returns the number of bytes copied,
+    /// which is less than the size of `buf` if the chain ends first.
+    pub fn copy_to(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let len = self.len();
+        if offset >= len { return 0; }
+        let count = core::cmp::min(buf.len(), len - offset);
+        let rc = unsafe { os::os_mbuf_copydata(
+            self.om,
+            offset as ::cty::c_int,
+            count as ::cty::c_int,
+            buf.as_mut_ptr() as *mut ::cty::c_void
+        ) };
+        assert!(rc == 0, "mbuf copy fail");
+        count
+    }
+
+    /// Return an iterator over the data of each mbuf in the chain
+    pub fn segments(&self) -> Segments {
+        Segments { om: self.om, mbuf: PhantomData }
+    }
+
+    /// Return the mbuf chain as the mbuf type used by the CoAP library, e.g. for `sensor_coap::json_rep_new()`.
+    /// The chain is still owned by this `Mbuf`, so it must not be dropped while the CoAP library is using it.
+    pub fn as_coap_ptr(&self) -> *mut sensor_coap::os_mbuf {
+        self.om as *mut sensor_coap::os_mbuf
+    }
+
+    /// Return the mbuf chain that holds the CoAP JSON payload being composed, or `None` if no payload has been started.
+    /// The chain is owned by the CoAP library and won't be freed when the returned value is dropped.
+    pub fn coap_json() -> Option<ManuallyDrop<Mbuf>> {
+        let om = unsafe { sensor_coap::coap_json_mbuf } as *mut os::os_mbuf;
+        unsafe { Mbuf::from_raw(om) }.map(ManuallyDrop::new)
+    }
+}
+
+impl Drop for Mbuf {
+    /// Free the mbuf chain
+    fn drop(&mut self) {
+        unsafe { os::os_mbuf_free_chain(self.om) };
+    }
+}
+
+/// Iterator over the data of each mbuf in a chain, returned by `Mbuf::segments()`
+pub struct Segments<'a> {
+    /// Next mbuf in the chain, or null if none
+    om: *const os::os_mbuf,
+    /// The iterator borrows the chain
+    mbuf: PhantomData<&'a Mbuf>,
+}
+
+impl<'a> Iterator for Segments<'a> {
+    type Item = &'a [u8];
+
+    /// Return the data of the next mbuf in the chain
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.om.is_null() { return None; }
+        let data = unsafe { core::slice::from_raw_parts((*self.om).om_data, (*self.om).om_len as usize) };
+        self.om = unsafe { (*self.om).om_next.sle_next };
+        Some(data)
+    }
+}
@@ -0,0 +1,144 @@
+//! Soft reset of the device, now or after a delay. `now()` flushes the console, records the reboot reason for the next
+//! boot in RAM that is not cleared at startup, and restarts the device with `os_reboot()`, which writes the Mynewt
+//! reboot log and shuts down the packages before resetting. `now()` blocks the calling task while the packages shut
+//! down, so the default event queue uses `after()`, which returns to the queue instead. After restarting,
+//! `take_last()` returns the reason.
+//! ```
+//! reboot::after(Duration::from_secs(2), RebootReason::LogoUpdated) ? ;  //  Let the BLE response go out first
+//! ```
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{ os, time, timer::Callout },
+    sys::{ console, noinit::{ NoInit, NoInitValue }, panic },
+};
+
+/// `HAL_RESET_REQUESTED` reset reason for the reboot log. From `hal/hal_system.h`
+const HAL_RESET_REQUESTED: i32 = 6;
+
+/// Max time for the Mynewt packages to shut down before we reset anyway
+const SHUTDOWN_TIMEOUT_MS: u32 = 1000;
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(SHUTDOWN_TIMEOUT_MS as u64);
+
+/// Reason for a requested reboot, recorded for the next boot
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum RebootReason {
+    /// Requested by the user, e.g. from the shell
+    Requested      = 1,
+    /// New logo was flashed, so that the bootloader shows it
+    LogoUpdated    = 2,
+    /// New firmware was uploaded or confirmed
+    FirmwareUpdate = 3,
+    /// Logo factory reset
+    FactoryReset   = 4,
+}
+
+impl RebootReason {
+    /// Return the name of the reason, for display
+    pub fn name(self) -> &'static str {
+        match self {
+            RebootReason::Requested      => "requested",
+            RebootReason::LogoUpdated    => "logo updated",
+            RebootReason::FirmwareUpdate => "firmware update",
+            RebootReason::FactoryReset   => "factory reset",
+        }
+    }
+
+    /// Convert the recorded code to a reason
+    fn from_code(code: u32) -> Option<RebootReason> {
+        match code {
+            1 => Some(RebootReason::Requested),
+            2 => Some(RebootReason::LogoUpdated),
+            3 => Some(RebootReason::FirmwareUpdate),
+            4 => Some(RebootReason::FactoryReset),
+            _ => None,
+        }
+    }
+}
+
+/// Reboot reason for the next boot
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct RebootRecord {
+    /// `RebootReason` code
+    reason: u32,
+}
+
+unsafe impl NoInitValue for RebootRecord {
+    const MAGIC: u32 = 0x544f_4f42;  //  `BOOT`
+}
+
+/// Reboot reason, stored in the Mynewt section `.bss.core.nz` that is not cleared at startup
+#[link_section = ".bss.core.nz"]
+static mut REBOOT_RECORD: NoInit<RebootRecord> = NoInit::new(RebootRecord { reason: 0 });
+
+/// Reason for the reboot scheduled by `after()`
+static mut SCHEDULED_REASON: RebootReason = RebootReason::Requested;
+
+/// Timer for the reboot scheduled by `after()`
+static REBOOT_TIMER: Callout<fn()> = Callout::new(scheduled_reboot);
+
+/// Timer that resets the device if the Mynewt packages take too long to shut down after `after()`
+static RESET_TIMER: Callout<fn()> = Callout::new(forced_reset);
+
+/// Restart the device now, recording `reason` for the next boot. Blocks the calling task until the device restarts,
+/// so this must not be called from the default event queue, which the packages may need to shut down. Use `after()`
+/// there instead.
+pub fn now(reason: RebootReason) -> ! {
+    shut_down(reason);
+    //  Some packages shut down in the background. Reset anyway if they take too long.
+    time::sleep_ms(SHUTDOWN_TIMEOUT_MS);
+    panic::reset()
+}
+
+/// Restart the device after `delay`, e.g. to let a Bluetooth LE response go out.
+/// Replaces any reboot that was scheduled earlier.
+pub fn after(delay: Duration, reason: RebootReason) -> MynewtResult<()> {
+    unsafe { SCHEDULED_REASON = reason };
+    REBOOT_TIMER.reset(delay)
+}
+
+/// Cancel the reboot scheduled by `after()`, if any
+pub fn cancel() {
+    REBOOT_TIMER.stop();
+}
+
+/// Record `reason` for the next boot and start shutting down the Mynewt packages with `os_reboot()`. Returns if some
+/// packages shut down in the background, and the device restarts when they are done.
+fn shut_down(reason: RebootReason) {
+    console::print("reboot: "); console::buffer(reason.name());
+    console::print("\n"); console::flush();
+    unsafe {
+        REBOOT_RECORD.save(RebootRecord { reason: reason as u32 });
+        os::os_reboot(HAL_RESET_REQUESTED);
+    }
+}
+
+/// Called by the timer to restart the device. Returns to the default event queue after starting the shutdown, so that
+/// the packages that shut down in the background can finish, and resets anyway after `SHUTDOWN_TIMEOUT`.
+fn scheduled_reboot() {
+    shut_down(unsafe { SCHEDULED_REASON });
+    if RESET_TIMER.reset(SHUTDOWN_TIMEOUT).is_err() { panic::reset(); }
+}
+
+/// Called by the timer when the packages have not shut down in time, to reset the device
+fn forced_reset() {
+    panic::reset()
+}
+
+/// Return the reason for the reboot before the last restart, and clear the record.
+/// Returns `None` if the last restart was not requested with `now()` or `after()`.
+pub fn take_last() -> Option<RebootReason> {
+    let record = unsafe { REBOOT_RECORD.take() } ? ;
+    RebootReason::from_code(record.reason)
+}
+
+/// Display the reason for the reboot before the last restart, if any, and clear the record
+pub fn show_last() {
+    if let Some(reason) = take_last() {
+        console::print("last reboot: "); console::buffer(reason.name());
+        console::print("\n"); console::flush();
+    }
+}
@@ -0,0 +1,127 @@
+//! Reason for the last reset of the device. `init()` reads the nRF52 `RESETREAS` register before `sysinit()`,
+//! because the Mynewt reboot log calls `hal_reset_cause()` during `sysinit()`, which clears the register and
+//! reports CPU lockups as watchdog resets. A software reset with a valid panic record is reported as `Panic`, and
+//! with a valid fault record from `kernel/fault.rs` as `Fault`, or as `StackOverflow` after `kernel/stack_guard.rs`
+//! detected a stack overflow.
+
+use crate::{
+    kernel::{ fault, stack_guard },
+    sys::{ console, panic },
+};
+
+/// Address of the nRF52 `POWER.RESETREAS` register
+const RESETREAS: *mut u32 = 0x4000_0400 as *mut u32;
+
+/// `RESETREAS` bits. From nRF52832 Product Specification, section 18.8.3
+const RESETREAS_RESETPIN: u32 = 1 << 0;
+const RESETREAS_DOG:      u32 = 1 << 1;
+const RESETREAS_SREQ:     u32 = 1 << 2;
+const RESETREAS_LOCKUP:   u32 = 1 << 3;
+const RESETREAS_WAKEUP:   u32 = (1 << 16) | (1 << 17) | (1 << 18) | (1 << 19);  //  OFF, LPCOMP, DIF, NFC
+
+/// `hal_reset_reason` codes returned by `hal_reset_cause()`. From `hal/hal_system.h`
+const HAL_RESET_PIN:         i32 = 2;
+const HAL_RESET_WATCHDOG:    i32 = 3;
+const HAL_RESET_SOFT:        i32 = 4;
+const HAL_RESET_REQUESTED:   i32 = 6;
+const HAL_RESET_SYS_OFF_INT: i32 = 7;
+
+/// Reason for the last reset. The value is transmitted to the CoAP server as the `reset` code.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum ResetReason {
+    /// Battery connected, or power-on reset after brownout
+    PowerOn   = 0,
+    /// Reset pin, e.g. by the debugger
+    Pin       = 1,
+    /// Watchdog expired because a task hung
+    Watchdog  = 2,
+    /// Software reset, e.g. after firmware update
+    SoftReset = 3,
+    /// CPU lockup, e.g. a fault in the HardFault handler
+    Lockup    = 4,
+    /// Software reset by the panic handler
+    Panic     = 5,
+    /// Wakeup from System OFF mode
+    Wakeup    = 6,
+    /// Software reset by the fault handler, e.g. after a HardFault
+    Fault     = 7,
+    /// Software reset after a task overflowed its stack
+    StackOverflow = 8,
+}
+
+impl ResetReason {
+    /// Return the name of the reset reason, for display
+    pub fn name(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn   => "Power On",
+            ResetReason::Pin       => "Reset Pin",
+            ResetReason::Watchdog  => "Watchdog",
+            ResetReason::SoftReset => "Soft Reset",
+            ResetReason::Lockup    => "Lockup",
+            ResetReason::Panic     => "Panic",
+            ResetReason::Wakeup    => "Wakeup",
+            ResetReason::Fault     => "Fault",
+            ResetReason::StackOverflow => "Stack Overflow",
+        }
+    }
+}
+
+/// Reason for the last reset, set by `init()`
+static mut REASON: Option<ResetReason> = None;
+
+/// Decode the reset reason before the Mynewt reboot log clears it. Called by `mynewt::sysinit()`.
+pub fn init() {
+    if unsafe { REASON.is_some() } { return; }
+    let resetreas = unsafe { core::ptr::read_volatile(RESETREAS) };
+    let cause = unsafe { hal_reset_cause() };  //  Clears `RESETREAS` so that the next reset starts afresh
+    let reason =
+        if resetreas != 0 { from_resetreas(resetreas) }
+        else { from_hal(cause) };  //  Cleared by an earlier call to `hal_reset_cause()`
+    //  A panic or a fault restarts the device with a software reset.
+    let reason =
+        if reason == ResetReason::SoftReset && panic::has_last() { ResetReason::Panic }
+        else if reason == ResetReason::SoftReset && fault::has_last() { ResetReason::Fault }
+        else if reason == ResetReason::SoftReset && stack_guard::has_last() { ResetReason::StackOverflow }
+        else { reason };
+    unsafe { REASON = Some(reason) };
+}
+
+/// Return the reason for the last reset
+pub fn reason() -> ResetReason {
+    init();
+    unsafe { REASON.unwrap_or(ResetReason::PowerOn) }
+}
+
+/// Display the reason for the last reset
+pub fn show_reason() {
+    console::print("reset: "); console::buffer(reason().name());
+    console::print("\n"); console::flush();
+}
+
+/// Decode the `RESETREAS` register. If multiple bits are set, the first match below wins.
+fn from_resetreas(resetreas: u32) -> ResetReason {
+    if      resetreas & RESETREAS_LOCKUP   != 0 { ResetReason::Lockup }
+    else if resetreas & RESETREAS_DOG      != 0 { ResetReason::Watchdog }
+    else if resetreas & RESETREAS_SREQ     != 0 { ResetReason::SoftReset }
+    else if resetreas & RESETREAS_RESETPIN != 0 { ResetReason::Pin }
+    else if resetreas & RESETREAS_WAKEUP   != 0 { ResetReason::Wakeup }
+    else { ResetReason::PowerOn }
+}
+
+/// Decode the reason returned by `hal_reset_cause()`, which doesn't distinguish lockups from watchdog resets
+fn from_hal(cause: i32) -> ResetReason {
+    match cause {
+        HAL_RESET_PIN         => ResetReason::Pin,
+        HAL_RESET_WATCHDOG    => ResetReason::Watchdog,
+        HAL_RESET_SOFT        => ResetReason::SoftReset,
+        HAL_RESET_REQUESTED   => ResetReason::SoftReset,
+        HAL_RESET_SYS_OFF_INT => ResetReason::Wakeup,
+        _                     => ResetReason::PowerOn,
+    }
+}
+
+extern "C" {
+    /// Return the reason for the last reset, and clear `RESETREAS`. C API: `enum hal_reset_reason hal_reset_cause(void)`
+    fn hal_reset_cause() -> i32;
+}
@@ -0,0 +1,269 @@
+//! Scheduler for periodic jobs and alarms, like cron. `every()` runs a job after every period, counted by the OS
+//! tick counter, e.g. the uploads to the CoAP server. `at()` runs a job every day at a local time of the wall clock,
+//! e.g. a nightly flush or a user alarm. All jobs share one `Callout`, which expires when the next job is due, so
+//! the CPU sleeps between the jobs. The jobs run on the default event queue, one after another.
+//! ```
+//! schedule::every("upload", Duration::from_secs(60 * 60), upload) ? ;
+//! schedule::at("flush", WallClockTime::new(23, 55), flush) ? ;
+//! ```
+//! The jobs at a wall clock time wait until the wall clock is set, e.g. by the phone over the Current Time Service.
+//! Call `clock_changed()` after setting the wall clock, so that the jobs are scheduled for the new time. The jobs are
+//! code, so they are scheduled again by the application at startup. The application saves the entries that are
+//! created at runtime, like the user alarms, in its settings and schedules them again from the settings.
+
+use core::{
+    fmt::{ self, Write },
+    sync::atomic::{ AtomicBool, Ordering },
+    time::Duration,
+};
+use crate::{
+    result::*,
+    kernel::{
+        os,
+        time::{ self, Instant },
+        timer::Callout,
+    },
+    sys::console,
+};
+
+/// Job that is run by the scheduler on the default event queue
+pub type Job = fn();
+
+/// Max number of scheduled jobs. Must match `MaxJobs`.
+pub const MAX_JOBS: usize = 12;
+type MaxJobs = heapless::consts::U12;
+
+/// Longest wait between checks of the jobs, so that the jobs at a wall clock time follow the drift of the clock
+const MAX_WAIT: Duration = Duration::from_secs(60 * 60);
+
+/// Wait between checks while the wall clock is not set
+const CLOCK_WAIT: Duration = Duration::from_secs(60);
+
+/// Number of seconds in a day
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Handle of a scheduled job, for cancelling it. Invalid after the job is cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct JobId(usize);
+
+/// Local time of day on the wall clock, e.g. `07:30`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WallClockTime {
+    /// Hours, 0 to 23
+    pub hour:   u8,
+    /// Minutes, 0 to 59
+    pub minute: u8,
+}
+
+impl WallClockTime {
+    /// Return the time of day `hour:minute`. Must be valid, see `parse()` for unchecked input.
+    pub const fn new(hour: u8, minute: u8) -> Self {
+        WallClockTime { hour, minute }
+    }
+
+    /// Parse the time of day `hh:mm`, e.g. `07:30`. Returns `SYS_EINVAL` if invalid.
+    pub fn parse(text: &str) -> MynewtResult<Self> {
+        let mut parts = text.splitn(2, ':');
+        let hour: u8 = parts.next().and_then(|hour| hour.parse().ok()).ok_or(MynewtError::SYS_EINVAL) ? ;
+        let minute: u8 = parts.next().and_then(|minute| minute.parse().ok()).ok_or(MynewtError::SYS_EINVAL) ? ;
+        if hour > 23 || minute > 59 { return Err(MynewtError::SYS_EINVAL); }
+        Ok(WallClockTime { hour, minute })
+    }
+
+    /// Return the seconds since midnight
+    fn secs(&self) -> i64 {
+        self.hour as i64 * 3600 + self.minute as i64 * 60
+    }
+
+    /// Return the next occurrence of this time of day after the local time `now`, in local seconds since 1970
+    fn next_after(&self, now: i64) -> i64 {
+        let midnight = now - now.rem_euclid(SECS_PER_DAY);
+        let due = midnight + self.secs();
+        if due > now { due } else { due + SECS_PER_DAY }
+    }
+}
+
+impl fmt::Display for WallClockTime {
+    /// Format as `hh:mm`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+/// When a job runs
+#[derive(Clone, Copy)]
+enum When {
+    /// After every period
+    Every(Duration),
+    /// Every day at the local time
+    At(WallClockTime),
+}
+
+/// Time when a job is due next
+#[derive(Clone, Copy)]
+enum Due {
+    /// At the OS tick count
+    Ticks(Instant),
+    /// At the local time in seconds since 1970
+    Local(i64),
+    /// When the wall clock is set, then at the local time
+    Clock,
+}
+
+/// Scheduled job
+#[derive(Clone, Copy)]
+struct Entry {
+    /// Name of the job, for `show()`
+    name: &'static str,
+    /// When the job runs
+    when: When,
+    /// When the job is due next
+    due:  Due,
+    /// Function that runs the job
+    job:  Job,
+}
+
+/// Scheduled jobs, `None` for a free entry. The index of the entry is the `JobId`.
+static mut JOBS: [Option<Entry>; MAX_JOBS] = [None; MAX_JOBS];
+
+/// True if the wall clock has been changed since the jobs at a wall clock time were scheduled
+static CLOCK_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Timer that expires when the next job is due
+static SCHEDULE_TIMER: Callout<fn()> = Callout::new(run_due);
+
+/// Run `job` after every `period`, starting `period` from now. Returns `SYS_EINVAL` if the period is zero or too
+/// long for the OS tick counter, `SYS_ENOMEM` if `MAX_JOBS` jobs are scheduled.
+pub fn every(name: &'static str, period: Duration, job: Job) -> MynewtResult<JobId> {
+    if period.as_millis() == 0 { return Err(MynewtError::SYS_EINVAL); }
+    let due = Instant::now().checked_add(period).ok_or(MynewtError::SYS_EINVAL) ? ;
+    add(Entry { name, when: When::Every(period), due: Due::Ticks(due), job })
+}
+
+/// Run `job` every day at the local time `time` of the wall clock. Returns `SYS_ENOMEM` if `MAX_JOBS` jobs are
+/// scheduled.
+pub fn at(name: &'static str, time: WallClockTime, job: Job) -> MynewtResult<JobId> {
+    if time.hour > 23 || time.minute > 59 { return Err(MynewtError::SYS_EINVAL); }
+    let due = match local_now() {
+        Some(now) => Due::Local(time.next_after(now)),
+        None      => Due::Clock,
+    };
+    add(Entry { name, when: When::At(time), due, job })
+}
+
+/// Cancel the job. Returns `SYS_ENOENT` if the job is not scheduled.
+pub fn cancel(id: JobId) -> MynewtResult<()> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let entry = unsafe { JOBS.get_mut(id.0) }.and_then(|entry| entry.take());
+    unsafe { os::os_arch_restore_sr(sr) };
+    if entry.is_none() { return Err(MynewtError::SYS_ENOENT); }
+    reschedule()
+}
+
+/// Schedule the jobs at a wall clock time for the new time of the wall clock. Call after setting the wall clock.
+/// May be called from any task.
+pub fn clock_changed() {
+    CLOCK_CHANGED.store(true, Ordering::Relaxed);
+    if let Err(err) = SCHEDULE_TIMER.reset(Duration::from_millis(0)) { log::warn!("schedule fail {:?}", err); }
+}
+
+/// Show the scheduled jobs on the console, with the seconds until each job is due
+pub fn show() {
+    let now = Instant::now();
+    let local = local_now();
+    for entry in unsafe { JOBS.iter() }.flatten() {
+        console::buffer(entry.name);
+        match entry.when {
+            When::Every(period) => {
+                console::print(" every "); console::printint(period.as_secs() as i32); console::print("s");
+            }
+            When::At(time) => {
+                let mut text = heapless::String::<heapless::consts::U8>::new();
+                write!(text, "{}", time).ok();
+                console::print(" at "); console::print(&text);
+            }
+        }
+        match wait(&entry.due, now, local) {
+            Some(wait) => {
+                console::print(", due in "); console::printint(wait.as_secs() as i32); console::print("s\n");
+            }
+            None => { console::print(", waiting for the clock\n"); }
+        }
+    }
+    console::flush();
+}
+
+/// Add the job and set the timer for the next job due
+fn add(entry: Entry) -> MynewtResult<JobId> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let index = unsafe { JOBS.iter().position(|entry| entry.is_none()) };
+    if let Some(index) = index { unsafe { JOBS[index] = Some(entry) }; }
+    unsafe { os::os_arch_restore_sr(sr) };
+    let index = index.ok_or(MynewtError::SYS_ENOMEM) ? ;
+    reschedule() ? ;
+    Ok(JobId(index))
+}
+
+/// Run the jobs that are due, then set the timer for the next job due. Called by the default event queue.
+fn run_due() {
+    let clock_changed = CLOCK_CHANGED.swap(false, Ordering::Relaxed);
+    let now = Instant::now();
+    let local = local_now();
+
+    //  Find the jobs that are due and schedule their next runs. The jobs run afterwards, since they may schedule
+    //  or cancel jobs.
+    let mut due_jobs = heapless::Vec::<Job, MaxJobs>::new();
+    let sr = unsafe { os::os_arch_save_sr() };
+    for entry in unsafe { JOBS.iter_mut() }.flatten() {
+        if let (When::At(time), Some(local)) = (entry.when, local) {
+            //  Schedule the jobs at a wall clock time for the new time, when the clock has been set or changed.
+            if clock_changed || matches!(entry.due, Due::Clock) {
+                entry.due = Due::Local(time.next_after(local));
+                continue;
+            }
+        }
+        if wait(&entry.due, now, local) != Some(Duration::from_millis(0)) { continue; }
+        entry.due = match (entry.when, local) {
+            (When::Every(period), _)      => Due::Ticks(now.checked_add(period).unwrap_or(now)),
+            (When::At(time), Some(local)) => Due::Local(time.next_after(local)),
+            (When::At(_), None)           => Due::Clock,
+        };
+        due_jobs.push(entry.job).ok();  //  Never full
+    }
+    unsafe { os::os_arch_restore_sr(sr) };
+
+    for job in due_jobs.iter() { job(); }
+    if let Err(err) = reschedule() { log::warn!("schedule fail {:?}", err); }
+}
+
+/// Set the timer for the next job due, at most `MAX_WAIT` from now
+fn reschedule() -> MynewtResult<()> {
+    let now = Instant::now();
+    let local = local_now();
+    let mut next: Option<Duration> = None;
+    for entry in unsafe { JOBS.iter() }.flatten() {
+        let wait = wait(&entry.due, now, local).unwrap_or(CLOCK_WAIT);
+        if next.map_or(true, |next| wait < next) { next = Some(wait); }
+    }
+    match next {
+        Some(wait) => SCHEDULE_TIMER.reset(core::cmp::min(wait, MAX_WAIT)),
+        None       => { SCHEDULE_TIMER.stop(); Ok(()) }  //  No jobs
+    }
+}
+
+/// Return the time until the job is due, zero if due now, or `None` if the job waits for the wall clock
+fn wait(due: &Due, now: Instant, local: Option<i64>) -> Option<Duration> {
+    match *due {
+        Due::Ticks(due) => Some(due.checked_duration_since(now).unwrap_or(Duration::from_millis(0))),
+        Due::Local(due) => {
+            let local = local ? ;
+            Some(Duration::from_secs(if due > local { (due - local) as u64 } else { 0 }))
+        }
+        Due::Clock => None,
+    }
+}
+
+/// Return the local time of the wall clock in seconds since 1970, or `None` if the clock has not been set
+fn local_now() -> Option<i64> {
+    time::local_time() ? .to_unix()
+}
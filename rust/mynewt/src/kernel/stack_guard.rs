@@ -0,0 +1,96 @@
+//! Stack overflow detection. `os_task_init()` paints the stack of each task with `OS_STACK_PATTERN` when the task is
+//! spawned, and the stack grows down from the top, so the lowest `GUARD_WORDS` words of the stack are canaries that
+//! keep the pattern until the stack is nearly full. Without a memory protection unit, an overflow silently corrupts
+//! the RAM below the stack, so the canaries are checked instead: for all tasks every `period` by the timer started
+//! with `start()`, and for the current task after each event processed by `idle::run()`. When a canary has been
+//! overwritten, the task is recorded in RAM that is not cleared at startup, displayed and the device is restarted
+//! before the corruption spreads. After restarting, `show_last()` displays the task that overflowed its stack.
+//! The canaries are part of the stack, so a task may use `GUARD_WORDS` words less than its stack size.
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{ os, task::Task, timer::Callout },
+    sys::{ console, crash_dump::{ self, CrashReason }, noinit::{ self, Name, NoInit, NoInitValue }, panic },
+};
+
+/// Number of 4-byte words at the bottom of each stack that must keep `OS_STACK_PATTERN`
+pub const GUARD_WORDS: usize = 8;
+
+/// Max number of bytes of the task name that will be recorded
+pub const OVERFLOW_NAME_SIZE: usize = noinit::NAME_SIZE;
+
+/// Task that overflowed its stack before the last restart
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Overflow {
+    /// Name of the task, truncated
+    name: Name,
+}
+
+unsafe impl NoInitValue for Overflow {
+    const MAGIC: u32 = 0x4b41_5453;  //  `STAK`
+}
+
+/// Task that overflowed its stack, stored in the Mynewt section `.bss.core.nz` that is not cleared at startup
+#[link_section = ".bss.core.nz"]
+static mut LAST_OVERFLOW: NoInit<Overflow> = NoInit::new(Overflow { name: Name::empty() });
+
+/// Timer that checks the canaries of all tasks
+static GUARD_TIMER: Callout<fn()> = Callout::new(check_all);
+
+/// Interval between checks of all tasks
+static mut CHECK_PERIOD: Duration = Duration::from_secs(0);
+
+/// Check the canaries of all tasks every `period`. The check runs in the default event queue.
+pub fn start(period: Duration) -> MynewtResult<()> {
+    unsafe { CHECK_PERIOD = period };
+    GUARD_TIMER.reset(period)
+}
+
+/// Return true if the canaries at the bottom of the stack of `task` are intact
+pub fn is_intact(task: &Task) -> bool {
+    let task = task.as_ptr();
+    let (top, size) = unsafe { ((*task).t_stacktop, (*task).t_stacksize as usize) };
+    if top.is_null() || size < GUARD_WORDS { return true; }  //  Stack too small to guard
+    let bottom = unsafe { top.sub(size) };
+    (0..GUARD_WORDS).all(|i| unsafe { core::ptr::read_volatile(bottom.add(i)) } == os::OS_STACK_PATTERN)
+}
+
+/// Check the canaries of the task that is running now, and restart if overwritten. Called by `idle::run()` after
+/// each event, and by tasks at points where the stack is deepest.
+pub fn check_current() {
+    let task = Task::current();
+    if !is_intact(&task) { overflow(&task); }
+}
+
+/// Return true if a stack overflow was recorded before the last restart and has not been shown
+pub fn has_last() -> bool {
+    unsafe { LAST_OVERFLOW.is_valid() }
+}
+
+/// Display the task that overflowed its stack before the last restart, if any, and clear the record
+pub fn show_last() {
+    if let Some(overflow) = unsafe { LAST_OVERFLOW.take() } {
+        console::print("last restart by stack overflow, task: "); console::buffer(overflow.name.as_str());
+        console::print("\n"); console::flush();
+    }
+}
+
+/// Check the canaries of all tasks, then check again after the period. Called by the default event queue.
+fn check_all() {
+    for task in Task::all().iter() {
+        if !is_intact(task) { overflow(task); }
+    }
+    GUARD_TIMER.reset(unsafe { CHECK_PERIOD }).expect("stack guard fail");
+}
+
+/// Record and display the task that overflowed its stack, and restart the device
+fn overflow(task: &Task) -> ! {
+    let name = task.name();
+    unsafe { LAST_OVERFLOW.save(Overflow { name: Name::new(name) }) };
+    console::print("stack overflow: "); console::buffer(name);
+    console::print("\n"); console::flush();
+    crash_dump::record(CrashReason::StackOverflow, 0, 0, Some(name), "").ok();
+    panic::reset()
+}
@@ -0,0 +1,160 @@
+//! Task-liveness supervisor that owns the hardware watchdog. Tasks call `register()` with the longest interval between
+//! their check-ins, then call `checkin()` periodically. The supervisor feeds the watchdog only when every registered task
+//! has checked in within its interval. When a task deadlocks or hangs, the supervisor records the task as the culprit
+//! and stops feeding the watchdog, so the watchdog restarts the device. If the supervisor itself can't run, e.g. when
+//! a higher priority task loops forever, the task that was running when the watchdog expired is recorded instead.
+//! `show_last_culprit()` displays the culprit after restarting.
+//!
+//! The supervisor runs in the default event queue, so the main task is also supervised. Mynewt must not feed the
+//! watchdog: set `WATCHDOG_INTERVAL: 0` in `syscfg.yml`.
+//! ```
+//! let worker = supervisor::register("worker", Duration::from_secs(10)) ? ;
+//! loop {
+//!     worker.checkin();
+//!     //  Do some work
+//! }
+//! ```
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    hal::watchdog::{ self, WatchdogConfig },
+    kernel::{
+        os,
+        task::Task,
+        time::Instant,
+        timer::Callout,
+    },
+    sys::{ console, crash_dump::{ self, CrashReason }, noinit::{ self, Name, NoInit, NoInitValue } },
+};
+
+/// Max number of tasks that may be registered
+pub const MAX_SUPERVISED: usize = 6;
+
+/// Max number of bytes of the culprit name that will be recorded
+pub const CULPRIT_NAME_SIZE: usize = noinit::NAME_SIZE;
+
+/// Handle for a registered task to check in with the supervisor
+#[derive(Clone, Copy)]
+pub struct Supervised {
+    /// Index of the task in `SUPERVISED`
+    index: usize,
+}
+
+impl Supervised {
+    /// Tell the supervisor that the task is alive
+    pub fn checkin(&self) {
+        let now = Instant::now();
+        if let Some(entry) = unsafe { SUPERVISED[self.index].as_mut() } { entry.last_checkin = now; }
+    }
+}
+
+/// Task registered with the supervisor
+#[derive(Clone, Copy)]
+struct Entry {
+    /// Task name, for recording the culprit
+    name: &'static str,
+    /// Longest interval allowed between check-ins
+    period: Duration,
+    /// Time of the last check-in
+    last_checkin: Instant,
+}
+
+/// Task that hung before the last restart
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Culprit {
+    /// Name of the task, truncated
+    name: Name,
+}
+
+unsafe impl NoInitValue for Culprit {
+    const MAGIC: u32 = 0x474e_5548;  //  `HUNG`
+}
+
+/// Registered tasks
+static mut SUPERVISED: [Option<Entry>; MAX_SUPERVISED] = [None; MAX_SUPERVISED];
+
+/// Task that hung before the last restart, stored in the Mynewt section `.bss.core.nz` that is not cleared at startup
+#[link_section = ".bss.core.nz"]
+static mut LAST_CULPRIT: NoInit<Culprit> = NoInit::new(Culprit { name: Name::empty() });
+
+/// Timer that checks the registered tasks and feeds the watchdog
+static SUPERVISOR_TIMER: Callout<fn()> = Callout::new(supervise);
+
+/// Interval between checks by the supervisor
+static mut CHECK_PERIOD: Duration = Duration::from_secs(0);
+
+/// True if a task has hung and the watchdog is no longer fed
+static mut HUNG: bool = false;
+
+/// Register the task named `name` with the supervisor. The task must call `checkin()` at least once every `period`,
+/// starting now. Returns `SYS_ENOMEM` if `MAX_SUPERVISED` tasks have been registered.
+pub fn register(name: &'static str, period: Duration) -> MynewtResult<Supervised> {
+    let entry = Entry { name, period, last_checkin: Instant::now() };
+    let sr = unsafe { os::os_arch_save_sr() };
+    let index = unsafe { SUPERVISED.iter().position(|e| e.is_none()) };
+    if let Some(i) = index { unsafe { SUPERVISED[i] = Some(entry) }; }
+    unsafe { os::os_arch_restore_sr(sr) };
+    match index {
+        Some(index) => Ok(Supervised { index }),
+        None => Err(MynewtError::SYS_ENOMEM),
+    }
+}
+
+/// Start the hardware watchdog with the configuration, e.g. `WatchdogConfig::new(30_000)` for 30 seconds, and start
+/// supervising the registered tasks. The watchdog can't be stopped once started.
+pub fn start(config: WatchdogConfig) -> MynewtResult<()> {
+    watchdog::set_early_warning(record_running_task);
+    watchdog::start(config) ? ;
+    //  Check 4 times per watchdog period, so that a late check doesn't restart the device.
+    unsafe { CHECK_PERIOD = config.timeout() / 4 };
+    SUPERVISOR_TIMER.reset(unsafe { CHECK_PERIOD })
+}
+
+/// Feed the watchdog if all registered tasks have checked in. Otherwise record the culprit and let the watchdog expire.
+fn supervise() {
+    if unsafe { HUNG } { return; }
+    let now = Instant::now();
+    for entry in unsafe { SUPERVISED.iter() } {
+        if let Some(entry) = entry {
+            if now - entry.last_checkin > entry.period {
+                record_culprit(entry.name);
+                return;  //  Stop feeding the watchdog
+            }
+        }
+    }
+    watchdog::feed();
+    SUPERVISOR_TIMER.reset(unsafe { CHECK_PERIOD }).expect("supervisor fail");
+}
+
+/// Record `name` as the task that hung, and display it
+fn record_culprit(name: &str) {
+    save_culprit(name);
+    console::print("task hung: "); console::buffer(name);
+    console::print("\n"); console::flush();
+    crash_dump::record(CrashReason::Hung, 0, 0, Some(name), "").ok();
+}
+
+/// Record the task that was running when the watchdog expired, unless the supervisor has recorded the culprit.
+/// Called by the watchdog interrupt just before the restart, so there is no time to display it.
+fn record_running_task() {
+    if unsafe { HUNG } { return; }
+    save_culprit(Task::current().name());
+}
+
+/// Save `name` as the culprit in the record that survives the restart
+fn save_culprit(name: &str) {
+    unsafe {
+        HUNG = true;
+        LAST_CULPRIT.save(Culprit { name: Name::new(name) });
+    }
+}
+
+/// Display the task that hung before the last restart, if any, and clear the record
+pub fn show_last_culprit() {
+    if let Some(culprit) = unsafe { LAST_CULPRIT.take() } {
+        console::print("last restart by watchdog, task hung: "); console::buffer(culprit.name.as_str());
+        console::print("\n"); console::flush();
+    }
+}
@@ -0,0 +1,205 @@
+//! Synchronisation primitives for sharing state between Mynewt tasks, wrapping the Mynewt OS API.
+//! `Mutex<T>` owns the protected value, which is only accessible through the guard returned by `lock()`.
+//! The guard releases the `os_mutex` when dropped, so the release can't be forgotten.
+//! `Semaphore` signals events between tasks, or from an interrupt handler (like an SPI or DMA callback) to a task.
+
+use core::{
+    cell::UnsafeCell,
+    ops::{ Deref, DerefMut },
+    time::Duration,
+};
+use crate::{
+    result::*,
+    kernel::{ os, time::duration_to_ticks },
+};
+
+/// Mutex backed by a Mynewt `os_mutex`. May be declared `static`: the `os_mutex` is initialised on first use.
+/// Mynewt mutexes support priority inheritance. They are recursive, but a second guard would give a second `&mut T`,
+/// so locking a mutex that the task already holds returns `SYS_EBUSY`. Must not be locked in an interrupt handler.
+pub struct Mutex<T> {
+    /// The Mynewt mutex
+    mutex: UnsafeCell<os::os_mutex>,
+    /// True if `os_mutex_init()` has been called
+    initialised: UnsafeCell<bool>,
+    /// The protected value
+    value: UnsafeCell<T>,
+}
+
+/// Guard that gives access to the value protected by a `Mutex`. The mutex is released when the guard is dropped.
+pub struct MutexGuard<'a, T> {
+    /// The locked mutex
+    mutex: &'a Mutex<T>,
+}
+
+/// `Mutex` may be shared between tasks
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+/// `Mutex` may be sent to another task
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    /// Create a mutex that protects `value`
+    pub const fn new(value: T) -> Self {
+        Mutex {
+            mutex: UnsafeCell::new(os::os_mutex {
+                mu_head:  os::os_mutex__bindgen_ty_1 { slh_first: core::ptr::null_mut() },
+                _pad:     0,
+                mu_prio:  0,
+                mu_level: 0,
+                mu_owner: core::ptr::null_mut(),
+            }),
+            initialised: UnsafeCell::new(false),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Lock the mutex, waiting forever until it's available. Returns a guard that releases the mutex when dropped.
+    /// Returns `SYS_EBUSY` if the calling task already holds the mutex.
+    pub fn lock(&self) -> MynewtResult<MutexGuard<T>> {
+        self.pend(os::OS_TIMEOUT_NEVER)
+    }
+
+    /// Lock the mutex, waiting up to `timeout` until it's available. Returns `SYS_ETIMEOUT` if the mutex was not available.
+    pub fn lock_timeout(&self, timeout: Duration) -> MynewtResult<MutexGuard<T>> {
+        self.pend(duration_to_ticks(timeout))
+    }
+
+    /// Lock the mutex if it's available, without waiting. Returns `SYS_ETIMEOUT` if the mutex is locked by another task.
+    pub fn try_lock(&self) -> MynewtResult<MutexGuard<T>> {
+        self.pend(0)
+    }
+
+    /// Wait up to `timeout` ticks for the mutex
+    fn pend(&self, timeout: os::os_time_t) -> MynewtResult<MutexGuard<T>> {
+        self.init();
+        //  Only the task that holds the mutex sets itself as owner, so the owner can't change to us while we check.
+        let owner = unsafe { (*self.mutex.get()).mu_owner };
+        if !owner.is_null() && owner == unsafe { os::os_sched_get_current_task() } {
+            return Err(MynewtError::SYS_EBUSY);
+        }
+        os_result(unsafe { os::os_mutex_pend(self.mutex.get(), timeout) }) ? ;
+        Ok(MutexGuard { mutex: self })
+    }
+
+    /// Initialise the `os_mutex` if this is the first use. Interrupts are disabled in case two tasks lock at the same time.
+    fn init(&self) {
+        unsafe {
+            if *self.initialised.get() { return; }
+            let sr = os::os_arch_save_sr();
+            if !*self.initialised.get() {
+                os::os_mutex_init(self.mutex.get());
+                *self.initialised.get() = true;
+            }
+            os::os_arch_restore_sr(sr);
+        }
+    }
+}
+
+impl<'a, T> Deref for MutexGuard<'a, T> {
+    type Target = T;
+
+    /// Return the protected value
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> DerefMut for MutexGuard<'a, T> {
+    /// Return the protected value for updating
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.value.get() }
+    }
+}
+
+impl<'a, T> Drop for MutexGuard<'a, T> {
+    /// Release the mutex
+    fn drop(&mut self) {
+        let rc = unsafe { os::os_mutex_release(self.mutex.mutex.get()) };
+        assert!(rc == os::os_error_OS_OK, "mutex release fail");
+    }
+}
+
+/// Counting semaphore backed by a Mynewt `os_sem`. May be declared `static`.
+/// `give()` may be called in an interrupt handler, `take()` must be called in a task.
+pub struct Semaphore {
+    /// The Mynewt semaphore
+    sem: UnsafeCell<os::os_sem>,
+}
+
+/// Error returned by `Semaphore::take()` and `Semaphore::try_take()`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TakeError {
+    /// No token was given before the timeout
+    TimedOut,
+    /// The OS returned an error, e.g. when called in an interrupt handler or before the OS has started
+    Os(MynewtError),
+}
+
+/// Convert `TakeError` to `MynewtError`, so that `?` may be used in functions that return `MynewtResult`
+impl From<TakeError> for MynewtError {
+    fn from(err: TakeError) -> Self {
+        match err {
+            TakeError::TimedOut => MynewtError::SYS_ETIMEOUT,
+            TakeError::Os(err)  => err,
+        }
+    }
+}
+
+/// `Semaphore` may be shared between tasks and interrupt handlers
+unsafe impl Sync for Semaphore {}
+
+/// `Semaphore` may be sent to another task
+unsafe impl Send for Semaphore {}
+
+impl Semaphore {
+    /// Create a semaphore with `tokens` tokens. Same as calling `os_sem_init()`.
+    pub const fn new(tokens: u16) -> Self {
+        Semaphore {
+            sem: UnsafeCell::new(os::os_sem {
+                sem_head:   os::os_sem__bindgen_ty_1 { slh_first: core::ptr::null_mut() },
+                _pad:       0,
+                sem_tokens: tokens,
+            }),
+        }
+    }
+
+    /// Take a token, waiting up to `timeout` for a token to be given. Returns `Err(TimedOut)` if no token was given.
+    pub fn take(&self, timeout: Duration) -> Result<(), TakeError> {
+        self.pend(duration_to_ticks(timeout))
+    }
+
+    /// Take a token, waiting forever until a token is given
+    pub fn take_forever(&self) -> MynewtResult<()> {
+        self.pend(os::OS_TIMEOUT_NEVER).map_err(MynewtError::from)
+    }
+
+    /// Take a token if one is available, without waiting. Returns `Err(TimedOut)` if no token is available.
+    pub fn try_take(&self) -> Result<(), TakeError> {
+        self.pend(0)
+    }
+
+    /// Give a token, waking up the highest priority task that is waiting. May be called in an interrupt handler.
+    pub fn give(&self) -> MynewtResult<()> {
+        os_result(unsafe { os::os_sem_release(self.sem.get()) })
+    }
+
+    /// Return the number of tokens available
+    pub fn tokens(&self) -> u16 {
+        unsafe { (*self.sem.get()).sem_tokens }
+    }
+
+    /// Wait up to `timeout` ticks for a token
+    fn pend(&self, timeout: os::os_time_t) -> Result<(), TakeError> {
+        match os_result(unsafe { os::os_sem_pend(self.sem.get(), timeout) }) {
+            Ok(()) => Ok(()),
+            Err(MynewtError::SYS_ETIMEOUT) => Err(TakeError::TimedOut),
+            Err(err) => Err(TakeError::Os(err)),
+        }
+    }
+}
+
+/// Convert the Mynewt OS error code `rc` to a `MynewtResult`
+pub fn os_result(rc: os::os_error_t) -> MynewtResult<()> {
+    if rc == os::os_error_OS_OK { Ok(()) }
+    else { Err(MynewtError::from_os(rc)) }
+}
@@ -0,0 +1,433 @@
+//! Safe API for creating Mynewt tasks. `spawn()` allocates the task object and the stack from static pools,
+//! so callers no longer declare `fill_zero!(os::os_task)` and call the unsafe `os_task_init()`. The stack pool only
+//! fits the small tasks that always run. Tasks with large stacks, or tasks enabled by a feature, declare their own
+//! `static` stack and call `spawn_with_stack()`, so the stack only takes up RAM when the task is linked in.
+//! There is no heap, so tasks and their stacks are never freed.
+//! `stats()` returns the stack usage and run counts of all Mynewt tasks, and `start_stack_monitor()` logs the tasks
+//! whose stacks are nearly full. Mynewt paints each stack with `OS_STACK_PATTERN` in `os_task_init()`, which also
+//! serves as the canaries checked by `stack_guard.rs` for stack overflows. `Task` changes the priority of any task
+//! at runtime, and suspends and resumes tasks.
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{ os, timer::Callout },
+    sys::console,
+    fill_zero,
+    Ptr, Strn,
+};
+
+/// Max number of tasks that may be spawned
+pub const MAX_TASKS: usize = 4;
+
+/// Total stack space for the tasks spawned with `spawn()`, in 4-byte units: the SPI task (384) and the pedometer
+/// task (512). Other tasks must bring their own stack with `spawn_with_stack()`.
+pub const STACK_POOL_SIZE: usize = 896;
+
+/// Handle to a spawned task
+#[derive(Clone, Copy)]
+pub struct TaskHandle {
+    /// Index of the task in `TASKS`
+    index: usize,
+}
+
+impl TaskHandle {
+    /// Return the Mynewt task object, for passing to Mynewt APIs
+    pub fn as_ptr(&self) -> *mut os::os_task {
+        unsafe { &mut TASKS[self.index] }
+    }
+
+    /// Return the Mynewt task ID
+    pub fn id(&self) -> u8 {
+        unsafe { TASKS[self.index].t_taskid }
+    }
+
+    /// Return the task priority: highest is 0, lowest is 255
+    pub fn prio(&self) -> u8 {
+        unsafe { TASKS[self.index].t_prio }
+    }
+
+    /// Return the task for changing the priority or suspending
+    pub fn task(&self) -> Task {
+        Task { task: self.as_ptr() }
+    }
+}
+
+/// Stack pool, aligned to `OS_STACK_ALIGNMENT`
+#[repr(align(8))]
+struct StackPool([os::os_stack_t; STACK_POOL_SIZE]);
+
+/// Task objects for the spawned tasks
+static mut TASKS: [os::os_task; MAX_TASKS] = fill_zero!([os::os_task; MAX_TASKS]);
+
+/// Function executed by each spawned task
+static mut TASK_FUNCS: [Option<fn()>; MAX_TASKS] = [None; MAX_TASKS];
+
+/// Number of tasks spawned
+static mut TASK_COUNT: usize = 0;
+
+/// Stack space for the spawned tasks
+static mut STACK_POOL: StackPool = StackPool([0; STACK_POOL_SIZE]);
+
+/// Number of 4-byte units of `STACK_POOL` that have been allocated
+static mut STACK_USED: usize = 0;
+
+/// Create a task named `name` with priority `prio` (highest is 0, lowest is 255, main task is 127) and a stack of
+/// `stack_size` 4-byte units from the stack pool, and start the task by calling `func`. The task sleeps forever if
+/// `func` returns. Returns `SYS_ENOMEM` if there are no free tasks or not enough stack space.
+pub fn spawn(name: &Strn, prio: u8, stack_size: usize, func: fn()) -> MynewtResult<TaskHandle> {
+    let stack_size = (stack_size + 1) & !1;  //  Keep the stacks aligned to 8 bytes
+    if stack_size > u16::max_value() as usize { return Err(MynewtError::SYS_EINVAL); }
+
+    //  Allocate the task and the stack with interrupts disabled, in case another task is spawning.
+    let sr = unsafe { os::os_arch_save_sr() };
+    let allocated = unsafe {
+        if STACK_USED + stack_size > STACK_POOL_SIZE { None }
+        else {
+            let index = alloc_task(func);
+            if index.is_some() { STACK_USED += stack_size; }
+            index.map(|index| (index, STACK_USED - stack_size))
+        }
+    };
+    unsafe { os::os_arch_restore_sr(sr) };
+    let (index, stack) = allocated.ok_or(MynewtError::SYS_ENOMEM) ? ;
+    start(index, name, prio, unsafe { &mut STACK_POOL.0[stack..stack + stack_size] })
+}
+
+/// Create a task named `name` with priority `prio` (highest is 0, lowest is 255, main task is 127) and the stack
+/// `stack`, and start the task by calling `func`. The task sleeps forever if `func` returns. Returns `SYS_ENOMEM`
+/// if there are no free tasks.
+/// ```
+/// static mut CHIP8_TASK_STACK: [os::os_stack_t; CHIP8_TASK_STACK_SIZE] = [0; CHIP8_TASK_STACK_SIZE];
+/// task::spawn_with_stack(&init_strn!("chip8"), 20, unsafe { &mut CHIP8_TASK_STACK }, task_func) ? ;
+/// ```
+pub fn spawn_with_stack(name: &Strn, prio: u8, stack: &'static mut [os::os_stack_t], func: fn())
+    -> MynewtResult<TaskHandle> {
+    if stack.len() > u16::max_value() as usize { return Err(MynewtError::SYS_EINVAL); }
+    let sr = unsafe { os::os_arch_save_sr() };
+    let index = unsafe { alloc_task(func) };
+    unsafe { os::os_arch_restore_sr(sr) };
+    let index = index.ok_or(MynewtError::SYS_ENOMEM) ? ;
+    start(index, name, prio, stack)
+}
+
+/// Allocate a task object for `func`. Returns `None` if there are no free tasks. Must be called with interrupts
+/// disabled.
+unsafe fn alloc_task(func: fn()) -> Option<usize> {
+    if TASK_COUNT >= MAX_TASKS { return None; }
+    let index = TASK_COUNT;
+    TASK_COUNT += 1;
+    TASK_FUNCS[index] = Some(func);
+    Some(index)
+}
+
+/// Start the allocated task `index` with the stack `stack`. `task_trampoline()` will call the task function.
+fn start(index: usize, name: &Strn, prio: u8, stack: &'static mut [os::os_stack_t]) -> MynewtResult<TaskHandle> {
+    name.validate();
+    let stack_size = stack.len() as u16;
+    os::task_init(
+        unsafe { &mut TASKS[index] },         //  Task object
+        name,                                 //  Name of task
+        Some(task_trampoline),                //  Function to execute when task starts
+        index as Ptr,                         //  Argument: index of the task
+        prio,                                 //  Task priority
+        os::OS_WAIT_FOREVER as u32,           //  Don't do sanity / watchdog checking
+        stack,                                //  Stack space for the task
+        stack_size                            //  Size of the stack (in 4-byte units)
+    ) ? ;
+    Ok(TaskHandle { index })
+}
+
+/// Start a spawned task by calling its function. `arg` is the index of the task.
+extern "C" fn task_trampoline(arg: Ptr) {
+    let index = arg as usize;
+    if let Some(func) = unsafe { TASK_FUNCS[index] } { func(); }
+
+    //  Mynewt tasks must not return.
+    loop { unsafe { os::os_time_delay(os::OS_TIMEOUT_NEVER) }; }
+}
+
+/// Max number of tasks returned by `stats()`
+pub type MaxTaskStats = heapless::consts::U12;
+
+/// Max number of bytes of the task name kept in `TaskStats`
+pub const TASK_NAME_SIZE: usize = 16;
+
+/// State of a Mynewt task
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TaskState {
+    /// Task is ready to run
+    Ready,
+    /// Task is sleeping or waiting for an event, semaphore or mutex
+    Sleep,
+    /// Unknown state
+    Unknown,
+}
+
+/// Statistics for a Mynewt task, returned by `stats()`
+#[derive(Clone, Copy)]
+pub struct TaskStats {
+    /// Mynewt task ID
+    pub id: u8,
+    /// Task priority: highest is 0, lowest is 255
+    pub prio: u8,
+    /// Task state
+    pub state: TaskState,
+    /// Highest stack usage (high-water mark) in 4-byte units, measured by the unused stack pattern
+    pub stack_used: u16,
+    /// Size of the stack in 4-byte units
+    pub stack_size: u16,
+    /// Number of times the task has been switched in
+    pub run_count: u32,
+    /// Total run time in OS ticks
+    pub run_time: u32,
+    /// Task name, truncated to `TASK_NAME_SIZE` bytes
+    name: [u8; TASK_NAME_SIZE],
+    /// Number of bytes used in `name`
+    name_len: u8,
+}
+
+impl TaskStats {
+    /// Return the task name
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(&self.name[..self.name_len as usize]).unwrap_or("?")
+    }
+
+    /// Return the highest stack usage as a percentage of the stack size
+    pub fn stack_percent(&self) -> u8 {
+        if self.stack_size == 0 { return 0; }
+        (self.stack_used as u32 * 100 / self.stack_size as u32) as u8
+    }
+
+    /// Display the statistics on the console
+    pub fn show(&self) {
+        console::print("Task ");      console::buffer(self.name());
+        console::print(": prio ");    console::printint(self.prio as i32);
+        console::print(", stack ");   console::printint(self.stack_used as i32 * 4);
+        console::print(" / ");        console::printint(self.stack_size as i32 * 4);
+        console::print(" bytes, runs "); console::printint(self.run_count as i32);
+        console::print(if self.state == TaskState::Ready { ", ready\n" } else { ", sleep\n" });
+        console::flush();
+    }
+}
+
+/// Return the statistics of all Mynewt tasks, including tasks not created by `spawn()`, by walking Mynewt's task list.
+/// Only the first `MaxTaskStats` tasks are returned.
+pub fn stats() -> heapless::Vec<TaskStats, MaxTaskStats> {
+    let mut result = heapless::Vec::new();
+    let mut prev: *mut os::os_task = core::ptr::null_mut();
+    loop {
+        let mut info = os::os_task_info::default();
+        prev = unsafe { os::os_task_info_get_next(prev, &mut info) };
+        if prev.is_null() { break; }
+        let mut name = [0; TASK_NAME_SIZE];
+        let name_len = info.oti_name.iter().position(|c| *c == 0).unwrap_or(info.oti_name.len());
+        let name_len = core::cmp::min(name_len, TASK_NAME_SIZE);
+        for i in 0..name_len { name[i] = info.oti_name[i] as u8; }
+        let stats = TaskStats {
+            id:         info.oti_taskid,
+            prio:       info.oti_prio,
+            state:      match info.oti_state as os::os_task_state {
+                os::os_task_state_OS_TASK_READY => TaskState::Ready,
+                os::os_task_state_OS_TASK_SLEEP => TaskState::Sleep,
+                _                               => TaskState::Unknown,
+            },
+            stack_used: info.oti_stkusage,
+            stack_size: info.oti_stksize,
+            run_count:  info.oti_cswcnt,
+            run_time:   info.oti_runtime,
+            name,
+            name_len:   name_len as u8,
+        };
+        if result.push(stats).is_err() { break; }  //  Too many tasks
+    }
+    result
+}
+
+/// Display the statistics of all Mynewt tasks on the console
+pub fn show_stats() {
+    for stats in stats().iter() { stats.show(); }
+}
+
+/// Timer that checks the stack usage of all tasks periodically
+static STACK_MONITOR: Callout<fn()> = Callout::new(check_stacks);
+
+/// Stack usage percentage that will be logged by the stack monitor
+static mut STACK_THRESHOLD: u8 = 0;
+
+/// Interval between stack checks by the stack monitor
+static mut STACK_CHECK_PERIOD: Duration = Duration::from_secs(0);
+
+/// Check the stack usage of all tasks every `period`, and log the tasks whose stack usage is `threshold` percent
+/// or more, so that stack overflows may be fixed before they happen. The check runs in the default event queue.
+pub fn start_stack_monitor(threshold: u8, period: Duration) -> MynewtResult<()> {
+    unsafe {
+        STACK_THRESHOLD = threshold;
+        STACK_CHECK_PERIOD = period;
+    }
+    STACK_MONITOR.reset(period)
+}
+
+/// Stop checking the stack usage
+pub fn stop_stack_monitor() {
+    STACK_MONITOR.stop();
+}
+
+/// Log the tasks whose stack usage exceeds the threshold, then check again after the period
+fn check_stacks() {
+    for stats in stats().iter() {
+        if stats.stack_percent() >= unsafe { STACK_THRESHOLD } {
+            console::print("stack high ");  console::printint(stats.stack_percent() as i32);
+            console::print("%: ");
+            stats.show();
+        }
+    }
+    STACK_MONITOR.reset(unsafe { STACK_CHECK_PERIOD }).expect("stack monitor fail");
+}
+
+/// Any Mynewt task, including tasks not created by `spawn()` like the main task. Used for changing the priority
+/// and suspending tasks at runtime.
+#[derive(Clone, Copy, PartialEq)]
+pub struct Task {
+    /// The Mynewt task object
+    task: *mut os::os_task,
+}
+
+/// IDs of the tasks suspended by `suspend()`, one bit per task ID
+static mut SUSPENDED: [u32; 8] = [0; 8];
+
+impl Task {
+    /// Return the task that is running now
+    pub fn current() -> Task {
+        Task { task: unsafe { os::os_sched_get_current_task() } }
+    }
+
+    /// Return all Mynewt tasks. Only the first `MaxTaskStats` tasks are returned.
+    pub fn all() -> heapless::Vec<Task, MaxTaskStats> {
+        let mut result = heapless::Vec::new();
+        let mut prev: *mut os::os_task = core::ptr::null_mut();
+        loop {
+            let mut info = os::os_task_info::default();
+            prev = unsafe { os::os_task_info_get_next(prev, &mut info) };
+            if prev.is_null() { break; }
+            if result.push(Task { task: prev }).is_err() { break; }  //  Too many tasks
+        }
+        result
+    }
+
+    /// Return the task named `name`, e.g. `main` or `spi`
+    pub fn find(name: &str) -> Option<Task> {
+        Task::all().iter()
+            .find(|t| t.name_bytes() == name.as_bytes())
+            .cloned()
+    }
+
+    /// Return the Mynewt task object, for passing to Mynewt APIs
+    pub fn as_ptr(&self) -> *mut os::os_task {
+        self.task
+    }
+
+    /// Return the Mynewt task ID
+    pub fn id(&self) -> u8 {
+        unsafe { (*self.task).t_taskid }
+    }
+
+    /// Return the task name, truncated to `TASK_NAME_SIZE` bytes
+    pub fn name(&self) -> &str {
+        core::str::from_utf8(self.name_bytes()).unwrap_or("?")
+    }
+
+    /// Return the task priority: highest is 0, lowest is 255
+    pub fn prio(&self) -> u8 {
+        unsafe { (*self.task).t_prio }
+    }
+
+    /// Change the task priority to `prio`. Mynewt requires each task to have a different priority, so this returns
+    /// `SYS_EBUSY` if another task has the priority. Don't call while the task holds a `Mutex`: the mutex restores
+    /// the old priority when released.
+    pub fn set_prio(&self, prio: u8) -> MynewtResult<()> {
+        if prio == self.prio() { return Ok(()); }
+        if Task::all().iter().any(|t| t.prio() == prio) { return Err(MynewtError::SYS_EBUSY); }
+        let sr = unsafe { os::os_arch_save_sr() };
+        unsafe {
+            (*self.task).t_prio = prio;
+            //  Move the task in the run list according to the new priority.
+            if (*self.task).t_state as os::os_task_state == os::os_task_state_OS_TASK_READY {
+                os::os_sched_resort(self.task);
+            }
+            os::os_arch_restore_sr(sr);
+            os::os_sched(core::ptr::null_mut());  //  Switch to the highest priority task
+        }
+        Ok(())
+    }
+
+    /// Raise the task priority to `prio` until the returned `PriorityBoost` is dropped, e.g. while writing to flash.
+    /// If the task already has a higher priority, the priority is not changed.
+    pub fn boost(&self, prio: u8) -> MynewtResult<PriorityBoost> {
+        let old_prio = self.prio();
+        if prio < old_prio { self.set_prio(prio) ? ; }
+        Ok(PriorityBoost { task: *self, old_prio })
+    }
+
+    /// Stop running the task until `resume()` is called. Only tasks that are ready to run may be suspended, so
+    /// this returns `SYS_EBUSY` if the task is waiting for an event, semaphore or mutex, or is sleeping.
+    pub fn suspend(&self) -> MynewtResult<()> {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let ready = unsafe { (*self.task).t_state as os::os_task_state == os::os_task_state_OS_TASK_READY };
+        if ready {
+            unsafe {
+                os::os_sched_sleep(self.task, os::OS_TIMEOUT_NEVER);
+                SUSPENDED[self.id() as usize / 32] |= 1 << (self.id() % 32);
+            }
+        }
+        unsafe { os::os_arch_restore_sr(sr) };
+        if !ready { return Err(MynewtError::SYS_EBUSY); }
+        unsafe { os::os_sched(core::ptr::null_mut()) };  //  If the current task was suspended, switch to another task
+        Ok(())
+    }
+
+    /// Resume the task that was suspended by `suspend()`. Returns `SYS_EINVAL` if the task is not suspended.
+    pub fn resume(&self) -> MynewtResult<()> {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let suspended = self.is_suspended();
+        if suspended {
+            unsafe {
+                SUSPENDED[self.id() as usize / 32] &= !(1 << (self.id() % 32));
+                os::os_sched_wakeup(self.task);
+            }
+        }
+        unsafe { os::os_arch_restore_sr(sr) };
+        if !suspended { return Err(MynewtError::SYS_EINVAL); }
+        unsafe { os::os_sched(core::ptr::null_mut()) };  //  Switch to the resumed task if it has higher priority
+        Ok(())
+    }
+
+    /// Return true if the task was suspended by `suspend()`
+    pub fn is_suspended(&self) -> bool {
+        unsafe { SUSPENDED[self.id() as usize / 32] & (1 << (self.id() % 32)) != 0 }
+    }
+
+    /// Return the task name as bytes, without the terminating null
+    fn name_bytes(&self) -> &[u8] {
+        let name = unsafe { (*self.task).t_name } as *const u8;
+        if name.is_null() { return &[]; }
+        let mut len = 0;
+        while len < TASK_NAME_SIZE && unsafe { *name.add(len) } != 0 { len += 1; }
+        unsafe { core::slice::from_raw_parts(name, len) }
+    }
+}
+
+/// Raised task priority. The old priority is restored when dropped.
+pub struct PriorityBoost {
+    /// Task whose priority was raised
+    task: Task,
+    /// Priority before raising
+    old_prio: u8,
+}
+
+impl Drop for PriorityBoost {
+    /// Restore the old priority
+    fn drop(&mut self) {
+        self.task.set_prio(self.old_prio).ok();
+    }
+}
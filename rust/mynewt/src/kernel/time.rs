@@ -0,0 +1,260 @@
+//! Time types for Mynewt: conversions between `os_time_t` ticks, milliseconds and `core::time::Duration`,
+//! and `Instant` for measuring elapsed time. Use these instead of multiplying by `OS_TICKS_PER_SEC` by hand.
+//! The wall clock is kept by `wallclock` from the real-time counter after it's set with `set_wall_clock()`, e.g. from
+//! a phone, and is read as Unix time with `wall_clock()` or as a calendar date and time with `local_time()`.
+
+use core::{
+    ops::{ Add, Sub },
+    time::Duration,
+};
+use crate::{
+    kernel::os,
+    result::*,
+};
+
+/// Wall clock kept by the real-time counter while the CPU sleeps
+pub mod wallclock;  //  Export `wallclock.rs` as Rust module `mynewt::kernel::time::wallclock`
+
+/// Number of OS ticks per second. Must sync with `OS_TICKS_PER_SEC` in Mynewt.
+pub const TICKS_PER_SEC: u32 = os::OS_TICKS_PER_SEC;
+
+/// Convert `ms` milliseconds to OS ticks, rounded up so that a short delay still waits. Saturates at `OS_TIMEOUT_NEVER - 1`.
+pub fn ms_to_ticks(ms: u32) -> os::os_time_t {
+    saturate((ms as u64 * TICKS_PER_SEC as u64 + 999) / 1000)
+}
+
+/// Convert `ticks` OS ticks to milliseconds, rounded down
+pub fn ticks_to_ms(ticks: os::os_time_t) -> u32 {
+    (ticks as u64 * 1000 / TICKS_PER_SEC as u64) as u32
+}
+
+/// Convert `duration` to OS ticks, rounded up from microseconds so that any non-zero timeout waits at least 1 tick.
+/// Saturates at `OS_TIMEOUT_NEVER - 1`, so that a long timeout doesn't become a wait forever.
+pub fn duration_to_ticks(duration: Duration) -> os::os_time_t {
+    let ticks = (duration.as_micros() * TICKS_PER_SEC as u128 + 999_999) / 1_000_000;
+    if ticks > u64::max_value() as u128 { return saturate(u64::max_value()); }
+    saturate(ticks as u64)
+}
+
+/// Convert `ticks` OS ticks to a `Duration`
+pub fn ticks_to_duration(ticks: os::os_time_t) -> Duration {
+    Duration::from_millis(ticks as u64 * 1000 / TICKS_PER_SEC as u64)
+}
+
+/// Sleep for `duration`. Other tasks will run while this task sleeps.
+pub fn sleep(duration: Duration) {
+    unsafe { os::os_time_delay(duration_to_ticks(duration)) };
+}
+
+/// Sleep for `ms` milliseconds. Other tasks will run while this task sleeps.
+pub fn sleep_ms(ms: u32) {
+    unsafe { os::os_time_delay(ms_to_ticks(ms)) };
+}
+
+/// Sleep until `deadline`, e.g. for running at a fixed rate without drift. Returns at once if `deadline` has passed.
+pub fn sleep_until(deadline: Instant) {
+    if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+        if remaining > Duration::from_millis(0) { sleep(remaining); }
+    }
+}
+
+/// Let lower priority tasks run, by sleeping for one tick. Mynewt always runs the highest priority task that is ready,
+/// so a task that doesn't sleep or wait would starve the lower priority tasks.
+pub fn yield_now() {
+    unsafe { os::os_time_delay(1) };
+}
+
+/// Limit `ticks` to the longest timeout that's not `OS_TIMEOUT_NEVER`
+fn saturate(ticks: u64) -> os::os_time_t {
+    if ticks >= os::OS_TIMEOUT_NEVER as u64 { os::OS_TIMEOUT_NEVER - 1 }
+    else { ticks as os::os_time_t }
+}
+
+/// Point in time measured by the OS tick counter. The tick counter wraps around after `2^32` ticks
+/// (about 49 days at 1000 ticks per second), so only durations shorter than that can be measured.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instant {
+    /// OS time in ticks
+    ticks: os::os_time_t,
+}
+
+impl Instant {
+    /// Return the current time
+    pub fn now() -> Self {
+        Instant { ticks: unsafe { os::os_time_get() } }
+    }
+
+    /// Return the instant for the OS time `ticks`
+    pub fn from_ticks(ticks: os::os_time_t) -> Self {
+        Instant { ticks }
+    }
+
+    /// Return the OS time in ticks
+    pub fn ticks(&self) -> os::os_time_t {
+        self.ticks
+    }
+
+    /// Return the time elapsed since this instant
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Return the time from `earlier` to this instant. Returns zero if `earlier` is later than this instant.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or(Duration::from_millis(0))
+    }
+
+    /// Return the time from `earlier` to this instant, or `None` if `earlier` is later than this instant
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        let diff = self.ticks.wrapping_sub(earlier.ticks);
+        if diff as i32 >= 0 { Some(ticks_to_duration(diff)) }  //  Allow for tick counter wraparound
+        else { None }
+    }
+
+    /// Return the instant `duration` after this instant, or `None` if the duration is too long
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let ticks = checked_ticks(duration) ? ;
+        Some(Instant { ticks: self.ticks.wrapping_add(ticks) })
+    }
+
+    /// Return the instant `duration` before this instant, or `None` if the duration is too long
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let ticks = checked_ticks(duration) ? ;
+        Some(Instant { ticks: self.ticks.wrapping_sub(ticks) })
+    }
+}
+
+/// Return `duration` in ticks, or `None` if the duration can't be measured by `Instant`
+fn checked_ticks(duration: Duration) -> Option<os::os_time_t> {
+    let ticks = duration.as_millis() * TICKS_PER_SEC as u128 / 1000;
+    if ticks > i32::max_value() as u128 { return None; }
+    Some(ticks as os::os_time_t)
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Return the instant `duration` after this instant. Panics if the duration is too long.
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration).expect("instant overflow")
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// Return the instant `duration` before this instant. Panics if the duration is too long.
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).expect("instant overflow")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Return the time from `earlier` to this instant. Returns zero if `earlier` is later than this instant.
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Set the wall clock to `unix_secs` seconds since 1970-01-01 00:00:00 UTC, for the time zone `minutes_west` minutes
+/// west of UTC. The clock is kept by `wallclock` from the real-time counter, so it keeps running while the CPU sleeps.
+pub fn set_wall_clock(unix_secs: i64, minutes_west: i16) -> MynewtResult<()> {
+    wallclock::set(unix_secs, minutes_west)
+}
+
+/// Return the wall clock in seconds since 1970-01-01 00:00:00 UTC, or `None` if the clock has not been set
+pub fn wall_clock() -> Option<i64> {
+    wallclock::now()
+}
+
+/// Return the local time of the wall clock, for the time zone set with `set_wall_clock()`, or `None` if the clock
+/// has not been set
+pub fn local_time() -> Option<DateTime> {
+    wallclock::local()
+}
+
+/// Calendar date and time in the proleptic Gregorian calendar, without time zone
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    /// Year, e.g. 2019
+    pub year: u16,
+    /// Month, 1 to 12
+    pub month: u8,
+    /// Day of the month, 1 to 31
+    pub day: u8,
+    /// Hours, 0 to 23
+    pub hour: u8,
+    /// Minutes, 0 to 59
+    pub minute: u8,
+    /// Seconds, 0 to 59
+    pub second: u8,
+}
+
+/// Number of seconds in a day
+const SECS_PER_DAY: i64 = 86_400;
+
+impl DateTime {
+    /// Return the date and time for `unix_secs` seconds since 1970-01-01 00:00:00
+    pub fn from_unix(unix_secs: i64) -> Self {
+        let days = div_floor(unix_secs, SECS_PER_DAY);
+        let secs = unix_secs - days * SECS_PER_DAY;
+        //  Convert the days since 1970-01-01 to a date, counting in 400-year eras that start on 1 March,
+        //  so that the leap day is the last day of the year.
+        let z = days + 719_468;
+        let era = div_floor(z, 146_097);
+        let doe = z - era * 146_097;                                  //  Day of era, 0 to 146096
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;  //  Year of era, 0 to 399
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);            //  Day of year starting 1 March, 0 to 365
+        let mp = (5 * doy + 2) / 153;                                 //  Month starting March, 0 to 11
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        DateTime {
+            year:   year as u16,
+            month:  month as u8,
+            day:    day as u8,
+            hour:   (secs / 3600) as u8,
+            minute: (secs % 3600 / 60) as u8,
+            second: (secs % 60) as u8,
+        }
+    }
+
+    /// Return the number of seconds since 1970-01-01 00:00:00, or `None` if the date or time is out of range
+    pub fn to_unix(&self) -> Option<i64> {
+        if self.month < 1 || self.month > 12 || self.day < 1 || self.day > days_in_month(self.year, self.month)
+            || self.hour > 23 || self.minute > 59 || self.second > 59 { return None; }
+        let month = self.month as i64;
+        let year = self.year as i64 - if month <= 2 { 1 } else { 0 };
+        let era = div_floor(year, 400);
+        let yoe = year - era * 400;
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        Some(days * SECS_PER_DAY + self.hour as i64 * 3600 + self.minute as i64 * 60 + self.second as i64)
+    }
+
+    /// Return the day of the week, 0 for Sunday to 6 for Saturday, or `None` if the date or time is out of range
+    pub fn weekday(&self) -> Option<u8> {
+        let days = div_floor(self.to_unix() ? , SECS_PER_DAY);
+        Some((days + 4).rem_euclid(7) as u8)  //  1970-01-01 was a Thursday
+    }
+}
+
+/// Return the number of days in `month` of `year`
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Divide `a` by `b`, rounding towards negative infinity
+fn div_floor(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { q - 1 } else { q }
+}
@@ -0,0 +1,106 @@
+//! Wall clock kept by the nRF52 real-time counter, so that the date and time survive the CPU sleeping between events.
+//! `os_cputime` counts the 32.768 kHz `LFCLK` on RTC0, which keeps running while the CPU sleeps with the `HFCLK`
+//! off. The 32-bit counter wraps around every 36 hours, so a timer carries the elapsed counter ticks into the seconds
+//! every hour, and at each read.
+//!
+//! The clock is set by the time sync sources, e.g. the Current Time Service client in the app, with `set()` or
+//! `set_local()`. The UI clock reads the local date and time with `local()`, and the readings sent to the CoAP server
+//! are timestamped with `now()`. Mynewt's `os_gettimeofday()` is set too, for the Mynewt log timestamps.
+
+use core::time::Duration;
+use crate::{
+    result::*,
+    kernel::{ os, time::DateTime, timer::Callout },
+};
+
+/// Frequency of the `os_cputime` counter. Must sync with `OS_CPUTIME_FREQ` in `hw/bsp/nrf52/syscfg.yml`.
+pub const RTC_FREQ: u32 = 32_768;
+
+/// Interval for carrying the counter ticks into the seconds. Must be shorter than the counter wraparound,
+/// `2^32 / RTC_FREQ` seconds (36 hours), with some margin for a busy default event queue.
+const UPDATE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Date and time, accessed with interrupts disabled
+#[derive(Clone, Copy)]
+struct Clock {
+    /// Seconds since 1970-01-01 00:00:00 UTC
+    secs: i64,
+    /// Counter ticks after `secs`, less than `RTC_FREQ`
+    ticks: u32,
+    /// Counter value when `secs` and `ticks` were updated
+    count: u32,
+    /// Time zone, in minutes west of UTC
+    minutes_west: i16,
+    /// True if the clock has been set
+    is_set: bool,
+}
+
+/// The wall clock. Not set until a time sync source calls `set()`.
+static mut CLOCK: Clock = Clock { secs: 0, ticks: 0, count: 0, minutes_west: 0, is_set: false };
+
+/// Timer that carries the counter ticks into the seconds before the counter wraps around
+static UPDATE_TIMER: Callout<fn()> = Callout::new(update_timer);
+
+/// Set the wall clock to `unix_secs` seconds since 1970-01-01 00:00:00 UTC, for the time zone `minutes_west` minutes
+/// west of UTC
+pub fn set(unix_secs: i64, minutes_west: i16) -> MynewtResult<()> {
+    let count = unsafe { os::os_cputime_get32() };
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe { CLOCK = Clock { secs: unix_secs, ticks: 0, count, minutes_west, is_set: true } };
+    unsafe { os::os_arch_restore_sr(sr) };
+
+    //  Keep Mynewt's time of day in step, for the log timestamps.
+    let mut utctime = os::os_timeval { tv_sec: unix_secs, tv_usec: 0 };
+    let mut tz = os::os_timezone { tz_minuteswest: minutes_west, tz_dsttime: 0 };
+    check(unsafe { os::os_settimeofday(&mut utctime, &mut tz) }) ? ;
+    UPDATE_TIMER.reset(UPDATE_INTERVAL)
+}
+
+/// Set the wall clock to the local date and time `local`, for the time zone `minutes_east` minutes east of UTC.
+/// Returns `SYS_EINVAL` if the date or time is out of range.
+pub fn set_local(local: &DateTime, minutes_east: i16) -> MynewtResult<()> {
+    let local_secs = local.to_unix().ok_or(MynewtError::SYS_EINVAL) ? ;
+    set(local_secs - minutes_east as i64 * 60, -minutes_east)
+}
+
+/// Return the wall clock in seconds since 1970-01-01 00:00:00 UTC, or `None` if the clock has not been set
+pub fn now() -> Option<i64> {
+    let clock = update() ? ;
+    Some(clock.secs)
+}
+
+/// Return the local date and time of the wall clock, for the time zone set with `set()`, or `None` if the clock has
+/// not been set
+pub fn local() -> Option<DateTime> {
+    let clock = update() ? ;
+    Some(DateTime::from_unix(clock.secs - clock.minutes_west as i64 * 60))
+}
+
+/// Return true if the wall clock has been set
+pub fn is_set() -> bool {
+    unsafe { CLOCK.is_set }
+}
+
+/// Carry the counter ticks elapsed since the last update into the seconds, and return the updated clock.
+/// Returns `None` if the clock has not been set.
+fn update() -> Option<Clock> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let clock = unsafe {
+        if CLOCK.is_set {
+            let count = os::os_cputime_get32();
+            let ticks = CLOCK.ticks as u64 + count.wrapping_sub(CLOCK.count) as u64;  //  Allow for wraparound
+            CLOCK.secs += (ticks / RTC_FREQ as u64) as i64;
+            CLOCK.ticks = (ticks % RTC_FREQ as u64) as u32;
+            CLOCK.count = count;
+            Some(CLOCK)
+        } else { None }
+    };
+    unsafe { os::os_arch_restore_sr(sr) };
+    clock
+}
+
+/// Update the clock and restart the timer. Called by the default event queue every `UPDATE_INTERVAL`.
+fn update_timer() {
+    update();
+    UPDATE_TIMER.reset(UPDATE_INTERVAL).expect("wallclock timer fail");
+}
@@ -0,0 +1,105 @@
+//! Timers with Rust callbacks. `Callout` owns a Mynewt `os_callout` and calls a Rust function or closure when
+//! the timer expires, through a C callback trampoline. The callback runs in the task that processes the default
+//! event queue (usually the main task), not in an interrupt handler. For a periodic timer, call `reset()` in the callback.
+//! ```
+//! static POLL_TIMER: Callout<fn()> = Callout::new(poll_sensor);
+//! fn poll_sensor() {
+//!     //  Read the sensor, then poll again in 10 seconds
+//!     POLL_TIMER.reset(Duration::from_secs(10)).expect("timer fail");
+//! }
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    time::Duration,
+};
+use crate::{
+    result::*,
+    kernel::{ os, time },
+};
+
+/// Timer that calls `F` when it expires. Must be declared `static`, since the `os_callout` refers to the `Callout`.
+pub struct Callout<F> {
+    /// The Mynewt callout
+    callout: UnsafeCell<os::os_callout>,
+    /// True if `os_callout_init()` has been called
+    initialised: UnsafeCell<bool>,
+    /// Function or closure to be called when the timer expires
+    func: UnsafeCell<F>,
+}
+
+/// `Callout` may be shared between tasks
+unsafe impl<F: Send> Sync for Callout<F> {}
+
+impl<F> Callout<F> {
+    /// Create a stopped timer that will call `func` when it expires
+    pub const fn new(func: F) -> Self {
+        Callout {
+            callout: UnsafeCell::new(os::os_callout {
+                c_ev: os::os_event {
+                    ev_queued: 0,
+                    ev_cb:     None,
+                    ev_arg:    core::ptr::null_mut(),
+                    ev_next:   os::os_event__bindgen_ty_1 { stqe_next: core::ptr::null_mut() },
+                },
+                c_evq:   core::ptr::null_mut(),
+                c_ticks: 0,
+                c_next:  os::os_callout__bindgen_ty_1 {
+                    tqe_next: core::ptr::null_mut(),
+                    tqe_prev: core::ptr::null_mut(),
+                },
+            }),
+            initialised: UnsafeCell::new(false),
+            func: UnsafeCell::new(func),
+        }
+    }
+}
+
+impl<F: FnMut() + Send> Callout<F> {
+    /// Start the timer so that it expires after `timeout`. If the timer is already running, it's restarted.
+    pub fn reset(&'static self, timeout: Duration) -> MynewtResult<()> {
+        self.init() ? ;
+        check(unsafe { os::os_callout_reset(self.callout.get(), time::duration_to_ticks(timeout)) })
+    }
+
+    /// Stop the timer. If the timer has expired but the callback has not been called, the callback is cancelled.
+    pub fn stop(&'static self) {
+        if unsafe { !*self.initialised.get() } { return; }  //  Never started
+        unsafe { os::os_callout_stop(self.callout.get()) };
+    }
+
+    /// Return the time remaining until the timer expires, or `None` if the timer is not running
+    pub fn remaining(&'static self) -> Option<Duration> {
+        if unsafe { !*self.initialised.get() || (*self.callout.get()).c_next.tqe_prev.is_null() } { return None; }
+        let now = unsafe { os::os_time_get() };
+        let ticks = unsafe { os::os_callout_remaining_ticks(self.callout.get(), now) };
+        Some(time::ticks_to_duration(ticks))
+    }
+
+    /// Initialise the `os_callout` if this is the first use. The callout posts to the default event queue.
+    fn init(&'static self) -> MynewtResult<()> {
+        if unsafe { *self.initialised.get() } { return Ok(()); }
+        let queue = os::eventq_dflt_get() ? ;
+        let sr = unsafe { os::os_arch_save_sr() };
+        unsafe {
+            if !*self.initialised.get() {
+                os::os_callout_init(
+                    self.callout.get(),             //  Callout to be initialised
+                    queue,                          //  Post to the default event queue
+                    Some(callout_trampoline::<F>),  //  Call the Rust callback
+                    self as *const Self as *mut ::cty::c_void  //  Argument: this `Callout`
+                );
+                *self.initialised.get() = true;
+            }
+            os::os_arch_restore_sr(sr);
+        }
+        Ok(())
+    }
+}
+
+/// Called by Mynewt when the callout expires. `ev_arg` of the event is the `Callout`.
+extern "C" fn callout_trampoline<F: FnMut() + Send>(ev: *mut os::os_event) {
+    let callout = unsafe { &*((*ev).ev_arg as *const Callout<F>) };
+    let func = unsafe { &mut *callout.func.get() };
+    func();
+}
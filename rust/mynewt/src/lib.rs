@@ -4,8 +4,17 @@
 #![no_std]                        //  Don't link with standard Rust library, which is not compatible with embedded systems
 #![feature(trace_macros)]         //  Enable tracing of macros
 #![feature(proc_macro_hygiene)]   //  Allow proc macros to be unhygienic
+#![feature(panic_info_message)]   //  Allow `PanicInfo::message()` for recording panics
+#![cfg_attr(feature = "alloc", feature(alloc_error_handler))]  //  Allow `#[alloc_error_handler]` for the global allocator
 
-extern crate macros as mynewt_macros;  //  Import Procedural Macros from `macros` library
+#[cfg(feature = "alloc")]         //  If the global allocator is enabled...
+extern crate alloc;               //  Export the `alloc` library for `Vec`, `String` and `Box`
+
+#[cfg(feature = "mock")]          //  If the Mynewt functions are mocked for host testing...
+extern crate std;                 //  Use the standard Rust library for capturing the output of the mocks
+
+#[doc(hidden)]                    //  Exported for the macros in `encoding/macros.rs`, e.g. in `tests/cbor.rs`
+pub extern crate macros as mynewt_macros;  //  Import Procedural Macros from `macros` library
 
 #[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
 #[allow(non_upper_case_globals)]  //  Allow globals to have lowercase letters
@@ -27,17 +36,35 @@ pub mod encoding;                 //  Mynewt Encoding API. Export folder `encodi
 #[macro_use]                      //  Allow macros from Rust module `util`
 pub mod util;                     //  Mynewt Utility API. Export folder `encoding` as Rust module `mynewt::util`
 
+pub mod diag;                     //  Mynewt Diagnostics API. Export folder `diag` as Rust module `mynewt::diag`
+
+#[cfg(feature = "littlefs")]      //  If the file system is enabled...
+pub mod fs;                       //  File System API. Export `fs.rs` as Rust module `mynewt::fs`
+
 #[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
 #[allow(non_upper_case_globals)]  //  Allow globals to have lowercase letters
 pub mod libs;                     //  Mynewt Custom API. Export folder `libs` as Rust module `mynewt::libs`
 
-mod hal;                            //  Import module `hal` for Embedded HAL functions but don't export it
+pub mod hal;                        //  Export module `hal` for Embedded HAL functions, e.g. `mynewt::hal::gpio`
 pub use hal::{ Delay, GPIO, SPI, I2C };  //  Export `hal` types GPIO, SPI and I2C
 
 pub mod spi;  //  Export Non-Blocking SPI API
 
+pub mod board;  //  Export the board pin maps, e.g. `mynewt::board::pinetime`
+
+#[allow(non_camel_case_types)]  //  Allow NimBLE type names to have non-camel case
+pub mod ble;  //  Export Bluetooth LE API
+
+#[cfg(feature = "sim")]  //  If sensor simulation is enabled...
+pub mod sim;             //  Export the simulated sensors and Mynewt functions for host testing
+
+#[cfg(feature = "mock")]  //  If the Mynewt functions are mocked for host testing...
+pub mod mock;            //  Export the mocked console, log and CBOR functions
+
 ///  Initialise the Mynewt system.  Start the Mynewt drivers and libraries.  Equivalent to `sysinit()` macro in C.
 pub fn sysinit() {
+    //  Decode the reset reason before the reboot log clears it.
+    kernel::reset::init();
     unsafe { rust_sysinit(); }
     sys::console::flush();
 }
@@ -52,7 +79,7 @@ pub mod result {
 
     /// Error codes for Mynewt API
     #[repr(i32)]
-    #[derive(PartialEq)]
+    #[derive(Clone, Copy, PartialEq)]
     #[allow(non_camel_case_types)]    //  Allow type names to have non-camel case
     pub enum MynewtError {
         /// Error code 0 means no error.
@@ -88,15 +115,105 @@ pub mod result {
         }
     }
 
-    /// Cast `i32` to `MynewtError`
+    /// Cast `i32` to `MynewtError`. Negative values are Mynewt `SYS_E*` error codes,
+    /// positive values are Mynewt OS `OS_*` error codes returned by kernel functions.
+    /// HAL functions return other positive codes, so convert their return codes with `check_hal()` instead.
     impl From<i32> for MynewtError {
         /// Cast `i32` to `MynewtError`
         fn from(num: i32) -> Self {
-            unsafe { 
-                ::core::mem::transmute::
-                    <i32, MynewtError>
-                    (num)
-            }  
+            match num {
+                0                  => MynewtError::SYS_EOK,
+                os::SYS_ENOMEM     => MynewtError::SYS_ENOMEM,
+                os::SYS_EINVAL     => MynewtError::SYS_EINVAL,
+                os::SYS_ETIMEOUT   => MynewtError::SYS_ETIMEOUT,
+                os::SYS_ENOENT     => MynewtError::SYS_ENOENT,
+                os::SYS_EIO        => MynewtError::SYS_EIO,
+                os::SYS_EAGAIN     => MynewtError::SYS_EAGAIN,
+                os::SYS_EACCES     => MynewtError::SYS_EACCES,
+                os::SYS_EBUSY      => MynewtError::SYS_EBUSY,
+                os::SYS_ENODEV     => MynewtError::SYS_ENODEV,
+                os::SYS_ERANGE     => MynewtError::SYS_ERANGE,
+                os::SYS_EALREADY   => MynewtError::SYS_EALREADY,
+                os::SYS_ENOTSUP    => MynewtError::SYS_ENOTSUP,
+                os::SYS_EREMOTEIO  => MynewtError::SYS_EREMOTEIO,
+                os::SYS_EDONE      => MynewtError::SYS_EDONE,
+                os::SYS_EPERUSER   => MynewtError::SYS_EPERUSER,
+                num if num > 0     => MynewtError::from_os(num as os::os_error_t),
+                _                  => MynewtError::SYS_EUNKNOWN,
+            }
+        }
+    }
+
+    impl MynewtError {
+        /// Convert the Mynewt OS error code `rc` (`OS_*`) to the equivalent `SYS_E*` error code
+        pub fn from_os(rc: os::os_error_t) -> Self {
+            match rc {
+                os::os_error_OS_OK            => MynewtError::SYS_EOK,
+                os::os_error_OS_TIMEOUT       => MynewtError::SYS_ETIMEOUT,
+                os::os_error_OS_ENOMEM        => MynewtError::SYS_ENOMEM,
+                os::os_error_OS_EINVAL        |
+                os::os_error_OS_INVALID_PARM  |
+                os::os_error_OS_MEM_NOT_ALIGNED => MynewtError::SYS_EINVAL,
+                os::os_error_OS_BAD_MUTEX     |
+                os::os_error_OS_ERR_IN_ISR    |
+                os::os_error_OS_ERR_PRIV      => MynewtError::SYS_EACCES,
+                os::os_error_OS_ENOENT        => MynewtError::SYS_ENOENT,
+                os::os_error_OS_EBUSY         => MynewtError::SYS_EBUSY,
+                os::os_error_OS_NOT_STARTED   => MynewtError::SYS_EAGAIN,
+                _                             => MynewtError::SYS_EUNKNOWN,
+            }
+        }
+
+        /// Return the name of the error code, e.g. `SYS_ENOMEM`
+        pub fn name(&self) -> &'static str {
+            match self {
+                MynewtError::SYS_EOK               => "SYS_EOK",
+                MynewtError::SYS_ENOMEM            => "SYS_ENOMEM",
+                MynewtError::SYS_EINVAL            => "SYS_EINVAL",
+                MynewtError::SYS_ETIMEOUT          => "SYS_ETIMEOUT",
+                MynewtError::SYS_ENOENT            => "SYS_ENOENT",
+                MynewtError::SYS_EIO               => "SYS_EIO",
+                MynewtError::SYS_EAGAIN            => "SYS_EAGAIN",
+                MynewtError::SYS_EACCES            => "SYS_EACCES",
+                MynewtError::SYS_EBUSY             => "SYS_EBUSY",
+                MynewtError::SYS_ENODEV            => "SYS_ENODEV",
+                MynewtError::SYS_ERANGE            => "SYS_ERANGE",
+                MynewtError::SYS_EALREADY          => "SYS_EALREADY",
+                MynewtError::SYS_ENOTSUP           => "SYS_ENOTSUP",
+                MynewtError::SYS_EUNKNOWN          => "SYS_EUNKNOWN",
+                MynewtError::SYS_EREMOTEIO         => "SYS_EREMOTEIO",
+                MynewtError::SYS_EDONE             => "SYS_EDONE",
+                MynewtError::SYS_EPERUSER          => "SYS_EPERUSER",
+                MynewtError::HAL_I2C_ERR_UNKNOWN   => "HAL_I2C_ERR_UNKNOWN",
+                MynewtError::HAL_I2C_ERR_INVAL     => "HAL_I2C_ERR_INVAL",
+                MynewtError::HAL_I2C_ERR_TIMEOUT   => "HAL_I2C_ERR_TIMEOUT",
+                MynewtError::HAL_I2C_ERR_ADDR_NACK => "HAL_I2C_ERR_ADDR_NACK",
+                MynewtError::HAL_I2C_ERR_DATA_NACK => "HAL_I2C_ERR_DATA_NACK",
+            }
+        }
+    }
+
+    /// Convert the return code `rc` of a Mynewt C function to a `MynewtResult`: `Ok(())` if `rc` is 0, else the error.
+    /// Use this instead of ignoring or asserting the return code, e.g. `check(unsafe { os::os_callout_reset(..) }) ? ;`
+    pub fn check(rc: i32) -> MynewtResult<()> {
+        if rc == 0 { Ok(()) }
+        else { Err(MynewtError::from(rc)) }
+    }
+
+    /// `errno` codes returned by the nRF52 HAL drivers
+    const EBUSY:  i32 = 16;
+    const EINVAL: i32 = 22;
+
+    /// Convert the return code `rc` of a Mynewt HAL function to a `MynewtResult`, e.g.
+    /// `check_hal(unsafe { hal::hal_spi_enable(0) }) ? ;`. The HAL drivers return `SYS_E*` codes or positive `errno`
+    /// codes, which are not Mynewt OS codes. The I2C functions return `HAL_I2C_ERR_*` codes, mapped by `hal::i2c`.
+    pub fn check_hal(rc: i32) -> MynewtResult<()> {
+        match rc {
+            0              => Ok(()),
+            rc if rc < 0   => Err(MynewtError::from(rc)),
+            EBUSY          => Err(MynewtError::SYS_EBUSY),
+            EINVAL         => Err(MynewtError::SYS_EINVAL),
+            _              => Err(MynewtError::SYS_EIO),
         }
     }
 
@@ -110,9 +227,8 @@ pub mod result {
 
     /// Implement formatted output for MynewtError
     impl core::fmt::Debug for MynewtError {
-        fn fmt(&self, _fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
-            //  TODO
-            Ok(())
+        fn fmt(&self, fmt: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+            fmt.write_str(self.name())
         }
     }
 }
@@ -232,6 +348,16 @@ impl Strn {
     }
 }
 
+///  Compare the characters of the strings, not the pointers, e.g. a device name from C with a name from Rust.
+impl PartialEq for Strn {
+    fn eq(&self, other: &Strn) -> bool {
+        let len = self.len();
+        if len != other.len() { return false; }
+        let (ptr, other_ptr) = (self.as_ptr(), other.as_ptr());
+        (0..len).all(|i| unsafe { *ptr.add(i) == *other_ptr.add(i) })
+    }
+}
+
 ///  Allow threads to share Strn, since it is static.
 unsafe impl Send for Strn {}
 
@@ -248,7 +374,7 @@ pub type Ptr = *mut ::cty::c_void;
 pub const NULL: Ptr = core::ptr::null_mut();
 
 ///  Import the custom interop helper library at `libs/mynewt_rust`
-#[link(name = "libs_mynewt_rust")]  //  Functions below are located in the Mynewt build output `libs_mynewt_rust.a`
+#[cfg_attr(not(feature = "sim"), link(name = "libs_mynewt_rust"))]  //  Functions below are located in the Mynewt build output `libs_mynewt_rust.a`
 extern {
     ///  Initialise the Mynewt system.  Start the Mynewt drivers and libraries.  Equivalent to `sysinit()` macro in C.
     ///  C API: `void rust_sysinit()`
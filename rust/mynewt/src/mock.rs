@@ -0,0 +1,267 @@
+//! Mocked Mynewt C functions for testing the Rust logic of this crate on the host with `cargo test --features mock`,
+//! without PineTime or the Mynewt C code. Builds on `sim.rs`, which simulates the OS clock, timers, Sensor Manager and
+//! JSON encoder, and adds fakes of the console, the Mynewt log and the TinyCBOR encoder. The fakes use the standard
+//! Rust library and capture their output for checking by the tests: `console()` returns the console output, `logs()`
+//! the entries appended to the Mynewt log, and `cbor()` the items encoded by the CoAP macros. The fakes are shared
+//! by the tests, which run in parallel threads, so each test starts with `start()`, which waits for the other tests,
+//! clears the captured output and resets the simulation.
+//! ```
+//! let _mock = mock::start();  //  Other tests wait until `_mock` is dropped
+//! console::print("hello\n");
+//! assert_eq!(mock::console(), "hello\n");
+//! ```
+//! Like `sim`, don't enable `mock` for the firmware, since these functions replace the Mynewt functions with the same
+//! names. The tests are in `rust/mynewt/tests` and run on the host with
+//! `cargo test --package mynewt --features mock --target x86_64-unknown-linux-gnu`.
+
+use std::{
+    fmt::Write,
+    string::String,
+    sync::atomic::{ AtomicBool, Ordering },
+    thread,
+    vec::Vec,
+};
+use crate::{
+    encoding::tinycbor::{ CborEncoder, CborError },
+    sim,
+};
+
+/// Entry appended to the Mynewt log by `modlog_append()`
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    /// Mynewt log module, e.g. `LOG_MODULE_RUST`
+    pub module: u8,
+    /// Mynewt log level: 0 for debug, 1 for info, 2 for warn, 3 for error, 4 for critical
+    pub level: u8,
+    /// Entry type: 0 for text, 1 for CBOR
+    pub etype: u8,
+    /// Text or CBOR bytes of the entry
+    pub data: Vec<u8>,
+}
+
+impl LogEntry {
+    /// Return the entry as text, or `None` if it's not valid UTF-8
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+/// Item encoded with the TinyCBOR functions, in order of encoding
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cbor {
+    /// Start of a map, from `cbor_encoder_create_map()`
+    Map,
+    /// Start of an array, from `cbor_encoder_create_array()`
+    Array,
+    /// End of the map or array, from `cbor_encoder_close_container()`
+    End,
+    /// Text string, from `cbor_encode_text_string()`
+    Text(String),
+    /// Signed integer, from `cbor_encode_int()`
+    Int(i64),
+    /// Unsigned integer, from `cbor_encode_uint()`
+    Uint(u64),
+}
+
+/// Held by the running test until dropped, so that the tests don't share the fakes
+pub struct MockGuard {
+    _private: (),
+}
+
+impl Drop for MockGuard {
+    fn drop(&mut self) {
+        LOCKED.store(false, Ordering::Release);
+    }
+}
+
+/// True while a test holds the `MockGuard`
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// Console output that was not sent to a console sink
+static mut CONSOLE: Option<String> = None;
+
+/// Callback that receives the console output, set by `console_set_output_cb()`
+static mut OUTPUT_CB: Option<extern "C" fn(*const u8, u32)> = None;
+
+/// Entries appended to the Mynewt log
+static mut LOGS: Option<Vec<LogEntry>> = None;
+
+/// Items encoded with the TinyCBOR functions
+static mut CBOR: Option<Vec<Cbor>> = None;
+
+/// Wait until no other test is running, then clear the captured output and reset the simulation. The test holds
+/// the fakes until the returned guard is dropped, also when the test fails.
+pub fn start() -> MockGuard {
+    while LOCKED.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        thread::yield_now();
+    }
+    unsafe {
+        CONSOLE = None;
+        OUTPUT_CB = None;
+        LOGS = None;
+        CBOR = None;
+    }
+    sim::reset();
+    MockGuard { _private: () }
+}
+
+/// Return the console output since `start()` that was not sent to a console sink, including the output of
+/// `SEMIHOSTING_SINK`
+pub fn console() -> String {
+    unsafe { CONSOLE.clone() }.unwrap_or_default()
+}
+
+/// Return the entries appended to the Mynewt log since `start()`
+pub fn logs() -> Vec<LogEntry> {
+    unsafe { LOGS.clone() }.unwrap_or_default()
+}
+
+/// Return the items encoded with the TinyCBOR functions since `start()`
+pub fn cbor() -> Vec<Cbor> {
+    unsafe { CBOR.clone() }.unwrap_or_default()
+}
+
+/// Append the bytes to the captured console output
+fn capture(bytes: &[u8]) {
+    unsafe { CONSOLE.get_or_insert_with(String::new) }.push_str(&String::from_utf8_lossy(bytes));
+}
+
+/// Send the bytes to the console output callback if set, like `libs/semihosting_console`, else capture them
+fn output(bytes: &[u8]) {
+    match unsafe { OUTPUT_CB } {
+        Some(cb) => cb(bytes.as_ptr(), bytes.len() as u32),
+        None     => capture(bytes),
+    }
+}
+
+/// Record a TinyCBOR item
+fn encode(item: Cbor) -> CborError {
+    unsafe { CBOR.get_or_insert_with(Vec::new) }.push(item);
+    0  //  `CborNoError`
+}
+
+/// Return the `length` bytes at `buffer`, or an empty slice if `buffer` is null
+unsafe fn bytes<'a>(buffer: *const u8, length: usize) -> &'a [u8] {
+    if buffer.is_null() { return &[]; }
+    std::slice::from_raw_parts(buffer, length)
+}
+
+//  Mocked console functions. C API: `libs/semihosting_console`
+
+#[no_mangle]
+unsafe extern "C" fn console_buffer(buffer: *const u8, length: u32) {
+    output(bytes(buffer, length as usize));
+}
+
+#[no_mangle]
+extern "C" fn console_printhex(v: u8) {
+    output(std::format!("{:02x}", v).as_bytes());
+}
+
+#[no_mangle]
+extern "C" fn console_printint(i: i32) {
+    output(std::format!("{}", i).as_bytes());
+}
+
+#[no_mangle]
+extern "C" fn console_printfloat(f: f32) {
+    output(std::format!("{:.2}", f).as_bytes());
+}
+
+#[no_mangle]
+extern "C" fn console_printdouble(d: f64) {
+    output(std::format!("{:.6}", d).as_bytes());
+}
+
+#[no_mangle]
+unsafe extern "C" fn console_dump(buffer: *const u8, len: u32) {
+    let mut hex = String::new();
+    for b in bytes(buffer, len as usize) { write!(hex, "{:02x} ", b).ok(); }
+    output(hex.as_bytes());
+}
+
+#[no_mangle]
+extern "C" fn console_flush() {}
+
+#[no_mangle]
+unsafe extern "C" fn console_set_output_cb(cb: Option<extern "C" fn(*const u8, u32)>) {
+    OUTPUT_CB = cb;
+}
+
+#[no_mangle]
+unsafe extern "C" fn semihosting_console_write(buffer: *const u8, length: u32) {
+    capture(bytes(buffer, length as usize));
+}
+
+//  Mocked RTT console sink, disabled like the default `RTT_CONSOLE_SINK: 0`.
+//  C API: `apps/my_sensor_app/src/rtt_console.c`
+
+#[no_mangle]
+extern "C" fn rtt_console_is_enabled() -> i32 { 0 }
+
+#[no_mangle]
+extern "C" fn rtt_console_write(_buffer: *const u8, _length: u32) {}
+
+//  Mocked Mynewt log functions. C API: `sys/log/modlog/include/modlog/modlog.h`
+
+#[no_mangle]
+unsafe extern "C" fn modlog_append(module: u8, level: u8, etype: u8, data: *const ::cty::c_void, len: u16)
+    -> ::cty::c_int {
+    let data = bytes(data as *const u8, len as usize).to_vec();
+    LOGS.get_or_insert_with(Vec::new).push(LogEntry { module, level, etype, data });
+    0
+}
+
+#[no_mangle]
+extern "C" fn log_level_set(_module: u8, _level: u8) -> ::cty::c_int { 0 }
+
+//  Mocked TinyCBOR encoder. The encoders are not used, the items are recorded in order of encoding.
+//  C API: `repos/apache-mynewt-core/encoding/tinycbor/include/tinycbor/cbor.h`
+
+/// Global CBOR encoder. C API: `net/oic/src/api/oc_rep.c`
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+static mut g_encoder: CborEncoder = CborEncoder {
+    writer: std::ptr::null_mut(), writer_arg: std::ptr::null_mut(), added: 0, flags: 0,
+};
+
+/// CBOR encoder for the root map. C API: `net/oic/src/api/oc_rep.c`
+#[no_mangle]
+#[allow(non_upper_case_globals)]
+static mut root_map: CborEncoder = CborEncoder {
+    writer: std::ptr::null_mut(), writer_arg: std::ptr::null_mut(), added: 0, flags: 0,
+};
+
+#[no_mangle]
+extern "C" fn cbor_encoder_create_map(_encoder: *mut CborEncoder, _map_encoder: *mut CborEncoder, _length: usize)
+    -> CborError {
+    encode(Cbor::Map)
+}
+
+#[no_mangle]
+extern "C" fn cbor_encoder_create_array(_encoder: *mut CborEncoder, _array_encoder: *mut CborEncoder,
+    _length: usize) -> CborError {
+    encode(Cbor::Array)
+}
+
+#[no_mangle]
+extern "C" fn cbor_encoder_close_container(_encoder: *mut CborEncoder, _container_encoder: *const CborEncoder)
+    -> CborError {
+    encode(Cbor::End)
+}
+
+#[no_mangle]
+unsafe extern "C" fn cbor_encode_text_string(_encoder: *mut CborEncoder, string: *const ::cty::c_char,
+    length: usize) -> CborError {
+    encode(Cbor::Text(String::from_utf8_lossy(bytes(string as *const u8, length)).into_owned()))
+}
+
+#[no_mangle]
+extern "C" fn cbor_encode_int(_encoder: *mut CborEncoder, value: i64) -> CborError {
+    encode(Cbor::Int(value))
+}
+
+#[no_mangle]
+extern "C" fn cbor_encode_uint(_encoder: *mut CborEncoder, value: u64) -> CborError {
+    encode(Cbor::Uint(value))
+}
@@ -0,0 +1,521 @@
+//! Simulated sensor backend for running the sensor pipeline on the host, without PineTime or the Mynewt C code.
+//! Enabled by the `sim` feature. `SimSensor` is a fake sensor driver that returns deterministic waveforms, e.g. a
+//! triangle wave of raw temperatures. The Mynewt C functions used by the sensor poller, listeners, timers and JSON
+//! encoder are implemented here in Rust: the OS clock only moves when `advance()` is called, and expired timers are
+//! called during `advance()`, so the tests in `tests/sim.rs` and `tests/sensor.rs` run the same way every time with
+//! `cargo test --features mock`. The JSON encoded by the CoAP macros is captured and returned by `json()`. Don't
+//! enable `sim` for the firmware, since these functions replace the Mynewt functions with the same names.
+//! ```
+//! static TEMP_SIM: SimSensor = SimSensor::new(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW,
+//!     Waveform::Triangle { min: 1700, max: 1800, period_ms: 60_000 });
+//! sim::reset();
+//! TEMP_SIM.register() ? ;
+//! TEMP_LISTENER.register(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW) ? ;
+//! poller::add(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW, Duration::from_secs(10)) ? ;
+//! sim::advance(Duration::from_secs(30));  //  Listener is called 4 times
+//! ```
+
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    time::Duration,
+};
+use crate::{
+    result::*,
+    kernel::{ os, time },
+    hw::sensor::{
+        self,
+        sensor_battery_data, sensor_heart_rate_data, sensor_link_quality_data, sensor_listener, sensor_orientation_data,
+        sensor_steps_data, sensor_temp_raw_data, sensor_type_t,
+        SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW, SENSOR_TYPE_BATTERY, SENSOR_TYPE_HEART_RATE, SENSOR_TYPE_LINK_QUALITY,
+        SENSOR_TYPE_ORIENTATION, SENSOR_TYPE_STEPS,
+    },
+    Strn,
+};
+
+/// Max number of simulated sensors
+type MaxSensors = heapless::consts::U8;
+
+/// Max number of listeners for each simulated sensor
+type MaxListeners = heapless::consts::U4;
+
+/// Max number of running timers
+type MaxCallouts = heapless::consts::U16;
+
+/// Max size of the captured JSON
+type MaxJson = heapless::consts::U1024;
+
+/// Max nesting of JSON arrays and objects
+type MaxNesting = heapless::consts::U8;
+
+/// Deterministic waveform returned by a simulated sensor. Times are measured from `SimSensor::register()`.
+#[derive(Clone, Copy, Debug)]
+pub enum Waveform {
+    /// Always the same value
+    Constant(i32),
+    /// `low` for the first half of each period, then `high`
+    Square { low: i32, high: i32, period_ms: u32 },
+    /// Rises from `min` to `max` in the first half of each period, then falls back to `min`
+    Triangle { min: i32, max: i32, period_ms: u32 },
+    /// Rises from `min` to `max` during each period, then restarts at `min`
+    Sawtooth { min: i32, max: i32, period_ms: u32 },
+    /// Returns the values in turn, one value per read, then restarts from the first value
+    Sequence(&'static [i32]),
+}
+
+impl Waveform {
+    /// Return the value after `elapsed_ms` milliseconds, for read number `reads` (starting from 0)
+    pub fn value(&self, elapsed_ms: u32, reads: u32) -> i32 {
+        match *self {
+            Waveform::Constant(value) => value,
+            Waveform::Square { low, high, period_ms } => {
+                if period_ms == 0 || elapsed_ms % period_ms < period_ms / 2 { low } else { high }
+            }
+            Waveform::Triangle { min, max, period_ms } => {
+                if period_ms < 2 { return min; }
+                let half = (period_ms / 2) as i64;
+                let phase = (elapsed_ms % period_ms) as i64;
+                let rising = if phase < half { phase } else { period_ms as i64 - phase };
+                (min as i64 + (max as i64 - min as i64) * rising / half) as i32
+            }
+            Waveform::Sawtooth { min, max, period_ms } => {
+                if period_ms == 0 { return min; }
+                let phase = (elapsed_ms % period_ms) as i64;
+                (min as i64 + (max as i64 - min as i64) * phase / period_ms as i64) as i32
+            }
+            Waveform::Sequence(values) => {
+                if values.is_empty() { 0 } else { values[reads as usize % values.len()] }
+            }
+        }
+    }
+}
+
+/// Fake sensor driver that returns a waveform. Must be declared `static`, since the listeners refer to the sensor.
+pub struct SimSensor {
+    /// Name of the sensor device
+    devname: &'static Strn,
+    /// Sensor type of the readings, e.g. `SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW`
+    sensor_type: sensor_type_t,
+    /// Values returned by the sensor
+    waveform: Waveform,
+    /// Sensor state, updated by the simulated Mynewt functions
+    state: UnsafeCell<State>,
+}
+
+/// State of a `SimSensor`
+struct State {
+    /// The Mynewt sensor, for identifying the sensor. Zeroed by `register()`.
+    sensor: MaybeUninit<sensor::sensor>,
+    /// Registered listeners
+    listeners: heapless::Vec<*mut sensor_listener, MaxListeners>,
+    /// Number of reads
+    reads: u32,
+    /// OS time when the sensor was registered
+    start: os::os_time_t,
+}
+
+/// `SimSensor` may be shared between tasks
+unsafe impl Sync for SimSensor {}
+
+impl SimSensor {
+    /// Create a simulated sensor named `devname` that returns `waveform` as readings of `sensor_type`
+    pub const fn new(devname: &'static Strn, sensor_type: sensor_type_t, waveform: Waveform) -> Self {
+        SimSensor {
+            devname,
+            sensor_type,
+            waveform,
+            state: UnsafeCell::new(State {
+                sensor:    MaybeUninit::uninit(),
+                listeners: heapless::Vec(heapless::i::Vec::new()),
+                reads:     0,
+                start:     0,
+            }),
+        }
+    }
+
+    /// Register the sensor, so that it may be found by device name, polled and listened to.
+    /// Restarts the waveform. Returns `SYS_ENOMEM` if too many sensors are registered.
+    pub fn register(&'static self) -> MynewtResult<()> {
+        let state = self.state();
+        unsafe { core::ptr::write_bytes(state.sensor.as_mut_ptr(), 0, 1) };
+        state.listeners.clear();
+        state.reads = 0;
+        state.start = now();
+        let sensors = unsafe { &mut SENSORS };
+        if sensors.iter().any(|s| core::ptr::eq(*s, self)) { return Ok(()); }
+        sensors.push(self).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+
+    /// Return the Mynewt sensor, e.g. for `Listener::register_sensor()`
+    pub fn as_sensor(&'static self) -> sensor::sensor_ptr {
+        self.state().sensor.as_mut_ptr()
+    }
+
+    /// Return the number of times the sensor has been read
+    pub fn reads(&'static self) -> u32 {
+        self.state().reads
+    }
+
+    /// Return the value for the next read
+    fn next_value(&'static self) -> i32 {
+        let state = self.state();
+        let elapsed_ms = time::ticks_to_ms(now().wrapping_sub(state.start));
+        let value = self.waveform.value(elapsed_ms, state.reads);
+        state.reads += 1;
+        value
+    }
+
+    /// Return the sensor state
+    fn state(&'static self) -> &mut State {
+        unsafe { &mut *self.state.get() }
+    }
+}
+
+/// Clear the simulated sensors, timers and captured JSON, and restart the OS clock at 0. Call at the start of each test.
+/// Listeners and timers declared `static` by the previous test must be registered and started again.
+pub fn reset() {
+    unsafe {
+        SENSORS.clear();
+        CALLOUTS.clear();
+        JSON.clear();
+        JSON_FIRST.clear();
+        NOW = 0;
+    }
+}
+
+/// Advance the OS clock by `duration`, calling the timers that expire, in order of expiry
+pub fn advance(duration: Duration) {
+    let end = now().wrapping_add(time::duration_to_ticks(duration));
+    loop {
+        //  Find the next timer that expires before the end.
+        let next = unsafe { CALLOUTS.iter() }
+            .enumerate()
+            .map(|(i, c)| (i, unsafe { (**c).c_ticks }))
+            .filter(|(_, ticks)| end.wrapping_sub(*ticks) as i32 >= 0)
+            .min_by_key(|(_, ticks)| ticks.wrapping_sub(now()) as i32);
+        let (i, ticks) = match next { Some(next) => next, None => break };
+
+        //  Move the clock to the expiry and call the timer. The timer may be restarted by the callback.
+        let callout = unsafe { CALLOUTS.swap_remove(i) };
+        if (ticks.wrapping_sub(now()) as i32) > 0 { unsafe { NOW = ticks }; }
+        unsafe {
+            (*callout).c_next.tqe_prev = core::ptr::null_mut();
+            let ev = &mut (*callout).c_ev as *mut os::os_event;
+            if let Some(cb) = (*ev).ev_cb { cb(ev); }
+        }
+    }
+    unsafe { NOW = end };
+}
+
+/// Return the JSON encoded since `reset()` or `clear_json()`, e.g. by `coap_item_val!()`
+pub fn json() -> &'static str {
+    unsafe { JSON.as_str() }
+}
+
+/// Clear the captured JSON
+pub fn clear_json() {
+    unsafe {
+        JSON.clear();
+        JSON_FIRST.clear();
+    }
+}
+
+/// Simulated sensors
+static mut SENSORS: heapless::Vec<&'static SimSensor, MaxSensors> = heapless::Vec(heapless::i::Vec::new());
+
+/// Running timers
+static mut CALLOUTS: heapless::Vec<*mut os::os_callout, MaxCallouts> = heapless::Vec(heapless::i::Vec::new());
+
+/// Simulated OS time in ticks
+static mut NOW: os::os_time_t = 0;
+
+/// Captured JSON
+static mut JSON: heapless::String<MaxJson> = heapless::String(heapless::i::String::new());
+
+/// For each open array or object, true if no values have been written
+static mut JSON_FIRST: heapless::Vec<bool, MaxNesting> = heapless::Vec(heapless::i::Vec::new());
+
+/// Default event queue. Events are not queued: expired timers are called by `advance()`.
+static mut DEFAULT_EVENTQ: os::os_eventq = os::os_eventq {
+    evq_owner: core::ptr::null_mut(),
+    evq_task:  core::ptr::null_mut(),
+    evq_list:  os::os_eventq__bindgen_ty_1 {
+        stqh_first: core::ptr::null_mut(),
+        stqh_last:  core::ptr::null_mut(),
+    },
+};
+
+/// Return the simulated OS time
+fn now() -> os::os_time_t {
+    unsafe { NOW }
+}
+
+/// Return the simulated sensor for the Mynewt sensor
+fn find_sensor(sensor: sensor::sensor_ptr) -> Option<&'static SimSensor> {
+    unsafe { SENSORS.iter() }
+        .find(|s| s.as_sensor() == sensor)
+        .cloned()
+}
+
+/// Return true if the null-terminated strings are equal
+fn cstr_eq(a: *const u8, b: *const u8) -> bool {
+    let mut i = 0;
+    loop {
+        let (ca, cb) = unsafe { (*a.add(i), *b.add(i)) };
+        if ca != cb { return false; }
+        if ca == 0 { return true; }
+        i += 1;
+    }
+}
+
+/// Return the null-terminated string as `&str`. `Strn` is not used because it assumes 32-bit pointers.
+fn cstr_to_str(cstr: *const u8) -> &'static str {
+    if cstr.is_null() { return ""; }
+    let mut len = 0;
+    while unsafe { *cstr.add(len) } != 0 { len += 1; }
+    let bytes = unsafe { core::slice::from_raw_parts(cstr, len) };
+    core::str::from_utf8(bytes).unwrap_or("?")
+}
+
+/// Call `func` with the C sensor data for `value`, converted to the struct for `sensor_type`
+fn with_sensor_data<R, F: FnOnce(*mut ::cty::c_void) -> R>(sensor_type: sensor_type_t, value: i32, func: F) -> R {
+    let unsigned = if value < 0 { 0 } else { value as u32 };
+    match sensor_type {
+        SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW => {
+            let mut data = sensor_temp_raw_data { strd_temp_raw: unsigned, strd_temp_raw_is_valid: 1 };
+            func(&mut data as *mut _ as *mut ::cty::c_void)
+        }
+        SENSOR_TYPE_HEART_RATE => {
+            let mut data = sensor_heart_rate_data { shrd_bpm: unsigned, shrd_bpm_is_valid: 1 };
+            func(&mut data as *mut _ as *mut ::cty::c_void)
+        }
+        SENSOR_TYPE_BATTERY => {
+            let mut data = sensor_battery_data { sbd_mv: unsigned, sbd_percent: 0, sbd_charging: 0, sbd_mv_is_valid: 1 };
+            func(&mut data as *mut _ as *mut ::cty::c_void)
+        }
+        SENSOR_TYPE_STEPS => {
+            let mut data = sensor_steps_data { ssd_steps: unsigned, ssd_steps_is_valid: 1 };
+            func(&mut data as *mut _ as *mut ::cty::c_void)
+        }
+        SENSOR_TYPE_ORIENTATION => {
+            let mut data = sensor_orientation_data { sod_pitch: value, sod_roll: 0, sod_is_valid: 1 };
+            func(&mut data as *mut _ as *mut ::cty::c_void)
+        }
+        SENSOR_TYPE_LINK_QUALITY => {
+            let mut data = sensor_link_quality_data { slqd_rssi: value, slqd_adv_tx_power: 0, slqd_rssi_is_valid: 1 };
+            func(&mut data as *mut _ as *mut ::cty::c_void)
+        }
+        _ => func(core::ptr::null_mut()),  //  Not supported
+    }
+}
+
+/// Append the key of a JSON value, preceded by a comma if this is not the first value
+fn json_key(key: *const ::cty::c_char) {
+    use core::fmt::Write;
+    unsafe {
+        if let Some(first) = JSON_FIRST.last_mut() {
+            if !*first { JSON.push(',').ok(); }
+            *first = false;
+        }
+        if key.is_null() { return; }
+        write!(JSON, "\"{}\":", cstr_to_str(key as *const u8)).ok();
+    }
+}
+
+/// Append a JSON value
+fn json_value(args: core::fmt::Arguments) {
+    use core::fmt::Write;
+    unsafe { JSON.write_fmt(args).ok() };
+}
+
+/// Open a JSON array or object
+fn json_open(bracket: char) {
+    unsafe {
+        JSON.push(bracket).ok();
+        JSON_FIRST.push(true).ok();
+    }
+}
+
+/// Close a JSON array or object
+fn json_close(bracket: char) {
+    unsafe {
+        JSON.push(bracket).ok();
+        JSON_FIRST.pop();
+    }
+}
+
+//  Simulated Mynewt kernel functions. C API: `kernel/os/include/os`
+
+#[no_mangle]
+extern "C" fn os_arch_save_sr() -> os::os_sr_t { 0 }
+
+#[no_mangle]
+extern "C" fn os_arch_restore_sr(_sr: os::os_sr_t) {}
+
+#[no_mangle]
+extern "C" fn os_time_get() -> os::os_time_t { now() }
+
+#[no_mangle]
+extern "C" fn os_time_delay(ticks: os::os_time_t) {
+    advance(time::ticks_to_duration(ticks));
+}
+
+/// The real-time counter of the wall clock counts with the simulated OS clock
+#[no_mangle]
+extern "C" fn os_cputime_get32() -> u32 {
+    (now() as u64 * time::wallclock::RTC_FREQ as u64 / time::TICKS_PER_SEC as u64) as u32
+}
+
+#[no_mangle]
+extern "C" fn os_settimeofday(_utctime: *mut os::os_timeval, _tz: *mut os::os_timezone) -> i32 { 0 }
+
+#[no_mangle]
+extern "C" fn os_eventq_dflt_get() -> *mut os::os_eventq {
+    unsafe { &mut DEFAULT_EVENTQ }
+}
+
+#[no_mangle]
+unsafe extern "C" fn os_callout_init(c: *mut os::os_callout, evq: *mut os::os_eventq, ev_cb: os::os_event_fn,
+    ev_arg: *mut ::cty::c_void) {
+    core::ptr::write_bytes(c, 0, 1);
+    (*c).c_evq = evq;
+    (*c).c_ev.ev_cb = ev_cb;
+    (*c).c_ev.ev_arg = ev_arg;
+}
+
+#[no_mangle]
+unsafe extern "C" fn os_callout_reset(c: *mut os::os_callout, ticks: os::os_time_t) -> ::cty::c_int {
+    (*c).c_ticks = now().wrapping_add(ticks);
+    (*c).c_next.tqe_prev = &mut (*c).c_next.tqe_next;  //  Non-null while running
+    if CALLOUTS.iter().any(|r| *r == c) { return 0; }
+    match CALLOUTS.push(c) {
+        Ok(()) => 0,
+        Err(_) => MynewtError::SYS_ENOMEM as ::cty::c_int,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn os_callout_stop(c: *mut os::os_callout) {
+    (*c).c_next.tqe_prev = core::ptr::null_mut();
+    if let Some(i) = CALLOUTS.iter().position(|r| *r == c) { CALLOUTS.swap_remove(i); }
+}
+
+#[no_mangle]
+unsafe extern "C" fn os_callout_remaining_ticks(c: *mut os::os_callout, now: os::os_time_t) -> os::os_time_t {
+    let remaining = (*c).c_ticks.wrapping_sub(now);
+    if (remaining as i32) < 0 { 0 } else { remaining }
+}
+
+//  Simulated Mynewt Sensor Manager functions. C API: `hw/sensor/include/sensor/sensor.h`
+
+#[no_mangle]
+unsafe extern "C" fn sensor_mgr_find_next_bydevname(devname: *const ::cty::c_char, prev_cursor: *mut sensor::sensor)
+    -> *mut sensor::sensor {
+    //  Skip the sensors up to the previous sensor.
+    let start = if prev_cursor.is_null() { 0 }
+        else {
+            match SENSORS.iter().position(|s| s.as_sensor() == prev_cursor) {
+                Some(i) => i + 1,
+                None => return core::ptr::null_mut(),
+            }
+        };
+    SENSORS.iter()
+        .skip(start)
+        .find(|s| cstr_eq(s.devname.as_cstr(), devname as *const u8))
+        .map(|s| s.as_sensor())
+        .unwrap_or(core::ptr::null_mut())
+}
+
+#[no_mangle]
+unsafe extern "C" fn sensor_register_listener(sensor: *mut sensor::sensor, listener: *mut sensor_listener)
+    -> ::cty::c_int {
+    let sim = match find_sensor(sensor) { Some(sim) => sim, None => return MynewtError::SYS_ENODEV as ::cty::c_int };
+    match sim.state().listeners.push(listener) {
+        Ok(()) => 0,
+        Err(_) => MynewtError::SYS_ENOMEM as ::cty::c_int,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn sensor_unregister_listener(sensor: *mut sensor::sensor, listener: *mut sensor_listener)
+    -> ::cty::c_int {
+    let sim = match find_sensor(sensor) { Some(sim) => sim, None => return MynewtError::SYS_ENODEV as ::cty::c_int };
+    let listeners = &mut sim.state().listeners;
+    match listeners.iter().position(|l| *l == listener) {
+        Some(i) => { listeners.swap_remove(i); 0 }
+        None => MynewtError::SYS_ENOENT as ::cty::c_int,
+    }
+}
+
+#[no_mangle]
+unsafe extern "C" fn sensor_read(sensor: *mut sensor::sensor, sensor_type: sensor_type_t,
+    data_func: sensor::sensor_data_func_t, arg: *mut ::cty::c_void, _timeout: u32) -> ::cty::c_int {
+    let sim = match find_sensor(sensor) { Some(sim) => sim, None => return MynewtError::SYS_ENODEV as ::cty::c_int };
+    if sensor_type & sim.sensor_type == 0 { return MynewtError::SYS_EINVAL as ::cty::c_int; }
+    let value = sim.next_value();
+    with_sensor_data(sim.sensor_type, value, |data| {
+        //  Call the read function, then the listeners, like the Sensor Manager.
+        if let Some(data_func) = data_func {
+            let rc = data_func(sensor, arg, data, sim.sensor_type);
+            if rc != 0 { return rc; }
+        }
+        let listeners = sim.state().listeners.clone();
+        for listener in listeners.iter() {
+            if (**listener).sl_sensor_type & sim.sensor_type == 0 { continue; }
+            if let Some(func) = (**listener).sl_func {
+                func(sensor, (**listener).sl_arg, data, sim.sensor_type);
+            }
+        }
+        0
+    })
+}
+
+//  Simulated JSON encoder functions for the CoAP macros. C API: `libs/sensor_coap/include/sensor_coap/sensor_coap.h`
+
+#[no_mangle]
+extern "C" fn json_helper_set_array(_object: *mut ::cty::c_void, key: *const ::cty::c_char) {
+    json_key(key);
+    json_open('[');
+}
+
+#[no_mangle]
+extern "C" fn json_helper_close_array(_object: *mut ::cty::c_void, _key: *const ::cty::c_char) {
+    json_close(']');
+}
+
+#[no_mangle]
+extern "C" fn json_helper_object_array_start_item(_key: *const ::cty::c_char) {
+    json_key(core::ptr::null());
+    json_open('{');
+}
+
+#[no_mangle]
+extern "C" fn json_helper_object_array_end_item(_key: *const ::cty::c_char) {
+    json_close('}');
+}
+
+#[no_mangle]
+extern "C" fn json_helper_set_int(_object: *mut ::cty::c_void, key: *const ::cty::c_char, value: u64) {
+    json_key(key);
+    json_value(format_args!("{}", value as i64));
+}
+
+#[no_mangle]
+extern "C" fn json_helper_set_uint(_object: *mut ::cty::c_void, key: *const ::cty::c_char, value: u64) {
+    json_key(key);
+    json_value(format_args!("{}", value));
+}
+
+#[cfg(feature = "use_float")]  //  If floating-point is enabled...
+#[no_mangle]
+extern "C" fn json_helper_set_float(_object: *mut ::cty::c_void, key: *const ::cty::c_char, value: f32) {
+    json_key(key);
+    json_value(format_args!("{}", value));
+}
+
+#[no_mangle]
+extern "C" fn json_helper_set_text_string(_object: *mut ::cty::c_void, key: *const ::cty::c_char,
+    value: *const ::cty::c_char) {
+    json_key(key);
+    json_value(format_args!("\"{}\"", cstr_to_str(value as *const u8)));
+}
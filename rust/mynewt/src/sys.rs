@@ -1,3 +1,28 @@
 //! Mynewt System API for Rust
 
 pub mod console;  // Export `sys/console.rs` as Rust module `mynewt::sys::console`
+
+pub mod console_sink;  // Export `sys/console_sink.rs` as Rust module `mynewt::sys::console_sink`
+
+pub mod uart_console;  // Export `sys/uart_console.rs` as Rust module `mynewt::sys::uart_console`
+
+pub mod panic;    // Export `sys/panic.rs` as Rust module `mynewt::sys::panic`
+
+pub mod noinit;   // Export `sys/noinit.rs` as Rust module `mynewt::sys::noinit`
+
+pub mod init;     // Export `sys/init.rs` as Rust module `mynewt::sys::init`
+
+pub mod crash_dump;  // Export `sys/crash_dump.rs` as Rust module `mynewt::sys::crash_dump`
+
+pub mod device_test;  // Export `sys/device_test.rs` as Rust module `mynewt::sys::device_test`
+
+pub mod config;   // Export `sys/config.rs` as Rust module `mynewt::sys::config`
+
+pub mod logger;   // Export `sys/logger.rs` as Rust module `mynewt::sys::logger`
+
+pub mod shell;    // Export `sys/shell.rs` as Rust module `mynewt::sys::shell`
+
+#[cfg(feature = "defmt_log")]  //  If defmt logging is enabled...
+pub mod defmt_log;  // Export `sys/defmt_log.rs` as Rust module `mynewt::sys::defmt_log`
+
+pub mod power_profile;  // Export `sys/power_profile.rs` as Rust module `mynewt::sys::power_profile`
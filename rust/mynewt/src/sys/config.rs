@@ -0,0 +1,442 @@
+//! Persisted settings with Mynewt `sys/config`. Each `Setting` is a typed value that is stored as text under the
+//! config handler `app`, e.g. `app/poll_ms`, in the config FCB on SPI flash. Settings are registered at startup, then
+//! `load()` restores the saved values. `Setting::get()` returns the saved value, or the default if nothing was saved.
+//! The settings may also be changed over newtmgr with `newtmgr config app/poll_ms 10000`.
+//! ```
+//! static POLL_TIME: Setting<u32> = Setting::new("poll_ms", "30000");
+//! POLL_TIME.register() ? ;
+//! config::load() ? ;
+//! POLL_TIME.set(10_000) ? ;  //  Saved to flash
+//! ```
+//! Settings may also be accessed by name with the type of the value, e.g. by a remote configuration server:
+//! `config::get::<u32>("poll_ms")` and `config::set("poll_ms", 10_000u32)`. Values are checked by parsing the text
+//! with the type of the registered setting, so `config::set("logo_slot", 300u32)` fails with `SYS_EINVAL` for a `u8`.
+//! `import()` sets a value from text, and `entry()` exports the values as text. The functions given to `on_change()`
+//! are called with the name of each setting whose value changes, also when changed over newtmgr.
+
+use core::{
+    cell::UnsafeCell,
+    fmt::Write,
+};
+use crate::{
+    result::*,
+    kernel::os,
+    Strn,
+};
+
+/// Name of the config handler for Rust settings. Setting `poll_ms` is stored as `app/poll_ms`.
+const HANDLER_NAME: &[u8] = b"app\0";
+
+/// Max number of registered settings
+type MaxSettings = heapless::consts::U32;
+
+/// Max number of functions called when a setting changes
+type MaxListeners = heapless::consts::U8;
+
+/// Text of a setting value, also used for setting names like `app/poll_ms`
+pub type ConfigString = heapless::String<heapless::consts::U64>;
+
+/// Small binary value of a setting, e.g. a key or an address, stored as hex text. The text must fit in a
+/// `ConfigString` with the terminating null.
+pub type ConfigBlob = heapless::Vec<u8, heapless::consts::U24>;
+
+/// Function called with the name of a setting after its value changes
+pub type Listener = fn(name: &str);
+
+/// Type that may be stored as a setting. Values are stored as text.
+pub trait ConfigValue: Sized {
+    /// Convert the stored text to a value. Returns `SYS_EINVAL` if the text is invalid.
+    fn parse(text: &str) -> MynewtResult<Self>;
+    /// Convert the value to text for storing
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()>;
+}
+
+impl ConfigValue for u32 {
+    /// Parse a decimal number
+    fn parse(text: &str) -> MynewtResult<Self> {
+        text.parse().map_err(|_| MynewtError::SYS_EINVAL)
+    }
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()> {
+        write!(text, "{}", self).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+}
+
+impl ConfigValue for i32 {
+    /// Parse a decimal number that may be negative
+    fn parse(text: &str) -> MynewtResult<Self> {
+        text.parse().map_err(|_| MynewtError::SYS_EINVAL)
+    }
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()> {
+        write!(text, "{}", self).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+}
+
+impl ConfigValue for u8 {
+    /// Parse a decimal number from 0 to 255
+    fn parse(text: &str) -> MynewtResult<Self> {
+        text.parse().map_err(|_| MynewtError::SYS_EINVAL)
+    }
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()> {
+        write!(text, "{}", self).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+}
+
+impl ConfigValue for bool {
+    /// Parse `1` or `0`, like Mynewt `conf_value_from_str()`
+    fn parse(text: &str) -> MynewtResult<Self> {
+        match text {
+            "1" | "true"  => Ok(true),
+            "0" | "false" => Ok(false),
+            _ => Err(MynewtError::SYS_EINVAL),
+        }
+    }
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()> {
+        text.push_str(if *self { "1" } else { "0" }).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+}
+
+impl ConfigValue for ConfigString {
+    /// Copy the text. Returns `SYS_ENOMEM` if the text is too long.
+    fn parse(text: &str) -> MynewtResult<Self> {
+        let mut value = ConfigString::new();
+        value.push_str(text).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+        Ok(value)
+    }
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()> {
+        text.push_str(self).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+}
+
+impl ConfigValue for ConfigBlob {
+    /// Parse pairs of hex digits, e.g. `0a1b`. Returns `SYS_ENOMEM` if the blob is too long.
+    fn parse(text: &str) -> MynewtResult<Self> {
+        if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) { return Err(MynewtError::SYS_EINVAL); }
+        let mut value = ConfigBlob::new();
+        for pair in text.as_bytes().chunks(2) {
+            let pair = core::str::from_utf8(pair).map_err(|_| MynewtError::SYS_EINVAL) ? ;
+            let byte = u8::from_str_radix(pair, 16).map_err(|_| MynewtError::SYS_EINVAL) ? ;
+            value.push(byte).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+        }
+        Ok(value)
+    }
+    fn format(&self, text: &mut ConfigString) -> MynewtResult<()> {
+        for byte in self.iter() {
+            write!(text, "{:02x}", byte).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+        }
+        Ok(())
+    }
+}
+
+/// Setting of type `T`, persisted as `app/<name>`. Must be declared `static`.
+pub struct Setting<T> {
+    /// Name of the setting, without the `app/` prefix
+    name: &'static str,
+    /// Text of the default value, used when nothing has been saved
+    default: &'static str,
+    /// Value that was loaded or set, `None` for the default
+    value: UnsafeCell<Option<T>>,
+}
+
+/// `Setting` may be shared between tasks
+unsafe impl<T: Send> Sync for Setting<T> {}
+
+impl<T> Setting<T> {
+    /// Create a setting named `name` with the default value given as text, e.g. `"30000"`
+    pub const fn new(name: &'static str, default: &'static str) -> Self {
+        Setting {
+            name,
+            default,
+            value: UnsafeCell::new(None),
+        }
+    }
+}
+
+impl<T: ConfigValue + Clone + Send + 'static> Setting<T> {
+    /// Register the setting, so that `load()` restores the saved value. Settings must be registered before `load()`.
+    pub fn register(&'static self) -> MynewtResult<()> {
+        register_handler() ? ;
+        let settings = unsafe { &mut SETTINGS };
+        if settings.iter().any(|s| s.name() == self.name) { return Ok(()); }  //  Already registered
+        settings.push(self).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+
+    /// Return the saved value, or the default value if nothing was saved
+    pub fn get(&'static self) -> T {
+        let sr = unsafe { os::os_arch_save_sr() };
+        let value = unsafe { (*self.value.get()).clone() };
+        unsafe { os::os_arch_restore_sr(sr) };
+        match value {
+            Some(value) => value,
+            None => T::parse(self.default).expect("bad config default"),
+        }
+    }
+
+    /// Change the value and save it to flash
+    pub fn set(&'static self, value: T) -> MynewtResult<()> {
+        let mut text = ConfigString::new();
+        value.format(&mut text) ? ;
+        let changed = self.differs(&text);
+        self.store(Some(value));
+        save_one(self.name, &text) ? ;
+        if changed { notify(self.name); }
+        Ok(())
+    }
+
+    /// Restore the default value and delete the saved value
+    pub fn reset(&'static self) -> MynewtResult<()> {
+        let changed = self.differs(self.default);
+        self.store(None);
+        save_one(self.name, "") ? ;
+        if changed { notify(self.name); }
+        Ok(())
+    }
+
+    /// Return true if the current value as text is not `text`
+    fn differs(&'static self, text: &str) -> bool {
+        let mut current = ConfigString::new();
+        self.export(&mut current).is_err() || current.as_str() != text
+    }
+
+    /// Update the value in RAM
+    fn store(&'static self, value: Option<T>) {
+        let sr = unsafe { os::os_arch_save_sr() };
+        unsafe { *self.value.get() = value };
+        unsafe { os::os_arch_restore_sr(sr) };
+    }
+}
+
+/// Setting of any type, for the config handler callbacks
+trait SettingEntry {
+    /// Name of the setting, without the `app/` prefix
+    fn name(&self) -> &'static str;
+    /// Set the value from the stored text. Empty text restores the default.
+    fn load(&'static self, text: &str) -> MynewtResult<()>;
+    /// Return the current value as text
+    fn export(&'static self, text: &mut ConfigString) -> MynewtResult<()>;
+}
+
+impl<T: ConfigValue + Clone + Send + 'static> SettingEntry for Setting<T> {
+    fn name(&self) -> &'static str { self.name }
+
+    fn load(&'static self, text: &str) -> MynewtResult<()> {
+        if text.is_empty() { self.store(None); return Ok(()); }  //  Deleted
+        let value = T::parse(text) ? ;
+        self.store(Some(value));
+        Ok(())
+    }
+
+    fn export(&'static self, text: &mut ConfigString) -> MynewtResult<()> {
+        self.get().format(text)
+    }
+}
+
+/// Registered settings
+static mut SETTINGS: heapless::Vec<&'static dyn SettingEntry, MaxSettings> = heapless::Vec(heapless::i::Vec::new());
+
+/// Functions called when a setting changes
+static mut LISTENERS: heapless::Vec<Listener, MaxListeners> = heapless::Vec(heapless::i::Vec::new());
+
+/// True if `HANDLER` has been registered with `conf_register()`
+static mut HANDLER_REGISTERED: bool = false;
+
+/// Config handler for the `app/...` settings
+static mut HANDLER: conf_handler = conf_handler {
+    ch_list:   conf_handler__bindgen_ty_1 { sle_next: core::ptr::null_mut() },
+    ch_name:   HANDLER_NAME.as_ptr() as *mut ::cty::c_char,
+    ch_ext:    false,
+    ch_get:    Some(handler_get),
+    ch_set:    Some(handler_set),
+    ch_commit: None,
+    ch_export: Some(handler_export),
+    ch_arg:    core::ptr::null_mut(),
+};
+
+/// Load the saved values of the registered settings from flash. Called at startup after registering the settings.
+pub fn load() -> MynewtResult<()> {
+    check(unsafe { conf_load() })
+}
+
+/// Save the current values of all settings to flash, e.g. after changing settings with `conf_set_value()`
+pub fn save() -> MynewtResult<()> {
+    check(unsafe { conf_save() })
+}
+
+/// Return the value of the registered setting named `name`, e.g. `config::get::<u32>("poll_ms")`. Returns
+/// `SYS_ENOENT` if the setting is not registered, or `SYS_EINVAL` if the value is not of type `T`.
+pub fn get<T: ConfigValue>(name: &str) -> MynewtResult<T> {
+    let setting = find_name(name).ok_or(MynewtError::SYS_ENOENT) ? ;
+    let mut text = ConfigString::new();
+    setting.export(&mut text) ? ;
+    T::parse(&text)
+}
+
+/// Change the value of the registered setting named `name` and save it to flash, e.g.
+/// `config::set("poll_ms", 10_000u32)`. Returns `SYS_ENOENT` if the setting is not registered, or `SYS_EINVAL` if
+/// the value is not valid for the type of the setting.
+pub fn set<T: ConfigValue>(name: &str, value: T) -> MynewtResult<()> {
+    let mut text = ConfigString::new();
+    value.format(&mut text) ? ;
+    import(name, &text)
+}
+
+/// Change the value of the registered setting named `name` to the value given as text, e.g. `"10000"`, and save it
+/// to flash. Empty text restores the default. Returns `SYS_ENOENT` if the setting is not registered, or
+/// `SYS_EINVAL` if the text is not valid for the type of the setting.
+pub fn import(name: &str, text: &str) -> MynewtResult<()> {
+    let setting = find_name(name).ok_or(MynewtError::SYS_ENOENT) ? ;
+    let mut old = ConfigString::new();
+    setting.export(&mut old) ? ;
+    setting.load(text) ? ;
+    save_one(setting.name(), text) ? ;
+    let mut new = ConfigString::new();
+    setting.export(&mut new) ? ;
+    if new != old { notify(setting.name()); }
+    Ok(())
+}
+
+/// Return the number of registered settings
+pub fn count() -> usize {
+    unsafe { SETTINGS.len() }
+}
+
+/// Return the name and the current value as text of the registered setting at `index`, for exporting the
+/// settings. Returns `None` if there is no such setting.
+pub fn entry(index: usize) -> Option<(&'static str, ConfigString)> {
+    let setting = unsafe { SETTINGS.get(index) }.cloned() ? ;
+    let mut text = ConfigString::new();
+    setting.export(&mut text).ok() ? ;
+    Some((setting.name(), text))
+}
+
+/// Call `listener` with the name of each setting whose value changes, after the change. Called at startup.
+/// Returns `SYS_ENOMEM` if there are too many listeners.
+pub fn on_change(listener: Listener) -> MynewtResult<()> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let result = unsafe { LISTENERS.push(listener) };
+    unsafe { os::os_arch_restore_sr(sr) };
+    result.map_err(|_| MynewtError::SYS_ENOMEM)
+}
+
+/// Call the listeners for the change of setting `name`
+fn notify(name: &str) {
+    for listener in unsafe { LISTENERS.iter() } {
+        listener(name);
+    }
+}
+
+/// Register the config handler, if not registered yet
+fn register_handler() -> MynewtResult<()> {
+    let sr = unsafe { os::os_arch_save_sr() };
+    let registered = unsafe { HANDLER_REGISTERED };
+    unsafe { HANDLER_REGISTERED = true };
+    unsafe { os::os_arch_restore_sr(sr) };
+    if registered { return Ok(()); }
+    check(unsafe { conf_register(&mut HANDLER) })
+}
+
+/// Save `text` as the value of setting `name`. Empty text deletes the saved value.
+fn save_one(name: &str, text: &str) -> MynewtResult<()> {
+    let mut full_name = ConfigString::new();
+    write!(&mut full_name, "app/{}\0", name).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    let mut value = ConfigString::new();
+    write!(&mut value, "{}\0", text).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    let value_ptr = if text.is_empty() { core::ptr::null_mut() } else { value.as_ptr() as *mut ::cty::c_char };
+    check(unsafe { conf_save_one(full_name.as_ptr() as *const ::cty::c_char, value_ptr) })
+}
+
+/// Return the registered setting named by the C string `name`
+fn find(name: *mut ::cty::c_char) -> Option<&'static dyn SettingEntry> {
+    find_name(cstr_to_str(name) ? )
+}
+
+/// Return the registered setting named `name`
+fn find_name(name: &str) -> Option<&'static dyn SettingEntry> {
+    unsafe { SETTINGS.iter() }
+        .find(|s| s.name() == name)
+        .cloned()
+}
+
+/// Convert a null-terminated C string to `&str`. Returns `None` if null or not UTF-8.
+fn cstr_to_str<'a>(cstr: *const ::cty::c_char) -> Option<&'a str> {
+    if cstr.is_null() { return None; }
+    let len = Strn::from_cstr(cstr as *const u8).len();
+    let bytes = unsafe { core::slice::from_raw_parts(cstr as *const u8, len) };
+    core::str::from_utf8(bytes).ok()
+}
+
+/// Called by `sys/config` to read setting `app/<argv[0]>` into `val`
+extern "C" fn handler_get(argc: ::cty::c_int, argv: *mut *mut ::cty::c_char, val: *mut ::cty::c_char, val_len_max: ::cty::c_int) -> *mut ::cty::c_char {
+    if argc != 1 || val.is_null() { return core::ptr::null_mut(); }
+    let setting = match find(unsafe { *argv }) { Some(s) => s, None => return core::ptr::null_mut() };
+    let mut text = ConfigString::new();
+    if setting.export(&mut text).is_err() || text.len() >= val_len_max as usize { return core::ptr::null_mut(); }
+    unsafe {
+        core::ptr::copy_nonoverlapping(text.as_ptr(), val as *mut u8, text.len());
+        *val.add(text.len()) = 0;
+    }
+    val
+}
+
+/// Called by `sys/config` to set `app/<argv[0]>` to `val`, when loading from flash or from newtmgr
+extern "C" fn handler_set(argc: ::cty::c_int, argv: *mut *mut ::cty::c_char, val: *mut ::cty::c_char) -> ::cty::c_int {
+    if argc != 1 { return os::os_error_OS_ENOENT as ::cty::c_int; }
+    let setting = match find(unsafe { *argv }) { Some(s) => s, None => return os::os_error_OS_ENOENT as ::cty::c_int };
+    let text = if val.is_null() { "" } else {
+        match cstr_to_str(val) { Some(t) => t, None => return os::os_error_OS_EINVAL as ::cty::c_int }
+    };
+    let mut old = ConfigString::new();
+    let exported = setting.export(&mut old).is_ok();
+    match setting.load(text) {
+        Ok(()) => {
+            let mut new = ConfigString::new();
+            if exported && setting.export(&mut new).is_ok() && new != old { notify(setting.name()); }
+            0
+        }
+        Err(_) => os::os_error_OS_EINVAL as ::cty::c_int,
+    }
+}
+
+/// Called by `sys/config` to export all settings, for `conf_save()` and newtmgr
+extern "C" fn handler_export(export_func: Option<unsafe extern "C" fn(name: *mut ::cty::c_char, val: *mut ::cty::c_char)>, _tgt: ::cty::c_int) -> ::cty::c_int {
+    let export_func = match export_func { Some(f) => f, None => return os::os_error_OS_EINVAL as ::cty::c_int };
+    for setting in unsafe { SETTINGS.iter() } {
+        let mut full_name = ConfigString::new();
+        let mut text = ConfigString::new();
+        if write!(&mut full_name, "app/{}\0", setting.name()).is_err() { continue; }
+        if setting.export(&mut text).is_err() || text.push('\0').is_err() { continue; }
+        unsafe { export_func(full_name.as_ptr() as *mut ::cty::c_char, text.as_ptr() as *mut ::cty::c_char) };
+    }
+    0
+}
+
+/// Config handler. From `sys/config/include/config/config.h` in Mynewt 1.7
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct conf_handler {
+    ch_list:   conf_handler__bindgen_ty_1,
+    ch_name:   *mut ::cty::c_char,
+    ch_ext:    bool,
+    ch_get:    Option<extern "C" fn(argc: ::cty::c_int, argv: *mut *mut ::cty::c_char, val: *mut ::cty::c_char, val_len_max: ::cty::c_int) -> *mut ::cty::c_char>,
+    ch_set:    Option<extern "C" fn(argc: ::cty::c_int, argv: *mut *mut ::cty::c_char, val: *mut ::cty::c_char) -> ::cty::c_int>,
+    ch_commit: Option<extern "C" fn() -> ::cty::c_int>,
+    ch_export: Option<extern "C" fn(export_func: Option<unsafe extern "C" fn(name: *mut ::cty::c_char, val: *mut ::cty::c_char)>, tgt: ::cty::c_int) -> ::cty::c_int>,
+    ch_arg:    *mut ::cty::c_void,
+}
+
+/// `SLIST_ENTRY(conf_handler)`
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct conf_handler__bindgen_ty_1 {
+    sle_next: *mut conf_handler,
+}
+
+extern "C" {
+    /// Register a config handler. C API: `int conf_register(struct conf_handler *cf)`
+    fn conf_register(cf: *mut conf_handler) -> ::cty::c_int;
+    /// Load the saved settings and call the `set` handlers. C API: `int conf_load(void)`
+    fn conf_load() -> ::cty::c_int;
+    /// Save all settings returned by the `export` handlers. C API: `int conf_save(void)`
+    fn conf_save() -> ::cty::c_int;
+    /// Save one setting. C API: `int conf_save_one(const char *name, char *var)`
+    fn conf_save_one(name: *const ::cty::c_char, var: *mut ::cty::c_char) -> ::cty::c_int;
+}
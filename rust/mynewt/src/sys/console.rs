@@ -76,7 +76,7 @@ pub fn flush() {
 
 ///  Import the custom Mynewt library for displaying messages on the Arm Semihosting Console (via OpenOCD).
 ///  The library is located at `libs/semihosting_console`
-#[link(name = "libs_semihosting_console")]  //  Functions below are located in the Mynewt build output `libs_semihosting_console.a`
+#[cfg_attr(not(feature = "sim"), link(name = "libs_semihosting_console"))]  //  Functions below are located in the Mynewt build output `libs_semihosting_console.a`
 extern {
     ///  Add the string to the output buffer.
     ///  C API: `void console_buffer(const char *buffer, unsigned int length)`
@@ -0,0 +1,140 @@
+//! Console sinks: the backends that receive the console output, e.g. `console::print()`, the logs and the shell
+//! output. The same firmware may be debugged on the bench with Semihosting, RTT or a UART, and on a sealed watch over
+//! the Bluetooth LE console, by selecting the sink at startup or with the shell command `console use <name>`.
+//! Each backend implements `ConsoleSink` and is registered with `register()`, then `select()` sends all console
+//! output to that sink. Until a sink is selected, the output goes to Semihosting.
+//! ```
+//! console_sink::register(&console_sink::SEMIHOSTING_SINK) ? ;
+//! console_sink::register(&uart_console::UART_SINK) ? ;
+//! console_sink::select("uart") ? ;
+//! ```
+//! The sinks provided here are `SEMIHOSTING_SINK` and `RTT_SINK`. The UART sink is in `sys/uart_console.rs`.
+
+use crate::{
+    kernel::os,
+    result::*,
+};
+
+/// Backend that receives the console output
+pub trait ConsoleSink: Sync {
+    /// Short name of the sink for selecting it, e.g. `uart`
+    fn name(&self) -> &'static str;
+    /// Send the bytes. Must not block, since it may be called by any task or interrupt.
+    fn write(&self, bytes: &[u8]);
+    /// Prepare the sink before it receives the output. Returns `SYS_ENOTSUP` if the sink is not supported by the
+    /// firmware, e.g. disabled in `syscfg.yml`.
+    fn attach(&self) -> MynewtResult<()> { Ok(()) }
+    /// Stop using the sink after another sink is selected
+    fn detach(&self) {}
+}
+
+/// Max number of sinks that may be registered. Must match `MaxSinks`.
+pub const MAX_SINKS: usize = 4;
+type MaxSinks = heapless::consts::U4;
+
+/// Sinks registered at startup
+static mut SINKS: heapless::Vec<&'static dyn ConsoleSink, MaxSinks> = heapless::Vec(heapless::i::Vec::new());
+
+/// Selected sink, `None` for the default Semihosting output of the console
+static mut SELECTED: Option<&'static dyn ConsoleSink> = None;
+
+/// Arm Semihosting, which halts the CPU for each flush while a debugger is attached
+pub static SEMIHOSTING_SINK: SemihostingSink = SemihostingSink;
+
+/// SEGGER RTT, read by the debug probe without halting the CPU. Needs `RTT_CONSOLE_SINK: 1` in `syscfg.yml`.
+pub static RTT_SINK: RttSink = RttSink;
+
+/// Make the `sink` available for `select()`. Returns `SYS_EALREADY` if a sink with the same name has been
+/// registered, or `SYS_ENOMEM` if there are more than `MAX_SINKS` sinks. Must be called before `select()`.
+pub fn register(sink: &'static dyn ConsoleSink) -> MynewtResult<()> {
+    if find(sink.name()).is_some() { return Err(MynewtError::SYS_EALREADY); }
+    unsafe { SINKS.push(sink) }.map_err(|_| MynewtError::SYS_ENOMEM)
+}
+
+/// Send all console output to the sink named `name`. The previous sink is detached. Returns `SYS_ENOENT` if no sink
+/// has the name, or the error from attaching the sink, in which case the previous sink is kept.
+pub fn select(name: &str) -> MynewtResult<()> {
+    let sink = find(name).ok_or(MynewtError::SYS_ENOENT) ? ;
+    sink.attach() ? ;
+    let previous = unsafe {
+        let sr = os::os_arch_save_sr();
+        let previous = SELECTED.replace(sink);
+        os::os_arch_restore_sr(sr);
+        previous
+    };
+    if let Some(previous) = previous {
+        if previous.name() != name { previous.detach(); }
+    }
+    unsafe { console_set_output_cb(Some(handle_console_output)) };
+    Ok(())
+}
+
+/// Return the name of the selected sink, or `None` if no sink has been selected
+pub fn selected() -> Option<&'static str> {
+    unsafe { SELECTED }.map(|sink| sink.name())
+}
+
+/// Return the registered sinks, in order of registration
+pub fn sinks() -> impl Iterator<Item = &'static dyn ConsoleSink> {
+    unsafe { SINKS.iter() }.cloned()
+}
+
+/// Return the registered sink named `name`
+fn find(name: &str) -> Option<&'static dyn ConsoleSink> {
+    sinks().find(|sink| sink.name() == name)
+}
+
+/// Send the console output to the selected sink. Called by the console in any task or interrupt.
+extern "C" fn handle_console_output(buffer: *const u8, length: u32) {
+    let sink = unsafe {
+        let sr = os::os_arch_save_sr();
+        let sink = SELECTED;
+        os::os_arch_restore_sr(sr);
+        sink
+    };
+    if let Some(sink) = sink {
+        sink.write(unsafe { core::slice::from_raw_parts(buffer, length as usize) });
+    }
+}
+
+/// Console sink for Arm Semihosting. The output is buffered until `console::flush()`.
+pub struct SemihostingSink;
+
+impl ConsoleSink for SemihostingSink {
+    fn name(&self) -> &'static str { "semihosting" }
+
+    fn write(&self, bytes: &[u8]) {
+        unsafe { semihosting_console_write(bytes.as_ptr(), bytes.len() as u32) };
+    }
+}
+
+/// Console sink for SEGGER RTT. The bytes that don't fit in the RTT buffer are dropped.
+pub struct RttSink;
+
+impl ConsoleSink for RttSink {
+    fn name(&self) -> &'static str { "rtt" }
+
+    fn write(&self, bytes: &[u8]) {
+        unsafe { rtt_console_write(bytes.as_ptr(), bytes.len() as u32) };
+    }
+
+    fn attach(&self) -> MynewtResult<()> {
+        if unsafe { rtt_console_is_enabled() } == 0 { return Err(MynewtError::SYS_ENOTSUP); }
+        Ok(())
+    }
+}
+
+extern "C" {
+    /// Send the console output to the callback instead of Semihosting. NULL to send to Semihosting again.
+    /// C API: `void console_set_output_cb(console_output_cb cb)` in `libs/semihosting_console`
+    fn console_set_output_cb(cb: Option<extern "C" fn(*const u8, u32)>);
+    /// Append the bytes to the Semihosting output buffer.
+    /// C API: `void semihosting_console_write(const char *buffer, unsigned int length)` in `libs/semihosting_console`
+    fn semihosting_console_write(buffer: *const u8, length: u32);
+    /// Append the bytes to the RTT up-buffer.
+    /// C API: `void rtt_console_write(const char *buffer, unsigned int length)` in `apps/my_sensor_app/src/rtt_console.c`
+    fn rtt_console_write(buffer: *const u8, length: u32);
+    /// Return 1 if the RTT console sink is enabled in `syscfg.yml`.
+    /// C API: `int rtt_console_is_enabled(void)` in `apps/my_sensor_app/src/rtt_console.c`
+    fn rtt_console_is_enabled() -> i32;
+}
@@ -0,0 +1,267 @@
+//! Crash dumps that survive power loss, for closing the loop on failures in the field. The records of `panic`,
+//! `kernel::fault` and `kernel::stack_guard` in RAM are lost when the battery runs out before someone reads the
+//! console, so the handlers also call `record()`, which writes a compact `CrashDump` with the reason, the `pc` and
+//! `lr`, the task, the uptime and the firmware version into the region `map::CRASH_LOG` of the Internal Flash ROM.
+//! The Internal Flash ROM is programmed by the NVMC without interrupts or locks, so the dump is written safely from
+//! the fault handler. Each dump takes a slot in the region and is checked with a CRC. When the region is full, new
+//! dumps are dropped, since the first crash is the most useful.
+//! After restarting, the app uploads the dumps with `for_each()` and `mark_uploaded()`, e.g. in
+//! `rust/app/src/crash_report.rs`, then erases the region with `clear()` when all dumps have been uploaded.
+//! Each dump is uploaded as a single CoAP payload, so `DUMP_TASK_SIZE` and `DUMP_DETAIL_SIZE` limit the size of the
+//! upload, which `rust/app/src/app_network.rs` checks at compile time.
+//! Dumps are written only after `start()`, which sets the firmware version.
+
+use crate::{
+    result::*,
+    hw::flash::map::{ self, Region, Storage },
+    kernel::{ os, task::Task, time },
+    util::crc::crc32,
+};
+
+/// Flash region of the crash dumps
+const DUMP_REGION: Region = map::CRASH_LOG;
+
+/// Size of a dump in flash
+const DUMP_SIZE: u32 = core::mem::size_of::<CrashDump>() as u32;
+
+/// Number of dumps that fit in the region
+const NUM_SLOTS: u32 = DUMP_REGION.size / DUMP_SIZE;
+
+/// Marks a written dump: `CRSH`
+const DUMP_MAGIC: u32 = 0x4853_5243;
+
+/// Value of an erased word
+const ERASED: u32 = 0xffff_ffff;
+
+/// Max number of bytes of the task name that will be recorded
+pub const DUMP_TASK_SIZE: usize = 12;
+
+/// Max number of bytes of the details that will be recorded, e.g. the panic location and message
+pub const DUMP_DETAIL_SIZE: usize = 48;
+
+/// Cause of a crash
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum CrashReason {
+    /// Rust panic
+    Panic         = 1,
+    /// HardFault, MemManage, BusFault or UsageFault
+    Fault         = 2,
+    /// Stack overflow detected by the canaries
+    StackOverflow = 3,
+    /// Task that stopped checking in with the supervisor
+    Hung          = 4,
+}
+
+impl CrashReason {
+    /// Return the name of the reason, for display and upload
+    pub fn name(self) -> &'static str {
+        match self {
+            CrashReason::Panic         => "panic",
+            CrashReason::Fault         => "fault",
+            CrashReason::StackOverflow => "stack overflow",
+            CrashReason::Hung          => "hung",
+        }
+    }
+
+    /// Convert the recorded code to a reason
+    fn from_code(code: u8) -> Option<CrashReason> {
+        match code {
+            1 => Some(CrashReason::Panic),
+            2 => Some(CrashReason::Fault),
+            3 => Some(CrashReason::StackOverflow),
+            4 => Some(CrashReason::Hung),
+            _ => None,
+        }
+    }
+}
+
+/// Crash dump in the region `map::CRASH_LOG`
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct CrashDump {
+    /// `DUMP_MAGIC` if written, `ERASED` if the slot is free
+    magic: u32,
+    /// Address of the faulting instruction, 0 if unknown
+    pub pc: u32,
+    /// Return address of the function that crashed, 0 if unknown
+    pub lr: u32,
+    /// Seconds since startup
+    pub uptime: u32,
+    /// Firmware version that crashed, packed as `major << 24 | minor << 16 | revision`
+    pub version: u32,
+    /// `CrashReason` code
+    reason: u8,
+    /// Number of bytes used in `task`
+    task_len: u8,
+    /// Number of bytes used in `detail`
+    detail_len: u8,
+    /// Unused, for alignment
+    _reserved: u8,
+    /// Name of the task that crashed, truncated
+    task: [u8; DUMP_TASK_SIZE],
+    /// Details of the crash, truncated
+    detail: [u8; DUMP_DETAIL_SIZE],
+    /// CRC32 of the fields above
+    crc: u32,
+    /// `ERASED` until uploaded, then 0. Programmed a second time without erasing, which clears the bits.
+    uploaded: u32,
+}
+
+impl CrashDump {
+    /// Return the cause of the crash, or `None` if unknown
+    pub fn reason(&self) -> Option<CrashReason> {
+        CrashReason::from_code(self.reason)
+    }
+
+    /// Return the name of the task that crashed. May be truncated.
+    pub fn task(&self) -> &str {
+        utf8_prefix(&self.task[..core::cmp::min(self.task_len as usize, DUMP_TASK_SIZE)])
+    }
+
+    /// Return the details of the crash, e.g. `app/src/lib.rs:42 index out of bounds`. May be truncated.
+    pub fn detail(&self) -> &str {
+        utf8_prefix(&self.detail[..core::cmp::min(self.detail_len as usize, DUMP_DETAIL_SIZE)])
+    }
+
+    /// Return true if the dump has been uploaded
+    pub fn is_uploaded(&self) -> bool {
+        self.uploaded != ERASED
+    }
+
+    /// Return true if the dump was written completely
+    fn is_valid(&self) -> bool {
+        self.magic == DUMP_MAGIC && self.crc == crc32(&as_bytes(self)[..CRC_SIZE])
+    }
+}
+
+/// Number of bytes covered by the CRC: all fields before `crc`
+const CRC_SIZE: usize = core::mem::size_of::<CrashDump>() - 8;
+
+/// Firmware version of the dumps, set by `start()`
+static mut VERSION: u32 = 0;
+
+/// True after `start()`, when the dumps are written
+static mut STARTED: bool = false;
+
+/// True while a dump is being written, so that a fault in `record()` doesn't write another dump
+static mut RECORDING: bool = false;
+
+/// Start writing the dumps, with the firmware version packed as `major << 24 | minor << 16 | revision`.
+/// Called by main() in `lib.rs`.
+pub fn start(version: u32) {
+    unsafe {
+        VERSION = version;
+        STARTED = true;
+    }
+}
+
+/// Write a dump of the crash into the next free slot. `task` is the name of the task that crashed, or `None` for
+/// the task that is running now. Called by the panic, fault, stack overflow and supervisor handlers just before
+/// restarting. Returns `SYS_EAGAIN` before `start()`, `SYS_ENOMEM` if the region is full, and `SYS_EALREADY` if a
+/// dump is being written.
+pub fn record(reason: CrashReason, pc: u32, lr: u32, task: Option<&str>, detail: &str) -> MynewtResult<()> {
+    unsafe {
+        if !STARTED { return Err(MynewtError::SYS_EAGAIN); }
+        if RECORDING { return Err(MynewtError::SYS_EALREADY); }
+        RECORDING = true;
+    }
+    let mut dump = CrashDump {
+        magic:      DUMP_MAGIC,
+        pc,
+        lr,
+        uptime:     unsafe { os::os_time_get() } / time::TICKS_PER_SEC,
+        version:    unsafe { VERSION },
+        reason:     reason as u8,
+        task_len:   0,
+        detail_len: 0,
+        _reserved:  0,
+        task:       [0; DUMP_TASK_SIZE],
+        detail:     [0; DUMP_DETAIL_SIZE],
+        crc:        0,
+        uploaded:   ERASED,
+    };
+    let current = Task::current();
+    let task = match task {
+        Some(task) => task,
+        None if !current.as_ptr().is_null() => current.name(),
+        None => "",  //  No task before the OS starts
+    };
+    let task = task.as_bytes();
+    let len = core::cmp::min(task.len(), DUMP_TASK_SIZE);
+    dump.task[..len].copy_from_slice(&task[..len]);
+    dump.task_len = len as u8;
+    let detail = detail.as_bytes();
+    let len = core::cmp::min(detail.len(), DUMP_DETAIL_SIZE);
+    dump.detail[..len].copy_from_slice(&detail[..len]);
+    dump.detail_len = len as u8;
+    dump.crc = crc32(&as_bytes(&dump)[..CRC_SIZE]);
+
+    let result = free_slot()
+        .and_then(|slot| DUMP_REGION.write(slot * DUMP_SIZE, as_bytes(&dump)));
+    unsafe { RECORDING = false };
+    result
+}
+
+/// Call `f` with the slot number and the dump in each slot that holds a valid dump, oldest first.
+/// Stops at the first error returned by `f`.
+pub fn for_each<F: FnMut(u32, &CrashDump) -> MynewtResult<()>>(mut f: F) -> MynewtResult<()> {
+    for slot in 0..NUM_SLOTS {
+        let dump = read_slot(slot) ? ;
+        if dump.magic == ERASED { break; }  //  Dumps are written in order, so the rest are free
+        if dump.is_valid() { f(slot, &dump) ? ; }
+    }
+    Ok(())
+}
+
+/// Return the number of dumps that have not been uploaded
+pub fn pending() -> MynewtResult<u32> {
+    let mut count = 0;
+    for_each(|_, dump| {
+        if !dump.is_uploaded() { count += 1; }
+        Ok(())
+    }) ? ;
+    Ok(count)
+}
+
+/// Mark the dump in `slot` as uploaded, so that it's not uploaded again after a restart
+pub fn mark_uploaded(slot: u32) -> MynewtResult<()> {
+    if slot >= NUM_SLOTS { return Err(MynewtError::SYS_EINVAL); }
+    let offset = slot * DUMP_SIZE + DUMP_SIZE - 4;  //  Offset of `uploaded`
+    DUMP_REGION.write(offset, &0u32.to_le_bytes())
+}
+
+/// Erase all dumps
+pub fn clear() -> MynewtResult<()> {
+    DUMP_REGION.erase(0, DUMP_REGION.size)
+}
+
+/// Return the first free slot, or `SYS_ENOMEM` if the region is full
+fn free_slot() -> MynewtResult<u32> {
+    for slot in 0..NUM_SLOTS {
+        if read_slot(slot) ? .magic == ERASED { return Ok(slot); }
+    }
+    Err(MynewtError::SYS_ENOMEM)
+}
+
+/// Read the dump in `slot`
+fn read_slot(slot: u32) -> MynewtResult<CrashDump> {
+    let mut buf = [0u8; DUMP_SIZE as usize];
+    DUMP_REGION.read(slot * DUMP_SIZE, &mut buf) ? ;
+    Ok(unsafe { core::ptr::read_unaligned(buf.as_ptr() as *const CrashDump) })
+}
+
+/// Return the dump as bytes for writing to flash
+fn as_bytes(dump: &CrashDump) -> &[u8] {
+    unsafe {
+        core::slice::from_raw_parts(dump as *const CrashDump as *const u8, DUMP_SIZE as usize)
+    }
+}
+
+/// Return the longest valid UTF-8 prefix of `bytes`, since truncation may split a character
+fn utf8_prefix(bytes: &[u8]) -> &str {
+    match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(err) => core::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap_or(""),
+    }
+}
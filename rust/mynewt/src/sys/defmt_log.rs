@@ -0,0 +1,14 @@
+//! Compact logging with `defmt`, enabled by the `defmt_log` feature and `DEFMT_LOG: 1` in `syscfg.yml`.
+//! The format strings are stored in the `.defmt` section of the ELF file on the host, not in Flash ROM, and only
+//! the index of the string and the binary-encoded arguments are sent over SEGGER RTT. So the sensor and SPI paths may
+//! be logged at high rates without adding the log strings to the firmware. Decode the logs on the host with
+//! `probe-run` or `defmt-print -e bin/targets/nrf52_my_sensor/app/apps/my_sensor_app/my_sensor_app.elf`.
+//! Log with the macros `defmt_trace!()`, `defmt_debug!()` and `defmt_info!()` in `util/macros.rs`, which compile to
+//! nothing when the feature is disabled. The linker script `hw/bsp/nrf52/defmt.ld` keeps the `.defmt` section.
+//! `defmt-rtt` has its own RTT control block, so `DEFMT_LOG` can't be enabled with `RTT_CONSOLE_SINK`.
+
+use defmt_rtt as _;  //  Link the RTT transport for defmt
+use crate::kernel::{ os, time };
+
+//  Timestamp each log with the milliseconds since startup.
+defmt::timestamp!("{=u32:ms}", time::ticks_to_ms(unsafe { os::os_time_get() }));
@@ -0,0 +1,218 @@
+//! On-target test runner for hardware-in-the-loop regression runs. Each test is a function
+//! `fn name() -> MynewtResult<()>` annotated with `#[device_test]` from the `macros` crate, which places a `DeviceTest`
+//! in the linker section `.device_tests` when the `device_test` feature of the app is enabled.
+//! `hw/bsp/nrf52/device_tests.ld` collects the section between `__device_tests_start` and `__device_tests_end`.
+//! ```
+//! #[device_test]
+//! fn flash_round_trip() -> MynewtResult<()> { ... }
+//! ```
+//! `start()` spawns the task `test`, which runs the tests when requested by `run()`, e.g. from the shell command
+//! `test run [filter]` in `apps/my_sensor_app/src/test_shell.c`, so that the tests run on their own stack and don't block the default event queue.
+//! The results are printed on the selected console sink, e.g. RTT or the UART, in the Test Anything Protocol (TAP),
+//! which test harnesses on the host parse without knowing the other console output:
+//! ```text
+//! 1..2
+//! ok 1 - app::device_tests::crc32_check # 0 ms
+//! not ok 2 - app::device_tests::flash_round_trip # SYS_EIO 35 ms
+//! # passed 1 failed 1
+//! ```
+//! A test that panics or faults restarts the device. The running test is recorded in RAM that is not cleared at
+//! startup, and `show_last()` reports it after restarting as `Bail out! <name> restarted the device`.
+
+use core::fmt::Write;
+use crate::{
+    result::*,
+    kernel::{
+        os,
+        sync::Semaphore,
+        task,
+        time::Instant,
+    },
+    sys::{ console, noinit::{ NoInit, NoInitValue } },
+    Strn, StrnRep,
+};
+
+/// Test registered by `#[device_test]`
+#[repr(C)]
+pub struct DeviceTest {
+    /// Module path and name of the test function, e.g. `app::device_tests::crc32_check`
+    pub name: &'static str,
+    /// Test function. Returns an error if the test fails.
+    pub func: fn() -> MynewtResult<()>,
+}
+
+/// Size of the stack of the test task, in 4-byte units
+const TEST_TASK_STACK_SIZE: usize = 512;
+
+/// Stack of the test task. Only linked into the firmware when `start()` is called.
+static mut TEST_TASK_STACK: [os::os_stack_t; TEST_TASK_STACK_SIZE] = [0; TEST_TASK_STACK_SIZE];
+
+/// Priority of the test task, lower than the main task so that the app keeps running during the tests
+const TEST_TASK_PRIO: u8 = 200;
+
+/// Name of the test task
+static TEST_TASK: Strn = Strn { rep: StrnRep::ByteStr(b"test\0") };
+
+/// Max length of the filter for the tests to run
+type MaxFilterSize = heapless::consts::U32;
+
+/// Given by `run()` to wake up the test task
+static RUN_REQUEST: Semaphore = Semaphore::new(0);
+
+/// Tests whose names contain the filter are run, all tests if empty
+static mut FILTER: heapless::String<MaxFilterSize> = heapless::String(heapless::i::String::new());
+
+/// True from `run()` until the test task has finished the tests
+static mut RUNNING: bool = false;
+
+/// Test that was running before the last restart
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct RunningTest {
+    /// Index of the test in `tests()`
+    index: u32,
+}
+
+unsafe impl NoInitValue for RunningTest {
+    const MAGIC: u32 = 0x5453_4554;  //  `TEST`
+}
+
+/// Test that is running, stored in the Mynewt section `.bss.core.nz` that is not cleared at startup
+#[link_section = ".bss.core.nz"]
+static mut RUNNING_TEST: NoInit<RunningTest> = NoInit::new(RunningTest { index: 0 });
+
+/// Return the tests registered in section `.device_tests`
+pub fn tests() -> &'static [DeviceTest] {
+    unsafe {
+        let start = &__device_tests_start as *const DeviceTest;
+        let end   = &__device_tests_end   as *const DeviceTest;
+        let count = (end as usize - start as usize) / core::mem::size_of::<DeviceTest>();
+        core::slice::from_raw_parts(start, count)
+    }
+}
+
+/// Spawn the test task, which waits for `run()`. Called by main() in `lib.rs` when the `device_test` feature is
+/// enabled.
+pub fn start() -> MynewtResult<()> {
+    task::spawn_with_stack(&TEST_TASK, TEST_TASK_PRIO, unsafe { &mut TEST_TASK_STACK }, test_task_func) ? ;
+    Ok(())
+}
+
+/// Run the tests whose names contain `filter`, or all tests if `filter` is empty, in the test task. Returns
+/// immediately. Returns `SYS_EBUSY` if the tests are running, or `SYS_EINVAL` if the filter is too long.
+pub fn run(filter: &str) -> MynewtResult<()> {
+    let mut new_filter = heapless::String::<MaxFilterSize>::new();
+    new_filter.push_str(filter).map_err(|_| MynewtError::SYS_EINVAL) ? ;
+    let busy = unsafe {
+        let sr = os::os_arch_save_sr();
+        let busy = RUNNING;
+        if !busy {
+            RUNNING = true;
+            FILTER = new_filter;
+        }
+        os::os_arch_restore_sr(sr);
+        busy
+    };
+    if busy { return Err(MynewtError::SYS_EBUSY); }
+    RUN_REQUEST.give()
+}
+
+/// Return `err` unless `condition` holds, for failing a test without restarting the device, unlike `assert!()`:
+/// `device_test::ensure(crc32(b"123456789") == 0xcbf4_3926, MynewtError::SYS_EUNKNOWN) ? ;`
+pub fn ensure(condition: bool, err: MynewtError) -> MynewtResult<()> {
+    if condition { Ok(()) } else { Err(err) }
+}
+
+/// Print the names of the registered tests
+pub fn list() {
+    for test in tests() {
+        console::buffer(test.name);
+        console::print("\n");
+    }
+    console::flush();
+}
+
+/// Report the test that restarted the device before the last restart, if any, and clear the record
+pub fn show_last() {
+    if let Some(running) = unsafe { RUNNING_TEST.take() } {
+        let name = tests().get(running.index as usize).map(|test| test.name).unwrap_or("?");
+        console::print("Bail out! "); console::buffer(name);
+        console::print(" restarted the device\n"); console::flush();
+    }
+}
+
+/// Shell command `test list`: Print the names of the registered tests
+#[no_mangle]
+extern "C" fn test_shell_list() -> i32 {
+    list();
+    0
+}
+
+/// Shell command `test run [filter]`: Run the tests whose names contain `filter`, or all tests if `filter` is NULL.
+/// Returns `SYS_EBUSY` if the tests are running.
+#[no_mangle]
+extern "C" fn test_shell_run(filter: *const u8) -> i32 {
+    let result =
+        if filter.is_null() { run("") }
+        else {
+            let filter = Strn::from_cstr(filter);
+            let bytes = unsafe { core::slice::from_raw_parts(filter.as_ptr(), filter.len()) };
+            core::str::from_utf8(bytes)
+                .map_err(|_| MynewtError::SYS_EINVAL)
+                .and_then(run)
+        };
+    match result {
+        Ok(())   => 0,
+        Err(err) => err.into(),
+    }
+}
+
+/// Run the tests when requested by `run()`, forever
+fn test_task_func() {
+    loop {
+        RUN_REQUEST.take_forever().expect("test sem fail");
+        run_tests(unsafe { &FILTER });
+        unsafe { RUNNING = false };
+    }
+}
+
+/// Run the tests whose names contain `filter` and report the results in TAP format
+fn run_tests(filter: &str) {
+    let selected = || tests().iter().enumerate().filter(move |(_, test)| test.name.contains(filter));
+    let mut line = heapless::String::<heapless::consts::U128>::new();
+    write!(line, "1..{}\n", selected().count()).ok();
+    console::print(&line);
+    console::flush();
+    let (mut passed, mut failed) = (0, 0);
+    for (number, (index, test)) in selected().enumerate() {
+        unsafe { RUNNING_TEST.save(RunningTest { index: index as u32 }) };
+        let start = Instant::now();
+        let result = (test.func)();
+        let elapsed = start.elapsed().as_millis() as u32;
+        unsafe { RUNNING_TEST.clear() };
+        line.clear();
+        match result {
+            Ok(()) => {
+                passed += 1;
+                write!(line, "ok {} - {} # {} ms\n", number + 1, test.name, elapsed).ok();
+            }
+            Err(err) => {
+                failed += 1;
+                write!(line, "not ok {} - {} # {:?} {} ms\n", number + 1, test.name, err, elapsed).ok();
+            }
+        }
+        console::print(&line);  //  Truncated if too long
+        console::flush();
+    }
+    line.clear();
+    write!(line, "# passed {} failed {}\n", passed, failed).ok();
+    console::print(&line);
+    console::flush();
+}
+
+extern "C" {
+    /// Start of section `.device_tests`, defined in `hw/bsp/nrf52/device_tests.ld`
+    static __device_tests_start: DeviceTest;
+    /// End of section `.device_tests`, defined in `hw/bsp/nrf52/device_tests.ld`
+    static __device_tests_end: DeviceTest;
+}
@@ -0,0 +1,90 @@
+//! Init hooks for Rust modules, called during Mynewt `sysinit()`. Each module registers its init function with
+//! `init_hook!()`, which places an `InitHook` in the linker section `.rust_init`. `hw/bsp/nrf52/rust_init.ld`
+//! collects the section between `__rust_init_start` and `__rust_init_end`. `sysinit()` calls `rust_init_hooks()`
+//! at Mynewt stage 900 (see `libs/mynewt_rust/pkg.yml`), after the Mynewt drivers have started, which calls the hooks
+//! in order of their stage number, lowest first. Hooks with the same stage are called in link order.
+//! ```
+//! fn start_display() -> MynewtResult<()> { ... }
+//! init_hook!(STAGE_DISPLAY, DISPLAY_HOOK, start_display);
+//! ```
+
+use crate::{
+    result::*,
+    sys::console,
+};
+
+/// Stage for flash drivers
+pub const STAGE_FLASH: u16 = 100;
+
+/// Stage for the display, after the flash since they share the SPI port
+pub const STAGE_DISPLAY: u16 = 200;
+
+/// Stage for input devices like the touch controller
+pub const STAGE_INPUT: u16 = 300;
+
+/// Stage for application services
+pub const STAGE_APP: u16 = 500;
+
+/// Init function registered by `init_hook!()`
+#[repr(C)]
+pub struct InitHook {
+    /// Hooks with lower stages are called first
+    pub stage: u16,
+    /// Name of the hook, for displaying errors
+    pub name: &'static str,
+    /// Init function
+    pub func: fn() -> MynewtResult<()>,
+}
+
+/// Register `func` to be called during `sysinit()` at `stage`. `name` is the name of the static `InitHook`.
+#[macro_export]
+macro_rules! init_hook {
+    ($stage:expr, $name:ident, $func:path) => {
+        #[used]
+        #[link_section = ".rust_init"]
+        static $name: $crate::sys::init::InitHook = $crate::sys::init::InitHook {
+            stage: $stage,
+            name:  stringify!($name),
+            func:  $func,
+        };
+    };
+}
+
+/// Return the hooks registered in section `.rust_init`
+fn hooks() -> &'static [InitHook] {
+    unsafe {
+        let start = &__rust_init_start as *const InitHook;
+        let end   = &__rust_init_end   as *const InitHook;
+        let count = (end as usize - start as usize) / core::mem::size_of::<InitHook>();
+        core::slice::from_raw_parts(start, count)
+    }
+}
+
+/// Call the registered hooks in order of stage. Called by Mynewt `sysinit()`. Panics if any hook fails.
+#[no_mangle]
+extern "C" fn rust_init_hooks() {
+    let hooks = hooks();
+    let mut stage: u32 = 0;  //  Call the hooks with the lowest stage that is at least `stage`
+    loop {
+        let next = hooks.iter()
+            .map(|hook| hook.stage as u32)
+            .filter(|s| *s >= stage)
+            .min();
+        let next = match next { Some(next) => next, None => break };
+        for hook in hooks.iter().filter(|hook| hook.stage as u32 == next) {
+            if let Err(err) = (hook.func)() {
+                console::print("init fail: "); console::buffer(hook.name);
+                console::print("\n"); console::flush();
+                panic!("init fail {:?}", err);
+            }
+        }
+        stage = next + 1;
+    }
+}
+
+extern "C" {
+    /// Start of section `.rust_init`, defined in `hw/bsp/nrf52/rust_init.ld`
+    static __rust_init_start: InitHook;
+    /// End of section `.rust_init`, defined in `hw/bsp/nrf52/rust_init.ld`
+    static __rust_init_end: InitHook;
+}
@@ -0,0 +1,306 @@
+//! Logger for the `log` crate, backed by Mynewt `sys/log`. Messages from `info!()`, `warn!()`, ... are appended as
+//! text entries to the Mynewt log module `LOG_MODULE_RUST` with `modlog_append()`. Mynewt timestamps each entry and
+//! writes it to the logs that are mapped to the module: the console log by default, and the flash log if configured.
+//! Each message is prefixed by the target, which is the Rust module path unless given with `target:`.
+//! ```
+//! logger::init(log::LevelFilter::Info) ? ;
+//! log::info!("logo slot {} selected", slot);
+//! log::warn!(target: "ble", "upload aborted");
+//! ```
+//! The messages are filtered in three ways:
+//! - At compile time by the `max_level_*` and `release_max_level_*` features of the `log` crate, which remove the
+//!   messages above the level from the firmware, e.g. `log_max_info` in `rust/app/Cargo.toml`.
+//! - At runtime by the default level from `init()` or `set_level()`.
+//! - At runtime by the per-module levels from `set_filters()`, e.g. `app::power=debug,mynewt::spi=warn`, which
+//!   override the default level for the targets that start with the module path.
+//!
+//! `log_kv()` and the macro `log_kv!()` log a structured entry with key-value pairs, which is appended to the Mynewt
+//! log as a CBOR map instead of text, so that the log entries uploaded by `newtmgr log show` may be decoded and
+//! searched on the server without parsing the messages.
+//! ```
+//! log_kv!(log::Level::Warn, "battery low"; percent = 12, mv = 3420, charging = false);
+//! ```
+//! The entry is encoded as the map `{"tgt": "app::power", "msg": "battery low", "percent": 12, "mv": 3420,
+//! "charging": false}`, so the keys `tgt` and `msg` are reserved.
+
+use core::fmt::Write;
+use log::{ Level, LevelFilter, Log, Metadata, Record };
+use crate::result::*;
+
+/// Mynewt log module for Rust messages. Modules from `LOG_MODULE_PERUSER` (64) onwards are for applications.
+pub const LOG_MODULE_RUST: u8 = 64;
+
+/// Max length of a log message, including the target. Longer messages are truncated.
+type MaxMessageSize = heapless::consts::U128;
+
+/// Max length of a structured entry, encoded as CBOR. Longer entries are logged as text without the pairs.
+type MaxEntrySize = heapless::consts::U128;
+
+/// Max length of the module path in a filter
+type MaxTargetSize = heapless::consts::U32;
+
+/// Max number of per-module filters. Must match `MaxFilters`.
+pub const MAX_FILTERS: usize = 8;
+type MaxFilters = heapless::consts::U8;
+
+/// Mynewt log entry type for text. From `sys/log/full/include/log/log.h`
+const LOG_ETYPE_STRING: u8 = 0;
+
+/// Mynewt log entry type for CBOR. From `sys/log/full/include/log/log.h`
+const LOG_ETYPE_CBOR: u8 = 1;
+
+/// Level of the messages whose targets don't match a filter
+static mut DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+/// Per-module levels from `set_filters()`
+static mut FILTERS: heapless::Vec<Filter, MaxFilters> = heapless::Vec(heapless::i::Vec::new());
+
+/// Level of the messages from a module and its submodules
+struct Filter {
+    /// Module path, e.g. `app::power`
+    target: heapless::String<MaxTargetSize>,
+    /// Level of the messages from the module
+    level: LevelFilter,
+}
+
+/// Value of a key-value pair in a structured entry. Created from integers, `bool` and `&str` with `Value::from()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Value<'a> {
+    /// Signed integer
+    Int(i64),
+    /// Unsigned integer
+    Uint(u64),
+    /// True or false
+    Bool(bool),
+    /// Text
+    Str(&'a str),
+}
+
+/// The logger registered with the `log` crate
+static LOGGER: MynewtLogger = MynewtLogger;
+
+/// Logger that appends messages to the Mynewt log
+struct MynewtLogger;
+
+impl Log for MynewtLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= filter_level(metadata.target())
+    }
+
+    /// Append the message to the Mynewt log, prefixed by the target
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) { return; }
+        let mut msg: heapless::String<MaxMessageSize> = heapless::String::new();
+        //  If the message is too long, log the part that fits.
+        write!(&mut msg, "{}: {}", record.target(), record.args()).ok();
+        append(record.level(), LOG_ETYPE_STRING, msg.as_bytes());
+    }
+
+    fn flush(&self) {}
+}
+
+/// Register the logger with the `log` crate and log messages up to `level`, e.g. `LevelFilter::Info`.
+/// Returns `SYS_EALREADY` if a logger has already been registered.
+pub fn init(level: LevelFilter) -> MynewtResult<()> {
+    log::set_logger(&LOGGER).map_err(|_| MynewtError::SYS_EALREADY) ? ;
+    set_level(level)
+}
+
+/// Log messages up to `level`, e.g. `LevelFilter::Debug` while debugging, from the modules that don't match a filter
+pub fn set_level(level: LevelFilter) -> MynewtResult<()> {
+    unsafe { DEFAULT_LEVEL = level };
+    update_max_level()
+}
+
+/// Replace the per-module filters by the comma-separated `spec`, e.g. `app::power=debug,mynewt::spi=warn`.
+/// An entry without a module path, e.g. `warn`, sets the default level. An empty `spec` removes the filters.
+/// The levels are `off`, `error`, `warn`, `info`, `debug` and `trace`. Returns `SYS_EINVAL` if a level is invalid,
+/// or `SYS_ENOMEM` if there are more than `MAX_FILTERS` filters or a module path is too long, in which case the
+/// filters are unchanged. Must be called at startup, before the other tasks log.
+pub fn set_filters(spec: &str) -> MynewtResult<()> {
+    let mut filters = heapless::Vec::<Filter, MaxFilters>::new();
+    let mut default_level = unsafe { DEFAULT_LEVEL };
+    for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+        let (target, level) = match entry.rfind('=') {
+            Some(pos) => (entry[..pos].trim(), entry[pos + 1..].trim()),
+            None => ("", entry),
+        };
+        let level: LevelFilter = level.parse().map_err(|_| MynewtError::SYS_EINVAL) ? ;
+        if target.is_empty() { default_level = level; continue; }
+        let mut filter = Filter { target: heapless::String::new(), level };
+        filter.target.push_str(target).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+        filters.push(filter).map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    }
+    unsafe {
+        FILTERS = filters;
+        DEFAULT_LEVEL = default_level;
+    }
+    update_max_level()
+}
+
+/// Return the level of the messages from `target`: the level of the longest module path that matches the target,
+/// or the default level if none
+pub fn filter_level(target: &str) -> LevelFilter {
+    let filters = unsafe { &FILTERS };
+    filters.iter()
+        .filter(|filter| is_submodule(target, &filter.target))
+        .max_by_key(|filter| filter.target.len())
+        .map(|filter| filter.level)
+        .unwrap_or(unsafe { DEFAULT_LEVEL })
+}
+
+/// Append a structured entry with the message `msg` and the key-value `pairs` to the Mynewt log, encoded as a CBOR
+/// map. The entry is filtered like the messages from `target`. If the entry is longer than `MaxEntrySize`, the
+/// message is logged as text without the pairs. Usually called by the macro `log_kv!()`.
+pub fn log_kv(level: Level, target: &str, msg: &str, pairs: &[(&str, Value)]) {
+    if level > log::STATIC_MAX_LEVEL || level > filter_level(target) { return; }
+    let mut entry = CborWriter::new();
+    if entry.entry(target, msg, pairs).is_err() {
+        //  Too long for the entry, log the part of the message that fits.
+        let mut text: heapless::String<MaxMessageSize> = heapless::String::new();
+        write!(&mut text, "{}: {}", target, msg).ok();
+        append(level, LOG_ETYPE_STRING, text.as_bytes());
+        return;
+    }
+    append(level, LOG_ETYPE_CBOR, &entry.buf);
+}
+
+/// Set the max level of the `log` crate and the Mynewt log module to the most verbose level of the default level
+/// and the filters
+fn update_max_level() -> MynewtResult<()> {
+    let filters = unsafe { &FILTERS };
+    let level = filters.iter()
+        .map(|filter| filter.level)
+        .fold(unsafe { DEFAULT_LEVEL }, core::cmp::max);
+    log::set_max_level(level);
+    let min_level = match level.to_level() {
+        Some(level) => to_mynewt_level(level),
+        None => LOG_LEVEL_NONE,  //  `LevelFilter::Off`
+    };
+    check(unsafe { log_level_set(LOG_MODULE_RUST, min_level) })
+}
+
+/// Return true if `target` is the module `module` or one of its submodules, e.g. `app::power::gauge` in `app::power`
+fn is_submodule(target: &str, module: &str) -> bool {
+    target.starts_with(module) && (target.len() == module.len() || target[module.len()..].starts_with("::"))
+}
+
+/// Append the entry of type `etype` to the logs mapped to the Mynewt log module for Rust
+fn append(level: Level, etype: u8, data: &[u8]) {
+    unsafe {
+        modlog_append(LOG_MODULE_RUST, to_mynewt_level(level), etype,
+            data.as_ptr() as *const ::cty::c_void, data.len() as u16)
+    };
+}
+
+/// Mynewt log level for disabling the module
+const LOG_LEVEL_NONE: u8 = 255;
+
+/// Convert the `log` level to a Mynewt log level. From `sys/log/common/include/log_common/log_common.h`
+fn to_mynewt_level(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,  //  LOG_LEVEL_ERROR
+        Level::Warn  => 2,  //  LOG_LEVEL_WARN
+        Level::Info  => 1,  //  LOG_LEVEL_INFO
+        Level::Debug | Level::Trace => 0,  //  LOG_LEVEL_DEBUG
+    }
+}
+
+/// Implement `From` for the integer types, converted to the `Value` variant
+macro_rules! value_from {
+    ($variant:ident, $cast:ty, $($ty:ty),+) => {
+        $( impl From<$ty> for Value<'_> {
+            fn from(value: $ty) -> Self { Value::$variant(value as $cast) }
+        } )+
+    };
+}
+value_from!(Int, i64, i8, i16, i32, i64, isize);
+value_from!(Uint, u64, u8, u16, u32, u64, usize);
+
+impl From<bool> for Value<'_> {
+    fn from(value: bool) -> Self { Value::Bool(value) }
+}
+
+impl<'a> From<&'a str> for Value<'a> {
+    fn from(value: &'a str) -> Self { Value::Str(value) }
+}
+
+/// Encoder for the CBOR map of a structured entry. Only encodes the types in `Value`. See RFC 7049.
+struct CborWriter {
+    /// Encoded entry
+    buf: heapless::Vec<u8, MaxEntrySize>,
+}
+
+impl CborWriter {
+    /// Create an empty entry
+    fn new() -> Self {
+        CborWriter { buf: heapless::Vec::new() }
+    }
+
+    /// Append the map of a structured entry from `target` with the message `msg` and the key-value `pairs`
+    fn entry(&mut self, target: &str, msg: &str, pairs: &[(&str, Value)]) -> MynewtResult<()> {
+        self.map(2 + pairs.len()) ? ;
+        self.text("tgt") ? ;
+        self.text(target) ? ;
+        self.text("msg") ? ;
+        self.text(msg) ? ;
+        for (key, value) in pairs {
+            self.text(key) ? ;
+            self.value(value) ? ;
+        }
+        Ok(())
+    }
+
+    /// Append the header of a map with `len` pairs
+    fn map(&mut self, len: usize) -> MynewtResult<()> {
+        self.head(5, len as u64)
+    }
+
+    /// Append a text string
+    fn text(&mut self, text: &str) -> MynewtResult<()> {
+        self.head(3, text.len() as u64) ? ;
+        self.push(text.as_bytes())
+    }
+
+    /// Append the value of a pair
+    fn value(&mut self, value: &Value) -> MynewtResult<()> {
+        match *value {
+            Value::Int(int) if int < 0 => self.head(1, !int as u64),  //  Encoded as -1 - int
+            Value::Int(int)   => self.head(0, int as u64),
+            Value::Uint(uint) => self.head(0, uint),
+            Value::Bool(flag) => self.push(&[ if flag { 0xf5 } else { 0xf4 } ]),  //  True or false
+            Value::Str(text)  => self.text(text),
+        }
+    }
+
+    /// Append the header with the major type `major` and the argument `arg` in the shortest form
+    fn head(&mut self, major: u8, arg: u64) -> MynewtResult<()> {
+        let major = major << 5;
+        if arg < 24 {
+            self.push(&[ major | arg as u8 ])
+        } else if arg <= 0xff {
+            self.push(&[ major | 24, arg as u8 ])
+        } else if arg <= 0xffff {
+            self.push(&[ major | 25 ]) ? ;
+            self.push(&(arg as u16).to_be_bytes())
+        } else if arg <= 0xffff_ffff {
+            self.push(&[ major | 26 ]) ? ;
+            self.push(&(arg as u32).to_be_bytes())
+        } else {
+            self.push(&[ major | 27 ]) ? ;
+            self.push(&arg.to_be_bytes())
+        }
+    }
+
+    /// Append the bytes. Returns `SYS_ENOMEM` if the entry is full.
+    fn push(&mut self, bytes: &[u8]) -> MynewtResult<()> {
+        self.buf.extend_from_slice(bytes).map_err(|_| MynewtError::SYS_ENOMEM)
+    }
+}
+
+extern "C" {
+    /// Append an entry to the logs mapped to `module`. C API: `int modlog_append(uint8_t module, uint8_t level, uint8_t etype, const void *data, uint16_t len)`
+    fn modlog_append(module: u8, level: u8, etype: u8, data: *const ::cty::c_void, len: u16) -> ::cty::c_int;
+    /// Set the minimum level of entries to be logged for `module`. C API: `int log_level_set(uint8_t module, uint8_t level)`
+    fn log_level_set(module: u8, level: u8) -> ::cty::c_int;
+}
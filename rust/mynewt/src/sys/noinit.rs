@@ -0,0 +1,132 @@
+//! Records that survive a restart, kept in the Mynewt section `.bss.core.nz` that is not cleared at startup. After
+//! power on, the section holds random bits, so a record is valid only if it has the magic number of its type and its
+//! CRC32 checksum matches. Lengths stored in a record must be clamped before use, e.g. with `text()`.
+//! ```
+//! #[derive(Clone, Copy)]
+//! #[repr(C)]
+//! struct LastReason { code: u32 }
+//! unsafe impl NoInitValue for LastReason { const MAGIC: u32 = 0x4e53_4552; }  //  `RESN`
+//!
+//! #[link_section = ".bss.core.nz"]
+//! static mut LAST_REASON: NoInit<LastReason> = NoInit::new(LastReason { code: 0 });
+//! unsafe { LAST_REASON.save(LastReason { code }) };
+//! if let Some(reason) = unsafe { LAST_REASON.take() } { ... }
+//! ```
+
+use crate::util::crc::Crc32;
+
+/// Max number of bytes of a task name in `Name`
+pub const NAME_SIZE: usize = 16;
+
+/// Value that may be saved in a `NoInit` record. The value must have no padding bytes, and any bit pattern must be a
+/// valid value, e.g. integers and byte arrays but not `bool`, `char` or enums, since the record is read back from RAM
+/// that may hold random bits.
+pub unsafe trait NoInitValue: Copy {
+    /// Magic number that marks a record of this type. Each type must have a different magic number.
+    const MAGIC: u32;
+}
+
+/// Record that survives a restart, marked by the magic number of `T` and checked with a CRC32 of the magic number and
+/// the value
+#[repr(C)]
+pub struct NoInit<T> {
+    /// `T::MAGIC` if the record was saved
+    magic: u32,
+    /// CRC32 of `magic` and `value`
+    checksum: u32,
+    /// Value of the record
+    value: T,
+}
+
+impl<T> NoInit<T> {
+    /// Create a record for a `static` in section `.bss.core.nz`. `value` is never stored, since the section is not
+    /// initialised at startup.
+    pub const fn new(value: T) -> Self {
+        NoInit { magic: 0, checksum: 0, value }
+    }
+}
+
+impl<T: NoInitValue> NoInit<T> {
+    /// Save `value` in the record, to be taken after the restart
+    pub fn save(&mut self, value: T) {
+        self.value = value;
+        self.magic = T::MAGIC;
+        self.checksum = self.compute_checksum();
+    }
+
+    /// Return true if the record has been saved and not taken or cleared
+    pub fn is_valid(&self) -> bool {
+        self.magic == T::MAGIC && self.checksum == self.compute_checksum()
+    }
+
+    /// Return the value saved before the last restart, and clear the record. Returns `None` if the record is not
+    /// valid.
+    pub fn take(&mut self) -> Option<T> {
+        if !self.is_valid() { return None; }
+        self.clear();
+        Some(self.value)
+    }
+
+    /// Clear the record, so that it is no longer valid
+    pub fn clear(&mut self) {
+        self.magic = 0;
+    }
+
+    /// Return the CRC32 of the magic number and the bytes of the value
+    fn compute_checksum(&self) -> u32 {
+        let value = unsafe {
+            core::slice::from_raw_parts(&self.value as *const T as *const u8, core::mem::size_of::<T>())
+        };
+        let mut crc = Crc32::new();
+        crc.update(&self.magic.to_le_bytes());
+        crc.update(value);
+        crc.finish()
+    }
+}
+
+/// Task name, truncated to `NAME_SIZE` bytes, for saving in a `NoInit` record
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Name {
+    /// Number of bytes used in `bytes`
+    len: u32,
+    /// Start of the name
+    bytes: [u8; NAME_SIZE],
+}
+
+impl Name {
+    /// Return an empty name, for the initial value of a `NoInit` record
+    pub const fn empty() -> Self {
+        Name { len: 0, bytes: [0; NAME_SIZE] }
+    }
+
+    /// Return the start of `name` that fits in `NAME_SIZE` bytes
+    pub fn new(name: &str) -> Self {
+        let mut bytes = [0; NAME_SIZE];
+        let len = copy_text(&mut bytes, name);
+        Name { len: len as u32, bytes }
+    }
+
+    /// Return the name. The length is clamped, in case the record was corrupted.
+    pub fn as_str(&self) -> &str {
+        text(&self.bytes, self.len as usize)
+    }
+}
+
+/// Copy the start of `text` that fits into `buf`, without splitting a character. Returns the number of bytes copied.
+pub fn copy_text(buf: &mut [u8], text: &str) -> usize {
+    let mut len = core::cmp::min(text.len(), buf.len());
+    while !text.is_char_boundary(len) { len -= 1; }
+    buf[..len].copy_from_slice(&text.as_bytes()[..len]);
+    len
+}
+
+/// Return the text in the first `len` bytes of `bytes`. `len` is clamped to the size of `bytes`, and the text is cut
+/// at the first invalid UTF-8 byte, since a record may be corrupted.
+pub fn text(bytes: &[u8], len: usize) -> &str {
+    let bytes = &bytes[..core::cmp::min(len, bytes.len())];
+    match core::str::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(err) => core::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap_or(""),
+    }
+}
@@ -0,0 +1,161 @@
+//! Panic records for Rust applications. The panic handler calls `record()` to save the file, line and message of the panic
+//! in RAM that is not cleared at startup, then `reset()` to restart the device. After restarting, `show_last()` displays
+//! the panic that caused the restart, so that panics in the field are not silent hangs.
+
+use core::{
+    fmt::{ self, Write },
+    panic::PanicInfo,
+};
+use crate::sys::{ console, crash_dump::{ self, CrashReason }, noinit::{ self, NoInit, NoInitValue } };
+
+/// Max number of bytes of the source file name to be recorded. Longer names keep the end of the name.
+pub const PANIC_FILE_SIZE: usize = 48;
+
+/// Max number of bytes of the panic message to be recorded. Longer messages are truncated.
+pub const PANIC_MESSAGE_SIZE: usize = 64;
+
+/// Max number of bytes of the source file name in the crash dump, which keeps the end of the name
+const DUMP_FILE_SIZE: usize = 20;
+
+/// Panic that was recorded before the last restart
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PanicRecord {
+    /// Line number of the panic, or 0 if unknown
+    pub line: u32,
+    /// Number of bytes used in `file`
+    file_len: u16,
+    /// Number of bytes used in `message`
+    message_len: u16,
+    /// End of the source file name
+    file: [u8; PANIC_FILE_SIZE],
+    /// Start of the panic message
+    message: [u8; PANIC_MESSAGE_SIZE],
+}
+
+impl PanicRecord {
+    /// Return an empty record
+    const fn new() -> Self {
+        PanicRecord {
+            line:        0,
+            file_len:    0,
+            message_len: 0,
+            file:        [0; PANIC_FILE_SIZE],
+            message:     [0; PANIC_MESSAGE_SIZE],
+        }
+    }
+
+    /// Return the source file name of the panic. May be truncated.
+    pub fn file(&self) -> &str {
+        noinit::text(&self.file, self.file_len as usize)
+    }
+
+    /// Return the panic message. May be truncated.
+    pub fn message(&self) -> &str {
+        noinit::text(&self.message, self.message_len as usize)
+    }
+}
+
+unsafe impl NoInitValue for PanicRecord {
+    const MAGIC: u32 = 0x434e_4150;  //  `PANC`
+}
+
+/// Panic record, stored in the Mynewt section `.bss.core.nz` that is not cleared at startup
+#[link_section = ".bss.core.nz"]
+static mut PANIC_RECORD: NoInit<PanicRecord> = NoInit::new(PanicRecord::new());
+
+/// True if we are handling a panic, to stop recursive panics
+static mut PANICKING: bool = false;
+
+/// Display the panic described by `info` on the console and save it in the panic record.
+/// Returns false if we are already handling a panic, i.e. the panic handler has panicked.
+pub fn record(info: &PanicInfo) -> bool {
+    unsafe {
+        if PANICKING { return false; }
+        PANICKING = true;
+    }
+    let mut record = PanicRecord::new();
+
+    //  Display the filename and line number.
+    console::print("panic ");
+    if let Some(location) = info.location() {
+        let file = location.file().as_bytes();
+        let file = &file[file.len().saturating_sub(PANIC_FILE_SIZE)..];  //  Keep the end of the path
+        record.file[..file.len()].copy_from_slice(file);
+        record.file_len = file.len() as u16;
+        record.line = location.line();
+        console::print("at ");       console::buffer(record.file());
+        console::print(" line ");    console::printint(record.line as i32);
+        console::print("\n");
+    } else {
+        console::print("no loc\n");
+    }
+
+    //  Display the message.
+    let mut writer = Truncate { buf: &mut record.message, len: 0 };
+    write!(writer, "{}", info.message()).ok();
+    record.message_len = writer.len as u16;
+    console::buffer(record.message());
+    console::print("\n");  console::flush();
+
+    unsafe { PANIC_RECORD.save(record) };
+
+    //  Write the crash dump with the end of the file name, the line and the start of the message.
+    let mut detail = [0; crash_dump::DUMP_DETAIL_SIZE];
+    let mut writer = Truncate { buf: &mut detail, len: 0 };
+    let file = record.file();
+    let file = file.get(file.len().saturating_sub(DUMP_FILE_SIZE)..).unwrap_or(file);  //  Don't split a character
+    write!(writer, "{}:{} {}", file, record.line, record.message()).ok();
+    let len = writer.len;
+    crash_dump::record(CrashReason::Panic, 0, 0, None, noinit::text(&detail, len)).ok();
+    true
+}
+
+/// Restart the device
+pub fn reset() -> ! {
+    console::flush();
+    unsafe { hal_system_reset() };
+}
+
+/// Return true if a panic was recorded before the last restart and has not been taken
+pub fn has_last() -> bool {
+    unsafe { PANIC_RECORD.is_valid() }
+}
+
+/// Return the panic that was recorded before the last restart, and clear the record. Returns `None` if there was no panic.
+pub fn take_last() -> Option<PanicRecord> {
+    unsafe { PANIC_RECORD.take() }
+}
+
+/// Display the panic that was recorded before the last restart, and clear the record
+pub fn show_last() {
+    if let Some(record) = take_last() {
+        console::print("last panic at ");  console::buffer(record.file());
+        console::print(" line ");          console::printint(record.line as i32);
+        console::print(": ");              console::buffer(record.message());
+        console::print("\n");              console::flush();
+    }
+}
+
+/// Formatter that writes into a fixed buffer and drops the text that doesn't fit
+struct Truncate<'a> {
+    /// Buffer for the text
+    buf: &'a mut [u8],
+    /// Number of bytes written
+    len: usize,
+}
+
+impl<'a> Write for Truncate<'a> {
+    /// Append `s` to the buffer, truncated to the space left
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let count = core::cmp::min(s.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        Ok(())
+    }
+}
+
+extern "C" {
+    /// Restart the device. C API: `void hal_system_reset(void)`
+    fn hal_system_reset() -> !;
+}
@@ -0,0 +1,178 @@
+//! Power profiling: estimate the charge drawn from the battery by each high-power subsystem, for tuning the battery
+//! life. The drivers mark the start and end of each activity with `begin()` and `end()`, e.g. the display switched
+//! on or a flash erase, or record an activity of known duration with `add()`, e.g. the airtime of a Bluetooth LE
+//! packet. The active time is multiplied by the typical current of the subsystem in `Subsystem::current_ua()`, taken
+//! from the datasheets, so the charge is an estimate, not a measurement. The radio only counts the data sent by the
+//! firmware, not the advertising and the empty packets of the connection events. `begin()` and `end()` measure in
+//! OS ticks of 1 millisecond, so they suit the activities that last several milliseconds or more.
+//! ```
+//! power_profile::begin(Subsystem::Flash);
+//! let rc = unsafe { hal_flash_erase(flash_id, offset, len) };
+//! power_profile::end(Subsystem::Flash);
+//! ```
+//! `usage()` returns the totals since startup or `reset()`, and `show()` displays them on the console.
+
+use core::time::Duration;
+use crate::{
+    kernel::{ os, time::Instant },
+    sys::console,
+};
+
+/// Number of subsystems in `Subsystem`
+pub const NUM_SUBSYSTEMS: usize = 4;
+
+/// High-power subsystem of the watch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Subsystem {
+    /// Bluetooth LE radio transmitting
+    Radio = 0,
+    /// External SPI Flash erasing or programming
+    Flash = 1,
+    /// Display controller and backlight switched on
+    Display = 2,
+    /// Vibration motor running
+    Haptic = 3,
+}
+
+/// Time to send a byte over the air at the 1M PHY, in microseconds
+const RADIO_US_PER_BYTE: u64 = 8;
+
+/// Time for each link layer packet besides the data, in microseconds: radio ramp-up, preamble, access address,
+/// link layer, L2CAP and ATT headers, and CRC
+const RADIO_PACKET_US: u64 = 140 + 17 * RADIO_US_PER_BYTE;
+
+/// Max data in each link layer packet, with the Data Length Extension
+const RADIO_MAX_PACKET: usize = 244;
+
+/// All subsystems, in the order of `Subsystem`
+pub const SUBSYSTEMS: [Subsystem; NUM_SUBSYSTEMS] =
+    [ Subsystem::Radio, Subsystem::Flash, Subsystem::Display, Subsystem::Haptic ];
+
+impl Subsystem {
+    /// Return the typical current drawn while the subsystem is active, in microamps
+    pub fn current_ua(self) -> u32 {
+        match self {
+            Subsystem::Radio   =>  7_000,  //  nRF52832 TX at 0 dBm with the DC/DC converter off
+            Subsystem::Flash   => 15_000,  //  SPI NOR Flash erase or page program
+            Subsystem::Display => 12_000,  //  ST7789 with the backlight at high brightness
+            Subsystem::Haptic  => 60_000,  //  Vibration motor
+        }
+    }
+
+    /// Return the short name of the subsystem, e.g. for the console and the CoAP keys
+    pub fn name(self) -> &'static str {
+        match self {
+            Subsystem::Radio   => "radio",
+            Subsystem::Flash   => "flash",
+            Subsystem::Display => "display",
+            Subsystem::Haptic  => "haptic",
+        }
+    }
+}
+
+/// Usage of a subsystem, returned by `usage()`
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    /// Number of activities
+    pub count: u32,
+    /// Total active time in microseconds
+    pub active_us: u64,
+}
+
+impl Usage {
+    /// Return the total active time
+    pub fn active(&self) -> Duration {
+        Duration::from_micros(self.active_us)
+    }
+
+    /// Return the estimated charge drawn by the `subsystem`, in microamp-hours
+    pub fn charge_uah(&self, subsystem: Subsystem) -> u32 {
+        (self.active_us * subsystem.current_ua() as u64 / 3_600_000_000) as u32
+    }
+}
+
+/// Totals since startup or `reset()`
+static mut USAGE: [Usage; NUM_SUBSYSTEMS] = [Usage { count: 0, active_us: 0 }; NUM_SUBSYSTEMS];
+
+/// Start time of the activity in progress for each subsystem, `None` if inactive
+static mut STARTED: [Option<Instant>; NUM_SUBSYSTEMS] = [None; NUM_SUBSYSTEMS];
+
+/// Mark the start of an activity of the `subsystem`. Does nothing if an activity is in progress. May be called by
+/// any task.
+pub fn begin(subsystem: Subsystem) {
+    let now = Instant::now();
+    let sr = unsafe { os::os_arch_save_sr() };
+    let started = unsafe { &mut STARTED[subsystem as usize] };
+    if started.is_none() { *started = Some(now); }
+    unsafe { os::os_arch_restore_sr(sr) };
+}
+
+/// Mark the end of the activity of the `subsystem` started by `begin()`. Does nothing if no activity is in progress.
+/// May be called by any task.
+pub fn end(subsystem: Subsystem) {
+    let now = Instant::now();
+    let sr = unsafe { os::os_arch_save_sr() };
+    if let Some(start) = unsafe { STARTED[subsystem as usize].take() } {
+        record(subsystem, now.duration_since(start));
+    }
+    unsafe { os::os_arch_restore_sr(sr) };
+}
+
+/// Record an activity of the `subsystem` that lasted `duration`, e.g. a packet sent by the radio. May be called by
+/// any task.
+pub fn add(subsystem: Subsystem, duration: Duration) {
+    let sr = unsafe { os::os_arch_save_sr() };
+    record(subsystem, duration);
+    unsafe { os::os_arch_restore_sr(sr) };
+}
+
+/// Record the airtime of `len` bytes sent by the radio, split into link layer packets. May be called by any task.
+pub fn add_radio(len: usize) {
+    let packets = (len / RADIO_MAX_PACKET + 1) as u64;
+    add(Subsystem::Radio, Duration::from_micros(packets * RADIO_PACKET_US + len as u64 * RADIO_US_PER_BYTE));
+}
+
+/// Return the usage of the `subsystem` since startup or `reset()`, including the activity in progress
+pub fn usage(subsystem: Subsystem) -> Usage {
+    let now = Instant::now();
+    let sr = unsafe { os::os_arch_save_sr() };
+    let mut usage = unsafe { USAGE[subsystem as usize] };
+    if let Some(start) = unsafe { STARTED[subsystem as usize] } {
+        usage.active_us += now.duration_since(start).as_micros() as u64;
+    }
+    unsafe { os::os_arch_restore_sr(sr) };
+    usage
+}
+
+/// Clear the totals of all subsystems. The activities in progress are counted from now.
+pub fn reset() {
+    let now = Instant::now();
+    let sr = unsafe { os::os_arch_save_sr() };
+    unsafe {
+        for (usage, started) in USAGE.iter_mut().zip(STARTED.iter_mut()) {
+            *usage = Usage::default();
+            if started.is_some() { *started = Some(now); }
+        }
+    }
+    unsafe { os::os_arch_restore_sr(sr) };
+}
+
+/// Display the usage and the estimated charge of all subsystems on the console
+pub fn show() {
+    for subsystem in SUBSYSTEMS.iter() {
+        let usage = usage(*subsystem);
+        console::print("power ");     console::buffer(subsystem.name());
+        console::print(": count ");   console::printint(usage.count as i32);
+        console::print(", active ");  console::printint((usage.active_us / 1000) as i32);
+        console::print(" ms, ");      console::printint(usage.charge_uah(*subsystem) as i32);
+        console::print(" uAh\n");
+    }
+    console::flush();
+}
+
+/// Add the activity to the totals. Called with interrupts disabled.
+fn record(subsystem: Subsystem, duration: Duration) {
+    let usage = unsafe { &mut USAGE[subsystem as usize] };
+    usage.count += 1;
+    usage.active_us += duration.as_micros() as u64;
+}
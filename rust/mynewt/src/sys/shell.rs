@@ -0,0 +1,240 @@
+//! Shell commands in Rust. Rust subsystems register their own console commands with `register()`, without writing
+//! a C stub for each command. The commands run on the Mynewt shell like the C commands, from the Bluetooth LE or
+//! UART console. The handler gets the arguments after the command name as `Args`, which parses them:
+//! ```
+//! shell::register("bench", "bench <offset> [len]", bench_command) ? ;
+//!
+//! fn bench_command(args: &mut Args) -> MynewtResult<()> {
+//!     let offset: u32 = args.int() ? ;       //  Required, decimal or hex like `0x1000`
+//!     let len:    u32 = args.int_or(4096) ? ; //  Optional
+//!     args.end() ? ;                         //  No more arguments
+//!     ...
+//! }
+//! ```
+//! When the handler returns `SYS_EINVAL`, the help of the command is printed, since the help of the Mynewt shell
+//! is disabled in `syscfg.yml` to save ROM. Needs the feature `shell` and `RUST_SHELL: 1` in `syscfg.yml`.
+//! Without the feature, `register()` does nothing, like the C shell commands that are disabled in `syscfg.yml`.
+
+use crate::{
+    kernel::os,
+    result::*,
+    sys::console,
+    Strn,
+};
+
+/// Handler of a shell command, called with the arguments after the command name
+pub type Handler = fn(args: &mut Args) -> MynewtResult<()>;
+
+/// Max number of commands that may be registered. Must match `MaxCommands`.
+/// The Mynewt shell also limits the commands to `SHELL_MAX_COMPAT_COMMANDS`.
+pub const MAX_COMMANDS: usize = 8;
+type MaxCommands = heapless::consts::U8;
+
+/// Max length of a command name
+pub const MAX_NAME_LEN: usize = 15;
+
+/// Shell command registered in Rust
+struct Command {
+    /// Command registered with the Mynewt shell
+    cmd:     shell_cmd,
+    /// Null-terminated command name
+    name:    [u8; MAX_NAME_LEN + 1],
+    /// Usage of the command, printed when the arguments are invalid
+    help:    &'static str,
+    /// Function that executes the command
+    handler: Handler,
+}
+
+impl Command {
+    /// Return the command name without the null terminator
+    fn name(&self) -> &[u8] {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(MAX_NAME_LEN);
+        &self.name[..len]
+    }
+}
+
+/// Commands registered at startup. Not moved after registration, because the shell keeps pointers to them.
+static mut COMMANDS: heapless::Vec<Command, MaxCommands> = heapless::Vec(heapless::i::Vec::new());
+
+/// Register the shell command `name` with the usage `help`, e.g. `bench <offset> [len]`. `handler` is called
+/// with the arguments after the name when the command is entered. Returns `SYS_EINVAL` if the name is empty or
+/// too long, `SYS_ENOMEM` if `MAX_COMMANDS` commands have been registered.
+pub fn register(name: &'static str, help: &'static str, handler: Handler) -> MynewtResult<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN { return Err(MynewtError::SYS_EINVAL); }
+    let mut command = Command {
+        cmd: shell_cmd {
+            sc_cmd:      core::ptr::null(),
+            sc_cmd_func: Some(dispatch),
+            help:        core::ptr::null(),
+        },
+        name: [0; MAX_NAME_LEN + 1],
+        help,
+        handler,
+    };
+    command.name[..name.len()].copy_from_slice(name.as_bytes());
+
+    //  Add the command to the list and point the Mynewt command to the name, which won't move after this.
+    let sr = unsafe { os::os_arch_save_sr() };
+    let result = unsafe { COMMANDS.push(command) }
+        .map(|_| unsafe {
+            let command = COMMANDS.last_mut().unwrap();
+            command.cmd.sc_cmd = command.name.as_ptr();
+            &command.cmd as *const shell_cmd
+        });
+    unsafe { os::os_arch_restore_sr(sr) };
+    let cmd = result.map_err(|_| MynewtError::SYS_ENOMEM) ? ;
+    register_cmd(cmd)
+}
+
+/// Arguments of a shell command after the command name. Iterate to get the arguments as `&str`, or call the
+/// parsing functions, which return `SYS_EINVAL` if the argument is missing or invalid.
+pub struct Args<'a> {
+    /// Arguments including the command name
+    argv:  &'a [*const u8],
+    /// Index of the next argument
+    index: usize,
+}
+
+impl<'a> Args<'a> {
+    /// Create the arguments from the null-terminated arguments `argv`, which start with the command name
+    fn new(argv: &'a [*const u8]) -> Self {
+        Args { argv, index: 1 }
+    }
+
+    /// Return the command name
+    pub fn command(&self) -> &'a str {
+        self.get(0).unwrap_or("")
+    }
+
+    /// Return the number of arguments not taken yet
+    pub fn remaining(&self) -> usize {
+        self.argv.len() - self.index
+    }
+
+    /// Return the next argument without taking it, or `None` if there are no more arguments
+    pub fn peek(&self) -> Option<&'a str> {
+        self.get(self.index)
+    }
+
+    /// Take the next argument. Returns `SYS_EINVAL` if there are no more arguments.
+    pub fn str(&mut self) -> MynewtResult<&'a str> {
+        self.next().ok_or(MynewtError::SYS_EINVAL)
+    }
+
+    /// Take the next argument if it equals `word`, e.g. an optional subcommand. Returns true if taken.
+    pub fn take(&mut self, word: &str) -> bool {
+        if self.peek() != Some(word) { return false; }
+        self.index += 1;
+        true
+    }
+
+    /// Take the next argument as an integer, decimal or hex like `0x1000`. Returns `SYS_EINVAL` if there are no
+    /// more arguments, or if the argument is not an integer or doesn't fit into `T`.
+    pub fn int<T: core::convert::TryFrom<i64>>(&mut self) -> MynewtResult<T> {
+        let arg = self.str() ? ;
+        parse_int(arg).ok_or(MynewtError::SYS_EINVAL)
+    }
+
+    /// Take the next argument as an integer like `int()`, or return `default` if there are no more arguments
+    pub fn int_or<T: core::convert::TryFrom<i64>>(&mut self, default: T) -> MynewtResult<T> {
+        if self.remaining() == 0 { return Ok(default); }
+        self.int()
+    }
+
+    /// Check that all arguments have been taken. Returns `SYS_EINVAL` if there are more arguments.
+    pub fn end(&self) -> MynewtResult<()> {
+        if self.remaining() > 0 { return Err(MynewtError::SYS_EINVAL); }
+        Ok(())
+    }
+
+    /// Return the argument at `index` as `&str`, or `None` if missing or not UTF-8
+    fn get(&self, index: usize) -> Option<&'a str> {
+        let arg = *self.argv.get(index) ? ;
+        if arg.is_null() { return None; }
+        let len = Strn::from_cstr(arg).len();
+        let bytes = unsafe { core::slice::from_raw_parts(arg, len) };
+        core::str::from_utf8(bytes).ok()
+    }
+}
+
+impl<'a> Iterator for Args<'a> {
+    type Item = &'a str;
+
+    /// Take the next argument, or return `None` if there are no more arguments
+    fn next(&mut self) -> Option<&'a str> {
+        if self.remaining() == 0 { return None; }
+        let arg = self.get(self.index);
+        self.index += 1;
+        Some(arg.unwrap_or(""))
+    }
+}
+
+/// Parse a decimal or hex integer like `-12` or `0x1000`. Returns `None` if invalid or doesn't fit into `T`.
+fn parse_int<T: core::convert::TryFrom<i64>>(arg: &str) -> Option<T> {
+    let (negative, digits) = match arg.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None         => (false, arg),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok() ? ,
+        None      => digits.parse::<i64>().ok() ? ,
+    };
+    T::try_from(if negative { -value } else { value }).ok()
+}
+
+/// Execute a Rust shell command. Called by the Mynewt shell with the arguments `argv`, starting with the name.
+extern "C" fn dispatch(argc: i32, argv: *mut *mut ::cty::c_char) -> i32 {
+    if argc <= 0 || argv.is_null() { return MynewtError::SYS_EINVAL.into(); }
+    let argv = unsafe { core::slice::from_raw_parts(argv as *const *const u8, argc as usize) };
+    let mut args = Args::new(argv);
+    let name = args.command();
+    let command = unsafe { COMMANDS.iter() }
+        .find(|command| command.name() == name.as_bytes());
+    let command = match command {
+        Some(command) => command,
+        None => return MynewtError::SYS_ENOENT.into(),
+    };
+    match (command.handler)(&mut args) {
+        Ok(()) => 0,
+        Err(MynewtError::SYS_EINVAL) => {
+            console::print("usage: "); console::print(command.help); console::print("\n");
+            console::flush();
+            MynewtError::SYS_EINVAL.into()
+        }
+        Err(err) => {
+            console::print(name); console::print(": FAILED ("); console::printint(err.into());
+            console::print(")\n"); console::flush();
+            err.into()
+        }
+    }
+}
+
+/// Register the command with the Mynewt shell
+#[cfg(feature = "shell")]  //  If shell commands are enabled...
+fn register_cmd(cmd: *const shell_cmd) -> MynewtResult<()> {
+    check(unsafe { shell_cmd_register(cmd) })
+}
+
+/// Shell commands not supported
+#[cfg(not(feature = "shell"))]  //  If shell commands are disabled...
+fn register_cmd(_cmd: *const shell_cmd) -> MynewtResult<()> {
+    Ok(())
+}
+
+/// Shell command for the Mynewt shell. From `sys/shell/include/shell/shell.h` in Mynewt 1.7
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct shell_cmd {
+    /// Null-terminated command name
+    sc_cmd:      *const u8,
+    /// Function that executes the command: `int (*)(int argc, char *argv[])`
+    sc_cmd_func: Option<extern "C" fn(argc: i32, argv: *mut *mut ::cty::c_char) -> i32>,
+    /// Help of the command, used only with `SHELL_CMD_HELP`
+    help:        *const ::cty::c_void,
+}
+
+#[cfg(feature = "shell")]  //  If shell commands are enabled...
+extern "C" {
+    /// Register a shell command. C API: `int shell_cmd_register(const struct shell_cmd *sc)`
+    fn shell_cmd_register(sc: *const shell_cmd) -> i32;
+}
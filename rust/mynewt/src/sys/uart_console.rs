@@ -0,0 +1,222 @@
+//! Console over a UART port, for debugging without a debugger. Complements the Semihosting console in
+//! `sys/console.rs`, which stalls when no debugger is attached, and the Bluetooth LE console in `ble_nus.c`.
+//! The console output is sent to the UART when `UART_SINK` is selected in `sys/console_sink.rs`.
+//!
+//! - RX: The UART interrupt pushes the received bytes into a channel, and the task that processes the event queue
+//!   passed to `start()`, e.g. the shell task, edits the line: Backspace and Delete erase a character, `Ctrl-U`
+//!   erases the line, and Enter passes the line to the line handler. When the channel is full, RX is stalled by
+//!   the UART flow control until the task catches up, so no byte is lost.
+//! - TX: `write()` copies the bytes into a ring buffer and starts the UART, which sends them in its interrupt.
+//!   `write()` never blocks and may be called in an interrupt handler: when the buffer is full, the bytes are
+//!   dropped and counted in `dropped()`, so that the debug output can't stall the sensor or Bluetooth LE tasks.
+//! ```
+//! uart_console::start(pinetime::UART_NUM, 115_200, os::eventq_dflt_get() ? , handle_line) ? ;
+//! console_sink::register(&uart_console::UART_SINK) ? ;
+//! console_sink::select("uart") ? ;  //  Send the console output to the UART
+//! ```
+//! The UART port must be enabled in `syscfg.yml`, e.g. `UART_0: 1`.
+
+use core::sync::atomic::{ AtomicU32, Ordering };
+use crate::{
+    kernel::{ channel::Channel, os },
+    result::*,
+    sys::console_sink::ConsoleSink,
+};
+
+/// Max length of a line, excluding the newline. Longer lines are rejected.
+pub const MAX_LINE: usize = 80;
+type MaxLine = heapless::consts::U80;
+
+/// Size of the TX ring buffer. One byte is always free, to tell a full buffer from an empty one.
+const TX_BUF_SIZE: usize = 512;
+
+/// Control characters for line editing
+const BACKSPACE: u8 = 0x08;
+const DELETE:    u8 = 0x7f;
+const CTRL_U:    u8 = 0x15;
+
+/// Configuration of the UART port. Set once by `start()`.
+struct UartConsole {
+    /// Mynewt UART port number
+    uart: i32,
+    /// Called with each line entered, without the newline
+    handler: fn(&str),
+}
+
+/// UART port, `None` until `start()`
+static mut CONSOLE: Option<UartConsole> = None;
+
+/// Bytes received by the UART interrupt, popped by the task that processes the line events
+static RX_BYTES: Channel<u8> = Channel::new();
+
+/// True if the UART interrupt has stalled RX because `RX_BYTES` was full
+static mut RX_STALLED: bool = false;
+
+/// Line being entered. Only used by the task that processes the line events.
+static mut LINE: heapless::String<MaxLine> = heapless::String(heapless::i::String::new());
+
+/// True if the line being entered is longer than `MAX_LINE` and will be rejected
+static mut LINE_OVERFLOW: bool = false;
+
+/// TX ring buffer, written by any task or interrupt, read by the UART interrupt
+static mut TX_BUF: [u8; TX_BUF_SIZE] = [0; TX_BUF_SIZE];
+static mut TX_HEAD: usize = 0;  //  Next byte to be written
+static mut TX_TAIL: usize = 0;  //  Next byte to be sent
+
+/// Number of TX bytes dropped because the buffer was full
+static DROPPED: AtomicU32 = AtomicU32::new(0);
+
+/// Configure the UART port `uart` at `baud` bits per second, 8N1 without flow control, and call `handler` with each
+/// line entered, in the task that processes `queue`. Returns `SYS_EALREADY` if the console has been started.
+pub fn start(uart: i32, baud: u32, queue: *mut os::os_eventq, handler: fn(&str)) -> MynewtResult<()> {
+    if unsafe { CONSOLE.is_some() } { return Err(MynewtError::SYS_EALREADY); }
+    unsafe { CONSOLE = Some(UartConsole { uart, handler }) };
+    RX_BYTES.notify(queue, handle_rx_bytes);
+    check_hal(unsafe { hal_uart_init_cbs(uart, Some(next_tx_byte), None, Some(handle_rx_byte), core::ptr::null_mut()) }) ? ;
+    check_hal(unsafe { hal_uart_config(uart, baud as i32, 8, 1, HAL_UART_PARITY_NONE, HAL_UART_FLOW_CTL_NONE) })
+}
+
+/// Console sink that sends the console output, e.g. `console::print()` and the logs, to the UART
+pub static UART_SINK: UartSink = UartSink;
+
+/// Console sink for the UART. The bytes that don't fit in the TX buffer are dropped.
+pub struct UartSink;
+
+impl ConsoleSink for UartSink {
+    fn name(&self) -> &'static str { "uart" }
+
+    fn write(&self, bytes: &[u8]) {
+        write(bytes);
+    }
+
+    fn attach(&self) -> MynewtResult<()> {
+        if unsafe { CONSOLE.is_none() } { return Err(MynewtError::SYS_ENODEV); }
+        Ok(())
+    }
+}
+
+/// Queue `bytes` for sending and return the number of bytes queued. The bytes that don't fit in the buffer are
+/// dropped. Never blocks, so it may be called in an interrupt handler.
+pub fn write(bytes: &[u8]) -> usize {
+    let uart = match unsafe { &CONSOLE } {
+        Some(console) => console.uart,
+        None => return 0,
+    };
+    let mut queued = 0;
+    unsafe {
+        let sr = os::os_arch_save_sr();
+        for byte in bytes {
+            let next = (TX_HEAD + 1) % TX_BUF_SIZE;
+            if next == TX_TAIL { break; }  //  Buffer is full
+            TX_BUF[TX_HEAD] = *byte;
+            TX_HEAD = next;
+            queued += 1;
+        }
+        os::os_arch_restore_sr(sr);
+    }
+    if queued < bytes.len() { DROPPED.fetch_add((bytes.len() - queued) as u32, Ordering::Relaxed); }
+    if queued > 0 { unsafe { hal_uart_start_tx(uart) }; }
+    queued
+}
+
+/// Queue the text `msg` for sending, like `write()`
+pub fn print(msg: &str) {
+    write(msg.as_bytes());
+}
+
+/// Return the number of TX bytes dropped because the buffer was full
+pub fn dropped() -> u32 {
+    DROPPED.load(Ordering::Relaxed)
+}
+
+/// Edit the line with the received bytes, and pass the line to the handler when Enter is received. Called by the
+/// task that processes the line events.
+extern "C" fn handle_rx_bytes(_ev: *mut os::os_event) {
+    let (uart, handler) = match unsafe { &CONSOLE } {
+        Some(console) => (console.uart, console.handler),
+        None => return,
+    };
+    let line = unsafe { &mut LINE };
+    while let Some(byte) = RX_BYTES.pop() {
+        match byte {
+            b'\r' | b'\n' => {
+                write(b"\r\n");
+                if unsafe { LINE_OVERFLOW } { print("line too long\r\n"); }
+                else if !line.is_empty() { handler(line.as_str()); }
+                line.clear();
+                unsafe { LINE_OVERFLOW = false };
+            }
+            BACKSPACE | DELETE => {
+                if line.pop().is_some() { write(b"\x08 \x08"); }  //  Erase the character on the terminal
+            }
+            CTRL_U => {
+                for _ in 0..line.len() { write(b"\x08 \x08"); }
+                line.clear();
+                unsafe { LINE_OVERFLOW = false };
+            }
+            0x20..=0x7e => {
+                if line.push(byte as char).is_ok() { write(&[byte]); }
+                else { unsafe { LINE_OVERFLOW = true }; }
+            }
+            _ => {}  //  Ignore the other control characters and the non-ASCII bytes
+        }
+    }
+    //  Resume RX, now that the channel has room.
+    let stalled = unsafe {
+        let sr = os::os_arch_save_sr();
+        let stalled = RX_STALLED;
+        RX_STALLED = false;
+        os::os_arch_restore_sr(sr);
+        stalled
+    };
+    if stalled { unsafe { hal_uart_start_rx(uart) }; }
+}
+
+/// Push the received byte into the channel. Returns -1 to stall RX if the channel is full, in which case the UART
+/// keeps the byte until `hal_uart_start_rx()`. Called by the UART interrupt.
+extern "C" fn handle_rx_byte(_arg: *mut ::cty::c_void, byte: u8) -> i32 {
+    if RX_BYTES.push(byte).is_err() {
+        unsafe { RX_STALLED = true };
+        return -1;
+    }
+    0
+}
+
+/// Return the next byte to be sent, or -1 if the buffer is empty. Called by the UART interrupt.
+extern "C" fn next_tx_byte(_arg: *mut ::cty::c_void) -> i32 {
+    unsafe {
+        let sr = os::os_arch_save_sr();
+        let byte = if TX_TAIL == TX_HEAD { -1 } else {
+            let byte = TX_BUF[TX_TAIL] as i32;
+            TX_TAIL = (TX_TAIL + 1) % TX_BUF_SIZE;
+            byte
+        };
+        os::os_arch_restore_sr(sr);
+        byte
+    }
+}
+
+/// `enum hal_uart_parity` and `enum hal_uart_flow_ctl` values
+const HAL_UART_PARITY_NONE:   i32 = 0;
+const HAL_UART_FLOW_CTL_NONE: i32 = 0;
+
+extern "C" {
+    /// Set the callbacks of the UART port, called by the UART interrupt.
+    /// C API: `int hal_uart_init_cbs(int uart, hal_uart_tx_char tx_func, hal_uart_tx_done tx_done,
+    ///   hal_uart_rx_char rx_func, void *arg)`
+    fn hal_uart_init_cbs(uart: i32,
+        tx_func: Option<extern "C" fn(*mut ::cty::c_void) -> i32>,
+        tx_done: Option<extern "C" fn(*mut ::cty::c_void)>,
+        rx_func: Option<extern "C" fn(*mut ::cty::c_void, u8) -> i32>,
+        arg: *mut ::cty::c_void) -> i32;
+    /// Configure and open the UART port.
+    /// C API: `int hal_uart_config(int uart, int32_t speed, uint8_t databits, uint8_t stopbits,
+    ///   enum hal_uart_parity parity, enum hal_uart_flow_ctl flow_ctl)`
+    fn hal_uart_config(uart: i32, speed: i32, databits: u8, stopbits: u8, parity: i32, flow_ctl: i32) -> i32;
+    /// Start sending the bytes returned by the TX callback, unless already sending.
+    /// C API: `void hal_uart_start_tx(int uart)`
+    fn hal_uart_start_tx(uart: i32);
+    /// Resume RX after the RX callback has returned -1.
+    /// C API: `void hal_uart_start_rx(int uart)`
+    fn hal_uart_start_rx(uart: i32);
+}
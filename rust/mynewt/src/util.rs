@@ -1,4 +1,10 @@
-//! Mynewt Utility Macros for Rust
+//! Mynewt Utility Macros and functions for Rust
 
 #[macro_use]   //  Allow macros from Rust module `util/macros.rs`
 pub mod macros;  //  Export macros from `util/macros.rs`
+
+/// CRC32 and CRC16 with incremental update
+pub mod crc;  // Export `util/crc.rs` as Rust module `mynewt::util::crc`
+
+/// Convert RGB888 pixels to RGB565 with optional dithering
+pub mod rgb565;  // Export `util/rgb565.rs` as Rust module `mynewt::util::rgb565`
@@ -84,3 +84,54 @@ macro_rules! nx {
     );
   };
 }
+
+///////////////////////////////////////////////////////////////////////////////
+//  Structured Logging Macros
+
+///  Log a structured entry with key-value pairs from the calling module, encoded as CBOR by `sys/logger.rs`.
+///  The keys are identifiers and the values are integers, `bool` or `&str`, e.g.
+///  `log_kv!(log::Level::Info, "logo flashed"; slot = 1, sectors = 48, ok = true)`
+#[macro_export]
+macro_rules! log_kv {
+  ($level:expr, $msg:expr) => {
+    $crate::sys::logger::log_kv($level, module_path!(), $msg, &[])
+  };
+  ($level:expr, $msg:expr; $($key:ident = $value:expr),+ $(,)*) => {
+    $crate::sys::logger::log_kv($level, module_path!(), $msg, &[
+      $( (stringify!($key), $crate::sys::logger::Value::from($value)) ),+
+    ])
+  };
+}
+
+///////////////////////////////////////////////////////////////////////////////
+//  defmt Logging Macros
+
+///  Log at trace level with `defmt` over RTT, e.g. `defmt_trace!("spi cmd {=u8:x} len {=usize}", cmd, len)`.
+///  The format string is kept on the host, see `sys/defmt_log.rs`. Compiles to nothing unless the calling crate
+///  enables its `defmt_log` feature, which must also enable `mynewt/defmt_log`. The arguments are not evaluated
+///  when disabled, so they must not have side effects.
+#[macro_export]
+macro_rules! defmt_trace {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "defmt_log")]
+    { defmt::trace!($($arg)*); }
+  };
+}
+
+///  Log at debug level with `defmt` over RTT. See `defmt_trace!()`.
+#[macro_export]
+macro_rules! defmt_debug {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "defmt_log")]
+    { defmt::debug!($($arg)*); }
+  };
+}
+
+///  Log at info level with `defmt` over RTT. See `defmt_trace!()`.
+#[macro_export]
+macro_rules! defmt_info {
+  ($($arg:tt)*) => {
+    #[cfg(feature = "defmt_log")]
+    { defmt::info!($($arg)*); }
+  };
+}
@@ -0,0 +1,101 @@
+//! Convert 24-bit RGB888 pixels to RGB565, one row at a time, with optional Floyd–Steinberg dithering.
+//! Without dithering, each colour channel is truncated to 5 or 6 bits, which shows visible banding on gradients.
+//! With dithering, the truncation error of each pixel is spread to the neighbouring pixels:
+//! ```text
+//!           pixel   7/16
+//!   3/16    5/16    1/16
+//! ```
+//! Rows are converted as they arrive (e.g. during a logo upload), so only two rows of errors are kept in RAM.
+//! ```
+//! let mut converter = Rgb565Converter::new(true);
+//! let pixels = converter.convert_row(&rgb888_row, &mut rgb565_row);
+//! ```
+
+/// Max number of pixels in a row: the width of the PineTime display
+pub const MAX_ROW_WIDTH: usize = 240;
+
+/// Number of colour channels: red, green, blue
+const CHANNELS: usize = 3;
+
+/// Size of each row of errors: one extra pixel on each side, so that the edges need no special case
+const ERROR_ROW_SIZE: usize = (MAX_ROW_WIDTH + 2) * CHANNELS;
+
+/// Converts rows of RGB888 pixels to big endian RGB565 pixels, as expected by the ST7789 display controller
+pub struct Rgb565Converter {
+    /// True if Floyd–Steinberg dithering is enabled
+    dither: bool,
+    /// Errors to be added to the pixels of the current row
+    curr:   [i16; ERROR_ROW_SIZE],
+    /// Errors to be added to the pixels of the next row
+    next:   [i16; ERROR_ROW_SIZE],
+}
+
+impl Rgb565Converter {
+    /// Create a converter. If `dither` is true, Floyd–Steinberg dithering is applied.
+    pub const fn new(dither: bool) -> Self {
+        Rgb565Converter {
+            dither,
+            curr: [0; ERROR_ROW_SIZE],
+            next: [0; ERROR_ROW_SIZE],
+        }
+    }
+
+    /// Restart the conversion at the first row of a new image. If `dither` is true, Floyd–Steinberg dithering is
+    /// applied.
+    pub fn reset(&mut self, dither: bool) {
+        self.dither = dither;
+        self.curr = [0; ERROR_ROW_SIZE];
+        self.next = [0; ERROR_ROW_SIZE];
+    }
+
+    /// Convert a row of RGB888 pixels in `src` (3 bytes per pixel) to RGB565 pixels in `dest` (2 bytes per pixel).
+    /// Rows must be converted from top to bottom. Returns the number of pixels converted, at most `MAX_ROW_WIDTH`.
+    pub fn convert_row(&mut self, src: &[u8], dest: &mut [u8]) -> usize {
+        let pixels = core::cmp::min(
+            core::cmp::min(src.len() / CHANNELS, dest.len() / 2),
+            MAX_ROW_WIDTH
+        );
+        for x in 0..pixels {
+            //  Add the error from the previous pixels and clamp to 0..255.
+            let mut rgb = [0u8; CHANNELS];
+            for c in 0..CHANNELS {
+                let value = src[x * CHANNELS + c] as i16 + if self.dither { self.curr[(x + 1) * CHANNELS + c] } else { 0 };
+                rgb[c] = clamp(value);
+            }
+            //  Truncate to 5 bits of red, 6 bits of green, 5 bits of blue.
+            let r = rgb[0] >> 3;
+            let g = rgb[1] >> 2;
+            let b = rgb[2] >> 3;
+            let pixel: u16 = ((r as u16) << 11) | ((g as u16) << 5) | (b as u16);
+            dest[x * 2]     = (pixel >> 8) as u8;
+            dest[x * 2 + 1] = pixel as u8;
+            if !self.dither { continue; }
+
+            //  Spread the truncation error to the neighbouring pixels. The remainder of the divisions goes below
+            //  right, so that no error is lost.
+            let quantised = [ (r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2) ];
+            for c in 0..CHANNELS {
+                let err = rgb[c] as i16 - quantised[c] as i16;
+                let (right, below_left, below) = (err * 7 / 16, err * 3 / 16, err * 5 / 16);
+                let i = (x + 1) * CHANNELS + c;  //  Position of this pixel in the error rows
+                self.curr[i + CHANNELS] += right;
+                self.next[i - CHANNELS] += below_left;
+                self.next[i]            += below;
+                self.next[i + CHANNELS] += err - right - below_left - below;
+            }
+        }
+        //  Move to the next row.
+        if self.dither {
+            self.curr = self.next;
+            self.next = [0; ERROR_ROW_SIZE];
+        }
+        pixels
+    }
+}
+
+/// Clamp the value to the range of a colour channel
+fn clamp(value: i16) -> u8 {
+    if value < 0 { 0 }
+    else if value > 255 { 255 }
+    else { value as u8 }
+}
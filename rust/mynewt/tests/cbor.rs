@@ -0,0 +1,23 @@
+//! Tests for the CBOR encoding by the CoAP macros in `encoding/macros.rs`, with TinyCBOR mocked by `mock.rs`
+
+use mynewt::{
+    encoding::coap_context::*,
+    mock::{ self, Cbor },
+    oc_rep_end_root_object, oc_rep_set_int, oc_rep_set_text_string, oc_rep_start_root_object,
+    d,
+};
+
+#[test]
+fn root_object_with_int_and_text() {
+    let _mock = mock::start();
+    oc_rep_start_root_object!(root);
+    oc_rep_set_int!(root, t, 2870);
+    oc_rep_set_text_string!(root, "device", "pinetime");
+    oc_rep_end_root_object!(root);
+    assert_eq!(mock::cbor(), vec![
+        Cbor::Map,
+        Cbor::Text("t".into()),      Cbor::Int(2870),
+        Cbor::Text("device".into()), Cbor::Text("pinetime".into()),
+        Cbor::End,
+    ]);
+}
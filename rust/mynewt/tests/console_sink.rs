@@ -0,0 +1,59 @@
+//! Tests for the console sinks in `sys/console_sink.rs`, with the console mocked by `mock.rs`.
+//! The sinks stay registered after each test, so all steps are in one test.
+
+use mynewt::{
+    result::*,
+    sys::{
+        console,
+        console_sink::{ self, ConsoleSink },
+    },
+    mock,
+};
+
+/// Sink that captures the output for checking
+static TEST_SINK: TestSink = TestSink;
+
+/// Output sent to `TEST_SINK`
+static mut TEST_OUTPUT: Vec<u8> = Vec::new();
+
+struct TestSink;
+
+impl ConsoleSink for TestSink {
+    fn name(&self) -> &'static str { "test" }
+
+    fn write(&self, bytes: &[u8]) {
+        unsafe { TEST_OUTPUT.extend_from_slice(bytes) };
+    }
+}
+
+#[test]
+fn output_goes_to_the_selected_sink() {
+    let _mock = mock::start();
+
+    //  Before a sink is selected, the output goes to the Semihosting console.
+    console::print("boot\n");
+    assert_eq!(mock::console(), "boot\n");
+    assert_eq!(console_sink::selected(), None);
+
+    console_sink::register(&console_sink::SEMIHOSTING_SINK).unwrap();
+    console_sink::register(&console_sink::RTT_SINK).unwrap();
+    console_sink::register(&TEST_SINK).unwrap();
+    assert!(console_sink::register(&TEST_SINK) == Err(MynewtError::SYS_EALREADY));
+
+    console_sink::select("test").unwrap();
+    assert_eq!(console_sink::selected(), Some("test"));
+    console::print("hello ");
+    console::printint(42);
+    assert_eq!(unsafe { TEST_OUTPUT.as_slice() }, b"hello 42");
+    assert_eq!(mock::console(), "boot\n");  //  Nothing more on Semihosting
+
+    //  A missing sink or a sink disabled in `syscfg.yml` keeps the selected sink.
+    assert!(console_sink::select("uart") == Err(MynewtError::SYS_ENOENT));
+    assert!(console_sink::select("rtt")  == Err(MynewtError::SYS_ENOTSUP));
+    assert_eq!(console_sink::selected(), Some("test"));
+
+    console_sink::select("semihosting").unwrap();
+    console::print("back\n");
+    assert_eq!(mock::console(), "boot\nback\n");
+    assert_eq!(unsafe { TEST_OUTPUT.as_slice() }, b"hello 42");
+}
@@ -0,0 +1,40 @@
+//! Tests for the CRC32 and CRC16 in `util/crc.rs`, with the standard check values for `"123456789"`
+
+use mynewt::util::crc::{ self, Crc16, Crc32 };
+
+const CHECK: &[u8] = b"123456789";
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc::crc32(CHECK), 0xcbf4_3926);
+    assert_eq!(crc::crc32(b""), 0);
+}
+
+#[test]
+fn crc16_check_value() {
+    assert_eq!(crc::crc16(CHECK), 0x31c3);                     //  CRC16-XMODEM
+    let mut ccitt_false = Crc16::with_init(0xffff);
+    ccitt_false.update(CHECK);
+    assert_eq!(ccitt_false.finish(), 0x29b1);                  //  CRC16-CCITT-FALSE
+}
+
+#[test]
+fn table_matches_bitwise() {
+    let data: Vec<u8> = (0..=255u8).chain(CHECK.iter().cloned()).collect();
+    let (mut bitwise, mut table) = (Crc32::new(), Crc32::new());
+    bitwise.update_bitwise(&data);
+    table.update_table(&data);
+    assert_eq!(bitwise.finish(), table.finish());
+    let (mut bitwise, mut table) = (Crc16::new(), Crc16::new());
+    bitwise.update_bitwise(&data);
+    table.update_table(&data);
+    assert_eq!(bitwise.finish(), table.finish());
+}
+
+#[test]
+fn incremental_matches_one_shot() {
+    let mut crc = Crc32::new();
+    crc.update(&CHECK[..4]);
+    crc.update(&CHECK[4..]);
+    assert_eq!(crc.finish(), crc::crc32(CHECK));
+}
@@ -0,0 +1,84 @@
+//! Tests for the logger in `sys/logger.rs`, with the Mynewt log mocked by `mock.rs`
+
+use log::{ Level, LevelFilter };
+use mynewt::{
+    result::*,
+    sys::logger::{ self, Value },
+    mock,
+    log_kv,
+};
+
+/// Register the logger and log up to `Info`. The logger may have been registered by another test.
+fn init() {
+    match logger::init(LevelFilter::Info) {
+        Ok(()) | Err(MynewtError::SYS_EALREADY) => {}
+        Err(_) => panic!("logger init fail"),
+    }
+    logger::set_level(LevelFilter::Info).unwrap();
+    logger::set_filters("").unwrap();
+}
+
+#[test]
+fn filters_match_the_longest_module_path() {
+    let _mock = mock::start();
+    init();
+    logger::set_filters("warn,app::power=debug,app::power::gauge=error,mynewt::spi=off").unwrap();
+    assert_eq!(logger::filter_level("app"),                   LevelFilter::Warn);
+    assert_eq!(logger::filter_level("app::power"),            LevelFilter::Debug);
+    assert_eq!(logger::filter_level("app::power::history"),   LevelFilter::Debug);
+    assert_eq!(logger::filter_level("app::power::gauge"),     LevelFilter::Error);
+    assert_eq!(logger::filter_level("app::powerful"),         LevelFilter::Warn);
+    assert_eq!(logger::filter_level("mynewt::spi"),           LevelFilter::Off);
+}
+
+#[test]
+fn invalid_filters_are_rejected() {
+    let _mock = mock::start();
+    init();
+    logger::set_filters("app=debug").unwrap();
+    assert!(logger::set_filters("app=loud") == Err(MynewtError::SYS_EINVAL));
+    assert_eq!(logger::filter_level("app"), LevelFilter::Debug);  //  Unchanged
+}
+
+#[test]
+fn messages_are_appended_as_text() {
+    let _mock = mock::start();
+    init();
+    log::info!(target: "app::ble", "slot {} selected", 2);
+    log::debug!(target: "app::ble", "not logged");
+    let logs = mock::logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].module, logger::LOG_MODULE_RUST);
+    assert_eq!(logs[0].level, 1);  //  LOG_LEVEL_INFO
+    assert_eq!(logs[0].etype, 0);  //  Text
+    assert_eq!(logs[0].text(), Some("app::ble: slot 2 selected"));
+}
+
+#[test]
+fn structured_entries_are_appended_as_cbor() {
+    let _mock = mock::start();
+    init();
+    logger::log_kv(Level::Warn, "app::test", "hi", &[ ("n", Value::from(-2)), ("ok", Value::from(true)) ]);
+    let logs = mock::logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].level, 2);  //  LOG_LEVEL_WARN
+    assert_eq!(logs[0].etype, 1);  //  CBOR
+    let mut expected = vec![ 0xa4 ];                            //  Map of 4 pairs
+    expected.extend_from_slice(b"\x63tgt\x69app::test");       //  "tgt": "app::test"
+    expected.extend_from_slice(b"\x63msg\x62hi");              //  "msg": "hi"
+    expected.extend_from_slice(b"\x61n\x21");                  //  "n": -2
+    expected.extend_from_slice(b"\x62ok\xf5");                 //  "ok": true
+    assert_eq!(logs[0].data, expected);
+}
+
+#[test]
+fn structured_entries_are_filtered() {
+    let _mock = mock::start();
+    init();
+    logger::set_filters("logger=warn").unwrap();
+    log_kv!(Level::Info, "dropped"; percent = 12);
+    log_kv!(Level::Error, "kept"; percent = 5u8, name = "gauge");
+    let logs = mock::logs();
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].level, 3);  //  LOG_LEVEL_ERROR
+}
@@ -0,0 +1,82 @@
+//! Tests for the RGB888 to RGB565 converter in `util/rgb565.rs`
+
+use mynewt::util::rgb565::{ Rgb565Converter, MAX_ROW_WIDTH };
+
+/// Convert `rows` rows of `width` pixels with the colour `rgb` and return the big endian RGB565 pixels
+fn convert_flat(dither: bool, rgb: [u8; 3], width: usize, rows: usize) -> Vec<u16> {
+    let mut converter = Rgb565Converter::new(dither);
+    let src: Vec<u8> = rgb.iter().cloned().cycle().take(width * 3).collect();
+    let mut pixels = Vec::new();
+    for _ in 0..rows {
+        let mut dest = vec![0u8; width * 2];
+        assert_eq!(converter.convert_row(&src, &mut dest), width);
+        pixels.extend(dest.chunks(2).map(|p| u16::from_be_bytes([ p[0], p[1] ])));
+    }
+    pixels
+}
+
+/// Return the 8-bit value of a 5-bit colour channel, as shown by the display
+fn expand5(v: u16) -> u32 { ((v << 3) | (v >> 2)) as u32 }
+
+/// Return the 8-bit value of a 6-bit colour channel, as shown by the display
+fn expand6(v: u16) -> u32 { ((v << 2) | (v >> 4)) as u32 }
+
+#[test]
+fn converts_without_dithering() {
+    let mut converter = Rgb565Converter::new(false);
+    let src = [
+        0xff, 0x00, 0x00,  //  Red
+        0x00, 0xff, 0x00,  //  Green
+        0x00, 0x00, 0xff,  //  Blue
+        0xff, 0xff, 0xff,  //  White
+        0x80, 0x80, 0x80,  //  Grey
+        0x07, 0x03, 0x07,  //  Truncated to black
+    ];
+    let mut dest = [0u8; 12];
+    assert_eq!(converter.convert_row(&src, &mut dest), 6);
+    assert_eq!(dest, [ 0xf8, 0x00, 0x07, 0xe0, 0x00, 0x1f, 0xff, 0xff, 0x84, 0x10, 0x00, 0x00 ]);
+}
+
+#[test]
+fn converts_at_most_the_max_width() {
+    let mut converter = Rgb565Converter::new(false);
+    let src = [0xffu8; (MAX_ROW_WIDTH + 1) * 3];
+    let mut dest = [0u8; (MAX_ROW_WIDTH + 1) * 2];
+    assert_eq!(converter.convert_row(&src, &mut dest), MAX_ROW_WIDTH);
+    assert_eq!(&dest[MAX_ROW_WIDTH * 2..], &[0, 0]);
+    assert_eq!(converter.convert_row(&src[..9], &mut dest), 3);  //  Limited by the source
+    assert_eq!(converter.convert_row(&src, &mut dest[..4]), 2);  //  Limited by the destination
+}
+
+#[test]
+fn dithering_keeps_the_average_colour() {
+    //  Red 0x04 and blue 0x0c are between two RGB565 levels (0x00 and 0x08 or 0x10), green 0x82 is a level.
+    let rgb = [0x04, 0x82, 0x0c];
+    let plain = convert_flat(false, rgb, MAX_ROW_WIDTH, 16);
+    assert!(plain.iter().all(|p| *p == 0x0401));  //  Banding: every pixel has the same truncated colour
+    let dithered = convert_flat(true, rgb, MAX_ROW_WIDTH, 16);
+    let count = dithered.len() as u32;
+    let red:   u32 = dithered.iter().map(|p| expand5(*p >> 11)).sum();
+    let green: u32 = dithered.iter().map(|p| expand6(*p >> 5 & 0x3f)).sum();
+    let blue:  u32 = dithered.iter().map(|p| expand5(*p & 0x1f)).sum();
+    assert!(dithered.iter().any(|p| *p != 0x0401));
+    assert!((red   as f32 / count as f32 - 4.0).abs()   < 0.5, "red {}",   red   as f32 / count as f32);
+    assert!((green as f32 / count as f32 - 130.0).abs() < 0.5, "green {}", green as f32 / count as f32);
+    assert!((blue  as f32 / count as f32 - 12.0).abs()  < 0.5, "blue {}",  blue  as f32 / count as f32);
+}
+
+#[test]
+fn reset_clears_the_errors() {
+    let rgb = [0x04, 0x82, 0x0c];
+    let width = 8;
+    let src: Vec<u8> = rgb.iter().cloned().cycle().take(width * 3).collect();
+    let mut converter = Rgb565Converter::new(true);
+    let mut first = vec![0u8; width * 2];
+    converter.convert_row(&src, &mut first);
+    let mut second = vec![0u8; width * 2];
+    converter.convert_row(&src, &mut second);
+    converter.reset(true);
+    let mut again = vec![0u8; width * 2];
+    converter.convert_row(&src, &mut again);
+    assert_eq!(again, first);  //  Same as the first row of an image
+}
@@ -0,0 +1,56 @@
+//! Tests for the sensor poller and listeners in `hw/sensor`, with the sensor simulated by `sim.rs`
+
+use core::sync::atomic::{ AtomicI32, AtomicU32, Ordering };
+use core::time::Duration;
+use mynewt::{
+    result::*,
+    hw::sensor::{
+        listener::{ Listener, Reading },
+        poller,
+        SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW,
+    },
+    mock,
+    sim::{ self, SimSensor, Waveform },
+    Strn, StrnRep,
+};
+
+/// Name of the simulated temperature sensor
+static TEMP_DEVICE: Strn = Strn { rep: StrnRep::ByteStr(b"temp_sim_0\0") };
+
+/// Simulated temperature sensor that returns the raw temperatures in turn
+static TEMP_SIM: SimSensor = SimSensor::new(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW,
+    Waveform::Sequence(&[ 2800, 2850, 2900 ]));
+
+/// Listener that counts the temperature readings
+static TEMP_LISTENER: Listener<fn(&Reading) -> MynewtResult<()>> = Listener::new(handle_temp);
+
+/// Number of temperature readings
+static READINGS: AtomicU32 = AtomicU32::new(0);
+
+/// Last temperature reading, in hundredths of a degree Celsius
+static LAST_TEMP: AtomicI32 = AtomicI32::new(0);
+
+fn handle_temp(reading: &Reading) -> MynewtResult<()> {
+    if let Reading::TempRaw(temp) = *reading {
+        READINGS.fetch_add(1, Ordering::SeqCst);
+        LAST_TEMP.store(temp.centi_celsius(), Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+#[test]
+fn poller_calls_the_listener_at_each_interval() {
+    let _mock = mock::start();
+    TEMP_SIM.register().unwrap();
+    TEMP_LISTENER.register(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW).unwrap();
+    poller::add(&TEMP_DEVICE, SENSOR_TYPE_AMBIENT_TEMPERATURE_RAW, Duration::from_secs(10)).unwrap();
+
+    sim::advance(Duration::from_secs(30));
+    assert_eq!(TEMP_SIM.reads(), 4);  //  At 0, 10, 20 and 30 seconds
+    assert_eq!(READINGS.load(Ordering::SeqCst), 4);
+    assert_eq!(LAST_TEMP.load(Ordering::SeqCst), 2800);  //  Sequence restarted at the 4th read
+
+    poller::enable(&TEMP_DEVICE, false).unwrap();
+    sim::advance(Duration::from_secs(30));
+    assert_eq!(TEMP_SIM.reads(), 4);  //  Not polled while disabled
+}
@@ -0,0 +1,76 @@
+//! Tests for the waveforms, the simulated OS clock and the timers in `sim.rs`
+
+use core::time::Duration;
+use std::sync::Mutex;
+use mynewt::{
+    kernel::{ time::Instant, timer::Callout },
+    mock,
+    sim::{ self, Waveform },
+};
+
+/// Names of the timers in the order they were called
+static CALLED: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
+
+/// Timer that expires first
+static FIRST_TIMER: Callout<fn()> = Callout::new(first_timer);
+
+/// Timer that expires second, and restarts itself once
+static SECOND_TIMER: Callout<fn()> = Callout::new(second_timer);
+
+fn first_timer() {
+    CALLED.lock().unwrap().push("first");
+}
+
+fn second_timer() {
+    let mut called = CALLED.lock().unwrap();
+    called.push("second");
+    if called.len() < 3 { SECOND_TIMER.reset(Duration::from_secs(5)).unwrap(); }
+}
+
+#[test]
+fn waveform_values() {
+    let triangle = Waveform::Triangle { min: 100, max: 200, period_ms: 1000 };
+    assert_eq!(triangle.value(0, 0), 100);
+    assert_eq!(triangle.value(250, 0), 150);
+    assert_eq!(triangle.value(500, 0), 200);
+    assert_eq!(triangle.value(750, 0), 150);
+    assert_eq!(triangle.value(1000, 0), 100);   //  Next period
+
+    let square = Waveform::Square { low: -1, high: 1, period_ms: 1000 };
+    assert_eq!(square.value(499, 0), -1);
+    assert_eq!(square.value(500, 0), 1);
+
+    let sawtooth = Waveform::Sawtooth { min: 0, max: 100, period_ms: 1000 };
+    assert_eq!(sawtooth.value(900, 0), 90);
+    assert_eq!(sawtooth.value(1100, 0), 10);
+
+    let sequence = Waveform::Sequence(&[ 1, 2, 3 ]);
+    assert_eq!(sequence.value(0, 1), 2);
+    assert_eq!(sequence.value(0, 4), 2);         //  Restarts after the last value
+    assert_eq!(Waveform::Constant(7).value(12_345, 6), 7);
+}
+
+#[test]
+fn advance_calls_the_timers_in_order_of_expiry() {
+    let _mock = mock::start();
+    CALLED.lock().unwrap().clear();
+    let start = Instant::now();
+    SECOND_TIMER.reset(Duration::from_secs(2)).unwrap();
+    FIRST_TIMER.reset(Duration::from_secs(1)).unwrap();
+
+    sim::advance(Duration::from_secs(10));
+    assert_eq!(*CALLED.lock().unwrap(), vec![ "first", "second", "second" ]);  //  At 1, 2 and 7 seconds
+    assert_eq!(start.elapsed(), Duration::from_secs(10));                       //  Clock stops at the end
+    assert!(SECOND_TIMER.remaining().is_none());                                //  Not restarted the third time
+}
+
+#[test]
+fn stopped_timer_is_not_called() {
+    let _mock = mock::start();
+    CALLED.lock().unwrap().clear();
+    FIRST_TIMER.reset(Duration::from_secs(1)).unwrap();
+    FIRST_TIMER.stop();
+
+    sim::advance(Duration::from_secs(2));
+    assert!(CALLED.lock().unwrap().is_empty());
+}
@@ -0,0 +1,26 @@
+//! Tests for the conversions between durations and OS ticks in `kernel/time.rs`
+
+use core::time::Duration;
+use mynewt::kernel::{ os, time::{ self, TICKS_PER_SEC } };
+
+#[test]
+fn short_durations_wait_at_least_one_tick() {
+    assert_eq!(time::duration_to_ticks(Duration::from_millis(0)), 0);
+    assert_eq!(time::duration_to_ticks(Duration::from_nanos(1_000)), 1);
+    assert_eq!(time::duration_to_ticks(Duration::from_micros(1)), 1);
+    assert_eq!(time::duration_to_ticks(Duration::from_micros(500)), 1);
+}
+
+#[test]
+fn durations_round_up_to_whole_ticks() {
+    let tick_us = 1_000_000 / TICKS_PER_SEC as u64;
+    assert_eq!(time::duration_to_ticks(Duration::from_micros(tick_us)), 1);
+    assert_eq!(time::duration_to_ticks(Duration::from_micros(tick_us + 1)), 2);
+    assert_eq!(time::duration_to_ticks(Duration::from_secs(1)), TICKS_PER_SEC);
+}
+
+#[test]
+fn long_durations_saturate() {
+    let ticks = time::duration_to_ticks(Duration::from_secs(u64::max_value()));
+    assert_eq!(ticks, os::OS_TIMEOUT_NEVER - 1);
+}
@@ -0,0 +1,82 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <stdint.h>
+#include <string.h>
+
+//  Create an asset bundle for the Rust `logo::bundle` module. Must sync with rust/app/src/logo/bundle.rs
+//  Usage: make-bundle bundle.bin name:kind:format:file ...
+//  kind is 1 (image), 2 (font) or 3 (animation). For images, format is 1 (RGB565) or 2 (heatshrink).
+//  The image named "logo" is installed as the boot logo. Convert the bundle to C with: xxd -i < bundle.bin > graphic.inc
+
+#define BUNDLE_MAGIC       0x444e4241  //  ABND
+#define BUNDLE_VERSION     1
+#define BUNDLE_HEADER_SIZE 16
+#define BUNDLE_ENTRY_SIZE  32
+#define ASSET_NAME_SIZE    16
+#define MAX_ASSETS         64
+#define MAX_BUNDLE_SIZE    (512 * 1024)  //  Size of the ASSETS flash region
+
+static uint8_t bundle[MAX_BUNDLE_SIZE];
+
+/// Return the CRC32 (IEEE 802.3) of the bytes
+static uint32_t crc32(const uint8_t *data, uint32_t len) {
+    uint32_t crc = 0xffffffff;
+    for (uint32_t i = 0; i < len; i++) {
+        crc ^= data[i];
+        for (int b = 0; b < 8; b++) { crc = (crc >> 1) ^ (0xedb88320 & -(crc & 1)); }
+    }
+    return crc ^ 0xffffffff;
+}
+
+/// Store a little-endian number
+static void put16(uint8_t *p, uint16_t v) { p[0] = v; p[1] = v >> 8; }
+static void put32(uint8_t *p, uint32_t v) { put16(p, v); put16(p + 2, v >> 16); }
+
+/// Write the assets in the command line to the bundle file.
+int main(int argc, char **argv) {
+    if (argc < 3 || argc - 2 > MAX_ASSETS) {
+        fprintf(stderr, "Usage: %s bundle.bin name:kind:format:file ...\n", argv[0]);
+        return 1;
+    }
+    int count = argc - 2;
+    uint32_t offset = BUNDLE_HEADER_SIZE + count * BUNDLE_ENTRY_SIZE;
+    for (int i = 0; i < count; i++) {
+        //  Parse the asset.
+        char name[ASSET_NAME_SIZE] = { 0 };
+        unsigned kind, format;
+        int pos = 0;
+        if (sscanf(argv[i + 2], "%15[^:]:%u:%u:%n", name, &kind, &format, &pos) != 3 || pos == 0) {
+            fprintf(stderr, "Invalid asset: %s\n", argv[i + 2]);
+            return 1;
+        }
+        //  Append the asset data.
+        FILE *f = fopen(argv[i + 2] + pos, "rb");
+        if (f == NULL) { perror(argv[i + 2] + pos); return 1; }
+        uint32_t len = fread(bundle + offset, 1, MAX_BUNDLE_SIZE - offset, f);
+        int full = !feof(f) && fgetc(f) != EOF;
+        fclose(f);
+        if (full) { fprintf(stderr, "Bundle too large\n"); return 1; }
+
+        //  Add the entry to the table of contents.
+        uint8_t *entry = bundle + BUNDLE_HEADER_SIZE + i * BUNDLE_ENTRY_SIZE;
+        memcpy(entry, name, ASSET_NAME_SIZE);
+        put16(entry + 16, kind);
+        put16(entry + 18, format);
+        put32(entry + 20, offset);
+        put32(entry + 24, len);
+        put32(entry + 28, crc32(bundle + offset, len));
+        offset += len;
+    }
+    //  Write the header.
+    put32(bundle,      BUNDLE_MAGIC);
+    put16(bundle + 4,  BUNDLE_VERSION);
+    put16(bundle + 6,  count);
+    put32(bundle + 8,  offset);
+    put32(bundle + 12, crc32(bundle + BUNDLE_HEADER_SIZE, offset - BUNDLE_HEADER_SIZE));
+
+    FILE *f = fopen(argv[1], "wb");
+    if (f == NULL) { perror(argv[1]); return 1; }
+    fwrite(bundle, 1, offset, f);
+    fclose(f);
+    return 0;
+}